@@ -0,0 +1,124 @@
+#!/usr/bin/env python3
+"""Generate the raw font images used by `src/theme.rs`
+
+Usage: generate_fonts.py <path to embedded-graphics-0.7.x>
+
+* font_10x20_subset.raw: the glyphs of FONT_10X20 we actually use
+* font_7seg_20x40.raw: a 7-segment style font for large numbers
+
+The images use the layout of embedded-graphics `ImageRaw<BinaryColor>`: one bit per pixel,
+most significant bit first, rows padded to whole bytes, 16 (subset) or 8 (7-segment) glyphs per row.
+"""
+
+import os
+import sys
+
+# Keep in sync with `SUBSET_GLYPHS` in src/theme.rs
+SUBSET_GLYPHS = " 0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ:/%.-?"
+# Keep in sync with `SEVEN_SEGMENT_GLYPHS` in src/theme.rs
+SEVEN_SEGMENT_GLYPHS = " 0123456789:.-"
+
+GLYPHS_PER_ROW = 16
+SEVEN_SEGMENT_GLYPHS_PER_ROW = 8
+
+
+def pack(pixels, width, height):
+    """Pack a set of (x, y) pixels into raw bytes"""
+    stride = (width + 7) // 8
+    data = bytearray(stride * height)
+    for x, y in pixels:
+        data[y * stride + x // 8] |= 0x80 >> (x % 8)
+    return bytes(data)
+
+
+def subset_font(e_g_path):
+    width, height = 10, 20
+    with open(os.path.join(e_g_path, "fonts/raw/ascii/font_10x20.raw"), "rb") as f:
+        ascii_data = f.read()
+    ascii_stride = 160 // 8
+
+    def pixel(c, x, y):
+        index = ord(c) - ord(" ")
+        gx = (index % 16) * width + x
+        gy = (index // 16) * height + y
+        return ascii_data[gy * ascii_stride + gx // 8] & (0x80 >> (gx % 8))
+
+    rows = (len(SUBSET_GLYPHS) + GLYPHS_PER_ROW - 1) // GLYPHS_PER_ROW
+    pixels = []
+    for i, c in enumerate(SUBSET_GLYPHS):
+        ox = (i % GLYPHS_PER_ROW) * width
+        oy = (i // GLYPHS_PER_ROW) * height
+        for y in range(height):
+            for x in range(width):
+                if pixel(c, x, y):
+                    pixels.append((ox + x, oy + y))
+    return pack(pixels, GLYPHS_PER_ROW * width, rows * height)
+
+
+#  aaa
+# f   b
+#  ggg
+# e   c
+#  ddd
+SEGMENTS = {
+    "0": "abcdef",
+    "1": "bc",
+    "2": "abged",
+    "3": "abgcd",
+    "4": "fgbc",
+    "5": "afgcd",
+    "6": "afgedc",
+    "7": "abc",
+    "8": "abcdefg",
+    "9": "abcdfg",
+    "-": "g",
+}
+
+
+def seven_segment_font():
+    width, height, thickness = 20, 40, 3
+    left, right, top, middle, bottom = 1, 16, 2, 18, 34
+
+    def horizontal(y):
+        return [(x, y + t) for x in range(left + thickness + 1, right - 1) for t in range(thickness)]
+
+    def vertical(x, y0, y1):
+        return [(x + t, y) for y in range(y0 + thickness + 1, y1 - 1) for t in range(thickness)]
+
+    segments = {
+        "a": horizontal(top),
+        "g": horizontal(middle),
+        "d": horizontal(bottom),
+        "f": vertical(left, top, middle),
+        "b": vertical(right, top, middle),
+        "e": vertical(left, middle, bottom),
+        "c": vertical(right, middle, bottom),
+    }
+    dot = [(x, y) for x in range(8, 12) for y in range(bottom - 1, bottom + thickness)]
+    colon = [(x, y) for x in range(8, 12) for y in list(range(11, 15)) + list(range(25, 29))]
+
+    rows = (len(SEVEN_SEGMENT_GLYPHS) + SEVEN_SEGMENT_GLYPHS_PER_ROW - 1) // SEVEN_SEGMENT_GLYPHS_PER_ROW
+    pixels = []
+    for i, c in enumerate(SEVEN_SEGMENT_GLYPHS):
+        ox = (i % SEVEN_SEGMENT_GLYPHS_PER_ROW) * width
+        oy = (i // SEVEN_SEGMENT_GLYPHS_PER_ROW) * height
+        if c == ".":
+            glyph = dot
+        elif c == ":":
+            glyph = colon
+        else:
+            glyph = [p for s in SEGMENTS.get(c, "") for p in segments[s]]
+        pixels += [(ox + x, oy + y) for x, y in glyph]
+    return pack(pixels, SEVEN_SEGMENT_GLYPHS_PER_ROW * width, rows * height)
+
+
+def main():
+    out = os.path.dirname(os.path.abspath(__file__))
+    with open(os.path.join(out, "font_10x20_subset.raw"), "wb") as f:
+        f.write(subset_font(sys.argv[1]))
+    with open(os.path.join(out, "font_7seg_20x40.raw"), "wb") as f:
+        f.write(seven_segment_font())
+
+
+if __name__ == "__main__":
+    main()
@@ -7,6 +7,9 @@
 //! Cargo re-run the build script whenever `memory.x` is changed,
 //! updating `memory.x` ensures a rebuild of the application with the
 //! new memory settings.
+//!
+//! The RAM length from `memory.x` is also exported as `RAM_LENGTH` so the
+//! library can check its RAM budget against it.
 
 use std::env;
 use std::fs::File;
@@ -17,12 +20,31 @@ fn main() {
     // Put `memory.x` in our output directory and ensure it's
     // on the linker search path.
     let out = &PathBuf::from(env::var_os("OUT_DIR").unwrap());
-    File::create(out.join("memory.x")).unwrap().write_all(include_bytes!("memory.x")).unwrap();
+    let memory_x = include_bytes!("memory.x");
+    File::create(out.join("memory.x")).unwrap().write_all(memory_x).unwrap();
     println!("cargo:rustc-link-search={}", out.display());
 
+    // Export the RAM length in bytes
+    let ram_length = ram_length(core::str::from_utf8(memory_x).unwrap()).expect("memory.x has no RAM region");
+    println!("cargo:rustc-env=RAM_LENGTH={}", ram_length);
+
     // By default, Cargo will re-run a build script whenever
     // any file in the project changes. By specifying `memory.x`
     // here, we ensure the build script is only re-run when
     // `memory.x` is changed.
     println!("cargo:rerun-if-changed=memory.x");
 }
+
+/// Find the `LENGTH` of the `RAM` region, e.g. `RAM : ORIGIN = 0x20000000, LENGTH = 256K`
+fn ram_length(memory_x: &str) -> Option<u32> {
+    let line = memory_x.lines().find(|line| line.trim_start().starts_with("RAM"))?;
+    let length = line.split("LENGTH").nth(1)?.trim_start_matches([' ', '=']).trim();
+
+    if let Some(kilobytes) = length.strip_suffix('K') {
+        kilobytes.parse::<u32>().ok().map(|k| k * 1024)
+    } else if let Some(hex) = length.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16).ok()
+    } else {
+        length.parse().ok()
+    }
+}
@@ -0,0 +1,230 @@
+//! Air integration
+//!
+//! A transmitter on the first stage reports the tank pressure. Comparing how fast it drops with
+//! the modeled consumption at the current depth catches a free-flowing regulator long before the
+//! gas-to-surface alarm does.
+//!
+//! The Pico Explorer has no transmitter, the simulation derives the tank pressure from the air
+//! left and can simulate a free flow instead.
+
+use fugit::{MicrosDurationU32, MicrosDurationU64};
+use serde::{Deserialize, Serialize};
+
+use crate::ring_buffer::RingBuffer;
+
+/// Pressure in bar of a full tank
+const FULL_TANK_BAR: u32 = 200;
+
+/// Extra gas lost per second by a free-flowing regulator
+pub const FREE_FLOW_RATE_CL: u32 = 150;
+
+/// Time between consumption samples
+const SAMPLE_INTERVAL: MicrosDurationU32 = MicrosDurationU32::secs(1);
+
+/// Number of samples the consumption is compared over, 30 seconds
+const WINDOW: usize = 30;
+
+/// Measured consumption from this percentage of the modeled consumption is a spike
+pub const SPIKE_PERCENT: u32 = 200;
+
+/// Water volume of the tank
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TankSize {
+    #[default]
+    L10,
+    L12,
+    L15,
+}
+
+impl TankSize {
+    pub const fn volume_l(&self) -> u32 {
+        match self {
+            TankSize::L10 => 10,
+            TankSize::L12 => 12,
+            TankSize::L15 => 15,
+        }
+    }
+
+    /// Air in centiliters at the surface pressure when filled to 200 bar
+    pub const fn full_air_in_cl(&self) -> u32 {
+        FULL_TANK_BAR * self.volume_l() * 100
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            TankSize::L10 => TankSize::L12,
+            TankSize::L12 => TankSize::L15,
+            TankSize::L15 => TankSize::L10,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TankSize::L10 => "10L",
+            TankSize::L12 => "12L",
+            TankSize::L15 => "15L",
+        }
+    }
+}
+
+/// Time the compressor stays connected after the last fill, longer than the repeat of a held button
+pub const FILL_HOLD_TIME: MicrosDurationU64 = MicrosDurationU64::millis(500);
+
+/// Free air a compressor delivers, in liters per minute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FillRate {
+    /// Small portable compressor
+    L100,
+    #[default]
+    L250,
+    /// Dive shop compressor
+    L500,
+}
+
+impl FillRate {
+    pub const fn liters_per_minute(&self) -> u32 {
+        match self {
+            FillRate::L100 => 100,
+            FillRate::L250 => 250,
+            FillRate::L500 => 500,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            FillRate::L100 => FillRate::L250,
+            FillRate::L250 => FillRate::L500,
+            FillRate::L500 => FillRate::L100,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FillRate::L100 => "100L/MIN",
+            FillRate::L250 => "250L/MIN",
+            FillRate::L500 => "500L/MIN",
+        }
+    }
+}
+
+/// Tank pressure in centibar when `tank` holds `air_in_cl` at the surface pressure
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::air_integration::{tank_pressure_in_cb, TankSize};
+/// assert_eq!(tank_pressure_in_cb(200_000, TankSize::L10), 20_000);
+/// assert_eq!(tank_pressure_in_cb(180_000, TankSize::L15), 12_000);
+/// ```
+///
+pub fn tank_pressure_in_cb(air_in_cl: u32, tank: TankSize) -> u32 {
+    air_in_cl / tank.volume_l()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    /// Measured tank pressure in centibar
+    pressure: u32,
+    /// Modeled gas used since the previous sample, in 1/1_000_000 cl
+    modeled: u64,
+}
+
+/// Rolling comparison of the measured tank pressure drop with the modeled consumption
+#[derive(Debug, Clone, Copy)]
+pub struct ConsumptionEstimator {
+    /// Tank the pressure is measured on
+    tank: TankSize,
+    samples: RingBuffer<Sample, WINDOW>,
+    /// Time since the last sample in microseconds
+    elapsed_us: u32,
+    /// Modeled gas used since the last sample, in 1/1_000_000 cl
+    modeled: u64,
+}
+
+impl ConsumptionEstimator {
+    pub const fn new(tank: TankSize) -> Self {
+        ConsumptionEstimator {
+            tank,
+            samples: RingBuffer::new(),
+            elapsed_us: 0,
+            modeled: 0,
+        }
+    }
+
+    /// Record `duration` of breathing `modeled_rate_in_cl` per second, ending at the measured `tank_pressure_in_cb`
+    pub fn push(&mut self, tank_pressure_in_cb: u32, modeled_rate_in_cl: u32, duration: MicrosDurationU32) {
+        self.modeled += modeled_rate_in_cl as u64 * duration.to_micros() as u64;
+        self.elapsed_us += duration.to_micros();
+
+        if self.elapsed_us >= SAMPLE_INTERVAL.to_micros() {
+            self.samples.push(Sample {
+                pressure: tank_pressure_in_cb,
+                modeled: self.modeled,
+            });
+            self.elapsed_us = 0;
+            self.modeled = 0;
+        }
+    }
+
+    /// Measured consumption in percent of the modeled consumption over the window, `None` until the window is full or
+    /// when no consumption is modeled
+    pub fn consumption_percent(&self) -> Option<u32> {
+        if self.samples.len() < WINDOW {
+            return None;
+        }
+
+        let oldest = self.samples.iter().next()?;
+        let newest = self.samples.last()?;
+        // The oldest sample's gas was used before the window started
+        let modeled: u64 = self.samples.iter().skip(1).map(|sample| sample.modeled).sum();
+        if modeled == 0 {
+            return None;
+        }
+
+        let measured = oldest.pressure.saturating_sub(newest.pressure) as u64 * self.tank.volume_l() as u64 * 1_000_000;
+        Some((measured * 100 / modeled) as u32)
+    }
+
+    /// Whether the tank empties abnormally fast
+    pub fn spiking(&self) -> bool {
+        self.consumption_percent().is_some_and(|percent| percent >= SPIKE_PERCENT)
+    }
+}
+
+impl Default for ConsumptionEstimator {
+    fn default() -> Self {
+        Self::new(TankSize::default())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// Breathe 40 cl per second for a minute while the tank loses `factor` times as much
+    fn breathe(factor: u32) -> ConsumptionEstimator {
+        let mut estimator = ConsumptionEstimator::new(TankSize::L10);
+        let mut air = 200_000;
+
+        for _ in 0..600 {
+            air -= 4 * factor;
+            estimator.push(tank_pressure_in_cb(air, TankSize::L10), 40, MicrosDurationU32::millis(100));
+        }
+
+        estimator
+    }
+
+    #[test]
+    fn test_spike_detection() {
+        let normal = breathe(1);
+        assert!((95..=105).contains(&normal.consumption_percent().unwrap()));
+        assert!(!normal.spiking());
+
+        let free_flow = breathe(4);
+        assert!((390..=410).contains(&free_flow.consumption_percent().unwrap()));
+        assert!(free_flow.spiking());
+
+        assert_eq!(ConsumptionEstimator::default().consumption_percent(), None);
+    }
+}
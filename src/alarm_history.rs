@@ -0,0 +1,84 @@
+//! History of alarm transitions
+//!
+//! The last `N` times an alarm was raised or cleared are kept in a ring buffer, so they can be
+//! reviewed after the dive.
+
+use core::fmt;
+
+use fugit::SecsDurationU64;
+
+use crate::{ring_buffer::RingBuffer, units::Depth, Alarm, Unit};
+
+/// Number of alarm transitions kept by the dive computer
+pub const ALARM_HISTORY_SIZE: usize = 16;
+
+/// Number of transitions shown on the warnings page
+const PAGE_EVENTS: usize = 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Raised,
+    Cleared,
+    /// Surfaced with a ceiling, the depth is the ceiling
+    MissedStop,
+    /// Felt a shock, see `shock`
+    Shock,
+}
+
+/// A single alarm transition
+#[derive(Debug, Clone, Copy)]
+pub struct AlarmEvent {
+    pub alarm: Alarm,
+    pub transition: Transition,
+    /// Elapsed dive time when it happened
+    pub edt: SecsDurationU64,
+    /// Depth in millimeters when it happened
+    pub depth: u32,
+}
+
+/// Ring buffer of the last `N` alarm transitions
+pub type AlarmHistory<const N: usize> = RingBuffer<AlarmEvent, N>;
+
+impl fmt::Display for AlarmEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let minutes = self.edt.to_minutes();
+        let seconds = self.edt.to_secs() % 60;
+        let transition = match self.transition {
+            Transition::Raised => "ON",
+            Transition::Cleared => "OFF",
+            Transition::MissedStop | Transition::Shock => {
+                let name = if self.transition == Transition::Shock { "SHOCK" } else { "MISSED" };
+                return write!(f, "{:>2}:{:0>2} {:10} {:>5}", minutes, seconds, name, Depth::new(self.depth, Unit::Metric));
+            }
+        };
+
+        write!(
+            f,
+            "{:>2}:{:0>2} {:6} {:3} {:>5}",
+            minutes,
+            seconds,
+            self.alarm.as_str(),
+            transition,
+            Depth::new(self.depth, Unit::Metric)
+        )
+    }
+}
+
+impl<const N: usize> fmt::Display for RingBuffer<AlarmEvent, N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Warnings")?;
+        writeln!(f)?;
+
+        if self.is_empty() {
+            writeln!(f, "NONE")?;
+        }
+
+        // Newest first
+        for event in self.iter().rev().take(PAGE_EVENTS) {
+            writeln!(f, "{}", event)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,455 @@
+//! Static apnea training
+//!
+//! CO2 and O2 tables are the dry training of free divers: a number of rounds of a rest followed
+//! by a breath hold. A CO2 table keeps the hold and shortens the rest every round, an O2 table
+//! keeps the rest and lengthens the hold. Both tables are part of the settings, the apnea page
+//! edits them and runs one.
+//!
+//! The buzzer marks the start of every rest and hold with a long beep and counts down the last
+//! `COUNTDOWN` of a rest. The timer counts real time, the buzzer task ticks it.
+
+use core::fmt;
+
+use fugit::{MicrosDurationU64, SecsDurationU32};
+use serde::{Deserialize, Serialize};
+
+use crate::{buzzer::BEEP_LENGTH, clock::Countdown, keymap::Action};
+
+/// Most rounds in a table
+pub const MAX_ROUNDS: u8 = 8;
+
+/// Fewest rounds in a table
+const MIN_ROUNDS: u8 = 2;
+
+/// Distance between the hold and rest times that can be set
+const TIME_STEP_S: u16 = 15;
+
+/// Longest hold that can be set
+const MAX_HOLD_S: u16 = 300;
+
+/// Longest rest that can be set
+const MAX_REST_S: u16 = 180;
+
+/// Distance between the changes per round that can be set
+const CHANGE_STEP_S: u16 = 5;
+
+/// Largest change per round
+const MAX_CHANGE_S: u16 = 30;
+
+/// Length of the beep at the start of a rest or hold
+pub const CUE_LENGTH: MicrosDurationU64 = MicrosDurationU64::millis(500);
+
+/// End of a rest counted down with a beep every second
+pub const COUNTDOWN: MicrosDurationU64 = MicrosDurationU64::secs(3);
+
+/// Items of the apnea page: table, rounds, hold, rest and change
+const ITEMS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TableKind {
+    /// Same hold, shorter rests
+    Co2,
+    /// Same rest, longer holds
+    O2,
+}
+
+impl TableKind {
+    pub fn next(self) -> Self {
+        match self {
+            TableKind::Co2 => TableKind::O2,
+            TableKind::O2 => TableKind::Co2,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TableKind::Co2 => "CO2",
+            TableKind::O2 => "O2",
+        }
+    }
+}
+
+/// Times of a table in seconds, the change is taken off the rest or added to the hold per round
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApneaTable {
+    pub rounds: u8,
+    pub hold_s: u16,
+    pub rest_s: u16,
+    pub change_s: u16,
+}
+
+impl ApneaTable {
+    /// Whether the table can be set on the apnea page
+    pub fn is_valid(&self) -> bool {
+        (MIN_ROUNDS..=MAX_ROUNDS).contains(&self.rounds)
+            && (TIME_STEP_S..=MAX_HOLD_S).contains(&self.hold_s)
+            && (TIME_STEP_S..=MAX_REST_S).contains(&self.rest_s)
+            && self.change_s <= MAX_CHANGE_S
+    }
+
+    /// Hold and rest of `round`, counting from 0
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::apnea::{ApneaTables, TableKind};
+    /// let tables = ApneaTables::new();
+    /// let (hold, rest) = tables.co2.round(TableKind::Co2, 2);
+    /// assert_eq!((hold.to_secs(), rest.to_secs()), (90, 90));
+    /// ```
+    ///
+    pub fn round(&self, kind: TableKind, round: u8) -> (SecsDurationU32, SecsDurationU32) {
+        let change = self.change_s * u16::from(round);
+        let (hold_s, rest_s) = match kind {
+            TableKind::Co2 => (self.hold_s, self.rest_s.saturating_sub(change).max(TIME_STEP_S)),
+            TableKind::O2 => (self.hold_s + change, self.rest_s),
+        };
+        (SecsDurationU32::secs(hold_s.into()), SecsDurationU32::secs(rest_s.into()))
+    }
+}
+
+/// The tables stored in the settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApneaTables {
+    pub co2: ApneaTable,
+    pub o2: ApneaTable,
+}
+
+impl ApneaTables {
+    /// Beginner tables of 8 rounds: 1:30 holds with rests from 2:00 down to 0:15, and 1:00 to
+    /// 2:45 holds with 2:00 rests
+    pub const fn new() -> Self {
+        ApneaTables {
+            co2: ApneaTable {
+                rounds: 8,
+                hold_s: 90,
+                rest_s: 120,
+                change_s: 15,
+            },
+            o2: ApneaTable {
+                rounds: 8,
+                hold_s: 60,
+                rest_s: 120,
+                change_s: 15,
+            },
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.co2.is_valid() && self.o2.is_valid()
+    }
+
+    pub fn table(&self, kind: TableKind) -> &ApneaTable {
+        match kind {
+            TableKind::Co2 => &self.co2,
+            TableKind::O2 => &self.o2,
+        }
+    }
+
+    fn table_mut(&mut self, kind: TableKind) -> &mut ApneaTable {
+        match kind {
+            TableKind::Co2 => &mut self.co2,
+            TableKind::O2 => &mut self.o2,
+        }
+    }
+}
+
+impl Default for ApneaTables {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Rest,
+    Hold,
+    /// All rounds done
+    Done,
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Rest => "REST",
+            Phase::Hold => "HOLD",
+            Phase::Done => "DONE",
+        }
+    }
+}
+
+/// A table being run
+#[derive(Debug, Clone, Copy)]
+struct Run {
+    /// Copy of the table when it was started, edits apply to the next run
+    table: ApneaTable,
+    round: u8,
+    phase: Phase,
+    /// Length of the current phase
+    length: MicrosDurationU64,
+    left: Countdown,
+}
+
+impl Run {
+    fn new(table: ApneaTable, kind: TableKind) -> Self {
+        let mut run = Run {
+            table,
+            round: 0,
+            phase: Phase::Rest,
+            length: MicrosDurationU64::from_ticks(0),
+            left: Countdown::new(),
+        };
+        run.begin(kind, Phase::Rest);
+        run
+    }
+
+    fn begin(&mut self, kind: TableKind, phase: Phase) {
+        let (hold, rest) = self.table.round(kind, self.round);
+        self.phase = phase;
+        self.length = match phase {
+            Phase::Rest => MicrosDurationU64::secs(rest.to_secs().into()),
+            Phase::Hold => MicrosDurationU64::secs(hold.to_secs().into()),
+            Phase::Done => MicrosDurationU64::from_ticks(0),
+        };
+        self.left.start(self.length);
+    }
+
+    /// Time into the current phase
+    fn elapsed(&self) -> MicrosDurationU64 {
+        self.length - self.left.remaining()
+    }
+}
+
+/// State of the apnea page: the selected table and item, and the table being run
+#[derive(Debug, Clone, Copy)]
+pub struct ApneaTimer {
+    kind: TableKind,
+    /// Selected item, the table first
+    item: usize,
+    run: Option<Run>,
+}
+
+impl ApneaTimer {
+    pub const fn new() -> Self {
+        ApneaTimer {
+            kind: TableKind::Co2,
+            item: 0,
+            run: None,
+        }
+    }
+
+    /// Whether a table is being run, a finished table still shows until it is stopped
+    pub fn running(&self) -> bool {
+        self.run.is_some_and(|run| run.phase != Phase::Done)
+    }
+
+    /// Handle one of the apnea page actions, the tables can't be edited while one runs
+    pub fn perform(&mut self, action: Action, tables: &mut ApneaTables) {
+        match action {
+            Action::StartTimer => {
+                self.run = match self.run {
+                    Some(_) => None,
+                    None => Some(Run::new(*tables.table(self.kind), self.kind)),
+                }
+            }
+            _ if self.run.is_some() => {}
+            Action::SelectItem => self.item = (self.item + 1) % ITEMS,
+            Action::ChangeItem => {
+                let table = tables.table_mut(self.kind);
+                match self.item {
+                    0 => self.kind = self.kind.next(),
+                    1 => table.rounds = if table.rounds >= MAX_ROUNDS { MIN_ROUNDS } else { table.rounds + 1 },
+                    2 => table.hold_s = if table.hold_s >= MAX_HOLD_S { TIME_STEP_S } else { table.hold_s + TIME_STEP_S },
+                    3 => table.rest_s = if table.rest_s >= MAX_REST_S { TIME_STEP_S } else { table.rest_s + TIME_STEP_S },
+                    _ => table.change_s = if table.change_s >= MAX_CHANGE_S { 0 } else { table.change_s + CHANGE_STEP_S },
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Count down `duration`, moving on to the next phase when the current one is over
+    pub fn tick(&mut self, duration: MicrosDurationU64) {
+        let kind = self.kind;
+        let Some(run) = &mut self.run else {
+            return;
+        };
+        if run.phase == Phase::Done {
+            return;
+        }
+
+        run.left.tick(duration);
+        if !run.left.active() {
+            match run.phase {
+                Phase::Rest => run.begin(kind, Phase::Hold),
+                _ if run.round + 1 < run.table.rounds => {
+                    run.round += 1;
+                    run.begin(kind, Phase::Rest);
+                }
+                _ => run.begin(kind, Phase::Done),
+            }
+        }
+    }
+
+    /// Whether the buzzer sounds: at the start of every phase and in the countdown of a rest
+    pub fn beeping(&self) -> bool {
+        let Some(run) = self.run else {
+            return false;
+        };
+        let left = run.left.remaining().to_micros();
+        match run.phase {
+            Phase::Done => false,
+            _ if run.elapsed() < CUE_LENGTH => true,
+            Phase::Rest if left <= COUNTDOWN.to_micros() => left % 1_000_000 > 1_000_000 - BEEP_LENGTH.to_micros(),
+            _ => false,
+        }
+    }
+
+    /// Apnea page showing `tables`
+    pub fn page<'a>(&'a self, tables: &'a ApneaTables) -> ApneaPage<'a> {
+        ApneaPage { timer: self, tables }
+    }
+}
+
+impl Default for ApneaTimer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct ApneaPage<'a> {
+    timer: &'a ApneaTimer,
+    tables: &'a ApneaTables,
+}
+
+impl fmt::Display for ApneaPage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = self.timer.kind;
+        // Write to buffer
+        writeln!(f, "Apnea {}", kind.as_str())?;
+        writeln!(f)?;
+
+        if let Some(run) = self.timer.run {
+            // Seconds left rounded up, like a countdown
+            let left = run.left.remaining().to_micros().div_ceil(1_000_000);
+            writeln!(f, "ROUND: {:>11}/{}", (run.round + 1).min(run.table.rounds), run.table.rounds)?;
+            writeln!(f, "{}: {:>11}:{:02}", run.phase.as_str(), left / 60, left % 60)?;
+            // Progress of the current phase
+            let filled = (run.elapsed().to_micros() * 20).checked_div(run.length.to_micros()).unwrap_or(20);
+            for column in 0..20 {
+                f.write_str(if column < filled { "#" } else { "-" })?;
+            }
+            writeln!(f)?;
+            let next = match run.phase {
+                Phase::Rest => Some((Phase::Hold, run.table.round(kind, run.round).0)),
+                Phase::Hold if run.round + 1 < run.table.rounds => Some((Phase::Rest, run.table.round(kind, run.round + 1).1)),
+                _ => None,
+            };
+            return match next {
+                Some((phase, time)) => write!(f, "NEXT: {} {:>6}:{:02}", phase.as_str(), time.to_secs() / 60, time.to_secs() % 60),
+                None => write!(f, "NEXT: {:>14}", Phase::Done.as_str()),
+            };
+        }
+
+        let table = self.tables.table(kind);
+        let marker = |item| if self.timer.item == item { '>' } else { ' ' };
+        writeln!(f, "{}TABLE: {:>12}", marker(0), kind.as_str())?;
+        writeln!(f, "{}ROUNDS: {:>11}", marker(1), table.rounds)?;
+        writeln!(f, "{}HOLD: {:>10}:{:02}", marker(2), table.hold_s / 60, table.hold_s % 60)?;
+        writeln!(f, "{}REST: {:>10}:{:02}", marker(3), table.rest_s / 60, table.rest_s % 60)?;
+        write!(f, "{}CHANGE: {:>8}:{:02}", marker(4), table.change_s / 60, table.change_s % 60)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_co2_table() {
+        let mut tables = ApneaTables::new();
+        let mut timer = ApneaTimer::new();
+        assert!(format!("{}", timer.page(&tables)).starts_with("Apnea CO2\n\n>TABLE:          CO2\n ROUNDS:           8\n"));
+
+        // Two rounds of 0:30 holds, rests of 0:30 and 0:15
+        timer.perform(Action::SelectItem, &mut tables);
+        timer.perform(Action::ChangeItem, &mut tables);
+        timer.perform(Action::SelectItem, &mut tables);
+        for _ in 0..16 {
+            timer.perform(Action::ChangeItem, &mut tables);
+        }
+        timer.perform(Action::SelectItem, &mut tables);
+        for _ in 0..6 {
+            timer.perform(Action::ChangeItem, &mut tables);
+        }
+        assert_eq!(
+            tables.co2,
+            ApneaTable {
+                rounds: 2,
+                hold_s: 30,
+                rest_s: 30,
+                change_s: 15
+            }
+        );
+        assert!(tables.is_valid());
+        assert!(format!("{}", timer.page(&tables)).ends_with(">REST:          0:30\n CHANGE:        0:15"));
+
+        timer.perform(Action::StartTimer, &mut tables);
+        assert!(timer.running());
+        assert!(timer.beeping());
+        // Edits wait for the next run
+        timer.perform(Action::ChangeItem, &mut tables);
+        assert_eq!(tables.co2.rest_s, 30);
+
+        let second = MicrosDurationU64::secs(1);
+        for _ in 0..10 {
+            timer.tick(second);
+        }
+        assert!(!timer.beeping());
+        assert_eq!(
+            format!("{}", timer.page(&tables)),
+            "Apnea CO2\n\nROUND:           1/2\nREST:           0:20\n######--------------\nNEXT: HOLD      0:30"
+        );
+
+        // Countdown at the end of the rest
+        for _ in 0..17 {
+            timer.tick(second);
+        }
+        timer.tick(MicrosDurationU64::millis(50));
+        assert!(timer.beeping());
+        timer.tick(MicrosDurationU64::millis(200));
+        assert!(!timer.beeping());
+
+        timer.tick(MicrosDurationU64::millis(2750));
+        assert!(timer.beeping());
+        assert!(format!("{}", timer.page(&tables)).contains("HOLD:           0:30\n--------------------\nNEXT: REST      0:15"));
+
+        // Shorter rest in the second round, then done
+        for _ in 0..(30 + 15 + 29) {
+            timer.tick(second);
+        }
+        assert!(format!("{}", timer.page(&tables)).contains("ROUND:           2/2\nHOLD:           0:01\n"));
+        assert!(format!("{}", timer.page(&tables)).ends_with("NEXT:           DONE"));
+        timer.tick(second);
+        assert!(!timer.running());
+        assert!(!timer.beeping());
+        assert!(format!("{}", timer.page(&tables)).contains("DONE:           0:00\n"));
+
+        timer.perform(Action::StartTimer, &mut tables);
+        assert!(format!("{}", timer.page(&tables)).contains(">REST:          0:30\n"));
+    }
+
+    #[test]
+    fn test_o2_table() {
+        let tables = ApneaTables::new();
+        let rounds: Vec<_> = (0..tables.o2.rounds).map(|round| tables.o2.round(TableKind::O2, round)).collect();
+        assert_eq!((rounds[0].0.to_secs(), rounds[0].1.to_secs()), (60, 120));
+        assert_eq!((rounds[7].0.to_secs(), rounds[7].1.to_secs()), (165, 120));
+
+        // The CO2 rest never drops below 0:15
+        let table = ApneaTable { change_s: 30, ..tables.co2 };
+        assert_eq!(table.round(TableKind::Co2, 7).1.to_secs(), 15);
+        assert!(!ApneaTable { rounds: 1, ..table }.is_valid());
+    }
+}
@@ -0,0 +1,253 @@
+//! Ascent coaching
+//!
+//! While ascending the main page shows one to three arrows depending on the smoothed ascent rate
+//! compared to the `AscentLimit`, and "SLOW DOWN" above it. When the limit is exceeded for
+//! longer than `GRACE_PERIOD` the buzzer beeps, faster the longer it goes on.
+//!
+//! Training agencies teach different limits, so the diver picks one in the deco model settings:
+//! the 18 m/min of PADI, the conservative 9 m/min most others use, or a custom limit. The same
+//! limit raises the medium alarm and sets the time the gas to the surface is counted for.
+
+use fugit::{MicrosDurationU32, MicrosDurationU64};
+use serde::{Deserialize, Serialize};
+
+use crate::{buzzer::beeping, clock::Instant, gas::MAX_SAFE_ASCEND_RATE};
+
+/// Slowest custom ascent rate limit in meters per minute
+pub const MIN_CUSTOM_LIMIT: u8 = 6;
+
+/// Fastest custom ascent rate limit in meters per minute
+pub const MAX_CUSTOM_LIMIT: u8 = 18;
+
+/// Ascent rate limit, the one of a training agency or a custom one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AscentLimit {
+    /// 18 m/min of the PADI recreational dive planner
+    Padi,
+    /// 9 m/min
+    Conservative,
+    /// From `MIN_CUSTOM_LIMIT` to `MAX_CUSTOM_LIMIT` meters per minute
+    Custom(u8),
+}
+
+impl AscentLimit {
+    /// Custom limit of `MAX_SAFE_ASCEND_RATE`
+    pub const fn new() -> Self {
+        AscentLimit::Custom(MAX_SAFE_ASCEND_RATE as u8)
+    }
+
+    /// Limit in meters per minute
+    pub const fn rate(&self) -> u32 {
+        match self {
+            AscentLimit::Padi => 18,
+            AscentLimit::Conservative => 9,
+            AscentLimit::Custom(rate) => *rate as u32,
+        }
+    }
+
+    /// Next limit, the custom one goes up a meter per minute at a time
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::ascent::{AscentLimit, MAX_CUSTOM_LIMIT, MIN_CUSTOM_LIMIT};
+    /// assert_eq!(AscentLimit::Conservative.next(), AscentLimit::Custom(MIN_CUSTOM_LIMIT));
+    /// assert_eq!(AscentLimit::Custom(MAX_CUSTOM_LIMIT).next(), AscentLimit::Padi);
+    /// ```
+    ///
+    pub fn next(self) -> Self {
+        match self {
+            AscentLimit::Padi => AscentLimit::Conservative,
+            AscentLimit::Conservative => AscentLimit::Custom(MIN_CUSTOM_LIMIT),
+            AscentLimit::Custom(rate) if rate < MAX_CUSTOM_LIMIT => AscentLimit::Custom(rate + 1),
+            AscentLimit::Custom(_) => AscentLimit::Padi,
+        }
+    }
+
+    /// Name of the preset on the settings page, the rate is shown below it
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AscentLimit::Padi => "PADI",
+            AscentLimit::Conservative => "CONSERVATIVE",
+            AscentLimit::Custom(_) => "CUSTOM",
+        }
+    }
+
+    /// Whether the limit can be set on the settings page, imported settings are checked with it
+    pub fn is_valid(&self) -> bool {
+        match self {
+            AscentLimit::Custom(rate) => (MIN_CUSTOM_LIMIT..=MAX_CUSTOM_LIMIT).contains(rate),
+            _ => true,
+        }
+    }
+}
+
+impl Default for AscentLimit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Ascent rates below this, in millimeter per minute, don't count as ascending
+const MIN_RATE: i32 = 500;
+
+/// Time the limit may be exceeded before the buzzer starts
+pub const GRACE_PERIOD: MicrosDurationU32 = MicrosDurationU32::secs(5);
+
+/// Time between beeps per time the limit has been exceeded, the last one goes on forever
+const CADENCE: [(MicrosDurationU32, MicrosDurationU64); 3] = [
+    (MicrosDurationU32::secs(10), MicrosDurationU64::millis(1000)),
+    (MicrosDurationU32::secs(20), MicrosDurationU64::millis(500)),
+    (MicrosDurationU32::micros(u32::MAX), MicrosDurationU64::millis(250)),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coaching {
+    /// Not ascending
+    None,
+    /// Below half the limit
+    Slow,
+    /// Below 80% of the limit
+    Moderate,
+    /// Up to the limit
+    Fast,
+    /// Above the limit
+    TooFast,
+}
+
+impl Coaching {
+    /// Coaching for an ascent rate in millimeter per minute, positive when ascending, under `limit`
+    pub fn for_rate(ascent_rate: i32, limit: AscentLimit) -> Self {
+        let limit = limit.rate() as i32 * 1000;
+        match ascent_rate {
+            rate if rate < MIN_RATE => Coaching::None,
+            rate if rate < limit / 2 => Coaching::Slow,
+            rate if rate < limit * 4 / 5 => Coaching::Moderate,
+            rate if rate <= limit => Coaching::Fast,
+            _ => Coaching::TooFast,
+        }
+    }
+
+    /// Number of arrows to show
+    pub fn arrows(&self) -> u8 {
+        match self {
+            Coaching::None => 0,
+            Coaching::Slow => 1,
+            Coaching::Moderate => 2,
+            Coaching::Fast | Coaching::TooFast => 3,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Coaching::None => "",
+            Coaching::Slow | Coaching::Moderate | Coaching::Fast => "OK",
+            Coaching::TooFast => "SLOW DOWN",
+        }
+    }
+}
+
+/// Follows the ascent rate to coach the diver
+#[derive(Debug, Clone, Copy)]
+pub struct AscentCoach {
+    limit: AscentLimit,
+    coaching: Coaching,
+    /// Time the limit has been exceeded without interruption, in microseconds
+    exceeded_us: u32,
+}
+
+impl AscentCoach {
+    pub const fn new() -> Self {
+        AscentCoach {
+            limit: AscentLimit::new(),
+            coaching: Coaching::None,
+            exceeded_us: 0,
+        }
+    }
+
+    /// Record `duration` at the smoothed `rate` in millimeter per minute, positive when descending
+    pub fn update(&mut self, rate: i32, duration: MicrosDurationU32) {
+        self.coaching = Coaching::for_rate(-rate, self.limit);
+        self.exceeded_us = if self.coaching == Coaching::TooFast {
+            self.exceeded_us.saturating_add(duration.to_micros())
+        } else {
+            0
+        };
+    }
+
+    pub fn limit(&self) -> AscentLimit {
+        self.limit
+    }
+
+    /// Coach for `limit` from the next update on
+    pub fn set_limit(&mut self, limit: AscentLimit) {
+        self.limit = limit;
+    }
+
+    pub fn coaching(&self) -> Coaching {
+        self.coaching
+    }
+
+    /// Time between beeps, `None` while the buzzer should be quiet
+    pub fn beep_interval(&self) -> Option<MicrosDurationU64> {
+        let exceeded = MicrosDurationU32::micros(self.exceeded_us);
+        if exceeded <= GRACE_PERIOD {
+            return None;
+        }
+
+        CADENCE.iter().find(|(until, _)| exceeded < *until).map(|&(_, interval)| interval)
+    }
+
+    /// Whether the buzzer should sound at `now`
+    pub fn buzzing(&self, now: Instant) -> bool {
+        self.beep_interval().is_some_and(|interval| beeping(now, interval))
+    }
+}
+
+impl Default for AscentCoach {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_coaching_and_cadence() {
+        let limit = AscentLimit::new();
+        assert_eq!(Coaching::for_rate(-9_000, limit), Coaching::None);
+        assert_eq!(Coaching::for_rate(6_000, limit).arrows(), 1);
+        assert_eq!(Coaching::for_rate(9_000, limit).arrows(), 2);
+        assert_eq!(Coaching::for_rate(15_000, limit), Coaching::Fast);
+        assert_eq!(Coaching::for_rate(18_000, limit).as_str(), "SLOW DOWN");
+        // The bands follow the limit
+        assert_eq!(Coaching::for_rate(18_000, AscentLimit::Padi), Coaching::Fast);
+        assert_eq!(Coaching::for_rate(6_000, AscentLimit::Conservative).arrows(), 2);
+        assert_eq!(Coaching::for_rate(10_000, AscentLimit::Conservative), Coaching::TooFast);
+        assert!(!AscentLimit::Custom(MAX_CUSTOM_LIMIT + 1).is_valid());
+
+        let mut coach = AscentCoach::new();
+        let step = MicrosDurationU32::millis(100);
+        for _ in 0..50 {
+            coach.update(-18_000, step);
+        }
+        assert_eq!(coach.beep_interval(), None);
+
+        coach.update(-18_000, step);
+        assert_eq!(coach.beep_interval(), Some(MicrosDurationU64::millis(1000)));
+        assert!(coach.buzzing(Instant::from_ticks(2_050_000)));
+        assert!(!coach.buzzing(Instant::from_ticks(2_150_000)));
+
+        for _ in 0..150 {
+            coach.update(-18_000, step);
+        }
+        assert_eq!(coach.beep_interval(), Some(MicrosDurationU64::millis(250)));
+
+        // Slowing down silences the buzzer right away
+        coach.update(-12_000, step);
+        assert_eq!(coach.beep_interval(), None);
+    }
+}
@@ -0,0 +1,99 @@
+//! Trail of the actions of the diver
+//!
+//! Every action the dive computer handles is kept with the time and depth it happened at, next
+//! to the alarm history, so an instructor can review after an exercise what the student actually
+//! pressed. `audit` on the console lists them. The actions during a dive also go in its
+//! `dive_log`, where the profile graph marks each with its `icon`.
+
+use core::fmt;
+
+use fugit::SecsDurationU64;
+
+use crate::{keymap::Action, ring_buffer::RingBuffer, units::Depth, Unit};
+
+/// Number of actions kept by the dive computer
+pub const AUDIT_TRAIL_SIZE: usize = 32;
+
+/// Longest line of an entry in the first 1000 minutes after boot, newline included
+pub const MAX_ENTRY_LEN: usize = 26;
+
+/// A single action of the diver
+#[derive(Debug, Clone, Copy)]
+pub struct AuditEntry {
+    pub action: Action,
+    /// Time since boot of the tick it took effect at, also at the surface, corrected for the drift of the clock
+    pub time: SecsDurationU64,
+    /// Depth in millimeters when it happened
+    pub depth: u32,
+}
+
+/// Ring buffer of the last `N` actions
+pub type AuditTrail<const N: usize> = RingBuffer<AuditEntry, N>;
+
+/// Mark of `action` on a profile
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::{audit::icon, keymap::Action};
+/// assert_eq!(icon(Action::FillAir), '+');
+/// assert_eq!(icon(Action::DecreaseRate), '^');
+/// ```
+///
+pub fn icon(action: Action) -> char {
+    match action {
+        Action::FillAir => '+',
+        Action::ToggleUnit => 'U',
+        // A higher rate descends
+        Action::IncreaseRate => 'v',
+        Action::DecreaseRate => '^',
+        Action::Mark => '*',
+        Action::FreeFlow => '~',
+        Action::Exertion => 'E',
+        _ => '.',
+    }
+}
+
+impl fmt::Display for AuditEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{:>3}:{:0>2} {:9} {:>5}",
+            icon(self.action),
+            self.time.to_minutes(),
+            self.time.to_secs() % 60,
+            self.action.as_str(),
+            Depth::new(self.depth, Unit::Metric)
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_entry() {
+        let entry = AuditEntry {
+            action: Action::FillAir,
+            time: SecsDurationU64::secs(125),
+            depth: 0,
+        };
+        assert_eq!(format!("{}", entry), "+  2:05 FILL AIR   0.0M");
+
+        let entry = AuditEntry {
+            action: Action::DecreaseRate,
+            time: SecsDurationU64::secs(59_999),
+            depth: 18_250,
+        };
+        assert_eq!(format!("{}", entry), "^999:59 RATE DOWN   18M");
+
+        let entry = AuditEntry {
+            action: Action::ReadyAlarm,
+            time: SecsDurationU64::secs(59_999),
+            depth: 99_000,
+        };
+        assert_eq!(format!("{}\n", entry).len(), MAX_ENTRY_LEN);
+    }
+}
@@ -0,0 +1,136 @@
+//! Automatic page switching during a dive
+//!
+//! When a ceiling first shows up the screen switches to the decompression page, and when the
+//! diver enters the safety stop range it switches to the safety stop page. Once the ceiling is
+//! gone or the stop is done or left, it goes back to the main page, as long as the page it
+//! switched to is still shown.
+//!
+//! Changing the page by hand during a dive overrides it: nothing switches automatically for the
+//! rest of that dive. The next dive starts with the switching on again.
+
+#[cfg(not(test))]
+use crate::info;
+#[cfg(test)]
+use log::info;
+
+use crate::ui::Page;
+
+/// What the dive looks like at one moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DiveConditions {
+    pub diving: bool,
+    /// The model has a ceiling
+    pub ceiling: bool,
+    /// In the safety stop range with some of the stop left
+    pub safety_stop: bool,
+}
+
+/// Switches pages on what the dive does, until the diver does it by hand
+#[derive(Debug, Clone, Copy)]
+pub struct AutoPage {
+    /// Page after the last update, another page means the diver switched
+    expected: Option<Page>,
+    /// The diver switched the page during this dive
+    overridden: bool,
+    /// Conditions of the last update
+    last: DiveConditions,
+    /// A ceiling showed up during this dive
+    had_ceiling: bool,
+}
+
+impl AutoPage {
+    pub const fn new() -> Self {
+        AutoPage {
+            expected: None,
+            overridden: false,
+            last: DiveConditions {
+                diving: false,
+                ceiling: false,
+                safety_stop: false,
+            },
+            had_ceiling: false,
+        }
+    }
+
+    /// Page to show instead of `page` under `conditions`, `page` itself when nothing changes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{auto_page::{AutoPage, DiveConditions}, ui::Page};
+    /// let mut auto_page = AutoPage::new();
+    /// let deco = DiveConditions { diving: true, ceiling: true, safety_stop: false };
+    /// assert_eq!(auto_page.update(Page::Main, deco), Page::Deco);
+    /// assert_eq!(auto_page.update(Page::Deco, DiveConditions { ceiling: false, ..deco }), Page::Main);
+    /// ```
+    ///
+    pub fn update(&mut self, page: Page, conditions: DiveConditions) -> Page {
+        if !conditions.diving {
+            *self = Self::new();
+            return page;
+        }
+
+        if !self.overridden && self.expected.is_some_and(|expected| expected != page) {
+            info!("automatic page switching off for this dive");
+            self.overridden = true;
+        }
+
+        let last = self.last;
+        let next = match page {
+            _ if self.overridden => page,
+            _ if conditions.ceiling && !self.had_ceiling => Page::Deco,
+            _ if conditions.safety_stop && !last.safety_stop => Page::SafetyStop,
+            Page::Deco if last.ceiling && !conditions.ceiling => Page::Main,
+            Page::SafetyStop if last.safety_stop && !conditions.safety_stop => Page::Main,
+            page => page,
+        };
+
+        self.had_ceiling |= conditions.ceiling;
+        self.last = conditions;
+        self.expected = Some(next);
+        next
+    }
+}
+
+impl Default for AutoPage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_switch_and_override() {
+        let mut auto_page = AutoPage::new();
+        let diving = DiveConditions {
+            diving: true,
+            ..DiveConditions::default()
+        };
+        let ceiling = DiveConditions { ceiling: true, ..diving };
+        let stop = DiveConditions { safety_stop: true, ..diving };
+
+        assert_eq!(auto_page.update(Page::Main, diving), Page::Main);
+        assert_eq!(auto_page.update(Page::Main, ceiling), Page::Deco);
+        assert_eq!(auto_page.update(Page::Deco, ceiling), Page::Deco);
+        assert_eq!(auto_page.update(Page::Deco, diving), Page::Main);
+        // Only the first ceiling of a dive switches
+        assert_eq!(auto_page.update(Page::Main, ceiling), Page::Main);
+        assert_eq!(auto_page.update(Page::Main, stop), Page::SafetyStop);
+        assert_eq!(auto_page.update(Page::SafetyStop, diving), Page::Main);
+
+        // Back in the range after drifting out of it
+        assert_eq!(auto_page.update(Page::Main, stop), Page::SafetyStop);
+        // The diver goes to another page, which sticks for the rest of the dive
+        assert_eq!(auto_page.update(Page::Warnings, stop), Page::Warnings);
+        assert_eq!(auto_page.update(Page::Warnings, diving), Page::Warnings);
+        assert_eq!(auto_page.update(Page::Warnings, stop), Page::Warnings);
+
+        // The next dive switches again
+        assert_eq!(auto_page.update(Page::Warnings, DiveConditions::default()), Page::Warnings);
+        assert_eq!(auto_page.update(Page::Main, ceiling), Page::Deco);
+    }
+}
@@ -0,0 +1,276 @@
+//! Battery level
+//!
+//! The Pico measures VSYS through a 1:3 divider on ADC input 3 (GPIO29). The level is estimated
+//! from the voltage of a single LiPo cell along its discharge curve. On USB power VSYS is about
+//! 5 V, which shows as full.
+//!
+//! `BatteryTrend` keeps a sample of the voltage every `TREND_INTERVAL` for the last
+//! `TREND_SAMPLES`, fits a line through them and estimates from its slope how long the cell
+//! lasts until `EMPTY_MV`, shown on the diagnostics page. Cold water makes a cell sag, so the
+//! voltage is also logged at the start and the end of every dive.
+
+use core::fmt;
+
+use fugit::MicrosDurationU64;
+
+#[cfg(not(test))]
+use crate::info;
+#[cfg(test)]
+use log::info;
+
+use crate::{clock::Instant, ring_buffer::RingBuffer};
+
+/// Reference voltage of the ADC in millivolts
+const ADC_REFERENCE_MV: u32 = 3_300;
+
+/// Largest 12-bit ADC reading plus one
+const ADC_RANGE: u32 = 4_096;
+
+/// Divider between VSYS and the ADC input
+const VSYS_DIVIDER: u32 = 3;
+
+/// Cell voltage in millivolts and the charge left at that voltage, from full to empty
+const DISCHARGE_CURVE: [(u32, u32); 8] = [(4_200, 100), (4_000, 85), (3_900, 70), (3_800, 55), (3_700, 35), (3_600, 15), (3_500, 5), (3_300, 0)];
+
+/// Cell voltage in millivolts that counts as empty, the end of the discharge curve
+pub const EMPTY_MV: u32 = DISCHARGE_CURVE[DISCHARGE_CURVE.len() - 1].0;
+
+/// VSYS in millivolts above which the board runs from USB, a cell doesn't go above 4.2 V
+const USB_MV: u32 = 4_400;
+
+/// Time between two samples of the trend
+pub const TREND_INTERVAL: MicrosDurationU64 = MicrosDurationU64::minutes(1);
+
+/// Samples the line is fitted through, an hour
+pub const TREND_SAMPLES: usize = 60;
+
+/// Samples needed for an estimate, fewer show the noise more than the trend
+const MIN_TREND_SAMPLES: usize = 10;
+
+/// Rise in millivolts per hour over which the cell counts as charging instead of noisy
+const CHARGING_MV_PER_HOUR: i64 = 20;
+
+/// Longest runtime shown, in tenths of an hour
+const MAX_RUNTIME_TENTHS: u32 = 9_999;
+
+/// VSYS in millivolts from a raw reading of ADC input 3
+pub fn vsys_millivolts(raw: u16) -> u32 {
+    u32::from(raw) * VSYS_DIVIDER * ADC_REFERENCE_MV / ADC_RANGE
+}
+
+/// Charge left in percent at a cell voltage of `millivolts`, interpolated along the discharge curve
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::battery::battery_percent;
+/// assert_eq!(battery_percent(5_000), 100);
+/// assert_eq!(battery_percent(3_750), 45);
+/// assert_eq!(battery_percent(3_000), 0);
+/// ```
+///
+pub fn battery_percent(millivolts: u32) -> u8 {
+    let mut higher = DISCHARGE_CURVE[0];
+    if millivolts >= higher.0 {
+        return higher.1 as u8;
+    }
+
+    for lower in DISCHARGE_CURVE.iter().copied().skip(1) {
+        if millivolts >= lower.0 {
+            let percent = lower.1 + (millivolts - lower.0) * (higher.1 - lower.1) / (higher.0 - lower.0);
+            return percent as u8;
+        }
+        higher = lower;
+    }
+
+    0
+}
+
+/// How long the battery lasts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    /// Too few samples, or the voltage doesn't drop
+    Unknown,
+    /// Powered from USB
+    External,
+    Charging,
+    /// Tenths of an hour until `EMPTY_MV`
+    Hours(u32),
+}
+
+impl fmt::Display for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Runtime::Unknown => write!(f, "RUNTIME: {:>11}", "--"),
+            Runtime::External => write!(f, "RUNTIME: {:>11}", "USB"),
+            Runtime::Charging => write!(f, "RUNTIME: {:>11}", "CHARGING"),
+            Runtime::Hours(tenths) => write!(f, "RUNTIME: {:>8}.{}H", tenths / 10, tenths % 10),
+        }
+    }
+}
+
+/// Voltage over time, for the runtime estimate and the sag during dives
+#[derive(Debug, Clone, Copy)]
+pub struct BatteryTrend {
+    /// In millivolts, one per `TREND_INTERVAL`
+    samples: RingBuffer<u32, TREND_SAMPLES>,
+    /// When the last sample was kept
+    last: Option<Instant>,
+    /// Voltage at the start of the dive going on
+    dive_start: Option<u32>,
+}
+
+impl BatteryTrend {
+    pub const fn new() -> Self {
+        BatteryTrend {
+            samples: RingBuffer::new(),
+            last: None,
+            dive_start: None,
+        }
+    }
+
+    /// VSYS was `millivolts` at `now`, kept when a `TREND_INTERVAL` passed since the last sample
+    pub fn record(&mut self, now: Instant, millivolts: u32) {
+        let due = self
+            .last
+            .is_none_or(|last| now.checked_duration_since(last).is_none_or(|since| since >= TREND_INTERVAL));
+        if due {
+            self.samples.push(millivolts);
+            self.last = Some(now);
+        }
+    }
+
+    /// Log the voltage when a dive starts or ends, `diving` is whether one goes on
+    pub fn dive(&mut self, diving: bool, millivolts: u32) {
+        match (diving, self.dive_start) {
+            (true, None) => {
+                info!("Battery {}mV at the start of the dive", millivolts);
+                self.dive_start = Some(millivolts);
+            }
+            (false, Some(start)) => {
+                info!(
+                    "Battery {}mV at the end of the dive, {}mV below the start",
+                    millivolts,
+                    start as i32 - millivolts as i32
+                );
+                self.dive_start = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Slope of the line through the samples in millivolts per hour, negative while draining
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{battery::{BatteryTrend, TREND_INTERVAL}, clock::Instant};
+    /// let mut trend = BatteryTrend::new();
+    /// for minute in 0..20 {
+    ///     trend.record(Instant::from_ticks(0) + TREND_INTERVAL * minute, 4_000 - minute * 2);
+    /// }
+    /// assert_eq!(trend.slope(), Some(-120));
+    /// ```
+    ///
+    pub fn slope(&self) -> Option<i64> {
+        let fit = self.fit()?;
+        Some(fit.rise * 60 / fit.run)
+    }
+
+    /// Time left until the cell is empty at the current slope
+    pub fn runtime(&self) -> Runtime {
+        match (self.samples.last(), self.fit()) {
+            (Some(&last), _) if last > USB_MV => Runtime::External,
+            (_, Some(fit)) if fit.rise * 60 > CHARGING_MV_PER_HOUR * fit.run => Runtime::Charging,
+            (_, Some(fit)) if fit.rise < 0 => {
+                // The fitted voltage of the last sample, less noisy than the sample itself
+                let n = fit.n;
+                let left = (fit.sum_y * fit.run + fit.rise * fit.sum_x - i64::from(EMPTY_MV) * n * fit.run).max(0);
+                let tenths = left * 10 / (n * -fit.rise * 60);
+                Runtime::Hours(tenths.min(i64::from(MAX_RUNTIME_TENTHS)) as u32)
+            }
+            _ => Runtime::Unknown,
+        }
+    }
+
+    /// Least squares line through the samples, with the sample number as x in minutes
+    fn fit(&self) -> Option<Fit> {
+        let n = self.samples.len() as i64;
+        if self.samples.len() < MIN_TREND_SAMPLES {
+            return None;
+        }
+
+        let sum_x = n * (n - 1) / 2;
+        let sum_xx = (n - 1) * n * (2 * n - 1) / 6;
+        let (sum_y, sum_xy) = self
+            .samples
+            .iter()
+            .enumerate()
+            .fold((0, 0), |(sum_y, sum_xy), (x, &y)| (sum_y + i64::from(y), sum_xy + x as i64 * i64::from(y)));
+        Some(Fit {
+            n,
+            sum_x,
+            sum_y,
+            rise: n * sum_xy - sum_x * sum_y,
+            run: n * sum_xx - sum_x * sum_x,
+        })
+    }
+}
+
+impl Default for BatteryTrend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Sums of a least squares fit, the slope in millivolts per minute is `rise / run`
+struct Fit {
+    n: i64,
+    sum_x: i64,
+    sum_y: i64,
+    rise: i64,
+    /// Positive with two samples or more
+    run: i64,
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_battery_level() {
+        // 4.2 V is a full cell
+        assert_eq!(vsys_millivolts(1_738), 4_200);
+        assert_eq!(battery_percent(vsys_millivolts(1_738)), 100);
+        assert_eq!(battery_percent(4_100), 92);
+        assert_eq!(battery_percent(3_600), 15);
+        assert_eq!(battery_percent(3_400), 2);
+        assert_eq!(battery_percent(3_300), 0);
+    }
+
+    #[test]
+    fn test_runtime() {
+        let mut trend = BatteryTrend::new();
+        let at = |minute: u32| Instant::from_ticks(0) + TREND_INTERVAL * minute;
+        assert_eq!(trend.runtime(), Runtime::Unknown);
+
+        // 10 mV a minute, samples in between are dropped
+        for minute in 0..30 {
+            trend.record(at(minute), 4_000 - minute * 10);
+            trend.record(at(minute) + TREND_INTERVAL / 2, 3_000);
+        }
+        assert_eq!(trend.slope(), Some(-600));
+        // 410 mV left at 600 mV an hour
+        assert_eq!(trend.runtime(), Runtime::Hours(6));
+        assert_eq!(format!("{}", trend.runtime()), "RUNTIME:        0.6H");
+
+        let mut trend = BatteryTrend::new();
+        for minute in 0..30 {
+            trend.record(at(minute), 3_700 + minute);
+        }
+        assert_eq!(format!("{}", trend.runtime()), "RUNTIME:    CHARGING");
+        trend.record(at(30), 5_000);
+        assert_eq!(trend.runtime(), Runtime::External);
+    }
+}
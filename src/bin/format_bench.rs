@@ -0,0 +1,76 @@
+#![deny(unsafe_code)]
+#![deny(warnings)]
+#![cfg(not(test))]
+#![no_std]
+#![no_main]
+//! Compare rendering the main page with `core::fmt` against the `format` module
+//!
+//! The main page is rendered the way the firmware does it, with the `format` module when the
+//! `fast-format` feature is on and with `core::fmt` otherwise. Only that path is linked, so the
+//! size of the `.text` section is the code size of the path plus the rest of the binary. Run it
+//! once with and once without the feature, each run prints its path, the time per render and the
+//! code size over defmt:
+//!
+//! ```sh
+//! cargo run --release --bin format_bench
+//! cargo run --release --bin format_bench --features fast-format
+//! ```
+
+use defmt::*;
+use defmt_rtt as _;
+// The panic handler is in `dive_computer::panic`
+
+// Provide an alias for our BSP so we can switch targets quickly.
+use pimoroni_pico_explorer as bsp;
+
+use bsp::XOSC_CRYSTAL_FREQ;
+
+use bsp::hal::{clocks::init_clocks_and_plls, entry, pac, watchdog::Watchdog, Timer};
+
+use dive_computer::{budget::UiBuffer, DiveComputer};
+
+const ITERATIONS: u32 = 1000;
+
+extern "C" {
+    // Provided by the cortex-m-rt linker script
+    static __stext: u32;
+    static __etext: u32;
+}
+
+#[entry]
+fn main() -> ! {
+    info!("Program start");
+    let mut pac = pac::Peripherals::take().unwrap();
+
+    // Enable watchdog and clocks
+    let mut watchdog = Watchdog::new(pac.WATCHDOG);
+    let _clocks = init_clocks_and_plls(XOSC_CRYSTAL_FREQ, pac.XOSC, pac.CLOCKS, pac.PLL_SYS, pac.PLL_USB, &mut pac.RESETS, &mut watchdog)
+        .ok()
+        .unwrap();
+
+    let timer = Timer::new(pac.TIMER, &mut pac.RESETS);
+
+    let mut buf = UiBuffer::new();
+    let mut dive_computer = DiveComputer::default();
+    for _ in 0..20 {
+        dive_computer.increase_rate();
+    }
+
+    let start = timer.get_counter_low();
+    for _ in 0..ITERATIONS {
+        buf.clear();
+        dive_computer.render(&mut buf);
+    }
+    let elapsed = timer.get_counter_low().wrapping_sub(start);
+
+    let path = if cfg!(feature = "fast-format") { "format" } else { "core::fmt" };
+    // All the code of the binary, the other path is not in it
+    #[allow(unsafe_code)]
+    let code = unsafe { (&__etext as *const u32 as usize) - (&__stext as *const u32 as usize) };
+    info!("{=str}: {=u32} us per render", path, elapsed / ITERATIONS);
+    info!("{=str}: {=usize} bytes of code", path, code);
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
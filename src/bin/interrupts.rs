@@ -3,22 +3,17 @@
 #![no_std]
 #![no_main]
 use core::cell::RefCell;
-use core::fmt::Write;
 
 use cortex_m::interrupt::Mutex;
-use defmt::info;
 use defmt_rtt as _;
-use panic_probe as _;
+// The panic handler is in `dive_computer::panic`
 
-use arraystring::{typenum::U200, ArrayString};
 use embedded_graphics::{
-    mono_font::{ascii::FONT_10X20, MonoTextStyleBuilder},
-    pixelcolor::Rgb565,
     prelude::*,
     text::{Alignment, Text},
 };
 use embedded_hal::digital::v2::{OutputPin, StatefulOutputPin};
-use fugit::{MicrosDurationU32, TimerInstantU32};
+use fugit::MicrosDurationU32;
 
 // Provide an alias for our BSP so we can switch targets quickly.
 use pimoroni_pico_explorer as bsp;
@@ -37,12 +32,22 @@ use bsp::hal::{
     Timer,
 };
 
-use dive_computer::DiveComputer;
+use dive_computer::{
+    budget::UiBuffer,
+    buttons::{ButtonEvent, Debouncer},
+    clock::{Clock as _, Rp2040Clock},
+    diagnostics::{self, InputLatency},
+    keymap::{Button, Press},
+    theme::Theme,
+    widgets::{AscentArrows, TrendArrow, ASCENT_ARROWS_POSITION, DEPTH_TREND_POSITION},
+    DiveComputer,
+};
+// Log macros filtered by the log level
+use dive_computer::info;
 
-const REPEAT_TIME: MicrosDurationU32 = MicrosDurationU32::millis(200);
-const DEBOUNCE_TIME: MicrosDurationU32 = MicrosDurationU32::millis(100);
 const UI_TASK_INTERVAL: MicrosDurationU32 = MicrosDurationU32::millis(100);
-const LOGIC_TICK_INTERVAL: MicrosDurationU32 = MicrosDurationU32::millis(500);
+/// Report the stack usage every this many logic ticks
+const STACK_REPORT_TICKS: u32 = 20;
 
 type APin = gpio::Pin<gpio::bank0::Gpio12, gpio::PullUpInput>;
 type BPin = gpio::Pin<gpio::bank0::Gpio13, gpio::PullUpInput>;
@@ -50,18 +55,18 @@ type XPin = gpio::Pin<gpio::bank0::Gpio14, gpio::PullUpInput>;
 type YPin = gpio::Pin<gpio::bank0::Gpio15, gpio::PullUpInput>;
 type LEDPin = gpio::Pin<gpio::bank0::Gpio25, gpio::Output<gpio::PushPull>>;
 
-type Instant = TimerInstantU32<1_000_000>;
-
-type ButtonsTimer = (APin, BPin, XPin, YPin, Timer);
+type ButtonsDebouncer = (APin, BPin, XPin, YPin, Debouncer);
 type LedScreenAlarm = (LEDPin, Screen, Alarm1);
 
 static GLOBAL_DIVE_COMPUTER: Mutex<RefCell<Option<DiveComputer>>> = Mutex::new(RefCell::new(None));
-static GLOBAL_BUTTONS_TIMER: Mutex<RefCell<Option<ButtonsTimer>>> = Mutex::new(RefCell::new(None));
+static GLOBAL_BUTTONS_DEBOUNCER: Mutex<RefCell<Option<ButtonsDebouncer>>> = Mutex::new(RefCell::new(None));
 static GLOBAL_LED_SCREEN_ALARM: Mutex<RefCell<Option<LedScreenAlarm>>> = Mutex::new(RefCell::new(None));
 static GLOBAL_DIVE_TICK_ALARM: Mutex<RefCell<Option<Alarm0>>> = Mutex::new(RefCell::new(None));
+static GLOBAL_INPUT_LATENCY: Mutex<RefCell<InputLatency>> = Mutex::new(RefCell::new(InputLatency::new()));
 
 #[entry]
 fn main() -> ! {
+    diagnostics::paint_stack();
     info!("Program start");
     let mut pac = pac::Peripherals::take().unwrap();
     let mut core = pac::CorePeripherals::take().unwrap();
@@ -110,7 +115,9 @@ fn main() -> ! {
 
     // Store for use in interrupts
     cortex_m::interrupt::free(|cs| {
-        GLOBAL_BUTTONS_TIMER.borrow(cs).replace(Some((explorer.a, explorer.b, explorer.x, explorer.y, timer)));
+        GLOBAL_BUTTONS_DEBOUNCER
+            .borrow(cs)
+            .replace(Some((explorer.a, explorer.b, explorer.x, explorer.y, Debouncer::new(Rp2040Clock))));
         GLOBAL_DIVE_COMPUTER.borrow(cs).replace(Some(dive_computer));
         GLOBAL_LED_SCREEN_ALARM.borrow(cs).replace(Some((led, explorer.screen, alarm1)));
         GLOBAL_DIVE_TICK_ALARM.borrow(cs).replace(Some(alarm0));
@@ -137,7 +144,7 @@ fn main() -> ! {
 #[interrupt]
 fn TIMER_IRQ_1() {
     // Create a fixed buffer to store screen contents
-    static mut BUF: Option<ArrayString<U200>> = None;
+    static mut BUF: Option<UiBuffer> = None;
     // The `#[interrupt]` attribute covertly converts this to `&'static mut Option<Buttons>`
     static mut LED_SCREEN_ALARM: Option<LedScreenAlarm> = None;
 
@@ -150,7 +157,7 @@ fn TIMER_IRQ_1() {
     }
 
     if BUF.is_none() {
-        *BUF = Some(ArrayString::<U200>::new());
+        *BUF = Some(UiBuffer::new());
     }
 
     info!("ui task");
@@ -169,21 +176,28 @@ fn TIMER_IRQ_1() {
 
         buffer.clear();
 
-        cortex_m::interrupt::free(|cs| {
+        let (trend, coaching, alarm_color) = cortex_m::interrupt::free(|cs| {
             let mut d_ref = GLOBAL_DIVE_COMPUTER.borrow(cs).borrow_mut();
             let dive_computer = d_ref.as_mut().unwrap();
 
             // Write to buffer
-            writeln!(buffer, "{}", dive_computer).unwrap();
+            dive_computer.render(buffer);
+            (dive_computer.trend(), dive_computer.ascent().coaching(), dive_computer.reserve().color())
         });
 
         // Draw buffer on screen
-        let style = MonoTextStyleBuilder::new()
-            .font(&FONT_10X20)
-            .text_color(Rgb565::GREEN)
-            .background_color(Rgb565::BLACK)
-            .build();
-        Text::with_alignment(buffer, Point::new(20, 30), style, Alignment::Left).draw(screen).unwrap();
+        let theme = Theme::default().with_text_color(alarm_color);
+        Text::with_alignment(buffer.as_str(), Point::new(20, 30), theme.text_style(), Alignment::Left)
+            .draw(screen)
+            .unwrap();
+        TrendArrow::new(trend, DEPTH_TREND_POSITION, theme.text_color, Some(theme.background_color))
+            .draw(screen)
+            .unwrap();
+        AscentArrows::new(coaching, ASCENT_ARROWS_POSITION, theme.text_color, Some(theme.background_color))
+            .draw(screen)
+            .unwrap();
+        let shown = Rp2040Clock.now();
+        cortex_m::interrupt::free(|cs| GLOBAL_INPUT_LATENCY.borrow(cs).borrow_mut().shown(shown));
     }
 }
 
@@ -191,6 +205,7 @@ fn TIMER_IRQ_1() {
 fn TIMER_IRQ_0() {
     // The `#[interrupt]` attribute covertly converts this to `&'static mut Option<Buttons>`
     static mut DIVE_TICK_ALARM: Option<Alarm0> = None;
+    static mut TICKS: u32 = 0;
 
     // This is one-time lazy initialization. We steal the variables given to us
     // via `LED`.
@@ -204,76 +219,87 @@ fn TIMER_IRQ_0() {
 
     if let Some(alarm0) = DIVE_TICK_ALARM {
         alarm0.clear_interrupt();
-        let _ = alarm0.schedule(LOGIC_TICK_INTERVAL);
 
-        cortex_m::interrupt::free(|cs| {
-            GLOBAL_DIVE_COMPUTER.borrow(cs).borrow_mut().as_mut().unwrap().change_depth(LOGIC_TICK_INTERVAL);
+        let next_interval = cortex_m::interrupt::free(|cs| {
+            let mut d_ref = GLOBAL_DIVE_COMPUTER.borrow(cs).borrow_mut();
+            let dive_computer = d_ref.as_mut().unwrap();
+
+            dive_computer.tick();
+            dive_computer.tick_interval()
         });
+
+        let _ = alarm0.schedule(next_interval);
+
+        *TICKS += 1;
+        if *TICKS >= STACK_REPORT_TICKS {
+            diagnostics::report_stack();
+            let latency = cortex_m::interrupt::free(|cs| GLOBAL_INPUT_LATENCY.borrow(cs).borrow().stats);
+            info!("input latency avg {=u32} us, max {=u32} us", latency.average_us(), latency.max_us);
+            *TICKS = 0;
+        }
     }
 }
 
 #[interrupt]
 fn IO_IRQ_BANK0() {
-    static mut LAST_TRIGGERED: Instant = Instant::from_ticks(0);
     // The `#[interrupt]` attribute covertly converts this to `&'static mut Option<Buttons>`
-    static mut BUTTONS_TIMER: Option<ButtonsTimer> = None;
+    static mut BUTTONS_DEBOUNCER: Option<ButtonsDebouncer> = None;
 
     // This is one-time lazy initialization. We steal the variables given to us
-    // via `BUTTONS_TIMER`.
-    if BUTTONS_TIMER.is_none() {
+    // via `BUTTONS_DEBOUNCER`.
+    if BUTTONS_DEBOUNCER.is_none() {
         cortex_m::interrupt::free(|cs| {
-            *BUTTONS_TIMER = GLOBAL_BUTTONS_TIMER.borrow(cs).take();
+            *BUTTONS_DEBOUNCER = GLOBAL_BUTTONS_DEBOUNCER.borrow(cs).take();
         });
     }
 
-    if let Some((button_a, button_b, button_x, button_y, timer)) = BUTTONS_TIMER {
-        let trigger_time = Instant::from_ticks(timer.get_counter_low());
-
-        let time_waited = if trigger_time <= *LAST_TRIGGERED {
-            MicrosDurationU32::micros(0)
-        } else {
-            trigger_time - *LAST_TRIGGERED
-        };
+    if let Some((button_a, button_b, button_x, button_y, debouncer)) = BUTTONS_DEBOUNCER {
+        let debounce = debouncer.check();
 
         let mut triggered = false;
 
         macro_rules! handle_button {
-            ($button:tt, $func:ident) => {
+            ($button:tt, $id:expr, $func:ident) => {
+                let mut press = None;
                 if $button.interrupt_status(EdgeLow) {
-                    if time_waited > DEBOUNCE_TIME {
-                        cortex_m::interrupt::free(|cs| {
-                            GLOBAL_DIVE_COMPUTER.borrow(cs).borrow_mut().as_mut().unwrap().$func();
-                        });
-                        triggered = true;
-                    }
+                    press = Some(Press::Tap).filter(|_| debounce.press);
                     $button.clear_interrupt(EdgeLow);
                 } else if $button.interrupt_status(LevelLow) {
-                    if time_waited > REPEAT_TIME {
-                        cortex_m::interrupt::free(|cs| {
-                            GLOBAL_DIVE_COMPUTER.borrow(cs).borrow_mut().as_mut().unwrap().$func();
-                        });
-                        triggered = true
-                    }
+                    press = Some(Press::Hold).filter(|_| debounce.repeat);
                     $button.clear_interrupt(LevelLow);
                 }
+
+                if let Some(press) = press {
+                    // The press happened when the interrupt came in
+                    let event = ButtonEvent {
+                        button: $id,
+                        press,
+                        at: debounce.now,
+                    };
+                    cortex_m::interrupt::free(|cs| {
+                        GLOBAL_DIVE_COMPUTER.borrow(cs).borrow_mut().as_mut().unwrap().$func();
+                        GLOBAL_INPUT_LATENCY.borrow(cs).borrow_mut().pressed(&event);
+                    });
+                    triggered = true;
+                }
             };
         }
 
         // Fill air
-        handle_button!(button_a, fill_air);
+        handle_button!(button_a, Button::A, fill_air);
 
         // Change unit
-        handle_button!(button_b, toggle_unit);
+        handle_button!(button_b, Button::B, toggle_unit);
 
         // Increase descend
-        handle_button!(button_x, increase_rate);
+        handle_button!(button_x, Button::X, increase_rate);
 
         // Increase ascend
-        handle_button!(button_y, decrease_rate);
+        handle_button!(button_y, Button::Y, decrease_rate);
 
         if triggered {
             info!("button pushed");
-            *LAST_TRIGGERED = trigger_time;
+            debouncer.triggered(debounce);
         }
     }
 }
@@ -0,0 +1,43 @@
+#![deny(warnings)]
+#![cfg(not(test))]
+#![no_std]
+#![no_main]
+//! Print the static RAM usage per subsystem so we can see how much of the RAM is left for the stack
+
+use defmt::*;
+use defmt_rtt as _;
+// The panic handler is in `dive_computer::panic`
+
+// Provide an alias for our BSP so we can switch targets quickly.
+use pimoroni_pico_explorer as bsp;
+
+use bsp::hal::entry;
+
+use dive_computer::budget::{self, BUDGET, RAM_LENGTH, STACK_RESERVE};
+
+extern "C" {
+    // Provided by the cortex-m-rt linker script
+    static __sdata: u32;
+    static __ebss: u32;
+}
+
+#[entry]
+fn main() -> ! {
+    info!("RAM budget for {=usize} bytes of RAM", RAM_LENGTH);
+
+    for entry in BUDGET {
+        info!("{=str}: {=usize} bytes", entry.name, entry.bytes);
+    }
+    info!("{=str}: {=usize} bytes", "total", budget::total());
+    info!("{=str}: {=usize} bytes", "stack reserve", STACK_RESERVE);
+
+    // Everything in .data and .bss, including the statics of the dependencies
+    #[allow(unsafe_code)]
+    let statics = unsafe { (&__ebss as *const u32 as usize) - (&__sdata as *const u32 as usize) };
+    info!("{=str}: {=usize} bytes", "linked statics", statics);
+    info!("{=str}: {=usize} bytes", "left for stack", RAM_LENGTH - statics);
+
+    loop {
+        cortex_m::asm::wfi();
+    }
+}
@@ -2,23 +2,16 @@
 #![cfg(not(test))]
 #![no_std]
 #![no_main]
-use core::fmt::Write;
-
-use defmt::*;
-use defmt_rtt as _;
-use panic_probe as _;
-
-use arraystring::{typenum::U200, ArrayString};
-use embedded_graphics::{
-    mono_font::{ascii::FONT_10X20, MonoTextStyleBuilder},
-    pixelcolor::Rgb565,
-    prelude::*,
-    text::{Alignment, Text},
-};
-use embedded_hal::digital::v2::{OutputPin, StatefulOutputPin};
-use fugit::{MicrosDurationU32, MicrosDurationU64};
+
+use core::cell::Cell;
+
+use rtt_target::{rtt_init, DownChannel, UpChannel};
+// The panic handler is in `dive_computer::panic`
+
+use embedded_graphics::{prelude::*, primitives::Rectangle};
+use embedded_hal::{blocking::i2c::Read, digital::v2::InputPin};
+use fugit::{HertzU32, MicrosDurationU64, RateExtU32};
 use rp2040_monotonic::Rp2040Monotonic;
-use rtic::Monotonic;
 
 // Provide an alias for our BSP so we can switch targets quickly.
 use pimoroni_pico_explorer as bsp;
@@ -28,27 +21,148 @@ use bsp::{PicoExplorer, Screen, XOSC_CRYSTAL_FREQ};
 use bsp::hal::{
     adc::Adc,
     clocks::{init_clocks_and_plls, Clock},
-    gpio::{self, Interrupt::EdgeLow, Interrupt::LevelLow},
+    gpio::{self, Interrupt::EdgeHigh, Interrupt::EdgeLow, Interrupt::LevelLow},
+    i2c::{
+        peripheral::{I2CEvent, I2CPeripheralEventIterator},
+        I2C,
+    },
+    pwm::{self, FreeRunning, Pwm0, Pwm4, Slices},
+    rtc::{DateTime, DateTimeFilter, DayOfWeek, RealTimeClock},
     sio::{self, Sio},
+    uart::{Reader, UartConfig, UartPeripheral, Writer},
     watchdog::Watchdog,
 };
 
-use dive_computer::DiveComputer;
+#[cfg(feature = "instructor")]
+use dive_computer::instructor::{Broadcaster, InstructorFrame, Scenario};
+#[cfg(feature = "joystick")]
+use dive_computer::joystick::{Joystick, JoystickConfig};
+#[cfg(all(feature = "mqtt-gateway", not(feature = "instructor")))]
+use dive_computer::mqtt_sn::{Gateway, Readings};
+#[cfg(feature = "thermistor")]
+use dive_computer::temperature::thermistor_tenths;
+use dive_computer::{
+    apnea::ApneaTimer,
+    auto_page::{AutoPage, DiveConditions},
+    battery::{battery_percent, vsys_millivolts, BatteryTrend},
+    blending::BlendCalculator,
+    buddy::{BuddyLink, SEND_INTERVAL},
+    budget::UiBuffer,
+    buttons::{ButtonEvent, ChordGuard, Debouncer, StuckButtons},
+    buzzer,
+    cesa::{Cesa, CesaGuide},
+    checklist::Checklist,
+    clock::{self, ClockSync, Instant, Rp2040Clock},
+    console::{self, Command, Device, LineReader, Reply, SCRATCH_SECTORS, SCRATCH_START},
+    diagnostics::{self, QueueSlots, RuntimeStats},
+    dive_log::DiveLog,
+    experiment::{Experiment, LoadPriority},
+    factory_reset::{self, FactoryReset, ResetState},
+    failure::{FailureInjector, AIR_LOSS_PERCENT},
+    fault::{FaultCode, FaultLog},
+    flash::Rp2040Flash,
+    frame::Frame,
+    help::{HelpOverlay, HelpPage},
+    i2c_slave::{self, RegisterMap},
+    imu::{Accelerometer, Lsm6ds3},
+    input_macro::{MacroRecorder, MacroStore, MACRO_SECTORS, MACRO_START},
+    instructor::{BusAddress, ScenarioCommand, StudentLink},
+    keymap::{chord_action, Action, Button, Press},
+    lock::ButtonLock,
+    morse::MorseSignal,
+    next_dive::NextDiveAlarm,
+    odometer::LifetimeStats,
+    outputs::{Channel, Outputs, Source, BUZZER_DUTY},
+    peripherals::{Inventory, Peripheral, Subsystem, Subsystems},
+    planner::PlanEditor,
+    render::{self, Background, DirtyRegions, DrawError, FrameCache, ScreenChunk, ScreenRecovery, SpiFrequency},
+    sampler::{AdcInput, AdcSampler, SampleRing},
+    screen_saver::{ScreenSaver, ScreenState},
+    self_test::{self, SelfTestReport},
+    settings::{Settings, SettingsEditor},
+    setup::BootState,
+    shock::ShockDetector,
+    storage::{erase_sectors, Storage, StorageError, WearMap},
+    surface::TimeOfDay,
+    telemetry::MAX_FRAME_LEN,
+    theme::{DepthGradient, Theme},
+    ui::Page,
+    wall_clock::WallClock,
+    warm_boot::{self, RetainedState},
+    Alarm, DiveComputer,
+};
+// Log macros filtered by the log level
+use dive_computer::{debug, info, warn};
+
+const STACK_REPORT_INTERVAL: MicrosDurationU64 = MicrosDurationU64::secs(10);
+const BUZZER_TASK_INTERVAL: MicrosDurationU64 = MicrosDurationU64::millis(25);
+/// PWM period of the buzzer tone, about 2.7 kHz from the 125 MHz system clock divided by 25
+const BUZZER_TOP: u16 = 1850;
+#[cfg(feature = "joystick")]
+const JOYSTICK_POLL_INTERVAL: MicrosDurationU64 = MicrosDurationU64::millis(10);
+#[cfg(feature = "thermistor")]
+const TEMPERATURE_POLL_INTERVAL: MicrosDurationU64 = MicrosDurationU64::secs(1);
+const BATTERY_POLL_INTERVAL: MicrosDurationU64 = MicrosDurationU64::secs(1);
+/// About the data rate of the accelerometer, the knocks it filters out are too short to matter
+const SHOCK_POLL_INTERVAL: MicrosDurationU64 = MicrosDurationU64::millis(10);
+/// Time between the beeps of the next dive alarm
+const READY_BEEP_INTERVAL: MicrosDurationU64 = MicrosDurationU64::millis(500);
+/// How often an idle load task checks whether an experiment started
+const LOAD_POLL_INTERVAL: MicrosDurationU64 = MicrosDurationU64::millis(100);
+/// Time between the checks for a due press while a button macro plays
+const MACRO_POLL_INTERVAL: MicrosDurationU64 = MicrosDurationU64::millis(10);
+/// Time between the reads of the console channel, a line of the host waits in its buffer
+const CONSOLE_POLL_INTERVAL: MicrosDurationU64 = MicrosDurationU64::millis(50);
+/// Time after a change of the settings before they are stored, the changes until then go in one write
+const SETTINGS_SAVE_DELAY: MicrosDurationU64 = MicrosDurationU64::secs(2);
+/// Time the end of a factory reset is shown before the reboot
+const REBOOT_DELAY: MicrosDurationU64 = MicrosDurationU64::secs(2);
+/// Software tasks with the optional polling tasks, each has one slot in the queues
+const SOFTWARE_TASKS: u8 = 17 + cfg!(feature = "joystick") as u8 + cfg!(feature = "thermistor") as u8;
+
+/// Slots of the task queues, outside of the resources because every spawn and every start counts
+static QUEUE: cortex_m::interrupt::Mutex<Cell<QueueSlots>> = cortex_m::interrupt::Mutex::new(Cell::new(QueueSlots::new(SOFTWARE_TASKS)));
+
+/// Count the slot a spawn took when it went through, and pass on its result
+fn queued<T, E>(spawn: Result<T, E>) -> Result<T, E> {
+    if spawn.is_ok() {
+        update_queue(QueueSlots::spawned);
+    }
+    spawn
+}
+
+/// Count the start of a software task, which frees its slot
+fn started() {
+    update_queue(QueueSlots::started);
+}
 
-const REPEAT_TIME: MicrosDurationU64 = MicrosDurationU64::millis(200);
-const DEBOUNCE_TIME: MicrosDurationU64 = MicrosDurationU64::millis(100);
-const UI_TASK_INTERVAL: MicrosDurationU64 = MicrosDurationU64::millis(100);
-const LOGIC_TICK_INTERVAL: MicrosDurationU64 = MicrosDurationU64::millis(500);
+fn update_queue(update: impl FnOnce(&mut QueueSlots)) {
+    cortex_m::interrupt::free(|cs| {
+        let queue = QUEUE.borrow(cs);
+        let mut slots = queue.get();
+        update(&mut slots);
+        queue.set(slots);
+    });
+}
 
 type APin = gpio::Pin<gpio::bank0::Gpio12, gpio::PullUpInput>;
 type BPin = gpio::Pin<gpio::bank0::Gpio13, gpio::PullUpInput>;
 type XPin = gpio::Pin<gpio::bank0::Gpio14, gpio::PullUpInput>;
 type YPin = gpio::Pin<gpio::bank0::Gpio15, gpio::PullUpInput>;
-type LEDPin = gpio::Pin<gpio::bank0::Gpio25, gpio::Output<gpio::PushPull>>;
-
-type Instant = <Rp2040Monotonic as Monotonic>::Instant;
+/// The piezo on the Explorer is driven from GPIO 0 (the AUDIO jumper)
+type BuzzerChannel = pwm::Channel<Pwm0, FreeRunning, pwm::A>;
+/// Gate of the MOSFET switching the external strobe, on GPIO 1
+type StrobeChannel = pwm::Channel<Pwm0, FreeRunning, pwm::B>;
+type LedChannel = pwm::Channel<Pwm4, FreeRunning, pwm::B>;
+/// I2C1 answering a controller, SDA on GP2 and SCL on GP3 of the breakout header
+type I2cPeripheral =
+    I2CPeripheralEventIterator<bsp::pac::I2C1, (gpio::Pin<gpio::bank0::Gpio2, gpio::FunctionI2C>, gpio::Pin<gpio::bank0::Gpio3, gpio::FunctionI2C>)>;
+/// I2C0 of the breakout sockets, SDA on GP20 and SCL on GP21
+type BreakoutBus = I2C<bsp::pac::I2C0, (gpio::Pin<gpio::bank0::Gpio20, gpio::FunctionI2C>, gpio::Pin<gpio::bank0::Gpio21, gpio::FunctionI2C>)>;
+/// UART to the buddy, TX on GP4 and RX on GP5 of the breakout header
+type BuddyPins = (gpio::Pin<gpio::bank0::Gpio4, gpio::FunctionUart>, gpio::Pin<gpio::bank0::Gpio5, gpio::FunctionUart>);
 
-#[rtic::app(device = bsp::hal::pac, peripherals = true, dispatchers = [TIMER_IRQ_1, TIMER_IRQ_2])]
+#[rtic::app(device = bsp::hal::pac, peripherals = true, dispatchers = [TIMER_IRQ_1, TIMER_IRQ_2, TIMER_IRQ_3])]
 mod app {
 
     use super::*;
@@ -60,23 +174,102 @@ mod app {
     #[shared]
     struct Shared {
         dive_computer: DiveComputer,
+        page: Page,
+        settings: Settings,
+        editor: SettingsEditor,
+        screen_saver: ScreenSaver,
+        stats: RuntimeStats,
+        button_lock: ButtonLock,
+        help: HelpOverlay,
+        planner: PlanEditor,
+        blending: BlendCalculator,
+        apnea: ApneaTimer,
+        signal: MorseSignal,
+        self_test: SelfTestReport,
+        next_dive: NextDiveAlarm,
+        /// Time of day, its alarm wakes us for the next dive alarm
+        rtc: RealTimeClock,
+        lifetime: LifetimeStats,
+        boot: BootState,
+        /// Averages of the ADC inputs, sampled by DMA
+        sampler: AdcSampler,
+        experiment: Experiment,
+        outputs: Outputs<BuzzerChannel, StrobeChannel, LedChannel>,
+        buddy: BuddyLink,
+        /// What of the optional parts can be used
+        subsystems: Subsystems,
+        /// Failures injected by the instructor
+        failures: FailureInjector,
+        factory_reset: FactoryReset,
+        checklist: Checklist,
+        /// Emergency ascent guide, opened by holding Y during a dive
+        cesa: Cesa,
+        /// Recorded button presses, for development
+        button_macro: MacroRecorder,
+        /// Voltage over time, for the runtime estimate
+        battery: BatteryTrend,
+        /// Erase counts of the storage sectors, for the diagnostics page
+        wear: WearMap,
+        /// Stored settings, `None` when the flash couldn't be mounted
+        storage: Option<Storage<Rp2040Flash>>,
+        /// Recent faults, for the corner of the screen and the console
+        faults: FaultLog,
+        /// Drift measurement of `clock sync` on the console
+        clock_sync: ClockSync,
+        /// Date set by `time set` on the console
+        wall_clock: WallClock,
     }
 
     // Local resources to specific tasks (cannot be shared)
     #[local]
     struct Local {
-        screen: Screen,
-        led: LEDPin,
-        buffer: ArrayString<U200>,
+        /// Only taken while the SPI clock changes
+        screen: Option<Screen>,
+        /// Clock the SPI clock of the screen is divided from
+        peripheral_frequency: HertzU32,
+        /// Delay for re-initializing the screen, SysTick is not used by the monotonic
+        delay: cortex_m::delay::Delay,
+        chunk: ScreenChunk,
+        buffer: UiBuffer,
         button_a: APin,
         button_b: BPin,
         button_x: XPin,
         button_y: YPin,
+        debouncer: Debouncer,
+        stuck: StuckButtons,
+        inventory: Inventory,
+        buddy_rx: Reader<bsp::pac::UART1, BuddyPins>,
+        buddy_tx: Writer<bsp::pac::UART1, BuddyPins>,
+        i2c_peripheral: I2cPeripheral,
+        /// Accelerometer of an IMU breakout, for the shock detection
+        imu: Option<Lsm6ds3<BreakoutBus>>,
+        /// Console lines from the host
+        console_rx: DownChannel,
+        /// Console replies to the host
+        console_tx: UpChannel,
+        /// Flash `flash test` on the console wears out
+        scratch: Rp2040Flash,
+        /// Button macro of `macro save`, `None` when the flash couldn't be mounted
+        macros: Option<Storage<Rp2040Flash>>,
     }
 
-    #[init]
+    #[init(local = [samples: SampleRing = SampleRing::new()])]
     fn init(cx: init::Context) -> (Shared, Local, init::Monotonics) {
+        diagnostics::paint_stack();
+        // The defmt logs on the first up channel, the console has a channel each way
+        let channels = rtt_init! {
+            up: {
+                0: { size: 1024, mode: NoBlockSkip, name: "defmt" }
+                1: { size: 1024, mode: NoBlockSkip, name: "console" }
+            }
+            down: {
+                0: { size: 512, name: "console" }
+            }
+        };
+        rtt_target::set_defmt_channel(channels.up.0);
         info!("Program start");
+        #[cfg(feature = "csv-stream")]
+        defmt::println!("{=str}", dive_computer::csv_stream::HEADER);
         let mut pac = cx.device;
         let mut core = cx.core;
 
@@ -87,6 +280,12 @@ mod app {
         }
 
         // Enable watchdog and clocks
+        if let Some(crash) = dive_computer::panic::take_crash_record() {
+            warn!("Rebooted after a panic at {=str}:{=u32}", crash.file(), crash.line);
+        }
+        // Before anything draws, so a watchdog reset mid-dive goes straight back to the page it showed
+        let warm = warm_boot::take();
+
         let mut watchdog = Watchdog::new(pac.WATCHDOG);
         let clocks = init_clocks_and_plls(XOSC_CRYSTAL_FREQ, pac.XOSC, pac.CLOCKS, pac.PLL_SYS, pac.PLL_USB, &mut pac.RESETS, &mut watchdog)
             .ok()
@@ -103,6 +302,10 @@ mod app {
 
         let (explorer, pins) = PicoExplorer::new(pac.IO_BANK0, pac.PADS_BANK0, sio.gpio_bank0, pac.SPI0, adc, &mut pac.RESETS, &mut delay);
 
+        let peripheral_frequency = clocks.peripheral_clock.freq();
+        let (screen, spi_frequency) = render::set_spi_frequency(explorer.screen, peripheral_frequency, Settings::new().render.spi_frequency.hertz());
+        info!("Screen SPI clock: {=u32} Hz", spi_frequency.to_Hz());
+
         explorer.a.set_interrupt_enabled(EdgeLow, true);
         explorer.b.set_interrupt_enabled(EdgeLow, true);
         explorer.x.set_interrupt_enabled(EdgeLow, true);
@@ -112,9 +315,178 @@ mod app {
         explorer.b.set_interrupt_enabled(LevelLow, true);
         explorer.x.set_interrupt_enabled(LevelLow, true);
         explorer.y.set_interrupt_enabled(LevelLow, true);
+        // The release of Y ends a hold for the emergency ascent guide, of A or B a tap held back for a chord
+        explorer.a.set_interrupt_enabled(EdgeHigh, true);
+        explorer.b.set_interrupt_enabled(EdgeHigh, true);
+        explorer.y.set_interrupt_enabled(EdgeHigh, true);
+
+        // A button that is down already is jammed, it is warned about and doesn't repeat
+        let stuck = StuckButtons::at_boot([
+            explorer.a.is_low().unwrap(),
+            explorer.b.is_low().unwrap(),
+            explorer.x.is_low().unwrap(),
+            explorer.y.is_low().unwrap(),
+        ]);
+        // Faults of the boot, at 0 s as the monotonic doesn't run yet
+        let mut faults = FaultLog::new();
+
+        // The settings of the last boot, the defaults when there are none or they don't load
+        let region = factory_reset::SETTINGS;
+        let mut storage = Storage::mount(unsafe { Rp2040Flash::new(region.start, region.sectors) }, 0, region.sectors).ok();
+        // A record that doesn't load or failed its CRC is a fault, even when an older one took its place
+        let mut corrupt = false;
+        let stored = match storage.as_mut().map(|storage| storage.load::<Settings>()) {
+            Some(Ok(stored)) => stored,
+            Some(Err(error)) => {
+                warn!("stored settings did not load, using the defaults");
+                corrupt = error == StorageError::Corrupt;
+                None
+            }
+            None => {
+                warn!("storage did not mount, settings are not kept");
+                None
+            }
+        };
+        let settings = stored.unwrap_or_else(Settings::new);
+
+        // A saved button macro is ready to play, e.g. for the scripted dive of the instructor
+        let mut macros = Storage::mount(unsafe { Rp2040Flash::new(MACRO_START, MACRO_SECTORS) }, 0, MACRO_SECTORS).ok();
+        let mut button_macro = MacroRecorder::new();
+        if let Some(recording) = macros.as_mut().and_then(|macros| macros.load_macro()) {
+            info!("button macro of {=usize} presses loaded", recording.presses().len());
+            button_macro.load(recording);
+        }
+        corrupt |= [storage.as_ref(), macros.as_ref()].into_iter().flatten().any(|storage| storage.corrupt() > 0);
+        if corrupt {
+            warn!("corrupt records in the flash");
+            faults.record(Instant::from_ticks(0), FaultCode::StorageCorrupt);
+        }
+
+        let mut dive_computer = DiveComputer::default();
+        dive_computer.set_stuck_button(stuck.stuck());
+        apply_settings(&mut dive_computer, &settings);
+        if let Some(state) = &warm {
+            warn!("Warm boot on {=str} at {=u32}mm", state.page.as_str(), state.depth);
+            dive_computer.resume(state);
+        }
+
+        let pwm_slices = Slices::new(pac.PWM, &mut pac.RESETS);
+        // The buzzer sets the period of slice 0, the strobe shares it
+        let mut slice0 = pwm_slices.pwm0;
+        slice0.set_div_int(25);
+        slice0.set_top(BUZZER_TOP);
+        slice0.enable();
+        slice0.channel_a.output_to(pins.gpio0);
+        slice0.channel_b.output_to(pins.gpio1);
+        let mut slice4 = pwm_slices.pwm4;
+        slice4.enable();
+        slice4.channel_b.output_to(pins.led);
+        let mut outputs = Outputs::new(slice0.channel_a, slice0.channel_b, slice4.channel_b);
+        outputs.apply();
+
+        // Find out which breakouts are attached, a device acknowledges a one byte read
+        let mut i2c = I2C::i2c0(pac.I2C0, pins.i2c_sda, pins.i2c_scl, 100.kHz(), &mut pac.RESETS, clocks.system_clock.freq());
+        let inventory = Inventory::scan(|address| i2c.read(address, &mut [0]).is_ok());
+        info!("I2C devices found: {=u32}", inventory.device_count());
+        for peripheral in Peripheral::ALL {
+            match inventory.address(peripheral) {
+                Some(address) => info!("{=str} at {=u8:#x}", peripheral.as_str(), address),
+                None => info!("{=str} not found, disabled", peripheral.as_str()),
+            }
+        }
+
+        // Only the accelerometer uses the bus after the scan
+        let imu = match inventory.address(Peripheral::Imu).map(|address| Lsm6ds3::new(i2c, address)) {
+            Some(Ok(imu)) => Some(imu),
+            Some(Err(_)) => {
+                warn!("IMU did not start, shock detection disabled");
+                None
+            }
+            None => None,
+        };
+
+        // Everything optional is checked before it is used, so any subset of them works
+        let mut subsystems = Subsystems::new();
+        #[cfg(feature = "buzzer")]
+        subsystems.set(Subsystem::Buzzer, true);
+        // Without it the settings start from the defaults every boot
+        subsystems.set(Subsystem::Storage, storage.is_some());
+        // Without a pressure sensor the simulator provides the depth
+        subsystems.set(Subsystem::Sensors, inventory.has(Peripheral::PressureSensor));
+        // There is no USB stack yet, the console runs over RTT through the debug probe
+        subsystems.set(Subsystem::Usb, false);
+        for subsystem in Subsystem::ALL {
+            info!("{=str}: {=char}", subsystem.as_str(), subsystems.get(subsystem).symbol());
+        }
+
+        // Link to the buddy, 115200 baud 8N1
+        let buddy_pins = (pins.gpio4.into_mode::<gpio::FunctionUart>(), pins.gpio5.into_mode::<gpio::FunctionUart>());
+        let (mut buddy_rx, buddy_tx) = UartPeripheral::new(pac.UART1, buddy_pins, &mut pac.RESETS)
+            .enable(UartConfig::default(), clocks.peripheral_clock.freq())
+            .unwrap()
+            .split();
+        buddy_rx.enable_rx_interrupt();
+
+        // Registers for a controller on the other I2C bus
+        let i2c_peripheral = I2C::new_peripheral_event_iterator(
+            pac.I2C1,
+            pins.gpio2.into_mode::<gpio::FunctionI2C>(),
+            pins.gpio3.into_mode::<gpio::FunctionI2C>(),
+            &mut pac.RESETS,
+            i2c_slave::ADDRESS,
+        );
+        // Only interrupt on the events the iterator handles: RX_FULL, RD_REQ, STOP_DET, START_DET
+        // and RESTART_DET. A set bit unmasks, unlike the names of the PAC suggest
+        unsafe {
+            (*bsp::pac::I2C1::ptr()).ic_intr_mask.write(|w| w.bits(1 << 2 | 1 << 5 | 1 << 9 | 1 << 10 | 1 << 12));
+        }
+
+        // There is no battery backed clock, the time of day counts from midnight at boot until
+        // `time set` on the console sets it, see `wall_clock`
+        let midnight = DateTime {
+            year: 2000,
+            month: 1,
+            day: 1,
+            day_of_week: DayOfWeek::Saturday,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+        let rtc = RealTimeClock::new(pac.RTC, clocks.rtc_clock, &mut pac.RESETS, midnight).unwrap();
+
+        // Nothing is queued yet, a spawn that fails anyway is a fault instead of a panic
+        let spawned = [
+            queued(ui_output::spawn()).is_ok(),
+            queued(dive_tick::spawn(MicrosDurationU64::micros(0))).is_ok(),
+            queued(stack_report::spawn(STACK_REPORT_INTERVAL)).is_ok(),
+            queued(buzzer_output::spawn(BUZZER_TASK_INTERVAL)).is_ok(),
+            queued(buddy_link::spawn()).is_ok(),
+            queued(load_low::spawn()).is_ok(),
+            queued(load_high::spawn()).is_ok(),
+            queued(battery_monitor::spawn()).is_ok(),
+            queued(console_input::spawn()).is_ok(),
+            // Only poll the joystick when it is there, the ADC pins float otherwise
+            #[cfg(feature = "joystick")]
+            queued(joystick_input::spawn()).is_ok(),
+            #[cfg(feature = "thermistor")]
+            queued(temperature_input::spawn()).is_ok(),
+            imu.is_none() || queued(shock_input::spawn()).is_ok(),
+        ];
+        for _ in spawned.into_iter().filter(|&spawned| !spawned) {
+            faults.record(Instant::from_ticks(0), FaultCode::QueueOverflow);
+        }
 
-        ui_output::spawn(UI_TASK_INTERVAL).unwrap();
-        dive_tick::spawn(LOGIC_TICK_INTERVAL).unwrap();
+        // The BSP keeps its ADC to itself, sampling all inputs by DMA behind its back is safe as
+        // long as `PicoExplorer::get_adc` is never used. The pads keep their configuration when
+        // the pins are dropped.
+        let _ = (pins.adc0.into_floating_input(), pins.adc1.into_floating_input());
+        let _ = (pins.adc2.into_floating_input(), pins.voltage_monitor.into_floating_input());
+        let sampler = {
+            let mut pac = unsafe { bsp::pac::Peripherals::steal() };
+            // Powers up the ADC
+            let _ = Adc::new(pac.ADC, &mut pac.RESETS);
+            AdcSampler::start(cx.local.samples, &mut pac.RESETS)
+        };
 
         // Set the ARM SLEEPONEXIT bit to go to sleep after handling interrupts
         // See https://developer.arm.com/docs/100737/0100/power-management/sleep-mode/sleep-on-exit-bit
@@ -123,17 +495,67 @@ mod app {
         (
             // Initialization of shared resources
             Shared {
-                dive_computer: DiveComputer::default(),
+                dive_computer,
+                page: warm.map_or(Page::Main, |state| state.page),
+                settings,
+                editor: SettingsEditor::new(),
+                screen_saver: ScreenSaver::new(),
+                stats: RuntimeStats::new(),
+                button_lock: ButtonLock::new(),
+                help: HelpOverlay::new(),
+                planner: PlanEditor::new(),
+                blending: BlendCalculator::new(),
+                apnea: ApneaTimer::new(),
+                signal: MorseSignal::new(),
+                self_test: SelfTestReport::new(),
+                next_dive: NextDiveAlarm::new(),
+                rtc,
+                lifetime: LifetimeStats::new(),
+                // A warm boot skips the wizard, it ran before the reset or wasn't needed
+                boot: match warm {
+                    Some(_) => BootState::Running,
+                    None => BootState::new(stored.as_ref()),
+                },
+                sampler,
+                experiment: Experiment::new(),
+                outputs,
+                buddy: BuddyLink::new(),
+                subsystems,
+                failures: FailureInjector::new(),
+                factory_reset: FactoryReset::new(),
+                checklist: Checklist::new(),
+                cesa: Cesa::new(),
+                button_macro,
+                battery: BatteryTrend::new(),
+                wear: storage.as_ref().map_or(WearMap::new(), Storage::wear),
+                storage,
+                faults,
+                clock_sync: ClockSync::new(),
+                wall_clock: WallClock::new(),
             },
             // Initialization of task local resources
             Local {
-                screen: explorer.screen,
-                led: pins.led.into_push_pull_output(),
-                buffer: ArrayString::<U200>::new(),
+                screen: Some(screen),
+                peripheral_frequency,
+                delay,
+                chunk: ScreenChunk::new(),
+                buffer: UiBuffer::new(),
                 button_a: explorer.a,
                 button_b: explorer.b,
                 button_x: explorer.x,
                 button_y: explorer.y,
+                debouncer: Debouncer::new(Rp2040Clock),
+                stuck,
+                inventory,
+                buddy_rx,
+                buddy_tx,
+                i2c_peripheral,
+                imu,
+                console_rx: channels.down.0,
+                console_tx: channels.up.1,
+                // Below the records, the program is linked below both
+                scratch: unsafe { Rp2040Flash::new(SCRATCH_START, SCRATCH_SECTORS) },
+                macros,
             },
             // Move the monotonic timer to the RTIC run-time, this enables
             // scheduling
@@ -142,104 +564,1259 @@ mod app {
     }
 
     // Background task, runs whenever no other tasks are running
-    #[idle]
-    fn idle(_: idle::Context) -> ! {
+    #[idle(shared = [stats])]
+    fn idle(mut cx: idle::Context) -> ! {
         loop {
-            // Now Wait For Interrupt is used instead of a busy-wait loop
-            // to allow MCU to sleep between interrupts
-            // https://developer.arm.com/documentation/ddi0406/c/Application-Level-Architecture/Instruction-Details/Alphabetical-list-of-instructions/WFI
-            rtic::export::wfi();
+            // Interrupts are masked while sleeping, a pending interrupt still wakes us up but only
+            // runs after we have measured how long we slept
+            let slept = cortex_m::interrupt::free(|_| {
+                let start = monotonics::now();
+                // Now Wait For Interrupt is used instead of a busy-wait loop
+                // to allow MCU to sleep between interrupts
+                // https://developer.arm.com/documentation/ddi0406/c/Application-Level-Architecture/Instruction-Details/Alphabetical-list-of-instructions/WFI
+                rtic::export::wfi();
+                monotonics::now() - start
+            });
+
+            cx.shared.stats.lock(|stats| stats.record_idle(slept.to_micros() as u32));
         }
     }
 
-    #[task(shared = [dive_computer], local = [screen, led, buffer], priority = 2)]
-    fn ui_output(mut cx: ui_output::Context, interval: MicrosDurationU64) {
-        ui_output::spawn_after(interval, interval).unwrap();
+    // Spawn with `$spawn`, a full queue is recorded as a fault with the shared resources of `$cx`
+    macro_rules! spawn_or_fault {
+        ($cx:ident, $spawn:expr) => {
+            if queued($spawn).is_err() {
+                $cx.shared.faults.lock(|faults| faults.record(monotonics::now(), FaultCode::QueueOverflow));
+            }
+        };
+    }
+
+    #[task(shared = [dive_computer, page, settings, editor, screen_saver, stats, button_lock, help, planner, blending, apnea, signal, self_test, next_dive, rtc, lifetime, boot, sampler, experiment, outputs, subsystems, failures, factory_reset, checklist, cesa, battery, wear, faults], local = [screen, peripheral_frequency, spi_frequency: SpiFrequency = Settings::new().render.spi_frequency, delay, recovery: ScreenRecovery = ScreenRecovery::new(), chunk, heartbeat: bool = false, buffer, inventory, release: Option<u64> = None, shown: Option<(Page, bool, bool, ScreenState, Point, Background)> = None, frame_cache: FrameCache<Frame> = FrameCache::new()], priority = 2)]
+    fn ui_output(mut cx: ui_output::Context) {
+        started();
+        let start = monotonics::now();
+        let interval = (&mut cx.shared.settings, &mut cx.shared.experiment).lock(|settings, experiment| experiment.ui_interval(settings.refresh_rate.interval()));
+        spawn_or_fault!(cx, ui_output::spawn_after(interval));
 
-        let ui_output::LocalResources { screen, led, buffer } = cx.local;
+        let ui_output::LocalResources {
+            screen,
+            peripheral_frequency,
+            spi_frequency,
+            delay,
+            recovery,
+            chunk,
+            heartbeat,
+            buffer,
+            inventory,
+            release,
+            shown,
+            frame_cache,
+        } = cx.local;
+
+        // The render configuration in the settings applies from this frame on
+        let config = cx.shared.settings.lock(|settings| settings.render);
+        chunk.set_rows(config.chunk_rows.rows());
+        if config.spi_frequency != *spi_frequency {
+            if let Some(old) = screen.take() {
+                let (new, frequency) = render::set_spi_frequency(old, *peripheral_frequency, config.spi_frequency.hertz());
+                info!("Screen SPI clock: {=u32} Hz", frequency.to_Hz());
+                *screen = Some(new);
+            }
+            *spi_frequency = config.spi_frequency;
+        }
+        let Some(screen) = screen.as_mut() else {
+            return;
+        };
 
-        if led.is_set_low().unwrap() {
+        *heartbeat = !*heartbeat;
+        let duty = if *heartbeat {
             info!("on!");
-            led.set_high().unwrap();
+            100
         } else {
             info!("off!");
-            led.set_low().unwrap();
-        }
+            0
+        };
+        cx.shared.outputs.lock(|outputs| outputs.set(Channel::Led, Source::User, Some(duty)));
 
-        buffer.clear();
+        let now = monotonics::now();
+        let (alarm, alarm_color, diving, depth) = cx
+            .shared
+            .dive_computer
+            .lock(|dive_computer| (dive_computer.alarm(), dive_computer.reserve().color(), dive_computer.diving(), dive_computer.depth()));
+        let cesa = cx.shared.cesa.lock(|cesa| {
+            cesa.update(diving);
+            cesa.is_active()
+        });
+        let (state, offset) = (&mut cx.shared.screen_saver, &mut cx.shared.settings).lock(|screen_saver, settings| {
+            // Alarms and the emergency ascent guide have to be seen
+            if alarm != Alarm::None || cesa {
+                screen_saver.wake(now, &settings.screen_saver);
+            }
+            (screen_saver.state(now, &settings.screen_saver), screen_saver.offset(now, &settings.screen_saver))
+        });
 
-        cx.shared.dive_computer.lock(|dive_computer| {
-            // Write to buffer
-            writeln!(buffer, "{}", dive_computer).unwrap();
+        let setup = cx.shared.boot.lock(|boot| boot.in_setup());
+        let page = cx.shared.page.lock(|page| *page);
+        let help = cx.shared.help.lock(|help| help.visible(now));
+        let reset = cx.shared.factory_reset.lock(|reset| reset.is_open());
+        let checklist = cx.shared.checklist.lock(|checklist| checklist.is_open());
+        let locked = cx.shared.button_lock.lock(|button_lock| {
+            button_lock.update(alarm);
+            button_lock.locked()
         });
 
-        // Draw buffer on screen
-        let style = MonoTextStyleBuilder::new()
-            .font(&FONT_10X20)
-            .text_color(Rgb565::GREEN)
-            .background_color(Rgb565::BLACK)
-            .build();
-        Text::with_alignment(buffer, Point::new(20, 30), style, Alignment::Left).draw(screen).unwrap();
+        // The main page shows the depth in its background during a dive, a dimmed screen stays black
+        let background = match page {
+            Page::Main if diving && !cesa && !help && !setup && !reset && !checklist && state == ScreenState::On => Background::Depth(DepthGradient::new(depth)),
+            _ => Background::Solid(Theme::default().background_color),
+        };
+
+        let mut result: Result<(), DrawError> = Ok(());
+
+        // Remove the leftovers of the previous page or position, this also blanks the screen
+        let overlay = cesa || help || setup || reset || checklist;
+        if Some((page, overlay, diving, state, offset, background)) != *shown {
+            result = background.fill(screen.bounding_box(), screen);
+            *shown = Some((page, overlay, diving, state, offset, background));
+            frame_cache.invalidate();
+        }
+
+        if state != ScreenState::Blank {
+            buffer.clear();
+            let mut frame = Frame::new(locked, alarm_color, state);
+
+            match page {
+                // Ahead of everything, the guide is for getting out of the water alive
+                _ if cesa => cx.shared.dive_computer.lock(|dive_computer| {
+                    let guide = CesaGuide::new(dive_computer, now);
+                    // Write to buffer
+                    writeln!(buffer, "{}", guide);
+                    frame.emergency = Some(guide);
+                }),
+                _ if setup => (&mut cx.shared.boot, &mut cx.shared.settings).lock(|boot, settings| {
+                    if let BootState::Setup(wizard) = boot {
+                        // Write to buffer
+                        writeln!(buffer, "{}", wizard.page(settings));
+                    }
+                }),
+                _ if reset => cx.shared.factory_reset.lock(|reset| {
+                    // Write to buffer
+                    writeln!(buffer, "{}", reset);
+                }),
+                _ if checklist => cx.shared.checklist.lock(|checklist| {
+                    // Write to buffer
+                    writeln!(buffer, "{}", checklist);
+                }),
+                page if help => cx.shared.settings.lock(|settings| {
+                    // Write to buffer
+                    writeln!(buffer, "{}", HelpPage::new(page, &settings.bindings));
+                }),
+                Page::Main if !diving => {
+                    let time = cx.shared.rtc.lock(|rtc| rtc.now().ok()).map(|now| TimeOfDay {
+                        hours: now.hour,
+                        minutes: now.minute,
+                    });
+                    let battery = cx.shared.sampler.lock(|sampler| {
+                        sampler.keep_running();
+                        sampler.average(AdcInput::Vsys)
+                    });
+                    let battery = battery.map(|raw| battery_percent(vsys_millivolts(raw)));
+                    let battery = cx.shared.failures.lock(|failures| failures.battery(battery));
+                    cx.shared
+                        .dive_computer
+                        .lock(|dive_computer| frame.write_dive_page(buffer, page, dive_computer, time, battery));
+                }
+                Page::Main | Page::Warnings | Page::Tech | Page::Deco | Page::SafetyStop => {
+                    cx.shared
+                        .dive_computer
+                        .lock(|dive_computer| frame.write_dive_page(buffer, page, dive_computer, None, None));
+                }
+                Page::Diagnostics => (
+                    &mut cx.shared.dive_computer,
+                    &mut cx.shared.stats,
+                    &mut cx.shared.lifetime,
+                    &mut cx.shared.subsystems,
+                    &mut cx.shared.battery,
+                    &mut cx.shared.wear,
+                )
+                    .lock(|dive_computer, stats, lifetime, subsystems, battery, wear| {
+                        stats.queue = cortex_m::interrupt::free(|cs| QUEUE.borrow(cs).get());
+                        // Write to buffer
+                        writeln!(buffer, "{}", stats);
+                        writeln!(buffer, "{}", battery.runtime());
+                        writeln!(buffer, "{}", wear);
+                        writeln!(buffer, "{}", dive_computer.replay());
+                        writeln!(buffer, "EXERTION: {:>10}", dive_computer.exertion().as_str());
+                        writeln!(buffer, "{}", subsystems);
+                        writeln!(buffer, "{}", inventory);
+                        writeln!(buffer, "{}", lifetime);
+                    }),
+                Page::Planner => (&mut cx.shared.dive_computer, &mut cx.shared.planner, &mut cx.shared.next_dive).lock(|dive_computer, planner, next_dive| {
+                    let result = dive_computer
+                        .planning_allowed()
+                        .then(|| planner.plan.evaluate(dive_computer.deco(), dive_computer.ascent().limit()));
+                    // Write to buffer
+                    writeln!(buffer, "{}", planner.page(result.as_ref(), dive_computer.depth_display()));
+                    writeln!(buffer, "{}", next_dive.line(dive_computer.surface_interval()));
+                }),
+                Page::Blending => cx.shared.blending.lock(|blending| {
+                    // Write to buffer
+                    writeln!(buffer, "{}", blending);
+                }),
+                Page::Apnea => (&mut cx.shared.settings, &mut cx.shared.apnea).lock(|settings, apnea| {
+                    // Write to buffer
+                    writeln!(buffer, "{}", apnea.page(&settings.apnea));
+                }),
+                Page::Signal => cx.shared.signal.lock(|signal| {
+                    // Write to buffer
+                    writeln!(buffer, "{}", signal);
+                }),
+                Page::Settings => (&mut cx.shared.settings, &mut cx.shared.editor).lock(|settings, editor| {
+                    // Write to buffer
+                    writeln!(buffer, "{}", editor.page(settings));
+                }),
+                Page::SelfTest => cx.shared.self_test.lock(|report| {
+                    // Write to buffer
+                    writeln!(buffer, "{}", report);
+                }),
+                Page::Failures => cx.shared.failures.lock(|failures| {
+                    // Write to buffer
+                    writeln!(buffer, "{}", failures);
+                }),
+            }
+            // Whatever didn't fit is cut off, the code in the corner tells
+            let fault = cx.shared.faults.lock(|faults| {
+                if buffer.truncated() {
+                    faults.record(now, FaultCode::RenderOverflow);
+                }
+                faults.shown(now)
+            });
+
+            frame.text = *buffer;
+            frame.fault = fault;
+
+            // Skip the refresh when the frame looks the same as the last one
+            // With the same widgets only the text that changed is sent
+            let previous = frame_cache.last().filter(|last| last.same_widgets(&frame)).map(|last| last.text);
+            let drawn = frame_cache.changed(&frame);
+            cx.shared.stats.lock(|stats| stats.record_frame(drawn));
+
+            if drawn {
+                // Draw buffer on screen
+                let theme = frame.theme();
+                // Over a gradient the text and widgets leave the background to the fill of the rows
+                let solid = background.solid();
+                let widgets = frame.text_widgets(&theme, offset, solid);
+                let text = &widgets.text;
+                let dirty = previous.and_then(|previous| {
+                    DirtyRegions::between(
+                        previous.as_str(),
+                        buffer.as_str(),
+                        text.bounding_box().top_left,
+                        text.character_style.font.character_size,
+                    )
+                });
+                let draw_start = monotonics::now();
+                if !config.batch && solid.is_none() {
+                    // Without a batch the rows are filled on the screen first, the text flickers
+                    let bounds = text.bounding_box();
+                    let rows = Rectangle::new(Point::new(0, bounds.top_left.y), Size::new(u32::from(render::SCREEN_SIZE), bounds.size.height));
+                    result = result.and_then(|()| background.fill(rows, screen));
+                }
+                result = result.and_then(|()| {
+                    if config.batch {
+                        // The widgets are within the rows of the text, so they have to go in the same batch
+                        chunk.draw_dirty(&widgets, text.bounding_box(), dirty.as_ref(), background, screen)
+                    } else {
+                        widgets.draw(screen)
+                    }
+                });
+                // Below the text, so outside of its batch
+                result = result.and_then(|()| frame.draw_below(&theme, offset, solid, screen));
+                debug!("draw took {=u64} us", (monotonics::now() - draw_start).to_micros());
+            }
+        }
+
+        if result.is_err() {
+            // Whatever made it to the screen, draw the whole frame again
+            frame_cache.invalidate();
+            if recovery.failed(now) {
+                warn!("screen draw failed, re-initializing the screen ({=u32} times so far)", recovery.reinits);
+                if render::reinit(screen, delay).is_err() {
+                    warn!("screen re-initialization failed");
+                }
+                // The screen is blank after a reset, clear it before the next frame
+                *shown = None;
+            }
+        }
+
+        // Due when the previous run asked for it, the first run right away
+        let start_us = start.duration_since_epoch().to_micros();
+        let release_us = release.replace(start_us + interval.to_micros()).unwrap_or(start_us);
+
+        let end = monotonics::now();
+        cx.shared.stats.lock(|stats| {
+            if result.is_ok() {
+                stats.input.shown(end);
+            }
+            stats.ui.record((end - start).to_micros() as u32);
+            stats.ui.record_deadline(release_us, end.duration_since_epoch().to_micros(), interval.to_micros());
+        });
     }
 
-    #[task(shared = [dive_computer], local = [], priority = 2)]
+    /// Advance the simulation to now, `interval` is the time since the previous tick
+    #[task(shared = [dive_computer, page, stats, lifetime, subsystems, failures, faults], local = [release: Option<u64> = None, #[cfg(feature = "csv-stream")] ticks: u32 = 0, auto_page: AutoPage = AutoPage::new(), sensor_fault: bool = false], priority = 2)]
     fn dive_tick(mut cx: dive_tick::Context, interval: MicrosDurationU64) {
-        dive_tick::spawn_after(interval, interval).unwrap();
+        started();
+        let start = monotonics::now();
 
+        // Injected failures go in before the tick, like a sensor reading would
+        (&mut cx.shared.failures, &mut cx.shared.dive_computer).lock(|failures, dive_computer| {
+            failures.tick(interval);
+            if failures.take_air_loss() {
+                dive_computer.lose_air(AIR_LOSS_PERCENT);
+            }
+            if let Some(depth) = failures.depth(dive_computer.depth()) {
+                dive_computer.update_sensor(Ok(depth));
+            } else if failures.take_cleared() {
+                dive_computer.release_sensor();
+            }
+        });
+
+        let (next_interval, finished_dive, sensor_fault) = cx.shared.dive_computer.lock(|dive_computer| {
+            dive_computer.tick();
+            (
+                dive_computer.tick_interval(),
+                dive_computer.take_finished_dive(),
+                dive_computer.sensor_fault().is_some(),
+            )
+        });
+        if let Some(dive) = finished_dive {
+            cx.shared.lifetime.lock(|lifetime| lifetime.record(&dive));
+        }
+        if let Some(log) = cx.shared.dive_computer.lock(|dive_computer| dive_computer.take_finished_log()) {
+            spawn_or_fault!(cx, save_dive_log::spawn(log));
+        }
+        // Show the decompression and safety stop pages when they matter, until the diver picks a page
+        let conditions = cx.shared.dive_computer.lock(|dive_computer| DiveConditions {
+            diving: dive_computer.diving(),
+            ceiling: dive_computer.deco().ceiling() > 0,
+            safety_stop: dive_computer.safety_stop().due(dive_computer.depth()).is_some(),
+        });
+        let auto_page = cx.local.auto_page;
+        let page = cx.shared.page.lock(|page| {
+            *page = auto_page.update(*page, conditions);
+            *page
+        });
+        // Kept every tick, a watchdog reset loses a tick at most
         cx.shared.dive_computer.lock(|dive_computer| {
-            dive_computer.change_depth(MicrosDurationU32::try_from(interval).unwrap());
+            warm_boot::store(&RetainedState {
+                page,
+                depth: dive_computer.depth(),
+                rate: dive_computer.rate(),
+                air: dive_computer.air(),
+                edt: dive_computer.edt(),
+            })
+        });
+        if sensor_fault {
+            // The dive computer fell back to the simulator already
+            cx.shared.subsystems.lock(|subsystems| subsystems.fault(Subsystem::Sensors));
+        }
+        // Once per fault, it lasts until the sensor is released
+        if sensor_fault && !*cx.local.sensor_fault {
+            cx.shared.faults.lock(|faults| faults.record(start, FaultCode::SensorFault));
+        }
+        *cx.local.sensor_fault = sensor_fault;
+
+        #[cfg(feature = "csv-stream")]
+        {
+            *cx.local.ticks = cx.local.ticks.wrapping_add(1);
+            let state = cx.shared.dive_computer.lock(|dive_computer| dive_computer.snapshot());
+            let line = dive_computer::csv_stream::CsvLine { tick: *cx.local.ticks, state };
+            defmt::println!("{=str}", line.text().as_str());
+        }
+
+        let next_interval = MicrosDurationU64::from(next_interval);
+        spawn_or_fault!(cx, dive_tick::spawn_after(next_interval, next_interval));
+        // Due when the previous run asked for it, the first run right away
+        let start_us = start.duration_since_epoch().to_micros();
+        let next_us = monotonics::now().duration_since_epoch().to_micros() + next_interval.to_micros();
+        let release_us = cx.local.release.replace(next_us).unwrap_or(start_us);
+
+        let end = monotonics::now();
+        cx.shared.stats.lock(|stats| {
+            stats.tick.record((end - start).to_micros() as u32);
+            stats.tick.record_deadline(release_us, end.duration_since_epoch().to_micros(), interval.to_micros());
+            // The logic tick doubles as the measuring window for the CPU load
+            stats.end_window(interval.to_micros());
+        });
+    }
+
+    /// Beep while the ascent is too fast, the air is at the reserve or for the apnea cues, flash the
+    /// strobe on high alarms, blink the code of the alarm on the LED, and send the Morse signal with both
+    #[task(shared = [dive_computer, settings, apnea, signal, next_dive, outputs, subsystems, faults], priority = 2)]
+    fn buzzer_output(mut cx: buzzer_output::Context, interval: MicrosDurationU64) {
+        started();
+        spawn_or_fault!(cx, buzzer_output::spawn_after(interval, interval));
+
+        // The apnea timer counts real time in the ticks of this task
+        let cue = cx.shared.apnea.lock(|apnea| {
+            apnea.tick(interval);
+            apnea.beeping()
+        });
+        let morse = cx.shared.signal.lock(|signal| {
+            signal.tick(interval);
+            signal.on()
+        });
+        let ringing = cx.shared.next_dive.lock(|next_dive| {
+            next_dive.tick(interval);
+            next_dive.ringing()
+        });
+
+        let now = monotonics::now();
+        let ready = ringing && buzzer::beeping(now, READY_BEEP_INTERVAL);
+        let mode = cx.shared.settings.lock(|settings| settings.strobe);
+        let (buzzing, strobing, blinking) = cx
+            .shared
+            .dive_computer
+            .lock(|dive_computer| (dive_computer.buzzing(now), dive_computer.strobing(now, mode), dive_computer.blinking(now)));
+        // Without the buzzer it stays silent, the strobe and screen still show the alarms
+        let buzzer = cx.shared.subsystems.lock(|subsystems| subsystems.available(Subsystem::Buzzer));
+        let sound = |on: bool| (buzzer && on).then_some(BUZZER_DUTY);
+        let light = |on: bool| on.then_some(100);
+        cx.shared.outputs.lock(|outputs| {
+            outputs.set(Channel::Buzzer, Source::Alarm, sound(buzzing));
+            outputs.set(Channel::Buzzer, Source::Cue, sound(cue || morse || ready));
+            outputs.set(Channel::Strobe, Source::Alarm, light(strobing));
+            outputs.set(Channel::Strobe, Source::Cue, light(morse));
+            // The LED blinks the code of the alarm over the heartbeat, its pauses included
+            outputs.set(Channel::Led, Source::Alarm, blinking.map(|on| if on { 100 } else { 0 }));
+            outputs.apply();
+        });
+    }
+
+    /// Send our state to the buddy, or to the MQTT-SN bridge, and pass on what is known about the buddy
+    ///
+    /// The instructor unit sends the changes of its scenario to the students instead.
+    #[task(shared = [dive_computer, buddy, settings, failures, button_macro, faults], local = [buddy_tx, #[cfg(all(feature = "mqtt-gateway", not(feature = "instructor")))] gateway: Gateway = Gateway::new(), #[cfg(feature = "instructor")] broadcaster: Broadcaster = Broadcaster::new()], priority = 1)]
+    fn buddy_link(mut cx: buddy_link::Context) {
+        started();
+        spawn_or_fault!(cx, buddy_link::spawn_after(SEND_INTERVAL));
+
+        let now = monotonics::now();
+        let status = cx.shared.buddy.lock(|buddy| buddy.status(now));
+        cx.shared.dive_computer.lock(|dive_computer| dive_computer.set_buddy(status));
+
+        let tx = cx.local.buddy_tx;
+        #[cfg(feature = "instructor")]
+        {
+            let scenario = Scenario {
+                exertion: cx.shared.dive_computer.lock(|dive_computer| dive_computer.exertion()),
+                failure: cx.shared.failures.lock(|failures| failures.armed()),
+                playing: cx.shared.button_macro.lock(|button_macro| button_macro.is_playing()),
+            };
+            let to = cx.shared.settings.lock(|settings| settings.bus.target());
+            for command in cx.local.broadcaster.changes(scenario) {
+                let mut buf = [0; MAX_FRAME_LEN];
+                if let Ok(frame) = (InstructorFrame { to, command }).encode(&mut buf) {
+                    // Outside of the lock, this waits for the transmit FIFO when several changes go out
+                    tx.write_full_blocking(frame);
+                }
+            }
+        }
+        // The instructor unit doesn't publish to a bridge
+        #[cfg(all(feature = "mqtt-gateway", not(feature = "instructor")))]
+        {
+            let readings = cx.shared.dive_computer.lock(|dive_computer| Readings::of(dive_computer));
+            // Outside of the lock, this waits for the transmit FIFO
+            cx.local.gateway.publish(&readings, now, |message| tx.write_full_blocking(message));
+        }
+        #[cfg(not(any(feature = "instructor", feature = "mqtt-gateway")))]
+        {
+            let telemetry = cx.shared.dive_computer.lock(|dive_computer| dive_computer.telemetry());
+            let mut buf = [0; MAX_FRAME_LEN];
+            if let Ok(frame) = telemetry.encode(&mut buf) {
+                // Fits in the transmit FIFO, so this doesn't wait
+                tx.write_full_blocking(frame);
+            }
+        }
+    }
+
+    /// Collect the bytes from the buddy, or the commands from the instructor bus
+    #[task(binds = UART1_IRQ, shared = [buddy, settings, dive_computer, failures, button_macro, stats], local = [buddy_rx, student: StudentLink = StudentLink::new()])]
+    fn buddy_input(mut cx: buddy_input::Context) {
+        cx.shared.stats.lock(|stats| stats.record_irq());
+        let now = monotonics::now();
+        let bus = cx.shared.settings.lock(|settings| settings.bus);
+        let mut bytes = [0; MAX_FRAME_LEN];
+        // Stops at an empty FIFO or a receive error, the interrupt fires again for what is left
+        while let Ok(count) = cx.local.buddy_rx.read_raw(&mut bytes) {
+            if bus == BusAddress::Off {
+                cx.shared.buddy.lock(|buddy| {
+                    for &byte in &bytes[..count] {
+                        buddy.receive(byte, now);
+                    }
+                });
+                continue;
+            }
+
+            for &byte in &bytes[..count] {
+                match cx.local.student.receive(byte, bus) {
+                    Some(ScenarioCommand::SetCurrent(exertion)) => cx.shared.dive_computer.lock(|dive_computer| dive_computer.set_exertion(exertion)),
+                    Some(ScenarioCommand::InjectFailure { failure, delay_min }) => cx.shared.failures.lock(|failures| failures.inject(failure, delay_min.into())),
+                    Some(ScenarioCommand::StopFailure) => cx.shared.failures.lock(|failures| failures.stop()),
+                    Some(ScenarioCommand::StartScriptedDive) => {
+                        cx.shared.button_macro.lock(|button_macro| button_macro.play(now));
+                        // Already polling when it was playing
+                        let _ = queued(replay_macro::spawn());
+                    }
+                    None => {}
+                }
+            }
+        }
+    }
+
+    /// Answer the I2C controller from the register map
+    #[task(binds = I2C1_IRQ, shared = [dive_computer, stats], local = [i2c_peripheral, registers: RegisterMap = RegisterMap::new()])]
+    fn i2c_input(mut cx: i2c_input::Context) {
+        cx.shared.stats.lock(|stats| stats.record_irq());
+        let (i2c, registers) = (cx.local.i2c_peripheral, cx.local.registers);
+        while let Some(event) = i2c.next() {
+            match event {
+                I2CEvent::Start | I2CEvent::Restart => cx.shared.dive_computer.lock(|dive_computer| registers.start(dive_computer)),
+                I2CEvent::TransferWrite => {
+                    let mut bytes = [0; 16];
+                    let count = i2c.read(&mut bytes);
+                    for &byte in &bytes[..count] {
+                        registers.write(byte);
+                    }
+                }
+                // One byte per request, the controller decides when to stop
+                I2CEvent::TransferRead => {
+                    i2c.write(&[registers.read()]);
+                }
+                I2CEvent::Stop => {}
+            }
+        }
+    }
+
+    /// Keep the CPU busy below every other task while an experiment asks for it
+    #[task(shared = [experiment, faults], priority = 1)]
+    fn load_low(mut cx: load_low::Context) {
+        started();
+        let load = cx.shared.experiment.lock(|experiment| experiment.load(LoadPriority::Low));
+        spawn_or_fault!(cx, load_low::spawn_after(load.map_or(LOAD_POLL_INTERVAL, |load| load.period.convert().into())));
+        if let Some(load) = load {
+            spin(load.busy.into());
+        }
+    }
+
+    /// Keep the CPU busy above the screen and the simulation while an experiment asks for it
+    #[task(shared = [experiment, faults], priority = 3)]
+    fn load_high(mut cx: load_high::Context) {
+        started();
+        let load = cx.shared.experiment.lock(|experiment| experiment.load(LoadPriority::High));
+        spawn_or_fault!(cx, load_high::spawn_after(load.map_or(LOAD_POLL_INTERVAL, |load| load.period.convert().into())));
+        if let Some(load) = load {
+            spin(load.busy.into());
+        }
+    }
+
+    #[task(shared = [faults], priority = 1)]
+    fn stack_report(mut cx: stack_report::Context, interval: MicrosDurationU64) {
+        started();
+        spawn_or_fault!(cx, stack_report::spawn_after(interval, interval));
+
+        diagnostics::report_stack();
+    }
+
+    /// Go to the next target of the next dive alarm and set the RTC alarm for it, at the lowest
+    /// priority as simulating the surface interval takes a while
+    #[task(shared = [dive_computer, planner, next_dive, rtc], priority = 1)]
+    fn arm_next_dive(mut cx: arm_next_dive::Context) {
+        started();
+        let (surface_interval, deco, limit, time_scale) = cx.shared.dive_computer.lock(|dive_computer| {
+            (
+                dive_computer.surface_interval(),
+                *dive_computer.deco(),
+                dive_computer.ascent().limit(),
+                dive_computer.time_scale(),
+            )
+        });
+        let plan = cx.shared.planner.lock(|planner| planner.plan);
+
+        let mut next_dive = cx.shared.next_dive.lock(|next_dive| *next_dive);
+        next_dive.next_target(surface_interval, &deco, &plan, limit);
+        cx.shared.next_dive.lock(|shared| *shared = next_dive);
+
+        let wait = next_dive.wait(surface_interval, time_scale);
+        cx.shared.rtc.lock(|rtc| schedule_wake(rtc, wait));
+    }
+
+    /// The RTC alarm for the next dive alarm went off, ring when the next dive is ready
+    #[task(binds = RTC_IRQ, shared = [dive_computer, next_dive, rtc, screen_saver, settings, stats])]
+    fn next_dive_wake(mut cx: next_dive_wake::Context) {
+        cx.shared.stats.lock(|stats| stats.record_irq());
+        let (surface_interval, time_scale) = cx
+            .shared
+            .dive_computer
+            .lock(|dive_computer| (dive_computer.surface_interval(), dive_computer.time_scale()));
+        let (ringing, wait) = cx
+            .shared
+            .next_dive
+            .lock(|next_dive| (next_dive.check(surface_interval), next_dive.wait(surface_interval, time_scale)));
+
+        if ringing {
+            info!("next dive ready");
+            (&mut cx.shared.screen_saver, &mut cx.shared.settings).lock(|screen_saver, settings| screen_saver.wake(monotonics::now(), &settings.screen_saver));
+        }
+        // Still waiting when the time scale changed since it was armed
+        cx.shared.rtc.lock(|rtc| {
+            rtc.clear_interrupt();
+            schedule_wake(rtc, wait);
+        });
+    }
+
+    /// Run the self test at the lowest priority, the checks take a while
+    #[task(shared = [page, self_test], priority = 1)]
+    fn run_self_test(mut cx: run_self_test::Context) {
+        started();
+        let report = self_test::run();
+        info!("self test: {} of {} passed", report.passed(), report.total());
+        cx.shared.self_test.lock(|self_test| *self_test = report);
+        cx.shared.page.lock(|page| *page = Page::SelfTest);
+    }
+
+    /// Store the settings after a change, when there is a storage to keep them in
+    ///
+    /// Spawned after every change, a save that is pending already stores the later changes too, so
+    /// a failed spawn is fine. Nothing else runs while the flash is written.
+    #[task(shared = [settings, storage, subsystems, wear], priority = 1)]
+    fn save_settings(mut cx: save_settings::Context) {
+        started();
+        if !cx.shared.subsystems.lock(|subsystems| subsystems.available(Subsystem::Storage)) {
+            return;
+        }
+        let settings = cx.shared.settings.lock(|settings| *settings);
+        let stored = cx
+            .shared
+            .storage
+            .lock(|storage| storage.as_mut().map(|storage| storage.store(&settings).map(|()| storage.wear())));
+        match stored {
+            Some(Ok(wear)) => cx.shared.wear.lock(|shared| *shared = wear),
+            Some(Err(_)) => {
+                warn!("settings not stored");
+                cx.shared.subsystems.lock(|subsystems| subsystems.fault(Subsystem::Storage));
+            }
+            None => {}
+        }
+    }
+
+    /// Store the log of a dive that ended in the logbook region
+    ///
+    /// The logbook is mounted at the first dive. A log that isn't stored is still on the surface
+    /// page until the next dive.
+    #[task(shared = [subsystems], local = [logbook: Option<Storage<Rp2040Flash>> = None], priority = 1)]
+    fn save_dive_log(mut cx: save_dive_log::Context, log: DiveLog) {
+        started();
+        let logbook = cx.local.logbook;
+        if logbook.is_none() {
+            let region = factory_reset::LOGBOOK;
+            *logbook = Storage::mount(unsafe { Rp2040Flash::new(region.start, region.sectors) }, 0, region.sectors).ok();
+        }
+        match logbook.as_mut().map(|logbook| logbook.store(&log)) {
+            Some(Ok(())) => info!("dive log stored"),
+            _ => {
+                warn!("dive log not stored");
+                cx.shared.subsystems.lock(|subsystems| subsystems.fault(Subsystem::Storage));
+            }
+        }
+    }
+
+    /// Erase the next region of a confirmed factory reset, then the one after, and reboot when all are erased
+    #[task(shared = [factory_reset, storage, faults], priority = 1)]
+    fn erase_records(mut cx: erase_records::Context) {
+        started();
+        let more = (&mut cx.shared.factory_reset, &mut cx.shared.storage).lock(|reset, storage| {
+            reset.step(|region| {
+                info!("erasing {=str}", region.name);
+                match storage.take() {
+                    // The settings are mounted on their region, they go with it so no save writes them again
+                    Some(mut mounted) if *region == factory_reset::SETTINGS => mounted.erase().map_err(drop),
+                    mounted => {
+                        *storage = mounted;
+                        // Nothing else has a window on the other regions
+                        let mut flash = unsafe { Rp2040Flash::new(region.start, region.sectors) };
+                        erase_sectors(&mut flash, 0, region.sectors).map_err(drop)
+                    }
+                }
+            })
         });
+
+        if more {
+            spawn_or_fault!(cx, erase_records::spawn());
+        } else if cx.shared.factory_reset.lock(|reset| reset.state()) == ResetState::Done {
+            spawn_or_fault!(cx, reboot::spawn_after(REBOOT_DELAY));
+        }
+    }
+
+    #[task(priority = 1)]
+    fn reboot(_: reboot::Context) {
+        started();
+        info!("rebooting");
+        // The records are gone, the dive is not restored either
+        warm_boot::forget();
+        cortex_m::peripheral::SCB::sys_reset();
+    }
+
+    // Perform `$action` with the shared resources of the task context `$cx`
+    macro_rules! perform {
+        ($cx:ident, $action:expr) => {
+            match $action {
+                Action::None => {}
+                action if $cx.shared.boot.lock(|boot| boot.in_setup()) => {
+                    let finished = (&mut $cx.shared.boot, &mut $cx.shared.settings).lock(|boot, settings| boot.perform(action, settings).then_some(*settings));
+                    if let Some(settings) = finished {
+                        info!("setup done");
+                        $cx.shared.dive_computer.lock(|dive_computer| {
+                            dive_computer.set_unit(settings.unit);
+                            dive_computer.set_tank(settings.tank);
+                        });
+                        let _ = queued(save_settings::spawn_after(SETTINGS_SAVE_DELAY));
+                    }
+                }
+                Action::ToggleUnit => {
+                    let unit = $cx.shared.settings.lock(|settings| {
+                        settings.unit = settings.unit.next();
+                        settings.unit
+                    });
+                    $cx.shared.dive_computer.lock(|dive_computer| dive_computer.set_unit(unit));
+                    let _ = queued(save_settings::spawn_after(SETTINGS_SAVE_DELAY));
+                }
+                Action::NextPage => $cx.shared.page.lock(|page| *page = page.next()),
+                Action::Help => $cx.shared.help.lock(|help| help.show(monotonics::now())),
+                // Already running when the spawn fails
+                Action::SelfTest => {
+                    let _ = queued(run_self_test::spawn());
+                }
+                Action::ReadyAlarm => {
+                    let _ = queued(arm_next_dive::spawn());
+                }
+                Action::Failures => $cx.shared.page.lock(|page| *page = Page::Failures),
+                Action::FactoryReset => $cx.shared.factory_reset.lock(|reset| reset.open()),
+                // The first descend at the surface shows the checklist, the next one starts the dive
+                Action::IncreaseRate if $cx.shared.dive_computer.lock(|dive_computer| dive_computer.checklist_due()) => {
+                    $cx.shared.checklist.lock(|checklist| checklist.open())
+                }
+                action @ (Action::SelectItem | Action::ChangeItem | Action::StartTimer) if $cx.shared.page.lock(|page| *page) == Page::Failures => {
+                    $cx.shared.failures.lock(|failures| failures.perform(action))
+                }
+                action @ (Action::SelectItem | Action::ChangeItem) if $cx.shared.page.lock(|page| *page) == Page::Planner => {
+                    $cx.shared.planner.lock(|planner| planner.perform(action))
+                }
+                action @ (Action::SelectItem | Action::ChangeItem) if $cx.shared.page.lock(|page| *page) == Page::Blending => {
+                    $cx.shared.blending.lock(|blending| blending.perform(action))
+                }
+                action @ (Action::SelectItem | Action::ChangeItem | Action::StartTimer) if $cx.shared.page.lock(|page| *page) == Page::Apnea => {
+                    (&mut $cx.shared.apnea, &mut $cx.shared.settings).lock(|apnea, settings| apnea.perform(action, &mut settings.apnea));
+                    let _ = queued(save_settings::spawn_after(SETTINGS_SAVE_DELAY));
+                }
+                action @ (Action::SelectItem | Action::ChangeItem | Action::StartTimer) if $cx.shared.page.lock(|page| *page) == Page::Signal => {
+                    let at_surface = $cx.shared.dive_computer.lock(|dive_computer| !dive_computer.diving());
+                    $cx.shared.signal.lock(|signal| signal.perform(action, at_surface))
+                }
+                action @ (Action::SelectItem | Action::ChangeItem | Action::SelectSection) => {
+                    let settings = (&mut $cx.shared.settings, &mut $cx.shared.editor).lock(|settings, editor| {
+                        editor.perform(action, settings);
+                        *settings
+                    });
+                    $cx.shared.dive_computer.lock(|dive_computer| apply_settings(dive_computer, &settings));
+                    let _ = queued(save_settings::spawn_after(SETTINGS_SAVE_DELAY));
+                }
+                action => $cx.shared.dive_computer.lock(|dive_computer| dive_computer.perform(action)),
+            }
+        };
+    }
+
+    // Keep the screen on, evaluates to false when the input at `$now` woke it up and should be ignored
+    macro_rules! wake {
+        ($cx:ident, $now:expr) => {
+            (&mut $cx.shared.screen_saver, &mut $cx.shared.settings).lock(|screen_saver, settings| screen_saver.wake($now, &settings.screen_saver))
+        };
     }
 
-    #[task(binds = IO_IRQ_BANK0, shared = [dive_computer], local = [button_a, button_b, button_x, button_y, last_triggered: Instant = Instant::from_ticks(0)])]
+    #[task(binds = IO_IRQ_BANK0, shared = [dive_computer, page, settings, editor, screen_saver, stats, button_lock, help, planner, blending, apnea, signal, boot, failures, factory_reset, checklist, cesa, button_macro, faults], local = [button_a, button_b, button_x, button_y, debouncer, stuck, chords: ChordGuard = ChordGuard::new()])]
     fn button_handler(mut cx: button_handler::Context) {
         let trigger_time = monotonics::now();
+        cx.shared.stats.lock(|stats| stats.record_irq());
+        let debounce = cx.local.debouncer.check();
+        // The setup wizard uses the buttons of the settings page
+        let page = match cx.shared.boot.lock(|boot| boot.in_setup()) {
+            true => Page::Settings,
+            false => cx.shared.page.lock(|page| *page),
+        };
+        let locked = cx.shared.button_lock.lock(|button_lock| button_lock.locked());
+
+        // Held buttons interrupt all the time, which is also when a stuck one is noticed
+        let down = [
+            cx.local.button_a.is_low().unwrap(),
+            cx.local.button_b.is_low().unwrap(),
+            cx.local.button_x.is_low().unwrap(),
+            cx.local.button_y.is_low().unwrap(),
+        ];
+        cx.local.stuck.update(trigger_time, down);
+        let stuck = *cx.local.stuck;
+        cx.shared.dive_computer.lock(|dive_computer| dive_computer.set_stuck_button(stuck.stuck()));
 
-        let time_waited = if trigger_time <= *cx.local.last_triggered {
-            MicrosDurationU64::micros(0)
+        // Holding Y alone during a dive opens the emergency ascent guide, ahead of the lock and everything else
+        cx.local.button_a.clear_interrupt(EdgeHigh);
+        cx.local.button_b.clear_interrupt(EdgeHigh);
+        cx.local.button_y.clear_interrupt(EdgeHigh);
+        let y_down = cx.local.button_y.is_low().unwrap();
+        let y_alone = y_down && !cx.local.button_a.is_low().unwrap() && !cx.local.button_b.is_low().unwrap() && !cx.local.button_x.is_low().unwrap();
+        // A short press of Y it held back is a tap once Y is up again
+        let mut withheld_tap = false;
+        if y_alone && stuck.accepts(Button::Y, Press::Hold) {
+            let pressed = cx.local.button_y.interrupt_status(EdgeLow);
+            let diving = cx.shared.dive_computer.lock(|dive_computer| dive_computer.diving());
+            let (toggled, withheld) = cx.shared.cesa.lock(|cesa| (cesa.hold(trigger_time, pressed, diving), cesa.withholds()));
+            if toggled || withheld {
+                cx.local.button_y.clear_interrupt(EdgeLow);
+                cx.local.button_y.clear_interrupt(LevelLow);
+                if toggled {
+                    wake!(cx, trigger_time);
+                }
+                return;
+            }
         } else {
-            trigger_time - *cx.local.last_triggered
-        };
+            withheld_tap = cx.shared.cesa.lock(|cesa| cesa.release()) && !y_down;
+        }
+
+        // The open guide takes all buttons, X slows the ascent down and Y speeds it up
+        if cx.shared.cesa.lock(|cesa| cesa.is_active()) {
+            let slower = cx.local.button_x.interrupt_status(EdgeLow) && debounce.press;
+            let faster = cx.local.button_y.interrupt_status(EdgeLow) && debounce.press;
+            cx.local.button_a.clear_interrupt(EdgeLow);
+            cx.local.button_a.clear_interrupt(LevelLow);
+            cx.local.button_b.clear_interrupt(EdgeLow);
+            cx.local.button_b.clear_interrupt(LevelLow);
+            cx.local.button_x.clear_interrupt(EdgeLow);
+            cx.local.button_x.clear_interrupt(LevelLow);
+            cx.local.button_y.clear_interrupt(EdgeLow);
+            cx.local.button_y.clear_interrupt(LevelLow);
+
+            wake!(cx, trigger_time);
+            cx.shared.dive_computer.lock(|dive_computer| {
+                if slower {
+                    dive_computer.perform(Action::IncreaseRate);
+                }
+                if faster {
+                    dive_computer.perform(Action::DecreaseRate);
+                }
+            });
+            return;
+        }
+
+        // The factory reset page takes all buttons, holding A and Y confirms and B or X cancels
+        if cx.shared.factory_reset.lock(|reset| reset.takes_buttons()) {
+            let chord = cx.local.button_a.is_low().unwrap() && cx.local.button_y.is_low().unwrap();
+            let pressed = cx.local.button_a.interrupt_status(EdgeLow) || cx.local.button_y.interrupt_status(EdgeLow);
+            let cancel = cx.local.button_b.interrupt_status(EdgeLow) || cx.local.button_x.interrupt_status(EdgeLow);
+            cx.local.button_a.clear_interrupt(EdgeLow);
+            cx.local.button_a.clear_interrupt(LevelLow);
+            cx.local.button_b.clear_interrupt(EdgeLow);
+            cx.local.button_b.clear_interrupt(LevelLow);
+            cx.local.button_x.clear_interrupt(EdgeLow);
+            cx.local.button_x.clear_interrupt(LevelLow);
+            cx.local.button_y.clear_interrupt(EdgeLow);
+            cx.local.button_y.clear_interrupt(LevelLow);
+
+            wake!(cx, trigger_time);
+            let confirmed = cx.shared.factory_reset.lock(|reset| {
+                if cancel {
+                    reset.cancel();
+                }
+                chord && !cancel && reset.hold(trigger_time, pressed)
+            });
+            // A reset already erasing keeps going, a second one can't be queued
+            if confirmed && queued(erase_records::spawn()).is_err() {
+                cx.shared.faults.lock(|faults| faults.record(trigger_time, FaultCode::QueueOverflow));
+            }
+            return;
+        }
+
+        // So does the checklist, A ticks the selected item off, X skips the rest and B cancels
+        if cx.shared.checklist.lock(|checklist| checklist.is_open()) {
+            let tick = cx.local.button_a.interrupt_status(EdgeLow) && debounce.press;
+            let cancel = cx.local.button_b.interrupt_status(EdgeLow) && debounce.press;
+            let skip = cx.local.button_x.interrupt_status(EdgeLow) && debounce.press;
+            cx.local.button_a.clear_interrupt(EdgeLow);
+            cx.local.button_a.clear_interrupt(LevelLow);
+            cx.local.button_b.clear_interrupt(EdgeLow);
+            cx.local.button_b.clear_interrupt(LevelLow);
+            cx.local.button_x.clear_interrupt(EdgeLow);
+            cx.local.button_x.clear_interrupt(LevelLow);
+            cx.local.button_y.clear_interrupt(EdgeLow);
+            cx.local.button_y.clear_interrupt(LevelLow);
+
+            wake!(cx, trigger_time);
+            let outcome = cx.shared.checklist.lock(|checklist| match (tick, skip, cancel) {
+                (_, _, true) => {
+                    checklist.cancel();
+                    None
+                }
+                (_, true, _) => checklist.skip(),
+                (true, _, _) => checklist.tick(),
+                _ => None,
+            });
+            if let Some(outcome) = outcome {
+                cx.shared.dive_computer.lock(|dive_computer| dive_computer.set_checklist(outcome));
+            }
+            return;
+        }
 
         let mut triggered = false;
 
+        // Look up the action of a button in the key bindings, or of a tap that was held back
         macro_rules! handle_button {
-            ($button:tt, $func:ident) => {
-                if cx.local.$button.interrupt_status(EdgeLow) {
-                    if time_waited > DEBOUNCE_TIME {
-                        cx.shared.dive_computer.lock(|dive_computer| {
-                            dive_computer.$func();
-                        });
-                        triggered = true;
-                    }
+            ($button:tt, $id:expr) => {
+                handle_button!($button, $id, false);
+            };
+            ($button:tt, $id:expr, $withheld:expr) => {
+                let press = if $withheld {
+                    Some(Press::Tap)
+                } else if cx.local.$button.interrupt_status(EdgeLow) {
                     cx.local.$button.clear_interrupt(EdgeLow);
+                    // A tap of A or B waits until it can't be the start of a chord
+                    Some(Press::Tap).filter(|_| debounce.press && !cx.local.chords.press($id, trigger_time))
                 } else if cx.local.$button.interrupt_status(LevelLow) {
-                    if time_waited > REPEAT_TIME {
-                        cx.shared.dive_computer.lock(|dive_computer| {
-                            dive_computer.$func();
-                        });
-                        triggered = true
-                    }
                     cx.local.$button.clear_interrupt(LevelLow);
+                    Some(Press::Hold).filter(|_| debounce.repeat && stuck.accepts($id, Press::Hold) && !cx.local.chords.withholds($id))
+                } else {
+                    None
+                };
+
+                if let Some(press) = press {
+                    if !locked && wake!(cx, trigger_time) {
+                        let action = cx.shared.settings.lock(|settings| settings.bindings.action(page, $id, press));
+                        perform!(cx, action);
+                        cx.shared
+                            .button_macro
+                            .lock(|button_macro| button_macro.pressed(trigger_time, $id, None, press));
+                        let event = ButtonEvent {
+                            button: $id,
+                            press,
+                            at: trigger_time,
+                        };
+                        cx.shared.stats.lock(|stats| stats.input.pressed(&event));
+                    }
+                    triggered = true;
+                }
+            };
+        }
+
+        // Handle two buttons pressed together, evaluates to true when they are
+        macro_rules! handle_chord {
+            ($first:tt, $second:tt, $id:expr, $other:expr) => {
+                if cx.local.$first.is_low().unwrap() && cx.local.$second.is_low().unwrap() {
+                    // The tap of either one that was held back is part of the chord
+                    cx.local.chords.chord($id, $other);
+                    if (cx.local.$first.interrupt_status(EdgeLow) || cx.local.$second.interrupt_status(EdgeLow)) && debounce.press {
+                        if !locked && wake!(cx, trigger_time) {
+                            perform!(cx, chord_action($id, $other));
+                            cx.shared
+                                .button_macro
+                                .lock(|button_macro| button_macro.pressed(trigger_time, $id, Some($other), Press::Tap));
+                            let event = ButtonEvent {
+                                button: $id,
+                                press: Press::Tap,
+                                at: trigger_time,
+                            };
+                            cx.shared.stats.lock(|stats| stats.input.pressed(&event));
+                        }
+                        triggered = true;
+                    }
+                    cx.local.$first.clear_interrupt(EdgeLow);
+                    cx.local.$first.clear_interrupt(LevelLow);
+                    cx.local.$second.clear_interrupt(EdgeLow);
+                    cx.local.$second.clear_interrupt(LevelLow);
+                    true
+                } else {
+                    false
                 }
             };
         }
 
-        // Fill air
-        handle_button!(button_a, fill_air);
+        // A tap of A or B that was held back is due once it can't be a chord anymore
+        match cx.local.chords.due(trigger_time, down) {
+            Some(Button::A) => {
+                handle_button!(button_a, Button::A, true);
+            }
+            Some(Button::B) => {
+                handle_button!(button_b, Button::B, true);
+            }
+            _ => {}
+        }
 
-        // Change unit
-        handle_button!(button_b, toggle_unit);
+        // Pressing B and Y together shows the help, the other chords are on one side of the screen
+        if !handle_chord!(button_b, button_y, Button::B, Button::Y) {
+            // Pressing A and B together switches the page
+            if !handle_chord!(button_a, button_b, Button::A, Button::B) {
+                handle_button!(button_a, Button::A);
+                handle_button!(button_b, Button::B);
+            }
 
-        // Increase descend
-        handle_button!(button_x, increase_rate);
+            // Holding X and Y together locks or unlocks the buttons
+            if cx.local.button_x.is_low().unwrap() && cx.local.button_y.is_low().unwrap() {
+                let pressed = cx.local.button_x.interrupt_status(EdgeLow) || cx.local.button_y.interrupt_status(EdgeLow);
+                if cx.shared.button_lock.lock(|button_lock| button_lock.hold(trigger_time, pressed)) {
+                    wake!(cx, trigger_time);
+                }
+            }
 
-        // Increase ascend
-        handle_button!(button_y, decrease_rate);
+            // Pressing X and Y together sets a mark
+            if !handle_chord!(button_x, button_y, Button::X, Button::Y) {
+                handle_button!(button_x, Button::X);
+                handle_button!(button_y, Button::Y, withheld_tap);
+            }
+        }
 
         if triggered {
             info!("button pushed");
-            *cx.local.last_triggered = trigger_time;
+            cx.local.debouncer.triggered(debounce);
         }
+
+        let elapsed = monotonics::now() - trigger_time;
+        cx.shared.stats.lock(|stats| stats.buttons.record(elapsed.to_micros() as u32));
+    }
+
+    /// Read the water temperature from the thermistor
+    #[cfg(feature = "thermistor")]
+    #[task(shared = [dive_computer, sampler, faults], priority = 1)]
+    fn temperature_input(mut cx: temperature_input::Context) {
+        started();
+        spawn_or_fault!(cx, temperature_input::spawn_after(TEMPERATURE_POLL_INTERVAL));
+
+        let raw = cx.shared.sampler.lock(|sampler| sampler.average(AdcInput::Adc2));
+        let temperature = raw.and_then(thermistor_tenths);
+        cx.shared.dive_computer.lock(|dive_computer| dive_computer.set_temperature(temperature));
+    }
+
+    /// Follow the battery voltage for the runtime estimate, and log it at the start and end of a dive
+    #[task(shared = [dive_computer, sampler, battery, faults], priority = 1)]
+    fn battery_monitor(mut cx: battery_monitor::Context) {
+        started();
+        let now = monotonics::now();
+        spawn_or_fault!(cx, battery_monitor::spawn_after(BATTERY_POLL_INTERVAL));
+
+        let Some(raw) = cx.shared.sampler.lock(|sampler| sampler.average(AdcInput::Vsys)) else {
+            return;
+        };
+        let millivolts = vsys_millivolts(raw);
+        let diving = cx.shared.dive_computer.lock(|dive_computer| dive_computer.diving());
+        cx.shared.battery.lock(|battery| {
+            battery.record(now, millivolts);
+            battery.dive(diving, millivolts);
+        });
     }
+
+    /// Watch the accelerometer for knocks and drops
+    #[task(shared = [dive_computer, faults], local = [imu, detector: ShockDetector = ShockDetector::new()], priority = 1)]
+    fn shock_input(mut cx: shock_input::Context) {
+        started();
+        let now = monotonics::now();
+        let Some(imu) = cx.local.imu else {
+            return;
+        };
+        let Ok(acceleration) = imu.read() else {
+            warn!("IMU stopped answering, shock detection disabled");
+            *cx.local.imu = None;
+            return;
+        };
+        spawn_or_fault!(cx, shock_input::spawn_after(SHOCK_POLL_INTERVAL));
+
+        if let Some(peak) = cx.local.detector.update(now, acceleration) {
+            cx.shared.dive_computer.lock(|dive_computer| dive_computer.record_shock(peak));
+        }
+    }
+
+    /// Poll the joystick and perform the action of a stable direction
+    #[cfg(feature = "joystick")]
+    #[task(shared = [dive_computer, page, settings, editor, screen_saver, button_lock, help, planner, blending, apnea, signal, boot, sampler, failures, factory_reset, checklist, faults], local = [joystick: Joystick = Joystick::new(JoystickConfig::new())], priority = 1)]
+    fn joystick_input(mut cx: joystick_input::Context) {
+        started();
+        let now = monotonics::now();
+        spawn_or_fault!(cx, joystick_input::spawn_after(JOYSTICK_POLL_INTERVAL));
+
+        let (x, y, button) = cx.shared.sampler.lock(|sampler| {
+            (
+                sampler.average(AdcInput::JoystickX),
+                sampler.average(AdcInput::JoystickY),
+                sampler.average(AdcInput::Adc2),
+            )
+        });
+
+        let direction = match (x, y, button) {
+            (Some(x), Some(y), Some(button)) => cx.local.joystick.poll(x, y, button),
+            _ => None,
+        };
+        if let Some(direction) = direction {
+            info!("joystick moved");
+            let locked = cx.shared.button_lock.lock(|button_lock| button_lock.locked());
+            if !locked && wake!(cx, now) {
+                let page = match cx.shared.boot.lock(|boot| boot.in_setup()) {
+                    true => Page::Settings,
+                    false => cx.shared.page.lock(|page| *page),
+                };
+                perform!(cx, direction.action(page));
+            }
+        }
+    }
+
+    /// Run the console lines from the host and send the replies back, see the `console` module
+    #[task(shared = [dive_computer, settings, lifetime, experiment, clock_sync, button_macro, wall_clock, rtc, wear, faults], local = [console_rx, console_tx, scratch, macros, reader: LineReader = LineReader::new(), reply: Reply = Reply::new()], priority = 1)]
+    fn console_input(mut cx: console_input::Context) {
+        started();
+        spawn_or_fault!(cx, console_input::spawn_after(CONSOLE_POLL_INTERVAL));
+
+        let console_input::LocalResources {
+            console_rx,
+            console_tx,
+            scratch,
+            macros,
+            reader,
+            reply,
+        } = cx.local;
+
+        let mut bytes = [0; 64];
+        let len = console_rx.read(&mut bytes);
+        for &byte in &bytes[..len] {
+            match reader.push(byte) {
+                None => continue,
+                Some(Ok(line)) => (
+                    &mut cx.shared.dive_computer,
+                    &mut cx.shared.settings,
+                    &mut cx.shared.lifetime,
+                    &mut cx.shared.experiment,
+                    &mut cx.shared.clock_sync,
+                    &mut cx.shared.button_macro,
+                    &mut cx.shared.wall_clock,
+                    &mut cx.shared.rtc,
+                    &mut cx.shared.wear,
+                    &mut cx.shared.faults,
+                )
+                    .lock(
+                        |dive_computer, settings, lifetime, experiment, clock_sync, button_macro, wall_clock, rtc, wear, faults| {
+                            let device = Device {
+                                settings: &mut *settings,
+                                lifetime,
+                                replay: &dive_computer.replay(),
+                                experiment,
+                                clock_sync,
+                                button_macro: &mut *button_macro,
+                                macro_store: macros.as_mut().map(|macros| macros as &mut dyn MacroStore),
+                                wall_clock,
+                                rtc,
+                                wear,
+                                faults,
+                                marks: dive_computer.marks(),
+                                audit_trail: dive_computer.audit_trail(),
+                            };
+                            console::execute(line, &Rp2040Clock, device, scratch, reply);
+                            if Command::parse(line).is_ok_and(|command| command.changes_settings()) {
+                                apply_settings(dive_computer, settings);
+                                let _ = queued(save_settings::spawn_after(SETTINGS_SAVE_DELAY));
+                            }
+                            // `macro play` starts the replay, it is polled until it ends
+                            if button_macro.is_playing() {
+                                let _ = queued(replay_macro::spawn());
+                            }
+                        },
+                    ),
+                Some(Err(error)) => {
+                    reply.clear();
+                    writeln!(reply, "ERROR: {}", error.as_str());
+                }
+            }
+            console_tx.write(reply.as_str().as_bytes());
+        }
+    }
+
+    /// Perform the presses of a button macro when they are due, like the buttons would
+    ///
+    /// Spawned when a replay starts, it polls until the replay ends.
+    #[task(shared = [dive_computer, page, settings, editor, screen_saver, help, planner, blending, apnea, signal, boot, failures, factory_reset, checklist, button_macro], priority = 1)]
+    fn replay_macro(mut cx: replay_macro::Context) {
+        started();
+        let now = monotonics::now();
+        let (press, playing) = cx.shared.button_macro.lock(|button_macro| (button_macro.poll(now), button_macro.is_playing()));
+        if playing {
+            // Pending already when a new replay spawned it in the meantime
+            let _ = queued(replay_macro::spawn_after(MACRO_POLL_INTERVAL));
+        }
+
+        if let Some(press) = press {
+            let page = match cx.shared.boot.lock(|boot| boot.in_setup()) {
+                true => Page::Settings,
+                false => cx.shared.page.lock(|page| *page),
+            };
+            let action = match press.second {
+                Some(second) => chord_action(press.button, second),
+                None => cx.shared.settings.lock(|settings| settings.bindings.action(page, press.button, press.press)),
+            };
+            wake!(cx, now);
+            perform!(cx, action);
+        }
+    }
+}
+
+/// Pass the changed `settings` on to the dive computer and the log timestamps
+fn apply_settings(dive_computer: &mut DiveComputer, settings: &Settings) {
+    dive_computer.set_gradient_factors(settings.gradient_factors);
+    dive_computer.set_deco_variant(settings.deco_variant);
+    dive_computer.set_ascent_limit(settings.ascent_limit);
+    dive_computer.set_time_scale(settings.time_scale);
+    dive_computer.set_fill_rate(settings.fill_rate);
+    dive_computer.set_rate_limit(settings.rate_limit);
+    dive_computer.set_reserve_config(settings.reserve);
+    dive_computer.set_edt_format(settings.edt_format);
+    dive_computer.set_depth_damping(settings.depth_damping);
+    dive_computer.set_depth_display(settings.depth_display);
+    dive_computer.set_unit(settings.unit);
+    dive_computer.set_tank(settings.tank);
+    dive_computer.set_checklist_mode(settings.checklist);
+    dive_computer.set_depth_alerts(settings.depth_alerts);
+    dive_computer.set_ndl_warnings(settings.ndl_warnings);
+    dive_computer.set_back_gas(settings.back_gas);
+    dive_computer.set_deco_gases(settings.deco_gases);
+    dive_computer.set_clock_drift(settings.clock_drift);
+    clock::set_drift(settings.clock_drift);
+}
+
+/// Let the RTC alarm go off after `wait`, or not at all
+///
+/// The core sleeps in WFI while no task runs, the alarm interrupt wakes it like any other.
+fn schedule_wake(rtc: &mut RealTimeClock, wait: Option<MicrosDurationU64>) {
+    let (Some(wait), Ok(now)) = (wait, rtc.now()) else {
+        rtc.disable_alarm();
+        return;
+    };
+
+    // The alarm matches the time of day, after a day or more it goes off early and is set again
+    let wait = (wait.to_secs() % 86_400).max(1) as u32;
+    let seconds = (u32::from(now.hour) * 3600 + u32::from(now.minute) * 60 + u32::from(now.second) + wait) % 86_400;
+    rtc.schedule_alarm(
+        DateTimeFilter::default()
+            .hour((seconds / 3600) as u8)
+            .minute((seconds / 60 % 60) as u8)
+            .second((seconds % 60) as u8),
+    );
+    // The HAL only sets up the match, not the interrupt
+    unsafe { (*bsp::pac::RTC::ptr()).inte.write(|w| w.rtc().set_bit()) };
+}
+
+/// Busy wait for `time`, the load of an experiment
+fn spin(time: MicrosDurationU64) {
+    let start = app::monotonics::now();
+    while app::monotonics::now() - start < time {}
 }
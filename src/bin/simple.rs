@@ -3,16 +3,11 @@
 #![cfg(not(test))]
 #![no_std]
 #![no_main]
-use core::fmt::Write;
 
-use defmt::*;
 use defmt_rtt as _;
-use panic_probe as _;
+// The panic handler is in `dive_computer::panic`
 
-use arraystring::{typenum::U200, ArrayString};
 use embedded_graphics::{
-    mono_font::{ascii::FONT_10X20, MonoTextStyleBuilder},
-    pixelcolor::Rgb565,
     prelude::*,
     text::{Alignment, Text},
 };
@@ -30,14 +25,28 @@ use bsp::hal::{
     entry, pac,
     sio::Sio,
     watchdog::Watchdog,
+    Timer,
 };
 
-use dive_computer::DiveComputer;
+use dive_computer::{
+    budget::UiBuffer,
+    buttons::ButtonEvent,
+    clock::{Clock as _, Rp2040Clock},
+    diagnostics::{self, InputLatency},
+    keymap::{self, Press},
+    theme::Theme,
+    widgets::{AscentArrows, TrendArrow, ASCENT_ARROWS_POSITION, DEPTH_TREND_POSITION},
+    DiveComputer,
+};
+// Log macros filtered by the log level
+use dive_computer::info;
 
 const TIME_TICK_MS: u32 = 50;
+const STACK_REPORT_MS: u32 = 10_000;
 
 #[entry]
 fn main() -> ! {
+    diagnostics::paint_stack();
     info!("Program start");
     let mut pac = pac::Peripherals::take().unwrap();
     let core = pac::CorePeripherals::take().unwrap();
@@ -50,6 +59,9 @@ fn main() -> ! {
 
     let mut delay = cortex_m::delay::Delay::new(core.SYST, clocks.system_clock.freq().to_Hz());
 
+    // Start the timer for the log timestamps
+    let _timer = Timer::new(pac.TIMER, &mut pac.RESETS);
+
     // Enable adc
     let adc = Adc::new(pac.ADC, &mut pac.RESETS);
 
@@ -58,15 +70,21 @@ fn main() -> ! {
     let (mut explorer, pins) = PicoExplorer::new(pac.IO_BANK0, pac.PADS_BANK0, sio.gpio_bank0, pac.SPI0, adc, &mut pac.RESETS, &mut delay);
 
     // Create a fixed buffer to store screen contents
-    let mut buf = ArrayString::<U200>::new();
+    let mut buf = UiBuffer::new();
 
     let mut led = pins.led.into_push_pull_output();
 
     let mut dive_computer = DiveComputer::default();
 
     let mut counter = 0;
+    let mut stack_report_counter = 0;
+    let mut latency = InputLatency::new();
 
     loop {
+        // Presses are only noticed here, up to `TIME_TICK_MS` after they happened
+        let polled = Rp2040Clock.now();
+        let mut pressed = None;
+
         if led.is_set_low().unwrap() {
             info!("on!");
             led.set_high().unwrap();
@@ -78,21 +96,33 @@ fn main() -> ! {
         // Fill air
         if explorer.is_pressed(Button::A) {
             dive_computer.fill_air();
+            pressed = Some(keymap::Button::A);
         }
 
         // Change unit
         if explorer.is_pressed(Button::B) {
             dive_computer.toggle_unit();
+            pressed = Some(keymap::Button::B);
         }
 
         // Increase descend
         if explorer.is_pressed(Button::X) {
             dive_computer.increase_rate();
+            pressed = Some(keymap::Button::X);
         }
 
         // Increase ascend
         if explorer.is_pressed(Button::Y) {
             dive_computer.decrease_rate();
+            pressed = Some(keymap::Button::Y);
+        }
+
+        if let Some(button) = pressed {
+            latency.pressed(&ButtonEvent {
+                button,
+                press: Press::Tap,
+                at: polled,
+            });
         }
 
         if counter == 0 {
@@ -102,22 +132,37 @@ fn main() -> ! {
 
         // Write to buffer
         buf.clear();
-        writeln!(&mut buf, "{}", dive_computer).unwrap();
+        dive_computer.render(&mut buf);
 
         // Draw buffer on screen
-        let style = MonoTextStyleBuilder::new()
-            .font(&FONT_10X20)
-            .text_color(Rgb565::GREEN)
-            .background_color(Rgb565::BLACK)
-            .build();
-        Text::with_alignment(&buf, Point::new(20, 30), style, Alignment::Left)
+        let theme = Theme::default().with_text_color(dive_computer.reserve().color());
+        Text::with_alignment(buf.as_str(), Point::new(20, 30), theme.text_style(), Alignment::Left)
             .draw(&mut explorer.screen)
             .unwrap();
+        TrendArrow::new(dive_computer.trend(), DEPTH_TREND_POSITION, theme.text_color, Some(theme.background_color))
+            .draw(&mut explorer.screen)
+            .unwrap();
+        AscentArrows::new(
+            dive_computer.ascent().coaching(),
+            ASCENT_ARROWS_POSITION,
+            theme.text_color,
+            Some(theme.background_color),
+        )
+        .draw(&mut explorer.screen)
+        .unwrap();
+        latency.shown(Rp2040Clock.now());
 
         counter += TIME_TICK_MS;
         if counter >= 500 {
             counter = 0;
         }
+
+        stack_report_counter += TIME_TICK_MS;
+        if stack_report_counter >= STACK_REPORT_MS {
+            diagnostics::report_stack();
+            info!("input latency avg {=u32} us, max {=u32} us", latency.stats.average_us(), latency.stats.max_us);
+            stack_report_counter = 0;
+        }
         delay.delay_ms(TIME_TICK_MS);
     }
 }
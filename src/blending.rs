@@ -0,0 +1,220 @@
+//! Nitrox blending calculator
+//!
+//! The blending page works out a partial pressure blend for the classroom: from what is left in
+//! the tank, first bleed down if needed, then add pure oxygen and top up with air to reach the
+//! target mix and pressure. Pressures are whole bar and fractions whole percent, the ideal gas
+//! law is close enough at these pressures.
+//!
+//! Oxygen added is `(target * (target O2 - 21) - start * (start O2 - 21)) / 79` bar. When that is
+//! negative the start is too rich, when it doesn't fit below the target pressure the start is too
+//! lean and full, both are solved by bleeding to the highest pressure that works.
+
+use core::fmt;
+
+use crate::keymap::Action;
+
+/// Oxygen in air in percent
+const AIR_O2: u32 = 21;
+
+/// Richest mix that can be entered, more needs oxygen clean gear for the top up
+pub const MAX_O2: u32 = 40;
+
+/// Highest tank pressure that can be entered in bar
+pub const MAX_PRESSURE_BAR: u32 = 230;
+
+/// Distance between the pressures that can be entered
+const PRESSURE_STEP_BAR: u32 = 10;
+
+/// Contents of a tank
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    pub pressure_bar: u32,
+    /// Oxygen in percent, the rest is nitrogen
+    pub o2: u32,
+}
+
+/// Steps of a partial pressure blend, in bar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Blend {
+    /// Pressure to bleed the tank down to first, if needed
+    pub bleed_to: Option<u32>,
+    pub add_o2: u32,
+    pub top_air: u32,
+}
+
+/// Blend `target` from `start` with oxygen and air
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::blending::{blend, Blend, Mix};
+/// // EAN32 at 200 bar from 50 bar of air
+/// let steps = blend(Mix { pressure_bar: 50, o2: 21 }, Mix { pressure_bar: 200, o2: 32 });
+/// assert_eq!(steps, Blend { bleed_to: None, add_o2: 28, top_air: 122 });
+/// ```
+///
+pub fn blend(start: Mix, target: Mix) -> Blend {
+    let (start_o2, target_o2) = (start.o2.clamp(AIR_O2, 99), target.o2.clamp(AIR_O2, 100));
+    // Highest start pressure that needs no negative oxygen
+    let not_rich = target.pressure_bar * (target_o2 - AIR_O2) / (start_o2 - AIR_O2).max(1);
+    // Highest start pressure that leaves room for the oxygen
+    let not_full = target.pressure_bar * (100 - target_o2) / (100 - start_o2);
+    let pressure = start.pressure_bar.min(not_full).min(if start_o2 > AIR_O2 { not_rich } else { u32::MAX });
+
+    let o2 = (target.pressure_bar * (target_o2 - AIR_O2)).saturating_sub(pressure * (start_o2 - AIR_O2));
+    let add_o2 = ((o2 + (100 - AIR_O2) / 2) / (100 - AIR_O2)).min(target.pressure_bar - pressure);
+    Blend {
+        bleed_to: (pressure < start.pressure_bar).then_some(pressure),
+        add_o2,
+        top_air: target.pressure_bar - pressure - add_o2,
+    }
+}
+
+/// Inputs of the blending page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlendCalculator {
+    pub start: Mix,
+    pub target: Mix,
+    /// Selected input, start pressure and oxygen then target pressure and oxygen
+    item: usize,
+}
+
+impl BlendCalculator {
+    /// EAN32 at 200 bar from 50 bar of air
+    pub const fn new() -> Self {
+        BlendCalculator {
+            start: Mix { pressure_bar: 50, o2: AIR_O2 },
+            target: Mix { pressure_bar: 200, o2: 32 },
+            item: 0,
+        }
+    }
+
+    /// Handle one of the blending page actions, others are ignored
+    pub fn perform(&mut self, action: Action) {
+        let mix = if self.item < 2 { &mut self.start } else { &mut self.target };
+        match action {
+            Action::SelectItem => self.item = (self.item + 1) % 4,
+            Action::ChangeItem if self.item.is_multiple_of(2) => {
+                mix.pressure_bar = if mix.pressure_bar >= MAX_PRESSURE_BAR {
+                    0
+                } else {
+                    mix.pressure_bar + PRESSURE_STEP_BAR
+                };
+            }
+            Action::ChangeItem => mix.o2 = if mix.o2 >= MAX_O2 { AIR_O2 } else { mix.o2 + 1 },
+            _ => {}
+        }
+    }
+}
+
+impl Default for BlendCalculator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for BlendCalculator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Gas blending")?;
+        writeln!(f)?;
+
+        let marker = |item| if self.item == item { '>' } else { ' ' };
+        writeln!(f, "{}START: {:>9}BAR", marker(0), self.start.pressure_bar)?;
+        writeln!(f, "{}START O2: {:>8}%", marker(1), self.start.o2)?;
+        writeln!(f, "{}TARGET: {:>8}BAR", marker(2), self.target.pressure_bar)?;
+        writeln!(f, "{}TARGET O2: {:>7}%", marker(3), self.target.o2)?;
+        writeln!(f)?;
+
+        let steps = blend(self.start, self.target);
+        if let Some(pressure) = steps.bleed_to {
+            writeln!(f, "BLEED TO: {:>7}BAR", pressure)?;
+        }
+        writeln!(f, "ADD O2: {:>9}BAR", steps.add_o2)?;
+        write!(f, "TOP AIR: {:>8}BAR", steps.top_air)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// Oxygen in the blended tank in tenths of a percent
+    fn blended_o2(start: Mix, steps: Blend) -> u32 {
+        let pressure = steps.bleed_to.unwrap_or(start.pressure_bar);
+        let o2 = pressure * start.o2 + steps.add_o2 * 100 + steps.top_air * AIR_O2;
+        o2 * 10 / (pressure + steps.add_o2 + steps.top_air)
+    }
+
+    #[test]
+    fn test_blend() {
+        // Too rich, bleed until no oxygen is needed
+        let start = Mix { pressure_bar: 150, o2: 36 };
+        let steps = blend(start, Mix { pressure_bar: 200, o2: 32 });
+        assert_eq!(
+            steps,
+            Blend {
+                bleed_to: Some(146),
+                add_o2: 0,
+                top_air: 54
+            }
+        );
+        assert_eq!(blended_o2(start, steps), 319);
+
+        // Fuller than the target, bleed until the oxygen fits
+        let start = Mix { pressure_bar: 200, o2: 32 };
+        let steps = blend(start, Mix { pressure_bar: 100, o2: 36 });
+        assert_eq!(
+            steps,
+            Blend {
+                bleed_to: Some(94),
+                add_o2: 6,
+                top_air: 0
+            }
+        );
+        assert_eq!(blended_o2(start, steps), 360);
+
+        // Same mix, only top up
+        let start = Mix { pressure_bar: 100, o2: 32 };
+        assert_eq!(blend(start, Mix { pressure_bar: 200, o2: 32 }).bleed_to, None);
+        assert_eq!(blended_o2(start, blend(start, Mix { pressure_bar: 200, o2: 32 })), 320);
+
+        // An empty target needs nothing
+        let steps = blend(start, Mix { pressure_bar: 0, o2: 32 });
+        assert_eq!(
+            steps,
+            Blend {
+                bleed_to: Some(0),
+                add_o2: 0,
+                top_air: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_page() {
+        let mut calculator = BlendCalculator::new();
+        assert_eq!(
+            format!("{}", calculator),
+            "Gas blending\n\n>START:        50BAR\n START O2:       21%\n TARGET:      200BAR\n TARGET O2:      32%\n\nADD O2:        28BAR\nTOP AIR:      122BAR"
+        );
+
+        // Start with 200 bar of EAN22
+        for _ in 0..15 {
+            calculator.perform(Action::ChangeItem);
+        }
+        calculator.perform(Action::SelectItem);
+        calculator.perform(Action::ChangeItem);
+        assert_eq!(calculator.start, Mix { pressure_bar: 200, o2: 22 });
+        assert!(format!("{}", calculator).ends_with("\nBLEED TO:     174BAR\nADD O2:        26BAR\nTOP AIR:        0BAR"));
+
+        // Oxygen wraps around to air
+        calculator.perform(Action::SelectItem);
+        calculator.perform(Action::SelectItem);
+        for _ in 0..9 {
+            calculator.perform(Action::ChangeItem);
+        }
+        assert_eq!(calculator.target.o2, 21);
+    }
+}
@@ -0,0 +1,112 @@
+//! Buddy mode
+//!
+//! Two kits connected with a UART crossover, TX of one to RX of the other and a common ground,
+//! send each other a telemetry frame every `SEND_INTERVAL`. Each shows the depth, tank pressure
+//! and alarm of its buddy, and raises a Medium alarm when the buddy's High alarm fires or no
+//! frame arrived for `LINK_TIMEOUT`. There is no buddy until the first frame arrives, so a kit on
+//! its own doesn't alarm.
+
+use fugit::MicrosDurationU64;
+
+use crate::{
+    clock::Instant,
+    telemetry::{FrameReader, Telemetry},
+    Alarm,
+};
+
+/// Time between two frames sent to the buddy
+pub const SEND_INTERVAL: MicrosDurationU64 = MicrosDurationU64::secs(1);
+
+/// Time without a frame after which the link counts as lost
+pub const LINK_TIMEOUT: MicrosDurationU64 = MicrosDurationU64::secs(5);
+
+/// What is known about the buddy
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuddyStatus {
+    Connected(Telemetry),
+    /// No frame for `LINK_TIMEOUT`, with the last state received
+    Lost(Telemetry),
+}
+
+impl BuddyStatus {
+    /// Whether the buddy needs attention
+    pub fn alarming(&self) -> bool {
+        match self {
+            BuddyStatus::Connected(telemetry) => telemetry.alarm == Alarm::High,
+            BuddyStatus::Lost(_) => true,
+        }
+    }
+}
+
+/// Receiving end of the link to the buddy
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BuddyLink {
+    reader: FrameReader,
+    /// Last frame received, and when
+    last: Option<(Telemetry, Instant)>,
+}
+
+impl BuddyLink {
+    pub const fn new() -> Self {
+        BuddyLink {
+            reader: FrameReader::new(),
+            last: None,
+        }
+    }
+
+    /// Handle a `byte` received at `now`
+    pub fn receive(&mut self, byte: u8, now: Instant) {
+        // A damaged frame is dropped, the next one follows within `SEND_INTERVAL`
+        if let Some(Ok(telemetry)) = self.reader.push(byte) {
+            self.last = Some((telemetry, now));
+        }
+    }
+
+    /// State of the buddy at `now`, `None` before the first frame
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{buddy::{BuddyLink, BuddyStatus, LINK_TIMEOUT}, clock::Instant, telemetry::{Telemetry, MAX_FRAME_LEN}, Alarm};
+    /// let telemetry = Telemetry { depth: 18_000, pressure: 150, alarm: Alarm::None };
+    /// let mut buf = [0; MAX_FRAME_LEN];
+    /// let mut link = BuddyLink::new();
+    /// let now = Instant::from_ticks(0);
+    /// assert_eq!(link.status(now), None);
+    ///
+    /// for &byte in telemetry.encode(&mut buf).unwrap() {
+    ///     link.receive(byte, now);
+    /// }
+    /// assert_eq!(link.status(now), Some(BuddyStatus::Connected(telemetry)));
+    /// assert_eq!(link.status(now + LINK_TIMEOUT * 2), Some(BuddyStatus::Lost(telemetry)));
+    /// ```
+    ///
+    pub fn status(&self, now: Instant) -> Option<BuddyStatus> {
+        self.last.map(|(telemetry, received)| {
+            if now.checked_duration_since(received).is_some_and(|age| age > LINK_TIMEOUT) {
+                BuddyStatus::Lost(telemetry)
+            } else {
+                BuddyStatus::Connected(telemetry)
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_alarming_buddy() {
+        let mut telemetry = Telemetry {
+            depth: 18_000,
+            pressure: 150,
+            alarm: Alarm::Medium,
+        };
+        assert!(!BuddyStatus::Connected(telemetry).alarming());
+        assert!(BuddyStatus::Lost(telemetry).alarming());
+        telemetry.alarm = Alarm::High;
+        assert!(BuddyStatus::Connected(telemetry).alarming());
+    }
+}
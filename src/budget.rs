@@ -0,0 +1,133 @@
+//! Compile-time RAM budget
+//!
+//! The RP2040 has 264 kB of SRAM: 256 kB of striped main memory (the `RAM` region in `memory.x`)
+//! and two 4 kB scratch banks which we don't use. Everything we keep in statics has to fit in
+//! the main memory together with the stack, so every subsystem with a fixed capacity is listed
+//! here. The `ram_budget` binary prints this table over defmt.
+//!
+//! | Subsystem     | Type            | Capacity                                               |
+//! |---------------|-----------------|--------------------------------------------------------|
+//! | Dive computer | `DiveComputer`  | `ALARM_HISTORY_SIZE`, `AUDIT_TRAIL_SIZE`, `MARK_COUNT` |
+//! | UI buffer     | `UiBuffer`      | `UI_BUFFER_SIZE` B                                     |
+//! | Runtime stats | `RuntimeStats`  | -                                                      |
+//! | Screen chunk  | `ScreenChunk`   | `MAX_CHUNK_ROWS` rows                                  |
+//! | Settings      | `Settings`      | -                                                      |
+//! | ADC samples   | `SampleRing`    | `DEPTH` samples per input                              |
+//! | Button macro  | `MacroRecorder` | `MAX_PRESSES` presses                                  |
+//! | Battery trend | `BatteryTrend`  | `TREND_SAMPLES` samples                                |
+//! | Wear map      | `WearMap`       | `MAX_SECTORS` sectors, with `logbook`                  |
+//! | Fault log     | `FaultLog`      | `MAX_FAULTS` faults                                    |
+//! | Console line  | `LineReader`    | `MAX_LINE_LEN` B                                       |
+//! | Console reply | `Reply`         | `MAX_REPLY_LEN` B                                      |
+//!
+//! A subsystem with a fixed capacity added to the firmware gets a row here and an entry in
+//! `BUDGET`, the test keeps the two the same.
+
+use core::mem::size_of;
+
+#[cfg(feature = "logbook")]
+use crate::storage::WearMap;
+use crate::{
+    battery::BatteryTrend,
+    console::{LineReader, Reply},
+    diagnostics::RuntimeStats,
+    fault::FaultLog,
+    input_macro::MacroRecorder,
+    render::ScreenChunk,
+    sampler::SampleRing,
+    settings::Settings,
+    text_buffer::TextBuffer,
+    DiveComputer,
+};
+
+/// Capacity of the buffer the screen contents are formatted into
+pub const UI_BUFFER_SIZE: usize = 255;
+
+/// Buffer to format the screen contents into
+pub type UiBuffer = TextBuffer<UI_BUFFER_SIZE>;
+
+/// Length of the `RAM` region in `memory.x`, exported by `build.rs`
+pub const RAM_LENGTH: usize = parse_usize(env!("RAM_LENGTH"));
+
+/// Bytes we want to keep free for the stack
+pub const STACK_RESERVE: usize = 16 * 1024;
+
+/// RAM used by a single subsystem
+#[derive(Debug, Clone, Copy)]
+pub struct BudgetEntry {
+    pub name: &'static str,
+    pub bytes: usize,
+}
+
+impl BudgetEntry {
+    const fn of<T>(name: &'static str) -> Self {
+        BudgetEntry { name, bytes: size_of::<T>() }
+    }
+}
+
+/// Static RAM used per subsystem
+pub const BUDGET: &[BudgetEntry] = &[
+    BudgetEntry::of::<DiveComputer>("dive computer"),
+    BudgetEntry::of::<UiBuffer>("ui buffer"),
+    BudgetEntry::of::<RuntimeStats>("runtime stats"),
+    BudgetEntry::of::<ScreenChunk>("screen chunk"),
+    BudgetEntry::of::<Settings>("settings"),
+    BudgetEntry::of::<SampleRing>("adc samples"),
+    BudgetEntry::of::<MacroRecorder>("button macro"),
+    BudgetEntry::of::<BatteryTrend>("battery trend"),
+    #[cfg(feature = "logbook")]
+    BudgetEntry::of::<WearMap>("wear map"),
+    BudgetEntry::of::<FaultLog>("fault log"),
+    BudgetEntry::of::<LineReader>("console line"),
+    BudgetEntry::of::<Reply>("console reply"),
+];
+
+/// Total static RAM claimed by all subsystems
+pub const fn total() -> usize {
+    let mut total = 0;
+    let mut i = 0;
+    while i < BUDGET.len() {
+        total += BUDGET[i].bytes;
+        i += 1;
+    }
+    total
+}
+
+// Fail the build when the budget doesn't leave room for the stack
+const _: () = assert!(total() + STACK_RESERVE <= RAM_LENGTH, "RAM budget exceeded");
+
+// The buffer type and its documented capacity have to agree
+const _: () = assert!(size_of::<UiBuffer>() > UI_BUFFER_SIZE);
+
+const fn parse_usize(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    let mut value = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        value = value * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    value
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_table_matches_budget() {
+        // The first column of every row of the table in the module documentation
+        let rows: Vec<String> = include_str!("budget.rs")
+            .lines()
+            .filter_map(|line| line.strip_prefix("//! | "))
+            .filter_map(|row| row.split('|').next())
+            .map(|name| name.trim().to_lowercase())
+            .filter(|name| name != "subsystem")
+            .filter(|name| cfg!(feature = "logbook") || name != "wear map")
+            .collect();
+        let names: Vec<&str> = BUDGET.iter().map(|entry| entry.name).collect();
+        assert_eq!(rows, names);
+        assert!(BUDGET.iter().all(|entry| entry.bytes > 0));
+    }
+}
@@ -0,0 +1,298 @@
+//! Button debouncing
+//!
+//! A button press is only accepted when no button was handled for `DEBOUNCE_TIME`, a held
+//! button repeats every `REPEAT_TIME`.
+//!
+//! A button that is down at boot or held for `STUCK_TIME` counts as stuck and stops repeating,
+//! so a jammed button can't keep filling the air. Releasing it brings it back.
+//!
+//! A and B together switch the page, so a press of A or B alone is held back by `ChordGuard`
+//! until it can't be the start of a chord anymore: the button went up, or it was held for
+//! `CHORD_TIME` without its partner. Otherwise the first of the two would fill the air first.
+
+use fugit::MicrosDurationU64;
+
+use crate::{
+    clock::{Clock, Instant, Rp2040Clock},
+    keymap::{Button, Press, BUTTON_COUNT, CHORDS},
+};
+
+/// Minimum time between two accepted presses
+pub const DEBOUNCE_TIME: MicrosDurationU64 = MicrosDurationU64::millis(100);
+/// Time between repeats of a held button
+pub const REPEAT_TIME: MicrosDurationU64 = MicrosDurationU64::millis(200);
+/// Time a button can be held before it counts as stuck
+pub const STUCK_TIME: MicrosDurationU64 = MicrosDurationU64::secs(30);
+/// Time the partner of a chord may go down after the first button, shorter than `REPEAT_TIME`
+pub const CHORD_TIME: MicrosDurationU64 = MicrosDurationU64::millis(100);
+
+/// Buttons whose tap waits for `CHORD_TIME`, the others act right away
+const GUARDED: [Button; 2] = [Button::A, Button::B];
+
+/// A press the application acts on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonEvent {
+    /// The first button of a chord
+    pub button: Button,
+    pub press: Press,
+    /// When the press was noticed, the time of the interrupt in the interrupt driven binaries
+    pub at: Instant,
+}
+
+/// Which button events are accepted right now
+#[derive(Debug, Clone, Copy)]
+pub struct Debounce {
+    /// Time of the check
+    pub now: Instant,
+    /// A new press may be handled
+    pub press: bool,
+    /// A held button may repeat
+    pub repeat: bool,
+}
+
+/// Debouncer shared by all buttons
+pub struct Debouncer<C: Clock = Rp2040Clock> {
+    clock: C,
+    last_triggered: Option<Instant>,
+}
+
+impl<C: Clock> Debouncer<C> {
+    pub fn new(clock: C) -> Self {
+        Debouncer { clock, last_triggered: None }
+    }
+
+    /// Check which events are accepted, call `triggered` with the result when one was handled
+    pub fn check(&self) -> Debounce {
+        let now = self.clock.now();
+
+        let time_waited = match self.last_triggered {
+            // The very first press is always accepted
+            None => MicrosDurationU64::from_ticks(u64::MAX),
+            Some(last_triggered) if now > last_triggered => now - last_triggered,
+            Some(_) => MicrosDurationU64::micros(0),
+        };
+
+        Debounce {
+            now,
+            press: time_waited > DEBOUNCE_TIME,
+            repeat: time_waited > REPEAT_TIME,
+        }
+    }
+
+    /// A button event was handled at the time of `debounce`
+    pub fn triggered(&mut self, debounce: Debounce) {
+        self.last_triggered = Some(debounce.now);
+    }
+}
+
+/// Buttons that are stuck down
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StuckButtons {
+    /// When each button went down, `None` while it is up
+    down_since: [Option<Instant>; BUTTON_COUNT],
+    stuck: [bool; BUTTON_COUNT],
+}
+
+impl StuckButtons {
+    /// Buttons that are `down` at boot are stuck right away, indexed like `Button::ALL`
+    pub fn at_boot(down: [bool; BUTTON_COUNT]) -> Self {
+        StuckButtons {
+            down_since: [None; BUTTON_COUNT],
+            stuck: down,
+        }
+    }
+
+    /// Which buttons are `down` at `now`, indexed like `Button::ALL`
+    pub fn update(&mut self, now: Instant, down: [bool; BUTTON_COUNT]) {
+        for (index, down) in down.into_iter().enumerate() {
+            if !down {
+                self.down_since[index] = None;
+                self.stuck[index] = false;
+                continue;
+            }
+
+            let since = *self.down_since[index].get_or_insert(now);
+            if now.checked_duration_since(since).is_some_and(|held| held >= STUCK_TIME) {
+                self.stuck[index] = true;
+            }
+        }
+    }
+
+    /// Whether `press` of `button` is handled, a stuck button can still be tapped but doesn't repeat
+    pub fn accepts(&self, button: Button, press: Press) -> bool {
+        press == Press::Tap || !self.stuck[button as usize]
+    }
+
+    /// First stuck button
+    pub fn stuck(&self) -> Option<Button> {
+        Button::ALL.into_iter().find(|&button| self.stuck[button as usize])
+    }
+}
+
+/// Holds back the tap of A or B while it may still become a chord
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChordGuard {
+    /// Button whose tap waits, and when it went down
+    withheld: Option<(Button, Instant)>,
+}
+
+impl ChordGuard {
+    pub const fn new() -> Self {
+        ChordGuard { withheld: None }
+    }
+
+    /// `button` was tapped at `now`, returns whether its tap is held back
+    pub fn press(&mut self, button: Button, now: Instant) -> bool {
+        if !GUARDED.contains(&button) {
+            return false;
+        }
+        // A bounce of the same press keeps the time it went down
+        if self.withheld.is_none_or(|(withheld, _)| withheld != button) {
+            self.withheld = Some((button, now));
+        }
+        true
+    }
+
+    /// Whether the tap of `button` waits, its repeats wait as well
+    pub fn withholds(&self, button: Button) -> bool {
+        self.withheld.is_some_and(|(withheld, _)| withheld == button)
+    }
+
+    /// `first` and `second` went down together, the held back tap of either is dropped
+    pub fn chord(&mut self, first: Button, second: Button) {
+        if self.withholds(first) || self.withholds(second) {
+            self.withheld = None;
+        }
+    }
+
+    /// The button whose held back tap is due at `now`, with the buttons that are `down` indexed like `Button::ALL`
+    pub fn due(&mut self, now: Instant, down: [bool; BUTTON_COUNT]) -> Option<Button> {
+        let (button, since) = self.withheld?;
+        let partner_down = CHORDS
+            .iter()
+            .any(|&(first, second, _)| (first == button && down[second as usize]) || (second == button && down[first as usize]));
+        let waited = now.checked_duration_since(since).is_some_and(|held| held >= CHORD_TIME);
+
+        // While both are down the chord takes the press
+        if !down[button as usize] || (waited && !partner_down) {
+            self.withheld = None;
+            Some(button)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::{clock::ManualClock, diagnostics::InputLatency};
+
+    #[test]
+    fn test_debounce_and_repeat() {
+        let clock = ManualClock::new();
+        let mut debouncer = Debouncer::new(&clock);
+
+        let debounce = debouncer.check();
+        assert!(debounce.press);
+        debouncer.triggered(debounce);
+
+        clock.advance(MicrosDurationU64::millis(50));
+        assert!(!debouncer.check().press);
+
+        clock.advance(MicrosDurationU64::millis(100));
+        let debounce = debouncer.check();
+        assert!(debounce.press && !debounce.repeat);
+
+        clock.advance(MicrosDurationU64::millis(100));
+        assert!(debouncer.check().repeat);
+    }
+
+    #[test]
+    fn test_stuck_buttons() {
+        // Y held at boot
+        let mut stuck = StuckButtons::at_boot([false, false, false, true]);
+        assert_eq!(stuck.stuck(), Some(Button::Y));
+        assert!(!stuck.accepts(Button::Y, Press::Hold));
+        assert!(stuck.accepts(Button::Y, Press::Tap));
+
+        let second = MicrosDurationU64::secs(1);
+        let mut now = Instant::from_ticks(0);
+        stuck.update(now, [true, false, false, false]);
+        assert_eq!(stuck.stuck(), None);
+
+        // A held for 30 seconds stops repeating
+        for _ in 0..29 {
+            now += second;
+            stuck.update(now, [true, false, false, false]);
+        }
+        assert!(stuck.accepts(Button::A, Press::Hold));
+        now += second;
+        stuck.update(now, [true, false, false, false]);
+        assert_eq!(stuck.stuck(), Some(Button::A));
+        assert!(!stuck.accepts(Button::A, Press::Hold));
+        assert!(stuck.accepts(Button::B, Press::Hold));
+
+        now += second;
+        stuck.update(now, [false; BUTTON_COUNT]);
+        assert_eq!(stuck.stuck(), None);
+    }
+
+    #[test]
+    fn test_chord_guard() {
+        let mut guard = ChordGuard::new();
+        let at = |ms: u64| Instant::from_ticks(ms * 1_000);
+        let a_down = [true, false, false, false];
+
+        // Only A and B wait
+        assert!(!guard.press(Button::X, at(0)));
+        assert_eq!(guard.due(at(0), [false, false, true, false]), None);
+
+        // A tapped and let go before the partner could join
+        assert!(guard.press(Button::A, at(0)));
+        assert!(guard.withholds(Button::A));
+        assert_eq!(guard.due(at(50), a_down), None);
+        assert_eq!(guard.due(at(60), [false; BUTTON_COUNT]), Some(Button::A));
+        assert!(!guard.withholds(Button::A));
+
+        // A held alone, a bounce doesn't restart the wait
+        guard.press(Button::A, at(1_000));
+        guard.press(Button::A, at(1_040));
+        assert_eq!(guard.due(at(1_090), a_down), None);
+        assert_eq!(guard.due(at(1_100), a_down), Some(Button::A));
+
+        // B joins A, the chord takes the press and A never fills the air
+        guard.press(Button::A, at(2_000));
+        assert_eq!(guard.due(at(2_050), [true, true, false, false]), None);
+        guard.chord(Button::A, Button::B);
+        assert_eq!(guard.due(at(2_200), [false; BUTTON_COUNT]), None);
+
+        // B waits for Y as well
+        guard.press(Button::B, at(3_000));
+        assert_eq!(guard.due(at(3_200), [false, true, false, true]), None);
+        guard.chord(Button::B, Button::Y);
+        assert!(!guard.withholds(Button::B));
+    }
+
+    #[test]
+    fn test_input_latency() {
+        let mut latency = InputLatency::new();
+        let press = |at: u64| ButtonEvent {
+            button: Button::X,
+            press: Press::Tap,
+            at: Instant::from_ticks(at),
+        };
+
+        // Two presses before the same frame, the first one waited longest
+        latency.pressed(&press(1_000));
+        latency.pressed(&press(30_000));
+        latency.shown(Instant::from_ticks(51_000));
+        // Frames without a press don't count
+        latency.shown(Instant::from_ticks(151_000));
+        latency.pressed(&press(160_000));
+        latency.shown(Instant::from_ticks(170_000));
+
+        assert_eq!((latency.stats.runs, latency.stats.average_us(), latency.stats.max_us), (2, 30_000, 50_000));
+    }
+}
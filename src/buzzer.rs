@@ -0,0 +1,52 @@
+//! Buzzer cadence
+//!
+//! Alarms beep for `BEEP_LENGTH` once per interval, a shorter interval is more urgent. Beeps are
+//! aligned to the clock so several alarms with the same interval beep together. The strobe
+//! flashes on the same schedule with its own lengths, the LED blinks its codes in bursts of it.
+
+use fugit::MicrosDurationU64;
+
+use crate::clock::Instant;
+
+/// Length of a single beep
+pub const BEEP_LENGTH: MicrosDurationU64 = MicrosDurationU64::millis(100);
+
+/// Whether a beep repeating every `interval` sounds at `now`
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::{buzzer::beeping, clock::Instant};
+/// use fugit::MicrosDurationU64;
+///
+/// let interval = MicrosDurationU64::secs(1);
+/// assert!(beeping(Instant::from_ticks(2_050_000), interval));
+/// assert!(!beeping(Instant::from_ticks(2_150_000), interval));
+/// ```
+///
+pub fn beeping(now: Instant, interval: MicrosDurationU64) -> bool {
+    pulsing(now, interval, BEEP_LENGTH)
+}
+
+/// Whether a pulse of `length` repeating every `interval` is on at `now`
+pub fn pulsing(now: Instant, interval: MicrosDurationU64, length: MicrosDurationU64) -> bool {
+    now.duration_since_epoch().to_micros() % interval.to_micros().max(1) < length.to_micros()
+}
+
+/// Whether a burst of `count` pulses of `length`, `spacing` apart and repeating every `interval`, is on at `now`
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::{buzzer::bursting, clock::Instant};
+/// use fugit::MicrosDurationU64;
+///
+/// let (interval, spacing, length) = (MicrosDurationU64::secs(2), MicrosDurationU64::millis(200), MicrosDurationU64::millis(100));
+/// assert!(bursting(Instant::from_ticks(2_450_000), interval, 3, spacing, length));
+/// assert!(!bursting(Instant::from_ticks(2_650_000), interval, 3, spacing, length));
+/// ```
+///
+pub fn bursting(now: Instant, interval: MicrosDurationU64, count: u32, spacing: MicrosDurationU64, length: MicrosDurationU64) -> bool {
+    let since_start = now.duration_since_epoch().to_micros() % interval.to_micros().max(1);
+    since_start < spacing.to_micros() * u64::from(count) && pulsing(Instant::from_ticks(since_start), spacing, length)
+}
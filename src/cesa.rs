@@ -0,0 +1,269 @@
+//! Controlled emergency swimming ascent guide
+//!
+//! Out of air at depth, the way up is a CESA: swimming up at a steady rate while breathing out
+//! all the way, so the expanding air can escape the lungs. Holding Y alone for `CESA_HOLD_TIME`
+//! during a dive opens a guide for it over whatever page is shown: the depth in the 7-segment
+//! font, a bar of the ascent rate with the ascent limit in the middle and "BREATHE OUT" flashing.
+//!
+//! The guide goes ahead of everything else in the button handler, even the button lock. While it
+//! is open X and Y change the rate like on the dive pages, the other buttons do nothing and
+//! holding Y again closes it. It closes by itself at the surface.
+//!
+//! So that the hold doesn't also decrease the rate or start the actions bound to holding Y, Y
+//! alone does nothing else while it is down during a dive. Released before the guide opens it was
+//! a tap, which acts then.
+
+use core::fmt;
+
+use fugit::MicrosDurationU64;
+
+#[cfg(not(test))]
+use crate::info;
+#[cfg(test)]
+use log::info;
+
+use crate::{
+    ascent::Coaching,
+    buzzer::pulsing,
+    clock::{Clock, Instant},
+    deco::DecoModel,
+    units::{rate_in, Depth},
+    DiveComputer,
+};
+
+/// Time Y has to be held to open or close the guide
+pub const CESA_HOLD_TIME: MicrosDurationU64 = MicrosDurationU64::secs(2);
+
+/// Time from one flash of the breathe out reminder to the next, it is shown for half of it
+const EXHALE_INTERVAL: MicrosDurationU64 = MicrosDurationU64::secs(1);
+
+/// Whether the guide is open, and the hold of Y that opens or closes it
+#[derive(Debug, Clone, Copy)]
+pub struct Cesa {
+    active: bool,
+    /// When Y went down
+    held_since: Option<Instant>,
+    /// Whether the current hold already opened or closed the guide
+    toggled: bool,
+    /// Whether the tap of the current hold waits for the release
+    withheld: bool,
+}
+
+impl Cesa {
+    pub const fn new() -> Self {
+        Cesa {
+            active: false,
+            held_since: None,
+            toggled: false,
+            withheld: false,
+        }
+    }
+
+    /// The guide is shown and takes the buttons
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Y is down alone at `now`, `pressed` when it just went down
+    ///
+    /// Returns whether this opened or closed the guide, once per hold. It only opens during a dive.
+    pub fn hold(&mut self, now: Instant, pressed: bool, diving: bool) -> bool {
+        if pressed {
+            self.held_since = Some(now);
+            self.toggled = false;
+            self.withheld = diving && !self.active;
+        }
+
+        let held_long_enough = self
+            .held_since
+            .is_some_and(|since| now.checked_duration_since(since).is_some_and(|held| held >= CESA_HOLD_TIME));
+        if self.toggled || !held_long_enough || !(self.active || diving) {
+            return false;
+        }
+
+        self.active = !self.active;
+        self.toggled = true;
+        self.withheld = false;
+        info!("emergency ascent guide {}", if self.active { "opened" } else { "closed" });
+        true
+    }
+
+    /// Y is held back from everything else, it may still become the hold that opens the guide
+    pub fn withholds(&self) -> bool {
+        self.withheld
+    }
+
+    /// Y went up or another button went down with it
+    ///
+    /// Returns whether the hold was held back and too short to open the guide, so its tap is due.
+    pub fn release(&mut self) -> bool {
+        self.held_since = None;
+        core::mem::take(&mut self.withheld)
+    }
+
+    /// Close the guide once the diver is at the surface
+    pub fn update(&mut self, diving: bool) {
+        if self.active && !diving {
+            self.active = false;
+            info!("emergency ascent guide closed at the surface");
+        }
+    }
+}
+
+impl Default for Cesa {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the guide shows at one moment
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CesaGuide {
+    pub depth: Depth,
+    /// Ascent rate in meters per minute, negative while descending
+    pub ascent_rate: i32,
+    pub coaching: Coaching,
+    /// Ascent rate the guide aims for in meters per minute, the ascent limit of the settings
+    pub target_rate: u32,
+    /// Whether the breathe out reminder is shown
+    pub exhale: bool,
+}
+
+impl CesaGuide {
+    pub fn new<C: Clock, M: DecoModel>(dive_computer: &DiveComputer<C, M>, now: Instant) -> Self {
+        CesaGuide {
+            depth: Depth::new(dive_computer.shown_depth(), dive_computer.unit()),
+            ascent_rate: -dive_computer.rate(),
+            coaching: dive_computer.ascent().coaching(),
+            target_rate: dive_computer.ascent().limit().rate(),
+            exhale: pulsing(now, EXHALE_INTERVAL, EXHALE_INTERVAL / 2),
+        }
+    }
+
+    /// Ascent rate in percent of the target rate, for the rate bar
+    pub fn rate_percent(&self) -> u32 {
+        self.ascent_rate.max(0) as u32 * 100 / self.target_rate
+    }
+}
+
+impl fmt::Display for CesaGuide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = self.depth.unit.primary();
+        // Write to buffer, the depth and the rate bar are drawn over the empty lines
+        writeln!(f, "EMERGENCY ASCENT")?;
+        writeln!(f)?;
+        writeln!(f, "{:12}{}", "", unit.as_str())?;
+        writeln!(
+            f,
+            "ASCENT {:>3}/{} {}/MIN",
+            rate_in(self.ascent_rate, unit),
+            rate_in(self.target_rate as i32, unit),
+            unit.as_str()
+        )?;
+        writeln!(f)?;
+        writeln!(f)?;
+        write!(f, "{}", if self.exhale { "BREATHE OUT" } else { "" })
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use fugit::MicrosDurationU32;
+
+    use super::*;
+    use crate::{ascent::AscentLimit, clock::ManualClock, keymap::Action, Unit};
+
+    #[test]
+    fn test_open_and_close() {
+        let mut cesa = Cesa::new();
+        let at = |millis: u64| Instant::from_ticks(millis * 1_000);
+
+        // Not at the surface
+        assert!(!cesa.hold(at(0), true, false));
+        assert!(!cesa.hold(at(3_000), false, false));
+        assert!(!cesa.is_active());
+
+        assert!(!cesa.hold(at(10_000), true, true));
+        assert!(!cesa.hold(at(11_900), false, true));
+        assert!(cesa.hold(at(12_000), false, true));
+        assert!(cesa.is_active());
+        // Keeping Y down doesn't close it again
+        assert!(!cesa.hold(at(15_000), false, true));
+
+        assert!(!cesa.hold(at(20_000), true, true));
+        assert!(cesa.hold(at(22_000), false, true));
+        assert!(!cesa.is_active());
+
+        cesa.hold(at(30_000), true, true);
+        cesa.hold(at(32_000), false, true);
+        cesa.update(true);
+        assert!(cesa.is_active());
+        cesa.update(false);
+        assert!(!cesa.is_active());
+    }
+
+    #[test]
+    fn test_withholds_y() {
+        let mut cesa = Cesa::new();
+        let at = |millis: u64| Instant::from_ticks(millis * 1_000);
+
+        // At the surface Y acts right away
+        cesa.hold(at(0), true, false);
+        assert!(!cesa.withholds());
+        assert!(!cesa.release());
+
+        // During a dive a short press waits for its release
+        cesa.hold(at(10_000), true, true);
+        assert!(cesa.withholds());
+        assert!(!cesa.hold(at(11_500), false, true));
+        assert!(cesa.withholds());
+        assert!(cesa.release());
+        assert!(!cesa.withholds());
+        // The hold ended with the release
+        assert!(!cesa.hold(at(13_000), false, true));
+
+        // A long one opens the guide instead of being a tap
+        cesa.hold(at(20_000), true, true);
+        assert!(cesa.hold(at(22_000), false, true));
+        assert!(!cesa.withholds());
+        assert!(!cesa.release());
+
+        // The open guide takes Y itself
+        cesa.hold(at(30_000), true, true);
+        assert!(!cesa.withholds());
+    }
+
+    #[test]
+    fn test_guide() {
+        let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+        for _ in 0..20 {
+            dive_computer.perform(Action::IncreaseRate);
+        }
+        dive_computer.change_depth(MicrosDurationU32::minutes(1));
+        for _ in 0..32 {
+            dive_computer.perform(Action::DecreaseRate);
+        }
+
+        let guide = CesaGuide::new(&dive_computer, Instant::from_ticks(250_000));
+        assert_eq!(guide.ascent_rate, 12);
+        assert_eq!(guide.rate_percent(), 80);
+        assert_eq!(guide.depth.digits().as_str(), "20");
+        let page = format!("{}", guide);
+        assert_eq!(page, "EMERGENCY ASCENT\n\n            M\nASCENT  12/15 M/MIN\n\n\nBREATHE OUT");
+        assert!(page.lines().all(|line| line.len() <= 20));
+
+        // The reminder flashes
+        assert!(!CesaGuide::new(&dive_computer, Instant::from_ticks(750_000)).exhale);
+
+        dive_computer.set_unit(Unit::Imperial);
+        let page = format!("{}", CesaGuide::new(&dive_computer, Instant::from_ticks(0)));
+        assert_eq!(page.lines().nth(3), Some("ASCENT  39/49 FT/MIN"));
+
+        // The guide aims for the ascent limit of the settings
+        dive_computer.set_ascent_limit(AscentLimit::Conservative);
+        let guide = CesaGuide::new(&dive_computer, Instant::from_ticks(0));
+        assert_eq!(guide.target_rate, 9);
+        assert_eq!(guide.rate_percent(), 133);
+    }
+}
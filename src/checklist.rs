@@ -0,0 +1,173 @@
+//! Dive-start checklist
+//!
+//! With the checklist switched on in the diver settings, descending from the surface first shows
+//! the checklist over the current page. It takes all buttons: A ticks the selected item off, X
+//! skips the items left and B closes it without diving. Once every item is ticked off or the
+//! rest skipped, the next descend starts the dive. The outcome is kept with the dive summary, so
+//! the logbook shows which dives started without a full check.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(test))]
+use crate::info;
+#[cfg(test)]
+use log::info;
+
+/// What the diver ticks off, in order
+pub const ITEMS: [&str; 3] = ["GAS ANALYZED", "TANK PRESSURE", "BUDDY CHECK"];
+
+/// Whether the checklist is shown before a dive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChecklistMode {
+    #[default]
+    Off,
+    On,
+}
+
+impl ChecklistMode {
+    pub fn next(self) -> Self {
+        match self {
+            ChecklistMode::Off => ChecklistMode::On,
+            ChecklistMode::On => ChecklistMode::Off,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChecklistMode::Off => "OFF",
+            ChecklistMode::On => "ON",
+        }
+    }
+}
+
+/// How the checklist went before a dive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecklistOutcome {
+    /// Switched off, or not gone through yet
+    None,
+    /// Every item ticked off
+    Complete,
+    /// Number of items ticked off before the rest was skipped
+    Skipped(u8),
+}
+
+impl ChecklistOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChecklistOutcome::None => "NONE",
+            ChecklistOutcome::Complete => "DONE",
+            ChecklistOutcome::Skipped(_) => "SKIPPED",
+        }
+    }
+}
+
+/// The checklist shown over the current page
+#[derive(Debug, Clone, Copy)]
+pub struct Checklist {
+    open: bool,
+    /// Items ticked off so far, the next one is selected
+    ticked: usize,
+}
+
+impl Checklist {
+    pub const fn new() -> Self {
+        Checklist { open: false, ticked: 0 }
+    }
+
+    /// The checklist is shown and takes the buttons
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Show the checklist from the first item
+    pub fn open(&mut self) {
+        info!("dive checklist");
+        self.open = true;
+        self.ticked = 0;
+    }
+
+    /// Close without an outcome, the dive doesn't start
+    pub fn cancel(&mut self) {
+        self.open = false;
+    }
+
+    /// Tick off the selected item, returns the outcome when it was the last one
+    pub fn tick(&mut self) -> Option<ChecklistOutcome> {
+        if !self.open {
+            return None;
+        }
+        self.ticked += 1;
+        if self.ticked < ITEMS.len() {
+            return None;
+        }
+        self.open = false;
+        Some(ChecklistOutcome::Complete)
+    }
+
+    /// Skip the items left, returns the outcome
+    pub fn skip(&mut self) -> Option<ChecklistOutcome> {
+        if !self.open {
+            return None;
+        }
+        self.open = false;
+        Some(ChecklistOutcome::Skipped(self.ticked as u8))
+    }
+}
+
+impl Default for Checklist {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Checklist {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Dive checklist")?;
+        writeln!(f)?;
+        for (index, item) in ITEMS.iter().enumerate() {
+            let selected = if index == self.ticked { '>' } else { ' ' };
+            let mark = if index < self.ticked { 'X' } else { ' ' };
+            writeln!(f, "{}[{}] {}", selected, mark, item)?;
+        }
+        writeln!(f)?;
+        writeln!(f, "A: TICK OFF")?;
+        writeln!(f, "X: SKIP REST")?;
+        write!(f, "B: CANCEL")
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_tick_and_skip() {
+        let mut checklist = Checklist::new();
+        // Nothing to tick off while closed
+        assert_eq!(checklist.tick(), None);
+        assert_eq!(checklist.skip(), None);
+
+        checklist.open();
+        assert_eq!(checklist.tick(), None);
+        assert_eq!(
+            format!("{}", checklist),
+            "Dive checklist\n\n [X] GAS ANALYZED\n>[ ] TANK PRESSURE\n [ ] BUDDY CHECK\n\nA: TICK OFF\nX: SKIP REST\nB: CANCEL"
+        );
+        assert!(format!("{}", checklist).lines().all(|line| line.len() <= 20));
+        assert_eq!(checklist.tick(), None);
+        assert_eq!(checklist.tick(), Some(ChecklistOutcome::Complete));
+        assert!(!checklist.is_open());
+
+        // Opening again starts over
+        checklist.open();
+        checklist.tick();
+        assert_eq!(checklist.skip(), Some(ChecklistOutcome::Skipped(1)));
+        checklist.open();
+        checklist.cancel();
+        assert!(!checklist.is_open());
+    }
+}
@@ -0,0 +1,330 @@
+//! Time source for the dive computer and button handling
+//!
+//! The binaries use the 1 MHz RP2040 timer, host tests use a `ManualClock` they can fast-forward.
+//!
+//! The timer runs off the crystal, which is off by some parts per million. `ClockSync` measures
+//! that drift against the time of a host given twice over the console, and the resulting
+//! `DriftCorrection` corrects the dive time and the log timestamps.
+
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicI32, Ordering},
+};
+
+use fugit::{MicrosDurationU64, TimerInstantU64};
+
+use pimoroni_pico_explorer::hal::pac;
+use serde::{Deserialize, Serialize};
+
+/// Point in time with microsecond resolution
+pub type Instant = TimerInstantU64<1_000_000>;
+
+pub trait Clock {
+    /// Current time
+    fn now(&self) -> Instant;
+}
+
+impl<C: Clock> Clock for &C {
+    fn now(&self) -> Instant {
+        (*self).now()
+    }
+}
+
+/// The 64 bit RP2040 timer
+///
+/// The timer has to be taken out of reset first, e.g. by creating a `hal::Timer` or the RTIC
+/// monotonic. Reading the counter doesn't interfere with either of them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Rp2040Clock;
+
+impl Clock for Rp2040Clock {
+    fn now(&self) -> Instant {
+        // Only reads the raw counter registers, which have no side effects
+        let timer = unsafe { &*pac::TIMER::ptr() };
+
+        // Read high, low, high again to detect the low word rolling over in between
+        let mut high = timer.timerawh.read().bits();
+        let ticks = loop {
+            let low = timer.timerawl.read().bits();
+            let next_high = timer.timerawh.read().bits();
+            if high == next_high {
+                break (u64::from(high) << 32) | u64::from(low);
+            }
+            high = next_high;
+        };
+
+        Instant::from_ticks(ticks)
+    }
+}
+
+// Every log line of every binary starts with the corrected time since boot, shown in seconds by
+// the host, or with the Unix time once the console set it. Logs from before the timer is out of
+// reset don't have a meaningful time.
+#[cfg(not(test))]
+defmt::timestamp!(
+    "{=u64:us}",
+    drift().correct(Rp2040Clock.now()).duration_since_epoch().to_micros() + u64::from(crate::wall_clock::log_offset()) * 1_000_000
+);
+
+/// Largest drift that is corrected, a crystal further off is broken
+pub const MAX_DRIFT_PPM: i32 = 1_000;
+
+/// Shortest time between the host times of a measurement
+pub const MIN_SYNC_SPAN: MicrosDurationU64 = MicrosDurationU64::secs(10);
+
+/// Drift of the log timestamps, only loads and stores so it works on the Cortex-M0+
+static DRIFT_PPM: AtomicI32 = AtomicI32::new(0);
+
+/// Correct the log timestamps with `correction` from now on
+pub fn set_drift(correction: DriftCorrection) {
+    DRIFT_PPM.store(correction.ppm, Ordering::Relaxed);
+}
+
+/// Correction of the log timestamps
+pub fn drift() -> DriftCorrection {
+    DriftCorrection {
+        ppm: DRIFT_PPM.load(Ordering::Relaxed),
+    }
+}
+
+/// How fast the timer runs in parts per million, positive when it runs ahead
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct DriftCorrection {
+    pub ppm: i32,
+}
+
+impl DriftCorrection {
+    /// No drift
+    pub const fn new() -> Self {
+        DriftCorrection { ppm: 0 }
+    }
+
+    /// Whether the drift is small enough to correct
+    pub fn is_valid(&self) -> bool {
+        self.ppm.abs() <= MAX_DRIFT_PPM
+    }
+
+    /// Drift of a timer that measured `timer` while the host measured `host`, `None` for an empty span
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::clock::DriftCorrection;
+    /// use fugit::MicrosDurationU64;
+    /// let drift = DriftCorrection::measure(MicrosDurationU64::micros(100_002_000), MicrosDurationU64::secs(100));
+    /// assert_eq!(drift, Some(DriftCorrection { ppm: 20 }));
+    /// ```
+    ///
+    pub fn measure(timer: MicrosDurationU64, host: MicrosDurationU64) -> Option<Self> {
+        let host = i128::from(host.ticks());
+        let drift = (i128::from(timer.ticks()) - host) * 1_000_000;
+        // Round to the nearest ppm
+        let ppm = (drift + drift.signum() * host / 2).checked_div(host)?;
+        Some(DriftCorrection {
+            ppm: ppm.clamp(i32::MIN.into(), i32::MAX.into()) as i32,
+        })
+    }
+
+    /// Time since boot of `instant` read from the timer
+    ///
+    /// Corrects the whole time since boot rather than every interval, so rounding doesn't add up.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::clock::{DriftCorrection, Instant};
+    /// let fast = DriftCorrection { ppm: 20 };
+    /// assert_eq!(fast.correct(Instant::from_ticks(100_002_000)), Instant::from_ticks(100_000_000));
+    /// ```
+    ///
+    pub fn correct(&self, instant: Instant) -> Instant {
+        let ticks = u128::from(instant.ticks()) * 1_000_000 / (1_000_000 + self.ppm.clamp(-MAX_DRIFT_PPM, MAX_DRIFT_PPM)) as u128;
+        Instant::from_ticks(ticks.min(u128::from(u64::MAX)) as u64)
+    }
+}
+
+/// What a host time given to `ClockSync` led to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncResult {
+    /// The host time is the reference from now on
+    Reference,
+    /// Less than `MIN_SYNC_SPAN` after the reference
+    TooSoon,
+    Drift(DriftCorrection),
+}
+
+/// Measures the drift of the timer against host times given over the console
+///
+/// The first host time is the reference, every later one measures the drift over the whole time
+/// since, so the longer the host waits the more precise the drift.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClockSync {
+    /// Timer and host time of the reference
+    reference: Option<(Instant, MicrosDurationU64)>,
+}
+
+impl ClockSync {
+    pub const fn new() -> Self {
+        ClockSync { reference: None }
+    }
+
+    /// The host time was `host` at the timer reading `now`
+    ///
+    /// A host time before the reference starts over with it as the reference.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::clock::{ClockSync, DriftCorrection, Instant, SyncResult};
+    /// use fugit::MicrosDurationU64;
+    /// let mut sync = ClockSync::new();
+    /// assert_eq!(sync.sync(Instant::from_ticks(5_000_000), MicrosDurationU64::secs(1_000)), SyncResult::Reference);
+    /// assert_eq!(sync.sync(Instant::from_ticks(6_000_000), MicrosDurationU64::secs(1_001)), SyncResult::TooSoon);
+    /// // 100 s on the host, 50 us less on the timer
+    /// let result = sync.sync(Instant::from_ticks(104_999_950), MicrosDurationU64::secs(1_100));
+    /// assert_eq!(result, SyncResult::Drift(DriftCorrection { ppm: -1 }));
+    /// ```
+    ///
+    pub fn sync(&mut self, now: Instant, host: MicrosDurationU64) -> SyncResult {
+        if let Some((reference, host_reference)) = self.reference {
+            let timer = now.checked_duration_since(reference);
+            match (timer, host.checked_sub(host_reference)) {
+                (Some(_), Some(span)) if span < MIN_SYNC_SPAN => return SyncResult::TooSoon,
+                (Some(timer), Some(span)) => {
+                    if let Some(drift) = DriftCorrection::measure(timer, span) {
+                        return SyncResult::Drift(drift);
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.reference = Some((now, host));
+        SyncResult::Reference
+    }
+}
+
+/// Clock that only moves when told to
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::clock::{Clock, ManualClock};
+/// use fugit::MicrosDurationU64;
+///
+/// let clock = ManualClock::new();
+/// clock.advance(MicrosDurationU64::secs(2));
+/// assert_eq!(clock.now().ticks(), 2_000_000);
+/// ```
+///
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    ticks: Cell<u64>,
+}
+
+impl ManualClock {
+    pub const fn new() -> Self {
+        ManualClock { ticks: Cell::new(0) }
+    }
+
+    /// Move the clock forward by `duration`
+    pub fn advance(&self, duration: MicrosDurationU64) {
+        self.ticks.set(self.ticks.get() + duration.ticks());
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        Instant::from_ticks(self.ticks.get())
+    }
+}
+
+/// Time left of something counted down in ticks of known length
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::clock::Countdown;
+/// use fugit::MicrosDurationU64;
+///
+/// let mut countdown = Countdown::new();
+/// countdown.start(MicrosDurationU64::secs(2));
+/// countdown.tick(MicrosDurationU64::millis(1500));
+/// assert_eq!(countdown.remaining(), MicrosDurationU64::millis(500));
+/// countdown.tick(MicrosDurationU64::secs(1));
+/// assert!(!countdown.active());
+/// ```
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Countdown {
+    remaining: MicrosDurationU64,
+}
+
+impl Countdown {
+    /// Nothing to count down
+    pub const fn new() -> Self {
+        Countdown {
+            remaining: MicrosDurationU64::from_ticks(0),
+        }
+    }
+
+    /// Count down `duration` from now, also when already counting
+    pub fn start(&mut self, duration: MicrosDurationU64) {
+        self.remaining = duration;
+    }
+
+    /// Count down `duration`, stopping at zero
+    pub fn tick(&mut self, duration: MicrosDurationU64) {
+        self.remaining = MicrosDurationU64::from_ticks(self.remaining.ticks().saturating_sub(duration.ticks()));
+    }
+
+    pub fn active(&self) -> bool {
+        self.remaining.ticks() > 0
+    }
+
+    pub fn remaining(&self) -> MicrosDurationU64 {
+        self.remaining
+    }
+}
+
+impl Default for Countdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Speed of the simulation compared to the clock, to show a whole dive in class in a minute
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TimeScale {
+    #[default]
+    RealTime,
+    X10,
+    X60,
+}
+
+impl TimeScale {
+    /// Simulated time per clock time
+    pub const fn factor(&self) -> u32 {
+        match self {
+            TimeScale::RealTime => 1,
+            TimeScale::X10 => 10,
+            TimeScale::X60 => 60,
+        }
+    }
+
+    /// Faster scale, wrapping around to real time
+    pub fn next(self) -> Self {
+        match self {
+            TimeScale::RealTime => TimeScale::X10,
+            TimeScale::X10 => TimeScale::X60,
+            TimeScale::X60 => TimeScale::RealTime,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeScale::RealTime => "1X",
+            TimeScale::X10 => "10X",
+            TimeScale::X60 => "60X",
+        }
+    }
+}
@@ -0,0 +1,758 @@
+//! Text console
+//!
+//! Commands come in a line at a time. `settings export` prints the settings as a single base64
+//! line, `settings import <base64>` loads such a line, so an instructor can set up one device and
+//! push the same settings to the rest of the class. `stats export` prints the lifetime
+//! statistics the same way, they can't be imported. `timestamp` prints the time since boot like
+//! the log timestamps, to line up the host and device logs. `clock sync <micros>` gives the time
+//! of the host, the first one is the reference and later ones measure the drift of the crystal
+//! against it and correct the dive time and the timestamps with it. `replay` prints the replay checksum
+//! in hex and the number of inputs it covers. `log level <off|error|info|debug>`
+//! sets how much is logged. `flash test <cycles>` is for development and not in the manual: it
+//! runs a wear test on the scratch flash and prints the statistics. `flash wear` prints the wear
+//! heatmap of the storage and the erase count of every sector, see the `storage` module. Builds
+//! without the `logbook` feature know neither of them. `experiment ...` loads the CPU for the scheduling lessons of
+//! the `experiment` module and prints what is running. `macro ...` records and replays the button
+//! presses, see the `input_macro` module, it is for development too. `time set <unix-ts>` sets the
+//! date and time and moves the log timestamps onto it, `time get` prints it, see the
+//! `wall_clock` module. `faults` lists the recent faults with their codes, newest first, see the
+//! `fault` module. `marks` prints the marks of the last dive as UDDF waypoints, see the `mark`
+//! module. `audit` lists the last actions of the diver with the time since boot and the depth,
+//! see the `audit` module.
+//!
+//! On the device the lines come in over the `console` RTT down channel, `LineReader` collects
+//! them, and the replies go out on the `console` RTT up channel next to the defmt logs, e.g.
+//! with `probe-rs attach --rtt-channel console`.
+//!
+//! Exports are serialized with postcard behind a format version byte and followed by a CRC-32,
+//! so a line that got cut off or mistyped is refused instead of loaded. Each device keeps
+//! its own pressure sensor calibration and clock drift, and the bindings of the planner, apnea, signal, settings and self test pages stay
+//! fixed like on the settings page.
+
+use core::fmt;
+
+#[cfg(not(test))]
+use crate::info;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crc::{Crc, CRC_32_ISO_HDLC};
+use embedded_storage::nor_flash::NorFlash;
+use fugit::MicrosDurationU64;
+#[cfg(test)]
+use log::info;
+use serde::{de::DeserializeOwned, Serialize};
+
+#[cfg(feature = "logbook")]
+use crate::storage::{wear_test, WearMap};
+use crate::{
+    audit::{AuditTrail, AUDIT_TRAIL_SIZE, MAX_ENTRY_LEN},
+    clock::{Clock, ClockSync, SyncResult},
+    experiment::{Experiment, ExperimentCommand},
+    fault::FaultLog,
+    input_macro::{ButtonMacro, MacroCommand, MacroRecorder, MacroStore},
+    keymap::{Button, Press},
+    log_level::{self, LogLevel},
+    mark::{Mark, MARK_COUNT, MAX_WAYPOINT_LEN},
+    odometer::LifetimeStats,
+    replay::ReplayChecksum,
+    ring_buffer::RingBuffer,
+    settings::Settings,
+    text_buffer::TextBuffer,
+    ui::Page,
+    wall_clock::{CivilTime, SetDateTime, WallClock, MIN_UNIX_TIME},
+};
+
+/// Version of the exported settings, raised when `Settings` changes
+pub const SETTINGS_FORMAT: u8 = 23;
+
+/// Version of the exported lifetime statistics, never accepted as settings
+pub const STATS_FORMAT: u8 = 0x81;
+
+/// Version of the exported button macros
+pub const MACRO_FORMAT: u8 = 0x82;
+
+/// Largest export in bytes, including the version and CRC
+const MAX_EXPORT_BYTES: usize = 224;
+
+/// Longest exported line, 4 characters per 3 bytes
+pub const MAX_EXPORT_LEN: usize = MAX_EXPORT_BYTES.div_ceil(3) * 4;
+
+/// Longest reply, the whole audit trail
+pub const MAX_REPLY_LEN: usize = AUDIT_TRAIL_SIZE * MAX_ENTRY_LEN;
+
+/// Reply to one console line
+pub type Reply = TextBuffer<MAX_REPLY_LEN>;
+
+/// Longest line that is read, `settings import` with an export and a CR LF
+pub const MAX_LINE_LEN: usize = MAX_EXPORT_LEN + 20;
+
+// An export and its newline always fit a reply, and so do the marks
+const _: () = assert!(MAX_EXPORT_LEN < MAX_REPLY_LEN);
+const _: () = assert!(MARK_COUNT * MAX_WAYPOINT_LEN <= MAX_REPLY_LEN);
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Offset of the scratch flash used by `flash test`, below the records of `factory_reset::REGIONS`
+pub const SCRATCH_START: u32 = 0x1F_6000;
+
+/// Sectors of the scratch flash used by `flash test`
+pub const SCRATCH_SECTORS: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command<'a> {
+    SettingsExport,
+    /// Base64 line from an export
+    SettingsImport(&'a str),
+    StatsExport,
+    Timestamp,
+    /// Host time in microseconds
+    ClockSync(u64),
+    LogLevel(LogLevel),
+    /// Wear test of this many writes
+    FlashTest(u32),
+    /// Erase counts of the storage
+    FlashWear,
+    /// Replay checksum and the number of inputs
+    Replay,
+    Experiment(ExperimentCommand),
+    Macro(MacroCommand<'a>),
+    /// Unix time in seconds
+    TimeSet(u32),
+    TimeGet,
+    Faults,
+    Marks,
+    Audit,
+}
+
+impl<'a> Command<'a> {
+    /// Parse one line, surrounding whitespace is ignored
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::console::Command;
+    /// assert_eq!(Command::parse(" settings export\r"), Ok(Command::SettingsExport));
+    /// assert!(Command::parse("settings import").is_err());
+    /// ```
+    ///
+    pub fn parse(line: &'a str) -> Result<Self, ConsoleError> {
+        let mut words = line.split_whitespace();
+        if line.split_whitespace().next() == Some("experiment") {
+            return ExperimentCommand::parse(words.skip(1)).map(Command::Experiment).ok_or(ConsoleError::UnknownCommand);
+        }
+        if line.split_whitespace().next() == Some("macro") {
+            return MacroCommand::parse(words.skip(1)).map(Command::Macro).ok_or(ConsoleError::UnknownCommand);
+        }
+        match (words.next(), words.next(), words.next(), words.next()) {
+            (Some("settings"), Some("export"), None, None) => Ok(Command::SettingsExport),
+            (Some("settings"), Some("import"), Some(data), None) => Ok(Command::SettingsImport(data)),
+            (Some("stats"), Some("export"), None, None) => Ok(Command::StatsExport),
+            (Some("timestamp"), None, None, None) => Ok(Command::Timestamp),
+            (Some("clock"), Some("sync"), Some(micros), None) => micros.parse().map(Command::ClockSync).map_err(|_| ConsoleError::UnknownCommand),
+            (Some("replay"), None, None, None) => Ok(Command::Replay),
+            (Some("time"), Some("set"), Some(unix), None) => unix.parse().map(Command::TimeSet).map_err(|_| ConsoleError::UnknownCommand),
+            (Some("time"), Some("get"), None, None) => Ok(Command::TimeGet),
+            (Some("faults"), None, None, None) => Ok(Command::Faults),
+            (Some("marks"), None, None, None) => Ok(Command::Marks),
+            (Some("audit"), None, None, None) => Ok(Command::Audit),
+            (Some("log"), Some("level"), Some(name), None) => LogLevel::parse(name).map(Command::LogLevel).ok_or(ConsoleError::UnknownLevel),
+            (Some("flash"), Some("test"), Some(cycles), None) => cycles.parse().map(Command::FlashTest).map_err(|_| ConsoleError::UnknownCommand),
+            (Some("flash"), Some("wear"), None, None) => Ok(Command::FlashWear),
+            _ => Err(ConsoleError::UnknownCommand),
+        }
+    }
+
+    /// Whether running it may change the settings, which then have to be stored again
+    pub fn changes_settings(&self) -> bool {
+        matches!(self, Command::SettingsImport(_) | Command::ClockSync(_))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleError {
+    UnknownCommand,
+    /// Not valid base64, or too long to be settings
+    Encoding,
+    /// Exported by a firmware with another settings format
+    Version,
+    /// The CRC doesn't match, or the data doesn't describe settings
+    Corrupt,
+    /// Values the settings page can't set
+    OutOfRange,
+    UnknownLevel,
+    /// The flash couldn't be read or written
+    Flash,
+    /// Less than `MIN_SYNC_SPAN` since the reference
+    ///
+    /// [`MIN_SYNC_SPAN`]: crate::clock::MIN_SYNC_SPAN
+    TooSoon,
+    /// `time get` before `time set`
+    TimeNotSet,
+    /// More than `MAX_LINE_LEN` bytes or not UTF-8
+    BadLine,
+    /// `macro load` before `macro save`
+    NotSaved,
+}
+
+impl ConsoleError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConsoleError::UnknownCommand => "UNKNOWN COMMAND",
+            ConsoleError::Encoding => "BAD BASE64",
+            ConsoleError::Version => "WRONG VERSION",
+            ConsoleError::Corrupt => "BAD CRC",
+            ConsoleError::OutOfRange => "OUT OF RANGE",
+            ConsoleError::UnknownLevel => "UNKNOWN LEVEL",
+            ConsoleError::Flash => "FLASH FAILED",
+            ConsoleError::TooSoon => "TOO SOON",
+            ConsoleError::TimeNotSet => "TIME NOT SET",
+            ConsoleError::BadLine => "BAD LINE",
+            ConsoleError::NotSaved => "NOT SAVED",
+        }
+    }
+}
+
+/// Collects the bytes from the host into lines
+#[derive(Debug, Clone)]
+pub struct LineReader {
+    bytes: [u8; MAX_LINE_LEN],
+    len: usize,
+    /// The line outgrew `bytes`, it is refused at its end
+    overflow: bool,
+    /// The last byte ended a line, the next one starts a new line
+    ended: bool,
+}
+
+impl LineReader {
+    pub const fn new() -> Self {
+        LineReader {
+            bytes: [0; MAX_LINE_LEN],
+            len: 0,
+            overflow: false,
+            ended: false,
+        }
+    }
+
+    /// Add `byte`, returns the line when it ended it
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::console::LineReader;
+    /// let mut reader = LineReader::new();
+    /// assert_eq!(reader.push(b'o'), None);
+    /// assert_eq!(reader.push(b'k'), None);
+    /// assert_eq!(reader.push(b'\n'), Some(Ok("ok")));
+    /// ```
+    ///
+    pub fn push(&mut self, byte: u8) -> Option<Result<&str, ConsoleError>> {
+        if self.ended {
+            self.len = 0;
+            self.overflow = false;
+            self.ended = false;
+        }
+
+        if byte == b'\n' {
+            self.ended = true;
+            return Some(match self.overflow {
+                true => Err(ConsoleError::BadLine),
+                false => core::str::from_utf8(&self.bytes[..self.len]).map_err(|_| ConsoleError::BadLine),
+            });
+        }
+        match self.bytes.get_mut(self.len) {
+            Some(slot) => {
+                *slot = byte;
+                self.len += 1;
+            }
+            None => self.overflow = true,
+        }
+        None
+    }
+}
+
+impl Default for LineReader {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Write `value` as a base64 line in `format` to `out`
+fn export<T: Serialize>(format: u8, value: &T, out: &mut Reply) -> fmt::Result {
+    let mut bytes = [0; MAX_EXPORT_BYTES];
+    bytes[0] = format;
+    // Only fails when `value` outgrows `MAX_EXPORT_BYTES`, which the tests catch
+    let len = 1 + postcard::to_slice_crc32(value, &mut bytes[1..], CRC.digest()).map_err(|_| fmt::Error)?.len();
+
+    let mut text = [0; MAX_EXPORT_LEN];
+    let text_len = STANDARD.encode_slice(&bytes[..len], &mut text).map_err(|_| fmt::Error)?;
+    out.push_str(core::str::from_utf8(&text[..text_len]).map_err(|_| fmt::Error)?)
+}
+
+/// Read a value in `format` from a base64 line made by `export`
+fn decode<T: DeserializeOwned>(format: u8, data: &str) -> Result<T, ConsoleError> {
+    let mut bytes = [0; MAX_EXPORT_BYTES];
+    let len = STANDARD.decode_slice(data, &mut bytes).map_err(|_| ConsoleError::Encoding)?;
+
+    match bytes[..len].split_first() {
+        Some((&version, rest)) if version == format => postcard::from_bytes_crc32(rest, CRC.digest()).map_err(|_| ConsoleError::Corrupt),
+        Some(_) => Err(ConsoleError::Version),
+        None => Err(ConsoleError::Corrupt),
+    }
+}
+
+/// Read settings from a base64 line made by `export`
+pub fn import(data: &str) -> Result<Settings, ConsoleError> {
+    let settings: Settings = decode(SETTINGS_FORMAT, data)?;
+    if !settings.gradient_factors.is_valid()
+        || !settings.ascent_limit.is_valid()
+        || !settings.reserve.is_valid()
+        || !settings.apnea.is_valid()
+        || !settings.depth_alerts.is_valid()
+        || !settings.ndl_warnings.is_valid()
+        || !settings.back_gas.is_valid()
+        || !settings.deco_gases.is_valid()
+    {
+        return Err(ConsoleError::OutOfRange);
+    }
+    Ok(settings)
+}
+
+/// Read a button macro from a base64 line made by `macro export`
+pub fn import_macro(data: &str) -> Result<ButtonMacro, ConsoleError> {
+    let recording: ButtonMacro = decode(MACRO_FORMAT, data)?;
+    match recording.is_valid() {
+        true => Ok(recording),
+        false => Err(ConsoleError::OutOfRange),
+    }
+}
+
+/// Load `imported` into `settings`, keeping what belongs to this device
+fn apply(imported: &Settings, settings: &mut Settings) {
+    *settings = Settings {
+        bindings: settings.bindings,
+        calibration: settings.calibration,
+        clock_drift: settings.clock_drift,
+        bus: settings.bus,
+        ..*imported
+    };
+
+    // `set` refuses the pages whose bindings are fixed
+    let mut page = Page::Main;
+    loop {
+        for button in Button::ALL {
+            for press in Press::ALL {
+                settings.bindings.set(page, button, press, imported.bindings.action(page, button, press));
+            }
+        }
+        page = page.next();
+        if page == Page::Main {
+            break;
+        }
+    }
+}
+
+/// State of the device the console works on
+pub struct Device<'a> {
+    pub settings: &'a mut Settings,
+    pub lifetime: &'a LifetimeStats,
+    pub replay: &'a ReplayChecksum,
+    pub experiment: &'a mut Experiment,
+    pub clock_sync: &'a mut ClockSync,
+    pub button_macro: &'a mut MacroRecorder,
+    /// `None` when the flash for it couldn't be mounted
+    pub macro_store: Option<&'a mut dyn MacroStore>,
+    pub wall_clock: &'a mut WallClock,
+    pub rtc: &'a mut dyn SetDateTime,
+    #[cfg(feature = "logbook")]
+    pub wear: &'a WearMap,
+    pub faults: &'a FaultLog,
+    pub marks: &'a RingBuffer<Mark, MARK_COUNT>,
+    pub audit_trail: &'a AuditTrail<AUDIT_TRAIL_SIZE>,
+}
+
+/// Run one console line on `device` and replace `out` with the reply
+///
+/// `scratch` is flash without records, `flash test` erases it.
+pub fn execute(line: &str, clock: &impl Clock, device: Device, scratch: &mut impl NorFlash, out: &mut Reply) {
+    let Device {
+        settings,
+        lifetime,
+        replay,
+        experiment,
+        clock_sync,
+        button_macro,
+        macro_store,
+        wall_clock,
+        rtc,
+        #[cfg(feature = "logbook")]
+        wear,
+        faults,
+        marks,
+        audit_trail,
+    } = device;
+    out.clear();
+    match Command::parse(line) {
+        // Exports only fail when a value outgrows `MAX_EXPORT_BYTES`, which the tests catch
+        Ok(Command::SettingsExport) => {
+            if export(SETTINGS_FORMAT, settings, out).is_ok() {
+                writeln!(out);
+            }
+        }
+        Ok(Command::StatsExport) => {
+            if export(STATS_FORMAT, lifetime, out).is_ok() {
+                writeln!(out);
+            }
+        }
+        Ok(Command::Timestamp) => {
+            // Same format and correction as the timestamps of the logs
+            let micros = settings.clock_drift.correct(clock.now()).duration_since_epoch().to_micros() + u64::from(wall_clock.offset()) * 1_000_000;
+            writeln!(out, "{}.{:06}", micros / 1_000_000, micros % 1_000_000)
+        }
+        Ok(Command::ClockSync(micros)) => match clock_sync.sync(clock.now(), MicrosDurationU64::micros(micros)) {
+            SyncResult::Reference => writeln!(out, "CLOCK: REFERENCE"),
+            SyncResult::TooSoon => writeln!(out, "ERROR: {}", ConsoleError::TooSoon.as_str()),
+            SyncResult::Drift(drift) if drift.is_valid() => {
+                settings.clock_drift = drift;
+                info!("clock drift {} ppm", drift.ppm);
+                writeln!(out, "DRIFT: {:+}PPM", drift.ppm)
+            }
+            SyncResult::Drift(_) => writeln!(out, "ERROR: {}", ConsoleError::OutOfRange.as_str()),
+        },
+        Ok(Command::TimeSet(unix)) => {
+            let time = CivilTime::from_unix(unix);
+            if unix < MIN_UNIX_TIME || !rtc.set_date_time(&time) || !wall_clock.set(settings.clock_drift.correct(clock.now()), unix) {
+                writeln!(out, "ERROR: {}", ConsoleError::OutOfRange.as_str())
+            } else {
+                info!("time set to {}", unix);
+                writeln!(out, "TIME: {}", time)
+            }
+        }
+        Ok(Command::TimeGet) => match wall_clock.unix_time(settings.clock_drift.correct(clock.now())) {
+            Some(unix) => writeln!(out, "{}", unix),
+            None => writeln!(out, "ERROR: {}", ConsoleError::TimeNotSet.as_str()),
+        },
+        Ok(Command::Replay) => writeln!(out, "{:08X} {}", replay.crc(), replay.inputs()),
+        Ok(Command::Experiment(command)) => {
+            experiment.apply(command);
+            writeln!(out, "{}", experiment)
+        }
+        Ok(Command::Macro(MacroCommand::Export)) => {
+            if export(MACRO_FORMAT, button_macro.recording(), out).is_ok() {
+                writeln!(out);
+            }
+        }
+        Ok(Command::Macro(MacroCommand::Import(data))) => match import_macro(data) {
+            Ok(recording) => {
+                button_macro.load(recording);
+                writeln!(out, "{}", button_macro)
+            }
+            Err(error) => writeln!(out, "ERROR: {}", error.as_str()),
+        },
+        Ok(Command::Macro(MacroCommand::Save)) => match macro_store {
+            Some(store) if store.save_macro(button_macro.recording()) => writeln!(out, "{}", button_macro),
+            _ => writeln!(out, "ERROR: {}", ConsoleError::Flash.as_str()),
+        },
+        Ok(Command::Macro(MacroCommand::Load)) => match macro_store.map(|store| store.load_macro()) {
+            Some(Some(recording)) => {
+                button_macro.load(recording);
+                writeln!(out, "{}", button_macro)
+            }
+            Some(None) => writeln!(out, "ERROR: {}", ConsoleError::NotSaved.as_str()),
+            None => writeln!(out, "ERROR: {}", ConsoleError::Flash.as_str()),
+        },
+        Ok(Command::Macro(command)) => {
+            match command {
+                MacroCommand::Record => button_macro.record(clock.now()),
+                MacroCommand::Stop => button_macro.stop(),
+                MacroCommand::Play => button_macro.play(clock.now()),
+                _ => {}
+            }
+            writeln!(out, "{}", button_macro)
+        }
+        #[cfg(feature = "logbook")]
+        Ok(Command::FlashWear) => {
+            writeln!(out, "{}", wear);
+            if !wear.erases().is_empty() {
+                write!(out, "ERASES:");
+                for erases in wear.erases() {
+                    write!(out, " {}", erases);
+                }
+                writeln!(out);
+            }
+        }
+        Ok(Command::Faults) => writeln!(out, "{}", faults),
+        Ok(Command::Marks) => {
+            for mark in marks.iter() {
+                writeln!(out, "{}", mark);
+            }
+        }
+        Ok(Command::Audit) => {
+            for entry in audit_trail.iter() {
+                writeln!(out, "{}", entry);
+            }
+        }
+        Ok(Command::LogLevel(level)) => {
+            log_level::set_level(level);
+            writeln!(out, "LOG LEVEL: {}", level.as_str())
+        }
+        #[cfg(feature = "logbook")]
+        Ok(Command::FlashTest(cycles)) => match wear_test(scratch, SCRATCH_SECTORS, cycles, clock) {
+            Ok(report) => writeln!(out, "{}", report),
+            Err(_) => writeln!(out, "ERROR: {}", ConsoleError::Flash.as_str()),
+        },
+        #[cfg(not(feature = "logbook"))]
+        Ok(Command::FlashTest(_) | Command::FlashWear) => {
+            let _ = scratch;
+            writeln!(out, "ERROR: {}", ConsoleError::UnknownCommand.as_str())
+        }
+        Ok(Command::SettingsImport(data)) => match import(data) {
+            Ok(imported) => {
+                apply(&imported, settings);
+                info!("settings imported");
+                writeln!(out, "OK");
+            }
+            Err(error) => writeln!(out, "ERROR: {}", error.as_str()),
+        },
+        Err(error) => writeln!(out, "ERROR: {}", error.as_str()),
+    }
+}
+
+#[cfg(all(test, feature = "logbook"))]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        clock::{Instant, ManualClock},
+        deco::GradientFactors,
+        fault::FaultCode,
+        input_macro::MAX_PRESSES,
+        keymap::Action,
+        settings::test::largest,
+        storage::{test::RamFlash, Storage},
+        DiveComputer,
+    };
+    use fugit::SecsDurationU64;
+
+    fn run(line: &str, settings: &mut Settings) -> String {
+        run_macro(line, settings, &mut MacroRecorder::new())
+    }
+
+    /// RTC that keeps the last time it was set to
+    struct FakeRtc(Option<CivilTime>);
+
+    impl SetDateTime for FakeRtc {
+        fn set_date_time(&mut self, time: &CivilTime) -> bool {
+            self.0 = Some(*time);
+            true
+        }
+    }
+
+    fn run_macro(line: &str, settings: &mut Settings, button_macro: &mut MacroRecorder) -> String {
+        run_time(line, settings, button_macro, &mut WallClock::new(), &mut FakeRtc(None))
+    }
+
+    fn run_time(line: &str, settings: &mut Settings, button_macro: &mut MacroRecorder, wall_clock: &mut WallClock, rtc: &mut FakeRtc) -> String {
+        run_device(line, settings, button_macro, None, wall_clock, rtc)
+    }
+
+    fn run_device(
+        line: &str,
+        settings: &mut Settings,
+        button_macro: &mut MacroRecorder,
+        macro_store: Option<&mut dyn MacroStore>,
+        wall_clock: &mut WallClock,
+        rtc: &mut FakeRtc,
+    ) -> String {
+        let mut out = Reply::new();
+        let lifetime = LifetimeStats {
+            dives: 1_000,
+            bottom_time: 1_000 * 3600,
+            deepest: 60_000,
+        };
+        let clock = ManualClock::new();
+        clock.advance(MicrosDurationU64::micros(83_000_042));
+        let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+        dive_computer.perform(Action::IncreaseRate);
+        let mut faults = FaultLog::new();
+        faults.record(Instant::from_ticks(12_000_000), FaultCode::SensorFault);
+        let mut marks = RingBuffer::new();
+        marks.push(Mark {
+            number: 1,
+            edt: SecsDurationU64::secs(300),
+            depth: 18_240,
+        });
+        let device = Device {
+            settings,
+            lifetime: &lifetime,
+            replay: &dive_computer.replay(),
+            experiment: &mut Experiment::new(),
+            clock_sync: &mut ClockSync::new(),
+            button_macro,
+            macro_store,
+            wall_clock,
+            rtc,
+            wear: &Storage::mount(RamFlash::new(None), 0, 2).unwrap().wear(),
+            faults: &faults,
+            marks: &marks,
+            audit_trail: dive_computer.audit_trail(),
+        };
+        execute(line, &clock, device, &mut RamFlash::new(Some(&clock)), &mut out);
+        out.as_str().to_string()
+    }
+
+    #[test]
+    fn test_line_reader() {
+        let mut reader = LineReader::new();
+        let mut lines = Vec::new();
+        for &byte in b"faults\r\n\nmarks\n" {
+            if let Some(line) = reader.push(byte) {
+                lines.push(line.map(str::to_string));
+            }
+        }
+        assert_eq!(lines, [Ok("faults\r".to_string()), Ok(String::new()), Ok("marks".to_string())]);
+
+        // A line that doesn't fit is refused as a whole, the next one is read again
+        for _ in 0..MAX_LINE_LEN + 1 {
+            assert_eq!(reader.push(b'A'), None);
+        }
+        assert_eq!(reader.push(b'\n'), Some(Err(ConsoleError::BadLine)));
+        reader.push(0xFF);
+        assert_eq!(reader.push(b'\n'), Some(Err(ConsoleError::BadLine)));
+        let settings = run("settings export", &mut Settings::new());
+        let line = format!("settings import {}\r\n", settings.trim_end());
+        let ended: Vec<_> = line.bytes().filter_map(|byte| reader.push(byte).map(|line| line.map(str::to_string))).collect();
+        assert_eq!(ended, [Ok(line.trim_end_matches('\n').to_string())]);
+    }
+
+    #[test]
+    fn test_export_import() {
+        // The instructor changes the gradient factors, the reserve and a binding
+        let mut instructor = Settings::new();
+        instructor.gradient_factors.step_low();
+        instructor.reserve.step_critical();
+        instructor.bindings.set(Page::Main, Button::X, Press::Hold, Action::Mark);
+        instructor.calibration.offset = -20;
+
+        let line = run("settings export", &mut instructor);
+        assert!(line.len() <= MAX_EXPORT_LEN + 1);
+
+        let mut student = Settings::new();
+        student.calibration.offset = 7;
+        assert_eq!(run(&format!("settings import {}", line), &mut student), "OK\n");
+        assert_eq!(student.gradient_factors, instructor.gradient_factors);
+        assert_eq!(student.reserve, instructor.reserve);
+        assert_eq!(student.bindings, instructor.bindings);
+        assert_eq!(student.calibration.offset, 7);
+
+        // A single changed character fails the CRC
+        let mut corrupt = line.trim_end().to_string().into_bytes();
+        corrupt[10] = if corrupt[10] == b'A' { b'B' } else { b'A' };
+        assert_eq!(import(core::str::from_utf8(&corrupt).unwrap()).err(), Some(ConsoleError::Corrupt));
+        assert_eq!(import("not base64!").err(), Some(ConsoleError::Encoding));
+        assert_eq!(run("settings import AQAA", &mut student), "ERROR: WRONG VERSION\n");
+        assert_eq!(run("settings dump", &mut student), "ERROR: UNKNOWN COMMAND\n");
+        assert_eq!(run("timestamp", &mut student), "83.000042\n");
+        assert_eq!(run("clock sync 83000000", &mut student), "CLOCK: REFERENCE\n");
+        assert_eq!(run("clock sync soon", &mut student), "ERROR: UNKNOWN COMMAND\n");
+        let replay = run("replay", &mut student);
+        assert!(replay.len() == 11 && replay.ends_with(" 1\n"), "{}", replay);
+        assert_eq!(
+            run("flash test 40", &mut student),
+            "CYCLES: 40 ERASES: 3\nERRORS: 0\nWRITE: AVG 830US MAX 1200US\nSECTOR ERASES: 1-2\nWEAR: 95 1-2\n"
+        );
+        assert_eq!(run("flash wear", &mut student), "WEAR: 00 0-0\nERASES: 0 0\n");
+        assert_eq!(run("faults", &mut student), "FAULTS: 1\n    12S E2 SENSOR FAULT\n");
+        assert_eq!(run("marks", &mut student), "<waypoint><divetime>300</divetime><depth>18.2</depth></waypoint>\n");
+        assert_eq!(run("audit", &mut student), "v  0:00 RATE UP    0.0M\n");
+        assert_eq!(run("flash test lots", &mut student), "ERROR: UNKNOWN COMMAND\n");
+        assert_eq!(run("experiment load 500 10 low", &mut student), "LOAD: 500US/10MS LOW\nUI: SETTINGS\n");
+        assert_eq!(run("experiment load 500", &mut student), "ERROR: UNKNOWN COMMAND\n");
+
+        // The only test that changes the global log level
+        assert_eq!(run("log level error", &mut student), "LOG LEVEL: ERROR\n");
+        assert!(log_level::enabled(LogLevel::Error) && !log_level::enabled(LogLevel::Info));
+        assert_eq!(run("log level loud", &mut student), "ERROR: UNKNOWN LEVEL\n");
+        assert_eq!(run("log level debug", &mut student), "LOG LEVEL: DEBUG\n");
+
+        // Statistics are exported but can't be imported as settings
+        let stats = run("stats export", &mut student);
+        assert_eq!(import(stats.trim_end()).err(), Some(ConsoleError::Version));
+
+        let mut invalid = Settings::new();
+        invalid.gradient_factors = GradientFactors { low: 0, high: 100 };
+        let line = run("settings export", &mut invalid);
+        assert_eq!(import(line.trim_end()).err(), Some(ConsoleError::OutOfRange));
+    }
+
+    #[test]
+    fn test_largest_export() {
+        let len = |settings: &Settings| postcard::to_slice(settings, &mut [0; 512]).map_or(usize::MAX, |bytes| bytes.len());
+        let mut settings = largest(len);
+
+        let line = run("settings export", &mut settings);
+        assert!(line.ends_with('\n'), "the largest settings don't export");
+        assert!(line.len() <= MAX_EXPORT_LEN + 1);
+
+        // They fit a slot of the storage as well
+        let mut storage = Storage::mount(RamFlash::new(None), 0, 2).unwrap();
+        assert!(storage.store(&settings).is_ok());
+    }
+
+    #[test]
+    fn test_macro_upload() {
+        let mut settings = Settings::new();
+        let mut recorder = MacroRecorder::new();
+        assert_eq!(run_macro("macro record", &mut settings, &mut recorder), "MACRO: RECORDING 0/20\n");
+        // The longest recording still fits a line
+        for press in 1..=MAX_PRESSES as u64 {
+            recorder.pressed(Instant::from_ticks(0) + MicrosDurationU64::secs(100 * press), Button::Y, Some(Button::B), Press::Hold);
+        }
+        let line = run_macro("macro export", &mut settings, &mut recorder);
+        assert!(line.len() <= MAX_EXPORT_LEN + 1);
+
+        // Uploaded to another device
+        let mut other = MacroRecorder::new();
+        let reply = run_macro(&format!("macro import {}", line), &mut settings, &mut other);
+        assert_eq!(reply, "MACRO: 20 PRESSES\n");
+        assert_eq!(other.recording(), recorder.recording());
+        assert_eq!(run_macro("macro play", &mut settings, &mut other), "MACRO: PLAYING 0/20\n");
+        assert_eq!(run_macro("macro stop", &mut settings, &mut other), "MACRO: 20 PRESSES\n");
+
+        // Kept in flash for the next boot
+        let mut store = Storage::mount(RamFlash::new(None), 0, 2).unwrap();
+        let mut run_stored = |line: &str, button_macro: &mut MacroRecorder| {
+            run_device(line, &mut Settings::new(), button_macro, Some(&mut store), &mut WallClock::new(), &mut FakeRtc(None))
+        };
+        let mut rebooted = MacroRecorder::new();
+        assert_eq!(run_stored("macro load", &mut rebooted), "ERROR: NOT SAVED\n");
+        assert_eq!(run_stored("macro save", &mut other), "MACRO: 20 PRESSES\n");
+        assert_eq!(run_stored("macro load", &mut rebooted), "MACRO: 20 PRESSES\n");
+        assert_eq!(rebooted.recording(), recorder.recording());
+        assert_eq!(run_macro("macro save", &mut settings, &mut other), "ERROR: FLASH FAILED\n");
+
+        // Settings aren't a macro
+        let settings_line = run("settings export", &mut settings);
+        let reply = run_macro(&format!("macro import {}", settings_line), &mut settings, &mut other);
+        assert_eq!(reply, "ERROR: WRONG VERSION\n");
+        assert_eq!(run("macro replay", &mut settings), "ERROR: UNKNOWN COMMAND\n");
+    }
+
+    #[test]
+    fn test_time_sync() {
+        let mut settings = Settings::new();
+        let mut wall_clock = WallClock::new();
+        let mut rtc = FakeRtc(None);
+        let mut run = |line: &str| run_time(line, &mut settings, &mut MacroRecorder::new(), &mut wall_clock, &mut rtc);
+        assert_eq!(run("time get"), "ERROR: TIME NOT SET\n");
+        assert_eq!(run("time set 946684799"), "ERROR: OUT OF RANGE\n");
+        assert_eq!(run("time set yesterday"), "ERROR: UNKNOWN COMMAND\n");
+
+        // Every console line runs 83 s after boot
+        assert_eq!(run("time set 1760659200"), "TIME: 2025-10-17 00:00:00\n");
+        assert_eq!(run("time get"), "1760659200\n");
+        // Timestamps move onto the Unix time
+        assert_eq!(run("timestamp"), "1760659200.000042\n");
+        assert_eq!(rtc.0.map(|time| time.weekday), Some(5));
+        assert_eq!(wall_clock.offset(), 1_760_659_200 - 83);
+    }
+}
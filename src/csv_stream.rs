@@ -0,0 +1,89 @@
+//! Dive samples as CSV over RTT
+//!
+//! With the `csv-stream` feature the rtic firmware prints a `CsvLine` after every logic tick, so
+//! a `probe-run` session can be piped straight into a plotting script without the USB stack:
+//!
+//! ```text
+//! probe-run --chip RP2040 target/thumbv6m-none-eabi/debug/rtic | grep -o 'csv,.*' > dive.csv
+//! ```
+//!
+//! The lines share the defmt RTT up channel with the logs, the `console` channel only carries the
+//! replies of the console. They are printed with `defmt::println`, which the log level doesn't
+//! filter, and contain `PREFIX` so the script can pick them out. `probe-run` puts the defmt
+//! timestamp in front of every line, so they don't start with it. `HEADER` is printed once at boot.
+
+use core::fmt;
+
+use crate::{replay::Snapshot, text_buffer::TextBuffer};
+
+/// First field of every line
+pub const PREFIX: &str = "csv";
+
+/// Names of the fields, printed once at boot
+pub const HEADER: &str = "csv,tick,depth_mm,rate_m_min,air_cl,alarm";
+
+/// Longest line: the prefix, four numbers and the longest alarm name
+pub const MAX_LINE: usize = 64;
+
+/// State after a logic tick
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CsvLine {
+    /// Logic ticks since boot
+    pub tick: u32,
+    pub state: Snapshot,
+}
+
+impl CsvLine {
+    /// The line as text for `defmt::println`
+    pub fn text(&self) -> TextBuffer<MAX_LINE> {
+        let mut buf = TextBuffer::new();
+        buf.clear_and_write(format_args!("{}", self));
+        buf
+    }
+}
+
+impl fmt::Display for CsvLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Snapshot { depth, rate, air, alarm, .. } = self.state;
+        write!(f, "{},{},{},{},{},{}", PREFIX, self.tick, depth, rate, air, alarm.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use fugit::MicrosDurationU64;
+
+    use super::*;
+    use crate::Alarm;
+
+    #[test]
+    fn test_line_matches_header() {
+        let line = CsvLine {
+            tick: 1234,
+            state: Snapshot {
+                depth: 18_250,
+                rate: -9,
+                air: 182_400,
+                edt: MicrosDurationU64::secs(600),
+                alarm: Alarm::AirReserve,
+            },
+        };
+        let text = line.text();
+        assert!(!text.truncated());
+        assert_eq!(text.as_str(), "csv,1234,18250,-9,182400,RESERV");
+        assert_eq!(text.as_str().split(',').count(), HEADER.split(',').count());
+
+        // Widest values still fit
+        let line = CsvLine {
+            tick: u32::MAX,
+            state: Snapshot {
+                depth: u32::MAX,
+                rate: i32::MIN,
+                air: u32::MAX,
+                ..line.state
+            },
+        };
+        assert!(!line.text().truncated());
+    }
+}
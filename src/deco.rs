@@ -0,0 +1,301 @@
+//! Decompression models
+//!
+//! A model follows the inert gas taken up by the body during the dive and tells how shallow the
+//! diver may go (the ceiling), how long they may stay (the no-decompression limit) and which
+//! stops they have to make on the way up.
+//!
+//! The model used by `DiveComputer` is chosen at build time: no model by default, the
+//! single-compartment teaching model with the `haldane` feature and Bühlmann ZHL-16 with the
+//! `zhl16` feature, its coefficient set is picked at runtime. Both models are left out of builds
+//! without the `deco` feature.
+
+#[cfg(feature = "deco")]
+pub mod haldane;
+#[cfg(feature = "deco")]
+pub mod zhl16;
+
+use fugit::{MicrosDurationU32, SecsDurationU32};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "deco")]
+use crate::ascent::AscentLimit;
+use crate::ring_buffer::RingBuffer;
+
+/// Longest no-decompression limit that is reported
+pub const MAX_NDL: SecsDurationU32 = SecsDurationU32::minutes(99);
+
+/// Most stops that are planned
+pub const MAX_STOPS: usize = 8;
+
+/// Distance between stops in millimeters
+pub const STOP_INTERVAL: u32 = 3_000;
+
+/// Longest time spent at a single planned stop
+#[cfg(feature = "deco")]
+const MAX_STOP_TIME: SecsDurationU32 = SecsDurationU32::minutes(99);
+
+/// Model used by `DiveComputer` when no other model is given
+#[cfg(feature = "zhl16")]
+pub type DefaultModel = zhl16::Zhl16;
+/// Model used by `DiveComputer` when no other model is given
+#[cfg(all(feature = "haldane", not(feature = "zhl16")))]
+pub type DefaultModel = haldane::Haldane;
+/// Model used by `DiveComputer` when no other model is given
+#[cfg(not(any(feature = "haldane", feature = "zhl16")))]
+pub type DefaultModel = NoDeco;
+
+/// Leanest back gas that can be set, in percent oxygen
+const MIN_O2: u8 = 10;
+
+/// Richest back gas that can be set, in percent oxygen
+const MAX_O2: u8 = 40;
+
+/// Most helium that can be set, in percent
+const MAX_HE: u8 = 70;
+
+/// Breathing gas, the rest is nitrogen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gas {
+    /// Oxygen in percent
+    pub o2: u8,
+    /// Helium in percent
+    pub he: u8,
+}
+
+impl Gas {
+    pub const AIR: Gas = Gas { o2: 21, he: 0 };
+
+    /// Nitrogen in percent
+    pub fn n2(&self) -> u8 {
+        100u8.saturating_sub(self.o2).saturating_sub(self.he)
+    }
+
+    /// Whether the gas is within what the settings page can set
+    pub fn is_valid(&self) -> bool {
+        (MIN_O2..=MAX_O2).contains(&self.o2) && self.he <= MAX_HE && self.o2 + self.he <= 100
+    }
+
+    /// Raise oxygen by 1, wrapping to `MIN_O2` after `MAX_O2`, helium follows so they fit
+    pub fn step_o2(&mut self) {
+        self.o2 = if self.o2 >= MAX_O2 { MIN_O2 } else { self.o2 + 1 };
+        self.he = self.he.min(100 - self.o2);
+    }
+
+    /// Raise helium by 5, wrapping to 0 after `MAX_HE` or when there is no room for it
+    pub fn step_he(&mut self) {
+        self.he = if self.he >= MAX_HE || self.o2 + self.he + 5 > 100 { 0 } else { self.he + 5 };
+    }
+}
+
+/// Gradient factors in percent of the M-value
+///
+/// The allowed supersaturation goes from `low` at the first stop to `high` at the surface, lower
+/// values are more conservative.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GradientFactors {
+    pub low: u8,
+    pub high: u8,
+}
+
+impl GradientFactors {
+    /// Plain Bühlmann
+    pub const fn new() -> Self {
+        GradientFactors { low: 100, high: 100 }
+    }
+
+    /// Whether the factors are within what the settings page can set
+    pub fn is_valid(&self) -> bool {
+        10 <= self.low && self.low <= self.high && self.high <= 100
+    }
+
+    /// Raise `low` by 5, wrapping to 10 after 100, `high` follows so it is never below `low`
+    pub fn step_low(&mut self) {
+        self.low = if self.low >= 100 { 10 } else { self.low + 5 };
+        self.high = self.high.max(self.low);
+    }
+
+    /// Raise `high` by 5, wrapping to 10 after 100, `low` follows so it is never above `high`
+    pub fn step_high(&mut self) {
+        self.high = if self.high >= 100 { 10 } else { self.high + 5 };
+        self.low = self.low.min(self.high);
+    }
+}
+
+impl Default for GradientFactors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Coefficient set of the Bühlmann ZHL-16 model
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Zhl16Variant {
+    /// As first published
+    A,
+    /// For dive tables
+    B,
+    /// For dive computers
+    #[default]
+    C,
+}
+
+impl Zhl16Variant {
+    pub fn next(self) -> Self {
+        match self {
+            Zhl16Variant::A => Zhl16Variant::B,
+            Zhl16Variant::B => Zhl16Variant::C,
+            Zhl16Variant::C => Zhl16Variant::A,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Zhl16Variant::A => "ZHL-16A",
+            Zhl16Variant::B => "ZHL-16B",
+            Zhl16Variant::C => "ZHL-16C",
+        }
+    }
+}
+
+/// A decompression stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stop {
+    /// Depth in millimeters
+    pub depth: u32,
+    pub duration: SecsDurationU32,
+}
+
+/// Planned stops, deepest first
+pub type Stops = RingBuffer<Stop, MAX_STOPS>;
+
+pub trait DecoModel {
+    /// Whether the model tracks anything, pages leave out its values otherwise
+    const ACTIVE: bool = true;
+
+    /// Breathe `gas` at `depth` in millimeters for `duration`
+    fn tick(&mut self, depth: u32, duration: MicrosDurationU32, gas: Gas);
+
+    /// Shallowest depth in millimeters the diver may ascend to
+    fn ceiling(&self) -> u32;
+
+    /// Time left at the current depth before a stop is needed, zero when one is needed already
+    fn ndl(&self) -> SecsDurationU32;
+
+    /// Whether staying at the current depth for `margin` needs a stop, can be cheaper than `ndl`
+    fn ndl_within(&self, margin: SecsDurationU32) -> bool {
+        self.ndl() < margin
+    }
+
+    /// Stops needed to reach the surface from the current depth
+    fn stops(&self) -> Stops;
+
+    /// Inert gas loading in percent of what can be taken to the surface
+    fn loading(&self) -> u32;
+
+    /// Use `gradient_factors` from now on, models without M-values ignore them
+    fn set_gradient_factors(&mut self, _gradient_factors: GradientFactors) {}
+
+    /// Use the coefficients of `variant` from now on, other models than ZHL-16 ignore it
+    fn set_variant(&mut self, _variant: Zhl16Variant) {}
+
+    /// Travel between the stops at `limit` from now on, models without stops ignore it
+    fn set_ascent_limit(&mut self, _limit: AscentLimit) {}
+
+    /// Coefficients in use, for the dive log, `None` for other models than ZHL-16
+    fn variant(&self) -> Option<Zhl16Variant> {
+        None
+    }
+}
+
+/// No decompression model, the diver may always ascend
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoDeco;
+
+impl DecoModel for NoDeco {
+    const ACTIVE: bool = false;
+
+    fn tick(&mut self, _depth: u32, _duration: MicrosDurationU32, _gas: Gas) {}
+
+    fn ceiling(&self) -> u32 {
+        0
+    }
+
+    fn ndl(&self) -> SecsDurationU32 {
+        MAX_NDL
+    }
+
+    fn stops(&self) -> Stops {
+        Stops::new()
+    }
+
+    fn loading(&self) -> u32 {
+        0
+    }
+}
+
+/// No-decompression limit of `model` staying at `depth` with `gas`, by simulating a minute at a time
+#[cfg(feature = "deco")]
+pub(crate) fn simulate_ndl<M: DecoModel + Clone>(model: &M, depth: u32, gas: Gas) -> SecsDurationU32 {
+    let mut model = model.clone();
+    let mut ndl = SecsDurationU32::minutes(0);
+
+    if model.ceiling() > 0 {
+        return ndl;
+    }
+
+    while ndl < MAX_NDL {
+        model.tick(depth, SecsDurationU32::minutes(1).convert(), gas);
+        if model.ceiling() > 0 {
+            break;
+        }
+        ndl += SecsDurationU32::minutes(1);
+    }
+
+    ndl
+}
+
+/// Whether `model` has a ceiling after breathing `gas` at `depth` for `duration`
+#[cfg(feature = "deco")]
+pub(crate) fn simulate_ceiling_after<M: DecoModel + Clone>(model: &M, depth: u32, gas: Gas, duration: SecsDurationU32) -> bool {
+    let mut model = model.clone();
+    model.tick(depth, duration.convert(), gas);
+    model.ceiling() > 0
+}
+
+/// Stops for `model` ascending from `depth` with `gas` at `limit`, by simulating the ascent
+///
+/// Stops are a multiple of `STOP_INTERVAL` deep and take whole minutes, the diver moves on to the
+/// next stop once the ceiling allows it.
+#[cfg(feature = "deco")]
+pub(crate) fn simulate_stops<M: DecoModel + Clone>(model: &M, depth: u32, gas: Gas, limit: AscentLimit) -> Stops {
+    let mut model = model.clone();
+    let mut stops = Stops::new();
+    let mut depth = depth;
+
+    while stops.len() < MAX_STOPS {
+        let ceiling = model.ceiling();
+        if ceiling == 0 {
+            break;
+        }
+
+        // Travel to the next stop at the ascent limit, breathing at the average depth
+        let stop_depth = ceiling.div_ceil(STOP_INTERVAL) * STOP_INTERVAL;
+        if depth > stop_depth {
+            let travel_ms = (depth - stop_depth) * 60 / limit.rate();
+            model.tick((depth + stop_depth) / 2, MicrosDurationU32::millis(travel_ms), gas);
+            depth = stop_depth;
+        }
+
+        let mut duration = SecsDurationU32::minutes(0);
+        while model.ceiling() > stop_depth.saturating_sub(STOP_INTERVAL) && duration < MAX_STOP_TIME {
+            model.tick(stop_depth, SecsDurationU32::minutes(1).convert(), gas);
+            duration += SecsDurationU32::minutes(1);
+        }
+
+        if duration.to_secs() > 0 {
+            stops.push(Stop { depth: stop_depth, duration });
+        }
+    }
+
+    stops
+}
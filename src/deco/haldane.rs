@@ -0,0 +1,125 @@
+//! Single-compartment Haldane model
+//!
+//! A stepping stone to the full multi-compartment model: a single tissue with a half-time of
+//! 20 minutes, which may hold nitrogen up to 1.58 times the ambient pressure. That is Haldane's
+//! 2:1 ratio applied to the nitrogen in air only.
+//!
+//! Pressures are kept in microbar so the math works without floats.
+
+use fugit::{MicrosDurationU32, SecsDurationU32};
+
+use super::{simulate_ceiling_after, simulate_ndl, simulate_stops, DecoModel, Gas, Stops};
+use crate::{ascent::AscentLimit, SIMULATION_STEP};
+
+/// Pressure at the surface in microbar
+const SURFACE_PRESSURE: i64 = 1_000_000;
+/// Nitrogen the tissue may hold compared to the ambient pressure, in percent
+const MAX_RATIO_PERCENT: i64 = 158;
+/// Fraction of the difference with the inspired pressure taken up per `SIMULATION_STEP`, in
+/// 1/2^32: 1 - 2^(-0.1 s / 1200 s)
+const STEP_FACTOR: i64 = 248_080;
+/// The same per minute: 1 - 2^(-60 s / 1200 s)
+const MINUTE_FACTOR: i64 = 146_302_353;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Haldane {
+    /// Nitrogen pressure in the tissue in microbar
+    tissue: i64,
+    /// Time not yet processed, in microseconds
+    pending_us: u32,
+    /// Depth in millimeters at the last tick
+    depth: u32,
+    /// Gas breathed at the last tick
+    gas: Gas,
+    /// Ascent rate between the stops
+    ascent_limit: AscentLimit,
+}
+
+impl Haldane {
+    /// A diver who has been at the surface for a long time
+    pub const fn new() -> Self {
+        Haldane {
+            tissue: SURFACE_PRESSURE * (100 - Gas::AIR.o2 as i64) / 100,
+            pending_us: 0,
+            depth: 0,
+            gas: Gas::AIR,
+            ascent_limit: AscentLimit::new(),
+        }
+    }
+}
+
+impl Default for Haldane {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DecoModel for Haldane {
+    fn tick(&mut self, depth: u32, duration: MicrosDurationU32, gas: Gas) {
+        // 10 m of water is 1 bar, so every millimeter adds 100 microbar
+        let inspired = (SURFACE_PRESSURE + depth as i64 * 100) * gas.n2() as i64 / 100;
+        self.depth = depth;
+        self.gas = gas;
+
+        self.pending_us += duration.to_micros();
+
+        let minute_us = SecsDurationU32::minutes(1).to_micros();
+        while self.pending_us >= minute_us {
+            self.pending_us -= minute_us;
+            self.tissue += ((inspired - self.tissue) * MINUTE_FACTOR + (1 << 31)) >> 32;
+        }
+
+        while self.pending_us >= SIMULATION_STEP.to_micros() {
+            self.pending_us -= SIMULATION_STEP.to_micros();
+            // Round to nearest, truncating every step adds up over a dive
+            self.tissue += ((inspired - self.tissue) * STEP_FACTOR + (1 << 31)) >> 32;
+        }
+    }
+
+    fn ceiling(&self) -> u32 {
+        let min_ambient = self.tissue * 100 / MAX_RATIO_PERCENT;
+        ((min_ambient - SURFACE_PRESSURE).max(0) / 100) as u32
+    }
+
+    fn ndl(&self) -> SecsDurationU32 {
+        simulate_ndl(self, self.depth, self.gas)
+    }
+
+    fn ndl_within(&self, margin: SecsDurationU32) -> bool {
+        simulate_ceiling_after(self, self.depth, self.gas, margin)
+    }
+
+    fn stops(&self) -> Stops {
+        simulate_stops(self, self.depth, self.gas, self.ascent_limit)
+    }
+
+    fn set_ascent_limit(&mut self, limit: AscentLimit) {
+        self.ascent_limit = limit;
+    }
+
+    fn loading(&self) -> u32 {
+        (self.tissue * 100 * 100 / (SURFACE_PRESSURE * MAX_RATIO_PERCENT)) as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_one_half_time_at_30m() {
+        let mut model = Haldane::new();
+        assert_eq!(model.loading(), 50);
+        assert_eq!(model.ceiling(), 0);
+
+        model.tick(30_000, MicrosDurationU32::minutes(20), Gas::AIR);
+
+        // Halfway from 0.79 bar to 3.16 bar
+        assert!((1_974_000..=1_976_000).contains(&model.tissue), "{}", model.tissue);
+        assert_eq!(model.loading(), 125);
+        assert!((2_490..=2_510).contains(&model.ceiling()), "{}", model.ceiling());
+        assert_eq!(model.ndl().to_secs(), 0);
+        assert_eq!(model.stops().iter().next().map(|stop| stop.depth), Some(3_000));
+    }
+}
@@ -0,0 +1,436 @@
+//! Bühlmann ZHL-16
+//!
+//! Sixteen nitrogen compartments with half-times from 5 to 635 minutes (compartment 1b is used
+//! instead of 1, except by ZHL-16A). Each compartment may hold nitrogen up to a linear function of the ambient
+//! pressure, the M-value: `a + ambient / b`. Helium is not tracked yet, it is counted as
+//! nitrogen.
+//!
+//! Gradient factors only allow a percentage of the supersaturation up to the M-value. The low
+//! factor applies at the deepest first stop of the ascent, the high factor at the surface, and
+//! in between the factor is interpolated on depth.
+//!
+//! The A, B and C variants only differ in their coefficients, C is the default. B and C lower the
+//! `a` coefficients of the middle compartments, which makes them more conservative, and
+//! `Zhl16Variant` picks one at runtime.
+//!
+//! Pressures are kept in microbar so the math works without floats.
+
+use fugit::{MicrosDurationU32, SecsDurationU32};
+
+use super::{simulate_ceiling_after, simulate_ndl, simulate_stops, DecoModel, Gas, GradientFactors, Stops, Zhl16Variant};
+use crate::{ascent::AscentLimit, SIMULATION_STEP};
+
+/// Number of compartments
+pub const COMPARTMENTS: usize = 16;
+
+/// Pressure at the surface in microbar
+const SURFACE_PRESSURE: i64 = 1_000_000;
+/// Water vapor pressure in the lungs in microbar
+const WATER_VAPOR_PRESSURE: i64 = 62_700;
+
+/// Coefficients of one variant of the model
+#[derive(Debug, PartialEq, Eq)]
+pub struct Tables {
+    /// Half-times in tenths of a minute
+    pub half_times: [u32; COMPARTMENTS],
+    /// Nitrogen `a` coefficients in microbar
+    pub a: [i64; COMPARTMENTS],
+    /// Nitrogen `b` coefficients in 1/10000
+    pub b: [i64; COMPARTMENTS],
+    /// Fraction of the difference with the inspired pressure taken up per `SIMULATION_STEP`, in
+    /// 1/2^32: 1 - 2^(-0.1 s / half-time)
+    step_factors: [i64; COMPARTMENTS],
+    /// The same per minute: 1 - 2^(-60 s / half-time)
+    minute_factors: [i64; COMPARTMENTS],
+}
+
+/// ZHL-16C, with the `a` coefficients lowered further for use in dive computers
+pub const ZHL16C: Tables = Tables {
+    half_times: [50, 80, 125, 185, 270, 383, 543, 770, 1090, 1460, 1870, 2390, 3050, 3900, 4980, 6350],
+    a: [
+        1_169_600, 1_000_000, 861_800, 756_200, 620_000, 504_300, 441_000, 400_000, 375_000, 350_000, 329_500, 306_500, 283_500, 261_000, 248_000, 232_700,
+    ],
+    b: [5578, 6514, 7222, 7825, 8126, 8434, 8693, 8910, 9092, 9222, 9319, 9403, 9477, 9544, 9602, 9653],
+    step_factors: [
+        992_234, 620_173, 396_921, 268_194, 183_764, 129_547, 91_375, 64_438, 45_520, 33_984, 26_533, 20_760, 16_268, 12_722, 9_963, 7_814,
+    ],
+    minute_factors: [
+        555_981_097,
+        356_464_920,
+        231_680_643,
+        157_943_970,
+        108_857_625,
+        77_030_476,
+        54_477_419,
+        38_489_416,
+        27_225_677,
+        20_342_389,
+        15_890_555,
+        12_438_208,
+        9_749_719,
+        7_626_668,
+        5_973_843,
+        4_685_701,
+    ],
+};
+
+/// ZHL-16B, with the `a` coefficients of some middle compartments lowered for dive tables
+pub const ZHL16B: Tables = Tables {
+    a: [
+        1_169_600, 1_000_000, 861_800, 756_200, 666_700, 560_000, 494_700, 450_000, 418_700, 379_800, 349_700, 322_300, 285_000, 273_700, 252_300, 232_700,
+    ],
+    ..ZHL16C
+};
+
+/// ZHL-16A as published, with the 4 minute compartment 1
+pub const ZHL16A: Tables = Tables {
+    half_times: [40, 80, 125, 185, 270, 383, 543, 770, 1090, 1460, 1870, 2390, 3050, 3900, 4980, 6350],
+    a: [
+        1_259_900, 1_000_000, 861_800, 756_200, 666_700, 593_300, 528_200, 470_100, 418_700, 379_800, 349_700, 322_300, 297_100, 273_700, 252_300, 232_700,
+    ],
+    b: [5050, 6514, 7222, 7825, 8126, 8434, 8693, 8910, 9092, 9222, 9319, 9403, 9477, 9544, 9602, 9653],
+    step_factors: [
+        1_240_256, 620_173, 396_921, 268_194, 183_764, 129_547, 91_375, 64_438, 45_520, 33_984, 26_533, 20_760, 16_268, 12_722, 9_963, 7_814,
+    ],
+    minute_factors: [
+        683_344_693,
+        356_464_920,
+        231_680_643,
+        157_943_970,
+        108_857_625,
+        77_030_476,
+        54_477_419,
+        38_489_416,
+        27_225_677,
+        20_342_389,
+        15_890_555,
+        12_438_208,
+        9_749_719,
+        7_626_668,
+        5_973_843,
+        4_685_701,
+    ],
+};
+
+/// Coefficients of `variant`
+pub const fn tables(variant: Zhl16Variant) -> &'static Tables {
+    match variant {
+        Zhl16Variant::A => &ZHL16A,
+        Zhl16Variant::B => &ZHL16B,
+        Zhl16Variant::C => &ZHL16C,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Zhl16 {
+    /// Nitrogen pressure per compartment in microbar
+    tissues: [i64; COMPARTMENTS],
+    /// Time not yet processed, in microseconds
+    pending_us: u32,
+    /// Depth in millimeters at the last tick
+    depth: u32,
+    /// Gas breathed at the last tick
+    gas: Gas,
+    gradient_factors: GradientFactors,
+    variant: Zhl16Variant,
+    /// Ascent rate between the stops
+    ascent_limit: AscentLimit,
+    /// Deepest ceiling with the low gradient factor since the diver was last free to surface, in microbar
+    low_anchor: i64,
+}
+
+impl Zhl16 {
+    /// A diver who has been at the surface for a long time
+    pub const fn new() -> Self {
+        Self::with_gradient_factors(GradientFactors::new())
+    }
+
+    pub const fn with_gradient_factors(gradient_factors: GradientFactors) -> Self {
+        Zhl16 {
+            tissues: [inspired(0, Gas::AIR); COMPARTMENTS],
+            pending_us: 0,
+            depth: 0,
+            gas: Gas::AIR,
+            gradient_factors,
+            variant: Zhl16Variant::C,
+            ascent_limit: AscentLimit::new(),
+            low_anchor: SURFACE_PRESSURE,
+        }
+    }
+
+    /// Nitrogen pressure per compartment in microbar
+    pub fn tissues(&self) -> &[i64; COMPARTMENTS] {
+        &self.tissues
+    }
+
+    fn tables(&self) -> &'static Tables {
+        tables(self.variant)
+    }
+
+    /// Move every compartment towards `inspired` by `factors`
+    fn saturate(&mut self, inspired: i64, factors: &[i64; COMPARTMENTS]) {
+        for (tissue, factor) in self.tissues.iter_mut().zip(factors) {
+            // Round to nearest, truncating every step adds up over a dive
+            *tissue += ((inspired - *tissue) * factor + (1 << 31)) >> 32;
+        }
+    }
+
+    /// Lowest ambient pressure in microbar the compartments tolerate with gradient factor `gf` in percent
+    fn tolerated_pressure(&self, gf: u8) -> i64 {
+        let gf = gf as i64;
+        self.tissues
+            .iter()
+            .zip(self.tables().a.iter().zip(self.tables().b))
+            // tissue <= ambient + gf * (a + ambient / b - ambient), solved for the ambient pressure
+            .map(|(tissue, (a, b))| (100 * tissue - a * gf) * b / (gf * 10_000 - gf * b + 100 * b))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Whether the compartments tolerate `ambient` pressure in microbar, with the gradient factor for that pressure
+    fn tolerates(&self, ambient: i64) -> bool {
+        let GradientFactors { low, high } = self.gradient_factors;
+        let (low, high) = (low as i64, high as i64);
+        let gf = high + (low - high) * (ambient - SURFACE_PRESSURE) / (self.low_anchor - SURFACE_PRESSURE).max(1);
+
+        // Same as `tolerated_pressure`, but per compartment and scaled by 100 * b to stay in integers
+        self.tissues
+            .iter()
+            .zip(self.tables().a.iter().zip(self.tables().b))
+            .all(|(tissue, (a, b))| 100 * tissue * b <= ambient * (gf * 10_000 - gf * b + 100 * b) + a * gf * b)
+    }
+
+    /// Track the deepest ceiling with the low gradient factor, it anchors the interpolation
+    fn update_low_anchor(&mut self) {
+        let low = self.tolerated_pressure(self.gradient_factors.low);
+        self.low_anchor = if low <= SURFACE_PRESSURE { SURFACE_PRESSURE } else { self.low_anchor.max(low) };
+    }
+}
+
+impl Default for Zhl16 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Nitrogen pressure in microbar breathed at `depth` in millimeters
+const fn inspired(depth: u32, gas: Gas) -> i64 {
+    // 10 m of water is 1 bar, so every millimeter adds 100 microbar
+    let ambient = SURFACE_PRESSURE + depth as i64 * 100;
+    let n2 = 100 - gas.o2 as i64;
+    (ambient - WATER_VAPOR_PRESSURE) * n2 / 100
+}
+
+impl DecoModel for Zhl16 {
+    fn tick(&mut self, depth: u32, duration: MicrosDurationU32, gas: Gas) {
+        let inspired = inspired(depth, gas);
+        self.depth = depth;
+        self.gas = gas;
+
+        self.pending_us += duration.to_micros();
+
+        let tables = self.tables();
+        let minute_us = SecsDurationU32::minutes(1).to_micros();
+        while self.pending_us >= minute_us {
+            self.pending_us -= minute_us;
+            self.saturate(inspired, &tables.minute_factors);
+        }
+
+        while self.pending_us >= SIMULATION_STEP.to_micros() {
+            self.pending_us -= SIMULATION_STEP.to_micros();
+            self.saturate(inspired, &tables.step_factors);
+        }
+
+        self.update_low_anchor();
+    }
+
+    fn ceiling(&self) -> u32 {
+        if self.tolerated_pressure(self.gradient_factors.high) <= SURFACE_PRESSURE {
+            return 0;
+        }
+
+        // The ceiling lies between the surface and the ceiling with the low factor, find the
+        // shallowest tolerated pressure in 1 mm (100 microbar) steps
+        let mut shallow = SURFACE_PRESSURE;
+        let mut deep = self.tolerated_pressure(self.gradient_factors.low).max(self.low_anchor).max(SURFACE_PRESSURE + 100);
+        while deep - shallow > 100 {
+            let middle = (shallow + deep) / 2;
+            if self.tolerates(middle) {
+                deep = middle;
+            } else {
+                shallow = middle;
+            }
+        }
+
+        ((deep - SURFACE_PRESSURE) / 100) as u32
+    }
+
+    fn ndl(&self) -> SecsDurationU32 {
+        simulate_ndl(self, self.depth, self.gas)
+    }
+
+    fn ndl_within(&self, margin: SecsDurationU32) -> bool {
+        simulate_ceiling_after(self, self.depth, self.gas, margin)
+    }
+
+    fn stops(&self) -> Stops {
+        simulate_stops(self, self.depth, self.gas, self.ascent_limit)
+    }
+
+    fn set_gradient_factors(&mut self, gradient_factors: GradientFactors) {
+        self.gradient_factors = gradient_factors;
+        self.update_low_anchor();
+    }
+
+    fn set_variant(&mut self, variant: Zhl16Variant) {
+        self.variant = variant;
+        self.update_low_anchor();
+    }
+
+    fn variant(&self) -> Option<Zhl16Variant> {
+        Some(self.variant)
+    }
+
+    fn set_ascent_limit(&mut self, limit: AscentLimit) {
+        self.ascent_limit = limit;
+    }
+
+    fn loading(&self) -> u32 {
+        // Loading of the leading compartment compared to its M-value at the surface
+        self.tissues
+            .iter()
+            .zip(self.tables().a.iter().zip(self.tables().b))
+            .map(|(tissue, (a, b))| tissue * 100 / (a + SURFACE_PRESSURE * 10_000 / b))
+            .max()
+            .unwrap_or(0) as u32
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// No-decompression limit after descending straight to `depth` in millimeters
+    fn ndl_minutes(depth: u32) -> u32 {
+        let mut model = Zhl16::new();
+        model.tick(depth, MicrosDurationU32::micros(0), Gas::AIR);
+        model.ndl().to_minutes()
+    }
+
+    #[test]
+    fn test_ndl_on_air() {
+        // Published ZHL-16C limits without gradient factors are around 16 min at 30 m and 8 min at 40 m
+        assert!((14..=18).contains(&ndl_minutes(30_000)), "{}", ndl_minutes(30_000));
+        assert!((7..=10).contains(&ndl_minutes(40_000)), "{}", ndl_minutes(40_000));
+        assert_eq!(ndl_minutes(9_000), 99);
+    }
+
+    #[test]
+    fn test_published_tables() {
+        // Bühlmann's coefficients in bar
+        let b = [
+            0.5578, 0.6514, 0.7222, 0.7825, 0.8126, 0.8434, 0.8693, 0.8910, 0.9092, 0.9222, 0.9319, 0.9403, 0.9477, 0.9544, 0.9602, 0.9653,
+        ];
+        let published = [
+            (
+                &ZHL16A,
+                4.0,
+                [
+                    1.2599, 1.0, 0.8618, 0.7562, 0.6667, 0.5933, 0.5282, 0.4701, 0.4187, 0.3798, 0.3497, 0.3223, 0.2971, 0.2737, 0.2523, 0.2327,
+                ],
+                0.5050,
+            ),
+            (
+                &ZHL16B,
+                5.0,
+                [
+                    1.1696, 1.0, 0.8618, 0.7562, 0.6667, 0.56, 0.4947, 0.45, 0.4187, 0.3798, 0.3497, 0.3223, 0.285, 0.2737, 0.2523, 0.2327,
+                ],
+                0.5578,
+            ),
+            (
+                &ZHL16C,
+                5.0,
+                [
+                    1.1696, 1.0, 0.8618, 0.7562, 0.62, 0.5043, 0.441, 0.4, 0.375, 0.35, 0.3295, 0.3065, 0.2835, 0.261, 0.248, 0.2327,
+                ],
+                0.5578,
+            ),
+        ];
+        let half_times = [8.0, 12.5, 18.5, 27.0, 38.3, 54.3, 77.0, 109.0, 146.0, 187.0, 239.0, 305.0, 390.0, 498.0, 635.0];
+
+        for (tables, first_half_time, a, first_b) in published {
+            let half_times: Vec<f64> = [first_half_time].into_iter().chain(half_times).collect();
+            assert_eq!(
+                tables.half_times.to_vec(),
+                half_times.iter().map(|minutes| (minutes * 10.0).round() as u32).collect::<Vec<_>>()
+            );
+            assert_eq!(tables.a, a.map(|a| (a * 1e6).round() as i64));
+            assert_eq!(tables.b[0], (first_b * 1e4).round() as i64);
+            assert_eq!(tables.b[1..], b[1..].iter().map(|b| (b * 1e4).round() as i64).collect::<Vec<_>>());
+
+            // The factors follow from the half-times
+            let factor = |seconds: f64, minutes: f64| ((1.0 - 2f64.powf(-seconds / (minutes * 60.0))) * 2f64.powi(32)).round() as i64;
+            for (i, minutes) in half_times.into_iter().enumerate() {
+                assert_eq!(tables.step_factors[i], factor(0.1, minutes));
+                assert_eq!(tables.minute_factors[i], factor(60.0, minutes));
+            }
+        }
+    }
+
+    #[test]
+    fn test_variants() {
+        let ndl = |variant| {
+            let mut model = Zhl16::new();
+            model.set_variant(variant);
+            model.tick(18_000, MicrosDurationU32::micros(0), Gas::AIR);
+            model.ndl().to_minutes()
+        };
+        // Lower `a` coefficients are more conservative
+        assert!(ndl(Zhl16Variant::A) > ndl(Zhl16Variant::C));
+        assert!(ndl(Zhl16Variant::B) >= ndl(Zhl16Variant::C));
+        assert_eq!(Zhl16::new().variant(), Some(Zhl16Variant::C));
+    }
+
+    #[test]
+    fn test_ndl_with_gradient_factors() {
+        // Approximate published ZHL-16C air limits in minutes at 18, 30 and 40 m, within 2 minutes
+        let table = [((100, 100), [57, 16, 8]), ((40, 85), [42, 12, 6]), ((30, 70), [28, 8, 5])];
+
+        for ((low, high), ndls) in table {
+            for (depth, ndl) in [18_000, 30_000, 40_000].into_iter().zip(ndls) {
+                let mut model = Zhl16::with_gradient_factors(GradientFactors { low, high });
+                model.tick(depth, MicrosDurationU32::micros(0), Gas::AIR);
+
+                let minutes = model.ndl().to_minutes();
+                assert!(minutes.abs_diff(ndl) <= 2, "GF {}/{} at {} mm: {} min", low, high, depth, minutes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_low_gradient_factor_deepens_first_stop() {
+        let mut plain = Zhl16::new();
+        let mut conservative = Zhl16::with_gradient_factors(GradientFactors { low: 30, high: 70 });
+        plain.tick(40_000, MicrosDurationU32::minutes(30), Gas::AIR);
+        conservative.tick(40_000, MicrosDurationU32::minutes(30), Gas::AIR);
+
+        assert!(conservative.ceiling() > plain.ceiling());
+        let first_stop = |model: &Zhl16| model.stops().iter().next().map(|stop| stop.depth);
+        assert!(first_stop(&conservative) > first_stop(&plain));
+    }
+
+    #[test]
+    fn test_stops_after_long_dive() {
+        let mut model = Zhl16::new();
+        model.tick(40_000, MicrosDurationU32::minutes(25), Gas::AIR);
+        assert!(model.ceiling() > 0);
+        assert_eq!(model.ndl().to_secs(), 0);
+
+        let stops = model.stops();
+        assert!(!stops.is_empty());
+        assert_eq!(stops.last().map(|stop| stop.depth), Some(3_000));
+        assert!(stops.iter().zip(stops.iter().skip(1)).all(|(deeper, shallower)| deeper.depth > shallower.depth));
+    }
+}
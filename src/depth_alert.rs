@@ -0,0 +1,174 @@
+//! Depth alerts set by the diver
+//!
+//! Up to `MAX_DEPTH_ALERTS` depths, e.g. "alert me at 18 m", each for crossing it going down or
+//! going up. They are set on the settings page. Crossing one chirps the buzzer once and shows the
+//! depth in the title line of the main page for `TOAST_TIME`.
+
+use fugit::MicrosDurationU64;
+use serde::{Deserialize, Serialize};
+
+/// Number of depth alerts
+pub const MAX_DEPTH_ALERTS: usize = 3;
+
+/// Time the crossed depth shows on the main page
+pub const TOAST_TIME: MicrosDurationU64 = MicrosDurationU64::secs(5);
+
+/// Distance between the depths that can be set
+const DEPTH_STEP_M: u32 = 3;
+
+/// Deepest alert that can be set
+const MAX_DEPTH_M: u32 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Crossing {
+    Descending,
+    Ascending,
+}
+
+impl Crossing {
+    pub fn next(self) -> Self {
+        match self {
+            Crossing::Descending => Crossing::Ascending,
+            Crossing::Ascending => Crossing::Descending,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Crossing::Descending => "DOWN",
+            Crossing::Ascending => "UP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthAlert {
+    pub depth_m: u32,
+    pub crossing: Crossing,
+}
+
+impl DepthAlert {
+    /// Whether going from `from` to `to`, in millimeters, crosses the alert in its direction
+    fn crossed(&self, from: u32, to: u32) -> bool {
+        let depth = self.depth_m * 1000;
+        match self.crossing {
+            Crossing::Descending => from < depth && to >= depth,
+            Crossing::Ascending => from > depth && to <= depth,
+        }
+    }
+}
+
+/// The alerts, `None` when off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DepthAlerts {
+    pub alerts: [Option<DepthAlert>; MAX_DEPTH_ALERTS],
+}
+
+impl DepthAlerts {
+    /// All off
+    pub const fn new() -> Self {
+        DepthAlerts {
+            alerts: [None; MAX_DEPTH_ALERTS],
+        }
+    }
+
+    /// Whether the settings page can set the alerts
+    pub fn is_valid(&self) -> bool {
+        self.alerts.iter().flatten().all(|alert| 0 < alert.depth_m && alert.depth_m <= MAX_DEPTH_M)
+    }
+
+    /// First alert crossed going from `from` to `to`, in millimeters
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::depth_alert::{Crossing, DepthAlert, DepthAlerts};
+    /// let mut alerts = DepthAlerts::new();
+    /// alerts.alerts[1] = Some(DepthAlert { depth_m: 18, crossing: Crossing::Descending });
+    /// assert_eq!(alerts.crossed(17_990, 18_000), Some(1));
+    /// assert_eq!(alerts.crossed(18_000, 17_990), None);
+    /// ```
+    ///
+    pub fn crossed(&self, from: u32, to: u32) -> Option<usize> {
+        self.alerts.iter().position(|alert| alert.is_some_and(|alert| alert.crossed(from, to)))
+    }
+
+    /// Next depth of alert `index`: off, then every `DEPTH_STEP_M` up to `MAX_DEPTH_M`
+    pub fn step_depth(&mut self, index: usize) {
+        self.alerts[index] = match self.alerts[index] {
+            None => Some(DepthAlert {
+                depth_m: DEPTH_STEP_M,
+                crossing: Crossing::Descending,
+            }),
+            Some(alert) if alert.depth_m >= MAX_DEPTH_M => None,
+            Some(alert) => Some(DepthAlert {
+                depth_m: alert.depth_m + DEPTH_STEP_M,
+                ..alert
+            }),
+        };
+    }
+
+    /// Other direction for alert `index`, when it is on
+    pub fn step_crossing(&mut self, index: usize) {
+        if let Some(alert) = &mut self.alerts[index] {
+            alert.crossing = alert.crossing.next();
+        }
+    }
+}
+
+impl Default for DepthAlerts {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_crossings() {
+        let mut alerts = DepthAlerts::new();
+        for _ in 0..6 {
+            alerts.step_depth(0);
+        }
+        alerts.step_depth(2);
+        alerts.step_depth(2);
+        alerts.step_crossing(2);
+        alerts.step_crossing(1);
+        assert_eq!(
+            alerts.alerts,
+            [
+                Some(DepthAlert {
+                    depth_m: 18,
+                    crossing: Crossing::Descending
+                }),
+                None,
+                Some(DepthAlert {
+                    depth_m: 6,
+                    crossing: Crossing::Ascending
+                }),
+            ]
+        );
+        assert!(alerts.is_valid());
+
+        // Each only in its direction
+        assert_eq!(alerts.crossed(17_000, 19_000), Some(0));
+        assert_eq!(alerts.crossed(19_000, 17_000), None);
+        assert_eq!(alerts.crossed(6_500, 6_000), Some(2));
+        assert_eq!(alerts.crossed(6_000, 5_000), None);
+        // Reaching the depth counts, staying there doesn't
+        assert_eq!(alerts.crossed(18_000, 18_000), None);
+
+        for _ in 0..19 {
+            alerts.step_depth(2);
+        }
+        assert_eq!(alerts.alerts[2], None);
+        alerts.alerts[1] = Some(DepthAlert {
+            depth_m: 61,
+            crossing: Crossing::Ascending,
+        });
+        assert!(!alerts.is_valid());
+    }
+}
@@ -0,0 +1,327 @@
+//! Stack usage monitoring
+//!
+//! At boot the unused part of the stack is painted with a known pattern. Later on we can find
+//! out how deep the stack has ever been by looking for the first word that was overwritten.
+//!
+//! Without `flip-link` the stack grows down from the end of RAM towards `.bss`, and an overflow
+//! silently corrupts our statics. `flip-link` swaps them around: the stack sits at the start of
+//! RAM and grows towards the RAM boundary, so an overflow hits a HardFault instead. This module
+//! handles both layouts, but the high-water mark is only a warning, `flip-link` is the guard.
+//!
+//! Only core 0 runs code in our binaries, so only its stack is monitored. Core 1 is never
+//! started: none of the binaries hands it a task, so it stays asleep in the boot ROM and has
+//! no stack of its own to watch. The report and the diagnostics page say so. A binary that
+//! starts core 1 has to paint and report its stack too.
+
+use core::fmt;
+
+use crate::{buttons::ButtonEvent, clock::Instant};
+
+#[cfg(not(test))]
+use crate::{info, warn};
+#[cfg(test)]
+use log::{info, warn};
+
+/// Pattern written to the unused stack
+const STACK_PAINT: u32 = 0xCCCC_CCCC;
+
+/// Bytes just below the current stack pointer that are left alone while painting
+const PAINT_MARGIN: usize = 256;
+
+/// Start of the `RAM` region in `memory.x`
+const RAM_ORIGIN: usize = 0x2000_0000;
+
+/// Warn when less than this many bytes of stack were never used
+const LOW_STACK_WARNING: usize = 1024;
+
+extern "C" {
+    // Provided by the cortex-m-rt linker script
+    static _stack_start: u32;
+    static __sheap: u32;
+}
+
+/// Memory reserved for the stack of core 0
+#[derive(Debug, Clone, Copy)]
+pub struct StackRegion {
+    /// Lowest address the stack may grow to
+    pub bottom: usize,
+    /// Initial stack pointer
+    pub top: usize,
+}
+
+impl StackRegion {
+    /// Stack region of core 0 as laid out by the linker
+    pub fn core0() -> Self {
+        let (top, sheap) = unsafe { (&_stack_start as *const u32 as usize, &__sheap as *const u32 as usize) };
+
+        // With flip-link the statics live above the stack
+        let bottom = if sheap > top { RAM_ORIGIN } else { sheap };
+
+        StackRegion { bottom, top }
+    }
+
+    pub fn size(&self) -> usize {
+        self.top - self.bottom
+    }
+}
+
+/// Fill the unused part of the stack with the paint pattern
+///
+/// Call this first thing at boot, the deeper the stack already is the less of it gets painted.
+pub fn paint_stack() {
+    let region = StackRegion::core0();
+    let sp = cortex_m::register::msp::read() as usize;
+
+    let mut address = region.bottom;
+    while address + 4 <= sp - PAINT_MARGIN {
+        unsafe { core::ptr::write_volatile(address as *mut u32, STACK_PAINT) };
+        address += 4;
+    }
+}
+
+/// Largest number of bytes the stack has used since it was painted
+pub fn stack_high_water_mark() -> usize {
+    let region = StackRegion::core0();
+
+    let mut address = region.bottom;
+    while address < region.top && unsafe { core::ptr::read_volatile(address as *const u32) } == STACK_PAINT {
+        address += 4;
+    }
+
+    region.top - address
+}
+
+/// Log the stack high-water mark of core 0
+pub fn report_stack() {
+    let size = StackRegion::core0().size();
+    let used = stack_high_water_mark();
+
+    info!("core 0 stack: {} of {} bytes used", used, size);
+    info!("core 1 stack: unused, core 1 is not started");
+
+    if size - used < LOW_STACK_WARNING {
+        warn!("core 0 stack almost full!");
+    }
+}
+
+/// Execution time statistics of a single task
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TaskStats {
+    /// Number of times the task ran
+    pub runs: u32,
+    /// Total execution time in microseconds
+    total_us: u64,
+    /// Longest execution time in microseconds
+    pub max_us: u32,
+    /// Runs that finished after the next run was due
+    pub missed: u32,
+}
+
+impl TaskStats {
+    pub const fn new() -> Self {
+        TaskStats {
+            runs: 0,
+            total_us: 0,
+            max_us: 0,
+            missed: 0,
+        }
+    }
+
+    /// Record a single run of the task
+    pub fn record(&mut self, duration_us: u32) {
+        self.runs = self.runs.wrapping_add(1);
+        self.total_us += duration_us as u64;
+        self.max_us = self.max_us.max(duration_us);
+    }
+
+    /// Record whether a run due at `release_us` that finished at `end_us` met its deadline, the
+    /// release of the next run `period_us` later
+    pub fn record_deadline(&mut self, release_us: u64, end_us: u64, period_us: u64) {
+        if end_us > release_us + period_us {
+            self.missed = self.missed.wrapping_add(1);
+        }
+    }
+
+    /// Average execution time in microseconds
+    pub fn average_us(&self) -> u32 {
+        self.total_us.checked_div(self.runs as u64).unwrap_or(0) as u32
+    }
+}
+
+/// Time from a button press until the screen shows what it did
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputLatency {
+    /// Oldest press that is not on the screen yet
+    pending: Option<Instant>,
+    /// Latency of every press in microseconds
+    pub stats: TaskStats,
+}
+
+impl InputLatency {
+    pub const fn new() -> Self {
+        InputLatency {
+            pending: None,
+            stats: TaskStats::new(),
+        }
+    }
+
+    /// A press was handled, when more follow before a frame the first one counts
+    pub fn pressed(&mut self, event: &ButtonEvent) {
+        self.pending.get_or_insert(event.at);
+    }
+
+    /// The screen showed the state at `now`, a skipped frame because nothing changed counts too
+    pub fn shown(&mut self, now: Instant) {
+        if let Some(pressed) = self.pending.take() {
+            let latency = now.checked_duration_since(pressed).map_or(0, |latency| latency.to_micros());
+            self.stats.record(latency.min(u64::from(u32::MAX)) as u32);
+        }
+    }
+}
+
+/// Slots of the queues of the software tasks
+///
+/// RTIC 1 doesn't tell how full its queues are, so the firmware counts every spawn that got a
+/// slot and every start of a task, which frees its slot again.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct QueueSlots {
+    capacity: u8,
+    /// Tasks spawned that did not start yet
+    queued: u8,
+    /// Fewest free slots since boot
+    min_free: u8,
+}
+
+impl QueueSlots {
+    /// `capacity` free slots
+    pub const fn new(capacity: u8) -> Self {
+        QueueSlots {
+            capacity,
+            queued: 0,
+            min_free: capacity,
+        }
+    }
+
+    /// A spawn took a slot
+    pub fn spawned(&mut self) {
+        self.queued = self.queued.saturating_add(1).min(self.capacity);
+        self.min_free = self.min_free.min(self.free());
+    }
+
+    /// A spawned task started and gave its slot back
+    pub fn started(&mut self) {
+        self.queued = self.queued.saturating_sub(1);
+    }
+
+    /// Slots free now
+    pub fn free(&self) -> u8 {
+        self.capacity - self.queued
+    }
+
+    /// Fewest slots that were free since boot
+    pub fn min_free(&self) -> u8 {
+        self.min_free
+    }
+}
+
+/// Runtime statistics shown on the diagnostics page
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RuntimeStats {
+    pub ui: TaskStats,
+    pub tick: TaskStats,
+    pub buttons: TaskStats,
+    pub input: InputLatency,
+    /// Time spent sleeping in WFI in the current window, in microseconds
+    idle_us: u64,
+    /// Idle percentage of the last complete window
+    idle_percent: u32,
+    /// Screen refreshes that were drawn
+    pub frames_drawn: u32,
+    /// Screen refreshes skipped because nothing changed
+    pub frames_skipped: u32,
+    /// Interrupts handled by the hardware tasks
+    irqs: u32,
+    /// Slots of the task queues, copied in before the page is shown
+    pub queue: QueueSlots,
+}
+
+impl RuntimeStats {
+    pub const fn new() -> Self {
+        RuntimeStats {
+            ui: TaskStats::new(),
+            tick: TaskStats::new(),
+            buttons: TaskStats::new(),
+            input: InputLatency::new(),
+            idle_us: 0,
+            idle_percent: 100,
+            frames_drawn: 0,
+            frames_skipped: 0,
+            irqs: 0,
+            queue: QueueSlots::new(0),
+        }
+    }
+
+    /// Record time spent sleeping
+    pub fn record_idle(&mut self, duration_us: u32) {
+        self.idle_us += duration_us as u64;
+    }
+
+    /// Close the current measuring window of `window_us` microseconds and update the idle percentage
+    pub fn end_window(&mut self, window_us: u64) {
+        if let Some(percent) = (self.idle_us * 100).checked_div(window_us) {
+            self.idle_percent = percent.min(100) as u32;
+        }
+        self.idle_us = 0;
+    }
+
+    /// Percentage of time the CPU was sleeping in the last window
+    pub fn idle_percent(&self) -> u32 {
+        self.idle_percent
+    }
+
+    /// Record a screen refresh, `drawn` unless it was skipped
+    pub fn record_frame(&mut self, drawn: bool) {
+        if drawn {
+            self.frames_drawn = self.frames_drawn.wrapping_add(1);
+        } else {
+            self.frames_skipped = self.frames_skipped.wrapping_add(1);
+        }
+    }
+
+    /// Percentage of screen refreshes that were skipped
+    pub fn skipped_percent(&self) -> u32 {
+        let total = self.frames_drawn as u64 + self.frames_skipped as u64;
+        (self.frames_skipped as u64 * 100).checked_div(total).unwrap_or(0) as u32
+    }
+
+    /// Record an interrupt of a hardware task
+    pub fn record_irq(&mut self) {
+        self.irqs = self.irqs.wrapping_add(1);
+    }
+
+    /// Number of interrupts handled by the hardware tasks, the timer and the dispatchers of the software tasks don't count
+    pub fn irq_count(&self) -> u32 {
+        self.irqs
+    }
+}
+
+impl fmt::Display for RuntimeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        // Core 1 is never started, these are all of core 0
+        writeln!(f, "Diagnostics core 0")?;
+        writeln!(f, "IDLE: {:13}%", self.idle_percent)?;
+        writeln!(f, "TASK   AVG/MAX us")?;
+        writeln!(f, "UI:   {:6}/{:6}", self.ui.average_us(), self.ui.max_us)?;
+        writeln!(f, "TICK: {:6}/{:6}", self.tick.average_us(), self.tick.max_us)?;
+        writeln!(f, "BTN:  {:6}/{:6}", self.buttons.average_us(), self.buttons.max_us)?;
+        // From a press to the screen showing it
+        writeln!(f, "LAT:  {:6}/{:6}", self.input.stats.average_us(), self.input.stats.max_us)?;
+        writeln!(f, "IRQS: {:14}", self.irq_count())?;
+        // Free slots of the task queues now and at the fewest
+        writeln!(f, "SLOTS: {:5}/{:6}", self.queue.free(), self.queue.min_free())?;
+        // Of the screen and the simulation, only a loaded CPU misses them
+        writeln!(f, "MISSED: {:5}/{:6}", self.ui.missed, self.tick.missed)?;
+        writeln!(f, "SKIPPED: {:10}%", self.skipped_percent())
+    }
+}
@@ -0,0 +1,203 @@
+//! Log of a dive: the profile and the actions of the diver
+//!
+//! During a dive the depth is sampled every `SAMPLE_INTERVAL` into a `ProfileWriter`, and every
+//! action the dive computer handles is kept with the dive time it happened at, next to the
+//! samples. When the dive ends `DiveLogger::finish` turns both into a `DiveLog`, which fits a
+//! single record of the storage, so the firmware writes it to the logbook region. The surface
+//! page draws it as a profile graph, with the `audit::icon` of each action at its time.
+//!
+//! The profile holds `PROFILE_BYTES`, enough for hours of a steady dive but only about 20
+//! minutes when the depth changes every sample. After that the samples stop and the log ends
+//! there. Actions after the first `MAX_LOG_EVENTS` are left out, the audit trail still has them.
+
+use fugit::{MicrosDurationU32, MicrosDurationU64, SecsDurationU32};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    keymap::Action,
+    profile_log::{self, ProfileWriter, Samples},
+};
+
+/// Dive time between the samples of the profile
+pub const SAMPLE_INTERVAL: SecsDurationU32 = SecsDurationU32::secs(10);
+
+/// Bytes of a chunk of the profile, serde arrays go up to 32 elements
+const PROFILE_CHUNK: usize = 32;
+
+/// Chunks of the profile, with the actions still within a storage record
+const PROFILE_CHUNKS: usize = 4;
+
+/// Compressed profile bytes kept of a dive
+pub const PROFILE_BYTES: usize = PROFILE_CHUNK * PROFILE_CHUNKS;
+
+/// Actions kept of a dive
+pub const MAX_LOG_EVENTS: usize = 16;
+
+/// An action during the dive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEvent {
+    pub action: Action,
+    /// Dive time in seconds when it happened
+    pub time: u16,
+}
+
+impl LogEvent {
+    const NONE: LogEvent = LogEvent { action: Action::None, time: 0 };
+}
+
+/// Profile and actions of a finished dive, as stored in the logbook
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiveLog {
+    /// Compressed samples, see `profile_log`
+    profile: [[u8; PROFILE_CHUNK]; PROFILE_CHUNKS],
+    profile_len: u8,
+    events: [LogEvent; MAX_LOG_EVENTS],
+    event_count: u8,
+}
+
+impl DiveLog {
+    /// Depths in mm, one every `SAMPLE_INTERVAL`
+    pub fn depths(&self) -> Samples<'_> {
+        profile_log::samples(self.profile())
+    }
+
+    /// Compressed samples
+    pub fn profile(&self) -> &[u8] {
+        let bytes = self.profile.as_flattened();
+        &bytes[..usize::from(self.profile_len).min(bytes.len())]
+    }
+
+    /// Actions in the order they happened
+    pub fn events(&self) -> &[LogEvent] {
+        &self.events[..usize::from(self.event_count).min(MAX_LOG_EVENTS)]
+    }
+}
+
+/// Records the log of the current dive
+#[derive(Debug, Clone)]
+pub struct DiveLogger {
+    profile: ProfileWriter<PROFILE_BYTES>,
+    /// The profile ran out of room, the samples stop
+    full: bool,
+    /// Dive time so far
+    elapsed: MicrosDurationU64,
+    /// Dive time of the next sample
+    next_sample: MicrosDurationU64,
+    events: [LogEvent; MAX_LOG_EVENTS],
+    event_count: usize,
+}
+
+impl DiveLogger {
+    pub const fn new() -> Self {
+        DiveLogger {
+            profile: ProfileWriter::new(),
+            full: false,
+            elapsed: MicrosDurationU64::from_ticks(0),
+            next_sample: MicrosDurationU64::from_ticks(0),
+            events: [LogEvent::NONE; MAX_LOG_EVENTS],
+            event_count: 0,
+        }
+    }
+
+    /// Spend `duration` at `depth` mm, the first sample is at the start of the dive
+    pub fn record(&mut self, depth: u32, duration: MicrosDurationU32) {
+        if self.elapsed >= self.next_sample && !self.full {
+            self.full = self.profile.push(depth).is_err();
+            self.next_sample += SAMPLE_INTERVAL.convert();
+        }
+        self.elapsed += MicrosDurationU64::from(duration);
+    }
+
+    /// The diver did `action` now
+    pub fn record_action(&mut self, action: Action) {
+        if let Some(event) = self.events.get_mut(self.event_count) {
+            let time = self.elapsed.to_secs().min(u64::from(u16::MAX)) as u16;
+            *event = LogEvent { action, time };
+            self.event_count += 1;
+        }
+    }
+
+    /// The log of the dive, ending with a sample at the surface when there is room for it
+    pub fn finish(&mut self) -> DiveLog {
+        if !self.full {
+            self.full = self.profile.push(0).is_err();
+        }
+        // Without room for the last run the samples end before it
+        let bytes = match self.profile.finish() {
+            Ok(bytes) => bytes,
+            Err(_) => self.profile.as_bytes(),
+        };
+        let mut profile = [[0; PROFILE_CHUNK]; PROFILE_CHUNKS];
+        profile.as_flattened_mut()[..bytes.len()].copy_from_slice(bytes);
+        DiveLog {
+            profile,
+            profile_len: bytes.len() as u8,
+            events: self.events,
+            event_count: self.event_count as u8,
+        }
+    }
+}
+
+impl Default for DiveLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::storage::{test::RamFlash, Storage};
+
+    #[test]
+    fn test_samples_and_actions() {
+        let mut logger = DiveLogger::new();
+        logger.record(1_000, MicrosDurationU32::secs(5));
+        logger.record_action(Action::Mark);
+        logger.record(2_000, MicrosDurationU32::secs(5));
+        logger.record(3_000, MicrosDurationU32::secs(5));
+        logger.record_action(Action::DecreaseRate);
+        logger.record(2_000, MicrosDurationU32::secs(15));
+        let log = logger.finish();
+
+        // At 0 s, 10 s, 20 s and the surface
+        assert_eq!(log.depths().collect::<Vec<_>>(), [1_000, 3_000, 2_000, 0]);
+        let events: Vec<_> = log.events().iter().map(|event| (event.action, event.time)).collect();
+        assert_eq!(events, [(Action::Mark, 5), (Action::DecreaseRate, 15)]);
+
+        // 40 minutes at the bottom with an action every minute, only the first actions are kept
+        let mut logger = DiveLogger::new();
+        for second in 0..40 * 60 {
+            if second % 60 == 0 {
+                logger.record_action(Action::IncreaseRate);
+            }
+            logger.record(18_000, MicrosDurationU32::secs(1));
+        }
+        let log = logger.finish();
+        assert_eq!(log.depths().count(), 40 * 6 + 1);
+        assert_eq!(log.profile().len(), 7);
+        assert_eq!(log.events().len(), MAX_LOG_EVENTS);
+        assert_eq!(log.events()[MAX_LOG_EVENTS - 1].time, 15 * 60);
+    }
+
+    #[test]
+    fn test_full_log_is_stored() {
+        // Every sample 10 cm deeper than the last takes a byte, until the profile is full
+        let mut logger = DiveLogger::new();
+        for sample in 1..1_000 {
+            logger.record_action(Action::FillAir);
+            logger.record(sample * 100, SAMPLE_INTERVAL.convert());
+        }
+        let log = logger.finish();
+        assert_eq!(log.profile().len(), PROFILE_BYTES);
+        assert_eq!(log.depths().count(), PROFILE_BYTES);
+        assert_eq!(log.depths().last(), Some(PROFILE_BYTES as u32 * 100));
+        assert_eq!(log.events().len(), MAX_LOG_EVENTS);
+
+        // Even a full log fits a record
+        let mut storage = Storage::mount(RamFlash::new(None), 0, 2).unwrap();
+        storage.store(&log).unwrap();
+        assert_eq!(storage.load::<DiveLog>().unwrap(), Some(log));
+    }
+}
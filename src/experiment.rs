@@ -0,0 +1,202 @@
+//! Scheduling experiments
+//!
+//! Console commands that turn the RTIC binary into a scheduling lesson:
+//!
+//! - `experiment load <us> <ms> <low|high>` keeps the CPU busy for `<us>` every `<ms>`, from a
+//!   task below every other task (`low`) or above the screen and simulation tasks (`high`)
+//! - `experiment ui <ms>` refreshes the screen every `<ms>`, without changing the settings
+//! - `experiment off` ends both, `experiment` alone shows what is running
+//!
+//! RTIC fixes the priorities at compile time, so the load runs in one of two tasks, at priority
+//! 1 or 3, instead of changing the priority of a task. A periodic task misses its deadline when
+//! it finishes after its next run was due, the diagnostics page counts the misses of the screen
+//! and the simulation.
+
+use core::fmt;
+
+use fugit::{MicrosDurationU32, MicrosDurationU64, MillisDurationU32};
+
+/// Longest period of the load
+pub const MAX_LOAD_PERIOD: MillisDurationU32 = MillisDurationU32::secs(1);
+
+/// Fastest and slowest screen refresh of `experiment ui`
+pub const UI_INTERVALS: (MillisDurationU32, MillisDurationU32) = (MillisDurationU32::millis(10), MillisDurationU32::secs(2));
+
+/// Task running the load
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadPriority {
+    /// Below every other task
+    Low,
+    /// Above the screen and simulation tasks
+    High,
+}
+
+impl LoadPriority {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "low" => Some(LoadPriority::Low),
+            "high" => Some(LoadPriority::High),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LoadPriority::Low => "LOW",
+            LoadPriority::High => "HIGH",
+        }
+    }
+}
+
+/// CPU time taken by the load
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Load {
+    /// Busy time of every run
+    pub busy: MicrosDurationU32,
+    pub period: MillisDurationU32,
+    pub priority: LoadPriority,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExperimentCommand {
+    Off,
+    Load(Load),
+    /// Screen refresh interval
+    Ui(MillisDurationU32),
+    Status,
+}
+
+impl ExperimentCommand {
+    /// Parse the words after `experiment`, `None` for unknown commands and out of range values
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::experiment::ExperimentCommand;
+    /// assert_eq!(ExperimentCommand::parse("off".split_whitespace()), Some(ExperimentCommand::Off));
+    /// assert!(ExperimentCommand::parse("load 500 10 high".split_whitespace()).is_some());
+    /// // The load can't be busy longer than its period
+    /// assert!(ExperimentCommand::parse("load 20000 10 high".split_whitespace()).is_none());
+    /// ```
+    ///
+    pub fn parse<'a>(mut words: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let number = |word: Option<&str>| word?.parse::<u32>().ok();
+        let command = match words.next() {
+            None => ExperimentCommand::Status,
+            Some("off") => ExperimentCommand::Off,
+            Some("load") => {
+                let busy = MicrosDurationU32::micros(number(words.next())?);
+                let period = MillisDurationU32::millis(number(words.next())?);
+                let priority = LoadPriority::parse(words.next()?)?;
+                if busy.ticks() == 0 || period.ticks() == 0 || period > MAX_LOAD_PERIOD || busy.ticks() > period.to_micros() {
+                    return None;
+                }
+                ExperimentCommand::Load(Load { busy, period, priority })
+            }
+            Some("ui") => {
+                let interval = MillisDurationU32::millis(number(words.next())?);
+                if interval < UI_INTERVALS.0 || interval > UI_INTERVALS.1 {
+                    return None;
+                }
+                ExperimentCommand::Ui(interval)
+            }
+            Some(_) => return None,
+        };
+        words.next().is_none().then_some(command)
+    }
+}
+
+/// Running experiment, nothing runs at boot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Experiment {
+    load: Option<Load>,
+    ui_interval: Option<MillisDurationU32>,
+}
+
+impl Experiment {
+    pub const fn new() -> Self {
+        Experiment { load: None, ui_interval: None }
+    }
+
+    pub fn apply(&mut self, command: ExperimentCommand) {
+        match command {
+            ExperimentCommand::Off => *self = Experiment::new(),
+            ExperimentCommand::Load(load) => self.load = Some(load),
+            ExperimentCommand::Ui(interval) => self.ui_interval = Some(interval),
+            ExperimentCommand::Status => {}
+        }
+    }
+
+    /// Load the task at `priority` runs, none when the load runs in the other task
+    pub fn load(&self, priority: LoadPriority) -> Option<Load> {
+        self.load.filter(|load| load.priority == priority)
+    }
+
+    /// Screen refresh interval, `configured` unless the experiment changes it
+    pub fn ui_interval(&self, configured: MicrosDurationU64) -> MicrosDurationU64 {
+        self.ui_interval.map_or(configured, |interval| MicrosDurationU64::millis(u64::from(interval.ticks())))
+    }
+}
+
+impl Default for Experiment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Experiment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.load {
+            Some(load) => writeln!(f, "LOAD: {}US/{}MS {}", load.busy.ticks(), load.period.ticks(), load.priority.as_str())?,
+            None => writeln!(f, "LOAD: OFF")?,
+        }
+        match self.ui_interval {
+            Some(interval) => write!(f, "UI: {}MS", interval.ticks()),
+            None => write!(f, "UI: SETTINGS"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::diagnostics::TaskStats;
+
+    fn parse(line: &str) -> Option<ExperimentCommand> {
+        ExperimentCommand::parse(line.split_whitespace())
+    }
+
+    #[test]
+    fn test_experiment() {
+        let mut experiment = Experiment::new();
+        assert_eq!(format!("{}", experiment), "LOAD: OFF\nUI: SETTINGS");
+        assert_eq!(experiment.ui_interval(MicrosDurationU64::millis(100)), MicrosDurationU64::millis(100));
+
+        experiment.apply(parse("load 2500 10 high").unwrap());
+        experiment.apply(parse("ui 20").unwrap());
+        assert_eq!(format!("{}", experiment), "LOAD: 2500US/10MS HIGH\nUI: 20MS");
+        assert_eq!(experiment.load(LoadPriority::Low), None);
+        assert_eq!(experiment.load(LoadPriority::High).map(|load| load.busy), Some(MicrosDurationU32::micros(2500)));
+        assert_eq!(experiment.ui_interval(MicrosDurationU64::millis(100)), MicrosDurationU64::millis(20));
+
+        // Status doesn't change anything, off ends everything
+        experiment.apply(parse("").unwrap());
+        assert!(experiment.load(LoadPriority::High).is_some());
+        experiment.apply(parse("off").unwrap());
+        assert_eq!(experiment, Experiment::new());
+
+        assert_eq!(parse("load 10 0 low"), None);
+        assert_eq!(parse("load 10 2000 low"), None);
+        assert_eq!(parse("load 10 10 medium"), None);
+        assert_eq!(parse("ui 5"), None);
+        assert_eq!(parse("off now"), None);
+
+        // Every 10 ms, the third run finishes after the fourth was due
+        let mut stats = TaskStats::new();
+        stats.record_deadline(0, 4_000, 10_000);
+        stats.record_deadline(10_000, 20_000, 10_000);
+        stats.record_deadline(20_000, 30_001, 10_000);
+        assert_eq!(stats.missed, 1);
+    }
+}
@@ -0,0 +1,268 @@
+//! Factory reset
+//!
+//! Holding Y on the settings page asks for a factory reset. The confirmation page takes over all
+//! buttons: holding A and Y together for `RESET_HOLD_TIME` confirms it, B or X cancels. A and Y
+//! are on opposite sides of the screen, so gear pressing against one side can't confirm it.
+//!
+//! Once confirmed the settings, the logbook and the calibration are erased one `Region` at a time
+//! so the page can show the progress, then the firmware reboots into the defaults. The erase
+//! itself is passed in, on flash it is `storage::erase_sectors` of the region, or `Storage::erase`
+//! of the settings while they are mounted.
+
+use core::fmt;
+
+use fugit::MicrosDurationU64;
+
+#[cfg(not(test))]
+use crate::info;
+#[cfg(test)]
+use log::info;
+
+use crate::clock::Instant;
+
+/// Time the confirmation chord has to be held
+pub const RESET_HOLD_TIME: MicrosDurationU64 = MicrosDurationU64::secs(5);
+
+/// Part of the flash holding one kind of record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub name: &'static str,
+    /// Offset from the start of the flash
+    pub start: u32,
+    /// Number of 4 KiB sectors
+    pub sectors: u32,
+}
+
+/// The settings, a `storage::Storage` ring
+pub const SETTINGS: Region = Region {
+    name: "SETTINGS",
+    start: 0x1F_8000,
+    sectors: 2,
+};
+
+pub const LOGBOOK: Region = Region {
+    name: "LOGBOOK",
+    start: 0x1F_A000,
+    sectors: 4,
+};
+
+pub const CALIBRATION: Region = Region {
+    name: "CALIBRATION",
+    start: 0x1F_E000,
+    sectors: 2,
+};
+
+/// Records a factory reset erases, at the top of the 2 MiB flash of the Pico
+pub const REGIONS: [Region; 3] = [SETTINGS, LOGBOOK, CALIBRATION];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResetState {
+    /// Nothing asked for
+    Closed,
+    /// Waiting for the chord
+    Confirm,
+    /// Number of regions erased so far
+    Erasing(usize),
+    /// Erasing the region at this index failed
+    Failed(usize),
+    /// All regions are erased, the firmware reboots
+    Done,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FactoryReset {
+    state: ResetState,
+    /// When the confirmation chord went down
+    held_since: Option<Instant>,
+}
+
+impl FactoryReset {
+    pub const fn new() -> Self {
+        FactoryReset {
+            state: ResetState::Closed,
+            held_since: None,
+        }
+    }
+
+    pub fn state(&self) -> ResetState {
+        self.state
+    }
+
+    /// The page is shown instead of the current page
+    pub fn is_open(&self) -> bool {
+        self.state != ResetState::Closed
+    }
+
+    /// The page handles the buttons itself
+    pub fn takes_buttons(&self) -> bool {
+        matches!(self.state, ResetState::Confirm | ResetState::Failed(_))
+    }
+
+    /// Show the confirmation page
+    pub fn open(&mut self) {
+        if self.state == ResetState::Closed {
+            self.state = ResetState::Confirm;
+            self.held_since = None;
+        }
+    }
+
+    /// Close the confirmation page or the failure, an erase that started can't be stopped
+    pub fn cancel(&mut self) {
+        if self.takes_buttons() {
+            self.state = ResetState::Closed;
+        }
+    }
+
+    /// The confirmation chord is down at `now`, `pressed` when it just went down
+    ///
+    /// Returns whether this confirmed the reset, from then on `step` erases the regions.
+    pub fn hold(&mut self, now: Instant, pressed: bool) -> bool {
+        if self.state != ResetState::Confirm {
+            return false;
+        }
+        if pressed {
+            self.held_since = Some(now);
+        }
+
+        let held_long_enough = self
+            .held_since
+            .is_some_and(|since| now.checked_duration_since(since).is_some_and(|held| held >= RESET_HOLD_TIME));
+        if held_long_enough {
+            info!("factory reset confirmed");
+            self.state = ResetState::Erasing(0);
+        }
+        held_long_enough
+    }
+
+    /// Erase the next region with `erase`, returns whether there are regions left
+    pub fn step<E>(&mut self, erase: impl FnOnce(&Region) -> Result<(), E>) -> bool {
+        let ResetState::Erasing(done) = self.state else {
+            return false;
+        };
+
+        if erase(&REGIONS[done]).is_err() {
+            info!("factory reset failed");
+            self.state = ResetState::Failed(done);
+            return false;
+        }
+        self.state = if done + 1 < REGIONS.len() {
+            ResetState::Erasing(done + 1)
+        } else {
+            info!("factory reset done");
+            ResetState::Done
+        };
+        self.state != ResetState::Done
+    }
+}
+
+impl Default for FactoryReset {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for FactoryReset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Factory reset")?;
+        writeln!(f)?;
+
+        let done = match self.state {
+            ResetState::Closed => return Ok(()),
+            ResetState::Confirm => {
+                writeln!(f, "ERASES SETTINGS,")?;
+                writeln!(f, "LOGBOOK, CALIBRATION")?;
+                writeln!(f, "AND REBOOTS")?;
+                writeln!(f)?;
+                writeln!(f, "HOLD A+Y {}S: ERASE", RESET_HOLD_TIME.to_secs())?;
+                return write!(f, "B OR X: CANCEL");
+            }
+            ResetState::Failed(index) => {
+                writeln!(f, "ERASE FAILED:")?;
+                writeln!(f, "{:>20}", REGIONS[index].name)?;
+                writeln!(f)?;
+                return write!(f, "B OR X: CLOSE");
+            }
+            ResetState::Erasing(done) => {
+                writeln!(f, "ERASING: {:>11}", REGIONS[done].name)?;
+                done
+            }
+            ResetState::Done => {
+                writeln!(f, "REBOOTING")?;
+                REGIONS.len()
+            }
+        };
+
+        // 20 characters for all regions
+        let filled = done * 20 / REGIONS.len();
+        writeln!(f, "{:#<filled$}{:.<empty$}", "", "", filled = filled, empty = 20 - filled)?;
+        write!(f, "PROGRESS: {:>9}%", done * 100 / REGIONS.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_confirm_and_erase() {
+        let mut reset = FactoryReset::new();
+        let at = |millis: u64| Instant::from_ticks(millis * 1_000);
+
+        // Holding the chord before the page is open does nothing
+        assert!(!reset.hold(at(0), true));
+        reset.open();
+        assert!(reset.takes_buttons());
+        assert!(format!("{}", reset).ends_with("\nHOLD A+Y 5S: ERASE\nB OR X: CANCEL"));
+
+        // Pressing the chord again starts the time over
+        assert!(!reset.hold(at(1_000), true));
+        assert!(!reset.hold(at(5_900), false));
+        assert!(!reset.hold(at(7_000), true));
+        reset.cancel();
+        assert!(!reset.is_open());
+
+        reset.open();
+        assert!(!reset.hold(at(10_000), true));
+        assert!(reset.hold(at(15_000), false));
+        assert_eq!(reset.state(), ResetState::Erasing(0));
+        // An erase can't be cancelled
+        reset.cancel();
+        assert!(!reset.takes_buttons());
+
+        let mut erased = [None; 3];
+        let mut index = 0;
+        while reset.step(|region| {
+            erased[index] = Some(region.name);
+            index += 1;
+            Ok::<(), ()>(())
+        }) {
+            if index == 1 {
+                assert_eq!(
+                    format!("{}", reset),
+                    "Factory reset\n\nERASING:     LOGBOOK\n######..............\nPROGRESS:        33%"
+                );
+            }
+        }
+        assert_eq!(erased, [Some("SETTINGS"), Some("LOGBOOK"), Some("CALIBRATION")]);
+        assert_eq!(reset.state(), ResetState::Done);
+        assert!(format!("{}", reset).ends_with("\nREBOOTING\n####################\nPROGRESS:       100%"));
+        assert!(!reset.step(|_| Ok::<(), ()>(())));
+    }
+
+    #[test]
+    fn test_failed_erase() {
+        let mut reset = FactoryReset::new();
+        reset.open();
+        reset.hold(Instant::from_ticks(0), true);
+        reset.hold(Instant::from_ticks(RESET_HOLD_TIME.to_micros()), false);
+
+        assert!(reset.step(|_| Ok::<(), ()>(())));
+        assert!(!reset.step(|_| Err(())));
+        assert_eq!(reset.state(), ResetState::Failed(1));
+        assert_eq!(format!("{}", reset), "Factory reset\n\nERASE FAILED:\n             LOGBOOK\n\nB OR X: CLOSE");
+        reset.cancel();
+        assert!(!reset.is_open());
+    }
+}
@@ -0,0 +1,287 @@
+//! Simulated equipment failures
+//!
+//! The failures page is left out of the page cycle and the help, the instructor opens it by
+//! holding X on the self test page. It picks a failure and a delay and arms it, when the delay has passed the failure
+//! strikes and the student has to recognize it and respond. A sudden air loss drains
+//! `AIR_LOSS_PERCENT` of the tank at once, a stuck depth sensor keeps reporting the depth it got
+//! stuck at and a dead battery reads empty. The failure lasts until the instructor stops it.
+//!
+//! `FailureInjector` sits between the sensors and simulation and the dive computer: the firmware
+//! passes the depth and battery readings through it and takes the air loss from it, so the dive
+//! computer itself can't tell an injected failure from a real one.
+
+use core::fmt;
+
+use fugit::MicrosDurationU64;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(test))]
+use crate::info;
+use crate::keymap::Action;
+#[cfg(test)]
+use log::info;
+
+/// Delays to choose from, in minutes after arming
+pub const DELAYS_MIN: [u32; 6] = [0, 1, 2, 5, 10, 20];
+
+/// Part of the air left that a sudden air loss drains
+pub const AIR_LOSS_PERCENT: u32 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Failure {
+    /// E.g. a burst hose
+    AirLoss,
+    /// The depth doesn't change anymore
+    StuckSensor,
+    /// The battery reads empty
+    DeadBattery,
+}
+
+impl Failure {
+    pub const ALL: [Failure; 3] = [Failure::AirLoss, Failure::StuckSensor, Failure::DeadBattery];
+
+    pub fn next(self) -> Self {
+        match self {
+            Failure::AirLoss => Failure::StuckSensor,
+            Failure::StuckSensor => Failure::DeadBattery,
+            Failure::DeadBattery => Failure::AirLoss,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Failure::AirLoss => "AIR LOSS",
+            Failure::StuckSensor => "STUCK DEPTH",
+            Failure::DeadBattery => "DEAD BATTERY",
+        }
+    }
+}
+
+/// Failure menu and the failure that struck
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FailureInjector {
+    failure: Failure,
+    /// Index into `DELAYS_MIN`
+    delay: usize,
+    /// Selected item, 0 is the failure and 1 the delay
+    item: usize,
+    /// Time left until the armed failure strikes
+    armed: Option<MicrosDurationU64>,
+    /// The failure struck
+    struck: bool,
+    /// Depth in millimeters the sensor got stuck at
+    stuck_depth: Option<u32>,
+    /// The air loss struck and wasn't taken yet
+    air_loss: bool,
+    /// The failure was stopped and the dive computer didn't hear of it yet
+    cleared: bool,
+}
+
+impl FailureInjector {
+    pub const fn new() -> Self {
+        FailureInjector {
+            failure: Failure::AirLoss,
+            delay: 0,
+            item: 0,
+            armed: None,
+            struck: false,
+            stuck_depth: None,
+            air_loss: false,
+            cleared: false,
+        }
+    }
+
+    /// Handle one of the failures page actions, the menu can't be changed while the failure is armed or struck
+    pub fn perform(&mut self, action: Action) {
+        match action {
+            Action::StartTimer if self.armed.is_some() || self.struck => self.stop(),
+            Action::StartTimer => self.arm(DELAYS_MIN[self.delay]),
+            _ if self.armed.is_some() || self.struck => {}
+            Action::SelectItem => self.item = (self.item + 1) % 2,
+            Action::ChangeItem if self.item == 0 => self.failure = self.failure.next(),
+            Action::ChangeItem => self.delay = (self.delay + 1) % DELAYS_MIN.len(),
+            _ => {}
+        }
+    }
+
+    /// Arm `failure` to strike after `delay_min` minutes, in place of the one armed or struck
+    ///
+    /// This is how the instructor bus injects a failure, the delay doesn't have to be one of the menu.
+    pub fn inject(&mut self, failure: Failure, delay_min: u32) {
+        self.stop();
+        self.failure = failure;
+        if let Some(delay) = DELAYS_MIN.iter().position(|&delay| delay == delay_min) {
+            self.delay = delay;
+        }
+        self.arm(delay_min);
+    }
+
+    /// Stop the failure that is armed or struck
+    pub fn stop(&mut self) {
+        if self.armed.is_none() && !self.struck {
+            return;
+        }
+        info!("{} stopped", self.failure.as_str());
+        self.cleared = self.struck;
+        self.armed = None;
+        self.struck = false;
+        self.stuck_depth = None;
+        self.air_loss = false;
+    }
+
+    fn arm(&mut self, delay_min: u32) {
+        info!("{} armed, strikes in {} min", self.failure.as_str(), delay_min);
+        self.armed = Some(MicrosDurationU64::minutes(delay_min.into()));
+    }
+
+    /// The failure that is armed or struck, with the delay of the menu in minutes
+    pub fn armed(&self) -> Option<(Failure, u32)> {
+        (self.armed.is_some() || self.struck).then_some((self.failure, DELAYS_MIN[self.delay]))
+    }
+
+    /// Advance the armed failure by `duration`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{failure::{Failure, FailureInjector}, keymap::Action};
+    /// use fugit::MicrosDurationU64;
+    /// let mut failures = FailureInjector::new();
+    /// failures.perform(Action::StartTimer);
+    /// failures.tick(MicrosDurationU64::millis(100));
+    /// assert!(failures.active(Failure::AirLoss));
+    /// assert!(failures.take_air_loss());
+    /// assert!(!failures.take_air_loss());
+    /// ```
+    ///
+    pub fn tick(&mut self, duration: MicrosDurationU64) {
+        let Some(left) = self.armed else {
+            return;
+        };
+        match left.checked_sub(duration) {
+            Some(left) if left.ticks() > 0 => self.armed = Some(left),
+            _ => {
+                info!("{} strikes", self.failure.as_str());
+                self.armed = None;
+                self.struck = true;
+                self.air_loss = self.failure == Failure::AirLoss;
+            }
+        }
+    }
+
+    /// Whether `failure` struck
+    pub fn active(&self, failure: Failure) -> bool {
+        self.struck && self.failure == failure
+    }
+
+    /// Depth in millimeters to report instead of `depth` while the sensor is stuck
+    pub fn depth(&mut self, depth: u32) -> Option<u32> {
+        if self.active(Failure::StuckSensor) {
+            Some(*self.stuck_depth.get_or_insert(depth))
+        } else {
+            None
+        }
+    }
+
+    /// Battery reading in percent to show instead of `battery`
+    pub fn battery(&self, battery: Option<u8>) -> Option<u8> {
+        if self.active(Failure::DeadBattery) {
+            Some(0)
+        } else {
+            battery
+        }
+    }
+
+    /// Whether the air loss struck since the last call
+    pub fn take_air_loss(&mut self) -> bool {
+        core::mem::take(&mut self.air_loss)
+    }
+
+    /// Whether the failure was stopped since the last call, the depth is simulated again
+    pub fn take_cleared(&mut self) -> bool {
+        core::mem::take(&mut self.cleared)
+    }
+}
+
+impl Default for FailureInjector {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for FailureInjector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Failures")?;
+        writeln!(f)?;
+
+        let marker = |item: usize| if self.item == item && self.armed.is_none() && !self.struck { '>' } else { ' ' };
+        writeln!(f, "{}TYPE: {:>13}", marker(0), self.failure.as_str())?;
+        writeln!(f, "{}DELAY: {:>9}MIN", marker(1), DELAYS_MIN[self.delay])?;
+        writeln!(f)?;
+
+        if let Some(left) = self.armed {
+            let secs = left.to_secs();
+            writeln!(f, "STRIKES IN: {:>5}:{:02}", secs / 60, secs % 60)?;
+        }
+        if self.struck {
+            writeln!(f, "ACTIVE, X TO STOP")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_stuck_sensor() {
+        let mut failures = FailureInjector::new();
+        failures.perform(Action::ChangeItem);
+        failures.perform(Action::SelectItem);
+        failures.perform(Action::ChangeItem);
+        failures.perform(Action::StartTimer);
+        assert_eq!(
+            format!("{}", failures),
+            "Failures\n\n TYPE:   STUCK DEPTH\n DELAY:         1MIN\n\nSTRIKES IN:     1:00\n"
+        );
+
+        // Locked while armed
+        failures.perform(Action::ChangeItem);
+        failures.tick(MicrosDurationU64::secs(59));
+        assert_eq!(failures.depth(12_000), None);
+        failures.tick(MicrosDurationU64::secs(1));
+        assert!(failures.active(Failure::StuckSensor));
+        assert_eq!(failures.depth(12_000), Some(12_000));
+        assert_eq!(failures.depth(15_000), Some(12_000));
+        assert!(!failures.take_air_loss());
+        assert_eq!(failures.battery(Some(80)), Some(80));
+
+        // Stopping it lets the depth through again
+        failures.perform(Action::StartTimer);
+        assert!(failures.take_cleared());
+        assert!(!failures.take_cleared());
+        assert_eq!(failures.depth(15_000), None);
+        assert_eq!(format!("{}", failures), "Failures\n\n TYPE:   STUCK DEPTH\n>DELAY:         1MIN\n\n");
+    }
+
+    #[test]
+    fn test_dead_battery() {
+        let mut failures = FailureInjector::new();
+        failures.perform(Action::ChangeItem);
+        failures.perform(Action::ChangeItem);
+        failures.perform(Action::StartTimer);
+        failures.tick(MicrosDurationU64::millis(100));
+        assert_eq!(failures.battery(Some(80)), Some(0));
+        assert_eq!(failures.battery(None), Some(0));
+        assert!(format!("{}", failures).ends_with(" TYPE:  DEAD BATTERY\n DELAY:         0MIN\n\nACTIVE, X TO STOP\n"));
+
+        // Locked while it lasts
+        failures.perform(Action::ChangeItem);
+        assert!(failures.active(Failure::DeadBattery));
+        failures.perform(Action::StartTimer);
+        assert_eq!(failures.battery(Some(80)), Some(80));
+    }
+}
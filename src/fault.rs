@@ -0,0 +1,183 @@
+//! Fault codes
+//!
+//! Things going wrong that the firmware carries on after get a `FaultCode` instead of a panic on
+//! an unwrap. The newest fault shows as a small code in the bottom right corner of every page for
+//! `FAULT_SHOW_TIME`, and `faults` on the console lists the last `MAX_FAULTS` of them with the
+//! seconds since boot, so a student can tell what happened without a debug probe.
+//!
+//! A fault repeating the newest one only counts up, so a fault on every frame doesn't push the
+//! others out.
+
+use core::fmt;
+
+use fugit::MicrosDurationU64;
+
+#[cfg(not(test))]
+use crate::info;
+#[cfg(test)]
+use log::info;
+
+use crate::{clock::Instant, ring_buffer::RingBuffer};
+
+/// Faults kept for the console
+pub const MAX_FAULTS: usize = 6;
+
+/// Time the newest fault stays on the screen after it happened
+pub const FAULT_SHOW_TIME: MicrosDurationU64 = MicrosDurationU64::secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultCode {
+    /// A record in the storage failed its check
+    StorageCorrupt,
+    /// The pressure sensor stopped answering, the simulator took over
+    SensorFault,
+    /// A task couldn't be spawned, its queue was full
+    QueueOverflow,
+    /// A page didn't fit the screen buffer and was cut off
+    RenderOverflow,
+}
+
+impl FaultCode {
+    /// Code shown on the screen
+    pub fn code(&self) -> &'static str {
+        match self {
+            FaultCode::StorageCorrupt => "E1",
+            FaultCode::SensorFault => "E2",
+            FaultCode::QueueOverflow => "E3",
+            FaultCode::RenderOverflow => "E4",
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FaultCode::StorageCorrupt => "STORAGE CORRUPT",
+            FaultCode::SensorFault => "SENSOR FAULT",
+            FaultCode::QueueOverflow => "QUEUE OVERFLOW",
+            FaultCode::RenderOverflow => "RENDER OVERFLOW",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FaultRecord {
+    pub code: FaultCode,
+    /// Last time it happened
+    pub at: Instant,
+    /// Times it happened in a row
+    pub repeats: u16,
+}
+
+impl fmt::Display for FaultRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:>6}S {} {}", self.at.duration_since_epoch().to_secs(), self.code.code(), self.code.as_str())?;
+        if self.repeats > 1 {
+            write!(f, " X{}", self.repeats)?;
+        }
+        Ok(())
+    }
+}
+
+/// Recent faults
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FaultLog {
+    faults: RingBuffer<FaultRecord, MAX_FAULTS>,
+    /// Faults since boot, repeats included
+    total: u32,
+}
+
+impl FaultLog {
+    pub const fn new() -> Self {
+        FaultLog {
+            faults: RingBuffer::new(),
+            total: 0,
+        }
+    }
+
+    /// Record that `code` happened at `now`
+    pub fn record(&mut self, now: Instant, code: FaultCode) {
+        self.total = self.total.saturating_add(1);
+        match self.faults.last_mut() {
+            Some(last) if last.code == code => {
+                last.at = now;
+                last.repeats = last.repeats.saturating_add(1);
+            }
+            _ => {
+                info!("fault {}", code.code());
+                self.faults.push(FaultRecord { code, at: now, repeats: 1 });
+            }
+        }
+    }
+
+    /// Code for the screen at `now`, the newest fault while it is recent
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{clock::Instant, fault::{FaultCode, FaultLog}};
+    /// let mut faults = FaultLog::new();
+    /// faults.record(Instant::from_ticks(1_000_000), FaultCode::SensorFault);
+    /// assert_eq!(faults.shown(Instant::from_ticks(2_000_000)), Some(FaultCode::SensorFault));
+    /// assert_eq!(faults.shown(Instant::from_ticks(60_000_000)), None);
+    /// ```
+    ///
+    pub fn shown(&self, now: Instant) -> Option<FaultCode> {
+        self.faults
+            .last()
+            .filter(|fault| now.checked_duration_since(fault.at).is_some_and(|since| since < FAULT_SHOW_TIME))
+            .map(|fault| fault.code)
+    }
+
+    /// Faults from oldest to newest
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &FaultRecord> + '_ {
+        self.faults.iter()
+    }
+
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+}
+
+impl fmt::Display for FaultLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.total == 0 {
+            return write!(f, "FAULTS: NONE");
+        }
+        write!(f, "FAULTS: {}", self.total)?;
+        // Newest first
+        for fault in self.iter().rev() {
+            write!(f, "\n{}", fault)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_repeats_and_newest_first() {
+        let at = |secs: u64| Instant::from_ticks(secs * 1_000_000);
+        let mut faults = FaultLog::new();
+        assert_eq!(faults.to_string(), "FAULTS: NONE");
+        assert_eq!(faults.shown(at(0)), None);
+
+        faults.record(at(12), FaultCode::SensorFault);
+        for secs in 40..52 {
+            faults.record(at(secs), FaultCode::RenderOverflow);
+        }
+        assert_eq!(faults.shown(at(60)), Some(FaultCode::RenderOverflow));
+        assert_eq!(faults.to_string(), "FAULTS: 13\n    51S E4 RENDER OVERFLOW X12\n    12S E2 SENSOR FAULT");
+
+        // Only the last ones are kept, the total still counts all
+        for secs in 100..110 {
+            let code = if secs % 2 == 0 { FaultCode::QueueOverflow } else { FaultCode::StorageCorrupt };
+            faults.record(at(secs), code);
+        }
+        assert_eq!(faults.iter().count(), MAX_FAULTS);
+        assert_eq!(faults.iter().next().map(|fault| fault.at), Some(at(104)));
+        assert_eq!(faults.total(), 23);
+        assert_eq!(faults.shown(at(200)), None);
+    }
+}
@@ -0,0 +1,202 @@
+//! On-board flash of the Pico
+//!
+//! The RP2040 runs its code straight from the flash (XIP), so while a sector is erased or a page
+//! programmed nothing may be read from it: no code, no constants. `Rp2040Flash` looks up the
+//! flash functions of the boot ROM and copies the second stage boot loader to the stack first,
+//! then calls them from a function in RAM with the interrupts off. The boot loader sets up the
+//! fast XIP mode again afterwards, as at boot.
+//!
+//! An erase of a sector takes about 50 ms, a page about 1 ms, in which no task runs. Only core 0
+//! runs code in our binaries, core 1 would have to be parked as well.
+//!
+//! Each `Rp2040Flash` is a window on the flash, offsets are from its start. The program is linked
+//! below the top `RESERVED` bytes, see `memory.x`, they hold the records of
+//! `factory_reset::REGIONS`, the scratch sectors of the console and the saved button macro.
+
+use embedded_storage::nor_flash::{ErrorType, NorFlash, NorFlashErrorKind, ReadNorFlash};
+
+/// Where the flash appears in the address space
+const XIP_BASE: u32 = 0x1000_0000;
+
+/// Size of the flash of the Pico
+pub const FLASH_SIZE: u32 = 2 * 1024 * 1024;
+
+/// Bytes at the top of the flash the program is not linked into
+pub const RESERVED: u32 = 48 * 1024;
+
+/// Smallest erase
+pub const SECTOR_SIZE: u32 = 4096;
+
+/// Smallest program
+pub const PAGE_SIZE: u32 = 256;
+
+/// Erase in 64 KiB blocks where the range allows it, like the SDK
+const BLOCK_SIZE: u32 = 1 << 16;
+const BLOCK_ERASE_CMD: u8 = 0xD8;
+
+/// Address of the pointer to the function table of the boot ROM
+const ROM_FUNC_TABLE: usize = 0x14;
+
+/// Address of the pointer to the lookup function of the boot ROM
+const ROM_TABLE_LOOKUP: usize = 0x18;
+
+/// Words of the second stage boot loader at the start of the flash
+const BOOT2_WORDS: usize = 64;
+
+/// Function of the boot ROM with the two character `tag`, see 2.8.3 of the datasheet
+unsafe fn rom_function(tag: &[u8; 2]) -> usize {
+    let table = core::ptr::read_volatile(ROM_FUNC_TABLE as *const u16) as usize as *const u16;
+    let lookup: unsafe extern "C" fn(*const u16, u32) -> usize = core::mem::transmute(core::ptr::read_volatile(ROM_TABLE_LOOKUP as *const u16) as usize);
+    lookup(table, u32::from(tag[0]) | u32::from(tag[1]) << 8)
+}
+
+/// Flash functions of the boot ROM
+struct Rom {
+    connect_internal_flash: unsafe extern "C" fn(),
+    flash_exit_xip: unsafe extern "C" fn(),
+    flash_range_erase: unsafe extern "C" fn(u32, usize, u32, u8),
+    flash_range_program: unsafe extern "C" fn(u32, *const u8, usize),
+    flash_flush_cache: unsafe extern "C" fn(),
+}
+
+impl Rom {
+    /// Look the functions up, while the flash can still be read
+    unsafe fn lookup() -> Self {
+        Rom {
+            connect_internal_flash: core::mem::transmute(rom_function(b"IF")),
+            flash_exit_xip: core::mem::transmute(rom_function(b"EX")),
+            flash_range_erase: core::mem::transmute(rom_function(b"RE")),
+            flash_range_program: core::mem::transmute(rom_function(b"RP")),
+            flash_flush_cache: core::mem::transmute(rom_function(b"FC")),
+        }
+    }
+}
+
+/// Erase `len` bytes at flash address `address`, or program a page from `page` when it isn't null
+///
+/// Runs from RAM and must not call anything in flash, so no panics, no `memcpy` and no `match`
+/// that could turn into a table.
+#[inline(never)]
+#[link_section = ".data.ram_func"]
+unsafe fn flash_op(rom: &Rom, boot2: &[u32; BOOT2_WORDS], address: u32, len: u32, page: *const u8) {
+    (rom.connect_internal_flash)();
+    (rom.flash_exit_xip)();
+    if page.is_null() {
+        (rom.flash_range_erase)(address, len as usize, BLOCK_SIZE, BLOCK_ERASE_CMD);
+    } else {
+        (rom.flash_range_program)(address, page, PAGE_SIZE as usize);
+    }
+    (rom.flash_flush_cache)();
+    // The copy of the boot loader on the stack, in Thumb mode
+    let boot2: unsafe extern "C" fn() = core::mem::transmute(boot2.as_ptr() as usize + 1);
+    boot2();
+}
+
+/// Run `flash_op` with the interrupts off
+fn run(address: u32, len: u32, page: *const u8) {
+    cortex_m::interrupt::free(|_| unsafe {
+        let rom = Rom::lookup();
+        let mut boot2 = [0; BOOT2_WORDS];
+        core::ptr::copy_nonoverlapping(XIP_BASE as *const u32, boot2.as_mut_ptr(), BOOT2_WORDS);
+        flash_op(&rom, &boot2, address, len, page);
+    });
+}
+
+/// Window of `sectors` sectors on the on-board flash
+#[derive(Debug)]
+pub struct Rp2040Flash {
+    /// Offset of the window from the start of the flash
+    start: u32,
+    len: u32,
+}
+
+impl Rp2040Flash {
+    /// Window from `start`, an offset from the start of the flash
+    ///
+    /// # Safety
+    ///
+    /// The window must be within `RESERVED`, only one window may cover a sector, and core 1
+    /// must not run.
+    pub const unsafe fn new(start: u32, sectors: u32) -> Self {
+        Rp2040Flash {
+            start,
+            len: sectors * SECTOR_SIZE,
+        }
+    }
+
+    /// Check that `len` bytes at `offset` are within the window and aligned to `align`
+    fn check(&self, offset: u32, len: usize, align: u32) -> Result<u32, NorFlashErrorKind> {
+        let end = offset.checked_add(len as u32).filter(|&end| end <= self.len).ok_or(NorFlashErrorKind::OutOfBounds)?;
+        if offset % align != 0 || end % align != 0 {
+            return Err(NorFlashErrorKind::NotAligned);
+        }
+        Ok(self.start + offset)
+    }
+}
+
+impl ErrorType for Rp2040Flash {
+    type Error = NorFlashErrorKind;
+}
+
+impl ReadNorFlash for Rp2040Flash {
+    const READ_SIZE: usize = 1;
+
+    fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        let address = self.check(offset, bytes.len(), 1)?;
+        // The cache was flushed after the last change
+        unsafe { core::ptr::copy_nonoverlapping((XIP_BASE + address) as *const u8, bytes.as_mut_ptr(), bytes.len()) };
+        Ok(())
+    }
+
+    fn capacity(&self) -> usize {
+        self.len as usize
+    }
+}
+
+impl NorFlash for Rp2040Flash {
+    const WRITE_SIZE: usize = PAGE_SIZE as usize;
+    const ERASE_SIZE: usize = SECTOR_SIZE as usize;
+
+    fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+        let len = to.checked_sub(from).ok_or(NorFlashErrorKind::OutOfBounds)?;
+        let address = self.check(from, len as usize, SECTOR_SIZE)?;
+        run(address, len, core::ptr::null());
+        Ok(())
+    }
+
+    fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        let address = self.check(offset, bytes.len(), PAGE_SIZE)?;
+        // `bytes` may be in flash itself, each page is programmed from a copy on the stack
+        for (page_address, chunk) in (address..).step_by(PAGE_SIZE as usize).zip(bytes.chunks(PAGE_SIZE as usize)) {
+            let mut page = [0; PAGE_SIZE as usize];
+            page.copy_from_slice(chunk);
+            run(page_address, PAGE_SIZE, page.as_ptr());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::{
+        console::SCRATCH_START,
+        input_macro::{MACRO_SECTORS, MACRO_START},
+    };
+
+    #[test]
+    fn test_window_bounds() {
+        let flash = unsafe { Rp2040Flash::new(FLASH_SIZE - RESERVED, 2) };
+        assert_eq!(flash.capacity(), 8192);
+        assert_eq!(flash.check(4096, 256, PAGE_SIZE), Ok(FLASH_SIZE - RESERVED + 4096));
+        assert_eq!(flash.check(100, 256, PAGE_SIZE), Err(NorFlashErrorKind::NotAligned));
+        assert_eq!(flash.check(4096, 8192, SECTOR_SIZE), Err(NorFlashErrorKind::OutOfBounds));
+        assert_eq!(flash.check(u32::MAX, 2, 1), Err(NorFlashErrorKind::OutOfBounds));
+
+        // The button macro is the lowest of the reserved flash, the scratch sectors follow
+        assert_eq!(MACRO_START, FLASH_SIZE - RESERVED);
+        assert_eq!(MACRO_START + MACRO_SECTORS * SECTOR_SIZE, SCRATCH_START);
+    }
+}
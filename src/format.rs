@@ -0,0 +1,137 @@
+//! Number formatting without `core::fmt`
+//!
+//! `core::fmt` is big and slow on a Cortex-M0+: every `{}` goes through dynamic dispatch and
+//! the generic padding code. The functions here write integers and fixed-point decimals straight
+//! into the UI buffer, which is all the main page needs. Like `write!`, they stop at the first
+//! text that doesn't fit and leave the buffer cut off.
+
+use core::fmt;
+
+use crate::budget::UiBuffer;
+
+/// Enough room for `i64::MIN` with a decimal point
+const MAX_DIGITS: usize = 22;
+
+/// Decimal representation of an integer or fixed-point number, written back to front into a fixed buffer
+pub struct Digits {
+    buf: [u8; MAX_DIGITS],
+    start: usize,
+}
+
+impl Digits {
+    /// Convert `value` to decimal digits
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::format::Digits;
+    /// assert_eq!(Digits::new(0).as_str(), "0");
+    /// assert_eq!(Digits::new(-42).as_str(), "-42");
+    /// ```
+    ///
+    pub fn new(value: i64) -> Self {
+        Self::fixed(value, 0)
+    }
+
+    /// Convert `tenths` to a decimal number with one digit after the point
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::format::Digits;
+    /// assert_eq!(Digits::tenths(73).as_str(), "7.3");
+    /// assert_eq!(Digits::tenths(-5).as_str(), "-0.5");
+    /// ```
+    ///
+    pub fn tenths(tenths: i64) -> Self {
+        Self::fixed(tenths, 1)
+    }
+
+    /// Convert `value` to a number with `decimals` digits after the point
+    fn fixed(value: i64, decimals: usize) -> Self {
+        let mut digits = Digits {
+            buf: [0; MAX_DIGITS],
+            start: MAX_DIGITS,
+        };
+        let mut rest = value.unsigned_abs();
+        let mut written = 0;
+
+        loop {
+            if decimals > 0 && written == decimals {
+                digits.start -= 1;
+                digits.buf[digits.start] = b'.';
+            }
+
+            digits.start -= 1;
+            digits.buf[digits.start] = b'0' + (rest % 10) as u8;
+            rest /= 10;
+            written += 1;
+
+            // Always at least one digit before the point
+            if rest == 0 && written > decimals {
+                break;
+            }
+        }
+
+        if value < 0 {
+            digits.start -= 1;
+            digits.buf[digits.start] = b'-';
+        }
+
+        digits
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only ASCII digits, '.' and '-' are ever written
+        core::str::from_utf8(&self.buf[self.start..]).unwrap()
+    }
+
+    pub fn len(&self) -> usize {
+        MAX_DIGITS - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl fmt::Display for Digits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+/// Append `s` to the buffer
+pub fn push_str(buf: &mut UiBuffer, s: &str) -> fmt::Result {
+    buf.push_str(s)
+}
+
+/// Append `fill` `count` times
+pub fn push_fill(buf: &mut UiBuffer, fill: char, count: usize) -> fmt::Result {
+    for _ in 0..count {
+        buf.push(fill)?;
+    }
+    Ok(())
+}
+
+/// Append `value` right aligned in a field of `width` characters, like `{:width$}`
+pub fn push_int(buf: &mut UiBuffer, value: i64, width: usize) -> fmt::Result {
+    push_int_with_fill(buf, value, width, ' ')
+}
+
+/// Append `value` right aligned in a field of `width` characters padded with `fill`, like `{:fill>width$}`
+pub fn push_int_with_fill(buf: &mut UiBuffer, value: i64, width: usize, fill: char) -> fmt::Result {
+    push_digits(buf, &Digits::new(value), width, fill)
+}
+
+/// Append `digits` right aligned in a field of `width` characters padded with `fill`
+pub fn push_digits(buf: &mut UiBuffer, digits: &Digits, width: usize, fill: char) -> fmt::Result {
+    push_fill(buf, fill, width.saturating_sub(digits.len()))?;
+    push_str(buf, digits.as_str())
+}
+
+/// Append `s` left aligned in a field of `width` characters, like `{:width$}`
+pub fn push_str_padded(buf: &mut UiBuffer, s: &str, width: usize) -> fmt::Result {
+    push_str(buf, s)?;
+    push_fill(buf, ' ', width.saturating_sub(s.len()))
+}
@@ -0,0 +1,224 @@
+//! What a refresh of the screen shows, and how it is drawn
+//!
+//! `ui_output` of the RTIC firmware writes the text of the page into a `Frame` and picks the
+//! widgets that go with it, and `FrameCache` skips a frame that looks the same as the last one.
+//! The golden images of the `golden` tests are written and drawn by the same code:
+//! `Frame::write_dive_page` writes the pages that only show the dive computer,
+//! `Frame::text_widgets` is the text with the widgets in its rows, which the firmware sends to
+//! the screen in a batch, and `Frame::draw_below` the widgets below the text. `Frame::draw` draws
+//! both without batching.
+
+use embedded_graphics::{
+    mono_font::MonoTextStyle,
+    pixelcolor::Rgb565,
+    prelude::*,
+    text::{Alignment, Text},
+};
+
+use crate::{
+    ascent::Coaching,
+    budget::UiBuffer,
+    cesa::CesaGuide,
+    clock::Clock,
+    deco::DecoModel,
+    fault::FaultCode,
+    screen_saver::ScreenState,
+    stops::{DecoPage, SafetyStopPage},
+    surface::{SurfacePage, TimeOfDay},
+    tech::TechPage,
+    theme::Theme,
+    trend::Trend,
+    ui::Page,
+    widgets::{
+        AscentArrows, FaultBadge, FillBar, LargeDepth, Padlock, RateBar, SecondaryUnits, TrendArrow, ASCENT_ARROWS_POSITION, CESA_ARROWS_POSITION,
+        CESA_DEPTH_POSITION, DEPTH_TREND_POSITION, FAULT_CODE_POSITION, FILL_BAR_POSITION, PADLOCK_POSITION, RATE_BAR_POSITION, SECONDARY_POSITION,
+    },
+    DiveComputer, SecondaryReadings,
+};
+#[cfg(feature = "logbook")]
+use crate::{
+    dive_log::DiveLog,
+    widgets::{ProfileGraph, PROFILE_GRAPH_POSITION},
+};
+
+/// Top left of the page text, the widgets are placed around it
+pub const TEXT_POSITION: Point = Point::new(20, 30);
+
+/// Everything that decides what a refresh of the screen looks like
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub text: UiBuffer,
+    /// The padlock of the button lock is shown
+    pub locked: bool,
+    /// Trend and ascent arrows and secondary units of the main page during a dive
+    pub arrows: Option<(Trend, Coaching, Option<SecondaryReadings>)>,
+    /// Fill progress of the surface page, `Some(None)` while not filling
+    pub fill: Option<Option<u8>>,
+    /// Profile of the last dive on the surface page
+    #[cfg(feature = "logbook")]
+    pub graph: Option<DiveLog>,
+    /// Text color of the alarm, `None` for the color of the theme
+    pub alarm_color: Option<Rgb565>,
+    pub state: ScreenState,
+    /// Depth and rate of the emergency ascent guide
+    pub emergency: Option<CesaGuide>,
+    /// Code in the corner of the screen
+    pub fault: Option<FaultCode>,
+}
+
+impl Frame {
+    /// Frame without text and without the widgets of a page
+    pub fn new(locked: bool, alarm_color: Option<Rgb565>, state: ScreenState) -> Self {
+        Frame {
+            text: UiBuffer::new(),
+            locked,
+            arrows: None,
+            fill: None,
+            #[cfg(feature = "logbook")]
+            graph: None,
+            alarm_color,
+            state,
+            emergency: None,
+            fault: None,
+        }
+    }
+
+    /// Write `page` into `buffer` and pick its widgets, when it only shows the dive computer
+    ///
+    /// `time` and `battery` are shown on the surface page. Returns `false` for the pages that
+    /// show more than the dive computer, those are left to the caller.
+    pub fn write_dive_page<C: Clock, M: DecoModel>(
+        &mut self,
+        buffer: &mut UiBuffer,
+        page: Page,
+        dive_computer: &DiveComputer<C, M>,
+        time: Option<TimeOfDay>,
+        battery: Option<u8>,
+    ) -> bool {
+        match page {
+            Page::Main if !dive_computer.diving() => {
+                writeln!(buffer, "{}", SurfacePage::new(dive_computer, time, battery));
+                self.fill = Some(dive_computer.filling());
+                #[cfg(feature = "logbook")]
+                {
+                    self.graph = dive_computer.last_log().copied();
+                }
+            }
+            Page::Main => {
+                dive_computer.render(buffer);
+                self.arrows = Some((dive_computer.trend(), dive_computer.ascent().coaching(), dive_computer.secondary_readings()));
+            }
+            Page::Warnings => writeln!(buffer, "{}", dive_computer.alarm_history()),
+            // No pressure sensor is read yet, only the simulated depth
+            Page::Tech => writeln!(buffer, "{}", TechPage::new(dive_computer, None)),
+            Page::Deco => writeln!(buffer, "{}", DecoPage::new(dive_computer)),
+            Page::SafetyStop => writeln!(buffer, "{}", SafetyStopPage::new(dive_computer)),
+            _ => return false,
+        }
+        true
+    }
+
+    /// Whether `other` has the same widgets, so only the text that changed has to be drawn
+    ///
+    /// The guide draws over empty lines after its text, so its values don't count.
+    pub fn same_widgets(&self, other: &Frame) -> bool {
+        Frame {
+            text: other.text,
+            emergency: other.emergency,
+            ..*self
+        } == *other
+    }
+
+    /// Colors of the frame, white on black for the emergency ascent guide
+    pub fn theme(&self) -> Theme {
+        let theme = match self.emergency {
+            Some(_) => Theme::emergency(),
+            None => Theme::default().with_text_color(self.alarm_color),
+        };
+        if self.state == ScreenState::Dimmed {
+            theme.dimmed()
+        } else {
+            theme
+        }
+    }
+
+    /// Text and the widgets in its rows, moved by `offset`, over `solid` or a gradient when `None`
+    pub fn text_widgets(&self, theme: &Theme, offset: Point, solid: Option<Rgb565>) -> TextWidgets<'_> {
+        // Over a gradient the text and widgets leave the background to the fill of the rows
+        let style = if solid.is_some() { theme.text_style() } else { theme.transparent_text_style() };
+        TextWidgets {
+            text: Text::with_alignment(self.text.as_str(), TEXT_POSITION + offset, style, Alignment::Left),
+            padlock: Padlock::new(self.locked, PADLOCK_POSITION + offset, theme.text_color, solid),
+            arrows: self.arrows.map(|(trend, coaching, secondary)| {
+                (
+                    TrendArrow::new(trend, DEPTH_TREND_POSITION + offset, theme.text_color, solid),
+                    AscentArrows::new(coaching, ASCENT_ARROWS_POSITION + offset, theme.text_color, solid),
+                    SecondaryUnits::new(secondary, SECONDARY_POSITION + offset, theme.text_color, solid),
+                )
+            }),
+        }
+    }
+
+    /// Draw the widgets outside the rows of the text, moved by `offset`, over `solid` or a gradient when `None`
+    pub fn draw_below<D>(&self, theme: &Theme, offset: Point, solid: Option<Rgb565>, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        if let Some(percent) = self.fill {
+            // The bar takes the place of the graph while filling, the graph clears the place of both
+            #[cfg(feature = "logbook")]
+            let bar_background = {
+                let log = self.graph.filter(|_| percent.is_none());
+                ProfileGraph::new(log, PROFILE_GRAPH_POSITION + offset, theme.text_color, Some(theme.background_color)).draw(target)?;
+                None
+            };
+            #[cfg(not(feature = "logbook"))]
+            let bar_background = Some(theme.background_color);
+            FillBar::new(percent, FILL_BAR_POSITION + offset, theme.text_color, bar_background).draw(target)?;
+        }
+        // Over the empty lines of the guide, after its text
+        if let Some(guide) = self.emergency {
+            LargeDepth::new(guide.depth, CESA_DEPTH_POSITION + offset, theme.text_color, solid).draw(target)?;
+            AscentArrows::new(guide.coaching, CESA_ARROWS_POSITION + offset, theme.text_color, solid).draw(target)?;
+            RateBar::new(guide.rate_percent(), RATE_BAR_POSITION + offset, theme.text_color, solid).draw(target)?;
+        }
+        FaultBadge::new(self.fault, FAULT_CODE_POSITION + offset, theme.text_color, Some(theme.background_color)).draw(target)
+    }
+
+    /// Draw the whole frame over a background that is drawn already
+    pub fn draw<D>(&self, offset: Point, solid: Option<Rgb565>, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let theme = self.theme();
+        self.text_widgets(&theme, offset, solid).draw(target)?;
+        self.draw_below(&theme, offset, solid, target)
+    }
+}
+
+/// Page text with the widgets in its rows, drawn as one
+pub struct TextWidgets<'a> {
+    pub text: Text<'a, MonoTextStyle<'static, Rgb565>>,
+    padlock: Padlock,
+    arrows: Option<(TrendArrow, AscentArrows, SecondaryUnits)>,
+}
+
+impl Drawable for TextWidgets<'_> {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        self.text.draw(target)?;
+        self.padlock.draw(target)?;
+        if let Some((trend, ascent, secondary)) = &self.arrows {
+            trend.draw(target)?;
+            ascent.draw(target)?;
+            secondary.draw(target)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,195 @@
+//! Gas consumption math
+//!
+//! Everything is in whole meters, centibar, centiliter and seconds so it works without floats.
+//! A diver breathes `RESPIRATORY_MINUTE_VOLUME_CL` at the surface, at depth the same volume holds
+//! more gas because of the higher ambient pressure. Ascending from depth happens at the ascent
+//! rate limit, the table is for the default `MAX_SAFE_ASCEND_RATE`.
+//!
+//! | Depth | Pressure | Gas rate | Gas to surface |
+//! |------:|---------:|---------:|---------------:|
+//! |   0 m |   100 cb |  20 cl/s |           0 cl |
+//! |  10 m |   200 cb |  40 cl/s |        1160 cl |
+//! |  20 m |   300 cb |  60 cl/s |        3120 cl |
+//! |  30 m |   400 cb |  80 cl/s |        5880 cl |
+//! |  40 m |   500 cb | 100 cl/s |        9440 cl |
+
+use fugit::SecsDurationU32;
+use serde::{Deserialize, Serialize};
+
+/// Default ascent rate limit in meters per minute, see `AscentLimit` for the others
+///
+/// [`AscentLimit`]: crate::ascent::AscentLimit
+pub const MAX_SAFE_ASCEND_RATE: u32 = 15;
+
+/// Gas breathed per minute at the surface
+pub const RESPIRATORY_MINUTE_VOLUME_CL: u32 = 1200;
+const RESPIRATORY_SECOND_VOLUME_CL: u32 = RESPIRATORY_MINUTE_VOLUME_CL / 60;
+
+/// Pressure at the surface
+pub const SURFACE_PRESSURE_CB: u32 = 100;
+
+/// Ambient pressure in centibar at a depth in meters
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::gas::pressure_in_cb;
+/// assert_eq!(pressure_in_cb(0), 100);
+/// assert_eq!(pressure_in_cb(10), 200);
+/// ```
+///
+pub fn pressure_in_cb(depth_in_m: u32) -> u32 {
+    /* 10m of water = 1 bar = 100 centibar */
+    SURFACE_PRESSURE_CB + (10 * depth_in_m)
+}
+
+/// Depth in meters at an ambient pressure in centibar, pressures below the surface pressure are at the surface
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::gas::depth_in_m;
+/// assert_eq!(depth_in_m(250), 15);
+/// assert_eq!(depth_in_m(90), 0);
+/// ```
+///
+pub fn depth_in_m(pressure_in_cb: u32) -> u32 {
+    pressure_in_cb.saturating_sub(SURFACE_PRESSURE_CB) / 10
+}
+
+/// How hard the diver is working, it scales the gas they breathe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Exertion {
+    Rest,
+    /// Breathes `RESPIRATORY_MINUTE_VOLUME_CL`
+    Normal,
+    /// Swimming against a current, towing a buddy
+    Working,
+}
+
+impl Exertion {
+    /// Respiratory minute volume in percent of `RESPIRATORY_MINUTE_VOLUME_CL`
+    pub fn percent(&self) -> u32 {
+        match self {
+            Exertion::Rest => 75,
+            Exertion::Normal => 100,
+            Exertion::Working => 200,
+        }
+    }
+
+    /// Scale gas in centiliter breathed at normal exertion to this exertion
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::gas::{gas_rate_in_cl, Exertion};
+    /// assert_eq!(Exertion::Working.scale(gas_rate_in_cl(10)), 80);
+    /// assert_eq!(Exertion::Rest.scale(gas_rate_in_cl(10)), 30);
+    /// ```
+    ///
+    pub fn scale(&self, gas_in_cl: u32) -> u32 {
+        gas_in_cl * self.percent() / 100
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Exertion::Rest => Exertion::Normal,
+            Exertion::Normal => Exertion::Working,
+            Exertion::Working => Exertion::Rest,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Exertion::Rest => "REST",
+            Exertion::Normal => "NORMAL",
+            Exertion::Working => "WORKING",
+        }
+    }
+}
+
+/// Calculate gas rate per second in centiliter for a depth in meters
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::gas::gas_rate_in_cl;
+/// assert_eq!(gas_rate_in_cl(0), 20);
+/// assert_eq!(gas_rate_in_cl(10), 40);
+/// ```
+///
+pub fn gas_rate_in_cl(depth_in_m: u32) -> u32 {
+    /* Gas consumed at STP = RSV * ambient pressure / standard pressure */
+    (RESPIRATORY_SECOND_VOLUME_CL * pressure_in_cb(depth_in_m)) / SURFACE_PRESSURE_CB
+}
+
+/// Calculate gas needed to reach the surface at `ascent_rate` in meters per minute
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::gas::{gas_to_surface_in_cl, MAX_SAFE_ASCEND_RATE};
+/// assert_eq!(gas_to_surface_in_cl(0, MAX_SAFE_ASCEND_RATE), 0);
+/// assert_eq!(gas_to_surface_in_cl(10, MAX_SAFE_ASCEND_RATE), 1160);
+/// assert_eq!(gas_to_surface_in_cl(10, 9), 1933);
+/// ```
+///
+pub fn gas_to_surface_in_cl(depth_in_m: u32, ascent_rate: u32) -> u32 {
+    let mut gas_per_minute = 0;
+
+    // Gas for every meter as if it took a minute, scaled to the time it takes at the end
+    for depth in 0..depth_in_m {
+        gas_per_minute += gas_rate_in_cl(depth) * 60;
+    }
+
+    gas_per_minute / ascent_rate
+}
+
+/// Calculate gas in centiliter used staying at a depth in meters for `duration`
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::gas::gas_for_segment;
+/// use fugit::SecsDurationU32;
+/// assert_eq!(gas_for_segment(10, SecsDurationU32::minutes(5)), 12_000);
+/// ```
+///
+pub fn gas_for_segment(depth_in_m: u32, duration: SecsDurationU32) -> u32 {
+    gas_rate_in_cl(depth_in_m) * duration.to_secs()
+}
+
+/// Time that can be spent at a depth in meters before the air in centiliter is only enough to
+/// reach the surface at `ascent_rate` in meters per minute
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::gas::{ndl_air_limited, MAX_SAFE_ASCEND_RATE};
+/// use fugit::SecsDurationU32;
+/// assert_eq!(ndl_air_limited(10, 5000, MAX_SAFE_ASCEND_RATE), SecsDurationU32::secs(96));
+/// assert_eq!(ndl_air_limited(10, 1000, MAX_SAFE_ASCEND_RATE), SecsDurationU32::secs(0));
+/// ```
+///
+pub fn ndl_air_limited(depth_in_m: u32, air_in_cl: u32, ascent_rate: u32) -> SecsDurationU32 {
+    let spare = air_in_cl.saturating_sub(gas_to_surface_in_cl(depth_in_m, ascent_rate));
+    SecsDurationU32::secs(spare / gas_rate_in_cl(depth_in_m))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_module_table() {
+        let table = [(0, 100, 20, 0), (10, 200, 40, 1160), (20, 300, 60, 3120), (30, 400, 80, 5880), (40, 500, 100, 9440)];
+
+        for (depth, pressure, rate, to_surface) in table {
+            assert_eq!(pressure_in_cb(depth), pressure);
+            assert_eq!(depth_in_m(pressure), depth);
+            assert_eq!(gas_rate_in_cl(depth), rate);
+            assert_eq!(gas_to_surface_in_cl(depth, MAX_SAFE_ASCEND_RATE), to_surface);
+        }
+    }
+}
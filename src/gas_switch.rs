@@ -0,0 +1,128 @@
+//! Gas switch reminders
+//!
+//! Up to `MAX_DECO_GASES` deco gases are carried next to the back gas, set by their oxygen
+//! fraction on the settings page. Each is only breathable shallower than its maximum operating
+//! depth at `MAX_PPO2_CB`, rounded down to a stop. The switch depths become ascending depth
+//! alerts, so crossing one on the way up chirps the buzzer and shows e.g. "SWITCH EAN50 @ 21M" in
+//! the title line of the main page for `TOAST_TIME`.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    deco::STOP_INTERVAL,
+    depth_alert::{Crossing, DepthAlert, DepthAlerts},
+    gas::depth_in_m,
+};
+
+/// Number of deco gases
+pub const MAX_DECO_GASES: usize = 2;
+
+/// Highest partial pressure of oxygen for a deco gas, in centibar
+pub const MAX_PPO2_CB: u32 = 160;
+
+/// Leanest deco gas that can be set, in percent oxygen
+const MIN_O2: u8 = 25;
+
+/// Distance between the fractions that can be set
+const O2_STEP: u8 = 5;
+
+/// Switch depth in meters of a gas with `o2` percent oxygen
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::gas_switch::switch_depth_m;
+/// assert_eq!(switch_depth_m(50), 21);
+/// assert_eq!(switch_depth_m(100), 6);
+/// ```
+///
+pub fn switch_depth_m(o2: u8) -> u32 {
+    let stop_m = STOP_INTERVAL / 1000;
+    depth_in_m(MAX_PPO2_CB * 100 / u32::from(o2.max(1))) / stop_m * stop_m
+}
+
+/// Deco gases in percent oxygen, `None` when not carried
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DecoGases {
+    pub o2: [Option<u8>; MAX_DECO_GASES],
+}
+
+impl DecoGases {
+    /// Only back gas
+    pub const fn new() -> Self {
+        DecoGases { o2: [None; MAX_DECO_GASES] }
+    }
+
+    /// Whether the settings page can set the gases
+    pub fn is_valid(&self) -> bool {
+        self.o2.iter().flatten().all(|&o2| (MIN_O2..=100).contains(&o2))
+    }
+
+    /// Next fraction of gas `index`: off, then every `O2_STEP` from `MIN_O2` up to pure oxygen
+    pub fn step(&mut self, index: usize) {
+        self.o2[index] = match self.o2[index] {
+            None => Some(MIN_O2),
+            Some(o2) if o2 >= 100 => None,
+            Some(o2) => Some(o2 + O2_STEP),
+        };
+    }
+
+    /// An ascending alert at the switch depth of each gas, in the order of the gases
+    pub fn alerts(&self) -> DepthAlerts {
+        let mut alerts = DepthAlerts::new();
+        for (alert, o2) in alerts.alerts.iter_mut().zip(self.o2) {
+            *alert = o2.map(|o2| DepthAlert {
+                depth_m: switch_depth_m(o2),
+                crossing: Crossing::Ascending,
+            });
+        }
+        alerts
+    }
+}
+
+impl Default for DecoGases {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::depth_alert::MAX_DEPTH_ALERTS;
+
+    #[test]
+    fn test_switch_alerts() {
+        let mut gases = DecoGases::new();
+        for _ in 0..6 {
+            gases.step(0);
+        }
+        gases.step(1);
+        assert_eq!(gases.o2, [Some(50), Some(25)]);
+        assert!(gases.is_valid());
+        // 1.6 bar of oxygen at 54 m
+        assert_eq!(switch_depth_m(25), 54);
+
+        let alerts = gases.alerts();
+        assert_eq!(
+            alerts.alerts[0],
+            Some(DepthAlert {
+                depth_m: 21,
+                crossing: Crossing::Ascending
+            })
+        );
+        assert_eq!(alerts.crossed(22_000, 21_000), Some(0));
+        assert_eq!(alerts.crossed(21_000, 22_000), None);
+        assert_eq!(alerts.alerts[MAX_DECO_GASES..], [None; MAX_DEPTH_ALERTS - MAX_DECO_GASES]);
+
+        for _ in 0..10 {
+            gases.step(0);
+        }
+        assert_eq!(gases.o2[0], Some(100));
+        gases.step(0);
+        assert_eq!(gases.o2[0], None);
+        gases.o2[1] = Some(21);
+        assert!(!gases.is_valid());
+    }
+}
@@ -0,0 +1,240 @@
+//! Golden images of the screen pages
+//!
+//! Every page is drawn on a simulated screen for a few representative states by the `Frame` that
+//! `ui_output` of the RTIC firmware draws with too, and compared pixel by pixel with the PNG of the same
+//! name in `assets/golden`. A change of a format, a font or the layout fails here instead of
+//! showing up on the hardware.
+//!
+//! After an intended change the images are written again with
+//! `UPDATE_GOLDEN=1 cargo test_pc golden`. A missing image is written too, but fails the test
+//! once so it gets looked at before it is committed.
+//!
+//! The images are not committed yet. Until someone writes them with the command above, checks
+//! them against the screen of a real board and commits them, these tests fail, naming each
+//! image they wrote.
+
+use std::{env, fmt, fs};
+
+use embedded_graphics::{
+    pixelcolor::{Rgb565, Rgb888},
+    prelude::*,
+};
+use embedded_graphics_simulator::{OutputSettingsBuilder, SimulatorDisplay};
+use fugit::MicrosDurationU32;
+
+#[cfg(feature = "logbook")]
+use crate::storage::WearMap;
+use crate::{
+    apnea::ApneaTimer,
+    battery::BatteryTrend,
+    blending::BlendCalculator,
+    budget::UiBuffer,
+    cesa::CesaGuide,
+    checklist::Checklist,
+    clock::{Instant, ManualClock},
+    diagnostics::RuntimeStats,
+    factory_reset::FactoryReset,
+    failure::FailureInjector,
+    fault::FaultCode,
+    frame::Frame,
+    help::HelpPage,
+    keymap::Action,
+    morse::MorseSignal,
+    next_dive::NextDiveAlarm,
+    odometer::LifetimeStats,
+    peripherals::{Inventory, Subsystems},
+    planner::PlanEditor,
+    render::{Background, SCREEN_SIZE},
+    screen_saver::ScreenState,
+    self_test::SelfTestReport,
+    settings::{Settings, SettingsEditor},
+    setup::SetupWizard,
+    surface::TimeOfDay,
+    theme::{DepthGradient, Theme, PAGE_FONT},
+    ui::Page,
+    DiveComputer, Unit,
+};
+
+/// Directory of the golden images
+const GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/assets/golden");
+
+type Screen = SimulatorDisplay<Rgb565>;
+
+/// Dive computer after diving `profile`, minutes at each depth in mm read from the sensor
+fn after_dive(profile: &[(u32, u32)]) -> DiveComputer<ManualClock> {
+    let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+    for &(depth, minutes) in profile {
+        dive_computer.update_sensor(Ok(depth));
+        for _ in 0..minutes {
+            dive_computer.change_depth(MicrosDurationU32::secs(60));
+        }
+    }
+    dive_computer
+}
+
+/// Frame of `page` as `ui_output` writes it, unlocked and with its own colors
+fn frame(page: Page, dive_computer: &DiveComputer<ManualClock>, settings: &Settings) -> Frame {
+    let mut frame = Frame::new(false, dive_computer.reserve().color(), ScreenState::On);
+    let mut buffer = UiBuffer::new();
+    let time = Some(TimeOfDay { hours: 9, minutes: 41 });
+    if !frame.write_dive_page(&mut buffer, page, dive_computer, time, Some(80)) {
+        match page {
+            Page::Diagnostics => {
+                writeln!(buffer, "{}", RuntimeStats::new());
+                writeln!(buffer, "{}", BatteryTrend::new().runtime());
+                #[cfg(feature = "logbook")]
+                writeln!(buffer, "{}", WearMap::new());
+                writeln!(buffer, "{}", dive_computer.replay());
+                writeln!(buffer, "EXERTION: {:>10}", dive_computer.exertion().as_str());
+                writeln!(buffer, "{}", Subsystems::new());
+                writeln!(buffer, "{}", Inventory::scan(|_| false));
+                writeln!(buffer, "{}", LifetimeStats::new());
+            }
+            Page::Planner => {
+                let planner = PlanEditor::new();
+                let result = dive_computer
+                    .planning_allowed()
+                    .then(|| planner.plan.evaluate(dive_computer.deco(), dive_computer.ascent().limit()));
+                writeln!(buffer, "{}", planner.page(result.as_ref(), dive_computer.depth_display()));
+                writeln!(buffer, "{}", NextDiveAlarm::new().line(dive_computer.surface_interval()));
+            }
+            Page::Blending => writeln!(buffer, "{}", BlendCalculator::new()),
+            Page::Apnea => writeln!(buffer, "{}", ApneaTimer::new().page(&settings.apnea)),
+            Page::Signal => writeln!(buffer, "{}", MorseSignal::new()),
+            Page::Settings => writeln!(buffer, "{}", SettingsEditor::new().page(settings)),
+            Page::SelfTest => writeln!(buffer, "{}", SelfTestReport::new()),
+            Page::Failures => writeln!(buffer, "{}", FailureInjector::new()),
+            // Written by the frame
+            Page::Main | Page::Warnings | Page::Tech | Page::Deco | Page::SafetyStop => {}
+        }
+    }
+    // A page that doesn't fit shows its fault code, like on the device
+    frame.fault = buffer.truncated().then_some(FaultCode::RenderOverflow);
+    frame.text = buffer;
+    frame
+}
+
+/// Text of the pages `ui_output` shows instead of the current page, in their states with the most text
+fn overlay_texts(dive_computer: &DiveComputer<ManualClock>, settings: &Settings) -> Vec<UiBuffer> {
+    let mut texts = Vec::new();
+    let mut write = |text: &dyn fmt::Display| {
+        let mut buffer = UiBuffer::new();
+        writeln!(buffer, "{}", text);
+        texts.push(buffer);
+    };
+
+    write(&CesaGuide::new(dive_computer, Instant::from_ticks(0)));
+    write(&SetupWizard::new().page(settings));
+    let mut reset = FactoryReset::new();
+    reset.open();
+    write(&reset);
+    let mut checklist = Checklist::new();
+    checklist.open();
+    checklist.tick();
+    write(&checklist);
+    for page in Page::ALL {
+        write(&HelpPage::new(page, &settings.bindings));
+    }
+    texts
+}
+
+/// Draw `page` like `ui_output` does, without the batching that only saves transfers to the screen
+fn draw(page: Page, dive_computer: &DiveComputer<ManualClock>, settings: &Settings) -> Screen {
+    let background = match page {
+        Page::Main if dive_computer.diving() => Background::Depth(DepthGradient::new(dive_computer.depth())),
+        _ => Background::Solid(Theme::default().background_color),
+    };
+
+    let mut screen = Screen::new(Size::new_equal(SCREEN_SIZE.into()));
+    background.fill(screen.bounding_box(), &mut screen).unwrap();
+    frame(page, dive_computer, settings).draw(Point::zero(), background.solid(), &mut screen).unwrap();
+    screen
+}
+
+/// Compare `screen` with the golden image `name`, or write it
+fn assert_golden(name: &str, screen: &Screen) {
+    let path = format!("{}/{}.png", GOLDEN_DIR, name);
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+
+    match SimulatorDisplay::<Rgb888>::load_png(&path) {
+        Ok(golden) if !update => {
+            assert_eq!(golden.size(), screen.size(), "{} has another size", path);
+            let differing = screen
+                .bounding_box()
+                .points()
+                .filter(|&point| Rgb888::from(screen.get_pixel(point)) != golden.get_pixel(point))
+                .count();
+            assert_eq!(differing, 0, "{} differs from {} in {} pixels", name, path, differing);
+        }
+        _ => {
+            fs::create_dir_all(GOLDEN_DIR).unwrap();
+            screen.to_rgb_output_image(&OutputSettingsBuilder::new().build()).save_png(&path).unwrap();
+            assert!(update, "{} had no golden image, wrote {}, check it and commit it", name, path);
+        }
+    }
+}
+
+/// File name of `page` in `state`
+fn name(state: &str, page: Page) -> String {
+    format!("{}_{}", state, page.as_str().to_lowercase().replace(' ', "_"))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_surface_pages() {
+        let dive_computer = after_dive(&[]);
+        let settings = Settings::new();
+        for page in Page::ALL {
+            assert_golden(&name("surface", page), &draw(page, &dive_computer, &settings));
+        }
+
+        // The last dive with a mark halfway, on the graph with the logbook
+        let mut dive_computer = after_dive(&[(18_000, 10)]);
+        dive_computer.perform(Action::Mark);
+        dive_computer.update_sensor(Ok(18_000));
+        dive_computer.change_depth(MicrosDurationU32::minutes(10));
+        dive_computer.update_sensor(Ok(0));
+        dive_computer.change_depth(MicrosDurationU32::minutes(5));
+        assert!(!dive_computer.diving());
+        assert_golden(&name("after_dive", Page::Main), &draw(Page::Main, &dive_computer, &settings));
+    }
+
+    #[test]
+    fn test_dive_pages() {
+        let settings = Settings::new();
+        // Within the no-decompression limit, with a ceiling, and at the safety stop
+        for (state, profile) in [
+            ("bottom", &[(18_000, 20)][..]),
+            ("deco", &[(40_000, 15)][..]),
+            ("safety_stop", &[(18_000, 20), (5_000, 1)][..]),
+        ] {
+            let dive_computer = after_dive(profile);
+            assert!(dive_computer.diving());
+            for page in [Page::Main, Page::Warnings, Page::Tech, Page::Deco, Page::SafetyStop] {
+                assert_golden(&name(state, page), &draw(page, &dive_computer, &settings));
+            }
+        }
+
+        // Both units leave room for the secondary values next to the depth and rate
+        let mut dive_computer = after_dive(&[(18_000, 20)]);
+        dive_computer.set_unit(Unit::Both);
+        assert_golden(&name("bottom_both_units", Page::Main), &draw(Page::Main, &dive_computer, &settings));
+    }
+
+    #[test]
+    fn test_pages_only_use_glyphs_of_the_font() {
+        let settings = Settings::new();
+        for profile in [&[][..], &[(18_000, 20)][..], &[(40_000, 15)][..], &[(18_000, 20), (5_000, 1)][..]] {
+            let dive_computer = after_dive(profile);
+            let pages = Page::ALL.into_iter().map(|page| frame(page, &dive_computer, &settings).text);
+            for text in pages.chain(overlay_texts(&dive_computer, &settings)) {
+                let missing = text.as_str().chars().find(|&c| c != '\n' && !PAGE_FONT.has_glyph(c));
+                assert_eq!(missing, None, "not in the font:\n{}", text.as_str());
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+//! Help overlay
+//!
+//! Pressing the help chord shows what every button does on the current page, for `HELP_TIME`.
+//! The overlay is generated from the key bindings and the chord table, so it shows remapped
+//! buttons too.
+
+use core::fmt;
+
+use fugit::MicrosDurationU64;
+
+use crate::{
+    clock::Instant,
+    keymap::{Action, Button, KeyBindings, Press, CHORDS},
+    lock::LOCK_HOLD_TIME,
+    ui::Page,
+};
+
+/// Time the overlay stays on screen
+pub const HELP_TIME: MicrosDurationU64 = MicrosDurationU64::secs(5);
+
+#[derive(Debug, Clone, Copy)]
+pub struct HelpOverlay {
+    /// When the overlay was asked for
+    shown_at: Option<Instant>,
+}
+
+impl HelpOverlay {
+    pub const fn new() -> Self {
+        HelpOverlay { shown_at: None }
+    }
+
+    /// Show the overlay from `now`
+    pub fn show(&mut self, now: Instant) {
+        self.shown_at = Some(now);
+    }
+
+    /// Whether the overlay is on screen at `now`
+    pub fn visible(&self, now: Instant) -> bool {
+        self.shown_at
+            .is_some_and(|shown_at| now.checked_duration_since(shown_at).is_some_and(|shown| shown < HELP_TIME))
+    }
+}
+
+impl Default for HelpOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// What the buttons do on a page
+pub struct HelpPage<'a> {
+    page: Page,
+    bindings: &'a KeyBindings,
+}
+
+impl<'a> HelpPage<'a> {
+    pub fn new(page: Page, bindings: &'a KeyBindings) -> Self {
+        HelpPage { page, bindings }
+    }
+}
+
+impl fmt::Display for HelpPage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Help: {}", self.page.as_str())?;
+        writeln!(f)?;
+
+        for button in Button::ALL {
+            // The failures page is kept from the students
+            let shown = |action| if action == Action::Failures { Action::None } else { action };
+            let tap = shown(self.bindings.action(self.page, button, Press::Tap));
+            let hold = shown(self.bindings.action(self.page, button, Press::Hold));
+            if tap != Action::None {
+                writeln!(f, "{}: {}", button.as_str(), tap.as_str())?;
+            }
+            // Holding mostly repeats the tap, only list the holds that differ
+            if hold != tap && hold != Action::None {
+                writeln!(f, "{} HOLD: {}", button.as_str(), hold.as_str())?;
+            }
+        }
+
+        for (first, second, action) in CHORDS {
+            writeln!(f, "{}+{}: {}", first.as_str(), second.as_str(), action.as_str())?;
+        }
+        write!(f, "X+Y {}S: LOCK", LOCK_HOLD_TIME.to_secs())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_help_follows_bindings() {
+        let mut bindings = KeyBindings::new();
+        bindings.set(Page::Main, Button::Y, Press::Tap, Action::None);
+        let text = format!("{}", HelpPage::new(Page::Main, &bindings));
+        assert!(text.starts_with("Help: MAIN\n\nA: FILL AIR\nB: UNIT\nX: RATE UP\nY HOLD: RATE DOWN\n"));
+        assert!(text.ends_with("B+Y: HELP\nX+Y 2S: LOCK"));
+        assert!(format!("{}", HelpPage::new(Page::Diagnostics, &bindings)).contains("A HOLD: FREE FLOW\n"));
+        assert!(!format!("{}", HelpPage::new(Page::SelfTest, &bindings)).contains("FAILURES"));
+
+        let mut help = HelpOverlay::new();
+        assert!(!help.visible(Instant::from_ticks(0)));
+        help.show(Instant::from_ticks(1_000_000));
+        assert!(help.visible(Instant::from_ticks(5_999_999)));
+        assert!(!help.visible(Instant::from_ticks(6_000_000)));
+    }
+}
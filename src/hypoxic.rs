@@ -0,0 +1,131 @@
+//! Hypoxic back gas interlock
+//!
+//! A back gas with less than `MIN_PPO2_CB` percent oxygen, e.g. trimix 10/70, doesn't keep a
+//! diver conscious at the surface. It is only breathable from its minimum operating depth on,
+//! where the ambient pressure raises the partial pressure of oxygen to `MIN_PPO2_CB`.
+//!
+//! With such a gas configured, starting a dive is blocked: a high alarm sounds at the surface and
+//! while descending until the minimum operating depth is reached. Ascending through it again
+//! raises a medium alarm, until the surface arms the block for the next dive.
+
+use crate::{
+    deco::Gas,
+    gas::{pressure_in_cb, SURFACE_PRESSURE_CB},
+};
+
+/// Lowest partial pressure of oxygen that is safe to breathe, in centibar
+pub const MIN_PPO2_CB: u32 = 18;
+
+/// Minimum operating depth of `gas` in millimeters, zero when it is breathable at the surface
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::{deco::Gas, hypoxic::min_depth};
+/// assert_eq!(min_depth(Gas { o2: 10, he: 70 }), 8_000);
+/// assert_eq!(min_depth(Gas::AIR), 0);
+/// ```
+///
+pub fn min_depth(gas: Gas) -> u32 {
+    let o2 = u32::from(gas.o2.max(1));
+    // Round the pressure up, shallower is still hypoxic
+    let pressure = (MIN_PPO2_CB * 100).div_ceil(o2);
+    // 1 centibar is 100 mm of water, see `pressure_in_cb`
+    pressure.saturating_sub(SURFACE_PRESSURE_CB) * 1000 / (pressure_in_cb(1) - SURFACE_PRESSURE_CB)
+}
+
+/// Where the diver is relative to the minimum operating depth
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interlock {
+    /// At or below the minimum operating depth, or the gas isn't hypoxic
+    Clear,
+    /// Not below the minimum operating depth yet since the surface
+    Blocked,
+    /// Ascended through the minimum operating depth
+    Ascended,
+}
+
+/// Minimum operating depth of the back gas and whether the current dive has been below it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HypoxicInterlock {
+    /// In millimeters
+    min_depth: u32,
+    reached: bool,
+}
+
+impl HypoxicInterlock {
+    /// For air, never blocks
+    pub const fn new() -> Self {
+        HypoxicInterlock { min_depth: 0, reached: false }
+    }
+
+    /// Breathe `gas` from now on
+    pub fn set_gas(&mut self, gas: Gas) {
+        self.min_depth = min_depth(gas);
+    }
+
+    /// Minimum operating depth in millimeters
+    pub fn min_depth(&self) -> u32 {
+        self.min_depth
+    }
+
+    /// Follow the diver to `depth` in millimeters
+    pub fn update(&mut self, depth: u32) {
+        if depth >= self.min_depth {
+            self.reached = true;
+        } else if depth == 0 {
+            self.reached = false;
+        }
+    }
+
+    /// State at `depth` in millimeters
+    pub fn state(&self, depth: u32) -> Interlock {
+        if depth >= self.min_depth {
+            Interlock::Clear
+        } else if self.reached {
+            Interlock::Ascended
+        } else {
+            Interlock::Blocked
+        }
+    }
+}
+
+impl Default for HypoxicInterlock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_min_depth() {
+        assert_eq!(min_depth(Gas { o2: 15, he: 55 }), 2_000);
+        // 18 / 17 bar is 0.59 m, rounded up
+        assert_eq!(min_depth(Gas { o2: 17, he: 0 }), 600);
+        assert_eq!(min_depth(Gas { o2: 18, he: 45 }), 0);
+    }
+
+    #[test]
+    fn test_interlock() {
+        let mut interlock = HypoxicInterlock::new();
+        interlock.update(0);
+        assert_eq!(interlock.state(0), Interlock::Clear);
+
+        interlock.set_gas(Gas { o2: 10, he: 70 });
+        assert_eq!(interlock.state(0), Interlock::Blocked);
+        interlock.update(7_900);
+        assert_eq!(interlock.state(7_900), Interlock::Blocked);
+        interlock.update(8_000);
+        assert_eq!(interlock.state(8_000), Interlock::Clear);
+
+        // Up again, blocked once more at the surface
+        interlock.update(5_000);
+        assert_eq!(interlock.state(5_000), Interlock::Ascended);
+        interlock.update(0);
+        assert_eq!(interlock.state(0), Interlock::Blocked);
+    }
+}
@@ -0,0 +1,188 @@
+//! I2C peripheral mode
+//!
+//! The dive computer answers on I2C1 at `ADDRESS`, so another microcontroller or a Raspberry Pi
+//! can poll it. A controller writes the number of the first register, then reads as many bytes
+//! as it wants; the register number advances with every byte. All registers are read-only and
+//! multi-byte values are big-endian. Reads past the last register return `0xFF`.
+//!
+//! | Register | Name     | Size | Contents                                            |
+//! |----------|----------|------|-----------------------------------------------------|
+//! | `0x00`   | ID       | 1    | Always `ID`                                         |
+//! | `0x01`   | VERSION  | 1    | Layout version, `VERSION`                           |
+//! | `0x02`   | DEPTH    | 4    | Depth in millimeters                                |
+//! | `0x06`   | RATE     | 2    | Signed rate in meters per minute, negative going up |
+//! | `0x08`   | AIR      | 2    | Air in liters at the surface pressure               |
+//! | `0x0A`   | PRESSURE | 2    | Tank pressure in bar                                |
+//! | `0x0C`   | ALARM    | 1    | 0 none, 1 low, 2 air reserve, 3 medium, 4 air critical, 5 high |
+//! | `0x0D`   | STATUS   | 1    | `STATUS_*` bits                                     |
+//! | `0x0E`   | EDT      | 4    | Elapsed dive time in seconds                        |
+//!
+//! The registers are a snapshot taken when a transfer starts, so the bytes of one read always
+//! belong together. E.g. `i2cget -y 1 0x42 0x0c` on a Raspberry Pi reads the alarm, and
+//! `i2ctransfer -y 1 w1@0x42 0x02 r4` the depth.
+
+use crate::{clock::Clock, deco::DecoModel, Alarm, DiveComputer};
+
+/// 7-bit address of the dive computer
+pub const ADDRESS: u16 = 0x42;
+
+/// Contents of the ID register
+pub const ID: u8 = 0xD1;
+
+/// Contents of the VERSION register, raised when the layout changes
+pub const VERSION: u8 = 1;
+
+/// A dive is going on
+pub const STATUS_DIVING: u8 = 1 << 0;
+/// The pressure sensor failed, the depth is simulated
+pub const STATUS_SENSOR_FAULT: u8 = 1 << 1;
+/// A decompression stop is required
+pub const STATUS_DECO: u8 = 1 << 2;
+/// Dive planning is locked after a missed stop
+pub const STATUS_LOCKED: u8 = 1 << 3;
+
+pub const REG_ID: u8 = 0x00;
+pub const REG_VERSION: u8 = 0x01;
+pub const REG_DEPTH: u8 = 0x02;
+pub const REG_RATE: u8 = 0x06;
+pub const REG_AIR: u8 = 0x08;
+pub const REG_PRESSURE: u8 = 0x0A;
+pub const REG_ALARM: u8 = 0x0C;
+pub const REG_STATUS: u8 = 0x0D;
+pub const REG_EDT: u8 = 0x0E;
+
+/// Number of registers
+pub const REGISTER_COUNT: usize = 0x12;
+
+/// Register value of `alarm`, higher is more severe
+fn alarm_code(alarm: Alarm) -> u8 {
+    match alarm {
+        Alarm::None => 0,
+        Alarm::Low => 1,
+        Alarm::AirReserve => 2,
+        Alarm::Medium => 3,
+        Alarm::AirCritical => 4,
+        Alarm::High => 5,
+    }
+}
+
+/// Registers as seen by the controller
+#[derive(Debug, Clone, Copy)]
+pub struct RegisterMap {
+    registers: [u8; REGISTER_COUNT],
+    /// Register of the next read
+    pointer: usize,
+    /// The register number was written in this transfer
+    addressed: bool,
+}
+
+impl RegisterMap {
+    pub const fn new() -> Self {
+        let mut registers = [0; REGISTER_COUNT];
+        registers[REG_ID as usize] = ID;
+        registers[REG_VERSION as usize] = VERSION;
+        RegisterMap {
+            registers,
+            pointer: 0,
+            addressed: false,
+        }
+    }
+
+    /// A transfer starts: take a snapshot of `dive_computer`
+    pub fn start<C: Clock, M: DecoModel>(&mut self, dive_computer: &DiveComputer<C, M>) {
+        self.addressed = false;
+
+        let mut status = 0;
+        if dive_computer.diving() {
+            status |= STATUS_DIVING;
+        }
+        if dive_computer.sensor_fault().is_some() {
+            status |= STATUS_SENSOR_FAULT;
+        }
+        if dive_computer.deco().ceiling() > 0 {
+            status |= STATUS_DECO;
+        }
+        if dive_computer.lockout().active() {
+            status |= STATUS_LOCKED;
+        }
+
+        let rate = dive_computer.rate().clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+        let air = (dive_computer.air() / 100).min(u16::MAX as u32) as u16;
+        let pressure = (dive_computer.tank_pressure() / 100).min(u16::MAX as u32) as u16;
+        let edt = dive_computer.edt().to_secs().min(u32::MAX as u64) as u32;
+
+        self.put(REG_DEPTH, &dive_computer.depth().to_be_bytes());
+        self.put(REG_RATE, &rate.to_be_bytes());
+        self.put(REG_AIR, &air.to_be_bytes());
+        self.put(REG_PRESSURE, &pressure.to_be_bytes());
+        self.put(REG_ALARM, &[alarm_code(dive_computer.alarm())]);
+        self.put(REG_STATUS, &[status]);
+        self.put(REG_EDT, &edt.to_be_bytes());
+    }
+
+    fn put(&mut self, register: u8, bytes: &[u8]) {
+        let start = register as usize;
+        self.registers[start..start + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// The controller wrote `byte`: the first one of a transfer selects the register, the others
+    /// are ignored
+    pub fn write(&mut self, byte: u8) {
+        if !self.addressed {
+            self.pointer = byte as usize;
+            self.addressed = true;
+        }
+    }
+
+    /// Next byte for the controller
+    pub fn read(&mut self) -> u8 {
+        let byte = self.registers.get(self.pointer).copied().unwrap_or(0xFF);
+        self.pointer = self.pointer.saturating_add(1);
+        byte
+    }
+}
+
+impl Default for RegisterMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use fugit::MicrosDurationU32;
+
+    use super::*;
+    use crate::{clock::ManualClock, keymap::Action};
+
+    #[test]
+    fn test_read_registers() {
+        let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+        for _ in 0..10 {
+            dive_computer.perform(Action::IncreaseRate);
+        }
+        dive_computer.change_depth(MicrosDurationU32::secs(90));
+
+        let mut map = RegisterMap::new();
+        map.start(&dive_computer);
+        map.write(REG_ID);
+        assert_eq!([map.read(), map.read()], [ID, VERSION]);
+
+        // Write then read with a repeated start
+        map.start(&dive_computer);
+        map.write(REG_DEPTH);
+        map.start(&dive_computer);
+        let depth: Vec<_> = (0..4).map(|_| map.read()).collect();
+        assert_eq!(depth, 15_000u32.to_be_bytes());
+        assert_eq!([map.read(), map.read()], 10i16.to_be_bytes());
+
+        map.start(&dive_computer);
+        map.write(REG_STATUS);
+        assert_eq!(map.read(), STATUS_DIVING);
+        // Past the end
+        map.start(&dive_computer);
+        map.write(REG_EDT + 3);
+        assert_eq!([map.read(), map.read()], [90, 0xFF]);
+    }
+}
@@ -0,0 +1,121 @@
+//! Accelerometer of an IMU breakout
+//!
+//! An LSM6DS3 on the breakout I2C bus, at one of the addresses of `Peripheral::Imu`. Only the
+//! accelerometer runs, at 104 Hz over ±16 g so the hardest knocks still fit, for the shock
+//! detection of `shock`. The gyroscope stays powered down as it is after reset.
+
+use embedded_hal::blocking::i2c::{Write, WriteRead};
+
+/// Register with the fixed id of the chip
+const WHO_AM_I: u8 = 0x0F;
+
+/// Ids of the LSM6DS3 and the LSM6DS3TR-C, the registers used here are the same
+const CHIP_IDS: [u8; 2] = [0x69, 0x6A];
+
+/// Data rate, full scale and filter bandwidth of the accelerometer
+const CTRL1_XL: u8 = 0x10;
+
+/// 104 Hz, ±16 g and a 100 Hz anti-aliasing filter
+const CTRL1_XL_104HZ_16G: u8 = 0b0100_0110;
+
+/// First of the six output registers, X, Y and Z low byte first
+const OUTX_L_XL: u8 = 0x28;
+
+/// Sensitivity at ±16 g in micro-g per bit
+const MICRO_G_PER_BIT: i32 = 488;
+
+/// Source of acceleration readings
+pub trait Accelerometer {
+    type Error;
+
+    /// Acceleration along X, Y and Z in milli-g, gravity included
+    fn read(&mut self) -> Result<[i32; 3], Self::Error>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImuError<E> {
+    Bus(E),
+    /// Another chip answered, with this id
+    UnknownChip(u8),
+}
+
+/// LSM6DS3 accelerometer
+pub struct Lsm6ds3<I2C> {
+    i2c: I2C,
+    address: u8,
+}
+
+impl<I2C, E> Lsm6ds3<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    /// Check the chip at `address` and start the accelerometer
+    pub fn new(mut i2c: I2C, address: u8) -> Result<Self, ImuError<E>> {
+        let mut id = [0];
+        i2c.write_read(address, &[WHO_AM_I], &mut id).map_err(ImuError::Bus)?;
+        if !CHIP_IDS.contains(&id[0]) {
+            return Err(ImuError::UnknownChip(id[0]));
+        }
+        i2c.write(address, &[CTRL1_XL, CTRL1_XL_104HZ_16G]).map_err(ImuError::Bus)?;
+        Ok(Lsm6ds3 { i2c, address })
+    }
+}
+
+impl<I2C, E> Accelerometer for Lsm6ds3<I2C>
+where
+    I2C: Write<Error = E> + WriteRead<Error = E>,
+{
+    type Error = E;
+
+    fn read(&mut self) -> Result<[i32; 3], E> {
+        let mut bytes = [0; 6];
+        // The register address increments on its own during a read, as it does after reset
+        self.i2c.write_read(self.address, &[OUTX_L_XL], &mut bytes)?;
+        Ok([0, 2, 4].map(|at| i32::from(i16::from_le_bytes([bytes[at], bytes[at + 1]])) * MICRO_G_PER_BIT / 1000))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// Registers of a chip, written ones are kept
+    #[derive(Clone, Copy)]
+    struct FakeBus {
+        registers: [u8; 0x30],
+    }
+
+    impl Write for FakeBus {
+        type Error = ();
+
+        fn write(&mut self, _address: u8, bytes: &[u8]) -> Result<(), ()> {
+            self.registers[usize::from(bytes[0])] = bytes[1];
+            Ok(())
+        }
+    }
+
+    impl WriteRead for FakeBus {
+        type Error = ();
+
+        fn write_read(&mut self, _address: u8, bytes: &[u8], buffer: &mut [u8]) -> Result<(), ()> {
+            let start = usize::from(bytes[0]);
+            buffer.copy_from_slice(&self.registers[start..start + buffer.len()]);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_lsm6ds3() {
+        let mut bus = FakeBus { registers: [0; 0x30] };
+        bus.registers[usize::from(WHO_AM_I)] = 0x33;
+        assert!(matches!(Lsm6ds3::new(bus, 0x6A), Err(ImuError::UnknownChip(0x33))));
+
+        bus.registers[usize::from(WHO_AM_I)] = 0x69;
+        // About 5 g to the side, full scale backwards and 1 g down
+        bus.registers[usize::from(OUTX_L_XL)..usize::from(OUTX_L_XL) + 6].copy_from_slice(&[0x00, 0x28, 0x00, 0x80, 0x01, 0x08]);
+        let mut imu = Lsm6ds3::new(bus, 0x6A).unwrap();
+        assert_eq!(imu.i2c.registers[usize::from(CTRL1_XL)], CTRL1_XL_104HZ_16G);
+        assert_eq!(imu.read(), Ok([4_997, -15_990, 999]));
+    }
+}
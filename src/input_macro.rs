@@ -0,0 +1,346 @@
+//! Button macros
+//!
+//! A developer mode to record the button presses with the time between them and replay them,
+//! for UI bug reports anyone can reproduce and for UI regression runs on the device:
+//!
+//! - `macro record` starts a new recording, `macro stop` ends it or the replay
+//! - `macro play` replays the recording with the same timing
+//! - `macro export` prints the recording as a base64 line, `macro import <base64>` loads one
+//! - `macro save` keeps the recording in flash, `macro load` loads it again
+//! - `macro` alone shows what is recorded and what is running
+//!
+//! Replayed presses go through the key bindings of the page that is shown, like real ones, so a
+//! recording only reproduces a bug when it starts on the same page with the same bindings. Only
+//! presses that perform an action are recorded, holding a chord to lock the buttons isn't.
+//! A `ButtonMacro` serializes like the settings, in flash it is a `Storage` record in the
+//! `MACRO_SECTORS` sectors at `MACRO_START`, with the `logbook` feature. The firmware loads it at
+//! boot, so the scripted dive of the instructor replays it without a console.
+
+use core::fmt;
+
+#[cfg(feature = "logbook")]
+use embedded_storage::nor_flash::NorFlash;
+use fugit::MicrosDurationU64;
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(test))]
+use crate::info;
+#[cfg(test)]
+use log::info;
+
+#[cfg(feature = "logbook")]
+use crate::storage::Storage;
+use crate::{
+    clock::Instant,
+    keymap::{Button, Press},
+};
+
+/// Offset of the flash `macro save` writes to, below the scratch flash of the console
+pub const MACRO_START: u32 = 0x1F_4000;
+
+/// Sectors of the flash `macro save` writes to
+pub const MACRO_SECTORS: u32 = 2;
+
+/// Most presses in a recording
+pub const MAX_PRESSES: usize = 20;
+
+/// Longest wait before a press, longer pauses are cut short
+pub const MAX_DELAY_MS: u16 = 60_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordedPress {
+    pub button: Button,
+    /// The other button of a chord
+    pub second: Option<Button>,
+    pub press: Press,
+    /// Since the previous press, or the start of the recording
+    pub delay_ms: u16,
+}
+
+impl RecordedPress {
+    const EMPTY: RecordedPress = RecordedPress {
+        button: Button::A,
+        second: None,
+        press: Press::Tap,
+        delay_ms: 0,
+    };
+}
+
+/// Recorded presses in order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ButtonMacro {
+    presses: [RecordedPress; MAX_PRESSES],
+    len: u8,
+}
+
+impl ButtonMacro {
+    pub const fn new() -> Self {
+        ButtonMacro {
+            presses: [RecordedPress::EMPTY; MAX_PRESSES],
+            len: 0,
+        }
+    }
+
+    pub fn presses(&self) -> &[RecordedPress] {
+        &self.presses[..usize::from(self.len).min(MAX_PRESSES)]
+    }
+
+    /// Add `press`, returns false when the recording is full
+    pub fn push(&mut self, press: RecordedPress) -> bool {
+        let Some(slot) = self.presses.get_mut(usize::from(self.len)) else {
+            return false;
+        };
+        *slot = press;
+        self.len += 1;
+        true
+    }
+
+    /// Whether an imported recording is one `push` could have made
+    pub fn is_valid(&self) -> bool {
+        usize::from(self.len) <= MAX_PRESSES && self.presses().iter().all(|press| press.delay_ms <= MAX_DELAY_MS)
+    }
+}
+
+impl Default for ButtonMacro {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Flash a recording is kept in
+pub trait MacroStore {
+    /// Replace the kept recording, returns whether it was written
+    fn save_macro(&mut self, recording: &ButtonMacro) -> bool;
+
+    /// The kept recording, `None` when there is none or it doesn't read back
+    fn load_macro(&mut self) -> Option<ButtonMacro>;
+}
+
+#[cfg(feature = "logbook")]
+impl<F: NorFlash> MacroStore for Storage<F> {
+    fn save_macro(&mut self, recording: &ButtonMacro) -> bool {
+        self.store(recording).is_ok()
+    }
+
+    fn load_macro(&mut self) -> Option<ButtonMacro> {
+        self.load::<ButtonMacro>().ok().flatten().filter(ButtonMacro::is_valid)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MacroCommand<'a> {
+    Record,
+    Stop,
+    Play,
+    Export,
+    /// Base64 line from an export
+    Import(&'a str),
+    Save,
+    Load,
+    Status,
+}
+
+impl<'a> MacroCommand<'a> {
+    /// Parse the words after `macro`, `None` for unknown commands
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::input_macro::MacroCommand;
+    /// assert_eq!(MacroCommand::parse("play".split_whitespace()), Some(MacroCommand::Play));
+    /// assert_eq!(MacroCommand::parse("".split_whitespace()), Some(MacroCommand::Status));
+    /// assert!(MacroCommand::parse("import".split_whitespace()).is_none());
+    /// ```
+    ///
+    pub fn parse(mut words: impl Iterator<Item = &'a str>) -> Option<Self> {
+        let command = match words.next() {
+            None => MacroCommand::Status,
+            Some("record") => MacroCommand::Record,
+            Some("stop") => MacroCommand::Stop,
+            Some("play") => MacroCommand::Play,
+            Some("export") => MacroCommand::Export,
+            Some("import") => MacroCommand::Import(words.next()?),
+            Some("save") => MacroCommand::Save,
+            Some("load") => MacroCommand::Load,
+            Some(_) => return None,
+        };
+        words.next().is_none().then_some(command)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MacroState {
+    Idle,
+    Recording,
+    /// Index of the next press
+    Playing(usize),
+}
+
+/// Records the presses and replays them
+#[derive(Debug, Clone, Copy)]
+pub struct MacroRecorder {
+    recording: ButtonMacro,
+    state: MacroState,
+    /// Time of the previous press, recorded or replayed, or of the start
+    last: Instant,
+}
+
+impl MacroRecorder {
+    pub const fn new() -> Self {
+        MacroRecorder {
+            recording: ButtonMacro::new(),
+            state: MacroState::Idle,
+            last: Instant::from_ticks(0),
+        }
+    }
+
+    pub fn recording(&self) -> &ButtonMacro {
+        &self.recording
+    }
+
+    pub fn is_playing(&self) -> bool {
+        matches!(self.state, MacroState::Playing(_))
+    }
+
+    /// Replace the recording, e.g. with one from the console or flash
+    pub fn load(&mut self, recording: ButtonMacro) {
+        self.recording = recording;
+        self.state = MacroState::Idle;
+    }
+
+    /// Start a new recording at `now`
+    pub fn record(&mut self, now: Instant) {
+        info!("macro recording");
+        self.recording = ButtonMacro::new();
+        self.state = MacroState::Recording;
+        self.last = now;
+    }
+
+    /// Replay the recording from `now`
+    pub fn play(&mut self, now: Instant) {
+        info!("macro playing");
+        self.state = MacroState::Playing(0);
+        self.last = now;
+    }
+
+    pub fn stop(&mut self) {
+        self.state = MacroState::Idle;
+    }
+
+    /// A press that performed an action at `now`, kept while recording
+    pub fn pressed(&mut self, now: Instant, button: Button, second: Option<Button>, press: Press) {
+        if self.state != MacroState::Recording {
+            return;
+        }
+
+        let delay = now.checked_duration_since(self.last).unwrap_or(MicrosDurationU64::micros(0));
+        let delay_ms = delay.to_millis().min(u64::from(MAX_DELAY_MS)) as u16;
+        if !self.recording.push(RecordedPress { button, second, press, delay_ms }) {
+            info!("macro full");
+            self.state = MacroState::Idle;
+        }
+        self.last = now;
+    }
+
+    /// Replayed press due at `now`, the replay ends after the last one
+    pub fn poll(&mut self, now: Instant) -> Option<RecordedPress> {
+        let MacroState::Playing(index) = self.state else {
+            return None;
+        };
+        let Some(&press) = self.recording.presses().get(index) else {
+            self.state = MacroState::Idle;
+            return None;
+        };
+
+        // From when the previous press was due, so late polls don't add up
+        let due = self.last + MicrosDurationU64::millis(u64::from(press.delay_ms));
+        if now < due {
+            return None;
+        }
+        self.last = due;
+        self.state = match index + 1 < self.recording.presses().len() {
+            true => MacroState::Playing(index + 1),
+            false => MacroState::Idle,
+        };
+        Some(press)
+    }
+}
+
+impl Default for MacroRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for MacroRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let presses = self.recording.presses().len();
+        match self.state {
+            MacroState::Idle => write!(f, "MACRO: {} PRESSES", presses),
+            MacroState::Recording => write!(f, "MACRO: RECORDING {}/{}", presses, MAX_PRESSES),
+            MacroState::Playing(index) => write!(f, "MACRO: PLAYING {}/{}", index, presses),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[cfg(feature = "logbook")]
+    use crate::storage::test::RamFlash;
+
+    #[test]
+    fn test_record_and_play() {
+        let at = |millis: u64| Instant::from_ticks(millis * 1_000);
+        let mut recorder = MacroRecorder::new();
+
+        // Presses before the recording starts are left out
+        recorder.pressed(at(0), Button::A, None, Press::Tap);
+        recorder.record(at(1_000));
+        recorder.pressed(at(1_500), Button::X, None, Press::Tap);
+        recorder.pressed(at(1_700), Button::X, None, Press::Hold);
+        recorder.pressed(at(100_000), Button::A, Some(Button::B), Press::Tap);
+        assert_eq!(format!("{}", recorder), "MACRO: RECORDING 3/20");
+        recorder.stop();
+        let delays: Vec<u16> = recorder.recording().presses().iter().map(|press| press.delay_ms).collect();
+        assert_eq!(delays, [500, 200, MAX_DELAY_MS]);
+
+        // The same timing from the start of the replay, a late poll doesn't delay the rest
+        recorder.play(at(200_000));
+        assert_eq!(recorder.poll(at(200_499)), None);
+        assert_eq!(recorder.poll(at(200_600)).map(|press| press.press), Some(Press::Tap));
+        assert_eq!(recorder.poll(at(200_700)).map(|press| press.press), Some(Press::Hold));
+        assert_eq!(format!("{}", recorder), "MACRO: PLAYING 2/3");
+        assert_eq!(recorder.poll(at(260_699)), None);
+        assert_eq!(recorder.poll(at(260_700)).and_then(|press| press.second), Some(Button::B));
+        assert!(!recorder.is_playing());
+        assert_eq!(format!("{}", recorder), "MACRO: 3 PRESSES");
+
+        // A full recording stops
+        recorder.record(at(300_000));
+        for _ in 0..=MAX_PRESSES {
+            recorder.pressed(at(300_000), Button::B, None, Press::Tap);
+        }
+        assert_eq!(format!("{}", recorder), "MACRO: 20 PRESSES");
+        assert!(recorder.recording().is_valid());
+    }
+
+    #[cfg(feature = "logbook")]
+    #[test]
+    fn test_store() {
+        let mut storage = Storage::mount(RamFlash::new(None), 0, 2).unwrap();
+        assert_eq!(storage.load_macro(), None);
+
+        let mut recording = ButtonMacro::new();
+        recording.push(RecordedPress {
+            button: Button::X,
+            second: None,
+            press: Press::Hold,
+            delay_ms: 1_200,
+        });
+        assert!(storage.save_macro(&recording));
+        assert_eq!(storage.load_macro(), Some(recording));
+    }
+}
@@ -0,0 +1,264 @@
+//! Instructor broadcast mode
+//!
+//! In a class one kit is the instructor unit, built with the `instructor` feature. Its buddy
+//! UART TX drives a bus to the RX of every student kit, directly for a few kits on a table or
+//! through RS-485 transceivers for longer runs, and instead of buddy frames it sends scenario
+//! commands for what the instructor does on it:
+//!
+//! - changing the exertion sets the current the students swim against
+//! - arming a failure on the failures page injects it with the same delay, stopping it stops it
+//! - playing the button macro starts the scripted dive, the macro loaded on each student kit
+//!
+//! The commands go to the student of the bus address in the diver settings, or to all of them
+//! when it is off. A student kit with a bus address listens to the bus instead of a buddy and
+//! takes the commands to all students and to its own address.
+
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(test))]
+use crate::info;
+#[cfg(test)]
+use log::info;
+
+use crate::{
+    failure::Failure,
+    gas::Exertion,
+    telemetry::{encode_frame, FrameError, FrameReader, MAX_FRAME_LEN},
+};
+
+/// Highest address of a student on the bus
+pub const MAX_STUDENTS: u8 = 8;
+
+/// Bus address of a kit in the settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum BusAddress {
+    /// A student kit talks to its buddy, the instructor unit sends to all students
+    #[default]
+    Off,
+    /// From 1 to `MAX_STUDENTS`
+    Student(u8),
+}
+
+impl BusAddress {
+    pub fn next(self) -> Self {
+        match self {
+            BusAddress::Off => BusAddress::Student(1),
+            BusAddress::Student(student) if student < MAX_STUDENTS => BusAddress::Student(student + 1),
+            BusAddress::Student(_) => BusAddress::Off,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        const STUDENTS: [&str; MAX_STUDENTS as usize] = ["1", "2", "3", "4", "5", "6", "7", "8"];
+        match self {
+            BusAddress::Off => "OFF",
+            BusAddress::Student(student) => STUDENTS.get(usize::from(*student).wrapping_sub(1)).copied().unwrap_or("?"),
+        }
+    }
+
+    /// Students the instructor unit sends to
+    pub fn target(&self) -> Address {
+        match *self {
+            BusAddress::Off => Address::All,
+            BusAddress::Student(student) => Address::Student(student),
+        }
+    }
+}
+
+/// Students a command is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Address {
+    All,
+    Student(u8),
+}
+
+impl Address {
+    /// Whether a kit at `bus` takes a command for these students
+    pub fn includes(&self, bus: BusAddress) -> bool {
+        match (self, bus) {
+            (_, BusAddress::Off) => false,
+            (Address::All, _) => true,
+            (Address::Student(student), BusAddress::Student(own)) => *student == own,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScenarioCommand {
+    /// Breathe as swimming against a current that takes this exertion
+    SetCurrent(Exertion),
+    /// Arm `failure` to strike after `delay_min` minutes
+    InjectFailure { failure: Failure, delay_min: u8 },
+    /// Stop the injected failure
+    StopFailure,
+    /// Play the button macro loaded on the student kit
+    StartScriptedDive,
+}
+
+/// A command and the students it is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstructorFrame {
+    pub to: Address,
+    pub command: ScenarioCommand,
+}
+
+impl InstructorFrame {
+    /// Encode as a frame into `buf`, returns the bytes to send
+    pub fn encode<'a>(&self, buf: &'a mut [u8; MAX_FRAME_LEN]) -> Result<&'a [u8], FrameError> {
+        encode_frame(self, buf)
+    }
+}
+
+/// What the instructor set up on the instructor unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Scenario {
+    pub exertion: Exertion,
+    /// Failure armed or struck, with its delay in minutes
+    pub failure: Option<(Failure, u32)>,
+    /// The button macro is playing
+    pub playing: bool,
+}
+
+/// Turns the changes of the scenario on the instructor unit into commands
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Broadcaster {
+    last: Option<Scenario>,
+}
+
+impl Broadcaster {
+    pub const fn new() -> Self {
+        Broadcaster { last: None }
+    }
+
+    /// Commands for what changed since the last call, the first call only takes the scenario in
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{failure::Failure, gas::Exertion, instructor::{Broadcaster, Scenario, ScenarioCommand}};
+    /// let mut broadcaster = Broadcaster::new();
+    /// let scenario = Scenario { exertion: Exertion::Normal, failure: None, playing: false };
+    /// assert_eq!(broadcaster.changes(scenario).count(), 0);
+    ///
+    /// let armed = Scenario { failure: Some((Failure::AirLoss, 5)), ..scenario };
+    /// let commands: Vec<_> = broadcaster.changes(armed).collect();
+    /// assert_eq!(commands, [ScenarioCommand::InjectFailure { failure: Failure::AirLoss, delay_min: 5 }]);
+    /// ```
+    ///
+    pub fn changes(&mut self, scenario: Scenario) -> impl Iterator<Item = ScenarioCommand> {
+        let last = self.last.replace(scenario).unwrap_or(scenario);
+
+        let current = (scenario.exertion != last.exertion).then_some(ScenarioCommand::SetCurrent(scenario.exertion));
+        let failure = match (last.failure, scenario.failure) {
+            (last, Some((failure, delay_min))) if last != scenario.failure => Some(ScenarioCommand::InjectFailure {
+                failure,
+                delay_min: delay_min.min(u32::from(u8::MAX)) as u8,
+            }),
+            (Some(_), None) => Some(ScenarioCommand::StopFailure),
+            _ => None,
+        };
+        let dive = (scenario.playing && !last.playing).then_some(ScenarioCommand::StartScriptedDive);
+        [current, failure, dive].into_iter().flatten()
+    }
+}
+
+/// Receiving end of the bus on a student kit
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StudentLink {
+    reader: FrameReader<InstructorFrame>,
+}
+
+impl StudentLink {
+    pub const fn new() -> Self {
+        StudentLink { reader: FrameReader::new() }
+    }
+
+    /// Handle a received `byte`, returns the command it ends when it is for the student at `bus`
+    pub fn receive(&mut self, byte: u8, bus: BusAddress) -> Option<ScenarioCommand> {
+        // A damaged frame is lost, the instructor sees on the class whether it took
+        let frame = self.reader.push(byte)?.ok()?;
+        if !frame.to.includes(bus) {
+            return None;
+        }
+        info!("instructor command received");
+        Some(frame.command)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_broadcast_to_students() {
+        let mut broadcaster = Broadcaster::new();
+        let scenario = Scenario {
+            exertion: Exertion::Normal,
+            failure: None,
+            playing: false,
+        };
+        assert_eq!(broadcaster.changes(scenario).count(), 0);
+        let busy = Scenario {
+            exertion: Exertion::Working,
+            failure: Some((Failure::StuckSensor, 0)),
+            playing: true,
+        };
+        let commands: Vec<_> = broadcaster.changes(busy).collect();
+        assert_eq!(
+            commands,
+            [
+                ScenarioCommand::SetCurrent(Exertion::Working),
+                ScenarioCommand::InjectFailure {
+                    failure: Failure::StuckSensor,
+                    delay_min: 0
+                },
+                ScenarioCommand::StartScriptedDive,
+            ]
+        );
+        assert_eq!(broadcaster.changes(busy).count(), 0);
+        let commands: Vec<_> = broadcaster.changes(scenario).collect();
+        assert_eq!(commands, [ScenarioCommand::SetCurrent(Exertion::Normal), ScenarioCommand::StopFailure]);
+
+        // Every command fits a frame
+        let mut stream = Vec::new();
+        for (to, command) in [
+            (Address::All, ScenarioCommand::SetCurrent(Exertion::Rest)),
+            (
+                Address::Student(MAX_STUDENTS),
+                ScenarioCommand::InjectFailure {
+                    failure: Failure::DeadBattery,
+                    delay_min: u8::MAX,
+                },
+            ),
+            (Address::Student(2), ScenarioCommand::StartScriptedDive),
+        ] {
+            let mut buf = [0; MAX_FRAME_LEN];
+            stream.extend_from_slice(InstructorFrame { to, command }.encode(&mut buf).unwrap());
+        }
+
+        let receive = |bus: BusAddress| {
+            let mut link = StudentLink::new();
+            stream.iter().filter_map(|&byte| link.receive(byte, bus)).collect::<Vec<_>>()
+        };
+        assert_eq!(receive(BusAddress::Off), []);
+        assert_eq!(receive(BusAddress::Student(1)), [ScenarioCommand::SetCurrent(Exertion::Rest)]);
+        assert_eq!(
+            receive(BusAddress::Student(MAX_STUDENTS)),
+            [
+                ScenarioCommand::SetCurrent(Exertion::Rest),
+                ScenarioCommand::InjectFailure {
+                    failure: Failure::DeadBattery,
+                    delay_min: u8::MAX
+                },
+            ]
+        );
+
+        let mut bus = BusAddress::Off;
+        for _ in 0..=MAX_STUDENTS {
+            bus = bus.next();
+        }
+        assert_eq!(bus, BusAddress::Off);
+        assert_eq!(BusAddress::Student(MAX_STUDENTS).as_str(), "8");
+    }
+}
@@ -0,0 +1,194 @@
+//! Analog joystick
+//!
+//! Some Explorer add-ons have a 5-way analog joystick: two potentiometers on ADC 0 and 1 for the
+//! axes and a push button pulling ADC 2 low. Every poll reads all three, a direction only counts
+//! once `DEBOUNCE_SAMPLES` polls in a row agree, and it is reported once until the stick returns
+//! to the center. The rtic firmware uses it next to the buttons with the `joystick` feature.
+//!
+//! The `thermistor` goes on ADC 2 too, so the firmware with both features tells by the reading
+//! which one is attached. The button reads at one of the rails, a thermistor in between, and
+//! while ADC 2 reads in between the joystick is taken as not attached and reports nothing.
+
+use crate::{keymap::Action, ui::Page};
+
+/// Polls in a row that have to agree on a direction
+pub const DEBOUNCE_SAMPLES: u8 = 3;
+
+/// Thresholds on the raw 12-bit ADC readings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoystickConfig {
+    /// Reading of a centered axis
+    pub center: u16,
+    /// Distance from the center an axis has to move to count
+    pub dead_zone: u16,
+    /// The button reads below this when pressed
+    pub press_below: u16,
+    /// The button reads above this when released, readings in between are no joystick
+    pub release_above: u16,
+}
+
+impl JoystickConfig {
+    pub const fn new() -> Self {
+        JoystickConfig {
+            center: 2048,
+            dead_zone: 1024,
+            press_below: 512,
+            release_above: 3800,
+        }
+    }
+}
+
+impl Default for JoystickConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    Press,
+}
+
+impl Direction {
+    /// What the direction does on `page`, right always goes to the next page
+    pub fn action(&self, page: Page) -> Action {
+        match (page, self) {
+            (_, Direction::Right) => Action::NextPage,
+            (Page::Apnea | Page::Signal | Page::Failures, Direction::Up) => Action::StartTimer,
+            (Page::Planner, Direction::Up) => Action::ReadyAlarm,
+            (Page::Settings | Page::Blending, Direction::Up) => Action::None,
+            (Page::Settings | Page::Planner | Page::Apnea | Page::Signal | Page::Failures | Page::Blending, Direction::Down) => Action::SelectItem,
+            (Page::Settings, Direction::Left) => Action::SelectSection,
+            (Page::Settings | Page::Planner | Page::Apnea | Page::Signal | Page::Failures | Page::Blending, Direction::Press) => Action::ChangeItem,
+            (Page::SelfTest, Direction::Press) => Action::SelfTest,
+            (_, Direction::Up) => Action::IncreaseRate,
+            (_, Direction::Down) => Action::DecreaseRate,
+            (_, Direction::Left) => Action::Help,
+            (_, Direction::Press) => Action::Mark,
+        }
+    }
+}
+
+/// Debounces joystick readings into directions
+#[derive(Debug, Clone, Copy)]
+pub struct Joystick {
+    config: JoystickConfig,
+    /// Direction of the last polls and how many agreed
+    candidate: Option<Direction>,
+    agreeing: u8,
+    /// Direction that was reported last, `None` once centered
+    reported: Option<Direction>,
+}
+
+impl Joystick {
+    pub const fn new(config: JoystickConfig) -> Self {
+        Joystick {
+            config,
+            candidate: None,
+            agreeing: 0,
+            reported: None,
+        }
+    }
+
+    /// Whether `button` reads like the joystick button, pressed or released
+    pub fn attached(&self, button: u16) -> bool {
+        button < self.config.press_below || button > self.config.release_above
+    }
+
+    /// Direction of a single reading, the button wins over the axes and the larger axis over the other
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::joystick::{Direction, Joystick, JoystickConfig};
+    /// let joystick = Joystick::new(JoystickConfig::new());
+    /// assert_eq!(joystick.direction(2048, 4000, 4095), Some(Direction::Up));
+    /// assert_eq!(joystick.direction(2100, 2000, 4095), None);
+    /// // A thermistor on ADC 2 instead of the button
+    /// assert_eq!(joystick.direction(2048, 4000, 2048), None);
+    /// ```
+    ///
+    pub fn direction(&self, x: u16, y: u16, button: u16) -> Option<Direction> {
+        if !self.attached(button) {
+            // The axes float without the joystick
+            return None;
+        }
+        if button < self.config.press_below {
+            return Some(Direction::Press);
+        }
+
+        let dx = x as i32 - self.config.center as i32;
+        let dy = y as i32 - self.config.center as i32;
+        let dead_zone = self.config.dead_zone as i32;
+        match (dx, dy) {
+            (dx, dy) if dx.abs() < dead_zone && dy.abs() < dead_zone => None,
+            (dx, dy) if dx.abs() > dy.abs() => Some(if dx > 0 { Direction::Right } else { Direction::Left }),
+            (_, dy) => Some(if dy > 0 { Direction::Up } else { Direction::Down }),
+        }
+    }
+
+    /// Handle one poll of the raw readings, returns a direction once it is stable
+    pub fn poll(&mut self, x: u16, y: u16, button: u16) -> Option<Direction> {
+        let direction = self.direction(x, y, button);
+        if direction == self.candidate {
+            self.agreeing = self.agreeing.saturating_add(1);
+        } else {
+            self.candidate = direction;
+            self.agreeing = 1;
+        }
+
+        if self.agreeing < DEBOUNCE_SAMPLES || self.candidate == self.reported {
+            return None;
+        }
+        self.reported = self.candidate;
+        self.reported
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_debounced_directions() {
+        let mut joystick = Joystick::new(JoystickConfig::new());
+        let center = (2048, 2048, 4095);
+        let left = (100, 2300, 4095);
+
+        // A single noisy poll doesn't count
+        assert_eq!(joystick.poll(left.0, left.1, left.2), None);
+        assert_eq!(joystick.poll(center.0, center.1, center.2), None);
+
+        let polls: Vec<_> = (0..5).map(|_| joystick.poll(left.0, left.1, left.2)).collect();
+        assert_eq!(polls, [None, None, Some(Direction::Left), None, None]);
+
+        // Back to the center before the next direction
+        for _ in 0..DEBOUNCE_SAMPLES {
+            assert_eq!(joystick.poll(center.0, center.1, center.2), None);
+        }
+        for _ in 1..DEBOUNCE_SAMPLES {
+            joystick.poll(left.0, left.1, 0);
+        }
+        assert_eq!(joystick.poll(left.0, left.1, 0), Some(Direction::Press));
+
+        // A thermistor on ADC 2 at 25 °C, whatever the floating axes read
+        let mut joystick = Joystick::new(JoystickConfig::new());
+        let polls: Vec<_> = (0..5).map(|_| joystick.poll(left.0, left.1, 2048)).collect();
+        assert_eq!(polls, [None; 5]);
+        assert!(!joystick.attached(2048));
+        assert!(joystick.attached(4095));
+
+        assert_eq!(Direction::Press.action(Page::Settings), Action::ChangeItem);
+        assert_eq!(Direction::Down.action(Page::Planner), Action::SelectItem);
+        assert_eq!(Direction::Up.action(Page::Apnea), Action::StartTimer);
+        assert_eq!(Direction::Press.action(Page::Blending), Action::ChangeItem);
+        assert_eq!(Direction::Up.action(Page::Signal), Action::StartTimer);
+        assert_eq!(Direction::Press.action(Page::SelfTest), Action::SelfTest);
+        assert_eq!(Direction::Down.action(Page::Main), Action::DecreaseRate);
+    }
+}
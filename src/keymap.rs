@@ -0,0 +1,282 @@
+//! Mapping of buttons to actions
+//!
+//! Every page has its own bindings for a tap and for holding each button, so left-handed users
+//! or exercises can remap the buttons from the settings page.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ui::{Page, PAGE_COUNT};
+
+/// Number of buttons on the Pico Explorer
+pub const BUTTON_COUNT: usize = 4;
+
+/// Number of ways to press a button
+pub const PRESS_COUNT: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Button {
+    A,
+    B,
+    X,
+    Y,
+}
+
+impl Button {
+    pub const ALL: [Button; BUTTON_COUNT] = [Button::A, Button::B, Button::X, Button::Y];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Button::A => "A",
+            Button::B => "B",
+            Button::X => "X",
+            Button::Y => "Y",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Press {
+    /// Button went down
+    Tap,
+    /// Button is held, repeats every `REPEAT_TIME`
+    Hold,
+}
+
+impl Press {
+    pub const ALL: [Press; PRESS_COUNT] = [Press::Tap, Press::Hold];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Press::Tap => "TAP",
+            Press::Hold => "HOLD",
+        }
+    }
+}
+
+/// What a button does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    None,
+    FillAir,
+    ToggleUnit,
+    IncreaseRate,
+    DecreaseRate,
+    Mark,
+    NextPage,
+    /// Start or stop simulating a free-flowing regulator
+    FreeFlow,
+    /// Settings and planner page: select the next item
+    SelectItem,
+    /// Settings and planner page: change the selected item
+    ChangeItem,
+    /// Settings page: edit the next section
+    SelectSection,
+    /// Show what the buttons do on the current page
+    Help,
+    /// Apnea and signal pages: start or stop the selected table or message
+    StartTimer,
+    /// Run the on-device self test and show its results
+    SelfTest,
+    /// Planner page: go through the targets of the next dive alarm
+    ReadyAlarm,
+    /// Breathe as at the next exertion level
+    Exertion,
+    /// Self test page: open the failure injection page
+    Failures,
+    /// Settings page: ask for a factory reset
+    FactoryReset,
+}
+
+impl Action {
+    /// Actions which can be bound from the settings page
+    pub const BINDABLE: [Action; 9] = [
+        Action::None,
+        Action::FillAir,
+        Action::ToggleUnit,
+        Action::IncreaseRate,
+        Action::DecreaseRate,
+        Action::Mark,
+        Action::NextPage,
+        Action::FreeFlow,
+        Action::Exertion,
+    ];
+
+    /// Bindable action after this one
+    pub fn next(self) -> Self {
+        let index = Self::BINDABLE.iter().position(|&action| action == self).map_or(0, |index| index + 1);
+        Self::BINDABLE[index % Self::BINDABLE.len()]
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::None => "NONE",
+            Action::FillAir => "FILL AIR",
+            Action::ToggleUnit => "UNIT",
+            Action::IncreaseRate => "RATE UP",
+            Action::DecreaseRate => "RATE DOWN",
+            Action::Mark => "MARK",
+            Action::NextPage => "NEXT PAGE",
+            Action::FreeFlow => "FREE FLOW",
+            Action::SelectItem => "NEXT ITEM",
+            Action::ChangeItem => "CHANGE",
+            Action::SelectSection => "SECTION",
+            Action::Help => "HELP",
+            Action::StartTimer => "START/STOP",
+            Action::SelfTest => "SELF TEST",
+            Action::ReadyAlarm => "READY ALARM",
+            Action::Exertion => "EXERTION",
+            Action::Failures => "FAILURES",
+            Action::FactoryReset => "RESET",
+        }
+    }
+}
+
+/// Actions of two buttons pressed together, on every page, they can't be remapped
+pub const CHORDS: [(Button, Button, Action); 3] = [
+    (Button::A, Button::B, Action::NextPage),
+    (Button::X, Button::Y, Action::Mark),
+    (Button::B, Button::Y, Action::Help),
+];
+
+/// Action of pressing `first` and `second` together
+pub fn chord_action(first: Button, second: Button) -> Action {
+    CHORDS
+        .iter()
+        .find(|&&(a, b, _)| (a, b) == (first, second) || (b, a) == (first, second))
+        .map_or(Action::None, |&(_, _, action)| action)
+}
+
+/// Action per page, button and press
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyBindings {
+    actions: [[[Action; PRESS_COUNT]; BUTTON_COUNT]; PAGE_COUNT],
+}
+
+impl KeyBindings {
+    pub const fn new() -> Self {
+        // Tap and hold do the same on the dive pages
+        const DIVE: [[Action; PRESS_COUNT]; BUTTON_COUNT] = [
+            [Action::FillAir, Action::FillAir],
+            [Action::ToggleUnit, Action::ToggleUnit],
+            [Action::IncreaseRate, Action::IncreaseRate],
+            [Action::DecreaseRate, Action::DecreaseRate],
+        ];
+        // Holding A on the diagnostics page simulates a free flow, holding B changes the exertion, holding Y runs the self test
+        const DIAGNOSTICS: [[Action; PRESS_COUNT]; BUTTON_COUNT] = [
+            [Action::FillAir, Action::FreeFlow],
+            [Action::ToggleUnit, Action::Exertion],
+            [Action::IncreaseRate, Action::IncreaseRate],
+            [Action::DecreaseRate, Action::SelfTest],
+        ];
+        // Tapping X sets the next dive alarm
+        const PLANNER: [[Action; PRESS_COUNT]; BUTTON_COUNT] = [
+            [Action::SelectItem, Action::SelectItem],
+            [Action::ChangeItem, Action::ChangeItem],
+            [Action::ReadyAlarm, Action::None],
+            [Action::None, Action::None],
+        ];
+        const APNEA: [[Action; PRESS_COUNT]; BUTTON_COUNT] = [
+            [Action::SelectItem, Action::SelectItem],
+            [Action::ChangeItem, Action::ChangeItem],
+            [Action::StartTimer, Action::None],
+            [Action::None, Action::None],
+        ];
+        const SIGNAL: [[Action; PRESS_COUNT]; BUTTON_COUNT] = APNEA;
+        // Holding Y asks for a factory reset
+        const SETTINGS: [[Action; PRESS_COUNT]; BUTTON_COUNT] = [
+            [Action::SelectItem, Action::SelectItem],
+            [Action::ChangeItem, Action::ChangeItem],
+            [Action::SelectSection, Action::None],
+            [Action::None, Action::FactoryReset],
+        ];
+        // Tapping A runs the self test again, holding X opens the failures page
+        const SELF_TEST: [[Action; PRESS_COUNT]; BUTTON_COUNT] = [
+            [Action::SelfTest, Action::None],
+            [Action::None, Action::None],
+            [Action::None, Action::Failures],
+            [Action::None, Action::None],
+        ];
+        // Tapping X arms or stops the failure
+        const FAILURES: [[Action; PRESS_COUNT]; BUTTON_COUNT] = APNEA;
+        const BLENDING: [[Action; PRESS_COUNT]; BUTTON_COUNT] = [
+            [Action::SelectItem, Action::SelectItem],
+            [Action::ChangeItem, Action::ChangeItem],
+            [Action::None, Action::None],
+            [Action::None, Action::None],
+        ];
+
+        KeyBindings {
+            actions: [
+                DIVE,
+                DIVE,
+                DIAGNOSTICS,
+                PLANNER,
+                APNEA,
+                SIGNAL,
+                SETTINGS,
+                SELF_TEST,
+                FAILURES,
+                BLENDING,
+                DIVE,
+                DIVE,
+                DIVE,
+            ],
+        }
+    }
+
+    pub fn action(&self, page: Page, button: Button, press: Press) -> Action {
+        self.actions[page as usize][button as usize][press as usize]
+    }
+
+    /// Bind `action`, the pages with menus and the self test page can't be changed so they can't lock themselves out
+    pub fn set(&mut self, page: Page, button: Button, press: Press, action: Action) {
+        if !matches!(
+            page,
+            Page::Planner | Page::Apnea | Page::Signal | Page::Settings | Page::SelfTest | Page::Failures | Page::Blending
+        ) {
+            self.actions[page as usize][button as usize][press as usize] = action;
+        }
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_remap_button() {
+        let mut bindings = KeyBindings::new();
+        assert_eq!(bindings.action(Page::Main, Button::A, Press::Tap), Action::FillAir);
+
+        // Left-handed: swap the rate buttons
+        bindings.set(Page::Main, Button::X, Press::Tap, Action::DecreaseRate);
+        bindings.set(Page::Main, Button::Y, Press::Tap, Action::IncreaseRate);
+        assert_eq!(bindings.action(Page::Main, Button::X, Press::Tap), Action::DecreaseRate);
+        assert_eq!(bindings.action(Page::Main, Button::X, Press::Hold), Action::IncreaseRate);
+        assert_eq!(bindings.action(Page::Warnings, Button::X, Press::Tap), Action::IncreaseRate);
+
+        bindings.set(Page::Settings, Button::A, Press::Tap, Action::None);
+        assert_eq!(bindings.action(Page::Settings, Button::A, Press::Tap), Action::SelectItem);
+
+        assert_eq!(Action::NextPage.next(), Action::FreeFlow);
+        assert_eq!(Action::FreeFlow.next(), Action::Exertion);
+        assert_eq!(bindings.action(Page::Diagnostics, Button::B, Press::Hold), Action::Exertion);
+        assert_eq!(bindings.action(Page::Diagnostics, Button::A, Press::Hold), Action::FreeFlow);
+        assert_eq!(bindings.action(Page::Diagnostics, Button::Y, Press::Hold), Action::SelfTest);
+        assert_eq!(bindings.action(Page::SelfTest, Button::X, Press::Hold), Action::Failures);
+        assert_eq!(bindings.action(Page::Failures, Button::X, Press::Tap), Action::StartTimer);
+        assert_eq!(bindings.action(Page::Planner, Button::X, Press::Tap), Action::ReadyAlarm);
+        assert_eq!(bindings.action(Page::Settings, Button::Y, Press::Hold), Action::FactoryReset);
+        assert_eq!(Page::Settings.next(), Page::Main);
+        assert_eq!(Action::SelectSection.next(), Action::None);
+        assert_eq!(chord_action(Button::Y, Button::B), Action::Help);
+        assert_eq!(chord_action(Button::A, Button::X), Action::None);
+    }
+}
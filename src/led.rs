@@ -0,0 +1,88 @@
+//! Blink codes on the LED of the Pico
+//!
+//! Without an alarm the LED shows the heartbeat of the screen task. During an alarm it blinks a
+//! code for the alarm instead, so it can be read from the back of the kit or with the screen
+//! off: fast blinks for the alarms that need action now, slow ones for the rest, and more blinks
+//! for a more severe alarm. Codes repeat every `CODE_INTERVAL` and are scheduled like the beeps.
+
+use fugit::MicrosDurationU64;
+
+use crate::{buzzer::bursting, clock::Instant, Alarm};
+
+/// Time from the start of one code to the start of the next
+pub const CODE_INTERVAL: MicrosDurationU64 = MicrosDurationU64::secs(3);
+
+/// Blinks of a code for one alarm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlinkCode {
+    pub count: u32,
+    pub fast: bool,
+}
+
+impl BlinkCode {
+    /// Code of `alarm`, `None` leaves the LED to the heartbeat
+    pub fn of(alarm: Alarm) -> Option<Self> {
+        let (count, fast) = match alarm {
+            Alarm::High => (3, true),
+            Alarm::AirCritical => (2, true),
+            Alarm::Medium => (3, false),
+            Alarm::AirReserve => (2, false),
+            Alarm::Low => (1, false),
+            Alarm::None => return None,
+        };
+        Some(BlinkCode { count, fast })
+    }
+
+    /// Time from the start of one blink to the next, a blink is lit for half of it
+    fn spacing(&self) -> MicrosDurationU64 {
+        match self.fast {
+            true => MicrosDurationU64::millis(200),
+            false => MicrosDurationU64::millis(600),
+        }
+    }
+
+    /// Whether the LED is lit at `now`
+    pub fn lit(&self, now: Instant) -> bool {
+        let spacing = self.spacing();
+        bursting(now, CODE_INTERVAL, self.count, spacing, spacing / 2)
+    }
+}
+
+/// Whether the LED is lit at `now` for `alarm`, `None` without an alarm
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::{clock::Instant, led::blinking, Alarm};
+///
+/// assert_eq!(blinking(Instant::from_ticks(450_000), Alarm::High), Some(true));
+/// assert_eq!(blinking(Instant::from_ticks(450_000), Alarm::Low), Some(false));
+/// assert_eq!(blinking(Instant::from_ticks(450_000), Alarm::None), None);
+/// ```
+///
+pub fn blinking(now: Instant, alarm: Alarm) -> Option<bool> {
+    BlinkCode::of(alarm).map(|code| code.lit(now))
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_blink_codes() {
+        // Rising edges in one code interval, and how long the LED is lit
+        let blinks = |alarm| {
+            let lit: Vec<bool> = (0..60).map(|tick| blinking(Instant::from_ticks(tick * 50_000), alarm) == Some(true)).collect();
+            let edges = lit.windows(2).filter(|pair| !pair[0] && pair[1]).count() + usize::from(lit[0]);
+            (edges, lit.iter().filter(|&&on| on).count() * 50)
+        };
+
+        assert_eq!(blinks(Alarm::High), (3, 300));
+        assert_eq!(blinks(Alarm::AirCritical), (2, 200));
+        assert_eq!(blinks(Alarm::Medium), (3, 900));
+        assert_eq!(blinks(Alarm::AirReserve), (2, 600));
+        assert_eq!(blinks(Alarm::Low), (1, 300));
+        assert_eq!(blinks(Alarm::None), (0, 0));
+    }
+}
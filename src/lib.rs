@@ -1,249 +1,2466 @@
 #![cfg_attr(not(test), no_std)]
 
-use core::{fmt, ops::Div};
+pub mod air_integration;
+pub mod alarm_history;
+pub mod apnea;
+pub mod ascent;
+pub mod audit;
+pub mod auto_page;
+pub mod battery;
+pub mod blending;
+pub mod buddy;
+pub mod budget;
+pub mod buttons;
+pub mod buzzer;
+pub mod cesa;
+pub mod checklist;
+pub mod clock;
+pub mod console;
+pub mod csv_stream;
+pub mod deco;
+pub mod depth_alert;
+pub mod diagnostics;
+#[cfg(feature = "logbook")]
+pub mod dive_log;
+pub mod experiment;
+pub mod factory_reset;
+pub mod failure;
+pub mod fault;
+pub mod flash;
+pub mod format;
+pub mod frame;
+pub mod gas;
+pub mod gas_switch;
+pub mod help;
+pub mod hypoxic;
+pub mod i2c_slave;
+pub mod imu;
+pub mod input_macro;
+pub mod instructor;
+pub mod joystick;
+pub mod keymap;
+pub mod led;
+pub mod lock;
+pub mod log_level;
+pub mod mark;
+pub mod morse;
+pub mod mqtt_sn;
+pub mod ndl_warning;
+pub mod next_dive;
+pub mod odometer;
+pub mod outputs;
+pub mod panic;
+pub mod peripherals;
+pub mod planner;
+#[cfg(feature = "logbook")]
+pub mod profile_log;
+pub mod rate_limit;
+pub mod render;
+pub mod replay;
+pub mod reserve;
+pub mod ring_buffer;
+pub mod safety_stop;
+pub mod sampler;
+pub mod screen_saver;
+pub mod self_test;
+pub mod sensor;
+pub mod settings;
+pub mod setup;
+pub mod shock;
+pub mod stops;
+#[cfg(feature = "logbook")]
+pub mod storage;
+pub mod strobe;
+pub mod surface;
+pub mod tech;
+pub mod telemetry;
+pub mod temperature;
+pub mod text_buffer;
+pub mod theme;
+pub mod trend;
+pub mod ui;
+pub mod units;
+pub mod violation;
+pub mod wall_clock;
+pub mod warm_boot;
+pub mod widgets;
 
-#[cfg(not(test))]
-use defmt::info;
-use fugit::{HertzU32, MicrosDurationU32, SecsDurationU64};
+#[cfg(test)]
+mod golden;
+#[cfg(test)]
+mod reference;
+
+use core::fmt;
+
+use fugit::{MicrosDurationU32, MicrosDurationU64, SecsDurationU32, SecsDurationU64};
 #[cfg(test)]
 use log::info;
-use num::FromPrimitive;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "logbook")]
+use crate::dive_log::{DiveLog, DiveLogger};
+use crate::{
+    air_integration::{tank_pressure_in_cb, ConsumptionEstimator, FillRate, TankSize, FILL_HOLD_TIME, FREE_FLOW_RATE_CL},
+    alarm_history::{AlarmEvent, AlarmHistory, Transition, ALARM_HISTORY_SIZE},
+    ascent::{AscentCoach, AscentLimit},
+    audit::{AuditEntry, AuditTrail, AUDIT_TRAIL_SIZE},
+    buddy::BuddyStatus,
+    budget::UiBuffer,
+    buzzer::{beeping, BEEP_LENGTH},
+    checklist::{ChecklistMode, ChecklistOutcome},
+    clock::{Clock, DriftCorrection, Instant, Rp2040Clock, TimeScale},
+    deco::{DecoModel, DefaultModel, Gas, GradientFactors, Stop, Zhl16Variant},
+    depth_alert::{DepthAlert, DepthAlerts, TOAST_TIME},
+    format::Digits,
+    gas::{gas_rate_in_cl, gas_to_surface_in_cl, Exertion, SURFACE_PRESSURE_CB},
+    gas_switch::DecoGases,
+    hypoxic::{HypoxicInterlock, Interlock},
+    keymap::{Action, Button},
+    led::blinking,
+    mark::{Mark, MARK_COUNT},
+    ndl_warning::{NdlCountdown, NdlWarnings},
+    odometer::{DiveProfile, DiveSummary, MIN_DIVE_DEPTH},
+    rate_limit::{RateLimit, RateLimiter},
+    replay::{Input, ReplayChecksum, Snapshot},
+    reserve::{Reserve, ReserveConfig},
+    ring_buffer::RingBuffer,
+    safety_stop::{SafetyStop, NDL_MARGIN},
+    sensor::Fault,
+    shock::SHOCK_WARNING_TIME,
+    strobe::{flashing, StrobeMode},
+    telemetry::Telemetry,
+    trend::{DepthDamping, RateSmoother, Trend},
+    units::{mm2ft, rate_in, AmbientPressure, Depth, DepthDisplay, Pressure},
+    violation::Lockout,
+    warm_boot::RetainedState,
+};
 
 const MAX_DEPTH: u32 = 40_000;
-/// Max safe ascend rate in mm per minute
-const MAX_SAFE_ASCEND_RATE: u32 = 15;
-const MAX_AIR: u32 = 2000 * 100;
-const AIR_INCREMENT: u32 = 500;
+/// Time the simulation advances per step
+pub const SIMULATION_STEP: MicrosDurationU32 = MicrosDurationU32::millis(100);
+/// Logic tick interval while diving
+pub const DIVE_TICK_INTERVAL: MicrosDurationU32 = MicrosDurationU32::millis(100);
+/// Logic tick interval at the surface
+pub const SURFACE_TICK_INTERVAL: MicrosDurationU32 = MicrosDurationU32::secs(1);
 
-#[derive(PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
 pub enum Unit {
+    #[default]
     Metric,
     Imperial,
+    /// Metric, with imperial in small text next to the depth and rate
+    Both,
 }
 
-impl fmt::Display for Unit {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let unit = match self {
+impl Unit {
+    pub fn next(self) -> Self {
+        match self {
+            Unit::Metric => Unit::Imperial,
+            Unit::Imperial => Unit::Both,
+            Unit::Both => Unit::Metric,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
             Unit::Imperial => "FT",
             Unit::Metric => "M",
-        };
+            Unit::Both => "M+FT",
+        }
+    }
+
+    /// Unit of the values in the page text
+    pub fn primary(self) -> Unit {
+        match self {
+            Unit::Both => Unit::Metric,
+            unit => unit,
+        }
+    }
+
+    /// Unit shown in small text next to the primary one
+    pub fn secondary(self) -> Option<Unit> {
+        match self {
+            Unit::Both => Some(Unit::Imperial),
+            _ => None,
+        }
+    }
+
+    /// Widths of the depth with its unit and of the rate value on the main page, narrower when
+    /// the secondary unit needs room next to them
+    fn field_widths(self) -> (usize, usize) {
+        match self {
+            Unit::Metric => (13, 11),
+            Unit::Imperial => (13, 10),
+            Unit::Both => (13 - SECONDARY_WIDTH, 11 - SECONDARY_WIDTH),
+        }
+    }
+}
+
+/// Characters of the page font given up for the secondary unit
+pub const SECONDARY_WIDTH: usize = 6;
+
+/// Depth and rate in the secondary unit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SecondaryReadings {
+    /// Depth in millimeters
+    pub depth: u32,
+    /// Rate in the secondary unit per minute
+    pub rate: i32,
+    pub unit: Unit,
+}
 
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // Write to buffer
-        write!(f, "{}", unit)
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// How the elapsed dive time is shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EdtFormat {
+    /// H:MM:SS
+    #[default]
+    HoursMinutesSeconds,
+    /// MMM:SS, the minutes keep counting past the hour
+    MinutesSeconds,
+}
+
+impl EdtFormat {
+    /// The other format
+    pub fn next(self) -> Self {
+        match self {
+            EdtFormat::HoursMinutesSeconds => EdtFormat::MinutesSeconds,
+            EdtFormat::MinutesSeconds => EdtFormat::HoursMinutesSeconds,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EdtFormat::HoursMinutesSeconds => "H:MM:SS",
+            EdtFormat::MinutesSeconds => "MMM:SS",
+        }
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Where the depth comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepthSource {
+    /// Changed with the rate buttons
+    Simulator,
+    /// Depth in millimeters measured by a pressure sensor
+    Sensor(u32),
+    /// The sensor broke, the simulator takes over from the last depth
+    Fault(Fault),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Alarm {
     High,
+    /// Tank pressure at the critical reserve
+    AirCritical,
     Medium,
+    /// Tank pressure at the reserve
+    AirReserve,
     Low,
     None,
 }
 
 impl Alarm {
-    pub fn display_len(&self) -> usize {
+    pub fn as_str(&self) -> &'static str {
         match self {
-            Alarm::High => 4,
-            Alarm::Medium => 6,
-            Alarm::Low => 3,
-            Alarm::None => 4,
+            Alarm::High => "HIGH",
+            Alarm::AirCritical => "CRIT",
+            Alarm::AirReserve => "RESERV",
+            Alarm::Medium => "MEDIUM",
+            Alarm::Low => "LOW",
+            Alarm::None => "NONE",
         }
     }
+
+    pub fn display_len(&self) -> usize {
+        self.as_str().len()
+    }
 }
 
 impl fmt::Display for Alarm {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let alarm = match self {
-            Alarm::High => "HIGH",
-            Alarm::Medium => "MEDIUM",
-            Alarm::Low => "LOW",
-            Alarm::None => "NONE",
-        };
-
         // Write to buffer
-        writeln!(f, "{:13}", alarm)
+        writeln!(f, "{:13}", self.as_str())
     }
 }
 
 // #[derive(Clone, Copy)]
-pub struct DiveComputer {
+pub struct DiveComputer<C: Clock = Rp2040Clock, M: DecoModel = DefaultModel> {
+    /// Time source for the logic tick
+    clock: C,
+    /// Time of the last logic tick
+    last_tick: Option<Instant>,
+    /// Drift of the clock, corrected in the time between ticks
+    clock_drift: DriftCorrection,
+    /// Simulated time per clock time
+    time_scale: TimeScale,
     /// Metric or imperial
     unit: Unit,
+    /// Depth or ambient pressure on the main page
+    depth_display: DepthDisplay,
     /// Depth in millimeters
     depth: u32,
     /// Rate in millimeter per minute
     rate: i32,
+    /// Rate the buttons set, the simulated rate follows it
+    rate_limiter: RateLimiter,
     /// Air in liters
     air: u32,
-    /// Elapsed Dive Time in seconds
-    edt: SecsDurationU64,
+    /// Elapsed Dive Time
+    edt: MicrosDurationU64,
+    edt_format: EdtFormat,
+    /// Simulation time not yet processed, in microseconds
+    pending_us: u32,
+    /// Depth change not yet applied, in 1/60_000 mm
+    depth_remainder: i64,
+    /// Gas used but not yet subtracted from air, in 1/1_000_000 cl
+    air_remainder: u64,
+    /// Alarm at the last tick
+    alarm: Alarm,
+    /// Last alarm transitions
+    alarm_history: AlarmHistory<ALARM_HISTORY_SIZE>,
+    /// Actions of the diver, for a review after the exercise
+    audit_trail: AuditTrail<AUDIT_TRAIL_SIZE>,
+    /// Bookmarks set during the dive
+    marks: RingBuffer<Mark, MARK_COUNT>,
+    /// Number of marks set during the dive
+    mark_count: u32,
+    /// Smoothed rate for the depth trend, and the depths for the damped readout
+    smoother: RateSmoother,
+    /// Averaging of the shown depth
+    depth_damping: DepthDamping,
+    /// Coaching based on the smoothed ascent rate
+    ascent: AscentCoach,
+    /// Decompression model
+    deco: M,
+    tank: TankSize,
+    /// Measured against modeled gas consumption
+    consumption: ConsumptionEstimator,
+    /// Simulated free-flowing regulator
+    free_flow: bool,
+    /// Scales the gas breathed and needed to reach the surface
+    exertion: Exertion,
+    /// Tank pressures for the reserve alarms
+    reserve_config: ReserveConfig,
+    /// Dive planning lockout after a missed stop
+    lockout: Lockout,
+    /// Profile of the current or last dive
+    profile: DiveProfile,
+    /// Dive that ended and wasn't taken yet
+    finished_dive: Option<DiveSummary>,
+    /// Most recent dive, for the surface page
+    last_dive: Option<DiveSummary>,
+    /// Samples and actions of the current dive
+    #[cfg(feature = "logbook")]
+    dive_log: DiveLogger,
+    /// Log of the dive that ended and wasn't taken yet
+    #[cfg(feature = "logbook")]
+    finished_log: Option<DiveLog>,
+    /// Log of the most recent dive, for the profile graph
+    #[cfg(feature = "logbook")]
+    last_log: Option<DiveLog>,
+    /// Time at the surface since the last dive
+    surface_interval: MicrosDurationU64,
+    depth_source: DepthSource,
+    /// Depths the diver wants to know about
+    depth_alerts: DepthAlerts,
+    /// Last depth alert crossed, and when
+    depth_alert: Option<(DepthAlert, Instant)>,
+    /// Gas breathed during the dive
+    back_gas: Gas,
+    /// Blocks the dive on a hypoxic back gas
+    hypoxic: HypoxicInterlock,
+    /// Carried deco gases
+    deco_gases: DecoGases,
+    /// Ascending alerts at the switch depths of `deco_gases`
+    gas_switches: DepthAlerts,
+    /// Percent oxygen of the last deco gas to switch to, and when
+    gas_switch: Option<(u8, Instant)>,
+    /// Warnings as the NDL runs out
+    ndl_countdown: NdlCountdown,
+    /// Recommended stop at the end of the current dive
+    safety_stop: SafetyStop,
+    /// Button that is stuck down
+    stuck_button: Option<Button>,
+    /// When the last shock was felt during a dive
+    shock: Option<Instant>,
+    /// Free air delivered by the compressor
+    fill_rate: FillRate,
+    /// Last fill while connected to the compressor
+    filling: Option<Instant>,
+    /// Air filled but not yet added, in 1/60_000_000 cl
+    fill_remainder: u64,
+    /// Buddy on the other end of the link, if any
+    buddy: Option<BuddyStatus>,
+    /// Checksum of the inputs since boot and the states they led to
+    replay: ReplayChecksum,
+    /// Water temperature in tenths of a degree Celsius, when a sensor measures it
+    temperature: Option<i16>,
+    /// Whether a checklist is shown before each dive
+    checklist_mode: ChecklistMode,
+    /// Checklist before the next or current dive
+    checklist: ChecklistOutcome,
 }
 
 impl DiveComputer {
     pub fn new() -> Self {
+        Self::with_clock(Rp2040Clock)
+    }
+}
+
+impl<C: Clock> DiveComputer<C> {
+    pub fn with_clock(clock: C) -> Self {
+        Self::with_model(clock, DefaultModel::default())
+    }
+}
+
+impl<C: Clock, M: DecoModel> DiveComputer<C, M> {
+    pub fn with_model(clock: C, deco: M) -> Self {
         DiveComputer {
+            clock,
+            last_tick: None,
+            clock_drift: DriftCorrection::new(),
+            time_scale: TimeScale::RealTime,
             unit: Unit::Metric,
+            depth_display: DepthDisplay::Depth,
             air: 5000,
             depth: 0,
-            edt: SecsDurationU64::secs(0),
+            edt: MicrosDurationU64::micros(0),
+            edt_format: EdtFormat::HoursMinutesSeconds,
+            pending_us: 0,
+            depth_remainder: 0,
+            air_remainder: 0,
             rate: 0,
+            rate_limiter: RateLimiter::new(RateLimit::Off),
+            alarm: Alarm::None,
+            alarm_history: AlarmHistory::new(),
+            audit_trail: AuditTrail::new(),
+            marks: RingBuffer::new(),
+            mark_count: 0,
+            smoother: RateSmoother::default(),
+            depth_damping: DepthDamping::Instant,
+            ascent: AscentCoach::new(),
+            deco,
+            tank: TankSize::L10,
+            consumption: ConsumptionEstimator::new(TankSize::L10),
+            free_flow: false,
+            exertion: Exertion::Normal,
+            reserve_config: ReserveConfig::new(),
+            lockout: Lockout::new(),
+            profile: DiveProfile::new(),
+            finished_dive: None,
+            last_dive: None,
+            #[cfg(feature = "logbook")]
+            dive_log: DiveLogger::new(),
+            #[cfg(feature = "logbook")]
+            finished_log: None,
+            #[cfg(feature = "logbook")]
+            last_log: None,
+            surface_interval: MicrosDurationU64::micros(0),
+            depth_source: DepthSource::Simulator,
+            depth_alerts: DepthAlerts::new(),
+            depth_alert: None,
+            back_gas: Gas::AIR,
+            hypoxic: HypoxicInterlock::new(),
+            deco_gases: DecoGases::new(),
+            gas_switches: DepthAlerts::new(),
+            gas_switch: None,
+            ndl_countdown: NdlCountdown::default(),
+            safety_stop: SafetyStop::new(),
+            stuck_button: None,
+            shock: None,
+            fill_rate: FillRate::L250,
+            filling: None,
+            fill_remainder: 0,
+            buddy: None,
+            replay: ReplayChecksum::new(),
+            temperature: None,
+            checklist_mode: ChecklistMode::Off,
+            checklist: ChecklistOutcome::None,
         }
     }
 
     fn get_alarm(&self) -> Alarm {
-        if gas_to_surface_in_cl(self.depth / 1000) > self.air {
+        let limit = self.ascent.limit().rate();
+        if self.exertion.scale(gas_to_surface_in_cl(self.depth / 1000, limit)) > self.air {
+            return Alarm::High;
+        }
+
+        if self.consumption.spiking() {
+            return Alarm::High;
+        }
+
+        let hypoxic = self.hypoxic.state(self.depth);
+        if hypoxic == Interlock::Blocked {
             return Alarm::High;
         }
 
-        if self.rate < -(MAX_SAFE_ASCEND_RATE as i32) {
+        let reserve = self.reserve();
+        if reserve == Reserve::Critical {
+            return Alarm::AirCritical;
+        }
+
+        let buddy_alarm = self.buddy.is_some_and(|buddy| buddy.alarming());
+        if self.rate < -(limit as i32) || self.sensor_fault().is_some() || buddy_alarm || hypoxic == Interlock::Ascended {
             return Alarm::Medium;
         }
 
-        if self.depth > MAX_DEPTH {
+        if reserve == Reserve::Warning {
+            return Alarm::AirReserve;
+        }
+
+        if self.depth > MAX_DEPTH || self.stuck_button.is_some() || self.shock_warning() {
             return Alarm::Low;
         }
 
         Alarm::None
     }
 
+    /// Connect the compressor at the surface, it fills at the fill rate until no fill followed
+    /// for `FILL_HOLD_TIME` or the tank is full
     pub fn fill_air(&mut self) {
         info!("Fill air");
 
-        if self.depth == 0 {
-            self.air += AIR_INCREMENT;
-            if self.air > MAX_AIR {
-                self.air = MAX_AIR;
-            }
+        if self.depth == 0 && self.air < self.tank.full_air_in_cl() {
+            self.filling = Some(self.clock.now());
         }
     }
 
+    /// Tank pressure in percent of a full tank while the compressor is connected
+    pub fn filling(&self) -> Option<u8> {
+        self.filling.map(|_| (self.air as u64 * 100 / self.tank.full_air_in_cl() as u64) as u8)
+    }
+
+    pub fn set_fill_rate(&mut self, fill_rate: FillRate) {
+        self.fill_rate = fill_rate;
+    }
+
+    /// Stop filling, e.g. when the fill button was released
+    fn disconnect_compressor(&mut self) {
+        self.filling = None;
+        self.fill_remainder = 0;
+    }
+
     pub fn increase_rate(&mut self) {
         info!("Increase dive rate");
 
-        self.rate += 1;
-        if self.rate > 50 {
-            self.rate = 50
-        }
+        self.change_rate(1);
     }
 
     pub fn decrease_rate(&mut self) {
         info!("Decrease dive rate");
 
         if self.depth > 0 {
-            self.rate -= 1;
-            if self.rate < -50 {
-                self.rate = -50
-            }
+            self.change_rate(-1);
+        }
+    }
+
+    /// Change the rate the simulated rate follows, without a limit the rate changes at once
+    fn change_rate(&mut self, change: i32) {
+        if self.rate_limiter.limit() == RateLimit::Off {
+            self.rate = (self.rate + change).clamp(-50, 50);
+            self.rate_limiter.set_target(self.rate);
+        } else {
+            self.rate_limiter.set_target((self.rate_limiter.target() + change).clamp(-50, 50));
+        }
+    }
+
+    pub fn set_rate_limit(&mut self, limit: RateLimit) {
+        match (self.rate_limiter.limit(), limit) {
+            (RateLimit::Off, RateLimit::Off) => {}
+            // Follow from the current rate
+            (RateLimit::Off, _) => self.rate_limiter.set_target(self.rate),
+            // Jump to where the rate was heading
+            (_, RateLimit::Off) => self.rate = self.rate_limiter.target(),
+            _ => {}
+        }
+        self.rate_limiter.set_limit(limit);
+    }
+
+    /// Rate in meters per minute the buttons set, the simulated rate gets there within the limit
+    pub fn target_rate(&self) -> i32 {
+        self.rate_limiter.target()
+    }
+
+    /// Handle a button action, actions which don't belong to the dive computer are ignored
+    pub fn perform(&mut self, action: Action) {
+        if action != Action::None {
+            self.record_action(action);
+        }
+        match action {
+            Action::FillAir => self.fill_air(),
+            Action::ToggleUnit => self.toggle_unit(),
+            Action::IncreaseRate => self.increase_rate(),
+            Action::DecreaseRate => self.decrease_rate(),
+            Action::Mark => self.mark(),
+            Action::FreeFlow => self.toggle_free_flow(),
+            Action::Exertion => self.set_exertion(self.exertion.next()),
+            _ => {}
+        }
+        self.record_replay(Input::Action(action));
+    }
+
+    /// Add `action` to the audit trail and the log of the dive, before it changes anything
+    fn record_action(&mut self, action: Action) {
+        // It takes effect at the next tick, so the last tick is when it happened, before the first the time is 0
+        let time: SecsDurationU64 = self
+            .last_tick
+            .map_or(SecsDurationU64::secs(0), |tick| self.clock_drift.correct(tick).duration_since_epoch().convert());
+        info!("Action {} at {}s, {}mm", action.as_str(), time.to_secs(), self.depth);
+        self.audit_trail.push(AuditEntry { action, time, depth: self.depth });
+        #[cfg(feature = "logbook")]
+        if self.diving() {
+            self.dive_log.record_action(action);
+        }
+    }
+
+    /// Fold `input` and the state it led to into the replay checksum
+    fn record_replay(&mut self, input: Input) {
+        let state = self.snapshot();
+        self.replay.record(input, &state);
+    }
+
+    /// State the replay checksum and the CSV stream follow
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            depth: self.depth,
+            rate: self.rate,
+            air: self.air,
+            edt: self.edt,
+            alarm: self.get_alarm(),
+        }
+    }
+
+    /// Continue the dive kept in `state` before a watchdog reset
+    ///
+    /// The tissues take up gas for the whole dive time at the kept depth, more than the real
+    /// profile could have, so the ceiling and the NDL are never more lenient than before the reset.
+    pub fn resume(&mut self, state: &RetainedState) {
+        info!("Resumed at {}mm after {}s", state.depth, state.edt.to_secs());
+        self.depth = state.depth;
+        self.rate = state.rate;
+        self.rate_limiter.set_target(state.rate);
+        self.air = state.air;
+        self.edt = state.edt;
+
+        // A minute at a time, a u32 in microseconds covers an hour at most
+        let mut left = state.edt.to_secs();
+        while left > 0 {
+            let step = left.min(60);
+            self.deco.tick(self.depth, MicrosDurationU32::secs(step as u32), self.back_gas);
+            left -= step;
         }
     }
 
+    /// Checksum of the actions and tick durations since boot and the states they led to
+    pub fn replay(&self) -> ReplayChecksum {
+        self.replay
+    }
+
+    /// Advance the simulation by the time since the last tick, times the time scale
+    pub fn tick(&mut self) {
+        let now = self.clock.now();
+
+        if let Some(last_tick) = self.last_tick {
+            // Both ends are corrected, so the rounding of the correction doesn't add up over the ticks
+            let corrected = self.clock_drift.correct(now).checked_duration_since(self.clock_drift.correct(last_tick));
+            // A u32 in microseconds covers more than an hour, far longer than any scaled tick interval
+            let elapsed = corrected.map_or(0, |corrected| corrected.to_micros()) * self.time_scale.factor() as u64;
+            let interval = MicrosDurationU32::micros(elapsed.min(u32::MAX as u64) as u32);
+            self.change_depth(interval);
+        }
+
+        self.last_tick = Some(now);
+    }
+
+    /// Advance the simulation by `interval`
+    ///
+    /// The simulation always moves in steps of `SIMULATION_STEP`, time left over is carried to
+    /// the next call. As long as the rate only changes between calls, the result only depends on
+    /// the total time and not on how often this is called.
     pub fn change_depth(&mut self, interval: MicrosDurationU32) {
         // Change depth based on rate
         info!("Change depth");
 
-        let hz: HertzU32 = interval.into_rate();
-        let rate_in_mm_per_interval = self.rate * 1000 / (60 * hz.raw() as i32);
+        let released = self
+            .filling
+            .is_some_and(|last| self.clock.now().checked_duration_since(last).is_some_and(|held| held > FILL_HOLD_TIME));
+        if released {
+            self.disconnect_compressor();
+        }
+
+        self.pending_us += interval.to_micros();
+        while self.pending_us >= SIMULATION_STEP.to_micros() {
+            self.pending_us -= SIMULATION_STEP.to_micros();
+            self.step();
+        }
+
+        self.update_alarm_history();
+        self.record_replay(Input::Tick(interval));
+    }
+
+    /// Advance the simulation by a single `SIMULATION_STEP`
+    fn step(&mut self) {
+        let step_us = SIMULATION_STEP.to_micros() as i64;
+
+        let previous_depth = self.depth;
+        let was_underwater = self.depth > 0;
+        if let DepthSource::Sensor(depth) = self.depth_source {
+            self.depth = depth;
+        } else {
+            self.rate = self.rate_limiter.follow(self.rate, step_us as u32);
+            // Rate is in m/min: mm = rate * 1000 * us / 60_000_000, keep the remainder for the next step
+            let depth_change = self.rate as i64 * step_us + self.depth_remainder;
+            self.depth_remainder = depth_change % 60_000;
+
+            self.depth = (self.depth as i64 + depth_change / 60_000).clamp(0, i32::MAX as i64) as u32;
+        }
 
-        self.depth = ((self.depth as i32) + rate_in_mm_per_interval).clamp(0, i32::MAX) as u32;
+        if let Some(index) = self.depth_alerts.crossed(previous_depth, self.depth) {
+            info!("Depth alert {} crossed at {}mm", index + 1, self.depth);
+            self.depth_alert = self.depth_alerts.alerts[index].map(|alert| (alert, self.clock.now()));
+        }
+        if let Some(index) = self.gas_switches.crossed(previous_depth, self.depth) {
+            info!("Gas switch {} reached at {}mm", index + 1, self.depth);
+            self.gas_switch = self.deco_gases.o2[index].map(|o2| (o2, self.clock.now()));
+        }
+        let was_clear = self.hypoxic.state(previous_depth) == Interlock::Clear;
+        self.hypoxic.update(self.depth);
+        if was_clear && self.hypoxic.state(self.depth) == Interlock::Ascended {
+            info!("Ascended above the minimum operating depth at {}mm", self.depth);
+        }
 
         if self.depth == 0 {
             // Reset rate since we can't ascend out of the water
             self.rate = 0;
+            if self.rate_limiter.target() < 0 {
+                self.rate_limiter.stop();
+            }
+            self.depth_remainder = 0;
+
+            let ceiling = self.deco.ceiling();
+            if was_underwater && ceiling > 0 {
+                self.missed_stop(ceiling);
+            }
+            if was_underwater && self.profile.max_depth() >= MIN_DIVE_DEPTH {
+                let dive = DiveSummary {
+                    deco_variant: self.deco.variant(),
+                    checklist: self.checklist,
+                    ..self.profile.summary()
+                };
+                info!("Dive ended, checklist {}", self.checklist.as_str());
+                self.finished_dive = Some(dive);
+                self.last_dive = Some(dive);
+                #[cfg(feature = "logbook")]
+                {
+                    let log = self.dive_log.finish();
+                    self.finished_log = Some(log);
+                    self.last_log = Some(log);
+                }
+                self.surface_interval = MicrosDurationU64::micros(0);
+            } else {
+                self.surface_interval += SIMULATION_STEP.convert();
+            }
+            if was_underwater {
+                // The next dive gets its own checklist
+                self.checklist = ChecklistOutcome::None;
+            }
+
+            if self.filling.is_some() {
+                // Fill rate is in l/min: cl = rate * 100 * us / 60_000_000, keep the remainder for the next step
+                let filled = self.fill_rate.liters_per_minute() as u64 * 100 * step_us as u64 + self.fill_remainder;
+                self.fill_remainder = filled % 60_000_000;
+                self.air = (self.air + (filled / 60_000_000) as u32).min(self.tank.full_air_in_cl());
+                if self.air == self.tank.full_air_in_cl() {
+                    info!("Tank full");
+                    self.disconnect_compressor();
+                }
+            }
         } else {
             // Underwater stuff
-            self.edt += interval.convert();
-            self.air = self.air.saturating_sub(gas_rate_in_cl(self.depth / 1000) / hz.raw());
+            self.disconnect_compressor();
+            if !was_underwater {
+                self.profile = DiveProfile::new();
+                #[cfg(feature = "logbook")]
+                {
+                    self.dive_log = DiveLogger::new();
+                }
+                self.safety_stop = SafetyStop::new();
+                self.marks.clear();
+                self.mark_count = 0;
+            }
+            self.profile.record(self.depth, self.deco.ceiling(), SIMULATION_STEP);
+            #[cfg(feature = "logbook")]
+            self.dive_log.record(self.depth, SIMULATION_STEP);
+            if let Some(temperature) = self.temperature {
+                self.profile.record_temperature(temperature);
+            }
+            self.edt += SIMULATION_STEP.convert();
+
+            // Gas rate is per second: cl = gas rate * us / 1_000_000, keep the remainder for the next step
+            let modeled_rate = self.exertion.scale(gas_rate_in_cl(self.depth / 1000));
+            let rate = if self.free_flow { modeled_rate + FREE_FLOW_RATE_CL } else { modeled_rate };
+            let gas_used = rate as u64 * step_us as u64 + self.air_remainder;
+            self.air_remainder = gas_used % 1_000_000;
+            self.air = self.air.saturating_sub((gas_used / 1_000_000) as u32);
+
+            let was_spiking = self.consumption.spiking();
+            self.consumption.push(self.tank_pressure(), modeled_rate, SIMULATION_STEP);
+            if self.consumption.spiking() && !was_spiking {
+                info!(
+                    "Consumption spike: {}% of the modeled consumption",
+                    self.consumption.consumption_percent().unwrap_or(0)
+                );
+            }
+        }
+
+        self.smoother.push(self.depth);
+        self.ascent.update(self.smoother.rate(), SIMULATION_STEP);
+
+        self.deco.tick(self.depth, SIMULATION_STEP, self.back_gas);
+        self.lockout.tick(SIMULATION_STEP);
+
+        if self.depth > 0 {
+            // Only deep enough to need the stop the no-decompression limit can be pushed
+            let near_ndl = self.depth > safety_stop::MIN_DEPTH && self.deco.ndl_within(NDL_MARGIN);
+            self.safety_stop.update(self.depth, near_ndl, SIMULATION_STEP);
+
+            if let Some(minutes) = self.ndl_countdown.update(|margin| self.deco.ndl_within(margin), || self.clock.now()) {
+                info!("NDL below {} minutes", minutes);
+            }
+        } else {
+            self.ndl_countdown.reset();
         }
     }
 
-    pub fn toggle_unit(&mut self) {
-        info!("Toggle measurement unit");
+    /// Surfaced with a `ceiling` in millimeters: lock dive planning and record it
+    fn missed_stop(&mut self, ceiling: u32) {
+        info!("Missed stop, ceiling {}mm", ceiling);
+        self.profile.miss_stop();
 
-        self.unit = if self.unit == Unit::Imperial { Unit::Metric } else { Unit::Imperial }
+        self.lockout.start();
+        self.alarm_history.push(AlarmEvent {
+            alarm: Alarm::High,
+            transition: Transition::MissedStop,
+            edt: self.edt.convert(),
+            depth: ceiling,
+        });
     }
-}
 
-impl Default for DiveComputer {
-    fn default() -> Self {
-        Self::new()
+    /// Recommended stop at the end of the current or last dive
+    pub fn safety_stop(&self) -> &SafetyStop {
+        &self.safety_stop
     }
-}
 
-impl fmt::Display for DiveComputer {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let depth = if self.unit == Unit::Imperial { mm2ft(self.depth) } else { self.depth / 1000 };
-        let rate = if self.unit == Unit::Imperial { mm2ft(self.rate * 1000) } else { self.rate };
+    /// Planning lockout after a missed stop
+    pub fn lockout(&self) -> &Lockout {
+        &self.lockout
+    }
 
-        let hours = self.edt.to_hours();
-        let minutes = self.edt.to_minutes();
-        let seconds = self.edt.to_secs();
+    /// Whether dives may be planned, not after a missed stop
+    pub fn planning_allowed(&self) -> bool {
+        !self.lockout.active()
+    }
 
-        // Write to buffer
-        writeln!(f, "DiveMaster")?;
-        writeln!(f)?;
-        writeln!(f, "DEPTH: {:width$}{}", depth, self.unit, width = if self.unit == Unit::Imperial { 11 } else { 12 })?;
-        writeln!(f, "RATE: {:width$}{}/M", rate, self.unit, width = if self.unit == Unit::Imperial { 10 } else { 11 })?;
-        writeln!(f, "AIR: {:14}L", self.air / 100)?;
-        writeln!(f, "EDT: {:9}:{:0>2}:{:0>2}", hours, minutes, seconds)?;
-        writeln!(f, "ALARM: {:width$}{}", "", self.get_alarm(), width = 13 - self.get_alarm().display_len())
+    /// Measured tank pressure in centibar, simulated from the air left
+    pub fn tank_pressure(&self) -> u32 {
+        tank_pressure_in_cb(self.air, self.tank)
+    }
+
+    /// Air reserve state, only while diving since the tank is filled at the surface
+    pub fn reserve(&self) -> Reserve {
+        if self.depth > 0 {
+            Reserve::for_pressure(self.tank_pressure(), &self.reserve_config)
+        } else {
+            Reserve::Ok
+        }
     }
-}
 
-const RESPIRATORY_MINUTE_VOLUME_CL: u32 = 1200;
-const RESPIRATORY_SECOND_VOLUME_CL: u32 = RESPIRATORY_MINUTE_VOLUME_CL / 60;
+    /// Tank pressures for the reserve alarms
+    pub fn set_reserve_config(&mut self, config: ReserveConfig) {
+        self.reserve_config = config;
+    }
 
-/// Calculate gas rate per second in centiliter for a depth in meters
-///
-/// # Examples
-///
-/// ```
-/// use dive_computer::gas_rate_in_cl;
-/// assert_eq!(gas_rate_in_cl(0), 20);
-/// assert_eq!(gas_rate_in_cl(10), 40);
-/// ```
-///
-pub fn gas_rate_in_cl(depth_in_m: u32) -> u32 {
-    /* 10m of water = 1 bar = 100 centibar */
-    let ambient_pressure_in_cb = 100 + (10 * depth_in_m);
+    /// Whether the buzzer should sound at `now`
+    pub fn buzzing(&self, now: Instant) -> bool {
+        let reserve = self.reserve().beep_interval().is_some_and(|interval| beeping(now, interval));
+        // A single chirp when a depth alert or a switch depth is crossed
+        let just = |at: Instant| now.checked_duration_since(at).is_some_and(|since| since < BEEP_LENGTH);
+        let chirp = self.depth_alert.is_some_and(|(_, at)| just(at)) || self.gas_switch.is_some_and(|(_, at)| just(at));
+        self.ascent.buzzing(now) || reserve || chirp || self.ndl_countdown.chiming(now)
+    }
 
-    /* Gas consumed at STP = RSV * ambient pressure / standard pressure */
-    (RESPIRATORY_SECOND_VOLUME_CL * ambient_pressure_in_cb) / 100
-}
+    /// Button that is stuck down, it raises a low alarm and a warning
+    pub fn set_stuck_button(&mut self, button: Option<Button>) {
+        if button != self.stuck_button {
+            if let Some(button) = button {
+                info!("Button {} stuck", button.as_str());
+            }
+            self.stuck_button = button;
+        }
+    }
 
-/// Calculate gas needed to reach the surface at max safe ascend rate
-///
-/// # Examples
-///
-/// ```
-/// use dive_computer::gas_to_surface_in_cl;
-/// assert_eq!(gas_to_surface_in_cl(0), 0);
-/// assert_eq!(gas_to_surface_in_cl(10), 1160);
-/// ```
-///
-pub fn gas_to_surface_in_cl(depth_in_m: u32) -> u32 {
-    let mut gas = 0;
-    let secs_to_ascend_1m = 60 / MAX_SAFE_ASCEND_RATE;
+    /// A shock of `peak` milli-g was felt, e.g. the tank knocked against something
+    ///
+    /// During a dive it goes into the alarm history and raises a low alarm with a reminder to
+    /// check the zero of the depth sensor for `SHOCK_WARNING_TIME`, at the surface it is ignored.
+    pub fn record_shock(&mut self, peak: u32) {
+        if self.depth == 0 {
+            return;
+        }
 
-    for depth in 0..depth_in_m {
-        gas += gas_rate_in_cl(depth) * secs_to_ascend_1m;
+        info!("Shock of {}mg at {}s, {}mm", peak, self.edt.to_secs(), self.depth);
+        self.alarm_history.push(AlarmEvent {
+            alarm: Alarm::Low,
+            transition: Transition::Shock,
+            edt: self.edt.convert(),
+            depth: self.depth,
+        });
+        self.shock = Some(self.clock.now());
     }
 
-    gas
-}
+    /// Whether a shock was felt in the last `SHOCK_WARNING_TIME`
+    pub fn shock_warning(&self) -> bool {
+        // Only reads the clock after a shock
+        self.shock
+            .is_some_and(|at| self.clock.now().checked_duration_since(at).is_some_and(|since| since < SHOCK_WARNING_TIME))
+    }
 
-fn mm2ft<T: Div<Output = T> + FromPrimitive>(depth: T) -> T {
-    depth / FromPrimitive::from_u32(305).unwrap()
-}
+    /// Latest state of the buddy, `None` without one
+    pub fn set_buddy(&mut self, buddy: Option<BuddyStatus>) {
+        if buddy.is_some_and(|buddy| buddy.alarming()) && !self.buddy.is_some_and(|buddy| buddy.alarming()) {
+            info!("Buddy needs attention");
+        }
+        self.buddy = buddy;
+    }
 
-#[cfg(test)]
-mod test {
+    pub fn buddy(&self) -> Option<BuddyStatus> {
+        self.buddy
+    }
 
-    use super::*;
-    use defmt::assert;
+    /// Water temperature in tenths of a degree Celsius, `None` without a working sensor
+    pub fn set_temperature(&mut self, temperature: Option<i16>) {
+        self.temperature = temperature;
+    }
 
-    #[test]
-    fn test_gas_rate_in_cl() {
-        assert!(true)
+    pub fn temperature(&self) -> Option<i16> {
+        self.temperature
+    }
+
+    /// State to send to the buddy
+    pub fn telemetry(&self) -> Telemetry {
+        Telemetry {
+            depth: self.depth,
+            pressure: (self.tank_pressure() / 100).min(u16::MAX as u32) as u16,
+            alarm: self.alarm,
+        }
+    }
+
+    /// Depths to alert on from now on
+    pub fn set_depth_alerts(&mut self, alerts: DepthAlerts) {
+        self.depth_alerts = alerts;
+    }
+
+    /// Gas breathed from now on, a hypoxic one blocks the dive until its minimum operating depth
+    pub fn set_back_gas(&mut self, gas: Gas) {
+        self.back_gas = gas;
+        self.hypoxic.set_gas(gas);
+    }
+
+    /// Minimum operating depth in millimeters while shallower than it on a hypoxic back gas
+    pub fn hypoxic_warning(&self) -> Option<u32> {
+        (self.hypoxic.state(self.depth) != Interlock::Clear).then(|| self.hypoxic.min_depth())
+    }
+
+    /// Deco gases to remind of at their switch depths from now on
+    pub fn set_deco_gases(&mut self, gases: DecoGases) {
+        self.deco_gases = gases;
+        self.gas_switches = gases.alerts();
+    }
+
+    /// Deco gas to switch to, as percent oxygen and switch depth in meters, for `TOAST_TIME` after reaching it
+    pub fn gas_switch_toast(&self) -> Option<(u8, u32)> {
+        // Only reads the clock after a switch depth
+        self.gas_switch
+            .filter(|(_, at)| self.clock.now().checked_duration_since(*at).is_some_and(|since| since < TOAST_TIME))
+            .map(|(o2, _)| (o2, gas_switch::switch_depth_m(o2)))
+    }
+
+    /// NDL thresholds to chime at from now on
+    pub fn set_ndl_warnings(&mut self, warnings: NdlWarnings) {
+        self.ndl_countdown.set_warnings(warnings);
+    }
+
+    /// Minutes of NDL left once it is below a warning threshold, `None` before and in deco
+    pub fn ndl_countdown(&self) -> Option<u32> {
+        let ndl = self.ndl_countdown.active().then(|| self.deco.ndl().to_minutes())?;
+        (ndl > 0).then_some(ndl)
+    }
+
+    /// Depth alert crossed in the last `TOAST_TIME`
+    pub fn depth_toast(&self) -> Option<DepthAlert> {
+        // Only reads the clock after an alert
+        self.depth_alert
+            .filter(|(_, at)| self.clock.now().checked_duration_since(*at).is_some_and(|since| since < TOAST_TIME))
+            .map(|(alert, _)| alert)
+    }
+
+    /// Whether the external strobe should be lit at `now`
+    pub fn strobing(&self, now: Instant, mode: StrobeMode) -> bool {
+        flashing(now, self.alarm, mode, self.depth == 0)
+    }
+
+    pub fn set_checklist_mode(&mut self, mode: ChecklistMode) {
+        self.checklist_mode = mode;
+    }
+
+    /// Whether descending should show the checklist first, it is shown once at the surface before each dive
+    pub fn checklist_due(&self) -> bool {
+        self.checklist_mode == ChecklistMode::On && !self.diving() && self.checklist == ChecklistOutcome::None
+    }
+
+    /// Outcome of the checklist before this dive, it is kept with the dive
+    pub fn set_checklist(&mut self, outcome: ChecklistOutcome) {
+        info!("Checklist {}", outcome.as_str());
+        self.checklist = outcome;
+    }
+
+    /// Whether the LED blinking the code of the alarm is lit at `now`, `None` leaves it to the heartbeat
+    pub fn blinking(&self, now: Instant) -> Option<bool> {
+        blinking(now, self.alarm)
+    }
+
+    /// Use a reading of the depth sensor from the next step on, a fault switches back to the simulator for good
+    pub fn update_sensor(&mut self, reading: Result<u32, Fault>) {
+        self.depth_source = match (self.depth_source, reading) {
+            (DepthSource::Fault(fault), _) => DepthSource::Fault(fault),
+            (_, Ok(depth)) => DepthSource::Sensor(depth),
+            (_, Err(fault)) => {
+                info!("Sensor fault: {}, falling back to the simulator", fault.as_str());
+                // Continue from the last depth without moving
+                self.rate = 0;
+                self.rate_limiter.stop();
+                self.depth_remainder = 0;
+                DepthSource::Fault(fault)
+            }
+        };
+    }
+
+    /// Go back to the simulator after readings were injected, from the last depth on
+    ///
+    /// Used when an injected stuck sensor is stopped, a fault stays.
+    pub fn release_sensor(&mut self) {
+        if let DepthSource::Sensor(_) = self.depth_source {
+            self.rate = 0;
+            self.rate_limiter.stop();
+            self.depth_remainder = 0;
+            self.depth_source = DepthSource::Simulator;
+        }
+    }
+
+    pub fn depth_source(&self) -> DepthSource {
+        self.depth_source
+    }
+
+    /// Fault of the depth sensor, if it broke
+    pub fn sensor_fault(&self) -> Option<Fault> {
+        match self.depth_source {
+            DepthSource::Fault(fault) => Some(fault),
+            _ => None,
+        }
+    }
+
+    /// Start or stop simulating a free-flowing regulator
+    pub fn toggle_free_flow(&mut self) {
+        self.free_flow = !self.free_flow;
+        info!("Free flow {}", if self.free_flow { "started" } else { "stopped" });
+    }
+
+    /// Lose `percent` of the air left at once, e.g. through a burst hose
+    pub fn lose_air(&mut self, percent: u32) {
+        let lost = (self.air as u64 * percent.min(100) as u64 / 100) as u32;
+        info!("Lost {}cl of air", lost);
+        self.air -= lost;
+    }
+
+    /// Breathe as at `exertion` from now on
+    pub fn set_exertion(&mut self, exertion: Exertion) {
+        self.exertion = exertion;
+        info!("Exertion {}", exertion.as_str());
+    }
+
+    pub fn exertion(&self) -> Exertion {
+        self.exertion
+    }
+
+    /// Interval at which the logic tick should run, slower when nothing happens at the surface to save power
+    pub fn tick_interval(&self) -> MicrosDurationU32 {
+        if self.depth == 0 && self.rate == 0 && self.filling.is_none() {
+            SURFACE_TICK_INTERVAL
+        } else {
+            DIVE_TICK_INTERVAL
+        }
+    }
+
+    /// Record the alarm transitions since the last tick
+    fn update_alarm_history(&mut self) {
+        let alarm = self.get_alarm();
+        if alarm == self.alarm {
+            return;
+        }
+
+        let mut record = |alarm: Alarm, transition| {
+            self.alarm_history.push(AlarmEvent {
+                alarm,
+                transition,
+                edt: self.edt.convert(),
+                depth: self.depth,
+            })
+        };
+
+        if self.alarm != Alarm::None {
+            info!("Alarm {} cleared", self.alarm.as_str());
+            record(self.alarm, Transition::Cleared);
+        }
+        if alarm != Alarm::None {
+            info!("Alarm {} raised", alarm.as_str());
+            record(alarm, Transition::Raised);
+        }
+
+        self.alarm = alarm;
+    }
+
+    /// Alarm at the last tick
+    pub fn alarm(&self) -> Alarm {
+        self.alarm
+    }
+
+    /// Decompression model
+    pub fn deco(&self) -> &M {
+        &self.deco
+    }
+
+    /// Use `gradient_factors` in the decompression model from now on
+    pub fn set_gradient_factors(&mut self, gradient_factors: GradientFactors) {
+        self.deco.set_gradient_factors(gradient_factors);
+    }
+
+    /// Use the ZHL-16 coefficients of `variant` from now on, it is recorded with every dive
+    pub fn set_deco_variant(&mut self, variant: Zhl16Variant) {
+        self.deco.set_variant(variant);
+    }
+
+    /// Run the simulation at `time_scale` from the next tick on
+    ///
+    /// Everything is computed per `SIMULATION_STEP`, so only the number of steps per tick changes.
+    pub fn set_time_scale(&mut self, time_scale: TimeScale) {
+        if time_scale != self.time_scale {
+            info!("Time scale {}", time_scale.as_str());
+            self.time_scale = time_scale;
+        }
+    }
+
+    pub fn time_scale(&self) -> TimeScale {
+        self.time_scale
+    }
+
+    /// Correct the time between ticks for `drift` of the clock from the next tick on
+    pub fn set_clock_drift(&mut self, drift: DriftCorrection) {
+        self.clock_drift = drift;
+    }
+
+    /// Elapsed dive time
+    pub fn edt(&self) -> MicrosDurationU64 {
+        self.edt
+    }
+
+    /// Dive that ended since the last call, for the lifetime statistics
+    pub fn take_finished_dive(&mut self) -> Option<DiveSummary> {
+        self.finished_dive.take()
+    }
+
+    /// Log of the dive that ended since the last call, for the logbook
+    #[cfg(feature = "logbook")]
+    pub fn take_finished_log(&mut self) -> Option<DiveLog> {
+        self.finished_log.take()
+    }
+
+    /// Depth in millimeters
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Ambient pressure in millibar the model works with, the surface pressure plus the water above
+    pub fn ambient_pressure(&self) -> u32 {
+        SURFACE_PRESSURE_CB * 10 + self.depth / 10
+    }
+
+    /// Rate in meters per minute, negative while ascending
+    pub fn rate(&self) -> i32 {
+        self.rate
+    }
+
+    /// Air in centiliters at the surface pressure
+    pub fn air(&self) -> u32 {
+        self.air
+    }
+
+    /// Whether a dive is going on, the main page shows the surface page otherwise
+    pub fn diving(&self) -> bool {
+        self.depth > 0
+    }
+
+    /// Most recent dive since the reset
+    pub fn last_dive(&self) -> Option<DiveSummary> {
+        self.last_dive
+    }
+
+    /// Profile and actions of the most recent dive since the reset
+    #[cfg(feature = "logbook")]
+    pub fn last_log(&self) -> Option<&DiveLog> {
+        self.last_log.as_ref()
+    }
+
+    /// Time at the surface since the last dive, `None` while diving or before the first dive
+    pub fn surface_interval(&self) -> Option<SecsDurationU32> {
+        (!self.diving() && self.last_dive.is_some()).then(|| SecsDurationU32::secs(self.surface_interval.to_secs() as u32))
+    }
+
+    pub fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    pub fn set_edt_format(&mut self, edt_format: EdtFormat) {
+        self.edt_format = edt_format;
+    }
+
+    pub fn set_depth_damping(&mut self, damping: DepthDamping) {
+        self.depth_damping = damping;
+    }
+
+    /// Depth in millimeters on the main page, averaged with the depth damping
+    pub fn shown_depth(&self) -> u32 {
+        match self.depth_damping {
+            DepthDamping::Instant => self.depth,
+            damping => self.smoother.average_depth(damping.steps()).unwrap_or(self.depth),
+        }
+    }
+
+    /// Depth and rate in small text, only while showing both units
+    pub fn secondary_readings(&self) -> Option<SecondaryReadings> {
+        self.unit.secondary().map(|unit| SecondaryReadings {
+            depth: self.shown_depth(),
+            rate: rate_in(self.rate, unit),
+            unit,
+        })
+    }
+
+    /// Depth of `stop` in the display unit
+    fn stop_depth(&self, stop: &Stop) -> u32 {
+        if self.unit.primary() == Unit::Imperial {
+            mm2ft(stop.depth)
+        } else {
+            stop.depth / 1000
+        }
+    }
+
+    /// Last alarm transitions
+    pub fn alarm_history(&self) -> &AlarmHistory<ALARM_HISTORY_SIZE> {
+        &self.alarm_history
+    }
+
+    /// Last actions of the diver, oldest first
+    pub fn audit_trail(&self) -> &AuditTrail<AUDIT_TRAIL_SIZE> {
+        &self.audit_trail
+    }
+
+    /// Direction of the depth change over the trend window
+    pub fn trend(&self) -> Trend {
+        self.smoother.trend()
+    }
+
+    /// Ascent rate coaching
+    pub fn ascent(&self) -> &AscentCoach {
+        &self.ascent
+    }
+
+    /// Coach, alarm and count the gas to the surface for `limit` from now on
+    pub fn set_ascent_limit(&mut self, limit: AscentLimit) {
+        if limit != self.ascent.limit() {
+            info!("Ascent limit {} m/min", limit.rate());
+            self.ascent.set_limit(limit);
+            self.deco.set_ascent_limit(limit);
+        }
+    }
+
+    /// Number of simulation steps the depth trend is smoothed over
+    pub fn set_trend_window(&mut self, steps: usize) {
+        self.smoother.set_window(steps);
+    }
+
+    pub fn set_unit(&mut self, unit: Unit) {
+        self.unit = unit;
+    }
+
+    pub fn set_depth_display(&mut self, display: DepthDisplay) {
+        self.depth_display = display;
+    }
+
+    pub fn depth_display(&self) -> DepthDisplay {
+        self.depth_display
+    }
+
+    /// Ambient pressure of the shown depth, in millibar
+    fn shown_ambient_pressure(&self) -> u32 {
+        SURFACE_PRESSURE_CB * 10 + self.shown_depth() / 10
+    }
+
+    /// Dive with `tank` from now on, the air is capped to what it holds
+    pub fn set_tank(&mut self, tank: TankSize) {
+        if tank == self.tank {
+            return;
+        }
+        self.tank = tank;
+        self.air = self.air.min(tank.full_air_in_cl());
+        self.consumption = ConsumptionEstimator::new(tank);
+    }
+
+    pub fn toggle_unit(&mut self) {
+        info!("Toggle measurement unit");
+
+        self.unit = self.unit.next();
+    }
+
+    /// Bookmark the current moment of the dive
+    pub fn mark(&mut self) {
+        if self.depth == 0 {
+            return;
+        }
+
+        self.mark_count += 1;
+        info!("Mark {} at {}s, {}mm", self.mark_count, self.edt.to_secs(), self.depth);
+
+        self.marks.push(Mark {
+            number: self.mark_count,
+            edt: self.edt.convert(),
+            depth: self.depth,
+        });
+    }
+
+    /// Last bookmarks of the dive
+    pub fn marks(&self) -> &RingBuffer<Mark, MARK_COUNT> {
+        &self.marks
+    }
+
+    /// Render the main page into `buf`
+    ///
+    /// With the `fast-format` feature this doesn't use `core::fmt`.
+    ///
+    /// Text that doesn't fit is cut off and marked with `text_buffer::OVERFLOW_MARKER`.
+    pub fn render(&self, buf: &mut UiBuffer) {
+        if cfg!(feature = "fast-format") {
+            // A failure means the text was cut off, which the buffer already shows
+            let _ = self.render_fast(buf);
+        } else {
+            writeln!(buf, "{}", self);
+        }
+    }
+
+    /// Render the main page into `buf` without `core::fmt`, the output is identical to the `Display` implementation
+    pub fn render_fast(&self, buf: &mut UiBuffer) -> fmt::Result {
+        use format::{push_digits, push_int, push_int_with_fill, push_str, push_str_padded};
+
+        let unit = self.unit.primary();
+        let (depth_width, rate_width) = self.unit.field_widths();
+        let depth = Depth::new(self.shown_depth(), unit);
+        let ambient = AmbientPressure::new(self.shown_ambient_pressure()).digits();
+        let rate = rate_in(self.rate, unit);
+        let alarm = self.get_alarm();
+
+        if self.sensor_fault().is_some() {
+            push_str(buf, "SENSOR FAULT\n\n")?;
+        } else if let Some(min_depth) = self.hypoxic_warning() {
+            push_str(buf, "HYPOXIC ABOVE ")?;
+            Depth::new(min_depth, unit).push(buf, 6)?;
+            push_str(buf, "\n\n")?;
+        } else if let Some(button) = self.stuck_button {
+            push_str(buf, "BUTTON ")?;
+            push_str(buf, button.as_str())?;
+            push_str(buf, " STUCK\n\n")?;
+        } else if self.shock_warning() {
+            push_str(buf, "CHECK SENSOR ZERO\n\n")?;
+        } else if let Some((o2, depth_m)) = self.gas_switch_toast() {
+            push_str(buf, "SWITCH EAN")?;
+            push_str_padded(buf, Digits::new(o2 as i64).as_str(), 3)?;
+            push_str(buf, "@")?;
+            Depth::new(depth_m * 1000, unit).push(buf, 6)?;
+            push_str(buf, "\n\n")?;
+        } else if let Some(alert) = self.depth_toast() {
+            push_str(buf, "DEPTH ALERT ")?;
+            Depth::new(alert.depth_m * 1000, unit).push(buf, 8)?;
+            push_str(buf, "\n\n")?;
+        } else if self.safety_stop.extended() && self.safety_stop.due(self.depth).is_some() {
+            push_str(buf, "EXTENDED: NDL PUSHED\n\n")?;
+        } else if self.lockout.active() {
+            push_str(buf, "DECO VIOLATION ")?;
+            push_int(buf, self.lockout.hours_left() as i64, 4)?;
+            push_str(buf, "H\n\n")?;
+        } else if let Some(minutes) = self.ndl_countdown() {
+            push_str(buf, "NDL LEFT ")?;
+            push_int(buf, minutes as i64, 8)?;
+            push_str(buf, "MIN\n\n")?;
+        } else {
+            push_str(buf, "DiveMaster\n\n")?;
+        }
+
+        match self.depth_display {
+            DepthDisplay::Pressure => {
+                push_str(buf, "AMB: ")?;
+                push_digits(buf, &ambient, depth_width + 2 - 3, ' ')?;
+                push_str(buf, "BAR")?;
+            }
+            DepthDisplay::Both if self.unit.secondary().is_none() => {
+                push_str(buf, "DEPTH: ")?;
+                depth.push(buf, depth_width - 8)?;
+                push_digits(buf, &ambient, 8 - 3, ' ')?;
+                push_str(buf, "BAR")?;
+            }
+            _ => {
+                push_str(buf, "DEPTH: ")?;
+                depth.push(buf, depth_width)?;
+            }
+        }
+
+        push_str(buf, "\nRATE: ")?;
+        push_int(buf, rate as i64, rate_width)?;
+        push_str(buf, unit.as_str())?;
+
+        push_str(buf, "/M\nASCENT: ")?;
+        push_str_padded(buf, "", 12 - self.ascent.coaching().as_str().len())?;
+        push_str(buf, self.ascent.coaching().as_str())?;
+
+        push_str(buf, "\nAIR: ")?;
+        push_int(buf, (self.air / 100) as i64, 14)?;
+
+        push_str(buf, "L\nEDT: ")?;
+        let edt = self.edt.to_secs();
+        match self.edt_format {
+            EdtFormat::HoursMinutesSeconds => {
+                push_int(buf, (edt / 3600) as i64, 9)?;
+                push_str(buf, ":")?;
+                push_int_with_fill(buf, (edt / 60 % 60) as i64, 2, '0')?;
+            }
+            EdtFormat::MinutesSeconds => push_int(buf, (edt / 60) as i64, 12)?,
+        }
+        push_str(buf, ":")?;
+        push_int_with_fill(buf, (edt % 60) as i64, 2, '0')?;
+
+        if M::ACTIVE {
+            push_str(buf, "\nN2: ")?;
+            push_int(buf, self.deco.loading() as i64, 15)?;
+            push_str(buf, "%")?;
+
+            match (self.deco.stops().iter().next(), self.safety_stop.due(self.depth)) {
+                _ if self.lockout.active() => push_str(buf, "\nNDL:          LOCKED")?,
+                (Some(stop), _) => {
+                    push_str(buf, "\nSTOP: ")?;
+                    push_int(buf, self.stop_depth(stop) as i64, if unit == Unit::Imperial { 4 } else { 5 })?;
+                    push_str(buf, unit.as_str())?;
+                    push_int(buf, stop.duration.to_minutes() as i64, 5)?;
+                    push_str(buf, "MIN")?;
+                }
+                (None, Some(left)) => {
+                    push_str(buf, "\nSAFETY STOP: ")?;
+                    push_int(buf, (left.to_secs() / 60) as i64, 4)?;
+                    push_str(buf, ":")?;
+                    push_int_with_fill(buf, (left.to_secs() % 60) as i64, 2, '0')?;
+                }
+                (None, None) => {
+                    push_str(buf, "\nNDL: ")?;
+                    push_int(buf, self.deco.ndl().to_minutes() as i64, 12)?;
+                    push_str(buf, "MIN")?;
+                }
+            }
+        }
+
+        match self.buddy {
+            Some(BuddyStatus::Connected(buddy)) => {
+                push_str(buf, "\nBUDDY:")?;
+                Depth::new(buddy.depth, unit).push(buf, 6)?;
+                if buddy.alarm == Alarm::High {
+                    push_str(buf, "    HIGH")?;
+                } else {
+                    let (pressure, symbol) = Pressure::new(buddy.pressure.into(), unit).value();
+                    push_int(buf, pressure as i64, 5)?;
+                    push_str(buf, symbol)?;
+                }
+            }
+            Some(BuddyStatus::Lost(_)) => push_str(buf, "\nBUDDY:     LINK LOST")?,
+            None => {}
+        }
+
+        push_str(buf, "\nALARM: ")?;
+        push_str_padded(buf, "", 13 - alarm.display_len())?;
+        push_str_padded(buf, alarm.as_str(), 13)?;
+        push_str(buf, "\n\n\n")
+    }
+}
+
+impl Default for DiveComputer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Clock, M: DecoModel> fmt::Display for DiveComputer<C, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = self.unit.primary();
+        let (depth_width, rate_width) = self.unit.field_widths();
+        let depth = Depth::new(self.shown_depth(), unit);
+        let ambient = AmbientPressure::new(self.shown_ambient_pressure());
+        let rate = rate_in(self.rate, unit);
+
+        let edt = self.edt.to_secs();
+
+        // Write to buffer
+        if self.sensor_fault().is_some() {
+            writeln!(f, "SENSOR FAULT")?;
+        } else if let Some(min_depth) = self.hypoxic_warning() {
+            writeln!(f, "HYPOXIC ABOVE {:>6}", Depth::new(min_depth, unit))?;
+        } else if let Some(button) = self.stuck_button {
+            writeln!(f, "BUTTON {} STUCK", button.as_str())?;
+        } else if self.shock_warning() {
+            writeln!(f, "CHECK SENSOR ZERO")?;
+        } else if let Some((o2, depth_m)) = self.gas_switch_toast() {
+            writeln!(f, "SWITCH EAN{:<3}@{:>6}", o2, Depth::new(depth_m * 1000, unit))?;
+        } else if let Some(alert) = self.depth_toast() {
+            writeln!(f, "DEPTH ALERT {:>8}", Depth::new(alert.depth_m * 1000, unit))?;
+        } else if self.safety_stop.extended() && self.safety_stop.due(self.depth).is_some() {
+            writeln!(f, "EXTENDED: NDL PUSHED")?;
+        } else if self.lockout.active() {
+            writeln!(f, "DECO VIOLATION {:4}H", self.lockout.hours_left())?;
+        } else if let Some(minutes) = self.ndl_countdown() {
+            writeln!(f, "NDL LEFT {:8}MIN", minutes)?;
+        } else {
+            writeln!(f, "DiveMaster")?;
+        }
+        writeln!(f)?;
+        match self.depth_display {
+            // The label is shorter, so the number lines up with the depth of the other modes
+            DepthDisplay::Pressure => writeln!(f, "AMB: {:>width$}", ambient, width = depth_width + 2)?,
+            // Both units leave no room next to the depth
+            DepthDisplay::Both if self.unit.secondary().is_none() => writeln!(f, "DEPTH: {:>width$}{:>8}", depth, ambient, width = depth_width - 8)?,
+            _ => writeln!(f, "DEPTH: {:>width$}", depth, width = depth_width)?,
+        }
+        writeln!(f, "RATE: {:width$}{}/M", rate, unit, width = rate_width)?;
+        writeln!(f, "ASCENT: {:>12}", self.ascent.coaching().as_str())?;
+        writeln!(f, "AIR: {:14}L", self.air / 100)?;
+        match self.edt_format {
+            EdtFormat::HoursMinutesSeconds => writeln!(f, "EDT: {:9}:{:0>2}:{:0>2}", edt / 3600, edt / 60 % 60, edt % 60)?,
+            EdtFormat::MinutesSeconds => writeln!(f, "EDT: {:12}:{:0>2}", edt / 60, edt % 60)?,
+        }
+        if M::ACTIVE {
+            writeln!(f, "N2: {:15}%", self.deco.loading())?;
+            match (self.deco.stops().iter().next(), self.safety_stop.due(self.depth)) {
+                _ if self.lockout.active() => writeln!(f, "NDL: {:>15}", "LOCKED")?,
+                (Some(stop), _) => writeln!(
+                    f,
+                    "STOP: {:width$}{}{:5}MIN",
+                    self.stop_depth(stop),
+                    unit,
+                    stop.duration.to_minutes(),
+                    width = if unit == Unit::Imperial { 4 } else { 5 }
+                )?,
+                (None, Some(left)) => writeln!(f, "SAFETY STOP: {:4}:{:02}", left.to_secs() / 60, left.to_secs() % 60)?,
+                (None, None) => writeln!(f, "NDL: {:12}MIN", self.deco.ndl().to_minutes())?,
+            }
+        }
+        match self.buddy {
+            Some(BuddyStatus::Connected(buddy)) => {
+                let depth = Depth::new(buddy.depth, unit);
+                if buddy.alarm == Alarm::High {
+                    writeln!(f, "BUDDY:{:>6}{:>8}", depth, "HIGH")?
+                } else {
+                    writeln!(f, "BUDDY:{:>6}{:>8}", depth, Pressure::new(buddy.pressure.into(), unit))?
+                }
+            }
+            Some(BuddyStatus::Lost(_)) => writeln!(f, "BUDDY: {:>13}", "LINK LOST")?,
+            None => {}
+        }
+        writeln!(f, "ALARM: {:width$}{}", "", self.get_alarm(), width = 13 - self.get_alarm().display_len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    #[cfg(feature = "deco")]
+    use crate::deco::zhl16::Zhl16;
+    use crate::{clock::ManualClock, failure::AIR_LOSS_PERCENT};
+    use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+
+    /// Air in a full default tank
+    const FULL_AIR: u32 = TankSize::L10.full_air_in_cl();
+
+    #[test]
+    fn test_gas_rate_in_cl() {
+        assert!(true)
+    }
+
+    /// Dive the same rate profile with ticks of `tick_ms`
+    fn dive_with_tick(tick_ms: u32) -> DiveComputer {
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.air = FULL_AIR;
+
+        // Rate in m/min for the next 30 seconds
+        for rate in [18, 7, 0, -3, -9, 0, 12, -10] {
+            dive_computer.rate = rate;
+            for _ in 0..(30_000 / tick_ms) {
+                dive_computer.change_depth(MicrosDurationU32::millis(tick_ms));
+            }
+        }
+
+        dive_computer
+    }
+
+    #[test]
+    fn test_result_independent_of_tick_rate() {
+        let reference = dive_with_tick(100);
+        assert!(reference.depth > 0);
+
+        for tick_ms in [200, 300, 500, 1000, 3000] {
+            let dive_computer = dive_with_tick(tick_ms);
+
+            assert_eq!(dive_computer.depth, reference.depth, "depth with {} ms ticks", tick_ms);
+            assert_eq!(dive_computer.air, reference.air, "air with {} ms ticks", tick_ms);
+            assert_eq!(dive_computer.edt, reference.edt, "edt with {} ms ticks", tick_ms);
+        }
+    }
+
+    #[test]
+    fn test_uneven_ticks_are_carried_over() {
+        let mut reference = DiveComputer::new();
+        reference.rate = 10;
+        reference.change_depth(MicrosDurationU32::millis(1000));
+
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.rate = 10;
+        for tick_ms in [130, 270, 50, 550] {
+            dive_computer.change_depth(MicrosDurationU32::millis(tick_ms));
+        }
+
+        assert_eq!(dive_computer.depth, reference.depth);
+        assert_eq!(dive_computer.edt, reference.edt);
+    }
+
+    /// Replay checksum after feeding `script` to a fresh dive computer
+    fn replay(script: &[Input]) -> ReplayChecksum {
+        let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+        for &input in script {
+            match input {
+                Input::Action(action) => dive_computer.perform(action),
+                Input::Tick(interval) => dive_computer.change_depth(interval),
+            }
+        }
+        dive_computer.replay()
+    }
+
+    #[test]
+    fn test_replay_checksum() {
+        let second = Input::Tick(MicrosDurationU32::secs(1));
+        let script = [
+            Input::Action(Action::IncreaseRate),
+            second,
+            Input::Action(Action::Mark),
+            second,
+            Input::Action(Action::DecreaseRate),
+            second,
+        ];
+
+        let reference = replay(&script);
+        assert_eq!(replay(&script), reference);
+        assert_eq!(reference.inputs(), 6);
+
+        // Another action, even one the dive computer ignores
+        let mut other = script;
+        other[2] = Input::Action(Action::Help);
+        assert_ne!(replay(&other).crc(), reference.crc());
+
+        // The same time in other ticks reaches the same state, but not the same checksum
+        let half = Input::Tick(MicrosDurationU32::millis(500));
+        let split = [
+            Input::Action(Action::IncreaseRate),
+            half,
+            half,
+            Input::Action(Action::Mark),
+            second,
+            Input::Action(Action::DecreaseRate),
+            second,
+        ];
+        assert_ne!(replay(&split).crc(), reference.crc());
+    }
+
+    #[test]
+    fn test_free_flow_raises_alarm() {
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.air = FULL_AIR;
+        dive_computer.depth = 10_000;
+        dive_computer.change_depth(MicrosDurationU32::secs(60));
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+
+        dive_computer.toggle_free_flow();
+        dive_computer.change_depth(MicrosDurationU32::secs(60));
+        assert_eq!(dive_computer.alarm(), Alarm::High);
+        assert!(dive_computer.alarm_history().iter().any(|event| event.alarm == Alarm::High));
+
+        dive_computer.toggle_free_flow();
+        dive_computer.change_depth(MicrosDurationU32::secs(60));
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+    }
+
+    #[test]
+    fn test_exertion() {
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.air = FULL_AIR;
+        dive_computer.depth = 10_000;
+        dive_computer.perform(Action::Exertion);
+        dive_computer.perform(Action::Exertion);
+        assert_eq!(dive_computer.exertion(), Exertion::Working);
+
+        // Twice the gas, expected so it isn't a spike
+        dive_computer.change_depth(MicrosDurationU32::secs(60));
+        assert_eq!(dive_computer.air, FULL_AIR - 4800);
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+
+        // Enough to reach the surface at rest, but not while working
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.air = 2000;
+        dive_computer.depth = 10_000;
+        dive_computer.set_exertion(Exertion::Working);
+        dive_computer.change_depth(MicrosDurationU32::secs(1));
+        assert_eq!(dive_computer.alarm(), Alarm::High);
+        dive_computer.set_exertion(Exertion::Rest);
+        dive_computer.change_depth(MicrosDurationU32::secs(1));
+        assert_ne!(dive_computer.alarm(), Alarm::High);
+    }
+
+    #[test]
+    fn test_checklist_kept_with_dive() {
+        let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+        dive_computer.air = FULL_AIR;
+        assert!(!dive_computer.checklist_due());
+        dive_computer.set_checklist_mode(ChecklistMode::On);
+        assert!(dive_computer.checklist_due());
+        dive_computer.set_checklist(ChecklistOutcome::Skipped(2));
+        assert!(!dive_computer.checklist_due());
+
+        dive_computer.rate = 10;
+        dive_computer.change_depth(MicrosDurationU32::minutes(1));
+        dive_computer.rate = -10;
+        dive_computer.change_depth(MicrosDurationU32::minutes(2));
+        let dive = dive_computer.take_finished_dive().unwrap();
+        assert_eq!(dive.checklist, ChecklistOutcome::Skipped(2));
+        // The next dive asks again
+        assert!(dive_computer.checklist_due());
+    }
+
+    #[test]
+    #[cfg(feature = "deco")]
+    fn test_dive_records_deco_variant() {
+        let mut dive_computer = DiveComputer::with_model(ManualClock::new(), Zhl16::new());
+        dive_computer.air = FULL_AIR;
+        dive_computer.set_deco_variant(Zhl16Variant::B);
+        dive_computer.rate = 10;
+        dive_computer.change_depth(MicrosDurationU32::minutes(1));
+        dive_computer.rate = -10;
+        dive_computer.change_depth(MicrosDurationU32::minutes(2));
+        let dive = dive_computer.take_finished_dive().unwrap();
+        assert_eq!(dive.deco_variant, Some(Zhl16Variant::B));
+    }
+
+    #[test]
+    #[cfg(feature = "deco")]
+    fn test_resume_after_watchdog_reset() {
+        let mut dive_computer = DiveComputer::with_model(ManualClock::new(), Zhl16::new());
+        dive_computer.air = FULL_AIR;
+        dive_computer.rate = 20;
+        dive_computer.change_depth(MicrosDurationU32::minutes(2));
+        dive_computer.rate = 0;
+        dive_computer.change_depth(MicrosDurationU32::minutes(20));
+
+        let state = RetainedState {
+            page: crate::ui::Page::Main,
+            depth: dive_computer.depth,
+            rate: 0,
+            air: dive_computer.air,
+            edt: dive_computer.edt,
+        };
+        let mut resumed = DiveComputer::with_model(ManualClock::new(), Zhl16::new());
+        resumed.resume(&state);
+        assert_eq!(
+            (resumed.depth(), resumed.air(), resumed.edt()),
+            (40_000, dive_computer.air, MicrosDurationU64::minutes(22))
+        );
+        // The whole dive at the kept depth, never less than the real profile
+        assert!(resumed.deco().ceiling() >= dive_computer.deco().ceiling());
+        assert!(resumed.deco().ceiling() > 0);
+    }
+
+    #[test]
+    #[cfg(feature = "deco")]
+    fn test_missed_stop_locks_planning() {
+        let mut dive_computer = DiveComputer::with_model(ManualClock::new(), Zhl16::new());
+        dive_computer.air = FULL_AIR;
+        dive_computer.depth = 40_000;
+        dive_computer.change_depth(MicrosDurationU32::minutes(25));
+        assert!(dive_computer.deco().ceiling() > 0);
+        assert!(dive_computer.planning_allowed());
+
+        dive_computer.rate = -50;
+        dive_computer.change_depth(MicrosDurationU32::minutes(1));
+        assert_eq!(dive_computer.depth, 0);
+        assert!(!dive_computer.planning_allowed());
+        assert_eq!(dive_computer.lockout().hours_left(), 24);
+        assert!(format!("{}", dive_computer).starts_with("DECO VIOLATION   24H\n"));
+        assert!(format!("{}", dive_computer).contains("NDL:          LOCKED\n"));
+
+        let missed = dive_computer.alarm_history().iter().find(|event| event.transition == Transition::MissedStop);
+        assert!(missed.is_some_and(|event| event.depth > 0));
+
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+    }
+
+    #[test]
+    fn test_finished_dive() {
+        let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+        dive_computer.air = FULL_AIR;
+        dive_computer.edt = MicrosDurationU64::minutes(30);
+
+        // Splashing at the surface isn't a dive
+        dive_computer.rate = 1;
+        dive_computer.change_depth(MicrosDurationU32::secs(30));
+        dive_computer.rate = -10;
+        dive_computer.change_depth(MicrosDurationU32::minutes(1));
+        assert_eq!(dive_computer.take_finished_dive(), None);
+
+        dive_computer.rate = 10;
+        dive_computer.change_depth(MicrosDurationU32::minutes(2));
+        dive_computer.mark();
+        dive_computer.rate = 0;
+        dive_computer.change_depth(MicrosDurationU32::minutes(10));
+        dive_computer.rate = -10;
+        dive_computer.change_depth(MicrosDurationU32::minutes(3));
+        let dive = dive_computer.take_finished_dive().unwrap();
+        assert_eq!(dive.max_depth, 20_000);
+        assert_eq!(dive.deco_variant, None);
+        // The step that reached the surface isn't underwater, whole seconds only
+        assert_eq!(dive.duration.to_secs(), 14 * 60 - 1);
+        assert_eq!(dive_computer.take_finished_dive(), None);
+        assert_eq!(dive_computer.last_dive(), Some(dive));
+        assert!(!dive_computer.diving());
+
+        // The surface interval runs until the next dive
+        dive_computer.rate = 0;
+        dive_computer.change_depth(MicrosDurationU32::minutes(5));
+        assert_eq!(dive_computer.surface_interval(), Some(SecsDurationU32::secs(6 * 60)));
+        // The marks stay for the console until the next dive
+        assert_eq!(dive_computer.marks().last().map(|mark| (mark.number, mark.depth)), Some((1, 20_000)));
+        dive_computer.rate = 10;
+        dive_computer.change_depth(MicrosDurationU32::secs(6));
+        assert_eq!(dive_computer.surface_interval(), None);
+        assert!(dive_computer.marks().is_empty());
+        dive_computer.mark();
+        assert_eq!(dive_computer.marks().last().map(|mark| mark.number), Some(1));
+    }
+
+    #[test]
+    #[cfg(feature = "deco")]
+    fn test_time_scale_only_speeds_up() {
+        let clock = ManualClock::new();
+        let mut scaled = DiveComputer::with_model(&clock, Zhl16::new());
+        scaled.set_time_scale(TimeScale::X60);
+        scaled.rate = 1;
+        scaled.tick();
+        clock.advance(MicrosDurationU64::secs(30));
+        scaled.tick();
+
+        let mut reference = DiveComputer::with_model(ManualClock::new(), Zhl16::new());
+        reference.rate = 1;
+        reference.change_depth(MicrosDurationU32::minutes(30));
+
+        assert_eq!(scaled.depth, reference.depth);
+        assert_eq!(scaled.air, reference.air);
+        assert_eq!(scaled.edt, reference.edt);
+        assert_eq!(scaled.deco().tissues(), reference.deco().tissues());
+    }
+
+    #[test]
+    fn test_clock_drift_corrects_edt() {
+        // A crystal 500 ppm fast counts 100.05 ms for every 100 ms
+        let clock = ManualClock::new();
+        let mut dive_computer = DiveComputer::with_clock(&clock);
+        dive_computer.set_clock_drift(DriftCorrection { ppm: 500 });
+        dive_computer.air = FULL_AIR;
+        dive_computer.depth = 10_000;
+        dive_computer.tick();
+        for _ in 0..1_000 {
+            clock.advance(MicrosDurationU64::micros(100_050));
+            dive_computer.tick();
+        }
+        assert_eq!(dive_computer.edt(), MicrosDurationU64::secs(100));
+    }
+
+    #[test]
+    fn test_reserve_alarms() {
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.depth = 10_000;
+        dive_computer.air = 60 * 100 * TankSize::L10.volume_l();
+        dive_computer.change_depth(MicrosDurationU32::millis(100));
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+
+        dive_computer.air = 45 * 100 * TankSize::L10.volume_l();
+        dive_computer.change_depth(MicrosDurationU32::millis(100));
+        assert_eq!(dive_computer.alarm(), Alarm::AirReserve);
+        assert_eq!(dive_computer.reserve().color(), Some(Rgb565::YELLOW));
+
+        dive_computer.air = 25 * 100 * TankSize::L10.volume_l();
+        dive_computer.change_depth(MicrosDurationU32::millis(100));
+        assert_eq!(dive_computer.alarm(), Alarm::AirCritical);
+        assert!(dive_computer.buzzing(Instant::from_ticks(0)));
+
+        let raised: Vec<_> = dive_computer.alarm_history().iter().map(|event| (event.alarm, event.transition)).collect();
+        assert!(raised.contains(&(Alarm::AirReserve, Transition::Raised)));
+        assert!(raised.contains(&(Alarm::AirCritical, Transition::Raised)));
+    }
+
+    #[test]
+    fn test_audit_trail() {
+        let clock = ManualClock::new();
+        let mut dive_computer = DiveComputer::with_clock(&clock);
+        // The times are corrected like the dive time, this crystal is 500 ppm fast
+        dive_computer.set_clock_drift(DriftCorrection { ppm: 500 });
+        // Without a clock read of its own, an action is at the last tick
+        dive_computer.perform(Action::FillAir);
+        dive_computer.tick();
+        clock.advance(MicrosDurationU64::millis(90_045));
+        dive_computer.tick();
+        clock.advance(MicrosDurationU64::millis(50));
+        dive_computer.perform(Action::None);
+        dive_computer.depth = 12_000;
+        dive_computer.perform(Action::DecreaseRate);
+
+        let trail: Vec<_> = dive_computer.audit_trail().iter().map(|entry| format!("{}", entry)).collect();
+        assert_eq!(trail, ["+  0:00 FILL AIR   0.0M", "^  1:30 RATE DOWN   12M"]);
+    }
+
+    #[cfg(feature = "logbook")]
+    #[test]
+    fn test_dive_log() {
+        let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+        dive_computer.air = FULL_AIR;
+        // Actions at the surface are not part of the dive
+        dive_computer.perform(Action::Exertion);
+
+        dive_computer.rate = 10;
+        dive_computer.change_depth(MicrosDurationU32::minutes(2));
+        dive_computer.perform(Action::Mark);
+        dive_computer.rate = 0;
+        dive_computer.change_depth(MicrosDurationU32::minutes(10));
+        dive_computer.rate = -10;
+        dive_computer.change_depth(MicrosDurationU32::minutes(3));
+        let log = dive_computer.take_finished_log().unwrap();
+        assert_eq!(dive_computer.take_finished_log(), None);
+        assert_eq!(dive_computer.last_log(), Some(&log));
+
+        // A sample every 10 s of the 839 s underwater, and one at the surface
+        let depths: Vec<_> = log.depths().collect();
+        assert_eq!(depths.len(), 85);
+        assert_eq!(depths.iter().max(), Some(&20_000));
+        assert_eq!(depths.last(), Some(&0));
+        assert_eq!(log.events(), [crate::dive_log::LogEvent { action: Action::Mark, time: 120 }]);
+    }
+
+    #[test]
+    fn test_injected_failures() {
+        let clock = ManualClock::new();
+        let mut dive_computer = DiveComputer::with_clock(&clock);
+        dive_computer.air = 10_000;
+        dive_computer.lose_air(AIR_LOSS_PERCENT);
+        assert_eq!(dive_computer.air(), 5_000);
+
+        // A stuck sensor holds the depth, the rate buttons don't move it
+        dive_computer.depth = 12_000;
+        dive_computer.update_sensor(Ok(12_000));
+        dive_computer.perform(Action::IncreaseRate);
+        for _ in 0..10 {
+            clock.advance(MicrosDurationU64::millis(100));
+            dive_computer.tick();
+        }
+        assert_eq!(dive_computer.depth(), 12_000);
+
+        // Released it goes on from there
+        dive_computer.release_sensor();
+        assert_eq!(dive_computer.depth_source(), DepthSource::Simulator);
+        dive_computer.perform(Action::IncreaseRate);
+        for _ in 0..10 {
+            clock.advance(MicrosDurationU64::millis(100));
+            dive_computer.tick();
+        }
+        assert!(dive_computer.depth() > 12_000);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.air = FULL_AIR;
+        dive_computer.set_rate_limit(RateLimit::M10);
+        for _ in 0..20 {
+            dive_computer.perform(Action::IncreaseRate);
+        }
+        assert_eq!((dive_computer.rate(), dive_computer.target_rate()), (0, 20));
+        dive_computer.change_depth(MicrosDurationU32::secs(1));
+        assert_eq!(dive_computer.rate(), 10);
+        dive_computer.change_depth(MicrosDurationU32::secs(2));
+        assert_eq!(dive_computer.rate(), 20);
+
+        // Turning around to a fast ascent takes four seconds
+        for _ in 0..40 {
+            dive_computer.perform(Action::DecreaseRate);
+        }
+        dive_computer.change_depth(MicrosDurationU32::secs(3));
+        assert_eq!(dive_computer.rate(), -10);
+
+        // Without the limit the rate jumps to the target
+        dive_computer.set_rate_limit(RateLimit::Off);
+        assert_eq!(dive_computer.rate(), -20);
+    }
+
+    #[test]
+    fn test_ascent_limit() {
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.air = FULL_AIR;
+        dive_computer.depth = 20_000;
+        dive_computer.rate = -12;
+        dive_computer.change_depth(MicrosDurationU32::secs(1));
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+
+        // The same ascent is too fast under the conservative limit
+        dive_computer.set_ascent_limit(AscentLimit::Conservative);
+        dive_computer.change_depth(MicrosDurationU32::secs(1));
+        assert_eq!(dive_computer.alarm(), Alarm::Medium);
+        dive_computer.set_ascent_limit(AscentLimit::Padi);
+        dive_computer.change_depth(MicrosDurationU32::secs(1));
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+    }
+
+    #[test]
+    fn test_sensor_fault_falls_back_to_simulator() {
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.air = FULL_AIR;
+        dive_computer.update_sensor(Ok(12_000));
+        dive_computer.change_depth(MicrosDurationU32::secs(10));
+        assert_eq!(dive_computer.depth, 12_000);
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+
+        dive_computer.update_sensor(Err(Fault::NoResponse));
+        dive_computer.change_depth(MicrosDurationU32::secs(10));
+        assert_eq!(dive_computer.depth, 12_000);
+        assert_eq!(dive_computer.edt.to_secs(), 20);
+        assert_eq!(dive_computer.alarm(), Alarm::Medium);
+        assert!(format!("{}", dive_computer).starts_with("SENSOR FAULT\n"));
+
+        // Buttons work again, and a late reading doesn't bring the sensor back
+        dive_computer.increase_rate();
+        dive_computer.update_sensor(Ok(30_000));
+        dive_computer.change_depth(MicrosDurationU32::secs(60));
+        assert_eq!(dive_computer.depth, 13_000);
+
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+    }
+
+    #[test]
+    fn test_depth_damping_only_changes_readout() {
+        let mut instant = DiveComputer::new();
+        let mut damped = DiveComputer::new();
+        damped.set_depth_damping(DepthDamping::FourSeconds);
+        for dive_computer in [&mut instant, &mut damped] {
+            dive_computer.air = FULL_AIR;
+            dive_computer.unit = Unit::Both;
+            dive_computer.update_sensor(Ok(10_000));
+            dive_computer.change_depth(MicrosDurationU32::secs(3));
+            dive_computer.update_sensor(Ok(12_000));
+            dive_computer.change_depth(MicrosDurationU32::secs(1));
+        }
+
+        assert_eq!(instant.shown_depth(), 12_000);
+        assert_eq!(damped.shown_depth(), 10_500);
+        assert_eq!(damped.secondary_readings().map(|readings| readings.depth), Some(10_500));
+        assert!(format!("{}", damped).contains("DEPTH:     10M\n"));
+        let mut fast = UiBuffer::new();
+        damped.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", damped));
+
+        // The model, the log and the replay see the measured depth
+        assert_eq!(damped.depth, 12_000);
+        assert_eq!(damped.deco().loading(), instant.deco().loading());
+        assert_eq!(damped.replay(), instant.replay());
+    }
+
+    #[test]
+    fn test_depth_alert_toast() {
+        let clock = ManualClock::new();
+        let mut dive_computer = DiveComputer::with_clock(&clock);
+        let mut alerts = DepthAlerts::new();
+        for _ in 0..6 {
+            alerts.step_depth(0);
+        }
+        dive_computer.set_depth_alerts(alerts);
+        dive_computer.air = FULL_AIR;
+        dive_computer.depth = 17_000;
+        dive_computer.rate = 20;
+
+        clock.advance(MicrosDurationU64::secs(1));
+        dive_computer.change_depth(MicrosDurationU32::secs(6));
+        assert_eq!(dive_computer.depth, 19_000);
+        assert!(format!("{}", dive_computer).starts_with("DEPTH ALERT      18M\n"));
+        assert!(dive_computer.buzzing(clock.now()));
+        assert!(!dive_computer.buzzing(clock.now() + BEEP_LENGTH));
+
+        dive_computer.unit = Unit::Imperial;
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+        assert!(fast.as_str().starts_with("DEPTH ALERT     59FT\n"));
+
+        // Gone after a while, and going back up doesn't cross a descending alert
+        clock.advance(TOAST_TIME);
+        assert!(format!("{}", dive_computer).starts_with("DiveMaster\n"));
+        dive_computer.rate = -20;
+        dive_computer.change_depth(MicrosDurationU32::secs(6));
+        assert_eq!(dive_computer.depth_toast(), None);
+    }
+
+    #[test]
+    fn test_gas_switch_toast() {
+        let clock = ManualClock::new();
+        let mut dive_computer = DiveComputer::with_clock(&clock);
+        dive_computer.set_deco_gases(DecoGases { o2: [Some(50), Some(100)] });
+        dive_computer.air = FULL_AIR;
+        dive_computer.depth = 23_000;
+        dive_computer.rate = -10;
+
+        clock.advance(MicrosDurationU64::secs(1));
+        dive_computer.change_depth(MicrosDurationU32::secs(12));
+        assert_eq!(dive_computer.depth, 21_000);
+        assert_eq!(dive_computer.gas_switch_toast(), Some((50, 21)));
+        assert!(format!("{}", dive_computer).starts_with("SWITCH EAN50 @   21M\n"));
+        assert!(dive_computer.buzzing(clock.now()));
+        assert!(!dive_computer.buzzing(clock.now() + BEEP_LENGTH));
+
+        dive_computer.unit = Unit::Imperial;
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+        assert!(fast.as_str().starts_with("SWITCH EAN50 @  68FT\n"));
+
+        // Oxygen at 6 m
+        dive_computer.unit = Unit::Metric;
+        clock.advance(TOAST_TIME);
+        dive_computer.change_depth(MicrosDurationU32::secs(90));
+        assert_eq!(dive_computer.depth, 6_000);
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+        assert!(fast.as_str().starts_with("SWITCH EAN100@  6.0M\n"));
+
+        // Going down again doesn't remind
+        clock.advance(TOAST_TIME);
+        dive_computer.rate = 10;
+        dive_computer.change_depth(MicrosDurationU32::secs(120));
+        assert_eq!(dive_computer.gas_switch_toast(), None);
+    }
+
+    #[test]
+    fn test_hypoxic_interlock() {
+        let clock = ManualClock::new();
+        let mut dive_computer = DiveComputer::with_clock(&clock);
+        dive_computer.air = FULL_AIR;
+        dive_computer.set_back_gas(Gas { o2: 10, he: 70 });
+
+        // Blocked at the surface and on the way down
+        dive_computer.change_depth(MicrosDurationU32::secs(1));
+        assert_eq!(dive_computer.alarm(), Alarm::High);
+        assert!(format!("{}", dive_computer).starts_with("HYPOXIC ABOVE   8.0M\n"));
+        dive_computer.rate = 20;
+        dive_computer.change_depth(MicrosDurationU32::secs(30));
+        assert_eq!(dive_computer.depth, 10_000);
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+        assert_eq!(dive_computer.hypoxic_warning(), None);
+
+        // Warned going up through it
+        dive_computer.rate = -10;
+        dive_computer.change_depth(MicrosDurationU32::secs(18));
+        assert_eq!(dive_computer.alarm(), Alarm::Medium);
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+        assert!(fast.as_str().starts_with("HYPOXIC ABOVE   8.0M\n"));
+
+        dive_computer.change_depth(MicrosDurationU32::secs(42));
+        assert_eq!(dive_computer.depth, 0);
+        assert_eq!(dive_computer.alarm(), Alarm::High);
+
+        dive_computer.set_back_gas(Gas::AIR);
+        dive_computer.change_depth(MicrosDurationU32::secs(1));
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+    }
+
+    #[test]
+    fn test_fill_while_held() {
+        let clock = ManualClock::new();
+        let mut dive_computer = DiveComputer::with_clock(&clock);
+        dive_computer.air = 5000;
+
+        // 25 l in 6 seconds at 250 l/min
+        dive_computer.perform(Action::FillAir);
+        dive_computer.change_depth(MicrosDurationU32::secs(6));
+        assert_eq!(dive_computer.air, 7500);
+        assert_eq!(dive_computer.filling(), Some(3));
+        assert_eq!(dive_computer.tick_interval(), DIVE_TICK_INTERVAL);
+
+        // Released
+        clock.advance(FILL_HOLD_TIME + MicrosDurationU64::millis(100));
+        dive_computer.change_depth(MicrosDurationU32::secs(6));
+        assert_eq!(dive_computer.air, 7500);
+        assert_eq!(dive_computer.filling(), None);
+        assert_eq!(dive_computer.tick_interval(), SURFACE_TICK_INTERVAL);
+
+        // Stops when full
+        dive_computer.set_fill_rate(FillRate::L500);
+        dive_computer.perform(Action::FillAir);
+        dive_computer.change_depth(MicrosDurationU32::minutes(10));
+        assert_eq!(dive_computer.air, FULL_AIR);
+        assert_eq!(dive_computer.filling(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "deco")]
+    fn test_safety_stop_extended_near_ndl() {
+        let mut dive_computer = DiveComputer::with_model(ManualClock::new(), Zhl16::new());
+        dive_computer.air = FULL_AIR;
+        dive_computer.depth = 30_000;
+        dive_computer.change_depth(MicrosDurationU32::minutes(5));
+        assert!(!dive_computer.safety_stop().extended());
+
+        // Close to the 16 minute limit at 30 m
+        dive_computer.change_depth(MicrosDurationU32::minutes(9));
+        assert!(dive_computer.safety_stop().extended());
+
+        dive_computer.depth = 5_000;
+        dive_computer.change_depth(MicrosDurationU32::secs(1));
+        assert!(format!("{}", dive_computer).starts_with("EXTENDED: NDL PUSHED\n"));
+        assert!(format!("{}", dive_computer).contains("SAFETY STOP:    4:59\n"));
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+
+        dive_computer.change_depth(MicrosDurationU32::minutes(5));
+        assert_eq!(dive_computer.safety_stop().remaining(), None);
+        assert!(format!("{}", dive_computer).starts_with("DiveMaster\n"));
+        assert!(format!("{}", dive_computer).contains("NDL:"));
+    }
+
+    #[test]
+    #[cfg(feature = "deco")]
+    fn test_ndl_countdown() {
+        let clock = ManualClock::new();
+        let mut dive_computer = DiveComputer::with_model(&clock, Zhl16::new());
+        dive_computer.air = FULL_AIR;
+        dive_computer.depth = 30_000;
+        dive_computer.change_depth(MicrosDurationU32::minutes(5));
+        assert_eq!(dive_computer.ndl_countdown(), None);
+        assert!(!dive_computer.buzzing(clock.now()));
+
+        // Below 5 minutes of the 16 minute limit at 30 m, one beep
+        dive_computer.change_depth(MicrosDurationU32::minutes(7));
+        assert_eq!(dive_computer.ndl_countdown(), Some(4));
+        assert!(format!("{}", dive_computer).starts_with("NDL LEFT        4MIN\n"));
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+        assert!(dive_computer.buzzing(clock.now()));
+        assert!(!dive_computer.buzzing(clock.now() + BEEP_LENGTH * 2));
+
+        // Three beeps at the last minute
+        dive_computer.change_depth(MicrosDurationU32::minutes(3));
+        assert_eq!(dive_computer.ndl_countdown(), Some(1));
+        assert!(dive_computer.buzzing(clock.now() + BEEP_LENGTH * 4));
+
+        // In deco there is nothing to count down
+        dive_computer.change_depth(MicrosDurationU32::minutes(2));
+        assert_eq!(dive_computer.ndl_countdown(), None);
+        assert!(format!("{}", dive_computer).starts_with("DiveMaster\n"));
+    }
+
+    #[test]
+    fn test_stuck_button_warning() {
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.air = FULL_AIR;
+        dive_computer.set_stuck_button(Some(Button::A));
+        dive_computer.change_depth(MicrosDurationU32::millis(100));
+        assert_eq!(dive_computer.alarm(), Alarm::Low);
+        assert!(format!("{}", dive_computer).starts_with("BUTTON A STUCK\n"));
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+
+        dive_computer.set_stuck_button(None);
+        dive_computer.change_depth(MicrosDurationU32::millis(100));
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+    }
+
+    #[test]
+    fn test_shock_warning() {
+        let clock = ManualClock::new();
+        let mut dive_computer = DiveComputer::with_clock(&clock);
+        dive_computer.air = FULL_AIR;
+        dive_computer.record_shock(9_000);
+        assert!(!dive_computer.shock_warning());
+
+        dive_computer.depth = 12_000;
+        dive_computer.record_shock(9_000);
+        dive_computer.change_depth(MicrosDurationU32::millis(100));
+        assert_eq!(dive_computer.alarm(), Alarm::Low);
+        assert!(format!("{}", dive_computer).starts_with("CHECK SENSOR ZERO\n"));
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+        let shocks: Vec<_> = dive_computer.alarm_history().iter().filter(|event| event.transition == Transition::Shock).collect();
+        assert_eq!(shocks.len(), 1);
+        assert_eq!(format!("{}", shocks[0]), " 0:00 SHOCK        12M");
+
+        clock.advance(SHOCK_WARNING_TIME);
+        dive_computer.change_depth(MicrosDurationU32::millis(100));
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+    }
+
+    #[test]
+    fn test_buddy_status() {
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.air = FULL_AIR;
+        let mut buddy = Telemetry {
+            depth: 18_000,
+            pressure: 150,
+            alarm: Alarm::None,
+        };
+        dive_computer.set_buddy(Some(BuddyStatus::Connected(buddy)));
+        dive_computer.change_depth(MicrosDurationU32::millis(100));
+        assert_eq!(dive_computer.alarm(), Alarm::None);
+        assert!(format!("{}", dive_computer).contains("\nBUDDY:   18M  150BAR\nALARM:"));
+
+        buddy.alarm = Alarm::High;
+        dive_computer.set_buddy(Some(BuddyStatus::Connected(buddy)));
+        dive_computer.change_depth(MicrosDurationU32::millis(100));
+        assert_eq!(dive_computer.alarm(), Alarm::Medium);
+        assert!(format!("{}", dive_computer).contains("\nBUDDY:   18M    HIGH\n"));
+
+        dive_computer.set_buddy(Some(BuddyStatus::Lost(buddy)));
+        assert!(format!("{}", dive_computer).contains("\nBUDDY:     LINK LOST\n"));
+
+        for (status, unit) in [(BuddyStatus::Lost(buddy), Unit::Metric), (BuddyStatus::Connected(buddy), Unit::Imperial)] {
+            dive_computer.set_buddy(Some(status));
+            dive_computer.unit = unit;
+            let mut fast = UiBuffer::new();
+            dive_computer.render_fast(&mut fast).unwrap();
+            assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+        }
+        buddy.alarm = Alarm::Medium;
+        dive_computer.set_buddy(Some(BuddyStatus::Connected(buddy)));
+        let mut fast = UiBuffer::new();
+        dive_computer.render_fast(&mut fast).unwrap();
+        assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+        assert!(fast.as_str().contains("\nBUDDY:  59FT 2176PSI\n"));
+        assert_eq!(dive_computer.telemetry().pressure, 200);
+    }
+
+    #[test]
+    fn test_render_fast_matches_display() {
+        let mut dive_computer = DiveComputer::new();
+        dive_computer.depth = 12_345;
+        dive_computer.rate = -20;
+        dive_computer.edt = MicrosDurationU64::secs(3723);
+        dive_computer.ascent.update(-18_000, SIMULATION_STEP);
+
+        // Below 10 m and 10 ft depth has a decimal
+        for depth in [12_345, 7_349, 2_000] {
+            dive_computer.depth = depth;
+
+            for unit in [Unit::Metric, Unit::Imperial, Unit::Both] {
+                dive_computer.unit = unit;
+
+                for display in [DepthDisplay::Depth, DepthDisplay::Pressure, DepthDisplay::Both] {
+                    dive_computer.depth_display = display;
+
+                    let mut fast = UiBuffer::new();
+                    dive_computer.render_fast(&mut fast).unwrap();
+
+                    assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+                }
+            }
+        }
+        dive_computer.unit = Unit::Metric;
+        assert!(format!("{}", dive_computer).contains("DEPTH:          2.0M\n"));
+        assert!(format!("{}", dive_computer).contains("ASCENT:    SLOW DOWN\n"));
+        assert!(format!("{}", dive_computer).contains("EDT:         1:02:03\n"));
+
+        // Both units leave room for the imperial values in small text
+        dive_computer.unit = Unit::Both;
+        assert!(format!("{}", dive_computer).contains("DEPTH:    2.0M\nRATE:   -20M/M\n"));
+        assert_eq!(
+            dive_computer.secondary_readings(),
+            Some(SecondaryReadings {
+                depth: 2_000,
+                rate: -65,
+                unit: Unit::Imperial
+            })
+        );
+        dive_computer.unit = Unit::Metric;
+        assert_eq!(dive_computer.secondary_readings(), None);
+
+        // The ambient pressure instead of the depth or next to it
+        dive_computer.depth_display = DepthDisplay::Pressure;
+        assert!(format!("{}", dive_computer).contains("AMB:          1.2BAR\n"));
+        dive_computer.depth_display = DepthDisplay::Both;
+        assert!(format!("{}", dive_computer).contains("DEPTH:  2.0M  1.2BAR\n"));
+    }
+
+    #[test]
+    fn test_edt_formats() {
+        let mut dive_computer = DiveComputer::new();
+        // More than 100 hours
+        dive_computer.edt = MicrosDurationU64::secs(100 * 3600 + 59 * 60 + 7);
+
+        for edt_format in [EdtFormat::HoursMinutesSeconds, EdtFormat::MinutesSeconds] {
+            dive_computer.set_edt_format(edt_format);
+            let mut fast = UiBuffer::new();
+            dive_computer.render_fast(&mut fast).unwrap();
+            assert_eq!(fast.as_str(), format!("{}\n", dive_computer));
+        }
+
+        assert!(format!("{}", dive_computer).contains("EDT:         6059:07\n"));
+        dive_computer.set_edt_format(EdtFormat::MinutesSeconds.next());
+        assert!(format!("{}", dive_computer).contains("EDT:       100:59:07\n"));
+        assert_eq!(dive_computer.edt(), MicrosDurationU64::secs(363_547));
     }
 }
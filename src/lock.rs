@@ -0,0 +1,110 @@
+//! Button lock
+//!
+//! A dive computer on the wrist bangs against gear, which presses buttons nobody meant to press.
+//! Holding the X and Y chord for `LOCK_HOLD_TIME` locks the buttons: every press is ignored, except
+//! holding the chord again to unlock. A high alarm unlocks right away, the diver has to be able
+//! to react to it.
+
+use fugit::MicrosDurationU64;
+
+#[cfg(not(test))]
+use crate::info;
+#[cfg(test)]
+use log::info;
+
+use crate::{clock::Instant, Alarm};
+
+/// Time the lock chord has to be held to lock or unlock
+pub const LOCK_HOLD_TIME: MicrosDurationU64 = MicrosDurationU64::secs(2);
+
+#[derive(Debug, Clone, Copy)]
+pub struct ButtonLock {
+    locked: bool,
+    /// When the lock chord went down
+    held_since: Option<Instant>,
+    /// Whether the current hold already toggled the lock
+    toggled: bool,
+}
+
+impl ButtonLock {
+    pub const fn new() -> Self {
+        ButtonLock {
+            locked: false,
+            held_since: None,
+            toggled: false,
+        }
+    }
+
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    /// The lock chord is down at `now`, `pressed` when it just went down
+    ///
+    /// Returns whether this toggled the lock, once per hold.
+    pub fn hold(&mut self, now: Instant, pressed: bool) -> bool {
+        if pressed {
+            self.held_since = Some(now);
+            self.toggled = false;
+        }
+
+        let held_long_enough = self
+            .held_since
+            .is_some_and(|since| now.checked_duration_since(since).is_some_and(|held| held >= LOCK_HOLD_TIME));
+        if self.toggled || !held_long_enough {
+            return false;
+        }
+
+        self.locked = !self.locked;
+        self.toggled = true;
+        info!("buttons {}", if self.locked { "locked" } else { "unlocked" });
+        true
+    }
+
+    /// Unlock when `alarm` needs the diver's attention
+    pub fn update(&mut self, alarm: Alarm) {
+        if self.locked && alarm == Alarm::High {
+            self.locked = false;
+            info!("buttons unlocked by alarm");
+        }
+    }
+}
+
+impl Default for ButtonLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_hold_to_lock() {
+        let mut lock = ButtonLock::new();
+        let at = |millis: u64| Instant::from_ticks(millis * 1_000);
+
+        assert!(!lock.hold(at(0), true));
+        assert!(!lock.hold(at(1_900), false));
+        assert!(lock.hold(at(2_000), false));
+        assert!(lock.locked());
+        // Keeping the chord down doesn't unlock again
+        assert!(!lock.hold(at(5_000), false));
+        assert!(lock.locked());
+
+        // A short press doesn't count the time of the previous hold
+        assert!(!lock.hold(at(10_000), true));
+        assert!(!lock.hold(at(10_500), false));
+        assert!(lock.hold(at(12_000), false));
+        assert!(!lock.locked());
+
+        lock.hold(at(20_000), true);
+        lock.hold(at(22_000), false);
+        lock.update(Alarm::Medium);
+        assert!(lock.locked());
+        lock.update(Alarm::High);
+        assert!(!lock.locked());
+    }
+}
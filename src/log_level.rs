@@ -0,0 +1,135 @@
+//! Log verbosity
+//!
+//! defmt drops log levels at compile time through `DEFMT_LOG`. What is compiled in is filtered
+//! once more at runtime by the `info!`, `debug!`, `warn!` and `error!` macros of this crate, so
+//! the per-tick logs can be silenced in the field from the console without reflashing. Warnings
+//! count as errors. Host tests log through `log` and aren't filtered.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+/// Most verbose level that is logged, only loads and stores so it works on the Cortex-M0+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Debug as u8);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Info,
+    Debug,
+}
+
+impl LogLevel {
+    /// Level from its name on the console
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::log_level::LogLevel;
+    /// assert_eq!(LogLevel::parse("info"), Some(LogLevel::Info));
+    /// assert_eq!(LogLevel::parse("trace"), None);
+    /// ```
+    ///
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(LogLevel::Off),
+            "error" => Some(LogLevel::Error),
+            "info" => Some(LogLevel::Info),
+            "debug" => Some(LogLevel::Debug),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogLevel::Off => "OFF",
+            LogLevel::Error => "ERROR",
+            LogLevel::Info => "INFO",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+
+    /// Whether a log of `level` passes when this is the most verbose level
+    pub fn allows(self, level: LogLevel) -> bool {
+        level != LogLevel::Off && level <= self
+    }
+}
+
+/// Log up to `level` from now on
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn level() -> LogLevel {
+    match LEVEL.load(Ordering::Relaxed) {
+        0 => LogLevel::Off,
+        1 => LogLevel::Error,
+        2 => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+/// Whether logs of `level` are let through
+pub fn enabled(level: LogLevel) -> bool {
+    self::level().allows(level)
+}
+
+/// `defmt::error!` while the level allows errors
+#[cfg(not(test))]
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => {
+        if $crate::log_level::enabled($crate::log_level::LogLevel::Error) {
+            ::defmt::error!($($arg)*);
+        }
+    };
+}
+
+/// `defmt::warn!` while the level allows errors
+#[cfg(not(test))]
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => {
+        if $crate::log_level::enabled($crate::log_level::LogLevel::Error) {
+            ::defmt::warn!($($arg)*);
+        }
+    };
+}
+
+/// `defmt::info!` while the level allows info
+#[cfg(not(test))]
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => {
+        if $crate::log_level::enabled($crate::log_level::LogLevel::Info) {
+            ::defmt::info!($($arg)*);
+        }
+    };
+}
+
+/// `defmt::debug!` while the level allows debug
+#[cfg(not(test))]
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => {
+        if $crate::log_level::enabled($crate::log_level::LogLevel::Debug) {
+            ::defmt::debug!($($arg)*);
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        assert!(LogLevel::Info.allows(LogLevel::Error));
+        assert!(LogLevel::Info.allows(LogLevel::Info));
+        assert!(!LogLevel::Info.allows(LogLevel::Debug));
+        assert!(!LogLevel::Off.allows(LogLevel::Error));
+        // Nothing is logged at level off, whatever the setting
+        assert!(!LogLevel::Debug.allows(LogLevel::Off));
+    }
+}
@@ -0,0 +1,64 @@
+//! Bookmarks set by the diver during a dive
+//!
+//! A mark is exported as a UDDF waypoint, the dive time and depth it was set at. `marks` on the
+//! console lists the marks of the last dive that way, to paste into the `samples` of a UDDF
+//! file.
+
+use core::fmt;
+
+use fugit::SecsDurationU64;
+
+/// Number of marks kept per dive
+pub const MARK_COUNT: usize = 8;
+
+/// Longest waypoint line of a mark, newline included
+pub const MAX_WAYPOINT_LEN: usize = 69;
+
+/// A bookmark, e.g. "saw wreck" or "started deco"
+#[derive(Debug, Clone, Copy)]
+pub struct Mark {
+    /// Number of the mark in this dive, starting at 1
+    pub number: u32,
+    /// Elapsed dive time when it was set
+    pub edt: SecsDurationU64,
+    /// Depth in millimeters when it was set
+    pub depth: u32,
+}
+
+impl fmt::Display for Mark {
+    /// UDDF waypoint, dive time in seconds and depth in meters
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::mark::Mark;
+    /// let mark = Mark { number: 1, edt: fugit::SecsDurationU64::secs(300), depth: 18_240 };
+    /// assert_eq!(mark.to_string(), "<waypoint><divetime>300</divetime><depth>18.2</depth></waypoint>");
+    /// ```
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "<waypoint><divetime>{}</divetime><depth>{}.{}</depth></waypoint>",
+            self.edt.to_secs(),
+            self.depth / 1000,
+            self.depth % 1000 / 100
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_longest_waypoint() {
+        let mark = Mark {
+            number: MARK_COUNT as u32,
+            edt: SecsDurationU64::secs(999_999),
+            depth: 999_999,
+        };
+        assert_eq!(format!("{}\n", mark).len(), MAX_WAYPOINT_LEN);
+    }
+}
@@ -0,0 +1,340 @@
+//! Morse signaling at the surface
+//!
+//! The signal page sends a short message in Morse with the strobe and the buzzer, e.g. to call
+//! the boat after surfacing away from it. SOS and a few other messages are preset, a custom
+//! message of `MAX_CUSTOM_LEN` letters is entered letter by letter. The message repeats until
+//! it is stopped, and can only be started at the surface.
+//!
+//! `pattern` turns a message into on and off elements in `UNIT`s: a dot is 1 unit, a dash 3,
+//! the gap within a letter 1, between letters 3 and between words 7. The buzzer task ticks the
+//! signal and ORs it into the buzzer and strobe like the other cues.
+
+use core::{
+    fmt::{self, Write},
+    str::Chars,
+};
+
+use fugit::MicrosDurationU64;
+
+use crate::keymap::Action;
+
+/// Length of a dot, about 6 words per minute
+pub const UNIT: MicrosDurationU64 = MicrosDurationU64::millis(200);
+
+/// Preset messages, the custom message follows them
+pub const PRESETS: [&str; 4] = ["SOS", "OK", "BOAT", "HELP"];
+
+/// Most letters of the custom message
+pub const MAX_CUSTOM_LEN: usize = 8;
+
+/// Letters that can be entered, a space separates words
+const CHARSET: &[u8] = b" ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+const DOT: u32 = 1;
+const DASH: u32 = 3;
+const SYMBOL_GAP: u32 = 1;
+const LETTER_GAP: u32 = 3;
+const WORD_GAP: u32 = 7;
+
+/// Code of `letter`, `None` for letters that can't be sent
+pub fn code(letter: char) -> Option<&'static str> {
+    const LETTERS: [&str; 26] = [
+        ".-", "-...", "-.-.", "-..", ".", "..-.", "--.", "....", "..", ".---", "-.-", ".-..", "--", "-.", "---", ".--.", "--.-", ".-.", "...", "-", "..-", "...-",
+        ".--", "-..-", "-.--", "--..",
+    ];
+    const DIGITS: [&str; 10] = ["-----", ".----", "..---", "...--", "....-", ".....", "-....", "--...", "---..", "----."];
+    match letter.to_ascii_uppercase() {
+        letter @ 'A'..='Z' => Some(LETTERS[letter as usize - 'A' as usize]),
+        digit @ '0'..='9' => Some(DIGITS[digit as usize - '0' as usize]),
+        _ => None,
+    }
+}
+
+/// Part of a pattern
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Element {
+    /// Whether the strobe and buzzer are on
+    pub on: bool,
+    /// Length in `UNIT`s
+    pub units: u32,
+    /// Letter being sent, or the one before a gap
+    pub letter: char,
+}
+
+/// Elements of a message followed by a word gap, so it can be repeated
+pub struct Pattern<'a> {
+    letters: Chars<'a>,
+    letter: char,
+    /// Symbols of the letter not yet sent
+    symbols: &'a [u8],
+    /// Off units before the next symbol
+    gap: u32,
+}
+
+impl Iterator for Pattern<'_> {
+    type Item = Element;
+
+    fn next(&mut self) -> Option<Element> {
+        loop {
+            if let Some((&symbol, rest)) = self.symbols.split_first() {
+                if self.gap > 0 {
+                    return Some(self.off());
+                }
+                self.symbols = rest;
+                self.gap = if rest.is_empty() { LETTER_GAP } else { SYMBOL_GAP };
+                let units = if symbol == b'-' { DASH } else { DOT };
+                return Some(Element {
+                    on: true,
+                    units,
+                    letter: self.letter,
+                });
+            }
+
+            match self.letters.next() {
+                // Spaces at the start are skipped, later ones widen the letter gap
+                Some(' ') if self.gap > 0 => self.gap = WORD_GAP,
+                Some(letter) => {
+                    if let Some(code) = code(letter) {
+                        self.letter = letter.to_ascii_uppercase();
+                        self.symbols = code.as_bytes();
+                    }
+                }
+                None if self.gap > 0 => {
+                    self.gap = WORD_GAP;
+                    return Some(self.off());
+                }
+                None => return None,
+            }
+        }
+    }
+}
+
+impl Pattern<'_> {
+    fn off(&mut self) -> Element {
+        let units = core::mem::take(&mut self.gap);
+        Element {
+            on: false,
+            units,
+            letter: self.letter,
+        }
+    }
+}
+
+/// Pattern of `message`, letters that can't be sent are skipped
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::morse::pattern;
+/// let units: Vec<_> = pattern("ET").map(|element| (element.on, element.units)).collect();
+/// assert_eq!(units, [(true, 1), (false, 3), (true, 3), (false, 7)]);
+/// ```
+///
+pub fn pattern(message: &str) -> Pattern<'_> {
+    Pattern {
+        letters: message.chars(),
+        letter: ' ',
+        symbols: &[],
+        gap: 0,
+    }
+}
+
+/// State of the signal page: the selected message and item, and the time since sending started
+#[derive(Debug, Clone, Copy)]
+pub struct MorseSignal {
+    /// Index in `PRESETS`, one past them for the custom message
+    message: usize,
+    custom: [u8; MAX_CUSTOM_LEN],
+    /// Selected item, the message first and then the letters of the custom message
+    item: usize,
+    sending: Option<MicrosDurationU64>,
+}
+
+impl MorseSignal {
+    pub const fn new() -> Self {
+        MorseSignal {
+            message: 0,
+            custom: [b' '; MAX_CUSTOM_LEN],
+            item: 0,
+            sending: None,
+        }
+    }
+
+    /// Text of the selected message
+    pub fn message(&self) -> &str {
+        match PRESETS.get(self.message) {
+            Some(preset) => preset,
+            // Only letters of `CHARSET` are ever stored
+            None => core::str::from_utf8(&self.custom).unwrap_or_default().trim(),
+        }
+    }
+
+    pub fn sending(&self) -> bool {
+        self.sending.is_some()
+    }
+
+    /// Handle one of the signal page actions, sending only starts `at_surface` and the message
+    /// can't be changed while it is sent
+    pub fn perform(&mut self, action: Action, at_surface: bool) {
+        let custom = self.message == PRESETS.len();
+        match action {
+            Action::StartTimer => {
+                self.sending = match self.sending {
+                    Some(_) => None,
+                    None => (at_surface && !self.message().is_empty()).then_some(MicrosDurationU64::from_ticks(0)),
+                }
+            }
+            _ if self.sending.is_some() => {}
+            Action::SelectItem => self.item = if custom { (self.item + 1) % (MAX_CUSTOM_LEN + 1) } else { 0 },
+            Action::ChangeItem if self.item == 0 => self.message = (self.message + 1) % (PRESETS.len() + 1),
+            Action::ChangeItem => {
+                let letter = &mut self.custom[self.item - 1];
+                let index = CHARSET.iter().position(|c| c == letter).unwrap_or(0);
+                *letter = CHARSET[(index + 1) % CHARSET.len()];
+            }
+            _ => {}
+        }
+    }
+
+    /// Advance the signal by `duration`
+    pub fn tick(&mut self, duration: MicrosDurationU64) {
+        if let Some(sending) = &mut self.sending {
+            *sending += duration;
+        }
+    }
+
+    /// Element being sent
+    fn current(&self) -> Option<Element> {
+        let sending = self.sending?;
+        let length: u32 = pattern(self.message()).map(|element| element.units).sum();
+        let mut unit = (sending.to_micros() / UNIT.to_micros() % u64::from(length.max(1))) as u32;
+        pattern(self.message()).find(|element| match unit.checked_sub(element.units) {
+            Some(left) => {
+                unit = left;
+                false
+            }
+            None => true,
+        })
+    }
+
+    /// Whether the strobe and buzzer are on
+    pub fn on(&self) -> bool {
+        self.current().is_some_and(|element| element.on)
+    }
+}
+
+impl Default for MorseSignal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for MorseSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let custom = self.message == PRESETS.len();
+        // Write to buffer
+        writeln!(f, "Morse signal")?;
+        writeln!(f)?;
+
+        if let Some(element) = self.current() {
+            writeln!(f, "MESSAGE: {:>11}", if custom { "CUSTOM" } else { self.message() })?;
+            return write!(f, "SENDING: {:>9} {}", code(element.letter).unwrap_or_default(), element.letter);
+        }
+
+        let marker = |selected: bool| if selected { '>' } else { ' ' };
+        write!(f, "{}MESSAGE: {:>10}", marker(self.item == 0), if custom { "CUSTOM" } else { self.message() })?;
+        if custom {
+            // Spaces show as underscores so every letter can be found
+            write!(f, "\n{}TEXT: {:5}", marker(self.item > 0), "")?;
+            for &letter in &self.custom {
+                f.write_char(if letter == b' ' { '_' } else { letter as char })?;
+            }
+            if self.item > 0 {
+                write!(f, "\n{:>width$}", "^", width = 12 + self.item)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_sos() {
+        let units: Vec<_> = pattern("SOS").map(|element| (element.on, element.units)).collect();
+        assert_eq!(
+            units,
+            [
+                (true, 1),
+                (false, 1),
+                (true, 1),
+                (false, 1),
+                (true, 1),
+                (false, 3),
+                (true, 3),
+                (false, 1),
+                (true, 3),
+                (false, 1),
+                (true, 3),
+                (false, 3),
+                (true, 1),
+                (false, 1),
+                (true, 1),
+                (false, 1),
+                (true, 1),
+                (false, 7)
+            ]
+        );
+
+        let mut signal = MorseSignal::new();
+        assert_eq!(format!("{}", signal), "Morse signal\n\n>MESSAGE:        SOS");
+        // Not underwater
+        signal.perform(Action::StartTimer, false);
+        assert!(!signal.sending());
+
+        signal.perform(Action::StartTimer, true);
+        assert!(signal.on());
+        signal.tick(UNIT);
+        assert!(!signal.on());
+        // Into the first dash of the O
+        signal.tick(UNIT * 7);
+        assert!(signal.on());
+        assert_eq!(format!("{}", signal), "Morse signal\n\nMESSAGE:         SOS\nSENDING:       --- O");
+        // The message repeats after a word gap
+        signal.tick(UNIT * 25);
+        assert!(!signal.on());
+        signal.tick(UNIT);
+        assert!(signal.on());
+
+        signal.perform(Action::StartTimer, true);
+        assert!(!signal.on());
+    }
+
+    #[test]
+    fn test_custom_message() {
+        let mut signal = MorseSignal::new();
+        for _ in 0..PRESETS.len() {
+            signal.perform(Action::ChangeItem, true);
+        }
+        // Nothing to send yet
+        signal.perform(Action::StartTimer, true);
+        assert!(!signal.sending());
+
+        signal.perform(Action::SelectItem, true);
+        signal.perform(Action::SelectItem, true);
+        for _ in 0..9 {
+            signal.perform(Action::ChangeItem, true);
+        }
+        signal.perform(Action::SelectItem, true);
+        signal.perform(Action::SelectItem, true);
+        signal.perform(Action::ChangeItem, true);
+        assert_eq!(signal.message(), "I A");
+        assert_eq!(format!("{}", signal), "Morse signal\n\n MESSAGE:     CUSTOM\n>TEXT:      _I_A____\n               ^");
+
+        let units: Vec<_> = pattern(signal.message()).map(|element| (element.on, element.units)).collect();
+        assert_eq!(units, [(true, 1), (false, 1), (true, 1), (false, 7), (true, 1), (false, 1), (true, 3), (false, 7)]);
+    }
+}
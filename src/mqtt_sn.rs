@@ -0,0 +1,244 @@
+//! MQTT-SN gateway telemetry
+//!
+//! With the `mqtt-gateway` feature the buddy UART carries MQTT-SN messages for a bridge on a
+//! host, which republishes them to a broker, e.g. for a classroom dashboard. Every
+//! `buddy::SEND_INTERVAL` each `Topic` is published with QoS 0 as ASCII text, and every
+//! `HEARTBEAT_INTERVAL` a PINGREQ with `CLIENT_ID` tells the bridge the board is alive.
+//!
+//! The topics are predefined: a PUBLISH carries the id of `Topic::id`, the bridge publishes to
+//! `Topic::name`. Every message is COBS encoded and ends with a zero byte like the telemetry
+//! frames, so the bridge can find the start of the next one after a lost byte.
+
+use fugit::MicrosDurationU64;
+
+use crate::{
+    clock::{Clock, Instant},
+    deco::DecoModel,
+    text_buffer::TextBuffer,
+    units::depth_digits,
+    Alarm, DiveComputer, Unit,
+};
+
+/// Client id of the heartbeat
+pub const CLIENT_ID: &str = "divemaster";
+
+/// Time between two heartbeats
+pub const HEARTBEAT_INTERVAL: MicrosDurationU64 = MicrosDurationU64::secs(5);
+
+/// Largest encoded message in bytes, including the zero at the end
+pub const MAX_MESSAGE_LEN: usize = 32;
+
+/// Largest message before encoding
+const MAX_RAW_LEN: usize = MAX_MESSAGE_LEN - 2;
+
+/// Message type of PUBLISH
+const PUBLISH: u8 = 0x0C;
+
+/// Message type of PINGREQ
+const PINGREQ: u8 = 0x16;
+
+/// Flags of a PUBLISH: not a duplicate, QoS 0, not retained, predefined topic id
+const PUBLISH_FLAGS: u8 = 0b0000_0001;
+
+/// Value published to a topic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Topic {
+    /// Meters with one decimal below 10 m
+    Depth,
+    /// Meters per minute, negative going up
+    Rate,
+    /// Liters at the surface pressure
+    Air,
+    /// Tank pressure in bar
+    Pressure,
+    /// `Alarm::as_str`
+    Alarm,
+}
+
+impl Topic {
+    pub const ALL: [Topic; 5] = [Topic::Depth, Topic::Rate, Topic::Air, Topic::Pressure, Topic::Alarm];
+
+    /// Predefined topic id
+    pub fn id(&self) -> u16 {
+        match self {
+            Topic::Depth => 1,
+            Topic::Rate => 2,
+            Topic::Air => 3,
+            Topic::Pressure => 4,
+            Topic::Alarm => 5,
+        }
+    }
+
+    /// Topic name the bridge publishes to
+    pub fn name(&self) -> &'static str {
+        match self {
+            Topic::Depth => "divemaster/depth",
+            Topic::Rate => "divemaster/rate",
+            Topic::Air => "divemaster/air",
+            Topic::Pressure => "divemaster/pressure",
+            Topic::Alarm => "divemaster/alarm",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageTooLong;
+
+/// Snapshot of the published values
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Readings {
+    /// Depth in millimeters
+    pub depth: u32,
+    /// Rate in meters per minute
+    pub rate: i32,
+    /// Air in centiliters
+    pub air: u32,
+    /// Tank pressure in centibar
+    pub pressure: u32,
+    pub alarm: Alarm,
+}
+
+impl Readings {
+    pub fn of<C: Clock, M: DecoModel>(dive_computer: &DiveComputer<C, M>) -> Self {
+        Readings {
+            depth: dive_computer.depth(),
+            rate: dive_computer.rate(),
+            air: dive_computer.air(),
+            pressure: dive_computer.tank_pressure(),
+            alarm: dive_computer.alarm(),
+        }
+    }
+
+    /// Text published to `topic`
+    fn value(&self, topic: Topic) -> TextBuffer<16> {
+        let mut value = TextBuffer::new();
+        // The values stay well within 16 characters
+        match topic {
+            Topic::Depth => write!(value, "{}", depth_digits(self.depth, Unit::Metric)),
+            Topic::Rate => write!(value, "{}", self.rate),
+            Topic::Air => write!(value, "{}", self.air / 100),
+            Topic::Pressure => write!(value, "{}", self.pressure / 100),
+            Topic::Alarm => write!(value, "{}", self.alarm.as_str()),
+        }
+        value
+    }
+}
+
+/// COBS encode `raw` into `buf` with the zero at the end
+fn frame<'a>(raw: &[u8], buf: &'a mut [u8; MAX_MESSAGE_LEN]) -> Result<&'a [u8], MessageTooLong> {
+    let len = cobs::try_encode(raw, &mut buf[..MAX_MESSAGE_LEN - 1]).map_err(|_| MessageTooLong)?;
+    buf[len] = 0;
+    Ok(&buf[..=len])
+}
+
+/// QoS 0 PUBLISH of `value` to the predefined `topic`
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::mqtt_sn::{publish, Topic, MAX_MESSAGE_LEN};
+/// let mut buf = [0; MAX_MESSAGE_LEN];
+/// let message = publish(Topic::Alarm, "HIGH", &mut buf).unwrap();
+/// // COBS encoded: length 11, PUBLISH, flags, topic id 5, message id 0 and the value
+/// assert_eq!(message, [4, 11, 0x0C, 1, 2, 5, 1, 5, b'H', b'I', b'G', b'H', 0]);
+/// ```
+///
+pub fn publish<'a>(topic: Topic, value: &str, buf: &'a mut [u8; MAX_MESSAGE_LEN]) -> Result<&'a [u8], MessageTooLong> {
+    let len = 7 + value.len();
+    if len > MAX_RAW_LEN {
+        return Err(MessageTooLong);
+    }
+
+    let mut raw = [0; MAX_RAW_LEN];
+    let [id_high, id_low] = topic.id().to_be_bytes();
+    // Message id 0, it is only used from QoS 1 up
+    raw[..7].copy_from_slice(&[len as u8, PUBLISH, PUBLISH_FLAGS, id_high, id_low, 0, 0]);
+    raw[7..len].copy_from_slice(value.as_bytes());
+    frame(&raw[..len], buf)
+}
+
+/// PINGREQ with `CLIENT_ID`
+pub fn ping(buf: &mut [u8; MAX_MESSAGE_LEN]) -> Result<&[u8], MessageTooLong> {
+    let len = 2 + CLIENT_ID.len();
+    let mut raw = [0; MAX_RAW_LEN];
+    raw[..2].copy_from_slice(&[len as u8, PINGREQ]);
+    raw[2..len].copy_from_slice(CLIENT_ID.as_bytes());
+    frame(&raw[..len], buf)
+}
+
+/// Sending side of the bridge link
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Gateway {
+    last_heartbeat: Option<Instant>,
+}
+
+impl Gateway {
+    pub const fn new() -> Self {
+        Gateway { last_heartbeat: None }
+    }
+
+    /// Pass the messages for `readings` at `now` to `send`, a heartbeat first when it is due
+    pub fn publish(&mut self, readings: &Readings, now: Instant, mut send: impl FnMut(&[u8])) {
+        let mut buf = [0; MAX_MESSAGE_LEN];
+
+        let due = self
+            .last_heartbeat
+            .is_none_or(|last| now.checked_duration_since(last).is_some_and(|since| since >= HEARTBEAT_INTERVAL));
+        if due {
+            self.last_heartbeat = Some(now);
+            if let Ok(message) = ping(&mut buf) {
+                send(message);
+            }
+        }
+
+        for topic in Topic::ALL {
+            if let Ok(message) = publish(topic, readings.value(topic).as_str(), &mut buf) {
+                send(message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// Decoded messages sent for `readings` at `now`
+    fn sent(gateway: &mut Gateway, readings: &Readings, now: Instant) -> Vec<Vec<u8>> {
+        let mut messages = Vec::new();
+        gateway.publish(readings, now, |message| {
+            let mut raw = [0; MAX_MESSAGE_LEN];
+            let len = cobs::decode(&message[..message.len() - 1], &mut raw).unwrap();
+            messages.push(raw[..len].to_vec());
+        });
+        messages
+    }
+
+    #[test]
+    fn test_publish_with_heartbeat() {
+        let readings = Readings {
+            depth: 7_349,
+            rate: -9,
+            air: 150_050,
+            pressure: 15_005,
+            alarm: Alarm::AirReserve,
+        };
+        let mut gateway = Gateway::new();
+        let now = Instant::from_ticks(0);
+
+        let messages = sent(&mut gateway, &readings, now);
+        assert_eq!(messages[0], b"\x0c\x16divemaster");
+        let values: Vec<_> = messages[1..].iter().map(|message| (message[4], &message[7..])).collect();
+        assert_eq!(values, [(1, &b"7.3"[..]), (2, b"-9"), (3, b"1500"), (4, b"150"), (5, b"RESERV")]);
+        // The length covers the whole message
+        assert!(messages.iter().all(|message| message[0] as usize == message.len()));
+
+        // No heartbeat until it is due
+        assert_eq!(sent(&mut gateway, &readings, now + MicrosDurationU64::secs(1)).len(), 5);
+        assert_eq!(sent(&mut gateway, &readings, now + HEARTBEAT_INTERVAL).len(), 6);
+
+        let mut buf = [0; MAX_MESSAGE_LEN];
+        assert_eq!(publish(Topic::Alarm, "FAR TOO LONG FOR ONE MESSAGE", &mut buf), Err(MessageTooLong));
+    }
+}
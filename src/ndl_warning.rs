@@ -0,0 +1,239 @@
+//! No-decompression limit warnings
+//!
+//! Up to `MAX_NDL_WARNINGS` thresholds, 5, 3 and 1 minutes unless the diver sets others on the
+//! settings page. When the NDL drops below one, the buzzer chimes: once for the highest
+//! threshold, twice for the next and three times for the last, so the chime says how close the
+//! limit is without looking. From the first threshold on, the title line of the main page counts
+//! down the NDL.
+//!
+//! A threshold is armed again once the NDL is `REARM_MARGIN` above it, e.g. after going
+//! shallower, so swell around the limit doesn't keep chiming.
+
+use fugit::SecsDurationU32;
+use serde::{Deserialize, Serialize};
+
+use crate::{buzzer::BEEP_LENGTH, clock::Instant};
+
+/// Number of NDL warnings
+pub const MAX_NDL_WARNINGS: usize = 3;
+
+/// Highest threshold that can be set, in minutes
+const MAX_MINUTES: u8 = 10;
+
+/// NDL above a threshold before it can warn again
+pub const REARM_MARGIN: SecsDurationU32 = SecsDurationU32::minutes(1);
+
+/// Thresholds in minutes of NDL left, `None` when off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NdlWarnings {
+    pub minutes: [Option<u8>; MAX_NDL_WARNINGS],
+}
+
+impl NdlWarnings {
+    /// At 5, 3 and 1 minutes
+    pub const fn new() -> Self {
+        NdlWarnings {
+            minutes: [Some(5), Some(3), Some(1)],
+        }
+    }
+
+    /// Whether the settings page can set the thresholds
+    pub fn is_valid(&self) -> bool {
+        self.minutes.iter().flatten().all(|&minutes| 0 < minutes && minutes <= MAX_MINUTES)
+    }
+
+    /// Next threshold of warning `index`: off, then every minute up to `MAX_MINUTES`
+    pub fn step(&mut self, index: usize) {
+        self.minutes[index] = match self.minutes[index] {
+            None => Some(1),
+            Some(minutes) if minutes >= MAX_MINUTES => None,
+            Some(minutes) => Some(minutes + 1),
+        };
+    }
+
+    /// Thresholds from high to low, without duplicates, the ones that are off last
+    fn sorted(&self) -> [Option<u8>; MAX_NDL_WARNINGS] {
+        let mut sorted = [None; MAX_NDL_WARNINGS];
+        let mut len = 0;
+        for minutes in (1..=MAX_MINUTES).rev() {
+            if self.minutes.contains(&Some(minutes)) {
+                sorted[len] = Some(minutes);
+                len += 1;
+            }
+        }
+        sorted
+    }
+}
+
+impl Default for NdlWarnings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Thresholds crossed during the current dive and the last chime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NdlCountdown {
+    warnings: NdlWarnings,
+    /// `NdlWarnings::sorted` of `warnings`
+    thresholds: [Option<u8>; MAX_NDL_WARNINGS],
+    /// Number of thresholds the NDL is below
+    level: usize,
+    /// Number of beeps and when they started
+    chime: Option<(usize, Instant)>,
+}
+
+impl NdlCountdown {
+    pub fn new(warnings: NdlWarnings) -> Self {
+        NdlCountdown {
+            warnings,
+            thresholds: warnings.sorted(),
+            level: 0,
+            chime: None,
+        }
+    }
+
+    /// Warn at `warnings` from now on, the crossed thresholds are forgotten when they change
+    pub fn set_warnings(&mut self, warnings: NdlWarnings) {
+        if warnings != self.warnings {
+            *self = NdlCountdown {
+                chime: self.chime,
+                ..NdlCountdown::new(warnings)
+            };
+        }
+    }
+
+    /// Check the thresholds, `within(margin)` tells whether the NDL is below `margin`
+    ///
+    /// Returns the threshold in minutes when one is crossed, `now` is only read then for the
+    /// chime. At most two thresholds are checked,
+    /// the next one down and the last one crossed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{clock::Instant, ndl_warning::{NdlCountdown, NdlWarnings}};
+    /// let mut countdown = NdlCountdown::new(NdlWarnings::new());
+    /// let now = || Instant::from_ticks(0);
+    /// // 4 minutes left
+    /// let within = |margin: fugit::SecsDurationU32| margin.to_secs() > 240;
+    /// assert_eq!(countdown.update(within, now), Some(5));
+    /// assert_eq!(countdown.update(within, now), None);
+    /// assert!(countdown.active());
+    /// ```
+    ///
+    pub fn update(&mut self, within: impl Fn(SecsDurationU32) -> bool, now: impl FnOnce() -> Instant) -> Option<u8> {
+        if let Some(next) = self.thresholds.get(self.level).copied().flatten() {
+            if within(SecsDurationU32::minutes(u32::from(next))) {
+                self.level += 1;
+                self.chime = Some((self.level, now()));
+                return Some(next);
+            }
+        }
+
+        if let Some(last) = self.level.checked_sub(1).and_then(|index| self.thresholds[index]) {
+            if !within(SecsDurationU32::minutes(u32::from(last)) + REARM_MARGIN) {
+                self.level -= 1;
+            }
+        }
+        None
+    }
+
+    /// Arm every threshold again, at the surface
+    pub fn reset(&mut self) {
+        self.level = 0;
+    }
+
+    /// Whether the NDL is below a threshold
+    pub fn active(&self) -> bool {
+        self.level > 0
+    }
+
+    /// Whether the chime of the last threshold sounds at `now`, beeps are `BEEP_LENGTH` apart
+    pub fn chiming(&self, now: Instant) -> bool {
+        self.chime.is_some_and(|(beeps, at)| {
+            now.checked_duration_since(at).is_some_and(|since| {
+                let slot = (since.to_micros() / BEEP_LENGTH.to_micros()) as usize;
+                slot < 2 * beeps && slot.is_multiple_of(2)
+            })
+        })
+    }
+}
+
+impl Default for NdlCountdown {
+    fn default() -> Self {
+        Self::new(NdlWarnings::new())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use fugit::MicrosDurationU64;
+
+    use super::*;
+
+    /// Whether an NDL of `seconds` is below `margin`
+    fn ndl(seconds: u32) -> impl Fn(SecsDurationU32) -> bool {
+        move |margin| seconds < margin.to_secs()
+    }
+
+    #[test]
+    fn test_escalating_chimes() {
+        let mut countdown = NdlCountdown::default();
+        let start = Instant::from_ticks(0);
+        assert_eq!(countdown.update(ndl(600), || start), None);
+        assert!(!countdown.active());
+
+        assert_eq!(countdown.update(ndl(299), || start), Some(5));
+        assert_eq!(countdown.update(ndl(200), || start), None);
+        let at = start + MicrosDurationU64::secs(60);
+        assert_eq!(countdown.update(ndl(179), || at), Some(3));
+
+        // Two beeps with a pause between them
+        let beeps: Vec<bool> = (0..6).map(|slot| countdown.chiming(at + BEEP_LENGTH * slot)).collect();
+        assert_eq!(beeps, [true, false, true, false, false, false]);
+        assert!(!countdown.chiming(start));
+
+        // Shallower the NDL grows, 3 minutes only warns again after passing 4 minutes
+        assert_eq!(countdown.update(ndl(200), || at), None);
+        assert_eq!(countdown.update(ndl(179), || at), None);
+        assert_eq!(countdown.update(ndl(240), || at), None);
+        assert_eq!(countdown.update(ndl(179), || at), Some(3));
+        assert_eq!(countdown.update(ndl(59), || at), Some(1));
+        let beeps = (0..6).filter(|&slot| countdown.chiming(at + BEEP_LENGTH * slot)).count();
+        assert_eq!(beeps, 3);
+        assert_eq!(countdown.update(ndl(0), || at), None);
+
+        countdown.reset();
+        assert!(!countdown.active());
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let mut warnings = NdlWarnings::new();
+        for _ in 0..5 {
+            warnings.step(2);
+        }
+        warnings.step(0);
+        // Duplicates warn once
+        assert_eq!(warnings.minutes, [Some(6), Some(3), Some(6)]);
+        assert_eq!(warnings.sorted(), [Some(6), Some(3), None]);
+        assert!(warnings.is_valid());
+
+        for _ in 0..5 {
+            warnings.step(0);
+        }
+        assert_eq!(warnings.minutes[0], None);
+        warnings.minutes[1] = Some(11);
+        assert!(!warnings.is_valid());
+
+        // Changed thresholds start over, the same ones don't
+        let mut countdown = NdlCountdown::default();
+        countdown.update(ndl(200), || Instant::from_ticks(0));
+        countdown.set_warnings(NdlWarnings::new());
+        assert!(countdown.active());
+        countdown.set_warnings(warnings);
+        assert!(!countdown.active());
+    }
+}
@@ -0,0 +1,263 @@
+//! Next dive alarm
+//!
+//! After a dive, tapping X on the planner page goes through the targets of an alarm telling the
+//! diver the next dive is ready: when the planned dive needs no stops again, or after a fixed
+//! surface interval. The alarm is armed right away and rings once, a new dive disarms it.
+//!
+//! The surface interval counts simulated time, `NextDiveAlarm::wait` converts what is left to
+//! clock time for the RTC alarm that wakes the firmware. Off-gassing at the surface is simulated
+//! in steps of `RECOVERY_STEP`, so the no-stop target rings up to that much late, never early.
+
+use core::fmt;
+
+use fugit::{MicrosDurationU64, SecsDurationU32};
+
+use crate::{
+    ascent::AscentLimit,
+    clock::TimeScale,
+    deco::{DecoModel, Gas},
+    planner::Plan,
+};
+
+/// Surface time simulated at once while waiting for the plan to need no stops
+pub const RECOVERY_STEP: SecsDurationU32 = SecsDurationU32::minutes(10);
+
+/// Longest surface interval simulated, a plan that still needs stops after it is never ready
+pub const MAX_RECOVERY: SecsDurationU32 = SecsDurationU32::hours(24);
+
+/// How long the alarm rings
+pub const RING_LENGTH: MicrosDurationU64 = MicrosDurationU64::secs(10);
+
+/// When the next dive is ready
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ReadyTarget {
+    #[default]
+    Off,
+    /// The plan on the planner page needs no stops
+    NoStop,
+    Minutes30,
+    Hour1,
+    Hour2,
+}
+
+impl ReadyTarget {
+    /// Next target, wrapping around to off
+    pub fn next(self) -> Self {
+        match self {
+            ReadyTarget::Off => ReadyTarget::NoStop,
+            ReadyTarget::NoStop => ReadyTarget::Minutes30,
+            ReadyTarget::Minutes30 => ReadyTarget::Hour1,
+            ReadyTarget::Hour1 => ReadyTarget::Hour2,
+            ReadyTarget::Hour2 => ReadyTarget::Off,
+        }
+    }
+
+    /// Fixed surface interval of the target
+    pub fn interval(&self) -> Option<SecsDurationU32> {
+        match self {
+            ReadyTarget::Minutes30 => Some(SecsDurationU32::minutes(30)),
+            ReadyTarget::Hour1 => Some(SecsDurationU32::hours(1)),
+            ReadyTarget::Hour2 => Some(SecsDurationU32::hours(2)),
+            ReadyTarget::Off | ReadyTarget::NoStop => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReadyTarget::Off => "OFF",
+            ReadyTarget::NoStop => "NO STOP",
+            ReadyTarget::Minutes30 => "30 MIN",
+            ReadyTarget::Hour1 => "1 H",
+            ReadyTarget::Hour2 => "2 H",
+        }
+    }
+}
+
+/// Surface time after which `plan` at `limit` needs no stops with the tissues of `model`
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::{ascent::AscentLimit, deco::NoDeco, next_dive::recovery_time, planner::Plan};
+/// // Without a model every plan is ready right away
+/// assert_eq!(recovery_time(&NoDeco, &Plan::new(), AscentLimit::new()).map(|time| time.to_secs()), Some(0));
+/// ```
+///
+pub fn recovery_time<M: DecoModel + Clone>(model: &M, plan: &Plan, limit: AscentLimit) -> Option<SecsDurationU32> {
+    let mut model = model.clone();
+    let mut time = SecsDurationU32::secs(0);
+    while !plan.evaluate(&model, limit).no_stop {
+        if time >= MAX_RECOVERY {
+            return None;
+        }
+        model.tick(0, RECOVERY_STEP.convert(), Gas::AIR);
+        time += RECOVERY_STEP;
+    }
+    Some(time)
+}
+
+/// Alarm for the end of the surface interval
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NextDiveAlarm {
+    target: ReadyTarget,
+    /// Surface interval at which the next dive is ready, `None` while not armed
+    ready_at: Option<SecsDurationU32>,
+    /// Ringing time left
+    ringing: MicrosDurationU64,
+}
+
+impl NextDiveAlarm {
+    pub const fn new() -> Self {
+        NextDiveAlarm {
+            target: ReadyTarget::Off,
+            ready_at: None,
+            ringing: MicrosDurationU64::micros(0),
+        }
+    }
+
+    pub fn target(&self) -> ReadyTarget {
+        self.target
+    }
+
+    /// Go to the next target and arm the alarm for it
+    ///
+    /// Only armed at the surface after a dive, and for the no-stop target only when the plan
+    /// needs no stops within `MAX_RECOVERY` at `limit`.
+    pub fn next_target<M: DecoModel + Clone>(&mut self, surface_interval: Option<SecsDurationU32>, model: &M, plan: &Plan, limit: AscentLimit) {
+        self.target = self.target.next();
+        self.ringing = MicrosDurationU64::micros(0);
+        self.ready_at = surface_interval.and_then(|surface_interval| {
+            let wait = match self.target {
+                ReadyTarget::Off => None,
+                ReadyTarget::NoStop => recovery_time(model, plan, limit),
+                target => target.interval(),
+            }?;
+            Some(surface_interval + wait)
+        });
+    }
+
+    /// Surface time left until the next dive is ready, `None` while not armed
+    pub fn remaining(&self, surface_interval: Option<SecsDurationU32>) -> Option<SecsDurationU32> {
+        let (ready_at, surface_interval) = (self.ready_at?, surface_interval?);
+        Some(ready_at.checked_sub(surface_interval).unwrap_or(SecsDurationU32::secs(0)))
+    }
+
+    /// Clock time until the alarm is due at `time_scale`, `None` while not armed
+    pub fn wait(&self, surface_interval: Option<SecsDurationU32>, time_scale: TimeScale) -> Option<MicrosDurationU64> {
+        let remaining = self.remaining(surface_interval)?;
+        Some(MicrosDurationU64::secs(u64::from(remaining.to_secs().div_ceil(time_scale.factor()))))
+    }
+
+    /// Start ringing when the next dive is ready, returns whether it started
+    ///
+    /// A dive, when there is no `surface_interval`, disarms the alarm.
+    pub fn check(&mut self, surface_interval: Option<SecsDurationU32>) -> bool {
+        let Some(ready_at) = self.ready_at else {
+            return false;
+        };
+
+        match surface_interval {
+            Some(surface_interval) if surface_interval < ready_at => false,
+            due => {
+                self.target = ReadyTarget::Off;
+                self.ready_at = None;
+                if due.is_some() {
+                    self.ringing = RING_LENGTH;
+                }
+                due.is_some()
+            }
+        }
+    }
+
+    /// Count down the ringing by `interval`
+    pub fn tick(&mut self, interval: MicrosDurationU64) {
+        self.ringing = self.ringing.checked_sub(interval).unwrap_or(MicrosDurationU64::micros(0));
+    }
+
+    pub fn ringing(&self) -> bool {
+        self.ringing.ticks() > 0
+    }
+
+    /// Line of the planner page at `surface_interval`
+    pub fn line(&self, surface_interval: Option<SecsDurationU32>) -> ReadyLine {
+        ReadyLine {
+            target: self.target,
+            remaining: self.remaining(surface_interval),
+        }
+    }
+}
+
+impl Default for NextDiveAlarm {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// State of the alarm, the time left once it is armed
+pub struct ReadyLine {
+    target: ReadyTarget,
+    remaining: Option<SecsDurationU32>,
+}
+
+impl fmt::Display for ReadyLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.target, self.remaining) {
+            (ReadyTarget::Off, _) => write!(f, "READY: {:>13}", "OFF"),
+            (_, Some(remaining)) => {
+                let minutes = remaining.to_secs().div_ceil(60);
+                write!(f, "READY: {:>10}:{:02}", minutes / 60, minutes % 60)
+            }
+            // Not after a dive, or the plan needs stops for too long
+            (target, None) => write!(f, "READY: {:>11} ?", target.as_str()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "deco"))]
+mod test {
+
+    use fugit::MicrosDurationU32;
+
+    use super::*;
+    use crate::deco::zhl16::Zhl16;
+
+    #[test]
+    fn test_ready_after_recovery() {
+        // 40 minutes at 30 m needs stops, a surface interval later it doesn't
+        let mut model = Zhl16::default();
+        model.tick(30_000, MicrosDurationU32::minutes(40), Gas::AIR);
+        let plan = Plan::new();
+        let recovery = recovery_time(&model, &plan, AscentLimit::new()).unwrap();
+        assert!(recovery > SecsDurationU32::minutes(0));
+        assert!(recovery < MAX_RECOVERY);
+
+        let mut alarm = NextDiveAlarm::new();
+        let surfaced = Some(SecsDurationU32::minutes(5));
+        alarm.next_target(surfaced, &model, &plan, AscentLimit::new());
+        assert_eq!(alarm.target(), ReadyTarget::NoStop);
+        assert_eq!(alarm.remaining(surfaced), Some(recovery));
+        assert_eq!(
+            alarm.wait(surfaced, TimeScale::X60),
+            Some(MicrosDurationU64::secs(u64::from(recovery.to_secs() / 60)))
+        );
+        assert!(!alarm.check(surfaced));
+
+        let ready = Some(SecsDurationU32::minutes(5) + recovery);
+        assert!(alarm.check(ready));
+        assert!(alarm.ringing());
+        assert_eq!(format!("{}", alarm.line(ready)), "READY:           OFF");
+        alarm.tick(RING_LENGTH);
+        assert!(!alarm.ringing());
+
+        // A fixed interval, until the next dive starts
+        alarm.next_target(surfaced, &model, &plan, AscentLimit::new());
+        alarm.next_target(surfaced, &model, &plan, AscentLimit::new());
+        assert_eq!(format!("{}", alarm.line(surfaced)), "READY:          0:30");
+        assert!(!alarm.check(None));
+        assert_eq!(alarm.target(), ReadyTarget::Off);
+
+        // Not before the first dive
+        alarm.next_target(None, &model, &plan, AscentLimit::new());
+        assert_eq!(format!("{}", alarm.line(None)), "READY:     NO STOP ?");
+    }
+}
@@ -0,0 +1,278 @@
+//! Lifetime statistics
+//!
+//! Counts the dives, the bottom time and the deepest depth over the life of the device. A dive
+//! is counted when the diver surfaces after having been at least `MIN_DIVE_DEPTH` deep, so
+//! splashing around at the surface doesn't add dives. The totals are meant to be kept with
+//! `storage`, which only writes when they changed.
+//!
+//! Every finished dive is also classified from its profile, so the last dive shows what kind of
+//! dive it was: a missed stop makes it a violation, a ceiling a decompression dive, a dive
+//! shorter than `FREEDIVE_MAX_DURATION` a free dive on one breath, and one that stayed above
+//! `TRAINING_MAX_DEPTH` a training dive in confined water.
+
+use core::fmt;
+
+use fugit::{MicrosDurationU32, MicrosDurationU64, SecsDurationU32};
+use serde::{Deserialize, Serialize};
+
+use crate::{checklist::ChecklistOutcome, deco::Zhl16Variant};
+
+/// Depth in millimeters a dive has to reach to be counted
+pub const MIN_DIVE_DEPTH: u32 = 1_000;
+
+/// Longest dive that counts as a free dive
+pub const FREEDIVE_MAX_DURATION: SecsDurationU32 = SecsDurationU32::minutes(3);
+
+/// Deepest depth in millimeters of a training dive
+pub const TRAINING_MAX_DEPTH: u32 = 6_000;
+
+/// What kind of dive a finished dive was
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiveKind {
+    /// Shallow, e.g. skills in a pool
+    Training,
+    /// Within the no-decompression limit
+    NoDeco,
+    /// The model asked for stops
+    Deco,
+    /// Short enough for a single breath
+    Freedive,
+    /// Surfaced with a ceiling
+    Violation,
+}
+
+impl DiveKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DiveKind::Training => "TRAINING",
+            DiveKind::NoDeco => "NO DECO",
+            DiveKind::Deco => "DECO",
+            DiveKind::Freedive => "FREEDIVE",
+            DiveKind::Violation => "VIOLATION",
+        }
+    }
+}
+
+/// A finished dive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiveSummary {
+    /// Time underwater
+    pub duration: SecsDurationU32,
+    /// Deepest depth in millimeters
+    pub max_depth: u32,
+    pub kind: DiveKind,
+    /// Coldest water temperature in tenths of a degree Celsius, when it was measured
+    pub min_temperature: Option<i16>,
+    /// Coefficients of the decompression model, to reproduce the dive, `None` for other models than ZHL-16
+    pub deco_variant: Option<Zhl16Variant>,
+    /// How the checklist before the dive went
+    pub checklist: ChecklistOutcome,
+}
+
+/// What happened during a dive, recorded a step at a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiveProfile {
+    duration: MicrosDurationU64,
+    max_depth: u32,
+    /// The model had a ceiling at some point
+    deco: bool,
+    missed_stop: bool,
+    /// Coldest water temperature in tenths of a degree Celsius
+    min_temperature: Option<i16>,
+}
+
+impl DiveProfile {
+    pub const fn new() -> Self {
+        DiveProfile {
+            duration: MicrosDurationU64::from_ticks(0),
+            max_depth: 0,
+            deco: false,
+            missed_stop: false,
+            min_temperature: None,
+        }
+    }
+
+    /// Spend `duration` at `depth` with a `ceiling`, both in millimeters
+    pub fn record(&mut self, depth: u32, ceiling: u32, duration: MicrosDurationU32) {
+        self.duration += MicrosDurationU64::from(duration);
+        self.max_depth = self.max_depth.max(depth);
+        self.deco |= ceiling > 0;
+    }
+
+    /// The water was `tenths` of a degree Celsius
+    pub fn record_temperature(&mut self, tenths: i16) {
+        self.min_temperature = Some(self.min_temperature.map_or(tenths, |min| min.min(tenths)));
+    }
+
+    /// The diver surfaced with a ceiling
+    pub fn miss_stop(&mut self) {
+        self.missed_stop = true;
+    }
+
+    pub fn max_depth(&self) -> u32 {
+        self.max_depth
+    }
+
+    /// Kind of the dive so far, the most serious one that applies
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::odometer::{DiveKind, DiveProfile};
+    /// use fugit::MicrosDurationU32;
+    /// let mut profile = DiveProfile::new();
+    /// profile.record(4_000, 0, MicrosDurationU32::minutes(20));
+    /// assert_eq!(profile.kind(), DiveKind::Training);
+    /// profile.record(18_000, 0, MicrosDurationU32::minutes(20));
+    /// assert_eq!(profile.kind(), DiveKind::NoDeco);
+    /// ```
+    ///
+    pub fn kind(&self) -> DiveKind {
+        if self.missed_stop {
+            DiveKind::Violation
+        } else if self.deco {
+            DiveKind::Deco
+        } else if self.duration <= MicrosDurationU64::secs(FREEDIVE_MAX_DURATION.to_secs().into()) {
+            DiveKind::Freedive
+        } else if self.max_depth <= TRAINING_MAX_DEPTH {
+            DiveKind::Training
+        } else {
+            DiveKind::NoDeco
+        }
+    }
+
+    pub fn summary(&self) -> DiveSummary {
+        DiveSummary {
+            duration: SecsDurationU32::secs(self.duration.to_secs() as u32),
+            max_depth: self.max_depth,
+            kind: self.kind(),
+            min_temperature: self.min_temperature,
+            deco_variant: None,
+            checklist: ChecklistOutcome::None,
+        }
+    }
+}
+
+impl Default for DiveProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct LifetimeStats {
+    pub dives: u32,
+    /// Total time underwater in seconds
+    pub bottom_time: u32,
+    /// Deepest depth ever in millimeters
+    pub deepest: u32,
+}
+
+impl LifetimeStats {
+    pub const fn new() -> Self {
+        LifetimeStats {
+            dives: 0,
+            bottom_time: 0,
+            deepest: 0,
+        }
+    }
+
+    /// Add `dive` to the totals
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{checklist::ChecklistOutcome, odometer::{DiveKind, DiveSummary, LifetimeStats}};
+    /// use fugit::SecsDurationU32;
+    /// let mut stats = LifetimeStats::new();
+    /// stats.record(&DiveSummary {
+    ///     duration: SecsDurationU32::minutes(42),
+    ///     max_depth: 18_300,
+    ///     kind: DiveKind::NoDeco,
+    ///     min_temperature: None,
+    ///     deco_variant: None,
+    ///     checklist: ChecklistOutcome::Complete,
+    /// });
+    /// assert_eq!(format!("{}", stats), "LOG:  1    42MIN 18M");
+    /// ```
+    ///
+    pub fn record(&mut self, dive: &DiveSummary) {
+        self.dives = self.dives.saturating_add(1);
+        self.bottom_time = self.bottom_time.saturating_add(dive.duration.to_secs());
+        self.deepest = self.deepest.max(dive.max_depth);
+    }
+}
+
+impl fmt::Display for LifetimeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LOG:{:3}{:6}MIN{:3}M", self.dives, self.bottom_time / 60, self.deepest / 1000)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_lifetime_totals() {
+        let mut stats = LifetimeStats::new();
+        stats.record(&DiveSummary {
+            duration: SecsDurationU32::minutes(50),
+            max_depth: 31_200,
+            kind: DiveKind::Deco,
+            min_temperature: Some(140),
+            deco_variant: Some(Zhl16Variant::C),
+            checklist: ChecklistOutcome::Complete,
+        });
+        stats.record(&DiveSummary {
+            duration: SecsDurationU32::secs(90),
+            max_depth: 4_000,
+            kind: DiveKind::Freedive,
+            min_temperature: None,
+            deco_variant: None,
+            checklist: ChecklistOutcome::None,
+        });
+        assert_eq!(
+            stats,
+            LifetimeStats {
+                dives: 2,
+                bottom_time: 3090,
+                deepest: 31_200
+            }
+        );
+        assert_eq!(format!("{}", stats), "LOG:  2    51MIN 31M");
+    }
+
+    /// Profile of `(depth in m, ceiling in m, minutes)` segments
+    fn profile(segments: &[(u32, u32, u32)]) -> DiveProfile {
+        let mut profile = DiveProfile::new();
+        for &(depth, ceiling, minutes) in segments {
+            profile.record(depth * 1000, ceiling * 1000, MicrosDurationU32::minutes(minutes));
+        }
+        profile
+    }
+
+    #[test]
+    fn test_classify_profiles() {
+        // Pool session
+        assert_eq!(profile(&[(3, 0, 10), (5, 0, 30), (2, 0, 5)]).kind(), DiveKind::Training);
+        // Reef dive with a safety stop
+        assert_eq!(profile(&[(18, 0, 35), (5, 0, 3)]).kind(), DiveKind::NoDeco);
+        // Breath hold to 20 m
+        assert_eq!(profile(&[(10, 0, 1), (20, 0, 1)]).kind(), DiveKind::Freedive);
+        // Past the limit, with the stops done
+        let mut wreck = profile(&[(40, 0, 10), (40, 6, 15), (6, 3, 5), (3, 0, 10)]);
+        assert_eq!(wreck.kind(), DiveKind::Deco);
+        assert_eq!(wreck.summary().duration, SecsDurationU32::minutes(40));
+        assert_eq!(wreck.max_depth(), 40_000);
+        assert_eq!(wreck.summary().min_temperature, None);
+        wreck.record_temperature(180);
+        wreck.record_temperature(121);
+        wreck.record_temperature(135);
+        assert_eq!(wreck.summary().min_temperature, Some(121));
+        // Same dive without the stops
+        wreck.miss_stop();
+        assert_eq!(wreck.summary().kind, DiveKind::Violation);
+    }
+}
@@ -0,0 +1,142 @@
+//! PWM outputs
+//!
+//! `Outputs` owns every PWM channel the firmware drives: the buzzer on GPIO0 and the strobe on
+//! GPIO1, both on slice 0, and the LED of the Pico on GPIO25. The Pico Explorer has no backlight
+//! or RGB LED, the screen dims through its colors instead.
+//!
+//! Tasks don't drive the pins themselves. They ask for a duty cycle on a channel on behalf of a
+//! `Source`, and the most important source wins: an alarm overrides a cue, which overrides what
+//! the user asked for. A source with nothing to say asks for `None`, which leaves the channel to
+//! the sources below it. `apply` writes the winning duty cycles to the pins.
+
+use embedded_hal::PwmPin;
+
+/// Number of channels
+pub const CHANNEL_COUNT: usize = 3;
+
+/// Number of sources
+pub const SOURCE_COUNT: usize = 3;
+
+/// Duty cycle of a sounding buzzer in percent, a square wave is the loudest
+pub const BUZZER_DUTY: u8 = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Buzzer,
+    /// MOSFET of the external strobe
+    Strobe,
+    /// LED on the Pico
+    Led,
+}
+
+/// Who asks for a duty cycle, from the least to the most important
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    /// Heartbeat and other settings of the user
+    User,
+    /// Apnea cues and the Morse signal
+    Cue,
+    Alarm,
+}
+
+/// The pins of the channels and what each source asked for
+pub struct Outputs<B, S, L> {
+    /// Duty cycle in percent per channel and source
+    requests: [[Option<u8>; SOURCE_COUNT]; CHANNEL_COUNT],
+    buzzer: B,
+    strobe: S,
+    led: L,
+}
+
+impl<B, S, L> Outputs<B, S, L>
+where
+    B: PwmPin<Duty = u16>,
+    S: PwmPin<Duty = u16>,
+    L: PwmPin<Duty = u16>,
+{
+    /// Take over the pins, which stay off until `apply`
+    pub fn new(buzzer: B, strobe: S, led: L) -> Self {
+        Outputs {
+            requests: [[None; SOURCE_COUNT]; CHANNEL_COUNT],
+            buzzer,
+            strobe,
+            led,
+        }
+    }
+
+    /// Ask for `duty` percent on `channel` on behalf of `source`, or leave it to the other sources
+    pub fn set(&mut self, channel: Channel, source: Source, duty: Option<u8>) {
+        self.requests[channel as usize][source as usize] = duty.map(|duty| duty.min(100));
+    }
+
+    /// Duty cycle in percent of the most important source asking for `channel`, off when none does
+    pub fn duty(&self, channel: Channel) -> u8 {
+        self.requests[channel as usize].iter().rev().find_map(|&duty| duty).unwrap_or(0)
+    }
+
+    /// Write the duty cycles to the pins
+    pub fn apply(&mut self) {
+        let (buzzer, strobe, led) = (self.duty(Channel::Buzzer), self.duty(Channel::Strobe), self.duty(Channel::Led));
+        write(&mut self.buzzer, buzzer);
+        write(&mut self.strobe, strobe);
+        write(&mut self.led, led);
+    }
+}
+
+/// Set `pin` to `duty` percent
+fn write<P: PwmPin<Duty = u16>>(pin: &mut P, duty: u8) {
+    let max = u32::from(pin.get_max_duty());
+    pin.set_duty((max * u32::from(duty) / 100) as u16);
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// Channel remembering its duty, with a top like the buzzer slice
+    #[derive(Default)]
+    struct FakePin {
+        duty: u16,
+    }
+
+    impl PwmPin for FakePin {
+        type Duty = u16;
+
+        fn disable(&mut self) {}
+
+        fn enable(&mut self) {}
+
+        fn get_duty(&self) -> u16 {
+            self.duty
+        }
+
+        fn get_max_duty(&self) -> u16 {
+            1850
+        }
+
+        fn set_duty(&mut self, duty: u16) {
+            self.duty = duty;
+        }
+    }
+
+    #[test]
+    fn test_alarm_overrides_user() {
+        let mut outputs = Outputs::new(FakePin::default(), FakePin::default(), FakePin::default());
+        outputs.set(Channel::Led, Source::User, Some(100));
+        outputs.set(Channel::Buzzer, Source::Cue, Some(BUZZER_DUTY));
+        outputs.apply();
+        assert_eq!((outputs.buzzer.duty, outputs.strobe.duty, outputs.led.duty), (925, 0, 1850));
+
+        // An alarm pattern owns the channel in its pauses too
+        outputs.set(Channel::Led, Source::Alarm, Some(0));
+        outputs.set(Channel::Buzzer, Source::Alarm, Some(0));
+        outputs.apply();
+        assert_eq!((outputs.buzzer.duty, outputs.led.duty), (0, 0));
+
+        outputs.set(Channel::Led, Source::Alarm, None);
+        assert_eq!(outputs.duty(Channel::Led), 100);
+        outputs.set(Channel::Strobe, Source::User, Some(250));
+        assert_eq!(outputs.duty(Channel::Strobe), 100);
+    }
+}
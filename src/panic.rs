@@ -0,0 +1,271 @@
+//! Panic handling
+//!
+//! Every binary gets its panic handler from here, selected with a feature:
+//!
+//! - `panic-probe`, the default: halt and print the message over the debug probe
+//! - `panic-reboot`: keep a `CrashRecord` in the watchdog scratch registers and reset the chip,
+//!   for demo builds that have to keep running. The next boot takes the record with
+//!   `take_crash_record` and logs it.
+//! - `panic-screen`: keep a `CrashRecord` too, then draw the message on the screen and halt
+//!
+//! `panic-screen` wins over `panic-reboot`, which wins over `panic-probe`. Without any of them
+//! the core halts silently, which is the smallest option.
+//!
+//! The screen belongs to a task when the panic happens, so `panic-screen` takes the
+//! peripherals and sets up the screen from scratch. That is best effort: when the panic came
+//! from the screen itself, it may not show anything.
+
+use core::fmt;
+
+use pimoroni_pico_explorer::hal::pac;
+
+use crate::text_buffer::TextBuffer;
+
+/// Marks the watchdog scratch registers as holding a `CrashRecord`
+const CRASH_MAGIC: u32 = 0xC4A5_4ED0;
+
+/// Bytes of the file name kept in a `CrashRecord`
+const FILE_LEN: usize = 8;
+
+/// Widest line on the screen in characters
+const LINE_WIDTH: usize = 20;
+
+/// Panic page, 11 lines of `LINE_WIDTH` characters
+pub type PanicPage = TextBuffer<240>;
+
+/// Where the last panic happened, it survives a watchdog reset
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrashRecord {
+    /// Start of the file name without the directories, padded with zeros
+    file: [u8; FILE_LEN],
+    pub line: u32,
+}
+
+impl CrashRecord {
+    /// Record of a panic in `file` at `line`, the file name is cut to 8 bytes
+    pub fn new(file: &str, line: u32) -> Self {
+        let name = file.rsplit(['/', '\\']).next().unwrap_or(file);
+
+        let mut record = CrashRecord { file: [0; FILE_LEN], line };
+        let mut len = 0;
+        for c in name.chars() {
+            if len + c.len_utf8() > FILE_LEN {
+                break;
+            }
+            len += c.encode_utf8(&mut record.file[len..]).len();
+        }
+        record
+    }
+
+    /// File name, or its start
+    pub fn file(&self) -> &str {
+        let len = self.file.iter().position(|&byte| byte == 0).unwrap_or(FILE_LEN);
+        core::str::from_utf8(&self.file[..len]).unwrap_or("?")
+    }
+
+    /// Contents of the four scratch registers
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::panic::CrashRecord;
+    /// let record = CrashRecord::new("src/bin/rtic.rs", 512);
+    /// assert_eq!(CrashRecord::from_words(record.to_words()), Some(record));
+    /// assert_eq!(CrashRecord::from_words([0; 4]), None);
+    /// ```
+    ///
+    pub fn to_words(&self) -> [u32; 4] {
+        let [a, b, c, d, e, f, g, h] = self.file;
+        [CRASH_MAGIC, self.line, u32::from_le_bytes([a, b, c, d]), u32::from_le_bytes([e, f, g, h])]
+    }
+
+    /// Record kept in the scratch registers, if there is one
+    pub fn from_words(words: [u32; 4]) -> Option<Self> {
+        let [magic, line, start, end] = words;
+        if magic != CRASH_MAGIC {
+            return None;
+        }
+
+        let mut file = [0; FILE_LEN];
+        file[..4].copy_from_slice(&start.to_le_bytes());
+        file[4..].copy_from_slice(&end.to_le_bytes());
+        Some(CrashRecord { file, line })
+    }
+}
+
+impl fmt::Display for CrashRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file(), self.line)
+    }
+}
+
+/// Text of the panic screen, the message wrapped at the screen width
+pub fn panic_page(record: Option<&CrashRecord>, message: impl fmt::Display) -> PanicPage {
+    let mut text = TextBuffer::<160>::new();
+    write!(text, "{}", message);
+
+    let mut page = PanicPage::new();
+    writeln!(page, "PANIC");
+    writeln!(page);
+    if let Some(record) = record {
+        writeln!(page, "{}", record);
+        writeln!(page);
+    }
+
+    let mut column = 0;
+    for c in text.as_str().chars() {
+        if c == '\n' || column == LINE_WIDTH {
+            let _ = page.push('\n');
+            column = 0;
+        }
+        if c != '\n' {
+            let _ = page.push(c);
+            column += 1;
+        }
+    }
+    page
+}
+
+/// Keep `record` for the next boot, the boot ROM owns scratch registers 4 to 7
+pub fn store_crash_record(record: &CrashRecord) {
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    let [magic, line, start, end] = record.to_words();
+    watchdog.scratch0.write(|w| unsafe { w.bits(magic) });
+    watchdog.scratch1.write(|w| unsafe { w.bits(line) });
+    watchdog.scratch2.write(|w| unsafe { w.bits(start) });
+    watchdog.scratch3.write(|w| unsafe { w.bits(end) });
+}
+
+/// Record of the panic before the last reset, it is removed so it is only reported once
+pub fn take_crash_record() -> Option<CrashRecord> {
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    let words = [
+        watchdog.scratch0.read().bits(),
+        watchdog.scratch1.read().bits(),
+        watchdog.scratch2.read().bits(),
+        watchdog.scratch3.read().bits(),
+    ];
+    watchdog.scratch0.write(|w| unsafe { w.bits(0) });
+    CrashRecord::from_words(words)
+}
+
+#[cfg(all(target_os = "none", feature = "panic-probe", not(any(feature = "panic-reboot", feature = "panic-screen"))))]
+use panic_probe as _;
+
+#[cfg(all(target_os = "none", any(not(feature = "panic-probe"), feature = "panic-reboot", feature = "panic-screen")))]
+mod handler {
+    use core::panic::PanicInfo;
+
+    #[cfg(any(feature = "panic-reboot", feature = "panic-screen"))]
+    use super::*;
+
+    /// Crash record of `info`, or none when the location is unknown
+    #[cfg(any(feature = "panic-reboot", feature = "panic-screen"))]
+    fn record(info: &PanicInfo) -> Option<CrashRecord> {
+        info.location().map(|location| CrashRecord::new(location.file(), location.line()))
+    }
+
+    #[cfg(all(feature = "panic-reboot", not(feature = "panic-screen")))]
+    #[panic_handler]
+    fn panic(info: &PanicInfo) -> ! {
+        cortex_m::interrupt::disable();
+        defmt::error!("{}", defmt::Display2Format(info));
+        if let Some(record) = record(info) {
+            store_crash_record(&record);
+        }
+
+        // Reset everything but the oscillators when the watchdog fires, like the Pico SDK does
+        let psm = unsafe { &*pac::PSM::ptr() };
+        psm.wdsel.write(|w| unsafe { w.bits(0x0001_FFFC) });
+        let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+        watchdog.ctrl.write(|w| w.trigger().set_bit());
+
+        loop {
+            cortex_m::asm::nop();
+        }
+    }
+
+    #[cfg(feature = "panic-screen")]
+    #[panic_handler]
+    fn panic(info: &PanicInfo) -> ! {
+        use core::sync::atomic::{AtomicBool, Ordering};
+
+        /// Set while drawing, a panic in the screen code must not try again
+        static DRAWING: AtomicBool = AtomicBool::new(false);
+
+        cortex_m::interrupt::disable();
+        defmt::error!("{}", defmt::Display2Format(info));
+        let record = record(info);
+        if let Some(record) = &record {
+            store_crash_record(record);
+        }
+
+        // Only this core runs and the interrupts are off, so a load and a store are enough
+        if !DRAWING.load(Ordering::Relaxed) {
+            DRAWING.store(true, Ordering::Relaxed);
+            screen::draw(&panic_page(record.as_ref(), info.message()));
+        }
+
+        loop {
+            cortex_m::asm::nop();
+        }
+    }
+
+    #[cfg(feature = "panic-screen")]
+    mod screen {
+        use embedded_graphics::{prelude::*, text::Text};
+        use pimoroni_pico_explorer::{
+            hal::{adc::Adc, pac, sio::Sio},
+            PicoExplorer,
+        };
+
+        use super::PanicPage;
+        use crate::theme::Theme;
+
+        /// System clock after `init_clocks_and_plls`, a panic before that only makes the delays longer
+        const SYSTEM_CLOCK_HZ: u32 = 125_000_000;
+
+        /// Set up the screen again and draw `page`
+        pub fn draw(page: &PanicPage) {
+            // The tasks owning the peripherals will not run again
+            let (mut pac, core) = unsafe { (pac::Peripherals::steal(), pac::CorePeripherals::steal()) };
+            let mut delay = cortex_m::delay::Delay::new(core.SYST, SYSTEM_CLOCK_HZ);
+            let adc = Adc::new(pac.ADC, &mut pac.RESETS);
+            let sio = Sio::new(pac.SIO);
+
+            // Also switches off the buzzer and the strobe, their pins go back to inputs
+            let (mut explorer, _) = PicoExplorer::new(pac.IO_BANK0, pac.PADS_BANK0, sio.gpio_bank0, pac.SPI0, adc, &mut pac.RESETS, &mut delay);
+
+            let _ = Text::new(page.as_str(), Point::new(20, 30), Theme::default().text_style()).draw(&mut explorer.screen);
+        }
+    }
+
+    #[cfg(not(any(feature = "panic-probe", feature = "panic-reboot", feature = "panic-screen")))]
+    #[panic_handler]
+    fn panic(_info: &PanicInfo) -> ! {
+        cortex_m::interrupt::disable();
+        loop {
+            cortex_m::asm::nop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_crash_record_and_page() {
+        let record = CrashRecord::new("src/deco/zhl16.rs", 42);
+        assert_eq!(format!("{}", record), "zhl16.rs:42");
+        assert_eq!(CrashRecord::new("C:\\src\\odometer.rs", 7).file(), "odometer");
+        // A character is never cut in half
+        assert_eq!(CrashRecord::new("ééééé.rs", 1).file(), "éééé");
+
+        let page = panic_page(Some(&record), "attempt to subtract with overflow");
+        assert_eq!(page.as_str(), "PANIC\n\nzhl16.rs:42\n\nattempt to subtract \nwith overflow");
+        assert!(page.lines().all(|line| line.chars().count() <= LINE_WIDTH));
+        assert_eq!(panic_page(None, "a\nb").as_str(), "PANIC\n\na\nb");
+    }
+}
@@ -0,0 +1,282 @@
+//! Optional I2C peripherals
+//!
+//! The Explorer's breakout sockets share one I2C bus. At boot every address is probed once and
+//! the addresses that answer tell which of the known breakouts are attached, so the firmware can
+//! use what is there and skip what is not. The inventory is logged and shown on the diagnostics
+//! page.
+//!
+//! `Subsystems` goes one step further and tracks every optional part of the firmware, whether it
+//! is built in, attached or broke since boot. The firmware checks it before using a part, so it
+//! runs with any subset of them, and the diagnostics page shows it as a compact status row.
+
+use core::fmt;
+
+/// First address that is not reserved by the I2C specification
+const FIRST_ADDRESS: u8 = 0x08;
+
+/// Last address that is not reserved by the I2C specification
+const LAST_ADDRESS: u8 = 0x77;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peripheral {
+    /// Water pressure sensor, e.g. MS5837 or BMP/BME280
+    PressureSensor,
+    /// Accelerometer and gyroscope, e.g. LSM6DS3
+    Imu,
+    /// Compass, e.g. QMC5883L, LIS3MDL or HMC5883L
+    Magnetometer,
+    /// Real-time clock, e.g. DS3231 or PCF85063
+    Rtc,
+}
+
+impl Peripheral {
+    pub const ALL: [Peripheral; 4] = [Peripheral::PressureSensor, Peripheral::Imu, Peripheral::Magnetometer, Peripheral::Rtc];
+
+    /// Addresses the supported breakouts of this kind answer on, in order of preference
+    pub fn addresses(&self) -> &'static [u8] {
+        match self {
+            Peripheral::PressureSensor => &[0x76, 0x77],
+            Peripheral::Imu => &[0x6A, 0x6B],
+            Peripheral::Magnetometer => &[0x0D, 0x1C, 0x1E],
+            Peripheral::Rtc => &[0x68, 0x51],
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Peripheral::PressureSensor => "PRS",
+            Peripheral::Imu => "IMU",
+            Peripheral::Magnetometer => "MAG",
+            Peripheral::Rtc => "RTC",
+        }
+    }
+}
+
+/// Addresses that answered the boot scan
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Inventory {
+    /// One bit per 7-bit address
+    responded: u128,
+}
+
+impl Inventory {
+    /// Nothing found
+    pub const fn new() -> Self {
+        Inventory { responded: 0 }
+    }
+
+    /// Probe every non-reserved address, `probe` returns whether a device acknowledged
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::peripherals::{Inventory, Peripheral};
+    /// let inventory = Inventory::scan(|address| address == 0x77);
+    /// assert_eq!(inventory.address(Peripheral::PressureSensor), Some(0x77));
+    /// assert!(!inventory.has(Peripheral::Rtc));
+    /// ```
+    ///
+    pub fn scan(mut probe: impl FnMut(u8) -> bool) -> Self {
+        let mut inventory = Inventory::new();
+        for address in FIRST_ADDRESS..=LAST_ADDRESS {
+            if probe(address) {
+                inventory.responded |= 1 << address;
+            }
+        }
+        inventory
+    }
+
+    /// Whether a device acknowledged `address`
+    pub fn responded(&self, address: u8) -> bool {
+        address < 128 && self.responded & (1 << address) != 0
+    }
+
+    /// Number of devices that acknowledged, known or not
+    pub fn device_count(&self) -> u32 {
+        self.responded.count_ones()
+    }
+
+    /// Address of `peripheral`, when one of its addresses answered
+    pub fn address(&self, peripheral: Peripheral) -> Option<u8> {
+        peripheral.addresses().iter().copied().find(|&address| self.responded(address))
+    }
+
+    pub fn has(&self, peripheral: Peripheral) -> bool {
+        self.address(peripheral).is_some()
+    }
+}
+
+impl fmt::Display for Inventory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "I2C:")?;
+        let mut found = false;
+        for peripheral in Peripheral::ALL.iter().filter(|peripheral| self.has(**peripheral)) {
+            write!(f, " {}", peripheral.as_str())?;
+            found = true;
+        }
+        if !found {
+            write!(f, " NONE")?;
+        }
+        Ok(())
+    }
+}
+
+/// Optional parts of the firmware
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subsystem {
+    /// Piezo buzzer for alarms and cues
+    Buzzer,
+    /// Flash for settings and records
+    Storage,
+    /// Depth from a pressure sensor instead of the simulator
+    Sensors,
+    /// Console over USB
+    Usb,
+}
+
+impl Subsystem {
+    pub const ALL: [Subsystem; 4] = [Subsystem::Buzzer, Subsystem::Storage, Subsystem::Sensors, Subsystem::Usb];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Subsystem::Buzzer => "BZR",
+            Subsystem::Storage => "STO",
+            Subsystem::Sensors => "SNS",
+            Subsystem::Usb => "USB",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Availability {
+    Present,
+    /// Not built in or not attached
+    Absent,
+    /// Was present but broke, stays so until a reboot
+    Faulted,
+}
+
+impl Availability {
+    /// Mark in the status row
+    pub fn symbol(&self) -> char {
+        match self {
+            Availability::Present => '+',
+            Availability::Absent => '-',
+            Availability::Faulted => '!',
+        }
+    }
+}
+
+/// Availability of every `Subsystem`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subsystems {
+    /// In the order of `Subsystem::ALL`
+    availability: [Availability; 4],
+}
+
+impl Subsystems {
+    /// Nothing available
+    pub const fn new() -> Self {
+        Subsystems {
+            availability: [Availability::Absent; 4],
+        }
+    }
+
+    pub fn set(&mut self, subsystem: Subsystem, available: bool) {
+        self.availability[subsystem as usize] = if available { Availability::Present } else { Availability::Absent };
+    }
+
+    /// `subsystem` broke, ignored when it was absent
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::peripherals::{Availability, Subsystem, Subsystems};
+    /// let mut subsystems = Subsystems::new();
+    /// subsystems.fault(Subsystem::Usb);
+    /// assert_eq!(subsystems.get(Subsystem::Usb), Availability::Absent);
+    /// subsystems.set(Subsystem::Sensors, true);
+    /// subsystems.fault(Subsystem::Sensors);
+    /// assert_eq!(subsystems.get(Subsystem::Sensors), Availability::Faulted);
+    /// assert!(!subsystems.available(Subsystem::Sensors));
+    /// ```
+    ///
+    pub fn fault(&mut self, subsystem: Subsystem) {
+        let availability = &mut self.availability[subsystem as usize];
+        if *availability == Availability::Present {
+            *availability = Availability::Faulted;
+        }
+    }
+
+    pub fn get(&self, subsystem: Subsystem) -> Availability {
+        self.availability[subsystem as usize]
+    }
+
+    /// Whether `subsystem` can be used
+    pub fn available(&self, subsystem: Subsystem) -> bool {
+        self.get(subsystem) == Availability::Present
+    }
+}
+
+impl Default for Subsystems {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for Subsystems {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, subsystem) in Subsystem::ALL.iter().enumerate() {
+            let separator = if i == 0 { "" } else { " " };
+            write!(f, "{}{}{}", separator, subsystem.as_str(), self.get(*subsystem).symbol())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    use crate::theme::PAGE_FONT;
+
+    #[test]
+    fn test_scan() {
+        let mut probed = 0;
+        let inventory = Inventory::scan(|address| {
+            probed += 1;
+            [0x1E, 0x42, 0x68, 0x6B].contains(&address)
+        });
+        assert_eq!(probed, 112);
+        assert_eq!(inventory.device_count(), 4);
+        assert!(inventory.responded(0x42));
+        assert_eq!(inventory.address(Peripheral::Imu), Some(0x6B));
+        assert_eq!(inventory.address(Peripheral::Magnetometer), Some(0x1E));
+        assert!(!inventory.has(Peripheral::PressureSensor));
+        assert_eq!(format!("{}", inventory), "I2C: IMU MAG RTC");
+
+        assert_eq!(format!("{}", Inventory::new()), "I2C: NONE");
+    }
+
+    #[test]
+    fn test_subsystems() {
+        let mut subsystems = Subsystems::new();
+        assert_eq!(format!("{}", subsystems), "BZR- STO- SNS- USB-");
+
+        subsystems.set(Subsystem::Buzzer, true);
+        subsystems.set(Subsystem::Sensors, true);
+        subsystems.fault(Subsystem::Sensors);
+        // A fault sticks, it is only cleared by setting it again
+        subsystems.fault(Subsystem::Sensors);
+        assert_eq!(format!("{}", subsystems), "BZR+ STO- SNS! USB-");
+        assert!(subsystems.available(Subsystem::Buzzer));
+        assert!(!subsystems.available(Subsystem::Storage));
+        // Fits a line of the screen
+        assert!(format!("{}", subsystems).len() <= 20);
+        // And can be drawn
+        for availability in [Availability::Present, Availability::Absent, Availability::Faulted] {
+            assert!(PAGE_FONT.has_glyph(availability.symbol()));
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! Multilevel dive planner
+//!
+//! A plan has up to `MAX_SEGMENTS` levels, each a depth and a time, entered on the planner
+//! page. The plan is run through a copy of the decompression model of the dive computer, so
+//! the nitrogen left from an earlier dive counts too. Travel between levels happens at the
+//! ascent limit of the settings and breathes gas at the average depth of the travel.
+//!
+//! Per level the planner shows how much of the no-decompression limit it uses, for the whole
+//! plan the gas needed including the ascent, and the time to surface at the end. When the main
+//! page shows the ambient pressure, the planner shows the one of the deepest level.
+
+use core::fmt;
+
+use fugit::{MicrosDurationU32, SecsDurationU32};
+
+use crate::{
+    ascent::AscentLimit,
+    deco::{DecoModel, Gas},
+    gas::{gas_for_segment, SURFACE_PRESSURE_CB},
+    keymap::Action,
+    units::{AmbientPressure, DepthDisplay},
+};
+
+/// Most levels in a plan
+pub const MAX_SEGMENTS: usize = 4;
+
+/// Distance between the depths that can be planned
+const DEPTH_STEP_M: u32 = 3;
+
+/// Deepest level that can be planned
+const MAX_DEPTH_M: u32 = 60;
+
+/// Distance between the times that can be planned
+const TIME_STEP_MIN: u32 = 5;
+
+/// Longest level that can be planned
+const MAX_TIME_MIN: u32 = 60;
+
+/// Part of the no-decompression limit shown when there is none left
+const NO_NDL_PERCENT: u32 = 999;
+
+/// Time spent at one depth, levels without time are left out of the plan
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub depth_in_m: u32,
+    pub time_in_min: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plan {
+    pub segments: [Segment; MAX_SEGMENTS],
+}
+
+impl Plan {
+    /// A single level of 20 minutes at 18 m
+    pub const fn new() -> Self {
+        const UNUSED: Segment = Segment { depth_in_m: 0, time_in_min: 0 };
+        Plan {
+            segments: [Segment { depth_in_m: 18, time_in_min: 20 }, UNUSED, UNUSED, UNUSED],
+        }
+    }
+
+    /// Run the plan through a copy of `model`, travelling at `limit`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{ascent::AscentLimit, deco::NoDeco, planner::Plan};
+    /// let result = Plan::new().evaluate(&NoDeco, AscentLimit::new());
+    /// // 20 minutes at 18 m and the ascent without stops
+    /// assert_eq!(result.tts.to_secs(), 72);
+    /// assert_eq!(Plan::new().evaluate(&NoDeco, AscentLimit::Conservative).tts.to_secs(), 120);
+    /// ```
+    ///
+    pub fn evaluate<M: DecoModel + Clone>(&self, model: &M, limit: AscentLimit) -> PlanResult {
+        let mut model = model.clone();
+        let mut result = PlanResult::new();
+        let mut depth_in_m: u32 = 0;
+
+        for (index, segment) in self.segments.iter().enumerate() {
+            if segment.time_in_min == 0 {
+                continue;
+            }
+
+            // Travel to the level at the average depth
+            let travel = travel_time(depth_in_m.abs_diff(segment.depth_in_m), limit);
+            let average_depth_in_m = (depth_in_m + segment.depth_in_m) / 2;
+            model.tick(average_depth_in_m * 1000, travel.convert(), Gas::AIR);
+            result.total_gas_in_cl += gas_for_segment(average_depth_in_m, travel);
+            depth_in_m = segment.depth_in_m;
+
+            model.tick(depth_in_m * 1000, MicrosDurationU32::micros(0), Gas::AIR);
+            let ndl = model.ndl().to_secs();
+            let time = SecsDurationU32::minutes(segment.time_in_min);
+            let gas_in_cl = gas_for_segment(depth_in_m, time);
+            model.tick(depth_in_m * 1000, time.convert(), Gas::AIR);
+
+            result.segments[index] = Some(SegmentResult {
+                gas_in_cl,
+                ndl_percent: (time.to_secs() * 100).checked_div(ndl).unwrap_or(NO_NDL_PERCENT).min(NO_NDL_PERCENT),
+            });
+            result.total_gas_in_cl += gas_in_cl;
+        }
+
+        // Ascent with the stops the model asks for
+        let mut tts = travel_time(depth_in_m, limit);
+        let mut from_in_m = depth_in_m;
+        let stops = model.stops();
+        result.no_stop = stops.is_empty();
+        for stop in stops.iter() {
+            let stop_in_m = stop.depth / 1000;
+            result.total_gas_in_cl += gas_for_segment((from_in_m + stop_in_m) / 2, travel_time(from_in_m.saturating_sub(stop_in_m), limit));
+            result.total_gas_in_cl += gas_for_segment(stop_in_m, stop.duration);
+            tts += stop.duration;
+            from_in_m = stop_in_m;
+        }
+        result.total_gas_in_cl += gas_for_segment(from_in_m / 2, travel_time(from_in_m, limit));
+        result.tts = tts;
+
+        result
+    }
+}
+
+impl Default for Plan {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Time to travel `distance_in_m` at `limit`
+fn travel_time(distance_in_m: u32, limit: AscentLimit) -> SecsDurationU32 {
+    SecsDurationU32::secs(distance_in_m * 60 / limit.rate())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentResult {
+    /// Gas breathed at the level
+    pub gas_in_cl: u32,
+    /// Time at the level in percent of the no-decompression limit when arriving there
+    pub ndl_percent: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlanResult {
+    /// Result per level of the plan, `None` for levels without time
+    pub segments: [Option<SegmentResult>; MAX_SEGMENTS],
+    /// Gas for the whole dive including travel, stops and the ascent
+    pub total_gas_in_cl: u32,
+    /// Time to surface at the end of the last level
+    pub tts: SecsDurationU32,
+    /// Whether the ascent needs no stops
+    pub no_stop: bool,
+}
+
+impl PlanResult {
+    const fn new() -> Self {
+        PlanResult {
+            segments: [None; MAX_SEGMENTS],
+            total_gas_in_cl: 0,
+            tts: SecsDurationU32::secs(0),
+            no_stop: true,
+        }
+    }
+}
+
+/// State of the planner page, one depth or time is selected at a time
+#[derive(Debug, Clone, Copy)]
+pub struct PlanEditor {
+    pub plan: Plan,
+    /// Selected field, depth and time of every level
+    field: usize,
+}
+
+impl PlanEditor {
+    pub const fn new() -> Self {
+        PlanEditor { plan: Plan::new(), field: 0 }
+    }
+
+    /// Handle one of the editing actions, others are ignored
+    pub fn perform(&mut self, action: Action) {
+        let segment = &mut self.plan.segments[self.field / 2];
+        match action {
+            Action::SelectItem => self.field = (self.field + 1) % (MAX_SEGMENTS * 2),
+            Action::ChangeItem if self.field.is_multiple_of(2) => {
+                segment.depth_in_m = if segment.depth_in_m >= MAX_DEPTH_M {
+                    DEPTH_STEP_M
+                } else {
+                    segment.depth_in_m + DEPTH_STEP_M
+                };
+            }
+            Action::ChangeItem => {
+                segment.time_in_min = if segment.time_in_min >= MAX_TIME_MIN {
+                    0
+                } else {
+                    segment.time_in_min + TIME_STEP_MIN
+                };
+            }
+            _ => {}
+        }
+    }
+
+    /// Planner page with `result`, `None` when planning isn't allowed, for the `display` of the main page
+    pub fn page<'a>(&'a self, result: Option<&'a PlanResult>, display: DepthDisplay) -> PlannerPage<'a> {
+        PlannerPage { editor: self, result, display }
+    }
+}
+
+impl Default for PlanEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct PlannerPage<'a> {
+    editor: &'a PlanEditor,
+    result: Option<&'a PlanResult>,
+    display: DepthDisplay,
+}
+
+impl fmt::Display for PlannerPage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(result) = self.result else {
+            writeln!(f, "PLANNING LOCKED")?;
+            writeln!(f)?;
+            return write!(f, "MISSED DECO STOP");
+        };
+
+        writeln!(f, "Planner")?;
+        writeln!(f)?;
+
+        let marker = |field| if self.editor.field == field { '>' } else { ' ' };
+        for (index, segment) in self.editor.plan.segments.iter().enumerate() {
+            write!(
+                f,
+                "{} {}{:>2}M {}{:>2}MIN",
+                index + 1,
+                marker(index * 2),
+                segment.depth_in_m,
+                marker(index * 2 + 1),
+                segment.time_in_min
+            )?;
+            match result.segments[index] {
+                Some(segment) => writeln!(f, " {:>3}%", segment.ndl_percent)?,
+                None => writeln!(f)?,
+            }
+        }
+
+        if self.display.pressure() {
+            let segments = self.editor.plan.segments.iter().filter(|segment| segment.time_in_min > 0);
+            let deepest = segments.map(|segment| segment.depth_in_m).max().unwrap_or(0);
+            writeln!(f, "MAX AMBIENT: {:>7}", AmbientPressure::new(SURFACE_PRESSURE_CB * 10 + deepest * 100))?;
+        }
+        writeln!(f, "GAS: {:>14}L", result.total_gas_in_cl / 100)?;
+        write!(f, "TTS: {:>12}MIN", result.tts.to_minutes())
+    }
+}
+
+#[cfg(all(test, feature = "deco"))]
+mod test {
+
+    use super::*;
+    use crate::deco::zhl16::Zhl16;
+
+    #[test]
+    fn test_multilevel_plan() {
+        let mut editor = PlanEditor::new();
+        // Deeper first level: 24 m for 20 minutes, then 12 m for 30 minutes
+        for _ in 0..2 {
+            editor.perform(Action::ChangeItem);
+        }
+        editor.perform(Action::SelectItem);
+        editor.perform(Action::SelectItem);
+        for _ in 0..4 {
+            editor.perform(Action::ChangeItem);
+        }
+        editor.perform(Action::SelectItem);
+        for _ in 0..6 {
+            editor.perform(Action::ChangeItem);
+        }
+        assert_eq!(editor.plan.segments[0], Segment { depth_in_m: 24, time_in_min: 20 });
+        assert_eq!(editor.plan.segments[1], Segment { depth_in_m: 12, time_in_min: 30 });
+
+        let result = editor.plan.evaluate(&Zhl16::new(), AscentLimit::new());
+        let first = result.segments[0].unwrap();
+        let second = result.segments[1].unwrap();
+        assert_eq!(first.gas_in_cl, 68 * 20 * 60);
+        assert!(first.ndl_percent < 100);
+        // The first level leaves nitrogen behind
+        assert!(second.ndl_percent > 0);
+        assert_eq!(result.segments[2], None);
+        assert!(result.total_gas_in_cl > first.gas_in_cl + second.gas_in_cl);
+        assert!(result.tts >= travel_time(12, AscentLimit::new()));
+
+        let page = format!("{}", editor.page(Some(&result), DepthDisplay::Depth));
+        assert!(page.starts_with("Planner\n\n1  24M  20MIN  74%\n2  12M >30MIN  30%\n3   0M   0MIN\n"));
+        assert!(!page.contains("AMBIENT"));
+        assert!(format!("{}", editor.page(Some(&result), DepthDisplay::Pressure)).contains("0MIN\nMAX AMBIENT:  3.4BAR\nGAS:"));
+        assert!(format!("{}", editor.page(None, DepthDisplay::Depth)).starts_with("PLANNING LOCKED"));
+
+        // A plan past the limit needs stops
+        editor.plan.segments[1] = Segment { depth_in_m: 30, time_in_min: 60 };
+        let deco = editor.plan.evaluate(&Zhl16::new(), AscentLimit::new());
+        assert_eq!(deco.segments[1].map(|segment| segment.ndl_percent), Some(NO_NDL_PERCENT));
+        assert!(deco.tts > SecsDurationU32::minutes(10));
+    }
+}
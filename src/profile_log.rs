@@ -0,0 +1,284 @@
+//! Compressed dive profiles
+//!
+//! A profile sampled once a second is four bytes per sample as raw millimeters, over 14 KB for
+//! an hour. Depths are stored in steps of `DEPTH_STEP` instead, as the difference to the
+//! previous sample: a diver moves far less than 6 m a second, so every difference fits in a
+//! single byte as a zigzag varint. A zero difference is never stored alone, it starts a run
+//! whose length follows as a varint, so a flat bottom or a safety stop takes two or three bytes
+//! in total.
+//!
+//! `ProfileWriter` compresses into a fixed buffer, `samples` decompresses it again. On the
+//! profile of the test, a 46 minute dive, that is 2771 samples in 312 bytes, 35 times smaller
+//! than raw samples. With 10 to 20 cm of swell at the bottom and the stop almost every sample
+//! takes a byte, it is still 2286 bytes, almost 5 times smaller.
+
+/// Resolution of the stored depths in mm
+pub const DEPTH_STEP: u32 = 100;
+
+/// Most bytes of one varint of a `u32`
+const MAX_VARINT_LEN: usize = 5;
+
+/// The buffer of a `ProfileWriter` is full
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProfileFull;
+
+/// Depth in whole `DEPTH_STEP`s, rounded to the nearest
+fn quantize(depth: u32) -> i64 {
+    i64::from(depth.saturating_add(DEPTH_STEP / 2) / DEPTH_STEP)
+}
+
+/// Write `value` as a varint to `out`, returns the number of bytes
+fn write_varint(mut value: u32, out: &mut [u8; MAX_VARINT_LEN]) -> usize {
+    let mut len = 0;
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out[len] = byte;
+            return len + 1;
+        }
+        out[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+/// Signed difference as an unsigned number, small differences of either sign stay small
+fn zigzag(delta: i32) -> u32 {
+    ((delta << 1) ^ (delta >> 31)) as u32
+}
+
+fn unzigzag(value: u32) -> i32 {
+    (value >> 1) as i32 ^ -((value & 1) as i32)
+}
+
+/// Compresses a profile into `N` bytes
+#[derive(Debug, Clone)]
+pub struct ProfileWriter<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    /// Last depth in `DEPTH_STEP`s
+    last: i64,
+    /// Samples equal to `last` that are not written yet
+    run: u32,
+    samples: u32,
+}
+
+impl<const N: usize> ProfileWriter<N> {
+    pub const fn new() -> Self {
+        ProfileWriter {
+            bytes: [0; N],
+            len: 0,
+            last: 0,
+            run: 0,
+            samples: 0,
+        }
+    }
+
+    /// Add a sample of `depth` mm, the writer is unchanged when it doesn't fit
+    pub fn push(&mut self, depth: u32) -> Result<(), ProfileFull> {
+        let depth = quantize(depth);
+        if depth == self.last && self.run < u32::MAX {
+            self.run += 1;
+            self.samples += 1;
+            return Ok(());
+        }
+
+        let mut encoded = [0; 2 * MAX_VARINT_LEN + MAX_VARINT_LEN];
+        let mut len = self.encode_run(&mut encoded);
+        // A `u32` of mm is at most 43 million steps, so the difference fits an `i32`
+        let delta = (depth - self.last) as i32;
+        let mut varint = [0; MAX_VARINT_LEN];
+        let varint_len = write_varint(zigzag(delta), &mut varint);
+        encoded[len..len + varint_len].copy_from_slice(&varint[..varint_len]);
+        len += varint_len;
+        self.append(&encoded[..len])?;
+
+        self.run = 0;
+        self.last = depth;
+        self.samples += 1;
+        Ok(())
+    }
+
+    /// Encode the pending run into `out`, returns the number of bytes
+    fn encode_run(&self, out: &mut [u8]) -> usize {
+        if self.run == 0 {
+            return 0;
+        }
+        let mut varint = [0; MAX_VARINT_LEN];
+        out[0] = 0;
+        let len = write_varint(self.run, &mut varint);
+        out[1..1 + len].copy_from_slice(&varint[..len]);
+        1 + len
+    }
+
+    fn append(&mut self, encoded: &[u8]) -> Result<(), ProfileFull> {
+        let end = self.len + encoded.len();
+        if end > N {
+            return Err(ProfileFull);
+        }
+        self.bytes[self.len..end].copy_from_slice(encoded);
+        self.len = end;
+        Ok(())
+    }
+
+    /// Write the pending run, after this the bytes hold every sample
+    pub fn finish(&mut self) -> Result<&[u8], ProfileFull> {
+        let mut encoded = [0; 1 + MAX_VARINT_LEN];
+        let len = self.encode_run(&mut encoded);
+        self.append(&encoded[..len])?;
+        self.run = 0;
+        Ok(self.as_bytes())
+    }
+
+    /// Compressed samples, without a run that is not finished
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Number of samples pushed
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+}
+
+impl<const N: usize> Default for ProfileWriter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Depths in mm of a compressed profile, in steps of `DEPTH_STEP`
+///
+/// The samples end at the first byte that can't be decoded, e.g. when the profile was cut off.
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::profile_log::{samples, ProfileWriter};
+/// let mut writer = ProfileWriter::<16>::new();
+/// for depth in [0, 1_000, 1_040, 1_000, 960, 2_000] {
+///     writer.push(depth).unwrap();
+/// }
+/// let depths: Vec<u32> = samples(writer.finish().unwrap()).collect();
+/// assert_eq!(depths, [0, 1_000, 1_000, 1_000, 1_000, 2_000]);
+/// ```
+///
+pub fn samples(bytes: &[u8]) -> Samples<'_> {
+    Samples { bytes, depth: 0, run: 0 }
+}
+
+/// Iterator of `samples`
+#[derive(Debug, Clone)]
+pub struct Samples<'a> {
+    bytes: &'a [u8],
+    /// Depth in `DEPTH_STEP`s
+    depth: i64,
+    /// Repeats of `depth` left
+    run: u32,
+}
+
+impl Samples<'_> {
+    fn read_varint(&mut self) -> Option<u32> {
+        let mut value: u32 = 0;
+        for (index, &byte) in self.bytes.iter().take(MAX_VARINT_LEN).enumerate() {
+            value |= u32::from(byte & 0x7F).checked_shl(7 * index as u32)?;
+            if byte & 0x80 == 0 {
+                self.bytes = &self.bytes[index + 1..];
+                return Some(value);
+            }
+        }
+        self.bytes = &[];
+        None
+    }
+}
+
+impl Iterator for Samples<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        if self.run == 0 {
+            let delta = unzigzag(self.read_varint()?);
+            if delta == 0 {
+                self.run = self.read_varint()?;
+                if self.run == 0 {
+                    self.bytes = &[];
+                    return None;
+                }
+            } else {
+                self.depth += i64::from(delta);
+                self.run = 1;
+            }
+        }
+
+        self.run -= 1;
+        let depth = u32::try_from(self.depth).ok()?;
+        Some(depth * DEPTH_STEP)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// Depth in mm once a second, a 30 m dive with a safety stop and `noise` mm of swell
+    fn dive(noise: u32) -> Vec<u32> {
+        let mut depths = Vec::new();
+        // Down at 18 m/min, 30 cm a second
+        depths.extend((0..100).map(|second| second * 300));
+        // 30 minutes at the bottom
+        depths.extend((0..1800).map(|second| 30_000 + noise * (second % 3)));
+        // Up at 9 m/min to the stop, 3 minutes at 5 m, then to the surface
+        depths.extend((0..167).map(|second| 30_000 - second * 150));
+        depths.extend((0..180).map(|second| 5_000 + noise * (second % 2)));
+        depths.extend((0..34).map(|second| 5_000 - second * 150));
+        depths.extend([0; 490]);
+        depths
+    }
+
+    fn round_trip(depths: &[u32]) -> usize {
+        let mut writer = ProfileWriter::<4096>::new();
+        for &depth in depths {
+            writer.push(depth).unwrap();
+        }
+        assert_eq!(writer.samples(), depths.len() as u32);
+        let bytes = writer.finish().unwrap();
+
+        // Every sample comes back within half a step
+        let decoded: Vec<u32> = samples(bytes).collect();
+        assert_eq!(decoded.len(), depths.len());
+        for (depth, decoded) in depths.iter().zip(decoded) {
+            assert!(depth.abs_diff(decoded) <= DEPTH_STEP / 2, "{} {}", depth, decoded);
+        }
+        bytes.len()
+    }
+
+    #[test]
+    fn test_round_trip_and_ratio() {
+        let noisy = dive(100);
+        assert_eq!(noisy.len(), 2771);
+        assert_eq!(round_trip(&noisy), 2286);
+        assert_eq!(round_trip(&dive(0)), 312);
+
+        // Large jumps and the deepest depths survive too
+        assert_eq!(round_trip(&[u32::MAX / 2, 0, 100_000, 100_000]), 12);
+    }
+
+    #[test]
+    fn test_full_and_truncated() {
+        let mut writer = ProfileWriter::<4>::new();
+        writer.push(0).unwrap();
+        writer.push(0).unwrap();
+        writer.push(12_800).unwrap();
+        // The run and the difference take 4 bytes, another difference doesn't fit
+        assert_eq!(writer.as_bytes(), [0, 2, 0x80, 0x02]);
+        assert_eq!(writer.push(0), Err(ProfileFull));
+        assert_eq!(writer.samples(), 3);
+        writer.push(12_800).unwrap();
+        assert_eq!(writer.finish(), Err(ProfileFull));
+
+        // A cut off varint ends the samples
+        assert_eq!(samples(&[0, 2, 0x80]).collect::<Vec<_>>(), [0, 0]);
+        assert_eq!(samples(&[0, 0, 2]).count(), 0);
+    }
+}
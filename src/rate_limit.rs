@@ -0,0 +1,141 @@
+//! Limit on how fast the simulated rate changes
+//!
+//! A diver can't go from a fast descent to a fast ascent from one moment to the next. The rate
+//! buttons set the rate the diver wants and the simulated rate follows it by at most the
+//! `RateLimit` per second, which makes the ascent rate alarm exercises closer to a real dive.
+//! An instructor who wants to show an alarm straight away turns the limit off in the simulation
+//! settings, then the buttons set the rate directly like before.
+
+use serde::{Deserialize, Serialize};
+
+/// Fastest change of the simulated rate, in meters per minute per second
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RateLimit {
+    /// The rate jumps to the target, for the instructor
+    Off,
+    M5,
+    #[default]
+    M10,
+    M20,
+}
+
+impl RateLimit {
+    pub const fn per_second(&self) -> Option<u32> {
+        match self {
+            RateLimit::Off => None,
+            RateLimit::M5 => Some(5),
+            RateLimit::M10 => Some(10),
+            RateLimit::M20 => Some(20),
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            RateLimit::Off => RateLimit::M5,
+            RateLimit::M5 => RateLimit::M10,
+            RateLimit::M10 => RateLimit::M20,
+            RateLimit::M20 => RateLimit::Off,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RateLimit::Off => "OFF",
+            RateLimit::M5 => "5M/MIN/S",
+            RateLimit::M10 => "10M/MIN/S",
+            RateLimit::M20 => "20M/MIN/S",
+        }
+    }
+}
+
+/// Rate the diver wants and how far the simulated rate may move towards it
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimiter {
+    limit: RateLimit,
+    /// In meters per minute, negative while ascending
+    target: i32,
+    /// Time in microseconds not yet turned into a rate change
+    pending_us: u32,
+}
+
+impl RateLimiter {
+    pub const fn new(limit: RateLimit) -> Self {
+        RateLimiter { limit, target: 0, pending_us: 0 }
+    }
+
+    pub fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    pub fn set_limit(&mut self, limit: RateLimit) {
+        self.limit = limit;
+    }
+
+    pub fn target(&self) -> i32 {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: i32) {
+        self.target = target;
+    }
+
+    /// Rate after following the target from `rate` for `step_us`, without a limit the rate is left as set
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::rate_limit::{RateLimit, RateLimiter};
+    /// let mut limiter = RateLimiter::new(RateLimit::M10);
+    /// limiter.set_target(-50);
+    /// assert_eq!(limiter.follow(50, 1_000_000), 40);
+    /// assert_eq!(limiter.follow(40, 9_000_000), -50);
+    /// ```
+    ///
+    pub fn follow(&mut self, rate: i32, step_us: u32) -> i32 {
+        let Some(per_second) = self.limit.per_second() else {
+            self.pending_us = 0;
+            return rate;
+        };
+        if rate == self.target {
+            self.pending_us = 0;
+            return rate;
+        }
+
+        // Time per 1 m/min of change
+        let interval_us = 1_000_000 / per_second;
+        self.pending_us += step_us;
+        let change = (self.pending_us / interval_us) as i32;
+        self.pending_us %= interval_us;
+        rate + (self.target - rate).clamp(-change, change)
+    }
+
+    /// Stop at once, e.g. at the surface or when the depth source changes
+    pub fn stop(&mut self) {
+        self.target = 0;
+        self.pending_us = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_follow() {
+        // Half a m/min per step
+        let mut limiter = RateLimiter::new(RateLimit::M5);
+        limiter.set_target(3);
+        let mut rate = 0;
+        let rates: [i32; 8] = core::array::from_fn(|_| {
+            rate = limiter.follow(rate, 100_000);
+            rate
+        });
+        assert_eq!(rates, [0, 1, 1, 2, 2, 3, 3, 3]);
+
+        // Off leaves the rate to whoever sets it
+        limiter.set_limit(RateLimit::Off);
+        limiter.set_target(-50);
+        assert_eq!(limiter.follow(rate, 100_000), 3);
+    }
+}
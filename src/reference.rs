@@ -0,0 +1,139 @@
+//! Reference tables
+//!
+//! Published dive tables encoded as constants, with tests that keep the models and alarms within
+//! a tolerance of them. The models are free to be refactored, these catch a change in the math
+//! that moves the numbers a diver relies on.
+
+use fugit::MicrosDurationU64;
+
+use crate::ascent::Coaching;
+
+/// No-decompression limits on air of the PADI Recreational Dive Planner, depth in meters and
+/// limit in minutes
+const RDP_NDL: [(u32, u32); 11] = [
+    (10, 219),
+    (12, 147),
+    (14, 98),
+    (16, 72),
+    (18, 56),
+    (20, 45),
+    (22, 37),
+    (25, 29),
+    (30, 20),
+    (35, 14),
+    (40, 9),
+];
+
+/// Bühlmann ZHL-16 may be this much more conservative than the RDP, in percent
+const ZHL16_MARGIN_PERCENT: u32 = 25;
+
+/// Bühlmann ZHL-16 may be this much less conservative than the RDP, in percent
+const ZHL16_EXCESS_PERCENT: u32 = 10;
+
+/// Ascent rates in meter per minute from published procedures, ascending for `secs` from
+/// `depth` in meters, with the coaching and the beep interval expected at the end
+const ASCENT_VECTORS: [AscentVector; 8] = [
+    // Final ascent from a safety stop
+    AscentVector::new(5, 3, 30, Coaching::Slow, None),
+    // US Navy, 30 ft/min
+    AscentVector::new(30, 9, 30, Coaching::Moderate, None),
+    // Bühlmann and most dive computers
+    AscentVector::new(40, 10, 60, Coaching::Moderate, None),
+    // Limit of this dive computer
+    AscentVector::new(30, 15, 60, Coaching::Fast, None),
+    // PADI RDP and the US Navy before 1993, 60 ft/min: within the grace period, then faster beeps
+    AscentVector::new(30, 18, 3, Coaching::TooFast, None),
+    AscentVector::new(30, 18, 10, Coaching::TooFast, Some(1000)),
+    AscentVector::new(30, 18, 30, Coaching::TooFast, Some(250)),
+    // Bolting up from a shallow stop
+    AscentVector::new(6, 30, 10, Coaching::TooFast, Some(1000)),
+];
+
+struct AscentVector {
+    depth: u32,
+    rate: u32,
+    secs: u32,
+    coaching: Coaching,
+    /// Beep interval in milliseconds
+    beep_ms: Option<u64>,
+}
+
+impl AscentVector {
+    const fn new(depth: u32, rate: u32, secs: u32, coaching: Coaching, beep_ms: Option<u64>) -> Self {
+        AscentVector {
+            depth,
+            rate,
+            secs,
+            coaching,
+            beep_ms,
+        }
+    }
+
+    fn beep_interval(&self) -> Option<MicrosDurationU64> {
+        self.beep_ms.map(MicrosDurationU64::millis)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use fugit::MicrosDurationU32;
+
+    use super::*;
+    use crate::{
+        air_integration::TankSize,
+        clock::ManualClock,
+        deco::{DecoModel, Gas, MAX_NDL},
+        gas::MAX_SAFE_ASCEND_RATE,
+        Alarm, DiveComputer,
+    };
+
+    #[cfg(feature = "deco")]
+    use crate::deco::{haldane::Haldane, zhl16::Zhl16};
+
+    /// No-decompression limit in minutes of a fresh `model` at `depth` meters
+    fn ndl(mut model: impl DecoModel, depth: u32) -> u32 {
+        model.tick(depth * 1000, MicrosDurationU32::micros(0), Gas::AIR);
+        model.ndl().to_minutes()
+    }
+
+    #[test]
+    #[cfg(feature = "deco")]
+    fn test_ndl_tables() {
+        for (depth, published) in RDP_NDL {
+            // The models don't count beyond `MAX_NDL`
+            let published = published.min(MAX_NDL.to_minutes());
+
+            let zhl16 = ndl(Zhl16::new(), depth);
+            assert!(
+                zhl16 * 100 >= published * (100 - ZHL16_MARGIN_PERCENT) && zhl16 * 100 <= published * (100 + ZHL16_EXCESS_PERCENT),
+                "ZHL-16 at {} m: {} min, RDP {} min",
+                depth,
+                zhl16,
+                published
+            );
+
+            // A single slow tissue is never less conservative
+            let haldane = ndl(Haldane::new(), depth);
+            assert!(haldane <= published, "Haldane at {} m: {} min, RDP {} min", depth, haldane, published);
+        }
+    }
+
+    #[test]
+    fn test_ascent_vectors() {
+        for vector in ASCENT_VECTORS {
+            let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+            dive_computer.air = TankSize::L10.full_air_in_cl();
+            dive_computer.depth = vector.depth * 1000;
+            dive_computer.rate = -(vector.rate as i32);
+            dive_computer.change_depth(MicrosDurationU32::secs(vector.secs));
+
+            let context = (vector.depth, vector.rate, vector.secs);
+            assert!(dive_computer.depth > 0, "{:?} reached the surface", context);
+            assert_eq!(dive_computer.ascent().coaching(), vector.coaching, "{:?}", context);
+            assert_eq!(dive_computer.ascent().beep_interval(), vector.beep_interval(), "{:?}", context);
+            let too_fast = vector.rate > MAX_SAFE_ASCEND_RATE;
+            assert_eq!(dive_computer.alarm() == Alarm::Medium, too_fast, "{:?}", context);
+        }
+    }
+}
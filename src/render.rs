@@ -0,0 +1,720 @@
+//! Screen render pipeline
+//!
+//! Drawing text pixel by pixel, or even glyph by glyph, means the ST7789 receives a new address
+//! window for every small run of pixels. Instead we draw into a band of full screen rows in RAM
+//! and send the whole band in a single write. The height of the band is a trade-off between the
+//! size of a write and the number of SPI transactions, `ScreenChunk` has room for
+//! `MAX_CHUNK_ROWS` and the `RenderConfig` in the settings chooses how many of them are used.
+//!
+//! The HAL we use has no DMA driver yet, so all writes are done by the CPU.
+//!
+//! The screen is refreshed at the `RefreshRate` from the settings, independent of the logic
+//! tick. A frame that looks the same as the previous one is not sent at all, `FrameCache`
+//! remembers what was drawn last.
+//!
+//! When only the text changed since the last frame, `DirtyRegions` finds the characters that
+//! differ on each line and only those columns of the rows are sent: `ChunkBuffer::flush_region`
+//! gives the ST7789 the columns and rows of the region as its address window. A depth that
+//! changes one digit sends a 10 by 20 pixel window instead of 240 rows of 20 pixels.
+//!
+//! During a dive the main page has a `DepthGradient` as its `Background` instead of a single
+//! color. Every row of a band is filled with its own shade before the text is drawn over it, so
+//! the gradient costs nothing extra on the SPI bus. Only a change of the shade redraws the whole
+//! screen, the rest of the time just the rows of the text are sent as before.
+//!
+//! A glitch on the SPI bus, e.g. from a loose connector, can leave the ST7789 confused. The
+//! screen of the Pico Explorer is write only, its MISO line is the data/command pin, so the
+//! state of the controller can't be read back. Failed draws are the only sign we get:
+//! `ScreenRecovery` decides when to `reinit` the screen, at most once per `REINIT_BACKOFF` as a
+//! re-initialization blocks for about 160 ms.
+
+use core::{convert::Infallible, iter};
+
+use crate::{clock::Instant, theme::DepthGradient};
+
+use display_interface_spi::SPIInterface;
+use embedded_graphics::{
+    pixelcolor::{raw::RawU16, Rgb565},
+    prelude::*,
+    primitives::Rectangle,
+};
+use embedded_hal::blocking::delay::DelayUs;
+use fugit::{HertzU32, MicrosDurationU64};
+use pimoroni_pico_explorer::Screen;
+use serde::{Deserialize, Serialize};
+use st7789::{Orientation, ST7789};
+
+/// Width and height of the screen in pixels
+pub const SCREEN_SIZE: u16 = 240;
+
+/// Most rows per band of `ScreenChunk`, two lines of `FONT_10X20`
+pub const MAX_CHUNK_ROWS: usize = 40;
+
+/// Band of rows used to batch screen writes
+pub type ScreenChunk = ChunkBuffer<{ SCREEN_SIZE as usize }, MAX_CHUNK_ROWS>;
+
+/// Most regions `DirtyRegions` keeps, one per line of text
+pub const MAX_DIRTY_REGIONS: usize = 12;
+
+/// Time between two re-initializations of the screen while draws keep failing
+pub const REINIT_BACKOFF: MicrosDurationU64 = MicrosDurationU64::secs(1);
+
+/// Error of a draw on the screen
+pub type DrawError = st7789::Error<()>;
+
+/// Configuration of the render pipeline, part of the settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderConfig {
+    pub spi_frequency: SpiFrequency,
+    /// Rows per band of `ScreenChunk`
+    pub chunk_rows: ChunkRows,
+    /// Draw through a `ScreenChunk` instead of straight to the screen
+    pub batch: bool,
+}
+
+impl RenderConfig {
+    pub const fn new() -> Self {
+        RenderConfig {
+            spi_frequency: SpiFrequency::M31,
+            chunk_rows: ChunkRows::R20,
+            batch: true,
+        }
+    }
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// SPI clock of the screen, the ST7789 handles up to 62.5 MHz
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SpiFrequency {
+    M16,
+    #[default]
+    M31,
+    M62,
+}
+
+impl SpiFrequency {
+    pub const fn hertz(&self) -> HertzU32 {
+        match self {
+            SpiFrequency::M16 => HertzU32::MHz(16),
+            SpiFrequency::M31 => HertzU32::MHz(31),
+            SpiFrequency::M62 => HertzU32::MHz(62),
+        }
+    }
+
+    /// Faster clock, wrapping around to the slowest
+    pub fn next(self) -> Self {
+        match self {
+            SpiFrequency::M16 => SpiFrequency::M31,
+            SpiFrequency::M31 => SpiFrequency::M62,
+            SpiFrequency::M62 => SpiFrequency::M16,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SpiFrequency::M16 => "16 MHZ",
+            SpiFrequency::M31 => "31 MHZ",
+            SpiFrequency::M62 => "62 MHZ",
+        }
+    }
+}
+
+/// Height of the bands the screen is drawn in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ChunkRows {
+    R10,
+    /// One line of `FONT_10X20`
+    #[default]
+    R20,
+    R40,
+}
+
+impl ChunkRows {
+    /// Rows per band, at most `MAX_CHUNK_ROWS`
+    pub const fn rows(&self) -> usize {
+        match self {
+            ChunkRows::R10 => 10,
+            ChunkRows::R20 => 20,
+            ChunkRows::R40 => MAX_CHUNK_ROWS,
+        }
+    }
+
+    /// Higher band, wrapping around to the lowest
+    pub fn next(self) -> Self {
+        match self {
+            ChunkRows::R10 => ChunkRows::R20,
+            ChunkRows::R20 => ChunkRows::R40,
+            ChunkRows::R40 => ChunkRows::R10,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChunkRows::R10 => "10 ROWS",
+            ChunkRows::R20 => "20 ROWS",
+            ChunkRows::R40 => "40 ROWS",
+        }
+    }
+}
+
+/// How often the screen is refreshed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RefreshRate {
+    Hz1,
+    Hz2,
+    Hz5,
+    #[default]
+    Hz10,
+}
+
+impl RefreshRate {
+    /// Time between refreshes
+    pub const fn interval(&self) -> MicrosDurationU64 {
+        match self {
+            RefreshRate::Hz1 => MicrosDurationU64::millis(1000),
+            RefreshRate::Hz2 => MicrosDurationU64::millis(500),
+            RefreshRate::Hz5 => MicrosDurationU64::millis(200),
+            RefreshRate::Hz10 => MicrosDurationU64::millis(100),
+        }
+    }
+
+    /// Faster rate, wrapping around to the slowest
+    pub fn next(self) -> Self {
+        match self {
+            RefreshRate::Hz1 => RefreshRate::Hz2,
+            RefreshRate::Hz2 => RefreshRate::Hz5,
+            RefreshRate::Hz5 => RefreshRate::Hz10,
+            RefreshRate::Hz10 => RefreshRate::Hz1,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefreshRate::Hz1 => "1 HZ",
+            RefreshRate::Hz2 => "2 HZ",
+            RefreshRate::Hz5 => "5 HZ",
+            RefreshRate::Hz10 => "10 HZ",
+        }
+    }
+}
+
+/// What is behind the text and widgets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Background {
+    Solid(Rgb565),
+    Depth(DepthGradient),
+}
+
+impl Background {
+    /// Color of screen row `row`
+    pub fn color(&self, row: i32) -> Rgb565 {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Depth(gradient) => gradient.color(row),
+        }
+    }
+
+    /// The color when every row has the same, text and widgets can then draw their own background
+    pub fn solid(&self) -> Option<Rgb565> {
+        match self {
+            Background::Solid(color) => Some(*color),
+            Background::Depth(_) => None,
+        }
+    }
+
+    /// Fill `area` of `target`, with one fill per run of rows of the same color
+    pub fn fill<D>(&self, area: Rectangle, target: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let Some(bottom_right) = area.bottom_right() else {
+            return Ok(());
+        };
+
+        let mut top = area.top_left.y;
+        while top <= bottom_right.y {
+            let color = self.color(top);
+            let mut bottom = top;
+            while bottom < bottom_right.y && self.color(bottom + 1) == color {
+                bottom += 1;
+            }
+            let rows = Rectangle::new(Point::new(area.top_left.x, top), Size::new(area.size.width, (bottom - top + 1) as u32));
+            target.fill_solid(&rows, color)?;
+            top = bottom + 1;
+        }
+
+        Ok(())
+    }
+}
+
+/// Everything that went into the last frame, to skip drawing the same frame again
+#[derive(Debug, Clone)]
+pub struct FrameCache<T> {
+    last: Option<T>,
+}
+
+impl<T: Clone + PartialEq> FrameCache<T> {
+    pub const fn new() -> Self {
+        FrameCache { last: None }
+    }
+
+    /// Whether `frame` differs from the last frame, it is remembered when it does
+    pub fn changed(&mut self, frame: &T) -> bool {
+        if self.last.as_ref() == Some(frame) {
+            return false;
+        }
+        self.last = Some(frame.clone());
+        true
+    }
+
+    /// The frame drawn last, `None` after `invalidate`
+    pub fn last(&self) -> Option<&T> {
+        self.last.as_ref()
+    }
+
+    /// Forget the last frame, e.g. after the screen was cleared
+    pub fn invalidate(&mut self) {
+        self.last = None;
+    }
+}
+
+impl<T: Clone + PartialEq> Default for FrameCache<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parts of the screen where the text of a frame differs from the last one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirtyRegions {
+    regions: [Rectangle; MAX_DIRTY_REGIONS],
+    len: usize,
+}
+
+impl DirtyRegions {
+    /// Columns of each line where `new` differs from `old`, for text with its top left corner
+    /// at `top_left` and glyphs of `glyph` pixels
+    ///
+    /// `None` when there are more changed lines than `MAX_DIRTY_REGIONS`, then the whole text
+    /// has to be drawn.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::render::DirtyRegions;
+    /// use embedded_graphics::{prelude::*, primitives::Rectangle};
+    /// let dirty = DirtyRegions::between("DEPTH: 12M\nRATE: 5", "DEPTH: 13M\nRATE: 5", Point::new(20, 15), Size::new(10, 20)).unwrap();
+    /// assert_eq!(dirty.iter().collect::<Vec<_>>(), [Rectangle::new(Point::new(100, 15), Size::new(10, 20))]);
+    /// ```
+    ///
+    pub fn between(old: &str, new: &str, top_left: Point, glyph: Size) -> Option<Self> {
+        let mut dirty = DirtyRegions {
+            regions: [Rectangle::zero(); MAX_DIRTY_REGIONS],
+            len: 0,
+        };
+
+        let (mut old_lines, mut new_lines) = (old.split('\n'), new.split('\n'));
+        let mut top = top_left.y;
+        loop {
+            let (old_line, new_line) = match (old_lines.next(), new_lines.next()) {
+                (None, None) => return Some(dirty),
+                (old_line, new_line) => (old_line.unwrap_or(""), new_line.unwrap_or("")),
+            };
+
+            // A line that got shorter leaves characters to be cleared behind it
+            let old_chars = old_line.chars().map(Some).chain(iter::repeat(None));
+            let new_chars = new_line.chars().map(Some).chain(iter::repeat(None));
+            let len = old_line.chars().count().max(new_line.chars().count());
+            let mut differs = old_chars
+                .zip(new_chars)
+                .take(len)
+                .enumerate()
+                .filter(|(_, (old, new))| old != new)
+                .map(|(column, _)| column as i32);
+            if let Some(first) = differs.next() {
+                let last = differs.last().unwrap_or(first);
+                let region = dirty.regions.get_mut(dirty.len)?;
+                *region = Rectangle::new(
+                    Point::new(top_left.x + first * glyph.width as i32, top),
+                    Size::new((last - first + 1) as u32 * glyph.width, glyph.height),
+                );
+                dirty.len += 1;
+            }
+            top += glyph.height as i32;
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Rectangle> + '_ {
+        self.regions[..self.len].iter().copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Keeps track of failed draws, to re-initialize the screen instead of giving up on it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ScreenRecovery {
+    last_reinit: Option<Instant>,
+    /// Failed draws since boot
+    pub errors: u32,
+    /// Re-initializations since boot
+    pub reinits: u32,
+}
+
+impl ScreenRecovery {
+    pub const fn new() -> Self {
+        ScreenRecovery {
+            last_reinit: None,
+            errors: 0,
+            reinits: 0,
+        }
+    }
+
+    /// Record a failed draw at `now`, returns whether the screen should be re-initialized
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{clock::Instant, render::{ScreenRecovery, REINIT_BACKOFF}};
+    /// let mut recovery = ScreenRecovery::new();
+    /// let now = Instant::from_ticks(0);
+    /// assert!(recovery.failed(now));
+    /// // Still failing right after the re-initialization
+    /// assert!(!recovery.failed(now + fugit::MicrosDurationU64::millis(100)));
+    /// assert!(recovery.failed(now + REINIT_BACKOFF));
+    /// assert_eq!((recovery.errors, recovery.reinits), (3, 2));
+    /// ```
+    ///
+    pub fn failed(&mut self, now: Instant) -> bool {
+        self.errors = self.errors.wrapping_add(1);
+
+        let due = self
+            .last_reinit
+            .is_none_or(|last| now.checked_duration_since(last).is_some_and(|since| since >= REINIT_BACKOFF));
+        if due {
+            self.last_reinit = Some(now);
+            self.reinits = self.reinits.wrapping_add(1);
+        }
+        due
+    }
+}
+
+/// Reset and configure the screen like the BSP does at boot, its contents are lost
+pub fn reinit(screen: &mut Screen, delay: &mut impl DelayUs<u32>) -> Result<(), DrawError> {
+    screen.init(delay)?;
+    screen.set_orientation(Orientation::Portrait)
+}
+
+/// Change the SPI clock of the screen, returns the screen and the frequency that was set
+///
+/// The SPI clock is divided from the peripheral clock, so the result is the closest frequency
+/// not above `spi_frequency`.
+pub fn set_spi_frequency(screen: Screen, peripheral_frequency: HertzU32, spi_frequency: HertzU32) -> (Screen, HertzU32) {
+    let (di, rst) = screen.release();
+    let (mut spi, dc, cs) = di.release();
+
+    let frequency = spi.set_baudrate(peripheral_frequency, spi_frequency);
+
+    // The screen itself keeps its configuration, only the driver is recreated
+    (ST7789::new(SPIInterface::new(spi, dc, cs), rst, SCREEN_SIZE, SCREEN_SIZE), frequency)
+}
+
+/// Screen that is written through an address window, like the ST7789
+pub trait WindowTarget {
+    type Error;
+
+    /// Send `pixels` row by row to the window from column `left` and row `top` to column `right`
+    /// and row `bottom`, all included
+    fn write_window(&mut self, left: u16, top: u16, right: u16, bottom: u16, pixels: impl IntoIterator<Item = u16>) -> Result<(), Self::Error>;
+}
+
+impl WindowTarget for Screen {
+    type Error = DrawError;
+
+    fn write_window(&mut self, left: u16, top: u16, right: u16, bottom: u16, pixels: impl IntoIterator<Item = u16>) -> Result<(), Self::Error> {
+        // Sets the column and row address window, then streams the pixels
+        self.set_pixels(left, top, right, bottom, pixels)
+    }
+}
+
+/// Framebuffer for up to `H` rows of `W` pixels, starting at row `top` of the screen
+pub struct ChunkBuffer<const W: usize, const H: usize> {
+    pixels: [[u16; W]; H],
+    top: i32,
+    /// Rows of the band in use
+    rows: usize,
+}
+
+impl<const W: usize, const H: usize> ChunkBuffer<W, H> {
+    pub const fn new() -> Self {
+        ChunkBuffer {
+            pixels: [[0; W]; H],
+            top: 0,
+            rows: H,
+        }
+    }
+
+    /// Use `rows` rows per band, at most `H`
+    pub fn set_rows(&mut self, rows: usize) {
+        self.rows = rows.clamp(1, H);
+    }
+
+    /// Move the band to start at row `top` and fill it with `background`
+    pub fn reset(&mut self, top: i32, background: Background) {
+        self.top = top;
+        for (row, pixels) in (top..).zip(self.pixels[..self.rows].iter_mut()) {
+            *pixels = [RawU16::from(background.color(row)).into_inner(); W];
+        }
+    }
+
+    /// Rows of the band as raw colors
+    pub fn pixels(&self) -> impl Iterator<Item = u16> + '_ {
+        self.pixels[..self.rows].iter().flatten().copied()
+    }
+
+    /// Draw `drawable` into the rows of `area` of the screen, one band at a time
+    pub fn draw_batched<D, S>(&mut self, drawable: &D, area: Rectangle, background: Background, screen: &mut S) -> Result<(), S::Error>
+    where
+        D: Drawable<Color = Rgb565>,
+        S: WindowTarget,
+    {
+        let rows = Rectangle::new(Point::new(0, area.top_left.y), Size::new(W as u32, area.size.height));
+        self.flush_region(drawable, rows, background, screen)
+    }
+
+    /// Draw `drawable` into the `dirty` regions of the screen, or into the rows of `area` when all
+    /// of it has to be drawn
+    pub fn draw_dirty<D, S>(&mut self, drawable: &D, area: Rectangle, dirty: Option<&DirtyRegions>, background: Background, screen: &mut S) -> Result<(), S::Error>
+    where
+        D: Drawable<Color = Rgb565>,
+        S: WindowTarget,
+    {
+        match dirty {
+            Some(dirty) => dirty.iter().try_for_each(|region| self.flush_region(drawable, region, background, screen)),
+            None => self.draw_batched(drawable, area, background, screen),
+        }
+    }
+
+    /// Draw `drawable` and send only the pixels inside `region`, one band at a time
+    ///
+    /// The rest of the screen keeps what it shows, so `region` has to cover everything that
+    /// changed.
+    pub fn flush_region<D, S>(&mut self, drawable: &D, region: Rectangle, background: Background, screen: &mut S) -> Result<(), S::Error>
+    where
+        D: Drawable<Color = Rgb565>,
+        S: WindowTarget,
+    {
+        let Some(bottom_right) = region.bottom_right() else {
+            return Ok(());
+        };
+        let (left, right) = (region.top_left.x.max(0), bottom_right.x.min(W as i32 - 1));
+        let bottom = bottom_right.y.min(SCREEN_SIZE as i32 - 1);
+        if left > right {
+            return Ok(());
+        }
+
+        let mut top = region.top_left.y.max(0);
+        while top <= bottom {
+            self.reset(top, background);
+            // Drawing into RAM can't fail
+            let _ = drawable.draw(self);
+
+            let last_row = (top + self.rows as i32 - 1).min(bottom);
+            let rows = (last_row - top + 1) as usize;
+            let pixels = self.pixels[..rows].iter().flat_map(|row| row[left as usize..=right as usize].iter().copied());
+            screen.write_window(left as u16, top as u16, right as u16, last_row as u16, pixels)?;
+
+            top += self.rows as i32;
+        }
+
+        Ok(())
+    }
+}
+
+impl<const W: usize, const H: usize> Default for ChunkBuffer<W, H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const W: usize, const H: usize> OriginDimensions for ChunkBuffer<W, H> {
+    fn size(&self) -> Size {
+        Size::new(W as u32, SCREEN_SIZE as u32)
+    }
+}
+
+impl<const W: usize, const H: usize> DrawTarget for ChunkBuffer<W, H> {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let row = point.y - self.top;
+            if (0..self.rows as i32).contains(&row) && (0..W as i32).contains(&point.x) {
+                self.pixels[row as usize][point.x as usize] = RawU16::from(color).into_inner();
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_frame_cache_skips_same_frame() {
+        let mut cache = FrameCache::new();
+        assert!(cache.changed(&("DEPTH: 10M", false)));
+        assert!(!cache.changed(&("DEPTH: 10M", false)));
+        // The padlock appeared
+        assert!(cache.changed(&("DEPTH: 10M", true)));
+
+        cache.invalidate();
+        assert!(cache.changed(&("DEPTH: 10M", true)));
+
+        assert_eq!(RefreshRate::default().interval(), MicrosDurationU64::millis(100));
+        assert_eq!(RefreshRate::Hz10.next(), RefreshRate::Hz1);
+    }
+
+    /// Records the fills instead of drawing
+    struct Fills(Vec<(Rectangle, Rgb565)>);
+
+    impl OriginDimensions for Fills {
+        fn size(&self) -> Size {
+            Size::new(SCREEN_SIZE as u32, SCREEN_SIZE as u32)
+        }
+    }
+
+    impl DrawTarget for Fills {
+        type Color = Rgb565;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, _pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            unreachable!()
+        }
+
+        fn fill_solid(&mut self, area: &Rectangle, color: Self::Color) -> Result<(), Self::Error> {
+            self.0.push((*area, color));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_depth_gradient_background() {
+        let screen = Rectangle::new(Point::zero(), Size::new(SCREEN_SIZE as u32, SCREEN_SIZE as u32));
+        let mut fills = Fills(Vec::new());
+        Background::Solid(Rgb565::BLACK).fill(screen, &mut fills).unwrap();
+        assert_eq!(fills.0, [(screen, Rgb565::BLACK)]);
+
+        // A run of rows per shade, darker down the screen and with depth
+        let surface = Background::Depth(DepthGradient::new(0));
+        let mut fills = Fills(Vec::new());
+        surface.fill(screen, &mut fills).unwrap();
+        assert_eq!(fills.0.len(), 8);
+        assert_eq!(fills.0.iter().map(|(rows, _)| rows.size.height).sum::<u32>(), SCREEN_SIZE as u32);
+        assert!(fills.0.windows(2).all(|pair| pair[1].1.b() < pair[0].1.b()));
+        assert_eq!(surface.solid(), None);
+        assert!(DepthGradient::new(30_000).color(0).b() < surface.color(0).b());
+        assert_eq!(DepthGradient::new(40_000), DepthGradient::new(100_000));
+        // Changes of a few centimeters look the same
+        assert_eq!(DepthGradient::new(10_000), DepthGradient::new(10_050));
+
+        // The band takes the shade of its rows on the screen
+        let mut chunk = ChunkBuffer::<2, 4>::new();
+        chunk.reset(236, surface);
+        let rows: Vec<u16> = chunk.pixels().step_by(2).collect();
+        let raw = |row| RawU16::from(surface.color(row)).into_inner();
+        assert_eq!(rows, [raw(236), raw(237), raw(238), raw(239)]);
+        assert_ne!(raw(0), raw(239));
+    }
+
+    /// Records the windows instead of sending them
+    struct Windows(Vec<((u16, u16, u16, u16), Vec<u16>)>);
+
+    impl WindowTarget for Windows {
+        type Error = Infallible;
+
+        fn write_window(&mut self, left: u16, top: u16, right: u16, bottom: u16, pixels: impl IntoIterator<Item = u16>) -> Result<(), Self::Error> {
+            self.0.push(((left, top, right, bottom), pixels.into_iter().collect()));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_flush_region() {
+        let black = Background::Solid(Rgb565::BLACK);
+        let white = RawU16::from(Rgb565::WHITE).into_inner();
+        let dot = Pixel(Point::new(3, 2), Rgb565::WHITE);
+
+        // Only the columns of the region, split at the bands
+        let mut chunk = ChunkBuffer::<8, 2>::new();
+        let mut windows = Windows(Vec::new());
+        let region = Rectangle::new(Point::new(2, 1), Size::new(3, 3));
+        chunk.flush_region(&dot, region, black, &mut windows).unwrap();
+        assert_eq!(windows.0.iter().map(|(window, _)| *window).collect::<Vec<_>>(), [(2, 1, 4, 2), (2, 3, 4, 3)]);
+        assert_eq!(windows.0[0].1, [0, 0, 0, 0, white, 0]);
+        assert_eq!(windows.0[1].1, [0, 0, 0]);
+
+        // Whole rows, and nothing outside the band
+        let mut windows = Windows(Vec::new());
+        chunk.draw_batched(&dot, region, black, &mut windows).unwrap();
+        assert_eq!(windows.0[0].0, (0, 1, 7, 2));
+        chunk
+            .flush_region(&dot, Rectangle::new(Point::new(8, 0), Size::new(4, 4)), black, &mut windows)
+            .unwrap();
+        assert_eq!(windows.0.len(), 2);
+
+        // Bands of fewer rows than the buffer has
+        let mut chunk = ChunkBuffer::<8, 4>::new();
+        chunk.set_rows(ChunkRows::R10.rows());
+        assert_eq!(chunk.rows, 4);
+        chunk.set_rows(1);
+        let mut windows = Windows(Vec::new());
+        chunk.flush_region(&dot, region, black, &mut windows).unwrap();
+        assert_eq!(
+            windows.0.iter().map(|(window, _)| *window).collect::<Vec<_>>(),
+            [(2, 1, 4, 1), (2, 2, 4, 2), (2, 3, 4, 3)]
+        );
+        assert_eq!(windows.0[1].1, [0, white, 0]);
+    }
+
+    #[test]
+    fn test_dirty_regions() {
+        let glyph = Size::new(10, 20);
+        let top_left = Point::new(20, 15);
+        let regions = |old, new| DirtyRegions::between(old, new, top_left, glyph).map(|dirty| dirty.iter().collect::<Vec<_>>());
+
+        assert_eq!(regions("DEPTH: 12M\nTIME: 1:00\n", "DEPTH: 12M\nTIME: 1:00\n"), Some(vec![]));
+        // From the first to the last changed character of each line
+        assert_eq!(
+            regions("DEPTH: 12M\nTIME: 1:09\n", "DEPTH: 12M\nTIME: 2:10\n"),
+            Some(vec![Rectangle::new(Point::new(80, 35), Size::new(40, 20))])
+        );
+        // A shorter line and a removed line are cleared
+        assert_eq!(
+            regions("RATE: 10\nALARM", "RATE: 9"),
+            Some(vec![
+                Rectangle::new(Point::new(80, 15), Size::new(20, 20)),
+                Rectangle::new(Point::new(20, 35), Size::new(50, 20))
+            ])
+        );
+        // Too many changed lines for single regions
+        assert_eq!(regions(&"A\n".repeat(MAX_DIRTY_REGIONS + 1), &"B\n".repeat(MAX_DIRTY_REGIONS + 1)), None);
+    }
+}
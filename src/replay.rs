@@ -0,0 +1,115 @@
+//! Replay checksum
+//!
+//! The simulation is deterministic: the same actions and tick durations always lead to the same
+//! state. To let a classroom check that, every input the dive computer gets is folded into a
+//! running CRC-32 together with the state it leads to. Two kits fed the same input stream show
+//! the same checksum on the diagnostics page and in the `replay` console command, and the first
+//! input where they differ changes every checksum after it.
+//!
+//! Only the simulated inputs are covered. Real tick durations follow the clock of each kit, so
+//! kits only agree when their ticks come from a script, like the tests do with `ManualClock`.
+
+use core::fmt;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use fugit::{MicrosDurationU32, MicrosDurationU64};
+
+use crate::{keymap::Action, Alarm};
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Input changing the state of the dive computer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Action(Action),
+    /// Simulated time passed, after the time scale
+    Tick(MicrosDurationU32),
+}
+
+/// State after an input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Depth in millimeters
+    pub depth: u32,
+    /// Rate in meters per minute
+    pub rate: i32,
+    /// Air in centiliters
+    pub air: u32,
+    pub edt: MicrosDurationU64,
+    pub alarm: Alarm,
+}
+
+/// Running checksum of inputs and states
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayChecksum {
+    crc: u32,
+    /// Inputs folded in
+    inputs: u32,
+}
+
+impl ReplayChecksum {
+    pub const fn new() -> Self {
+        ReplayChecksum { crc: 0, inputs: 0 }
+    }
+
+    /// Fold `input` and the `state` it led to into the checksum
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{keymap::Action, replay::{Input, ReplayChecksum, Snapshot}, Alarm};
+    /// use fugit::MicrosDurationU64;
+    /// let state = Snapshot { depth: 0, rate: 1, air: 5000, edt: MicrosDurationU64::micros(0), alarm: Alarm::None };
+    /// let (mut first, mut second) = (ReplayChecksum::new(), ReplayChecksum::new());
+    /// first.record(Input::Action(Action::IncreaseRate), &state);
+    /// second.record(Input::Action(Action::IncreaseRate), &state);
+    /// assert_eq!(first, second);
+    ///
+    /// // The same state after another input
+    /// second.record(Input::Action(Action::Mark), &state);
+    /// first.record(Input::Action(Action::FillAir), &state);
+    /// assert_ne!(first.crc(), second.crc());
+    /// ```
+    ///
+    pub fn record(&mut self, input: Input, state: &Snapshot) {
+        let mut bytes = [0; 30];
+        bytes[..4].copy_from_slice(&self.crc.to_le_bytes());
+        match input {
+            Input::Action(action) => bytes[4..6].copy_from_slice(&[b'A', action as u8]),
+            Input::Tick(interval) => {
+                bytes[4] = b'T';
+                bytes[5] = 0;
+                bytes[6..10].copy_from_slice(&interval.to_micros().to_le_bytes());
+            }
+        }
+        bytes[10..14].copy_from_slice(&state.depth.to_le_bytes());
+        bytes[14..18].copy_from_slice(&state.rate.to_le_bytes());
+        bytes[18..22].copy_from_slice(&state.air.to_le_bytes());
+        bytes[22..29].copy_from_slice(&state.edt.to_micros().to_le_bytes()[..7]);
+        bytes[29] = state.alarm as u8;
+
+        self.crc = CRC.checksum(&bytes);
+        self.inputs = self.inputs.wrapping_add(1);
+    }
+
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Number of inputs folded in, wraps around
+    pub fn inputs(&self) -> u32 {
+        self.inputs
+    }
+}
+
+impl Default for ReplayChecksum {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for ReplayChecksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "REPLAY: {:>4}{:08X}", "", self.crc)
+    }
+}
@@ -0,0 +1,115 @@
+//! Air reserve
+//!
+//! Besides the alarm for not having enough air to reach the surface, the tank pressure has two
+//! thresholds of its own: a warning when the reserve is reached, 50 bar by default, and a
+//! critical alarm at 30 bar. Each has its own alarm, text color and beep interval.
+
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+use fugit::MicrosDurationU64;
+use serde::{Deserialize, Serialize};
+
+/// Distance between the thresholds that can be set
+const STEP_BAR: u32 = 10;
+
+/// Lowest threshold that can be set
+const MIN_BAR: u32 = 10;
+
+/// Highest threshold that can be set, a third of a full 200 bar tank
+const MAX_BAR: u32 = 70;
+
+/// Tank pressures in bar at which the reserve alarms go off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReserveConfig {
+    pub warning: u32,
+    pub critical: u32,
+}
+
+impl ReserveConfig {
+    pub const fn new() -> Self {
+        ReserveConfig { warning: 50, critical: 30 }
+    }
+
+    /// Whether the thresholds are within what the settings page can set
+    pub fn is_valid(&self) -> bool {
+        MIN_BAR <= self.critical && self.critical < self.warning && self.warning <= MAX_BAR
+    }
+
+    /// Raise `warning` by 10 bar, wrapping around, `critical` is lowered to stay below it
+    pub fn step_warning(&mut self) {
+        self.warning = if self.warning >= MAX_BAR { MIN_BAR + STEP_BAR } else { self.warning + STEP_BAR };
+        self.critical = self.critical.min(self.warning - STEP_BAR);
+    }
+
+    /// Raise `critical` by 10 bar, wrapping around, `warning` is raised to stay above it
+    pub fn step_critical(&mut self) {
+        self.critical = if self.critical >= MAX_BAR - STEP_BAR { MIN_BAR } else { self.critical + STEP_BAR };
+        self.warning = self.warning.max(self.critical + STEP_BAR);
+    }
+}
+
+impl Default for ReserveConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reserve {
+    Ok,
+    Warning,
+    Critical,
+}
+
+impl Reserve {
+    /// Reserve state at a tank pressure in centibar
+    pub fn for_pressure(tank_pressure_in_cb: u32, config: &ReserveConfig) -> Self {
+        if tank_pressure_in_cb <= config.critical * 100 {
+            Reserve::Critical
+        } else if tank_pressure_in_cb <= config.warning * 100 {
+            Reserve::Warning
+        } else {
+            Reserve::Ok
+        }
+    }
+
+    /// Text color while the alarm is on
+    pub fn color(&self) -> Option<Rgb565> {
+        match self {
+            Reserve::Ok => None,
+            Reserve::Warning => Some(Rgb565::YELLOW),
+            Reserve::Critical => Some(Rgb565::RED),
+        }
+    }
+
+    /// Time between beeps
+    pub fn beep_interval(&self) -> Option<MicrosDurationU64> {
+        match self {
+            Reserve::Ok => None,
+            Reserve::Warning => Some(MicrosDurationU64::secs(4)),
+            Reserve::Critical => Some(MicrosDurationU64::millis(750)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_thresholds() {
+        let mut config = ReserveConfig::new();
+        assert_eq!(Reserve::for_pressure(5_001, &config), Reserve::Ok);
+        assert_eq!(Reserve::for_pressure(5_000, &config), Reserve::Warning);
+        assert_eq!(Reserve::for_pressure(3_000, &config), Reserve::Critical);
+
+        config.step_warning();
+        config.step_warning();
+        assert_eq!(config, ReserveConfig { warning: 70, critical: 30 });
+        config.step_warning();
+        assert_eq!(config, ReserveConfig { warning: 20, critical: 10 });
+
+        config.step_critical();
+        assert_eq!(config, ReserveConfig { warning: 30, critical: 20 });
+    }
+}
@@ -0,0 +1,89 @@
+//! Fixed size ring buffer keeping the last `N` items
+
+/// Ring buffer of the last `N` items, pushing into a full buffer overwrites the oldest item
+#[derive(Debug, Clone, Copy)]
+pub struct RingBuffer<T: Copy, const N: usize> {
+    items: [Option<T>; N],
+    /// Index of the oldest item
+    start: usize,
+    len: usize,
+}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> Self {
+        RingBuffer {
+            items: [None; N],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    /// Add an item, overwriting the oldest one when full
+    pub fn push(&mut self, item: T) {
+        if N == 0 {
+            return;
+        }
+
+        self.items[(self.start + self.len) % N] = Some(item);
+        if self.len < N {
+            self.len += 1;
+        } else {
+            self.start = (self.start + 1) % N;
+        }
+    }
+
+    /// Items from oldest to newest
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &T> + '_ {
+        (0..self.len).filter_map(move |i| self.items[(self.start + i) % N].as_ref())
+    }
+
+    /// Newest item
+    pub fn last(&self) -> Option<&T> {
+        self.iter().next_back()
+    }
+
+    /// Newest item, to change in place
+    pub fn last_mut(&mut self) -> Option<&mut T> {
+        match self.len {
+            0 => None,
+            len => self.items[(self.start + len - 1) % N].as_mut(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.start = 0;
+        self.len = 0;
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer_overwrites_oldest() {
+        let mut buffer = RingBuffer::<u32, 3>::new();
+        for item in 0..5 {
+            buffer.push(item);
+        }
+
+        let items: Vec<u32> = buffer.iter().copied().collect();
+        assert_eq!(items, [2, 3, 4]);
+        assert_eq!(buffer.last(), Some(&4));
+    }
+}
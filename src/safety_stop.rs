@@ -0,0 +1,132 @@
+//! Recommended safety stop
+//!
+//! Dives deeper than `MIN_DEPTH` end with a stop of `STANDARD_STOP` between `TOP` and `BOTTOM`
+//! on the way up, also when the decompression model doesn't ask for one. A dive that came
+//! within `NDL_MARGIN` of the no-decompression limit gets `EXTENDED_STOP` instead, and the main
+//! page says why. Time outside the stop range doesn't count but isn't lost either, so the
+//! diver can drift a little.
+
+use fugit::{MicrosDurationU32, MicrosDurationU64, SecsDurationU32};
+
+/// Dives deeper than this in millimeters need a safety stop
+pub const MIN_DEPTH: u32 = 10_000;
+
+/// Shallowest depth of the stop in millimeters
+pub const TOP: u32 = 3_000;
+
+/// Deepest depth of the stop in millimeters
+pub const BOTTOM: u32 = 6_000;
+
+pub const STANDARD_STOP: SecsDurationU32 = SecsDurationU32::minutes(3);
+
+/// Stop after pushing the no-decompression limit
+pub const EXTENDED_STOP: SecsDurationU32 = SecsDurationU32::minutes(5);
+
+/// A no-decompression limit shorter than this extends the stop
+pub const NDL_MARGIN: SecsDurationU32 = SecsDurationU32::minutes(3);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SafetyStop {
+    /// The dive went deeper than `MIN_DEPTH`
+    required: bool,
+    /// The dive came within `NDL_MARGIN` of the no-decompression limit
+    extended: bool,
+    /// Time spent in the stop range
+    done: MicrosDurationU64,
+}
+
+impl SafetyStop {
+    /// No stop needed
+    pub const fn new() -> Self {
+        SafetyStop {
+            required: false,
+            extended: false,
+            done: MicrosDurationU64::from_ticks(0),
+        }
+    }
+
+    /// Spend `duration` at `depth` in millimeters, `near_ndl` when a stop would be needed within
+    /// `NDL_MARGIN`
+    pub fn update(&mut self, depth: u32, near_ndl: bool, duration: MicrosDurationU32) {
+        self.required |= depth > MIN_DEPTH;
+        self.extended |= self.required && near_ndl;
+        if self.required && (TOP..=BOTTOM).contains(&depth) {
+            self.done += MicrosDurationU64::from(duration);
+        }
+    }
+
+    /// Length of the stop of this dive
+    pub fn length(&self) -> SecsDurationU32 {
+        if self.extended {
+            EXTENDED_STOP
+        } else {
+            STANDARD_STOP
+        }
+    }
+
+    /// Whether the stop was extended because the dive pushed the no-decompression limit
+    pub fn extended(&self) -> bool {
+        self.extended
+    }
+
+    /// Time left of the stop rounded up, `None` when no stop is needed or it is done
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::safety_stop::SafetyStop;
+    /// use fugit::{MicrosDurationU32, SecsDurationU32};
+    ///
+    /// let mut stop = SafetyStop::new();
+    /// stop.update(18_000, false, MicrosDurationU32::secs(1));
+    /// stop.update(5_000, false, MicrosDurationU32::secs(60));
+    /// assert_eq!(stop.remaining(), Some(SecsDurationU32::minutes(2)));
+    /// ```
+    ///
+    pub fn remaining(&self) -> Option<SecsDurationU32> {
+        let length = MicrosDurationU64::secs(self.length().to_secs().into());
+        (self.required && self.done < length).then(|| SecsDurationU32::secs((length - self.done).to_micros().div_ceil(1_000_000) as u32))
+    }
+
+    /// Time left of the stop while it is shown, from the bottom of the stop up
+    pub fn due(&self, depth: u32) -> Option<SecsDurationU32> {
+        self.remaining().filter(|_| 0 < depth && depth <= BOTTOM)
+    }
+}
+
+impl Default for SafetyStop {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_stop_extended_after_pushing_ndl() {
+        let minute = MicrosDurationU32::minutes(1);
+        let mut stop = SafetyStop::new();
+        stop.update(8_000, false, minute);
+        assert_eq!(stop.remaining(), None);
+
+        stop.update(20_000, false, minute);
+        assert_eq!(stop.remaining(), Some(STANDARD_STOP));
+        // Not shown before the ascent
+        assert_eq!(stop.due(20_000), None);
+
+        stop.update(25_000, true, minute);
+        assert!(stop.extended());
+        stop.update(5_000, false, minute);
+        // Time above the stop doesn't count
+        stop.update(2_000, false, minute);
+        stop.update(6_000, false, MicrosDurationU32::secs(150));
+        assert_eq!(stop.due(6_000), Some(SecsDurationU32::secs(90)));
+        assert_eq!(stop.due(0), None);
+
+        stop.update(4_000, false, MicrosDurationU32::secs(90));
+        assert_eq!(stop.remaining(), None);
+    }
+}
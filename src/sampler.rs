@@ -0,0 +1,212 @@
+//! ADC sampling ring
+//!
+//! Instead of blocking reads, the ADC converts its four inputs round robin on its own at
+//! `SAMPLE_RATE`, and a DMA channel writes every result into a `SampleRing` in RAM. The tasks
+//! read the average of the last `DEPTH` samples of an input whenever they like, without waiting
+//! for a conversion and with the same spacing between samples every time.
+//!
+//! The round robin starts at input 0 and the ring holds a whole number of rounds, so sample `i`
+//! always belongs to input `i % INPUTS`. The DMA wraps its write address within the ring, which
+//! is why the ring is aligned to its own size.
+//!
+//! There is no oxygen cell on the kit. One would need an amplifier for its millivolts and take
+//! the place of the joystick or thermistor on input 2.
+
+use pimoroni_pico_explorer::hal::pac;
+
+/// Inputs sampled round robin
+pub const INPUTS: usize = 4;
+
+/// Samples kept per input
+pub const DEPTH: usize = 16;
+
+/// Length of the ring in samples
+const RING_LEN: usize = INPUTS * DEPTH;
+
+/// Conversions per second over all inputs, each input gets a quarter of them
+pub const SAMPLE_RATE: u32 = 4_000;
+
+/// Clock of the ADC after `init_clocks_and_plls`
+const ADC_CLOCK_HZ: u32 = 48_000_000;
+
+/// DMA channel of the ring, no other code uses DMA
+const DMA_CHANNEL: usize = 0;
+
+/// DREQ of the ADC FIFO, `hal::dma::DREQ_ADC`
+const DREQ_ADC: u8 = 36;
+
+/// Input of the ADC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdcInput {
+    /// Joystick X on GPIO26
+    JoystickX,
+    /// Joystick Y on GPIO27
+    JoystickY,
+    /// Joystick button or thermistor on GPIO28
+    Adc2,
+    /// VSYS through a 1:3 divider on GPIO29
+    Vsys,
+}
+
+/// Last `DEPTH` samples of every input, written by the DMA
+#[repr(C, align(128))]
+pub struct SampleRing {
+    samples: [u16; RING_LEN],
+}
+
+impl SampleRing {
+    pub const fn new() -> Self {
+        SampleRing { samples: [0; RING_LEN] }
+    }
+
+    /// Average of the last samples of `input` after `written` samples, `None` before its first
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::sampler::{AdcInput, SampleRing};
+    /// let ring = SampleRing::new();
+    /// assert_eq!(ring.average(AdcInput::Vsys, 3), None);
+    /// assert_eq!(ring.average(AdcInput::Vsys, 4), Some(0));
+    /// ```
+    ///
+    pub fn average(&self, input: AdcInput, written: u32) -> Option<u16> {
+        let input = input as usize;
+        let rounds = (written as usize + INPUTS - 1 - input) / INPUTS;
+        let count = rounds.min(DEPTH);
+        if count == 0 {
+            return None;
+        }
+
+        // The DMA writes while we read, a single sample is written at once
+        let sum: u32 = (0..count)
+            .map(|round| u32::from(unsafe { core::ptr::read_volatile(&self.samples[round * INPUTS + input]) }))
+            .sum();
+        Some((sum / count as u32) as u16)
+    }
+}
+
+impl Default for SampleRing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Free-running ADC filling a `SampleRing` through DMA
+pub struct AdcSampler {
+    ring: &'static SampleRing,
+}
+
+impl AdcSampler {
+    /// Start sampling into `ring`, the ADC has to be powered up, e.g. by creating a `hal::adc::Adc`
+    pub fn start(ring: &'static mut SampleRing, resets: &mut pac::RESETS) -> Self {
+        resets.reset.modify(|_, w| w.dma().clear_bit());
+        while resets.reset_done.read().dma().bit_is_clear() {}
+
+        let ring: &'static SampleRing = ring;
+        let sampler = AdcSampler { ring };
+        sampler.restart();
+        sampler
+    }
+
+    /// Number of samples written since the DMA was started
+    pub fn written(&self) -> u32 {
+        let dma = unsafe { &*pac::DMA::ptr() };
+        u32::MAX - dma.ch[DMA_CHANNEL].ch_trans_count.read().bits()
+    }
+
+    /// Average of the last `DEPTH` samples of `input`
+    pub fn average(&self, input: AdcInput) -> Option<u16> {
+        self.ring.average(input, self.written())
+    }
+
+    /// Start over once the transfer count ran out, after about 12 days
+    pub fn keep_running(&self) {
+        let dma = unsafe { &*pac::DMA::ptr() };
+        if dma.ch[DMA_CHANNEL].ch_ctrl_trig.read().busy().bit_is_clear() {
+            self.restart();
+        }
+    }
+
+    /// Stop the ADC, empty its FIFO and start again from input 0 at the start of the ring
+    fn restart(&self) {
+        // Only touches the registers of the ADC and our DMA channel
+        let (adc, dma) = unsafe { (&*pac::ADC::ptr(), &*pac::DMA::ptr()) };
+
+        adc.cs.modify(|_, w| w.start_many().clear_bit());
+        while adc.cs.read().ready().bit_is_clear() {}
+        adc.fcs.write(|w| {
+            w.en()
+                .set_bit()
+                .dreq_en()
+                .set_bit()
+                .err()
+                .clear_bit()
+                .shift()
+                .clear_bit()
+                .over()
+                .set_bit()
+                .under()
+                .set_bit()
+        });
+        unsafe { adc.fcs.modify(|_, w| w.thresh().bits(1)) };
+        while adc.fcs.read().level().bits() > 0 {
+            adc.fifo.read();
+        }
+        // A conversion every `div + 1` cycles of the ADC clock
+        unsafe { adc.div.write(|w| w.int().bits((ADC_CLOCK_HZ / SAMPLE_RATE - 1) as u16)) };
+
+        let channel = &dma.ch[DMA_CHANNEL];
+        channel.ch_read_addr.write(|w| unsafe { w.bits(&adc.fifo as *const _ as u32) });
+        channel.ch_write_addr.write(|w| unsafe { w.bits(self.ring.samples.as_ptr() as u32) });
+        channel.ch_trans_count.write(|w| unsafe { w.bits(u32::MAX) });
+        channel.ch_ctrl_trig.write(|w| unsafe {
+            w.treq_sel()
+                .bits(DREQ_ADC)
+                // Chaining to itself means no chaining
+                .chain_to()
+                .bits(DMA_CHANNEL as u8)
+                // Wrap the write address at the size of the ring in bytes, 2 to the power of 7
+                .ring_sel()
+                .set_bit()
+                .ring_size()
+                .bits((RING_LEN * 2).trailing_zeros() as u8)
+                .incr_write()
+                .set_bit()
+                .incr_read()
+                .clear_bit()
+                .data_size()
+                .size_halfword()
+                .en()
+                .set_bit()
+        });
+
+        adc.cs
+            .modify(|_, w| unsafe { w.ainsel().bits(0).rrobin().bits((1 << INPUTS) - 1).start_many().set_bit() });
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_average_per_input() {
+        let mut ring = SampleRing::new();
+        for (index, sample) in ring.samples.iter_mut().enumerate() {
+            // Every input reads its own level, with a little noise
+            *sample = (index % INPUTS) as u16 * 1000 + (index / INPUTS % 2) as u16 * 10;
+        }
+
+        // Only the first round is written
+        assert_eq!(ring.average(AdcInput::JoystickY, 2), Some(1000));
+        assert_eq!(ring.average(AdcInput::Adc2, 2), None);
+        // Two rounds of input 3, one of the others
+        assert_eq!(ring.average(AdcInput::Vsys, 8), Some(3005));
+        assert_eq!(ring.average(AdcInput::Adc2, 7), Some(2005));
+        // Wrapped around, all samples count
+        assert_eq!(ring.average(AdcInput::JoystickX, 1_000_001), Some(5));
+        assert_eq!(core::mem::align_of::<SampleRing>(), RING_LEN * 2);
+    }
+}
@@ -0,0 +1,134 @@
+//! Burn-in protection
+//!
+//! The ST7789 shows the same text for hours in the classroom. To spread the wear the layout moves
+//! by a pixel every `shift_interval`, and without button presses or alarms the text is dimmed
+//! and later blanked.
+
+use embedded_graphics::prelude::Point;
+use fugit::SecsDurationU32;
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Instant;
+
+/// Offsets the layout cycles through, at most a pixel from the original position
+const SHIFT_PATTERN: [Point; 4] = [Point::new(0, 0), Point::new(1, 0), Point::new(1, 1), Point::new(0, 1)];
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ScreenSaverConfig {
+    /// Inactivity before the text is dimmed
+    #[serde(with = "secs")]
+    pub dim_after: SecsDurationU32,
+    /// Inactivity before the screen is blanked
+    #[serde(with = "secs")]
+    pub blank_after: SecsDurationU32,
+    /// Time between moves of the layout
+    #[serde(with = "secs")]
+    pub shift_interval: SecsDurationU32,
+}
+
+impl ScreenSaverConfig {
+    pub const fn new() -> Self {
+        ScreenSaverConfig {
+            dim_after: SecsDurationU32::minutes(2),
+            blank_after: SecsDurationU32::minutes(10),
+            shift_interval: SecsDurationU32::minutes(1),
+        }
+    }
+}
+
+impl Default for ScreenSaverConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Durations as whole seconds, fugit has no serde support
+mod secs {
+    use fugit::SecsDurationU32;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &SecsDurationU32, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(duration.ticks())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<SecsDurationU32, D::Error> {
+        u32::deserialize(deserializer).map(SecsDurationU32::secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenState {
+    On,
+    Dimmed,
+    Blank,
+}
+
+/// Tracks activity to decide what the screen should show
+#[derive(Debug, Clone, Copy)]
+pub struct ScreenSaver {
+    last_activity: Instant,
+}
+
+impl ScreenSaver {
+    pub const fn new() -> Self {
+        ScreenSaver {
+            last_activity: Instant::from_ticks(0),
+        }
+    }
+
+    /// Register a button press or alarm at `now`, returns whether the screen was on
+    ///
+    /// A press which wakes the screen should not do anything else, the user couldn't see what it would do.
+    pub fn wake(&mut self, now: Instant, config: &ScreenSaverConfig) -> bool {
+        let was_on = self.state(now, config) == ScreenState::On;
+        self.last_activity = now;
+        was_on
+    }
+
+    pub fn state(&self, now: Instant, config: &ScreenSaverConfig) -> ScreenState {
+        let inactive = now.checked_duration_since(self.last_activity).map_or(0, |inactive| inactive.to_secs());
+
+        if inactive >= u64::from(config.blank_after.to_secs()) {
+            ScreenState::Blank
+        } else if inactive >= u64::from(config.dim_after.to_secs()) {
+            ScreenState::Dimmed
+        } else {
+            ScreenState::On
+        }
+    }
+
+    /// Offset to move the layout by at `now`
+    pub fn offset(&self, now: Instant, config: &ScreenSaverConfig) -> Point {
+        let shifts = now.duration_since_epoch().to_secs() / u64::from(config.shift_interval.to_secs().max(1));
+        SHIFT_PATTERN[(shifts % SHIFT_PATTERN.len() as u64) as usize]
+    }
+}
+
+impl Default for ScreenSaver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use fugit::MicrosDurationU64;
+
+    #[test]
+    fn test_dim_blank_and_wake() {
+        let config = ScreenSaverConfig::new();
+        let mut screen_saver = ScreenSaver::new();
+        let at = |secs| Instant::from_ticks(0) + MicrosDurationU64::secs(secs);
+
+        assert_eq!(screen_saver.state(at(60), &config), ScreenState::On);
+        assert_eq!(screen_saver.offset(at(60), &config), Point::new(1, 0));
+        assert_eq!(screen_saver.state(at(120), &config), ScreenState::Dimmed);
+        assert_eq!(screen_saver.state(at(600), &config), ScreenState::Blank);
+
+        assert!(!screen_saver.wake(at(600), &config));
+        assert_eq!(screen_saver.state(at(601), &config), ScreenState::On);
+        assert!(screen_saver.wake(at(602), &config));
+    }
+}
@@ -0,0 +1,202 @@
+//! On-device self test
+//!
+//! Holding Y on the diagnostics page runs a curated set of pure-logic checks on the target and
+//! shows how many passed on the hidden self test page. The host tests cover the same code, but
+//! only on the host: these checks catch what goes wrong on the Cortex-M0+ alone, like integer
+//! widths, the 64-bit math of the deco models or a formatter that behaves differently.
+//!
+//! Every check is a plain function without side effects, so the checks can run at any time.
+
+use core::fmt;
+
+use fugit::SecsDurationU32;
+
+use crate::{
+    budget::UiBuffer,
+    clock::ManualClock,
+    format::Digits,
+    gas::{depth_in_m, gas_for_segment, gas_rate_in_cl, gas_to_surface_in_cl, ndl_air_limited, pressure_in_cb, MAX_SAFE_ASCEND_RATE},
+    keymap::Action,
+    text_buffer::TextBuffer,
+    DiveComputer, Unit,
+};
+
+/// Widest line on the screen in characters
+const LINE_WIDTH: usize = 20;
+
+/// A named check, `run` returns whether it passed
+pub struct Check {
+    /// At most 12 characters, to fit after "FAILED: "
+    pub name: &'static str,
+    pub run: fn() -> bool,
+}
+
+/// Checks that run in every build
+pub const CHECKS: &[Check] = &[
+    Check {
+        name: "GAS PRESSURE",
+        run: || pressure_in_cb(30) == 400 && depth_in_m(250) == 15,
+    },
+    Check {
+        name: "GAS RATE",
+        run: || gas_rate_in_cl(10) == 40 && gas_for_segment(10, SecsDurationU32::minutes(5)) == 12_000,
+    },
+    Check {
+        name: "GAS TO SURF",
+        run: || gas_to_surface_in_cl(10, MAX_SAFE_ASCEND_RATE) == 1160 && ndl_air_limited(10, 5000, MAX_SAFE_ASCEND_RATE) == SecsDurationU32::secs(96),
+    },
+    Check {
+        name: "DIGITS",
+        run: || Digits::tenths(-5).as_str() == "-0.5" && Digits::new(i64::MIN).as_str() == "-9223372036854775808",
+    },
+    Check {
+        name: "PAGE WIDTH",
+        run: page_width,
+    },
+    Check {
+        name: "FAST FORMAT",
+        run: fast_format,
+    },
+];
+
+/// Spot checks of the deco models
+#[cfg(feature = "deco")]
+pub const DECO_CHECKS: &[Check] = &[
+    Check {
+        name: "ZHL16 NDL",
+        run: || (14..=18).contains(&deco_checks::ndl_minutes::<crate::deco::zhl16::Zhl16>(30_000)),
+    },
+    Check {
+        name: "HALDANE NDL",
+        run: || (9..=13).contains(&deco_checks::ndl_minutes::<crate::deco::haldane::Haldane>(30_000)),
+    },
+];
+
+/// Spot checks of the deco models
+#[cfg(not(feature = "deco"))]
+pub const DECO_CHECKS: &[Check] = &[];
+
+#[cfg(feature = "deco")]
+mod deco_checks {
+    use fugit::MicrosDurationU32;
+
+    use crate::deco::{DecoModel, Gas};
+
+    /// NDL of a fresh `M` at `depth` on air
+    pub fn ndl_minutes<M: DecoModel + Default>(depth: u32) -> u32 {
+        let mut model = M::default();
+        model.tick(depth, MicrosDurationU32::micros(0), Gas::AIR);
+        model.ndl().to_minutes()
+    }
+}
+
+/// Dive computer 15 m deep
+fn diving() -> DiveComputer<ManualClock> {
+    let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+    for _ in 0..10 {
+        dive_computer.perform(Action::IncreaseRate);
+    }
+    dive_computer.change_depth(fugit::MicrosDurationU32::secs(90));
+    dive_computer
+}
+
+/// Every line of the main page fits on the screen in every unit, trailing spaces only clear old text
+fn page_width() -> bool {
+    let mut dive_computer = diving();
+    let mut buf = UiBuffer::new();
+    [Unit::Metric, Unit::Imperial, Unit::Both].into_iter().all(|unit| {
+        dive_computer.set_unit(unit);
+        buf.clear_and_write(format_args!("{}", dive_computer));
+        !buf.truncated() && buf.lines().all(|line| line.trim_end().chars().count() <= LINE_WIDTH)
+    })
+}
+
+/// The fast formatter writes the same text as `Display`
+fn fast_format() -> bool {
+    let dive_computer = diving();
+    let (mut fast, mut display) = (UiBuffer::new(), UiBuffer::new());
+    display.clear_and_write(format_args!("{}\n", dive_computer));
+    dive_computer.render_fast(&mut fast).is_ok() && fast == display
+}
+
+/// All checks, the deco checks last
+pub fn checks() -> impl Iterator<Item = &'static Check> {
+    CHECKS.iter().chain(DECO_CHECKS)
+}
+
+/// Outcome of a self test run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// Bit per check in the order of `checks`, set when it failed
+    failed: u32,
+    total: u8,
+}
+
+impl SelfTestReport {
+    /// Report of no checks, before the first run
+    pub const fn new() -> Self {
+        SelfTestReport { failed: 0, total: 0 }
+    }
+
+    pub fn passed(&self) -> u32 {
+        u32::from(self.total) - self.failed.count_ones()
+    }
+
+    pub fn total(&self) -> u32 {
+        u32::from(self.total)
+    }
+
+    /// Names of the failed checks
+    pub fn failures(&self) -> impl Iterator<Item = &'static str> + '_ {
+        checks().enumerate().filter(|(index, _)| self.failed & (1 << index) != 0).map(|(_, check)| check.name)
+    }
+}
+
+impl Default for SelfTestReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Run all checks
+pub fn run() -> SelfTestReport {
+    let mut report = SelfTestReport::new();
+    for (index, check) in checks().enumerate().take(32) {
+        if !(check.run)() {
+            report.failed |= 1 << index;
+        }
+        report.total += 1;
+    }
+    report
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Self test")?;
+        writeln!(f)?;
+        let mut count = TextBuffer::<8>::new();
+        write!(count, "{}/{}", self.passed(), self.total());
+        write!(f, "PASSED: {:>12}", count)?;
+        for name in self.failures() {
+            write!(f, "\nFAILED: {:>12}", name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_all_checks_pass() {
+        let report = run();
+        assert_eq!(report.total() as usize, CHECKS.len() + DECO_CHECKS.len());
+        assert_eq!(report.failures().count(), 0);
+        assert!(checks().all(|check| check.name.len() <= 12));
+
+        let failing = SelfTestReport { failed: 0b10, total: 3 };
+        assert_eq!(format!("{}", failing), "Self test\n\nPASSED:          2/3\nFAILED:     GAS RATE");
+    }
+}
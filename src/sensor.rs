@@ -0,0 +1,309 @@
+//! Pressure sensor path
+//!
+//! A pressure sensor reports the absolute pressure, which includes the air pressure at the
+//! surface. That changes with the weather and altitude, so before a dive the surface pressure is
+//! measured by averaging `ZERO_SAMPLES` readings and stored as an offset from the standard
+//! atmosphere. Zeroing is refused when the pressure looks like the diver is already more than
+//! `MAX_ZERO_DEPTH` deep, or like no sensor is there at all.
+//!
+//! Water pressure follows the rest of the crate: 10 m of sea water is 1 bar, so a millibar is
+//! 10 mm. Fresh water is lighter, a millibar is 10.2 mm of it.
+//!
+//! `Monitored` wraps a sensor driver and watches its health: a sensor that stops answering or
+//! keeps returning the exact same reading is considered broken, and stays so until it is reset.
+
+use serde::{Deserialize, Serialize};
+
+/// Air pressure of the standard atmosphere in millibar
+pub const STANDARD_SURFACE_PRESSURE: u32 = 1013;
+
+/// Number of readings averaged when zeroing
+pub const ZERO_SAMPLES: usize = 16;
+
+/// Deepest apparent depth in millimeters, compared to the standard atmosphere, that can be zeroed
+pub const MAX_ZERO_DEPTH: u32 = 2_000;
+
+/// Lowest surface pressure in millibar that can be zeroed, about 4000 m altitude
+const MIN_SURFACE_PRESSURE: u32 = 600;
+
+/// Failed readings in a row before the sensor is considered broken, e.g. I2C NAKs
+pub const MAX_FAILED_READS: u8 = 3;
+
+/// Identical readings in a row before the sensor is considered stuck, a working sensor is noisy
+pub const MAX_IDENTICAL_READS: u16 = 300;
+
+/// Source of absolute pressure readings
+pub trait PressureSensor {
+    type Error;
+
+    /// Absolute pressure in millibar
+    fn read(&mut self) -> Result<u32, Self::Error>;
+}
+
+/// Water the diver is in, sets how deep a millibar of water pressure is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Water {
+    #[default]
+    Salt,
+    Fresh,
+}
+
+impl Water {
+    /// Depth in millimeters of `pressure` millibar of this water
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::sensor::Water;
+    /// assert_eq!(Water::Salt.depth(1_000), 10_000);
+    /// assert_eq!(Water::Fresh.depth(1_000), 10_200);
+    /// ```
+    ///
+    pub fn depth(&self, pressure: u32) -> u32 {
+        match self {
+            Water::Salt => pressure * 10,
+            Water::Fresh => pressure * 102 / 10,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Water::Salt => Water::Fresh,
+            Water::Fresh => Water::Salt,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Water::Salt => "SALT",
+            Water::Fresh => "FRESH",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ZeroError<E> {
+    /// The sensor failed to give a reading
+    Sensor(E),
+    /// Apparent depth over `MAX_ZERO_DEPTH`, the diver is probably in the water
+    TooDeep,
+    /// Below any air pressure a diver would zero at, the sensor is probably broken
+    TooLow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// Stopped answering
+    NoResponse,
+    /// Keeps returning the same value
+    Stuck,
+}
+
+impl Fault {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Fault::NoResponse => "NO RESPONSE",
+            Fault::Stuck => "STUCK",
+        }
+    }
+}
+
+/// Surface pressure as an offset from the standard atmosphere
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Calibration {
+    /// Offset in millibar
+    pub offset: i32,
+}
+
+impl Calibration {
+    /// Assume the standard atmosphere
+    pub const fn new() -> Self {
+        Calibration { offset: 0 }
+    }
+
+    /// Surface pressure in millibar
+    pub fn surface_pressure(&self) -> u32 {
+        STANDARD_SURFACE_PRESSURE.saturating_add_signed(self.offset)
+    }
+
+    /// Depth in millimeters of salt water at an absolute pressure in millibar, zero above the surface pressure
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::sensor::Calibration;
+    /// let calibration = Calibration { offset: -13 };
+    /// assert_eq!(calibration.depth(1_500), 5_000);
+    /// assert_eq!(calibration.depth(990), 0);
+    /// ```
+    ///
+    pub fn depth(&self, pressure: u32) -> u32 {
+        self.depth_in(pressure, Water::Salt)
+    }
+
+    /// Depth in millimeters of `water` at an absolute pressure in millibar
+    pub fn depth_in(&self, pressure: u32, water: Water) -> u32 {
+        water.depth(pressure.saturating_sub(self.surface_pressure()))
+    }
+
+    /// Measure the surface pressure with `sensor`, by averaging `ZERO_SAMPLES` readings
+    pub fn zero<S: PressureSensor>(sensor: &mut S) -> Result<Self, ZeroError<S::Error>> {
+        let mut total = 0;
+        for _ in 0..ZERO_SAMPLES {
+            total += sensor.read().map_err(ZeroError::Sensor)?;
+        }
+        let average = total / ZERO_SAMPLES as u32;
+
+        if Calibration::new().depth(average) > MAX_ZERO_DEPTH {
+            return Err(ZeroError::TooDeep);
+        }
+        if average < MIN_SURFACE_PRESSURE {
+            return Err(ZeroError::TooLow);
+        }
+
+        Ok(Calibration {
+            offset: average as i32 - STANDARD_SURFACE_PRESSURE as i32,
+        })
+    }
+}
+
+/// A sensor with health monitoring
+pub struct Monitored<S> {
+    sensor: S,
+    calibration: Calibration,
+    water: Water,
+    /// Failed readings in a row
+    failed: u8,
+    /// Last reading in millibar and how often it came in a row
+    last: Option<(u32, u16)>,
+    fault: Option<Fault>,
+}
+
+impl<S: PressureSensor> Monitored<S> {
+    pub fn new(sensor: S, calibration: Calibration) -> Self {
+        Monitored {
+            sensor,
+            calibration,
+            water: Water::Salt,
+            failed: 0,
+            last: None,
+            fault: None,
+        }
+    }
+
+    /// Depth in millimeters, or the fault once the sensor is considered broken
+    pub fn read_depth(&mut self) -> Result<u32, Fault> {
+        if let Some(fault) = self.fault {
+            return Err(fault);
+        }
+
+        match self.sensor.read() {
+            Ok(pressure) => {
+                self.failed = 0;
+                let repeats = match self.last {
+                    Some((last, repeats)) if last == pressure => repeats.saturating_add(1),
+                    _ => 1,
+                };
+                self.last = Some((pressure, repeats));
+
+                if repeats >= MAX_IDENTICAL_READS {
+                    return Err(*self.fault.insert(Fault::Stuck));
+                }
+                Ok(self.calibration.depth_in(pressure, self.water))
+            }
+            Err(_) => {
+                self.failed += 1;
+                if self.failed >= MAX_FAILED_READS {
+                    return Err(*self.fault.insert(Fault::NoResponse));
+                }
+                // A single failed read keeps the last depth
+                self.last
+                    .map(|(pressure, _)| self.calibration.depth_in(pressure, self.water))
+                    .ok_or(Fault::NoResponse)
+            }
+        }
+    }
+
+    pub fn set_water(&mut self, water: Water) {
+        self.water = water;
+    }
+
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    /// Last raw reading in millibar, for the technical page
+    pub fn pressure(&self) -> Option<u32> {
+        self.last.map(|(pressure, _)| pressure)
+    }
+
+    /// Start monitoring from scratch, e.g. after the sensor was reconnected
+    pub fn reset(&mut self) {
+        self.failed = 0;
+        self.last = None;
+        self.fault = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    /// Sensor repeating a list of readings
+    struct Replay<'a>(core::iter::Cycle<core::slice::Iter<'a, u32>>);
+
+    impl PressureSensor for Replay<'_> {
+        type Error = ();
+
+        fn read(&mut self) -> Result<u32, ()> {
+            self.0.next().copied().ok_or(())
+        }
+    }
+
+    fn zero(readings: &[u32]) -> Result<Calibration, ZeroError<()>> {
+        Calibration::zero(&mut Replay(readings.iter().cycle()))
+    }
+
+    #[test]
+    fn test_zero() {
+        // Noisy readings on a low pressure day
+        let calibration = zero(&[985, 987, 989, 987]).unwrap();
+        assert_eq!(calibration.offset, -26);
+        assert_eq!(calibration.depth(987 + 100), 1_000);
+
+        // 2 m deep is still accepted, any deeper is not
+        assert!(zero(&[1_213]).is_ok());
+        assert_eq!(zero(&[1_214]), Err(ZeroError::TooDeep));
+        assert_eq!(zero(&[300]), Err(ZeroError::TooLow));
+        assert_eq!(zero(&[]), Err(ZeroError::Sensor(())));
+    }
+
+    #[test]
+    fn test_monitored_faults() {
+        let readings = [1_013, 1_014, 1_513];
+        let mut sensor = Monitored::new(Replay(readings.iter().cycle()), Calibration::new());
+        assert_eq!(sensor.read_depth(), Ok(0));
+        assert_eq!(sensor.read_depth(), Ok(10));
+        assert_eq!(sensor.read_depth(), Ok(5_000));
+        sensor.set_water(Water::Fresh);
+        assert_eq!(sensor.read_depth(), Ok(0));
+        assert_eq!(sensor.read_depth(), Ok(10));
+        assert_eq!(sensor.read_depth(), Ok(5_100));
+
+        let mut stuck = Monitored::new(Replay([1_200].iter().cycle()), Calibration::new());
+        for _ in 1..MAX_IDENTICAL_READS {
+            assert_eq!(stuck.read_depth(), Ok(1_870));
+        }
+        assert_eq!(stuck.read_depth(), Err(Fault::Stuck));
+        stuck.reset();
+        assert_eq!(stuck.read_depth(), Ok(1_870));
+
+        let mut silent = Monitored::new(Replay([].iter().cycle()), Calibration::new());
+        assert_eq!(silent.read_depth(), Err(Fault::NoResponse));
+        assert_eq!(silent.fault(), None);
+        silent.read_depth().unwrap_err();
+        assert_eq!(silent.read_depth(), Err(Fault::NoResponse));
+        assert_eq!(silent.fault(), Some(Fault::NoResponse));
+    }
+}
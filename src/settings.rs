@@ -0,0 +1,639 @@
+//! User settings and the settings page
+//!
+//! Settings only live in RAM, they are back to the defaults after a reset.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    air_integration::{FillRate, TankSize},
+    apnea::ApneaTables,
+    ascent::AscentLimit,
+    checklist::ChecklistMode,
+    clock::{DriftCorrection, TimeScale},
+    deco::{Gas, GradientFactors, Zhl16Variant},
+    depth_alert::{DepthAlerts, MAX_DEPTH_ALERTS},
+    gas_switch::{DecoGases, MAX_DECO_GASES},
+    instructor::BusAddress,
+    keymap::{Action, Button, KeyBindings, Press, BUTTON_COUNT, PRESS_COUNT},
+    ndl_warning::{NdlWarnings, MAX_NDL_WARNINGS},
+    rate_limit::RateLimit,
+    render::{RefreshRate, RenderConfig},
+    reserve::ReserveConfig,
+    screen_saver::ScreenSaverConfig,
+    sensor::{Calibration, Water},
+    strobe::StrobeMode,
+    trend::DepthDamping,
+    ui::Page,
+    units::DepthDisplay,
+    EdtFormat, Unit,
+};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Settings {
+    pub bindings: KeyBindings,
+    pub screen_saver: ScreenSaverConfig,
+    pub gradient_factors: GradientFactors,
+    /// Coefficients of the ZHL-16 model
+    pub deco_variant: Zhl16Variant,
+    /// Ascent rate of the coaching, the alarm and the gas to the surface
+    pub ascent_limit: AscentLimit,
+    pub reserve: ReserveConfig,
+    /// Simulation speed, for demos
+    pub time_scale: TimeScale,
+    /// Simulated compressor
+    pub fill_rate: FillRate,
+    /// How fast the simulated rate follows the buttons
+    pub rate_limit: RateLimit,
+    /// Surface pressure offset of the pressure sensor
+    pub calibration: Calibration,
+    /// Drift of the crystal, measured with `clock sync` on the console
+    pub clock_drift: DriftCorrection,
+    pub refresh_rate: RefreshRate,
+    /// SPI clock and bands of the screen
+    pub render: RenderConfig,
+    pub edt_format: EdtFormat,
+    /// Averaging of the shown depth
+    pub depth_damping: DepthDamping,
+    /// Depth or ambient pressure on the main page
+    pub depth_display: DepthDisplay,
+    pub unit: Unit,
+    /// Water for the pressure sensor
+    pub water: Water,
+    pub tank: TankSize,
+    /// Checklist before each dive
+    pub checklist: ChecklistMode,
+    /// Address on the instructor bus
+    pub bus: BusAddress,
+    /// Locator beacon of the external strobe
+    pub strobe: StrobeMode,
+    /// Tables of the apnea page
+    pub apnea: ApneaTables,
+    pub depth_alerts: DepthAlerts,
+    pub ndl_warnings: NdlWarnings,
+    /// Gas breathed during the dive
+    pub back_gas: Gas,
+    /// Gases to remind of at their switch depths
+    pub deco_gases: DecoGases,
+}
+
+impl Settings {
+    pub const fn new() -> Self {
+        Settings {
+            bindings: KeyBindings::new(),
+            screen_saver: ScreenSaverConfig::new(),
+            gradient_factors: GradientFactors::new(),
+            deco_variant: Zhl16Variant::C,
+            ascent_limit: AscentLimit::new(),
+            reserve: ReserveConfig::new(),
+            time_scale: TimeScale::RealTime,
+            fill_rate: FillRate::L250,
+            rate_limit: RateLimit::M10,
+            calibration: Calibration::new(),
+            clock_drift: DriftCorrection::new(),
+            refresh_rate: RefreshRate::Hz10,
+            render: RenderConfig::new(),
+            edt_format: EdtFormat::HoursMinutesSeconds,
+            depth_damping: DepthDamping::Instant,
+            depth_display: DepthDisplay::Depth,
+            unit: Unit::Metric,
+            water: Water::Salt,
+            tank: TankSize::L10,
+            checklist: ChecklistMode::Off,
+            bus: BusAddress::Off,
+            strobe: StrobeMode::Alarms,
+            apnea: ApneaTables::new(),
+            depth_alerts: DepthAlerts::new(),
+            ndl_warnings: NdlWarnings::new(),
+            back_gas: Gas::AIR,
+            deco_gases: DecoGases::new(),
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Part of the settings edited on the settings page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    /// Key bindings of a page
+    Bindings(Page),
+    GradientFactors,
+    Reserve,
+    /// Speed and fill rate
+    TimeScale,
+    Display,
+    /// Render pipeline of the screen
+    Screen,
+    /// Unit, water and tank, also asked by the setup wizard, the checklist and the bus address
+    Diver,
+    DepthAlerts,
+    NdlWarnings,
+    BackGas,
+    DecoGases,
+}
+
+impl Section {
+    /// Section after this one, the bindings of the planner, apnea, signal and settings pages can't be edited
+    fn next(self) -> Self {
+        match self {
+            Section::Bindings(page) => match page.next() {
+                Page::Planner => Section::GradientFactors,
+                page => Section::Bindings(page),
+            },
+            Section::GradientFactors => Section::Reserve,
+            Section::Reserve => Section::TimeScale,
+            Section::TimeScale => Section::Display,
+            Section::Display => Section::Screen,
+            Section::Screen => Section::Diver,
+            Section::Diver => Section::DepthAlerts,
+            Section::DepthAlerts => Section::NdlWarnings,
+            Section::NdlWarnings => Section::BackGas,
+            Section::BackGas => Section::DecoGases,
+            Section::DecoGases => Section::Bindings(Page::Main),
+        }
+    }
+
+    /// Number of items in the section
+    fn items(self) -> usize {
+        match self {
+            Section::Bindings(_) => BUTTON_COUNT * PRESS_COUNT,
+            // Low and high, the coefficients they apply to and the ascent limit
+            Section::GradientFactors => 4,
+            // Warning and critical
+            Section::Reserve => 2,
+            // Speed, fill rate and rate limit
+            Section::TimeScale => 3,
+            // Refresh rate, dive time format, strobe, depth damping and depth readout
+            Section::Display => 5,
+            // SPI clock, band height and batching
+            Section::Screen => 3,
+            // Unit, water and tank, and the checklist and bus address the wizard leaves out
+            Section::Diver => DIVER_ITEMS + 2,
+            // Depth and direction per alert
+            Section::DepthAlerts => MAX_DEPTH_ALERTS * 2,
+            Section::NdlWarnings => MAX_NDL_WARNINGS,
+            // Oxygen and helium
+            Section::BackGas => 2,
+            Section::DecoGases => MAX_DECO_GASES,
+        }
+    }
+}
+
+/// Items of the diver section: unit, water and tank
+pub(crate) const DIVER_ITEMS: usize = 3;
+
+/// State of the settings page, one item of a section is selected at a time
+#[derive(Debug, Clone, Copy)]
+pub struct SettingsEditor {
+    section: Section,
+    /// Index of the selected item, for bindings buttons first
+    item: usize,
+}
+
+impl SettingsEditor {
+    pub const fn new() -> Self {
+        SettingsEditor {
+            section: Section::Bindings(Page::Main),
+            item: 0,
+        }
+    }
+
+    /// Editor of the diver section, for the setup wizard
+    pub(crate) const fn diver() -> Self {
+        SettingsEditor {
+            section: Section::Diver,
+            item: 0,
+        }
+    }
+
+    pub(crate) fn item(&self) -> usize {
+        self.item
+    }
+
+    /// Name and value of the selected diver item
+    pub(crate) fn write_diver_item(&self, f: &mut fmt::Formatter<'_>, settings: &Settings) -> fmt::Result {
+        let (name, value) = match self.item {
+            0 => ("UNIT", settings.unit.as_str()),
+            1 => ("WATER", settings.water.as_str()),
+            2 => ("TANK", settings.tank.as_str()),
+            3 => ("CHECKLIST", settings.checklist.as_str()),
+            _ => ("BUS ADDRESS", settings.bus.as_str()),
+        };
+        writeln!(f, "ITEM: {:>14}", name)?;
+        writeln!(f, "VALUE: {:>13}", value)
+    }
+
+    fn button(&self) -> Button {
+        Button::ALL[self.item / PRESS_COUNT]
+    }
+
+    fn press(&self) -> Press {
+        Press::ALL[self.item % PRESS_COUNT]
+    }
+
+    /// Handle one of the settings page actions, others are ignored
+    pub fn perform(&mut self, action: Action, settings: &mut Settings) {
+        match action {
+            Action::SelectItem => self.item = (self.item + 1) % self.section.items(),
+            Action::ChangeItem => match self.section {
+                Section::Bindings(page) => {
+                    let (button, press) = (self.button(), self.press());
+                    let next = settings.bindings.action(page, button, press).next();
+                    settings.bindings.set(page, button, press, next);
+                }
+                Section::GradientFactors if self.item == 0 => settings.gradient_factors.step_low(),
+                Section::GradientFactors if self.item == 1 => settings.gradient_factors.step_high(),
+                Section::GradientFactors if self.item == 2 => settings.deco_variant = settings.deco_variant.next(),
+                Section::GradientFactors => settings.ascent_limit = settings.ascent_limit.next(),
+                Section::Reserve if self.item == 0 => settings.reserve.step_warning(),
+                Section::Reserve => settings.reserve.step_critical(),
+                Section::TimeScale if self.item == 0 => settings.time_scale = settings.time_scale.next(),
+                Section::TimeScale if self.item == 1 => settings.fill_rate = settings.fill_rate.next(),
+                Section::TimeScale => settings.rate_limit = settings.rate_limit.next(),
+                Section::Display => match self.item {
+                    0 => settings.refresh_rate = settings.refresh_rate.next(),
+                    1 => settings.edt_format = settings.edt_format.next(),
+                    2 => settings.strobe = settings.strobe.next(),
+                    3 => settings.depth_damping = settings.depth_damping.next(),
+                    _ => settings.depth_display = settings.depth_display.next(),
+                },
+                Section::Screen => match self.item {
+                    0 => settings.render.spi_frequency = settings.render.spi_frequency.next(),
+                    1 => settings.render.chunk_rows = settings.render.chunk_rows.next(),
+                    _ => settings.render.batch = !settings.render.batch,
+                },
+                Section::Diver => match self.item {
+                    0 => settings.unit = settings.unit.next(),
+                    1 => settings.water = settings.water.next(),
+                    2 => settings.tank = settings.tank.next(),
+                    3 => settings.checklist = settings.checklist.next(),
+                    _ => settings.bus = settings.bus.next(),
+                },
+                Section::DepthAlerts if self.item.is_multiple_of(2) => settings.depth_alerts.step_depth(self.item / 2),
+                Section::DepthAlerts => settings.depth_alerts.step_crossing(self.item / 2),
+                Section::NdlWarnings => settings.ndl_warnings.step(self.item),
+                Section::BackGas if self.item == 0 => settings.back_gas.step_o2(),
+                Section::BackGas => settings.back_gas.step_he(),
+                Section::DecoGases => settings.deco_gases.step(self.item),
+            },
+            Action::SelectSection => {
+                self.section = self.section.next();
+                self.item = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Settings page showing the selected item
+    pub fn page<'a>(&'a self, settings: &'a Settings) -> SettingsPage<'a> {
+        SettingsPage { editor: self, settings }
+    }
+}
+
+impl Default for SettingsEditor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct SettingsPage<'a> {
+    editor: &'a SettingsEditor,
+    settings: &'a Settings,
+}
+
+impl fmt::Display for SettingsPage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Settings")?;
+        writeln!(f)?;
+
+        match self.editor.section {
+            Section::Bindings(page) => {
+                let (button, press) = (self.editor.button(), self.editor.press());
+                writeln!(f, "PAGE: {:>14}", page.as_str())?;
+                writeln!(f, "KEY: {:>10} {:>4}", button.as_str(), press.as_str())?;
+                writeln!(f, "DOES: {:>14}", self.settings.bindings.action(page, button, press).as_str())?;
+            }
+            Section::GradientFactors if self.editor.item == 2 => {
+                writeln!(f, "DECO MODEL")?;
+                writeln!(f, "ITEM: {:>14}", "COEFFICIENTS")?;
+                writeln!(f, "VALUE: {:>13}", self.settings.deco_variant.as_str())?;
+            }
+            Section::GradientFactors if self.editor.item == 3 => {
+                writeln!(f, "ASCENT LIMIT")?;
+                writeln!(f, "PRESET: {:>12}", self.settings.ascent_limit.as_str())?;
+                writeln!(f, "VALUE: {:>8}M/MIN", self.settings.ascent_limit.rate())?;
+            }
+            Section::GradientFactors => {
+                let GradientFactors { low, high } = self.settings.gradient_factors;
+                let (name, value) = if self.editor.item == 0 { ("LOW", low) } else { ("HIGH", high) };
+                writeln!(f, "GRADIENT FACTORS")?;
+                writeln!(f, "GF: {:>16}", name)?;
+                writeln!(f, "VALUE: {:>12}%", value)?;
+            }
+            Section::Reserve => {
+                let ReserveConfig { warning, critical } = self.settings.reserve;
+                let (name, value) = if self.editor.item == 0 { ("WARNING", warning) } else { ("CRITICAL", critical) };
+                writeln!(f, "AIR RESERVE")?;
+                writeln!(f, "ALARM: {:>13}", name)?;
+                writeln!(f, "VALUE: {:>10}BAR", value)?;
+            }
+            Section::TimeScale => {
+                let (name, value) = match self.editor.item {
+                    0 => ("SPEED", self.settings.time_scale.as_str()),
+                    1 => ("FILL RATE", self.settings.fill_rate.as_str()),
+                    _ => ("RATE LIMIT", self.settings.rate_limit.as_str()),
+                };
+                writeln!(f, "SIMULATION")?;
+                writeln!(f, "ITEM: {:>14}", name)?;
+                writeln!(f, "VALUE: {:>13}", value)?;
+            }
+            Section::Display => {
+                let (name, value) = match self.editor.item {
+                    0 => ("REFRESH", self.settings.refresh_rate.as_str()),
+                    1 => ("DIVE TIME", self.settings.edt_format.as_str()),
+                    2 => ("STROBE", self.settings.strobe.as_str()),
+                    3 => ("DAMPING", self.settings.depth_damping.as_str()),
+                    _ => ("DEPTH READOUT", self.settings.depth_display.as_str()),
+                };
+                writeln!(f, "DISPLAY")?;
+                writeln!(f, "ITEM: {:>14}", name)?;
+                writeln!(f, "VALUE: {:>13}", value)?;
+            }
+            Section::Screen => {
+                let RenderConfig {
+                    spi_frequency,
+                    chunk_rows,
+                    batch,
+                } = self.settings.render;
+                let (name, value) = match self.editor.item {
+                    0 => ("SPI CLOCK", spi_frequency.as_str()),
+                    1 => ("BAND", chunk_rows.as_str()),
+                    _ => ("BATCH", if batch { "ON" } else { "OFF" }),
+                };
+                writeln!(f, "SCREEN")?;
+                writeln!(f, "ITEM: {:>14}", name)?;
+                writeln!(f, "VALUE: {:>13}", value)?;
+            }
+            Section::Diver => {
+                writeln!(f, "DIVER")?;
+                self.editor.write_diver_item(f, self.settings)?;
+            }
+            Section::DepthAlerts => {
+                let index = self.editor.item / 2;
+                let name = if self.editor.item.is_multiple_of(2) { "DEPTH" } else { "CROSSING" };
+                writeln!(f, "DEPTH ALERTS")?;
+                writeln!(f, "ALERT {} {:>12}", index + 1, name)?;
+                match self.settings.depth_alerts.alerts[index] {
+                    Some(alert) if self.editor.item.is_multiple_of(2) => writeln!(f, "VALUE: {:>12}M", alert.depth_m)?,
+                    Some(alert) => writeln!(f, "VALUE: {:>13}", alert.crossing.as_str())?,
+                    None => writeln!(f, "VALUE: {:>13}", "OFF")?,
+                }
+            }
+            Section::NdlWarnings => {
+                writeln!(f, "NDL WARNINGS")?;
+                writeln!(f, "WARNING: {:>11}", self.editor.item + 1)?;
+                match self.settings.ndl_warnings.minutes[self.editor.item] {
+                    Some(minutes) => writeln!(f, "VALUE: {:>10}MIN", minutes)?,
+                    None => writeln!(f, "VALUE: {:>13}", "OFF")?,
+                }
+            }
+            Section::BackGas => {
+                let Gas { o2, he } = self.settings.back_gas;
+                let (name, value) = if self.editor.item == 0 { ("O2", o2) } else { ("HE", he) };
+                writeln!(f, "BACK GAS")?;
+                writeln!(f, "GAS: {:>15}", name)?;
+                writeln!(f, "VALUE: {:>12}%", value)?;
+            }
+            Section::DecoGases => {
+                writeln!(f, "DECO GASES")?;
+                writeln!(f, "GAS {} {:>14}", self.editor.item + 1, "O2")?;
+                match self.settings.deco_gases.o2[self.editor.item] {
+                    Some(o2) => writeln!(f, "VALUE: {:>12}%", o2)?,
+                    None => writeln!(f, "VALUE: {:>13}", "OFF")?,
+                }
+            }
+        }
+
+        writeln!(f)?;
+        for button in Button::ALL {
+            let action = self.settings.bindings.action(Page::Settings, button, Press::Tap);
+            if action != Action::None {
+                writeln!(f, "{}: {}", button.as_str(), action.as_str())?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+
+    use fugit::SecsDurationU32;
+
+    use super::*;
+    use crate::{
+        depth_alert::{Crossing, DepthAlert},
+        render::{ChunkRows, SpiFrequency},
+    };
+
+    /// Steps tried for each item of the settings page, more than any item has values
+    const MAX_STEPS: usize = 200;
+
+    /// Settings with the longest encoding `len` measures: every item of the settings page at its
+    /// longest value and the numbers set elsewhere at their extremes
+    pub(crate) fn largest(len: impl Fn(&Settings) -> usize) -> Settings {
+        let mut settings = Settings::new();
+        settings.screen_saver.dim_after = SecsDurationU32::secs(u32::MAX);
+        settings.screen_saver.blank_after = SecsDurationU32::secs(u32::MAX);
+        settings.screen_saver.shift_interval = SecsDurationU32::secs(u32::MAX);
+        settings.calibration.offset = i32::MIN;
+        settings.clock_drift.ppm = i32::MIN;
+        for table in [&mut settings.apnea.co2, &mut settings.apnea.o2] {
+            table.rounds = u8::MAX;
+            table.hold_s = u16::MAX;
+            table.rest_s = u16::MAX;
+            table.change_s = u16::MAX;
+        }
+
+        // The fields encode one after the other, the longest value of each makes the longest whole
+        let mut editor = SettingsEditor::new();
+        loop {
+            for _ in 0..editor.section.items() {
+                let mut longest = settings;
+                for _ in 0..MAX_STEPS {
+                    editor.perform(Action::ChangeItem, &mut settings);
+                    if len(&settings) > len(&longest) {
+                        longest = settings;
+                    }
+                }
+                settings = longest;
+                editor.perform(Action::SelectItem, &mut settings);
+            }
+            editor.perform(Action::SelectSection, &mut settings);
+            if editor.section == Section::Bindings(Page::Main) {
+                return settings;
+            }
+        }
+    }
+
+    #[test]
+    fn test_change_selected_binding() {
+        let mut settings = Settings::new();
+        let mut editor = SettingsEditor::new();
+
+        // Main page, B tap
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.bindings.action(Page::Main, Button::B, Press::Tap), Action::IncreaseRate);
+
+        for _ in 0..6 {
+            editor.perform(Action::SelectSection, &mut settings);
+        }
+        assert_eq!(editor.section, Section::GradientFactors);
+        assert_eq!(editor.item, 0);
+
+        // Low wraps around to 10
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.gradient_factors, GradientFactors { low: 10, high: 100 });
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.deco_variant, Zhl16Variant::A);
+        assert!(format!("{}", editor.page(&settings)).contains("DECO MODEL\nITEM:   COEFFICIENTS\nVALUE:       ZHL-16A\n"));
+        editor.perform(Action::SelectItem, &mut settings);
+        for _ in 0..5 {
+            editor.perform(Action::ChangeItem, &mut settings);
+        }
+        assert_eq!(settings.ascent_limit, AscentLimit::Conservative);
+        assert!(format!("{}", editor.page(&settings)).contains("ASCENT LIMIT\nPRESET: CONSERVATIVE\nVALUE:        9M/MIN\n"));
+
+        editor.perform(Action::SelectSection, &mut settings);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.reserve, ReserveConfig { warning: 50, critical: 40 });
+
+        editor.perform(Action::SelectSection, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.time_scale, TimeScale::X10);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.fill_rate, FillRate::L500);
+        assert!(format!("{}", editor.page(&settings)).contains("SIMULATION\nITEM:      FILL RATE\nVALUE:      500L/MIN\n"));
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.rate_limit, RateLimit::M20);
+
+        editor.perform(Action::SelectSection, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.refresh_rate, RefreshRate::Hz1);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.edt_format, EdtFormat::MinutesSeconds);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.strobe, StrobeMode::Beacon);
+        assert!(format!("{}", editor.page(&settings)).contains("DISPLAY\nITEM:         STROBE\nVALUE:        BEACON\n"));
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.depth_damping, DepthDamping::FourSeconds);
+        assert!(format!("{}", editor.page(&settings)).contains("DISPLAY\nITEM:        DAMPING\nVALUE:            4S\n"));
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.depth_display, DepthDisplay::Both);
+        assert!(format!("{}", editor.page(&settings)).contains("DISPLAY\nITEM:  DEPTH READOUT\nVALUE:     DEPTH+BAR\n"));
+
+        editor.perform(Action::SelectSection, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.render.spi_frequency, SpiFrequency::M62);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.render.chunk_rows, ChunkRows::R40);
+        assert!(format!("{}", editor.page(&settings)).contains("SCREEN\nITEM:           BAND\nVALUE:       40 ROWS\n"));
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert!(!settings.render.batch);
+
+        editor.perform(Action::SelectSection, &mut settings);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.water, Water::Fresh);
+        assert!(format!("{}", editor.page(&settings)).contains("DIVER\nITEM:          WATER\nVALUE:         FRESH\n"));
+        // The checklist after the tank, the setup wizard stops before it
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.checklist, ChecklistMode::On);
+        assert!(format!("{}", editor.page(&settings)).contains("DIVER\nITEM:      CHECKLIST\nVALUE:            ON\n"));
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.bus, BusAddress::Student(1));
+        assert!(format!("{}", editor.page(&settings)).contains("DIVER\nITEM:    BUS ADDRESS\nVALUE:             1\n"));
+
+        // Alert 2 at 6 m going up
+        editor.perform(Action::SelectSection, &mut settings);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(
+            settings.depth_alerts.alerts[1],
+            Some(DepthAlert {
+                depth_m: 6,
+                crossing: Crossing::Ascending
+            })
+        );
+        assert!(format!("{}", editor.page(&settings)).contains("DEPTH ALERTS\nALERT 2     CROSSING\nVALUE:            UP\n"));
+
+        // Second warning at 4 minutes, the last one off
+        editor.perform(Action::SelectSection, &mut settings);
+        editor.perform(Action::SelectItem, &mut settings);
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.ndl_warnings.minutes, [Some(5), Some(4), Some(1)]);
+        assert!(format!("{}", editor.page(&settings)).contains("NDL WARNINGS\nWARNING:           2\nVALUE:          4MIN\n"));
+        editor.perform(Action::SelectItem, &mut settings);
+        for _ in 0..10 {
+            editor.perform(Action::ChangeItem, &mut settings);
+        }
+        assert_eq!(settings.ndl_warnings.minutes[2], None);
+
+        // Trimix 10/70, oxygen wraps around after 40%
+        editor.perform(Action::SelectSection, &mut settings);
+        for _ in 0..20 {
+            editor.perform(Action::ChangeItem, &mut settings);
+        }
+        editor.perform(Action::SelectItem, &mut settings);
+        for _ in 0..14 {
+            editor.perform(Action::ChangeItem, &mut settings);
+        }
+        assert_eq!(settings.back_gas, Gas { o2: 10, he: 70 });
+        assert!(format!("{}", editor.page(&settings)).contains("BACK GAS\nGAS:              HE\nVALUE:           70%\n"));
+        editor.perform(Action::ChangeItem, &mut settings);
+        assert_eq!(settings.back_gas.he, 0);
+
+        // Oxygen as the second deco gas
+        editor.perform(Action::SelectSection, &mut settings);
+        editor.perform(Action::SelectItem, &mut settings);
+        for _ in 0..16 {
+            editor.perform(Action::ChangeItem, &mut settings);
+        }
+        assert_eq!(settings.deco_gases.o2, [None, Some(100)]);
+        assert!(format!("{}", editor.page(&settings)).contains("DECO GASES\nGAS 2             O2\nVALUE:          100%\n"));
+
+        editor.perform(Action::SelectSection, &mut settings);
+        assert_eq!(editor.section, Section::Bindings(Page::Main));
+    }
+}
@@ -0,0 +1,148 @@
+//! First boot setup
+//!
+//! When no settings are stored the device starts in setup: a wizard walks the diver section of
+//! the settings, one page per item for the unit, the water and the tank, before the dive pages
+//! show up. The wizard uses the settings page's editor and buttons, A for the next item and B to
+//! change it. Finishing the last item is the moment to store the settings.
+
+use core::fmt;
+
+use crate::{
+    keymap::Action,
+    settings::{Settings, SettingsEditor, DIVER_ITEMS},
+};
+
+/// Editor of the setup items
+#[derive(Debug, Clone, Copy)]
+pub struct SetupWizard {
+    editor: SettingsEditor,
+}
+
+impl SetupWizard {
+    pub const fn new() -> Self {
+        SetupWizard { editor: SettingsEditor::diver() }
+    }
+
+    /// Handle one of the editing actions, returns whether the wizard is done
+    pub fn perform(&mut self, action: Action, settings: &mut Settings) -> bool {
+        match action {
+            Action::SelectItem if self.editor.item() + 1 == DIVER_ITEMS => true,
+            Action::SelectItem | Action::ChangeItem => {
+                self.editor.perform(action, settings);
+                false
+            }
+            _ => false,
+        }
+    }
+
+    pub fn page<'a>(&'a self, settings: &'a Settings) -> SetupPage<'a> {
+        SetupPage { wizard: self, settings }
+    }
+}
+
+impl Default for SetupWizard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct SetupPage<'a> {
+    wizard: &'a SetupWizard,
+    settings: &'a Settings,
+}
+
+impl fmt::Display for SetupPage<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Setup {}/{}", self.wizard.editor.item() + 1, DIVER_ITEMS)?;
+        writeln!(f)?;
+        self.wizard.editor.write_diver_item(f, self.settings)?;
+        writeln!(f)?;
+        writeln!(f, "A: {}", if self.wizard.editor.item() + 1 == DIVER_ITEMS { "DONE" } else { "NEXT" })?;
+        write!(f, "B: {}", Action::ChangeItem.as_str())
+    }
+}
+
+/// What the device does after a reset
+#[derive(Debug, Clone, Copy)]
+pub enum BootState {
+    /// First boot, the wizard runs before anything else
+    Setup(SetupWizard),
+    Running,
+}
+
+impl BootState {
+    /// Setup when no settings were stored, otherwise straight to the dive pages
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{settings::Settings, setup::BootState};
+    /// assert!(BootState::new(None).in_setup());
+    /// assert!(!BootState::new(Some(&Settings::new())).in_setup());
+    /// ```
+    ///
+    pub fn new(stored: Option<&Settings>) -> Self {
+        match stored {
+            Some(_) => BootState::Running,
+            None => BootState::Setup(SetupWizard::new()),
+        }
+    }
+
+    pub fn in_setup(&self) -> bool {
+        matches!(self, BootState::Setup(_))
+    }
+
+    /// Pass `action` to the wizard, returns whether setup just finished and `settings` should be stored
+    pub fn perform(&mut self, action: Action, settings: &mut Settings) -> bool {
+        let BootState::Setup(wizard) = self else {
+            return false;
+        };
+
+        let done = wizard.perform(action, settings);
+        if done {
+            *self = BootState::Running;
+        }
+        done
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::{air_integration::TankSize, sensor::Water, Unit};
+
+    #[test]
+    fn test_first_boot_wizard() {
+        let mut settings = Settings::new();
+        let mut boot = BootState::new(None);
+        let page = |boot: &BootState, settings: &Settings| match boot {
+            BootState::Setup(wizard) => format!("{}", wizard.page(settings)),
+            BootState::Running => String::new(),
+        };
+        assert!(page(&boot, &settings).starts_with("Setup 1/3\n\nITEM:           UNIT\nVALUE:             M\n"));
+
+        // Imperial, fresh water and a 15 liter tank
+        let actions = [
+            Action::ChangeItem,
+            Action::SelectItem,
+            Action::ChangeItem,
+            Action::SelectItem,
+            Action::ChangeItem,
+            Action::ChangeItem,
+        ];
+        for action in actions {
+            assert!(!boot.perform(action, &mut settings));
+        }
+        // Other buttons don't leave the wizard
+        assert!(!boot.perform(Action::NextPage, &mut settings));
+        assert!(page(&boot, &settings).ends_with("VALUE:           15L\n\nA: DONE\nB: CHANGE"));
+
+        assert!(boot.perform(Action::SelectItem, &mut settings));
+        assert!(!boot.in_setup());
+        assert_eq!((settings.unit, settings.water, settings.tank), (Unit::Imperial, Water::Fresh, TankSize::L15));
+        assert!(!boot.perform(Action::ChangeItem, &mut settings));
+        assert_eq!(settings.unit, Unit::Imperial);
+    }
+}
@@ -0,0 +1,99 @@
+//! Shock detection
+//!
+//! A knock of the tank against a wall or a drop of the kit shows up on the accelerometer as a
+//! short peak far above the 1 g of gravity. `ShockDetector` turns every peak over
+//! `SHOCK_THRESHOLD_MG` into a single shock: the samples of the next `SHOCK_HOLDOFF` belong to the
+//! same knock, and the shock is reported with the highest of them once it is over.
+//!
+//! A hard knock can shift the zero of the pressure sensor. During a dive the dive computer logs
+//! every shock in the alarm history and raises a low alarm for `SHOCK_WARNING_TIME`, with a
+//! reminder to check the zero after the dive.
+
+use fugit::MicrosDurationU64;
+
+use crate::clock::Instant;
+
+/// Acceleration in milli-g over which a sample is a shock, swimming stays far below it
+pub const SHOCK_THRESHOLD_MG: u32 = 4_000;
+
+/// Time after the start of a shock that belongs to it
+pub const SHOCK_HOLDOFF: MicrosDurationU64 = MicrosDurationU64::millis(500);
+
+/// Time a shock keeps the low alarm and the reminder up
+pub const SHOCK_WARNING_TIME: MicrosDurationU64 = MicrosDurationU64::secs(30);
+
+/// Finds shocks in the acceleration samples
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ShockDetector {
+    /// Start of the shock going on and its peak in milli-g
+    shock: Option<(Instant, u32)>,
+}
+
+impl ShockDetector {
+    pub const fn new() -> Self {
+        ShockDetector { shock: None }
+    }
+
+    /// Take the `acceleration` along X, Y and Z in milli-g at `now`, returns the peak of a shock that is over
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{clock::Instant, shock::ShockDetector};
+    /// let mut detector = ShockDetector::new();
+    /// assert_eq!(detector.update(Instant::from_ticks(0), [0, 0, 1_000]), None);
+    /// assert_eq!(detector.update(Instant::from_ticks(10_000), [6_000, 0, 8_000]), None);
+    /// assert_eq!(detector.update(Instant::from_ticks(510_000), [0, 0, 1_000]), Some(10_000));
+    /// ```
+    ///
+    pub fn update(&mut self, now: Instant, acceleration: [i32; 3]) -> Option<u32> {
+        let magnitude = magnitude(acceleration);
+
+        let over = match self.shock {
+            Some((start, _)) if now.checked_duration_since(start).is_some_and(|since| since < SHOCK_HOLDOFF) => None,
+            shock => {
+                self.shock = None;
+                shock.map(|(_, peak)| peak)
+            }
+        };
+
+        match &mut self.shock {
+            Some((_, peak)) => *peak = (*peak).max(magnitude),
+            None if magnitude > SHOCK_THRESHOLD_MG => self.shock = Some((now, magnitude)),
+            None => {}
+        }
+        over
+    }
+}
+
+/// Length of the acceleration vector in milli-g
+fn magnitude(acceleration: [i32; 3]) -> u32 {
+    let squares: u64 = acceleration.iter().map(|&axis| i64::from(axis).pow(2) as u64).sum();
+    squares.isqrt() as u32
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_one_shock_per_knock() {
+        let mut detector = ShockDetector::new();
+        let at = |millis: u64| Instant::from_ticks(millis * 1_000);
+
+        // A knock that rings for a while is one shock with the highest peak
+        assert_eq!(detector.update(at(0), [0, 0, 1_000]), None);
+        assert_eq!(detector.update(at(10), [0, -4_500, 1_000]), None);
+        assert_eq!(detector.update(at(20), [-12_000, 5_000, 0]), None);
+        assert_eq!(detector.update(at(300), [0, 5_000, 1_000]), None);
+        assert_eq!(detector.update(at(510), [0, 0, 1_000]), Some(13_000));
+
+        // A knock right at the end of the last starts a new one
+        assert_eq!(detector.update(at(1_000), [-3_000, 3_000, 1_000]), None);
+        assert_eq!(detector.update(at(1_010), [-9_000, 0, 0]), None);
+        assert_eq!(detector.update(at(1_510), [0, 0, -16_000]), Some(9_000));
+        assert_eq!(detector.update(at(2_010), [0, 0, 1_000]), Some(16_000));
+        assert_eq!(detector.update(at(5_000), [0, 0, 1_000]), None);
+    }
+}
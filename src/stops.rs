@@ -0,0 +1,157 @@
+//! Decompression and safety stop pages
+//!
+//! The main page only has room for the next stop. The decompression page lists every stop the
+//! model asks for with the ceiling above them, the safety stop page shows the stop range and the
+//! time left in it. `auto_page` switches to them during a dive when they matter.
+
+use core::fmt;
+
+use fugit::SecsDurationU32;
+
+use crate::{
+    clock::Clock,
+    deco::{DecoModel, Stops},
+    safety_stop::{BOTTOM, TOP},
+    units::Depth,
+    DiveComputer, Unit,
+};
+
+/// Every stop to the surface
+pub struct DecoPage {
+    /// Whether there is a model, without one there is nothing to show
+    active: bool,
+    unit: Unit,
+    /// In millimeters
+    ceiling: u32,
+    stops: Stops,
+    ndl: SecsDurationU32,
+    /// Planning is locked after a missed stop
+    locked: bool,
+}
+
+impl DecoPage {
+    pub fn new<C: Clock, M: DecoModel>(dive_computer: &DiveComputer<C, M>) -> Self {
+        let deco = dive_computer.deco();
+        DecoPage {
+            active: M::ACTIVE,
+            unit: dive_computer.unit(),
+            ceiling: deco.ceiling(),
+            stops: deco.stops(),
+            ndl: deco.ndl(),
+            locked: dive_computer.lockout().active(),
+        }
+    }
+}
+
+impl fmt::Display for DecoPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Decompression")?;
+        writeln!(f)?;
+        if !self.active {
+            return write!(f, "NO DECO MODEL");
+        }
+
+        match self.ceiling {
+            0 => writeln!(f, "CEILING: {:>11}", "NONE")?,
+            ceiling => writeln!(f, "CEILING: {:>11}", Depth::new(ceiling, self.unit))?,
+        }
+        if self.locked {
+            return write!(f, "NDL: {:>15}", "LOCKED");
+        }
+        if self.stops.is_empty() {
+            return write!(f, "NDL: {:12}MIN", self.ndl.to_minutes());
+        }
+        for stop in self.stops.iter() {
+            writeln!(f, "STOP: {:>7}{:4}MIN", Depth::new(stop.depth, self.unit), stop.duration.to_minutes())?;
+        }
+        Ok(())
+    }
+}
+
+/// The safety stop on the way up
+pub struct SafetyStopPage {
+    unit: Unit,
+    /// In millimeters
+    depth: u32,
+    remaining: Option<SecsDurationU32>,
+    length: SecsDurationU32,
+    extended: bool,
+}
+
+impl SafetyStopPage {
+    pub fn new<C: Clock, M: DecoModel>(dive_computer: &DiveComputer<C, M>) -> Self {
+        let stop = dive_computer.safety_stop();
+        SafetyStopPage {
+            unit: dive_computer.unit(),
+            depth: dive_computer.depth(),
+            remaining: stop.remaining(),
+            length: stop.length(),
+            extended: stop.extended(),
+        }
+    }
+}
+
+impl fmt::Display for SafetyStopPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Safety stop")?;
+        writeln!(f)?;
+        match self.remaining {
+            Some(left) => writeln!(f, "LEFT: {:11}:{:02}", left.to_secs() / 60, left.to_secs() % 60)?,
+            None => writeln!(f, "LEFT: {:>14}", "NONE")?,
+        }
+        let length = self.length.to_secs();
+        writeln!(f, "LENGTH: {:9}:{:02}", length / 60, length % 60)?;
+        writeln!(f, "FROM: {:>14}", Depth::new(BOTTOM, self.unit))?;
+        writeln!(f, "TO: {:>16}", Depth::new(TOP, self.unit))?;
+        write!(f, "DEPTH: {:>13}", Depth::new(self.depth, self.unit))?;
+        if self.extended {
+            write!(f, "\nEXTENDED: NDL PUSHED")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use fugit::MicrosDurationU32;
+
+    use super::*;
+    use crate::{clock::ManualClock, keymap::Action};
+
+    #[test]
+    fn test_safety_stop_page() {
+        let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+        assert_eq!(
+            format!("{}", SafetyStopPage::new(&dive_computer)),
+            "Safety stop\n\nLEFT:           NONE\nLENGTH:         3:00\nFROM:           6.0M\nTO:             3.0M\nDEPTH:          0.0M"
+        );
+
+        // A minute at 12 m, then up to the stop
+        for _ in 0..12 {
+            dive_computer.perform(Action::IncreaseRate);
+        }
+        dive_computer.change_depth(MicrosDurationU32::minutes(1));
+        for _ in 0..19 {
+            dive_computer.perform(Action::DecreaseRate);
+        }
+        dive_computer.change_depth(MicrosDurationU32::minutes(1));
+        assert_eq!(dive_computer.depth(), 5_000);
+        let page = format!("{}", SafetyStopPage::new(&dive_computer));
+        assert!(page.starts_with("Safety stop\n\nLEFT:           2:5"), "{}", page);
+        assert!(page.ends_with("DEPTH:          5.0M"));
+        assert!(page.lines().all(|line| line.len() <= 20));
+    }
+
+    #[test]
+    fn test_deco_page_at_the_surface() {
+        let dive_computer = DiveComputer::with_clock(ManualClock::new());
+        let page = format!("{}", DecoPage::new(&dive_computer));
+        match DecoPage::new(&dive_computer).active {
+            true => assert!(page.starts_with("Decompression\n\nCEILING:        NONE\nNDL: "), "{}", page),
+            false => assert_eq!(page, "Decompression\n\nNO DECO MODEL"),
+        }
+    }
+}
@@ -0,0 +1,636 @@
+//! Non-volatile records in flash
+//!
+//! A flash sector survives about 100k erases, so a record that changes after every dive can't be
+//! rewritten in place. Instead every write goes to the next `SLOT_SIZE` slot of a ring of
+//! sectors, and a sector is only erased when the ring comes back around to it. Each slot starts
+//! with a sequence number, the payload length, a CRC-32 and a commit marker.
+//!
+//! A record is written in two phases: first the whole slot with the marker left erased, then
+//! the marker alone, which programming can clear without touching the rest. At mount the newest
+//! committed slot with a matching CRC wins, so when the power fails halfway through either
+//! phase the previous record stays in place. A slot that was written but not committed is
+//! skipped together with the rest of its sector, it can't be written again before an erase.
+//! A committed slot that fails its CRC was damaged afterwards, mount counts those as corrupt.
+//!
+//! The first page of every sector is its metadata page, it holds how often the sector was
+//! erased. The count is written right after the erase, so it never needs an erase of its own,
+//! and mounting the ring reads them into a `WearMap`, shown as a heatmap on the diagnostics page
+//! and printed by the console. A count cut off by a power failure starts over at zero.
+//!
+//! Works on any `NorFlash`. The ring needs at least two sectors, otherwise erasing it would lose
+//! the only copy before the new one is written.
+//!
+//! `wear_test` runs the same ring on scratch flash for a bounded number of writes, to measure
+//! write times and check the erases are spread over the sectors.
+
+use core::fmt;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use embedded_storage::nor_flash::NorFlash;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::clock::Clock;
+
+/// Bytes per record slot, the RP2040 programs its flash in 256 byte pages
+pub const SLOT_SIZE: usize = 256;
+
+/// Sequence number, payload length, CRC and commit marker in front of the payload
+const HEADER_SIZE: usize = 11;
+
+/// Offset of the commit marker in a slot
+const MARKER: usize = 10;
+
+/// Commit marker of a complete record, any other value is not committed
+const COMMITTED: u8 = 0x00;
+
+/// Value of erased flash
+const ERASED: u8 = 0xFF;
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Most sectors of a ring
+pub const MAX_SECTORS: usize = 16;
+
+/// Most writes of one wear test, about 2500 erases of a 4 KiB sector
+pub const MAX_WEAR_CYCLES: u32 = 10_000;
+
+/// Most sectors a wear test can spread its writes over
+pub const MAX_WEAR_SECTORS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageError<E> {
+    Flash(E),
+    /// The record doesn't fit in a slot
+    TooLarge,
+    /// The newest record isn't of the requested type
+    Corrupt,
+    /// The ring has less than two or more than `MAX_SECTORS` sectors
+    Sectors,
+}
+
+/// Ring of flash sectors holding the newest record
+pub struct Storage<F> {
+    flash: F,
+    /// Offset of the first sector
+    start: u32,
+    sectors: u32,
+    /// Slot the next record goes to
+    next_slot: u32,
+    /// Newest slot with a valid record, and its sequence number
+    newest: Option<(u32, u32)>,
+    /// Sequence number of the next write, a write that failed may have been committed
+    next_sequence: u32,
+    wear: WearMap,
+    /// Committed slots that failed their CRC at mount
+    corrupt: u32,
+}
+
+impl<F: NorFlash> Storage<F> {
+    /// Find the newest record in `sectors` sectors from `start`
+    pub fn mount(flash: F, start: u32, sectors: u32) -> Result<Self, StorageError<F::Error>> {
+        debug_assert!(SLOT_SIZE.is_multiple_of(F::WRITE_SIZE));
+        if !(2..=MAX_SECTORS as u32).contains(&sectors) {
+            return Err(StorageError::Sectors);
+        }
+
+        let mut storage = Storage {
+            flash,
+            start,
+            sectors,
+            next_slot: 0,
+            newest: None,
+            next_sequence: 0,
+            wear: WearMap {
+                erases: [0; MAX_SECTORS],
+                sectors: sectors as usize,
+            },
+            corrupt: 0,
+        };
+
+        let mut slot = [0; SLOT_SIZE];
+        for sector in 0..sectors {
+            storage.flash.read(storage.sector_address(sector), &mut slot).map_err(StorageError::Flash)?;
+            let count = u32::from_le_bytes([slot[0], slot[1], slot[2], slot[3]]);
+            let check = u32::from_le_bytes([slot[4], slot[5], slot[6], slot[7]]);
+            storage.wear.erases[sector as usize] = if check == !count { count } else { 0 };
+        }
+        for index in 0..storage.slot_count() {
+            storage.read_slot(index, &mut slot)?;
+            match valid_sequence(&slot) {
+                Some(sequence) => {
+                    if storage.newest.is_none_or(|(_, newest)| sequence > newest) {
+                        storage.newest = Some((index, sequence));
+                    }
+                }
+                None if slot[MARKER] == COMMITTED => storage.corrupt += 1,
+                None => {}
+            }
+        }
+
+        storage.next_slot = storage.newest.map_or(0, |(index, _)| (index + 1) % storage.slot_count());
+        storage.next_sequence = storage.newest.map_or(0, |(_, sequence)| sequence.wrapping_add(1));
+        // A write that was cut off or not committed leaves a slot that can't be written again
+        // before an erase
+        storage.read_slot(storage.next_slot, &mut slot)?;
+        if !storage.next_slot.is_multiple_of(storage.slots_per_sector()) && slot.iter().any(|&byte| byte != ERASED) {
+            storage.next_slot = (storage.next_slot / storage.slots_per_sector() + 1) % storage.sectors * storage.slots_per_sector();
+        }
+
+        Ok(storage)
+    }
+
+    /// Slots after the metadata page of a sector
+    fn slots_per_sector(&self) -> u32 {
+        (F::ERASE_SIZE / SLOT_SIZE) as u32 - 1
+    }
+
+    fn slot_count(&self) -> u32 {
+        self.sectors * self.slots_per_sector()
+    }
+
+    fn sector_address(&self, sector: u32) -> u32 {
+        self.start + sector * F::ERASE_SIZE as u32
+    }
+
+    fn address(&self, index: u32) -> u32 {
+        self.sector_address(index / self.slots_per_sector()) + (index % self.slots_per_sector() + 1) * SLOT_SIZE as u32
+    }
+
+    /// Count an erase of `sector` in its metadata page, which the erase left blank
+    fn count_erase(&mut self, sector: u32) -> Result<(), StorageError<F::Error>> {
+        let count = &mut self.wear.erases[sector as usize];
+        *count = count.saturating_add(1);
+        let mut page = [ERASED; SLOT_SIZE];
+        page[0..4].copy_from_slice(&count.to_le_bytes());
+        page[4..8].copy_from_slice(&(!*count).to_le_bytes());
+        self.flash.write(self.sector_address(sector), &page).map_err(StorageError::Flash)
+    }
+
+    /// Erase counts of the sectors
+    pub fn wear(&self) -> WearMap {
+        self.wear
+    }
+
+    /// Committed records that failed their CRC at mount, an older record is loaded instead
+    pub fn corrupt(&self) -> u32 {
+        self.corrupt
+    }
+
+    fn read_slot(&mut self, index: u32, slot: &mut [u8; SLOT_SIZE]) -> Result<(), StorageError<F::Error>> {
+        self.flash.read(self.address(index), slot).map_err(StorageError::Flash)
+    }
+
+    /// Newest record, `None` when nothing was stored yet
+    pub fn load<T: DeserializeOwned>(&mut self) -> Result<Option<T>, StorageError<F::Error>> {
+        let Some((index, _)) = self.newest else {
+            return Ok(None);
+        };
+
+        let mut slot = [0; SLOT_SIZE];
+        self.read_slot(index, &mut slot)?;
+        let len = u16::from_le_bytes([slot[4], slot[5]]) as usize;
+        postcard::from_bytes(&slot[HEADER_SIZE..HEADER_SIZE + len])
+            .map(Some)
+            .map_err(|_| StorageError::Corrupt)
+    }
+
+    /// Write `value` as the newest record, erasing the next sector when the ring reaches it
+    ///
+    /// After an error the previous record is kept, unless the power failed after the commit.
+    pub fn store<T: Serialize>(&mut self, value: &T) -> Result<(), StorageError<F::Error>> {
+        let mut slot = [ERASED; SLOT_SIZE];
+        let len = postcard::to_slice(value, &mut slot[HEADER_SIZE..]).map_err(|_| StorageError::TooLarge)?.len();
+        let sequence = self.next_sequence;
+        slot[0..4].copy_from_slice(&sequence.to_le_bytes());
+        slot[4..6].copy_from_slice(&(len as u16).to_le_bytes());
+        let crc = CRC.checksum(&slot[HEADER_SIZE..HEADER_SIZE + len]);
+        slot[6..10].copy_from_slice(&crc.to_le_bytes());
+
+        let index = self.next_slot;
+        if index.is_multiple_of(self.slots_per_sector()) {
+            let sector = index / self.slots_per_sector();
+            let address = self.sector_address(sector);
+            self.flash.erase(address, address + F::ERASE_SIZE as u32).map_err(StorageError::Flash)?;
+            self.count_erase(sector)?;
+        }
+
+        // Once written to, the slot and its sequence number are used up, even when a write fails
+        self.next_slot = (index + 1) % self.slot_count();
+        self.next_sequence = sequence.wrapping_add(1);
+        self.flash.write(self.address(index), &slot).map_err(StorageError::Flash)?;
+        let mut commit = [ERASED; SLOT_SIZE];
+        commit[MARKER] = COMMITTED;
+        self.flash.write(self.address(index), &commit).map_err(StorageError::Flash)?;
+
+        self.newest = Some((index, sequence));
+        Ok(())
+    }
+
+    /// Erase the whole ring, e.g. for a factory reset, the next record starts a new sequence
+    ///
+    /// The erase counts are kept.
+    pub fn erase(&mut self) -> Result<(), StorageError<F::Error>> {
+        erase_sectors(&mut self.flash, self.start, self.sectors).map_err(StorageError::Flash)?;
+        self.next_slot = 0;
+        self.newest = None;
+        self.next_sequence = 0;
+        for sector in 0..self.sectors {
+            self.count_erase(sector)?;
+        }
+        Ok(())
+    }
+
+    /// Read-modify-write of the newest record, starting from the default when there is none
+    ///
+    /// Nothing is written when `change` leaves the record as it was.
+    pub fn update<T>(&mut self, change: impl FnOnce(&mut T)) -> Result<T, StorageError<F::Error>>
+    where
+        T: Serialize + DeserializeOwned + Default + PartialEq + Clone,
+    {
+        let old: T = self.load()?.unwrap_or_default();
+        let mut new = old.clone();
+        change(&mut new);
+        if new != old {
+            self.store(&new)?;
+        }
+        Ok(new)
+    }
+}
+
+/// Erase `sectors` sectors from `start` in one go, mounting a ring there afterwards finds no records
+///
+/// The erase counts of the ring start over too, `Storage::erase` keeps them.
+pub fn erase_sectors<F: NorFlash>(flash: &mut F, start: u32, sectors: u32) -> Result<(), F::Error> {
+    flash.erase(start, start + sectors * F::ERASE_SIZE as u32)
+}
+
+/// Erase counts of the sectors of a ring
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WearMap {
+    erases: [u32; MAX_SECTORS],
+    sectors: usize,
+}
+
+impl WearMap {
+    /// Map without sectors, while there is no storage
+    pub const fn new() -> Self {
+        WearMap {
+            erases: [0; MAX_SECTORS],
+            sectors: 0,
+        }
+    }
+
+    /// Erases of every sector, in the order of the ring
+    pub fn erases(&self) -> &[u32] {
+        &self.erases[..self.sectors]
+    }
+
+    /// Fewest and most erases of a single sector
+    pub fn range(&self) -> (u32, u32) {
+        let erases = self.erases();
+        (erases.iter().copied().min().unwrap_or(0), erases.iter().copied().max().unwrap_or(0))
+    }
+
+    /// Heat of every sector, from `0` for no erases to `9` for the most erased ones
+    ///
+    /// Even wear shows as a row of the same digit, a sector the ring skips stays cooler.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::storage::WearMap;
+    /// let heat: String = WearMap::new().heatmap().collect();
+    /// assert_eq!(heat, "");
+    /// ```
+    ///
+    pub fn heatmap(&self) -> impl Iterator<Item = char> + '_ {
+        let (_, most) = self.range();
+        self.erases().iter().map(move |&erases| match most {
+            0 => '0',
+            // Rounded, so sectors a single erase behind the others look the same
+            most => char::from(b'0' + ((u64::from(erases) * 9 + u64::from(most) / 2) / u64::from(most)) as u8),
+        })
+    }
+}
+
+impl fmt::Display for WearMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.sectors == 0 {
+            return write!(f, "WEAR: NO STORAGE");
+        }
+        write!(f, "WEAR: ")?;
+        for heat in self.heatmap() {
+            write!(f, "{}", heat)?;
+        }
+        let (fewest, most) = self.range();
+        write!(f, " {}-{}", fewest, most)
+    }
+}
+
+/// Outcome of a wear test
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WearReport {
+    /// Records written
+    pub cycles: u32,
+    pub erases: u32,
+    /// Failed writes and records that didn't read back
+    pub errors: u32,
+    pub total_write_us: u64,
+    pub max_write_us: u64,
+    /// Fewest and most erases of a single sector
+    pub sector_erases: (u32, u32),
+    /// Erase counts of the scratch ring, including earlier tests
+    pub wear: WearMap,
+}
+
+impl fmt::Display for WearReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let average = self.total_write_us.checked_div(self.cycles.into()).unwrap_or(0);
+        writeln!(f, "CYCLES: {} ERASES: {}", self.cycles, self.erases)?;
+        writeln!(f, "ERRORS: {}", self.errors)?;
+        writeln!(f, "WRITE: AVG {}US MAX {}US", average, self.max_write_us)?;
+        writeln!(f, "SECTOR ERASES: {}-{}", self.sector_erases.0, self.sector_erases.1)?;
+        write!(f, "{}", self.wear)
+    }
+}
+
+/// Write `cycles` records, at most `MAX_WEAR_CYCLES`, to a ring of `sectors` sectors of `scratch`
+///
+/// Every record is read back. Only mounting the ring stops the test, other flash errors are
+/// counted. `scratch` must not hold anything else, the ring erases it as it goes.
+pub fn wear_test<F: NorFlash>(scratch: F, sectors: u32, cycles: u32, clock: &impl Clock) -> Result<WearReport, StorageError<F::Error>> {
+    debug_assert!(sectors as usize <= MAX_WEAR_SECTORS);
+
+    let mut storage = Storage::mount(scratch, 0, sectors)?;
+    let mut report = WearReport::default();
+    let before = storage.wear();
+
+    for cycle in 0..cycles.min(MAX_WEAR_CYCLES) {
+        let start = clock.now();
+        let stored = storage.store(&cycle);
+        let write_us = (clock.now() - start).to_micros();
+        report.total_write_us += write_us;
+        report.max_write_us = report.max_write_us.max(write_us);
+        report.cycles += 1;
+
+        if stored.is_err() || !matches!(storage.load::<u32>(), Ok(Some(value)) if value == cycle) {
+            report.errors += 1;
+        }
+    }
+
+    report.wear = storage.wear();
+    let mut erases = report.wear;
+    for (erases, before) in erases.erases.iter_mut().zip(before.erases) {
+        *erases = erases.saturating_sub(before);
+    }
+    report.erases = erases.erases().iter().sum();
+    report.sector_erases = erases.range();
+    Ok(report)
+}
+
+/// Sequence number of a slot holding a complete, committed record
+fn valid_sequence(slot: &[u8; SLOT_SIZE]) -> Option<u32> {
+    if slot[MARKER] != COMMITTED {
+        return None;
+    }
+    let sequence = u32::from_le_bytes([slot[0], slot[1], slot[2], slot[3]]);
+    let len = u16::from_le_bytes([slot[4], slot[5]]) as usize;
+    let crc = u32::from_le_bytes([slot[6], slot[7], slot[8], slot[9]]);
+    let payload = slot.get(HEADER_SIZE..HEADER_SIZE + len)?;
+    (CRC.checksum(payload) == crc).then_some(sequence)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+
+    use embedded_storage::nor_flash::{ErrorType, NorFlashErrorKind, ReadNorFlash};
+
+    use fugit::MicrosDurationU64;
+
+    use super::*;
+    use crate::clock::ManualClock;
+
+    const SECTOR: usize = 4096;
+
+    /// Two sectors of flash in RAM, counting erases
+    pub(crate) struct RamFlash<'a> {
+        data: [u8; 2 * SECTOR],
+        erases: [u32; 2],
+        /// Moved on by the time a write takes
+        clock: Option<&'a ManualClock>,
+        /// Bytes that can be programmed before the power fails, `None` while it doesn't
+        power: Option<usize>,
+    }
+
+    impl<'a> RamFlash<'a> {
+        /// Erased flash, writes take 400 us of `clock`
+        pub(crate) fn new(clock: Option<&'a ManualClock>) -> Self {
+            RamFlash {
+                data: [ERASED; 2 * SECTOR],
+                erases: [0; 2],
+                clock,
+                power: None,
+            }
+        }
+    }
+
+    impl ErrorType for RamFlash<'_> {
+        type Error = NorFlashErrorKind;
+    }
+
+    impl ReadNorFlash for RamFlash<'_> {
+        const READ_SIZE: usize = 1;
+
+        fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            bytes.copy_from_slice(&self.data[offset as usize..offset as usize + bytes.len()]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            self.data.len()
+        }
+    }
+
+    impl NorFlash for RamFlash<'_> {
+        const WRITE_SIZE: usize = 256;
+        const ERASE_SIZE: usize = SECTOR;
+
+        fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            if self.power == Some(0) {
+                return Err(NorFlashErrorKind::Other);
+            }
+            self.data[from as usize..to as usize].fill(ERASED);
+            for erases in &mut self.erases[from as usize / SECTOR..to as usize / SECTOR] {
+                *erases += 1;
+            }
+            Ok(())
+        }
+
+        fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            if let Some(clock) = self.clock {
+                clock.advance(MicrosDurationU64::micros(400));
+            }
+            // The power fails partway through a write
+            let len = match &mut self.power {
+                Some(left) => {
+                    let len = bytes.len().min(*left);
+                    *left -= len;
+                    len
+                }
+                None => bytes.len(),
+            };
+            // Programming only clears bits
+            for (old, new) in self.data[offset as usize..].iter_mut().zip(&bytes[..len]) {
+                *old &= new;
+            }
+            match len == bytes.len() {
+                true => Ok(()),
+                false => Err(NorFlashErrorKind::Other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_wear_levelled_records() {
+        let flash = RamFlash::new(None);
+        let mut storage = Storage::mount(flash, 0, 2).unwrap();
+        assert_eq!(storage.load::<u32>(), Ok(None));
+
+        // 40 updates go round the 30 slots, each sector is erased when the ring reaches it
+        for _ in 0..40 {
+            storage.update(|count: &mut u32| *count += 1).unwrap();
+        }
+        assert_eq!(storage.flash.erases, [2, 1]);
+        // An update without change doesn't write
+        storage.update(|_: &mut u32| {}).unwrap();
+        assert_eq!(storage.next_slot, 10);
+
+        // A write cut off by a reset
+        let mut storage = Storage::mount(storage.flash, 0, 2).unwrap();
+        assert_eq!(storage.load::<u32>(), Ok(Some(40)));
+        assert_eq!(storage.wear().erases(), [2, 1]);
+        let cut = storage.address(10) as usize;
+        storage.flash.data[cut..cut + 4].fill(0);
+
+        let mut storage = Storage::mount(storage.flash, 0, 2).unwrap();
+        assert_eq!(storage.load::<u32>(), Ok(Some(40)));
+        assert_eq!(storage.next_slot, 15);
+        storage.store(&41u32).unwrap();
+        assert_eq!(storage.flash.erases, [2, 2]);
+
+        let mut storage = Storage::mount(storage.flash, 0, 2).unwrap();
+        assert_eq!(storage.load::<u32>(), Ok(Some(41)));
+        assert_eq!(storage.store(&[[u64::MAX; 20]; 2]), Err(StorageError::TooLarge));
+
+        // A factory reset erases every sector at once, and keeps counting
+        storage.erase().unwrap();
+        assert_eq!(storage.flash.erases, [3, 3]);
+        assert_eq!(storage.load::<u32>(), Ok(None));
+        let mut storage = Storage::mount(storage.flash, 0, 2).unwrap();
+        assert_eq!(storage.load::<u32>(), Ok(None));
+        assert_eq!(storage.wear().erases(), [3, 3]);
+        storage.store(&1u32).unwrap();
+        assert_eq!(storage.flash.erases, [4, 3]);
+        assert_eq!(storage.wear().erases(), [4, 3]);
+        assert_eq!(format!("{}", storage.wear()), "WEAR: 97 3-4");
+
+        // Erasing the region of the ring starts the counts over
+        erase_sectors(&mut storage.flash, 0, 2).unwrap();
+        let storage = Storage::mount(storage.flash, 0, 2).unwrap();
+        assert_eq!(storage.wear().erases(), [0, 0]);
+        assert_eq!(format!("{}", storage.wear()), "WEAR: 00 0-0");
+        assert_eq!(format!("{}", WearMap::new()), "WEAR: NO STORAGE");
+
+        // One sector can't hold a ring
+        assert_eq!(Storage::mount(storage.flash, 0, 1).err(), Some(StorageError::Sectors));
+    }
+
+    #[test]
+    fn test_corrupt_record() {
+        let mut storage = Storage::mount(RamFlash::new(None), 0, 2).unwrap();
+        storage.store(&1u32).unwrap();
+        storage.store(&2u32).unwrap();
+        assert_eq!(storage.corrupt(), 0);
+
+        // A flipped bit in the newest record fails its CRC, the one before is loaded
+        let payload = storage.address(1) as usize + HEADER_SIZE;
+        storage.flash.data[payload] ^= 1;
+        let mut storage = Storage::mount(storage.flash, 0, 2).unwrap();
+        assert_eq!(storage.corrupt(), 1);
+        assert_eq!(storage.load::<u32>(), Ok(Some(1)));
+    }
+
+    /// Ring holding `records` records, and whether another one was stored when the power failed
+    /// after `cut` programmed bytes of it, including the erase count when it starts a sector
+    fn cut_off(records: u32, cut: usize) -> (Storage<RamFlash<'static>>, bool) {
+        let mut storage = Storage::mount(RamFlash::new(None), 0, 2).unwrap();
+        for record in 0..records {
+            storage.store(&record).unwrap();
+        }
+        storage.flash.power = Some(cut);
+        let stored = storage.store(&records).is_ok();
+        storage.flash.power = None;
+        (storage, stored)
+    }
+
+    #[test]
+    fn test_power_cut_while_storing() {
+        // In the middle of a sector, and at the start of the next one, which is erased and
+        // counted first
+        for (records, count) in [(14, 0), (15, SLOT_SIZE)] {
+            for cut in 0..=count + 2 * SLOT_SIZE {
+                // The power comes back without a reset
+                let (mut storage, stored) = cut_off(records, cut);
+                assert_eq!(stored, cut == count + 2 * SLOT_SIZE);
+                storage.store(&100u32).unwrap();
+                assert_eq!(storage.load::<u32>(), Ok(Some(100)));
+                let mut storage = Storage::mount(storage.flash, 0, 2).unwrap();
+                assert_eq!(storage.load::<u32>(), Ok(Some(100)));
+
+                // After a reset the record is there once its marker is
+                let (storage, _) = cut_off(records, cut);
+                let mut storage = Storage::mount(storage.flash, 0, 2).unwrap();
+                let expected = if cut > count + SLOT_SIZE + MARKER { records } else { records - 1 };
+                assert_eq!(storage.load::<u32>(), Ok(Some(expected)), "{} {}", records, cut);
+                storage.store(&100u32).unwrap();
+                let mut storage = Storage::mount(storage.flash, 0, 2).unwrap();
+                assert_eq!(storage.load::<u32>(), Ok(Some(100)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_wear_test() {
+        let clock = ManualClock::new();
+        let mut flash = RamFlash::new(Some(&clock));
+
+        // 100 writes round 15 slot sectors erase them 4 and 3 times, writing the count takes
+        // another page after each erase
+        let report = wear_test(&mut flash, 2, 100, &clock).unwrap();
+        assert_eq!(flash.erases, [4, 3]);
+        assert_eq!(report.wear.erases(), [4, 3]);
+        assert_eq!(
+            report,
+            WearReport {
+                cycles: 100,
+                erases: 7,
+                errors: 0,
+                total_write_us: 82_800,
+                max_write_us: 1_200,
+                sector_erases: (3, 4),
+                wear: report.wear,
+            }
+        );
+        assert_eq!(
+            format!("{}", report),
+            "CYCLES: 100 ERASES: 7\nERRORS: 0\nWRITE: AVG 828US MAX 1200US\nSECTOR ERASES: 3-4\nWEAR: 97 3-4"
+        );
+
+        // The run continues the ring of the last one, and is bounded
+        let report = wear_test(&mut flash, 2, u32::MAX, &clock).unwrap();
+        assert_eq!(report.cycles, MAX_WEAR_CYCLES);
+        assert_eq!(report.sector_erases, (333, 334));
+        assert_eq!(report.wear.erases(), [337, 337]);
+        assert_eq!(report.wear.heatmap().collect::<String>(), "99");
+    }
+}
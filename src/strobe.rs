@@ -0,0 +1,83 @@
+//! External strobe
+//!
+//! An LED strobe on a breakout pin, switched through a MOSFET, flashes during high alarms so a
+//! buddy can see something is wrong. At the surface it can also flash slowly as a locator beacon,
+//! switched on in the settings. Flashes are scheduled like the beeps of the buzzer.
+
+use fugit::MicrosDurationU64;
+use serde::{Deserialize, Serialize};
+
+use crate::{buzzer::pulsing, clock::Instant, Alarm};
+
+/// Length of a single flash
+pub const FLASH_LENGTH: MicrosDurationU64 = MicrosDurationU64::millis(50);
+
+/// Flash interval during a high alarm
+const ALARM_INTERVAL: MicrosDurationU64 = MicrosDurationU64::millis(500);
+
+/// Flash interval of the locator beacon
+const BEACON_INTERVAL: MicrosDurationU64 = MicrosDurationU64::secs(2);
+
+/// Whether the strobe also works as a locator beacon at the surface
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum StrobeMode {
+    /// Only during high alarms
+    #[default]
+    Alarms,
+    Beacon,
+}
+
+impl StrobeMode {
+    pub fn next(self) -> Self {
+        match self {
+            StrobeMode::Alarms => StrobeMode::Beacon,
+            StrobeMode::Beacon => StrobeMode::Alarms,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StrobeMode::Alarms => "ALARMS",
+            StrobeMode::Beacon => "BEACON",
+        }
+    }
+}
+
+/// Whether the strobe is lit at `now`
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::{clock::Instant, strobe::{flashing, StrobeMode}, Alarm};
+///
+/// let now = Instant::from_ticks(1_020_000);
+/// assert!(flashing(now, Alarm::High, StrobeMode::Alarms, false));
+/// assert!(!flashing(now, Alarm::Medium, StrobeMode::Alarms, false));
+/// ```
+///
+pub fn flashing(now: Instant, alarm: Alarm, mode: StrobeMode, at_surface: bool) -> bool {
+    match (alarm, mode) {
+        (Alarm::High, _) => pulsing(now, ALARM_INTERVAL, FLASH_LENGTH),
+        (_, StrobeMode::Beacon) if at_surface => pulsing(now, BEACON_INTERVAL, FLASH_LENGTH),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_strobe_patterns() {
+        let flashes = |alarm, mode, at_surface| (0..40).filter(|tick| flashing(Instant::from_ticks(tick * 100_000), alarm, mode, at_surface)).count();
+
+        // Twice a second during a high alarm, also with the beacon on
+        assert_eq!(flashes(Alarm::High, StrobeMode::Alarms, false), 8);
+        assert_eq!(flashes(Alarm::High, StrobeMode::Beacon, true), 8);
+        // The beacon only flashes at the surface, once every 2 seconds
+        assert_eq!(flashes(Alarm::None, StrobeMode::Beacon, true), 2);
+        assert_eq!(flashes(Alarm::None, StrobeMode::Beacon, false), 0);
+        assert_eq!(flashes(Alarm::AirCritical, StrobeMode::Alarms, true), 0);
+    }
+}
@@ -0,0 +1,145 @@
+//! Surface page
+//!
+//! Between dives the main page shows a watch face instead of the dive data: the time of day, the
+//! time since the last dive, that dive, its kind and coldest water, and the battery. The dive data comes back by itself as
+//! soon as the next dive starts. With the `logbook` feature a graph of the last dive is drawn
+//! below, its profile with the actions of the diver. While the compressor is connected the page
+//! also shows the tank pressure, with a progress bar in the place of the graph.
+
+use core::fmt;
+
+use fugit::SecsDurationU32;
+
+use crate::{
+    clock::Clock,
+    deco::DecoModel,
+    odometer::DiveSummary,
+    units::{Depth, Temperature},
+    DiveComputer, Unit,
+};
+
+/// Wall clock time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeOfDay {
+    pub hours: u8,
+    pub minutes: u8,
+}
+
+pub struct SurfacePage {
+    time: Option<TimeOfDay>,
+    surface_interval: Option<SecsDurationU32>,
+    last_dive: Option<DiveSummary>,
+    /// Charge left in percent
+    battery: Option<u8>,
+    unit: Unit,
+    /// Tank pressure in bar while filling
+    filling: Option<u32>,
+}
+
+impl SurfacePage {
+    /// Watch face for `dive_computer`, `None` for what couldn't be read
+    pub fn new<C: Clock, M: DecoModel>(dive_computer: &DiveComputer<C, M>, time: Option<TimeOfDay>, battery: Option<u8>) -> Self {
+        SurfacePage {
+            time,
+            surface_interval: dive_computer.surface_interval(),
+            last_dive: dive_computer.last_dive(),
+            battery,
+            unit: dive_computer.unit(),
+            filling: dive_computer.filling().map(|_| dive_computer.tank_pressure() / 100),
+        }
+    }
+}
+
+impl fmt::Display for SurfacePage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "DiveMaster")?;
+        writeln!(f)?;
+
+        match self.time {
+            Some(time) => writeln!(f, "TIME: {:>11}:{:02}", time.hours, time.minutes)?,
+            None => writeln!(f, "TIME: {:>14}", "--:--")?,
+        }
+
+        match self.surface_interval {
+            Some(interval) => {
+                let minutes = interval.to_minutes();
+                writeln!(f, "SURFACE: {:>8}:{:02}", minutes / 60, minutes % 60)?
+            }
+            None => writeln!(f, "SURFACE: {:>11}", "--:--")?,
+        }
+
+        match self.last_dive {
+            Some(dive) => {
+                writeln!(f, "LAST: {:>5}MIN{:>5}", dive.duration.to_minutes(), Depth::new(dive.max_depth, self.unit))?;
+                writeln!(f, "TYPE: {:>14}", dive.kind.as_str())?;
+                if let Some(tenths) = dive.min_temperature {
+                    writeln!(f, "MIN TEMP: {:>10}", Temperature { tenths, unit: self.unit })?
+                }
+            }
+            None => writeln!(f, "LAST: {:>14}", "NONE")?,
+        }
+
+        match self.battery {
+            Some(percent) => write!(f, "BATTERY: {:>10}%", percent)?,
+            None => write!(f, "BATTERY: {:>11}", "--")?,
+        }
+
+        match self.filling {
+            Some(pressure) => write!(f, "\nFILLING: {:>8}BAR", pressure),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use fugit::MicrosDurationU32;
+
+    use super::*;
+    use crate::{clock::ManualClock, keymap::Action};
+
+    #[test]
+    fn test_watch_face() {
+        let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+        let page = format!("{}", SurfacePage::new(&dive_computer, None, None));
+        assert_eq!(
+            page,
+            "DiveMaster\n\nTIME:          --:--\nSURFACE:       --:--\nLAST:           NONE\nBATTERY:          --"
+        );
+
+        // 25 minutes at 12 m and an hour and a quarter at the surface
+        let perform = |dive_computer: &mut DiveComputer<ManualClock>, action, times| {
+            for _ in 0..times {
+                dive_computer.perform(action);
+            }
+        };
+        perform(&mut dive_computer, Action::IncreaseRate, 10);
+        dive_computer.change_depth(MicrosDurationU32::secs(72));
+        assert!(dive_computer.diving());
+        perform(&mut dive_computer, Action::DecreaseRate, 10);
+        dive_computer.set_temperature(Some(184));
+        dive_computer.change_depth(MicrosDurationU32::minutes(25));
+        dive_computer.set_temperature(Some(212));
+        perform(&mut dive_computer, Action::DecreaseRate, 10);
+        dive_computer.change_depth(MicrosDurationU32::secs(72));
+        // A u32 in microseconds only covers 71 minutes
+        for _ in 0..3 {
+            dive_computer.change_depth(MicrosDurationU32::minutes(25));
+        }
+        assert!(!dive_computer.diving());
+
+        let time = TimeOfDay { hours: 14, minutes: 5 };
+        let page = format!("{}", SurfacePage::new(&dive_computer, Some(time), Some(83)));
+        assert_eq!(
+            page,
+            "DiveMaster\n\nTIME:          14:05\nSURFACE:        1:15\nLAST:    27MIN  12M\nTYPE:        NO DECO\nMIN TEMP:      18.4C\nBATTERY:         83%"
+        );
+
+        dive_computer.perform(Action::FillAir);
+        dive_computer.change_depth(MicrosDurationU32::secs(1));
+        let page = format!("{}", SurfacePage::new(&dive_computer, Some(time), Some(83)));
+        let expected = format!("BATTERY:         83%\nFILLING: {:>8}BAR", dive_computer.tank_pressure() / 100);
+        assert!(page.ends_with(&expected));
+    }
+}
@@ -0,0 +1,77 @@
+//! Technical page
+//!
+//! Shows the pressures behind the depth, for calibration labs: the ambient pressure the
+//! decompression model and the gas consumption work with, split into the surface pressure and
+//! the water above, and the raw reading of the pressure sensor when one is attached. With the
+//! simulator the ambient pressure follows the simulated depth.
+
+use core::fmt;
+
+use crate::{clock::Clock, deco::DecoModel, gas::SURFACE_PRESSURE_CB, DepthSource, DiveComputer};
+
+pub struct TechPage {
+    /// In millibar
+    ambient: u32,
+    depth_source: DepthSource,
+    /// Last raw reading of the pressure sensor in millibar
+    sensor: Option<u32>,
+}
+
+impl TechPage {
+    /// Page for `dive_computer`, `sensor` is `None` without a pressure sensor
+    pub fn new<C: Clock, M: DecoModel>(dive_computer: &DiveComputer<C, M>, sensor: Option<u32>) -> Self {
+        TechPage {
+            ambient: dive_computer.ambient_pressure(),
+            depth_source: dive_computer.depth_source(),
+            sensor,
+        }
+    }
+}
+
+impl fmt::Display for TechPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Write to buffer
+        writeln!(f, "Technical")?;
+        writeln!(f)?;
+
+        let surface = SURFACE_PRESSURE_CB * 10;
+        writeln!(f, "AMBIENT: {:>7}MBAR", self.ambient)?;
+        writeln!(f, "SURFACE: {:>7}MBAR", surface)?;
+        writeln!(f, "WATER: {:>9}MBAR", self.ambient - surface)?;
+        match self.sensor {
+            Some(pressure) => writeln!(f, "SENSOR: {:>8}MBAR", pressure)?,
+            None => writeln!(f, "SENSOR: {:>12}", "NONE")?,
+        }
+        let source = match self.depth_source {
+            DepthSource::Simulator => "SIMULATOR",
+            DepthSource::Sensor(_) => "SENSOR",
+            DepthSource::Fault(_) => "FAULT",
+        };
+        write!(f, "DEPTH: {:>13}", source)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use fugit::MicrosDurationU32;
+
+    use crate::clock::ManualClock;
+
+    #[test]
+    fn test_pressures() {
+        let mut dive_computer = DiveComputer::with_clock(ManualClock::new());
+        assert_eq!(
+            format!("{}", TechPage::new(&dive_computer, None)),
+            "Technical\n\nAMBIENT:    1000MBAR\nSURFACE:    1000MBAR\nWATER:         0MBAR\nSENSOR:         NONE\nDEPTH:     SIMULATOR"
+        );
+
+        dive_computer.update_sensor(Ok(18_250));
+        dive_computer.change_depth(MicrosDurationU32::secs(10));
+        assert_eq!(dive_computer.depth(), 18_250);
+        assert_eq!(dive_computer.ambient_pressure(), 2_825);
+        let text = format!("{}", TechPage::new(&dive_computer, Some(2_839)));
+        assert!(text.ends_with("WATER:      1825MBAR\nSENSOR:     2839MBAR\nDEPTH:        SENSOR"));
+    }
+}
@@ -0,0 +1,164 @@
+//! Telemetry frames
+//!
+//! The state of a dive computer in a few bytes, to send to another board over a serial link.
+//! A frame is serialized with postcard and followed by a CRC-32 like the console exports, then
+//! COBS encoded so a zero byte ends every frame: a receiver that starts listening halfway
+//! through a frame drops that one and picks up the next.
+//!
+//! The scenario commands of `instructor` are sent in the same frames, `encode_frame` and
+//! `FrameReader` take any type that serializes.
+
+use core::marker::PhantomData;
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::Alarm;
+
+/// Largest encoded frame in bytes, including the zero at the end
+pub const MAX_FRAME_LEN: usize = 24;
+
+/// Largest serialized frame in bytes, including the CRC
+const MAX_PAYLOAD_LEN: usize = 16;
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Telemetry {
+    /// Depth in millimeters
+    pub depth: u32,
+    /// Tank pressure in bar
+    pub pressure: u16,
+    pub alarm: Alarm,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// More bytes than `MAX_FRAME_LEN` before the zero
+    TooLong,
+    /// Bad encoding or CRC
+    Corrupt,
+}
+
+impl Telemetry {
+    /// Encode as a frame into `buf`, returns the bytes to send
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{telemetry::{FrameReader, Telemetry, MAX_FRAME_LEN}, Alarm};
+    /// let telemetry = Telemetry { depth: 18_000, pressure: 150, alarm: Alarm::None };
+    /// let mut buf = [0; MAX_FRAME_LEN];
+    /// let frame = telemetry.encode(&mut buf).unwrap();
+    /// assert_eq!(frame.last(), Some(&0));
+    ///
+    /// let mut reader = FrameReader::new();
+    /// let received: Vec<_> = frame.iter().filter_map(|&byte| reader.push(byte)).collect();
+    /// assert_eq!(received, [Ok(telemetry)]);
+    /// ```
+    ///
+    pub fn encode<'a>(&self, buf: &'a mut [u8; MAX_FRAME_LEN]) -> Result<&'a [u8], FrameError> {
+        encode_frame(self, buf)
+    }
+}
+
+/// Encode `value` as a frame into `buf`, returns the bytes to send
+pub fn encode_frame<'a, T: Serialize>(value: &T, buf: &'a mut [u8; MAX_FRAME_LEN]) -> Result<&'a [u8], FrameError> {
+    let mut payload = [0; MAX_PAYLOAD_LEN];
+    // Only fails when `value` outgrows `MAX_PAYLOAD_LEN`, which the tests catch
+    let payload = postcard::to_slice_crc32(value, &mut payload, CRC.digest()).map_err(|_| FrameError::TooLong)?;
+    let len = cobs::try_encode(payload, &mut buf[..MAX_FRAME_LEN - 1]).map_err(|_| FrameError::TooLong)?;
+    buf[len] = 0;
+    Ok(&buf[..=len])
+}
+
+/// Collects received bytes into frames of `T`
+#[derive(Debug, Clone, Copy)]
+pub struct FrameReader<T = Telemetry> {
+    buf: [u8; MAX_FRAME_LEN],
+    len: usize,
+    /// The frame didn't fit, it is dropped at the next zero
+    overflow: bool,
+    frame: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> FrameReader<T> {
+    pub const fn new() -> Self {
+        FrameReader {
+            buf: [0; MAX_FRAME_LEN],
+            len: 0,
+            overflow: false,
+            frame: PhantomData,
+        }
+    }
+
+    /// Add a received byte, returns the frame it ends
+    pub fn push(&mut self, byte: u8) -> Option<Result<T, FrameError>> {
+        if byte != 0 {
+            match self.buf.get_mut(self.len) {
+                Some(slot) => {
+                    *slot = byte;
+                    self.len += 1;
+                }
+                None => self.overflow = true,
+            }
+            return None;
+        }
+
+        let (len, overflow) = (self.len, self.overflow);
+        self.len = 0;
+        self.overflow = false;
+        match (len, overflow) {
+            (_, true) => Some(Err(FrameError::TooLong)),
+            // Nothing between two zeros, e.g. line noise at startup
+            (0, false) => None,
+            (len, false) => Some(self.decode(len)),
+        }
+    }
+
+    fn decode(&mut self, len: usize) -> Result<T, FrameError> {
+        let len = cobs::decode_in_place(&mut self.buf[..len]).map_err(|_| FrameError::Corrupt)?;
+        postcard::from_bytes_crc32(&self.buf[..len], CRC.digest()).map_err(|_| FrameError::Corrupt)
+    }
+}
+
+impl<T: DeserializeOwned> Default for FrameReader<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_frames_survive_noise() {
+        let telemetry = Telemetry {
+            depth: u32::MAX,
+            pressure: u16::MAX,
+            alarm: Alarm::High,
+        };
+        let mut buf = [0; MAX_FRAME_LEN];
+        let frame = telemetry.encode(&mut buf).unwrap();
+        // No zero but the last
+        assert_eq!(frame.iter().position(|&byte| byte == 0), Some(frame.len() - 1));
+
+        let mut reader = FrameReader::new();
+        // Second half of a frame, then a whole one
+        let mut stream = frame[frame.len() / 2..].to_vec();
+        stream.extend_from_slice(frame);
+        let received: Vec<_> = stream.iter().filter_map(|&byte| reader.push(byte)).collect();
+        assert_eq!(received, [Err(FrameError::Corrupt), Ok(telemetry)]);
+
+        // A flipped bit fails the CRC
+        let mut damaged = frame.to_vec();
+        damaged[2] ^= 0x10;
+        let received: Vec<_> = damaged.iter().filter_map(|&byte| reader.push(byte)).collect();
+        assert_eq!(received, [Err(FrameError::Corrupt)]);
+
+        let received: Vec<_> = [1; MAX_FRAME_LEN + 1].iter().chain(&[0]).filter_map(|&byte| reader.push(byte)).collect();
+        assert_eq!(received, [Err(FrameError::TooLong)]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Water temperature
+//!
+//! Temperatures are in tenths of a degree Celsius. There is no digital temperature sensor
+//! driver yet, so with the `thermistor` feature the water temperature comes from a cheap NTC
+//! thermistor on ADC input 2 (GPIO28) instead: the thermistor from the pin to ground, and
+//! `SERIES_RESISTANCE` from 3.3 V to the pin. A digital sensor would feed
+//! `DiveComputer::set_temperature` the same way. The button of the `joystick` uses the same
+//! input, it reads outside the table, so either can be attached to a firmware with both.
+//!
+//! The conversion avoids floats with a table of ADC readings every 5 °C, computed from the
+//! Steinhart–Hart equation for a 10 kΩ B3950 thermistor: A = 1.0223e-3, B = 2.5316e-4. A
+//! thermistor only specified by its B value has no third coefficient, C = 0. Between the table
+//! entries the temperature is interpolated, which is within 0.1 °C from 0 to 40 °C. Pages show
+//! temperatures with `units::Temperature`.
+
+/// Resistor between 3.3 V and the thermistor pin in ohms, the same as the thermistor at 25 °C
+pub const SERIES_RESISTANCE: u32 = 10_000;
+
+/// ADC reading and the temperature in tenths of a degree Celsius, from cold to warm
+const THERMISTOR_TABLE: [(u16, i16); 13] = [
+    (3496, -100),
+    (3338, -50),
+    (3157, 0),
+    (2956, 50),
+    (2739, 100),
+    (2511, 150),
+    (2278, 200),
+    (2048, 250),
+    (1825, 300),
+    (1614, 350),
+    (1419, 400),
+    (1241, 450),
+    (1082, 500),
+];
+
+/// Temperature in tenths of a degree Celsius from a raw reading of the thermistor pin
+///
+/// `None` outside the table, e.g. when no thermistor is connected and the pin reads as a short
+/// or an open circuit.
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::temperature::thermistor_tenths;
+/// assert_eq!(thermistor_tenths(2048), Some(250));
+/// assert_eq!(thermistor_tenths(2625), Some(125));
+/// assert_eq!(thermistor_tenths(4095), None);
+/// ```
+///
+pub fn thermistor_tenths(raw: u16) -> Option<i16> {
+    let mut colder = THERMISTOR_TABLE[0];
+    if raw == colder.0 {
+        return Some(colder.1);
+    }
+
+    for warmer in THERMISTOR_TABLE.iter().copied().skip(1) {
+        if (warmer.0..=colder.0).contains(&raw) {
+            // Readings go down as the temperature goes up
+            let tenths = i32::from(warmer.1) - i32::from(raw - warmer.0) * i32::from(warmer.1 - colder.1) / i32::from(colder.0 - warmer.0);
+            return Some(tenths as i16);
+        }
+        colder = warmer;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_thermistor_table() {
+        // Readings go down as the temperature goes up
+        assert!(THERMISTOR_TABLE.windows(2).all(|pair| pair[0].0 > pair[1].0 && pair[0].1 < pair[1].1));
+
+        assert_eq!(thermistor_tenths(3496), Some(-100));
+        assert_eq!(thermistor_tenths(1082), Some(500));
+        assert_eq!(thermistor_tenths(3497), None);
+        assert_eq!(thermistor_tenths(0), None);
+        assert_eq!(thermistor_tenths(3175), Some(-4));
+    }
+}
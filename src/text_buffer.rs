@@ -0,0 +1,213 @@
+//! Fixed-capacity text
+//!
+//! Every piece of text the firmware builds goes into a `TextBuffer`: the screen contents, the
+//! console replies and the short values of the widgets and the MQTT-SN topics. Text that doesn't
+//! fit must not panic the firmware, so the buffer keeps what fits up to a character boundary
+//! and ends with `OVERFLOW_MARKER` instead, which makes the missing text visible on screen.
+//! `write!` and `writeln!` never fail on a `TextBuffer`, the buffer counts the writes it cut off.
+
+use core::{fmt, str::Lines};
+
+/// Last character of a buffer that was cut off
+pub const OVERFLOW_MARKER: char = '~';
+
+/// Text of at most `N` bytes
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::text_buffer::TextBuffer;
+/// let mut buf = TextBuffer::<8>::new();
+/// buf.clear_and_write(format_args!("{:>4}\n{}", 12, "M"));
+/// assert_eq!(buf.lines().collect::<Vec<_>>(), ["  12", "M"]);
+///
+/// writeln!(buf, "{:10}", "DEPTH");
+/// assert!(buf.truncated());
+/// assert_eq!(buf.as_str(), "  12\nMD~");
+/// assert_eq!(buf.truncations(), 1);
+/// ```
+///
+#[derive(Debug, Clone, Copy)]
+pub struct TextBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+    /// Text was cut off since the last clear
+    truncated: bool,
+    /// Writes cut off since the buffer was made
+    truncations: u32,
+    /// Longest text held
+    peak: usize,
+}
+
+impl<const N: usize> TextBuffer<N> {
+    pub const fn new() -> Self {
+        TextBuffer {
+            bytes: [0; N],
+            len: 0,
+            truncated: false,
+            truncations: 0,
+            peak: 0,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only whole characters are ever copied in
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Lines of the text, e.g. to draw them one at a time
+    pub fn lines(&self) -> Lines<'_> {
+        self.as_str().lines()
+    }
+
+    /// Remove the text, the statistics are kept
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.truncated = false;
+    }
+
+    /// Replace the text with `args`
+    pub fn clear_and_write(&mut self, args: fmt::Arguments<'_>) {
+        self.clear();
+        self.write_fmt(args);
+    }
+
+    /// Whether text was cut off since the last clear
+    pub fn truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// Number of writes cut off since the buffer was made
+    pub fn truncations(&self) -> u32 {
+        self.truncations
+    }
+
+    /// Longest text held since the buffer was made, in bytes
+    pub fn peak(&self) -> usize {
+        self.peak
+    }
+
+    /// Append `s`, or as much as fits followed by `OVERFLOW_MARKER`
+    ///
+    /// Fails when `s` didn't fit or text was already cut off, so a writer can stop early.
+    pub fn push_str(&mut self, s: &str) -> fmt::Result {
+        if self.truncated {
+            return Err(fmt::Error);
+        }
+        if let Some(free) = self.bytes.get_mut(self.len..self.len + s.len()) {
+            free.copy_from_slice(s.as_bytes());
+            self.len += s.len();
+            self.peak = self.peak.max(self.len);
+            return Ok(());
+        }
+
+        // Keep what fits, up to a character boundary
+        let mut end = (N - self.len).min(s.len());
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.bytes[self.len..self.len + end].copy_from_slice(&s.as_bytes()[..end]);
+        self.len += end;
+        self.truncate();
+        Err(fmt::Error)
+    }
+
+    /// Append `c`, see `push_str`
+    pub fn push(&mut self, c: char) -> fmt::Result {
+        self.push_str(c.encode_utf8(&mut [0; 4]))
+    }
+
+    /// End the text with `OVERFLOW_MARKER`, replacing the last characters when it is full
+    fn truncate(&mut self) {
+        let marker_len = OVERFLOW_MARKER.len_utf8();
+        while self.len + marker_len > N && self.len > 0 {
+            self.len -= self.as_str().chars().next_back().map_or(1, char::len_utf8);
+        }
+        if self.len + marker_len <= N {
+            OVERFLOW_MARKER.encode_utf8(&mut self.bytes[self.len..self.len + marker_len]);
+            self.len += marker_len;
+        }
+        self.peak = self.peak.max(self.len);
+        self.truncated = true;
+        self.truncations = self.truncations.saturating_add(1);
+    }
+
+    /// Used by `write!` and `writeln!` instead of `fmt::Write::write_fmt`, there is no error to handle
+    pub fn write_fmt(&mut self, args: fmt::Arguments<'_>) {
+        // Only a `Display` implementation failing on its own leaves the text untruncated
+        if fmt::Write::write_fmt(self, args).is_err() && !self.truncated {
+            self.truncate();
+        }
+    }
+}
+
+impl<const N: usize> Default for TextBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Buffers are equal when their text is, whatever they held before
+impl<const N: usize> PartialEq for TextBuffer<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<const N: usize> Eq for TextBuffer<N> {}
+
+impl<const N: usize> fmt::Write for TextBuffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s)
+    }
+}
+
+impl<const N: usize> fmt::Display for TextBuffer<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_truncate_at_char_boundary() {
+        let mut buf = TextBuffer::<6>::new();
+        write!(buf, "AB");
+        assert_eq!(buf.push_str("°C°C"), Err(fmt::Error));
+        // The marker replaces the half of the second degree sign that would fit
+        assert_eq!(buf.as_str(), "AB°C~");
+        // Nothing more is added once it is cut off
+        assert_eq!(buf.push('X'), Err(fmt::Error));
+        assert_eq!(buf.truncations(), 1);
+        assert_eq!(buf.peak(), 6);
+
+        buf.clear_and_write(format_args!("OK"));
+        assert!(!buf.truncated());
+        assert_eq!(buf, {
+            let mut other = TextBuffer::<6>::new();
+            write!(other, "OK");
+            other
+        });
+
+        // A full buffer gives up its last character for the marker
+        buf.clear_and_write(format_args!("ABCDEFGH"));
+        assert_eq!(buf.as_str(), "ABCDE~");
+        assert_eq!(buf.truncations(), 2);
+    }
+}
@@ -0,0 +1,237 @@
+//! Fonts and colors used to draw the pages
+//!
+//! `FONT_10X20` contains all printable ASCII characters. The subset font keeps just the digits,
+//! the letters and `:/%.-` to save flash, and the 7-segment font is meant for large numbers. Both
+//! are generated by `assets/fonts/generate_fonts.py`.
+//!
+//! The pages use more than the subset has, e.g. `+` in the key bindings and `[ ]` on the
+//! checklist, so the pages are drawn with the full font. The `subset-font` feature draws them
+//! with the subset instead, for builds that only show pages within it, `FontChoice::has_glyph`
+//! tells which characters those are. The font is picked when building, so the other one is not
+//! linked.
+
+use embedded_graphics::{
+    geometry::Size,
+    image::ImageRaw,
+    mono_font::{ascii::FONT_10X20, mapping::GlyphMapping, DecorationDimensions, MonoFont, MonoTextStyle, MonoTextStyleBuilder},
+    pixelcolor::{Rgb565, RgbColor},
+};
+
+/// Glyphs in the subset font, in image order
+const SUBSET_GLYPHS: &str = " 0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ:/%.-?";
+
+/// Glyphs in the 7-segment font, in image order
+const SEVEN_SEGMENT_GLYPHS: &str = " 0123456789:.-";
+
+/// Maps characters to a glyph in a list of glyphs
+struct GlyphList {
+    glyphs: &'static str,
+    /// Glyph used for characters which are not in the list
+    replacement: char,
+    /// Show lowercase characters as uppercase
+    uppercase: bool,
+}
+
+impl GlyphMapping for GlyphList {
+    fn index(&self, c: char) -> usize {
+        let c = if self.uppercase { c.to_ascii_uppercase() } else { c };
+
+        self.glyphs
+            .chars()
+            .position(|glyph| glyph == c)
+            .or_else(|| self.glyphs.chars().position(|glyph| glyph == self.replacement))
+            .unwrap_or(0)
+    }
+}
+
+/// `FONT_10X20` with only digits, uppercase letters and `:/%.-`, lowercase letters are shown as uppercase
+pub const FONT_10X20_SUBSET: MonoFont = MonoFont {
+    image: ImageRaw::new_binary(include_bytes!("../assets/fonts/font_10x20_subset.raw"), 160),
+    glyph_mapping: &GlyphList {
+        glyphs: SUBSET_GLYPHS,
+        replacement: '?',
+        uppercase: true,
+    },
+    character_size: Size::new(10, 20),
+    character_spacing: 0,
+    baseline: 15,
+    underline: DecorationDimensions::new(15 + 2, 1),
+    strikethrough: DecorationDimensions::new(20 / 2, 1),
+};
+
+/// Large 7-segment style font with digits and `:.-`
+pub const FONT_7SEG_20X40: MonoFont = MonoFont {
+    image: ImageRaw::new_binary(include_bytes!("../assets/fonts/font_7seg_20x40.raw"), 160),
+    glyph_mapping: &GlyphList {
+        glyphs: SEVEN_SEGMENT_GLYPHS,
+        replacement: '-',
+        uppercase: false,
+    },
+    character_size: Size::new(20, 40),
+    character_spacing: 2,
+    baseline: 37,
+    underline: DecorationDimensions::new(37 + 2, 2),
+    strikethrough: DecorationDimensions::new(40 / 2, 2),
+};
+
+/// Font to draw text with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FontChoice {
+    /// The complete `FONT_10X20`
+    Full,
+    /// `FONT_10X20_SUBSET`
+    Subset,
+    /// `FONT_7SEG_20X40`, only for numbers
+    SevenSegment,
+}
+
+impl FontChoice {
+    pub const fn font(&self) -> &'static MonoFont<'static> {
+        match self {
+            FontChoice::Full => &FONT_10X20,
+            FontChoice::Subset => &FONT_10X20_SUBSET,
+            FontChoice::SevenSegment => &FONT_7SEG_20X40,
+        }
+    }
+
+    /// Whether `c` is drawn as itself instead of the replacement glyph
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::theme::FontChoice;
+    /// assert!(FontChoice::Full.has_glyph('+'));
+    /// assert!(FontChoice::Subset.has_glyph('m'));
+    /// assert!(!FontChoice::Subset.has_glyph('+'));
+    /// ```
+    ///
+    pub fn has_glyph(&self, c: char) -> bool {
+        match self {
+            FontChoice::Full => c == ' ' || c.is_ascii_graphic(),
+            FontChoice::Subset => SUBSET_GLYPHS.contains(c.to_ascii_uppercase()),
+            FontChoice::SevenSegment => SEVEN_SEGMENT_GLYPHS.contains(c),
+        }
+    }
+}
+
+/// Font of the pages, the subset with the `subset-font` feature
+#[cfg(not(feature = "subset-font"))]
+pub const PAGE_FONT: FontChoice = FontChoice::Full;
+
+/// Font of the pages, the subset with the `subset-font` feature
+#[cfg(feature = "subset-font")]
+pub const PAGE_FONT: FontChoice = FontChoice::Subset;
+
+/// `PAGE_FONT`, looked up when building
+const PAGE_MONO_FONT: &MonoFont<'static> = PAGE_FONT.font();
+
+/// Depth at which the background of the main page is at its darkest, in mm
+pub const GRADIENT_DEPTH: u32 = 40_000;
+
+/// Blue of the top row at the surface and at `GRADIENT_DEPTH`, out of 31
+const GRADIENT_BLUE: (u32, u32) = (24, 4);
+
+/// Rows of the screen the gradient runs over
+const GRADIENT_ROWS: u32 = 240;
+
+/// Blue background of the main page during a dive, lighter near the surface and darker at depth
+///
+/// Down the screen the blue gets a third darker, like looking down into the water. Only the
+/// shade of the top row is kept, so two gradients are equal when they look the same.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthGradient {
+    /// Blue of the top row, out of 31
+    top: u8,
+}
+
+impl DepthGradient {
+    /// Gradient at `depth` mm
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::theme::DepthGradient;
+    /// use embedded_graphics::pixelcolor::RgbColor;
+    /// let surface = DepthGradient::new(0);
+    /// assert!(DepthGradient::new(20_000).color(0).b() < surface.color(0).b());
+    /// assert!(surface.color(239).b() < surface.color(0).b());
+    /// ```
+    ///
+    pub fn new(depth: u32) -> Self {
+        let (surface, deep) = GRADIENT_BLUE;
+        let depth = depth.min(GRADIENT_DEPTH);
+        DepthGradient {
+            top: (surface - (surface - deep) * depth / GRADIENT_DEPTH) as u8,
+        }
+    }
+
+    /// Color of screen row `row`
+    pub fn color(&self, row: i32) -> Rgb565 {
+        let row = row.clamp(0, GRADIENT_ROWS as i32 - 1) as u32;
+        let top = u32::from(self.top);
+        let blue = top - top * row / (3 * GRADIENT_ROWS);
+        // Half as much green as blue, in its six bits, gives the color of water
+        Rgb565::new(0, blue as u8, blue as u8)
+    }
+}
+
+/// Colors of the pages, they are drawn with `PAGE_FONT`
+#[derive(Debug, Clone, Copy)]
+pub struct Theme {
+    pub text_color: Rgb565,
+    pub background_color: Rgb565,
+}
+
+impl Theme {
+    pub const fn new() -> Self {
+        Theme {
+            text_color: Rgb565::GREEN,
+            background_color: Rgb565::BLACK,
+        }
+    }
+
+    /// White on black for the emergency ascent guide, an alarm doesn't change its color
+    pub const fn emergency() -> Self {
+        Theme {
+            text_color: Rgb565::WHITE,
+            background_color: Rgb565::BLACK,
+        }
+    }
+
+    /// The same theme with the text at a quarter of the brightness
+    pub fn dimmed(&self) -> Self {
+        let color = self.text_color;
+        Theme {
+            text_color: Rgb565::new(color.r() / 4, color.g() / 4, color.b() / 4),
+            ..*self
+        }
+    }
+
+    /// The same theme with `color` as the text color, e.g. for an alarm
+    pub fn with_text_color(&self, color: Option<Rgb565>) -> Self {
+        Theme {
+            text_color: color.unwrap_or(self.text_color),
+            ..*self
+        }
+    }
+
+    /// Style to draw text with
+    pub fn text_style(&self) -> MonoTextStyle<'static, Rgb565> {
+        MonoTextStyleBuilder::new()
+            .font(PAGE_MONO_FONT)
+            .text_color(self.text_color)
+            .background_color(self.background_color)
+            .build()
+    }
+
+    /// Style to draw text over a background that was drawn already, e.g. a `DepthGradient`
+    pub fn transparent_text_style(&self) -> MonoTextStyle<'static, Rgb565> {
+        MonoTextStyleBuilder::new().font(PAGE_MONO_FONT).text_color(self.text_color).build()
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::new()
+    }
+}
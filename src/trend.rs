@@ -0,0 +1,192 @@
+//! Depth trend
+//!
+//! The rate set with the buttons jumps in steps, the depth trend is based on the depth change over
+//! the last `window` simulation steps instead, so it shows what the diver actually did.
+//!
+//! The same depths damp the depth readout when the diver picks a `DepthDamping`, like the
+//! smoothing setting of real computers. Only the shown depth is damped, the model, the alarms and
+//! the log keep the measured depth.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ring_buffer::RingBuffer, SIMULATION_STEP};
+
+/// Maximum number of simulation steps to smooth the rate over
+pub const MAX_TREND_WINDOW: usize = 50;
+
+/// Default number of simulation steps to smooth the rate over, 2 seconds
+pub const DEFAULT_TREND_WINDOW: usize = 20;
+
+/// Smoothed rates below this, in millimeter per minute, count as steady
+const STEADY_RATE: i32 = 500;
+
+/// Direction the diver is moving in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trend {
+    /// Ascending
+    Up,
+    /// Descending
+    Down,
+    Steady,
+}
+
+/// Averaging of the shown depth
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DepthDamping {
+    /// The measured depth
+    #[default]
+    Instant,
+    /// Average of the last second
+    OneSecond,
+    /// Average of the last 4 seconds
+    FourSeconds,
+}
+
+impl DepthDamping {
+    /// Simulation steps to average over
+    pub fn steps(&self) -> usize {
+        let step_ms = SIMULATION_STEP.to_millis() as usize;
+        match self {
+            DepthDamping::Instant => 1,
+            DepthDamping::OneSecond => 1000 / step_ms,
+            DepthDamping::FourSeconds => 4000 / step_ms,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            DepthDamping::Instant => DepthDamping::OneSecond,
+            DepthDamping::OneSecond => DepthDamping::FourSeconds,
+            DepthDamping::FourSeconds => DepthDamping::Instant,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DepthDamping::Instant => "INSTANT",
+            DepthDamping::OneSecond => "1S",
+            DepthDamping::FourSeconds => "4S",
+        }
+    }
+}
+
+/// Moving average of the rate over the depth of the last simulation steps
+#[derive(Debug, Clone, Copy)]
+pub struct RateSmoother {
+    /// Depth in millimeters after each step
+    depths: RingBuffer<u32, MAX_TREND_WINDOW>,
+    /// Number of steps to average over
+    window: usize,
+}
+
+impl RateSmoother {
+    pub const fn new(window: usize) -> Self {
+        RateSmoother {
+            depths: RingBuffer::new(),
+            window: clamp_window(window),
+        }
+    }
+
+    /// Number of steps to average over, clamped to `2..=MAX_TREND_WINDOW`
+    pub fn set_window(&mut self, window: usize) {
+        self.window = clamp_window(window);
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Record the depth in millimeters after a simulation step
+    pub fn push(&mut self, depth: u32) {
+        self.depths.push(depth);
+    }
+
+    /// Average rate over the window in millimeter per minute, positive when descending
+    pub fn rate(&self) -> i32 {
+        let mut depths = self.depths.iter().rev().take(self.window);
+        let (newest, oldest, steps) = match (depths.next(), depths.enumerate().last()) {
+            (Some(&newest), Some((steps, &oldest))) => (newest, oldest, steps as i64 + 1),
+            _ => return 0,
+        };
+
+        let elapsed_us = steps * SIMULATION_STEP.to_micros() as i64;
+        ((newest as i64 - oldest as i64) * 60_000_000 / elapsed_us) as i32
+    }
+
+    /// Average depth in millimeters of the last `steps` simulation steps, at most `MAX_TREND_WINDOW`, `None` before the first
+    pub fn average_depth(&self, steps: usize) -> Option<u32> {
+        let (sum, count) = self
+            .depths
+            .iter()
+            .rev()
+            .take(steps.clamp(1, MAX_TREND_WINDOW))
+            .fold((0u64, 0u64), |(sum, count), &depth| (sum + u64::from(depth), count + 1));
+        (count > 0).then(|| ((sum + count / 2) / count) as u32)
+    }
+
+    pub fn trend(&self) -> Trend {
+        match self.rate() {
+            rate if rate >= STEADY_RATE => Trend::Down,
+            rate if rate <= -STEADY_RATE => Trend::Up,
+            _ => Trend::Steady,
+        }
+    }
+}
+
+impl Default for RateSmoother {
+    fn default() -> Self {
+        Self::new(DEFAULT_TREND_WINDOW)
+    }
+}
+
+const fn clamp_window(window: usize) -> usize {
+    if window < 2 {
+        2
+    } else if window > MAX_TREND_WINDOW {
+        MAX_TREND_WINDOW
+    } else {
+        window
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_rate_is_averaged_over_window() {
+        let mut smoother = RateSmoother::new(5);
+        assert_eq!(smoother.trend(), Trend::Steady);
+
+        // 10 m/min is 16.666 mm per 100 ms step, then a sudden stop
+        for depth in [0, 17, 33, 50, 67, 83, 83] {
+            smoother.push(depth);
+        }
+        assert_eq!(smoother.rate(), 7_500);
+        assert_eq!(smoother.trend(), Trend::Down);
+
+        smoother.set_window(2);
+        assert_eq!(smoother.trend(), Trend::Steady);
+
+        smoother.push(0);
+        assert_eq!(smoother.trend(), Trend::Up);
+    }
+
+    #[test]
+    fn test_depth_damping() {
+        let mut smoother = RateSmoother::default();
+        assert_eq!(smoother.average_depth(DepthDamping::OneSecond.steps()), None);
+
+        for _ in 0..30 {
+            smoother.push(10_000);
+        }
+        for _ in 0..10 {
+            smoother.push(12_000);
+        }
+        assert_eq!(smoother.average_depth(DepthDamping::Instant.steps()), Some(12_000));
+        assert_eq!(smoother.average_depth(DepthDamping::OneSecond.steps()), Some(12_000));
+        assert_eq!(smoother.average_depth(DepthDamping::FourSeconds.steps()), Some(10_500));
+        assert_eq!(DepthDamping::FourSeconds.steps(), 40);
+    }
+}
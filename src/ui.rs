@@ -0,0 +1,91 @@
+//! Screen pages
+
+/// Number of pages
+pub const PAGE_COUNT: usize = 13;
+
+/// Page shown on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Page {
+    /// Depth, rate, air, time and alarm
+    Main,
+    /// Last alarm transitions
+    Warnings,
+    /// Runtime statistics
+    Diagnostics,
+    /// Multilevel dive planner
+    Planner,
+    /// Static apnea tables
+    Apnea,
+    /// Morse signal at the surface
+    Signal,
+    /// Key binding editor
+    Settings,
+    /// Results of the on-device self test, only shown after running it
+    SelfTest,
+    /// Failure injection for the instructor, only reached from the self test page
+    Failures,
+    /// Nitrox blending calculator
+    Blending,
+    /// Ambient and sensor pressures
+    Tech,
+    /// Ceiling and every stop of the decompression model
+    Deco,
+    /// Time left of the safety stop
+    SafetyStop,
+}
+
+impl Page {
+    /// All pages in the order of their discriminants
+    pub const ALL: [Page; PAGE_COUNT] = [
+        Page::Main,
+        Page::Warnings,
+        Page::Diagnostics,
+        Page::Planner,
+        Page::Apnea,
+        Page::Signal,
+        Page::Settings,
+        Page::SelfTest,
+        Page::Failures,
+        Page::Blending,
+        Page::Tech,
+        Page::Deco,
+        Page::SafetyStop,
+    ];
+
+    /// Page to show after this one, the self test and failures pages are left out
+    pub fn next(self) -> Self {
+        match self {
+            Page::Main => Page::Warnings,
+            Page::Warnings => Page::Diagnostics,
+            Page::Diagnostics => Page::Tech,
+            Page::Tech => Page::Deco,
+            Page::Deco => Page::SafetyStop,
+            Page::SafetyStop => Page::Planner,
+            Page::Planner => Page::Blending,
+            Page::Blending => Page::Apnea,
+            Page::Apnea => Page::Signal,
+            Page::Signal => Page::Settings,
+            Page::Settings => Page::Main,
+            Page::SelfTest => Page::Planner,
+            Page::Failures => Page::Main,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Page::Main => "MAIN",
+            Page::Warnings => "WARNINGS",
+            Page::Diagnostics => "DIAGNOSTICS",
+            Page::Planner => "PLANNER",
+            Page::Apnea => "APNEA",
+            Page::Signal => "SIGNAL",
+            Page::Settings => "SETTINGS",
+            Page::SelfTest => "SELF TEST",
+            Page::Failures => "FAILURES",
+            Page::Blending => "BLENDING",
+            Page::Tech => "TECH",
+            Page::Deco => "DECO",
+            Page::SafetyStop => "SAFETY STOP",
+        }
+    }
+}
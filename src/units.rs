@@ -0,0 +1,256 @@
+//! Values shown in the unit of the diver
+//!
+//! The conversions between meters and feet, Celsius and Fahrenheit and bar and psi live here,
+//! pages write their values through `Depth`, `Temperature` and `Pressure` instead of converting
+//! and appending the unit themselves. `Display` writes the number and its unit as a single field,
+//! so the width and alignment of the format string cover both: `{:>6}` right aligns "12.3M" as
+//! well as "40FT" in six characters.
+//!
+//! The values are shown in the primary unit, `Unit::Both` shows metric.
+//!
+//! For the exercises that reason in pressure instead of depth the diver can pick a
+//! `DepthDisplay`: the ambient pressure in bar instead of the depth on the main page, or next to
+//! it. The planner then shows the ambient pressure of the deepest level too.
+
+use core::{
+    fmt,
+    ops::{Div, Mul},
+};
+
+use num::FromPrimitive;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    budget::UiBuffer,
+    format::{self, Digits},
+    text_buffer::TextBuffer,
+    Unit,
+};
+
+/// Tenths of a millimeter in a foot
+const FOOT: u32 = 3048;
+
+/// Depth in millimeters as shown to the user, with one decimal below 10 m or 10 ft
+///
+/// # Examples
+///
+/// ```
+/// use dive_computer::{units::depth_digits, Unit};
+/// assert_eq!(depth_digits(7_349, Unit::Metric).as_str(), "7.3");
+/// assert_eq!(depth_digits(7_350, Unit::Metric).as_str(), "7.4");
+/// assert_eq!(depth_digits(12_345, Unit::Metric).as_str(), "12");
+/// assert_eq!(depth_digits(2_000, Unit::Imperial).as_str(), "6.6");
+/// ```
+///
+pub fn depth_digits(depth: u32, unit: Unit) -> Digits {
+    // Round to tenths of the unit
+    let tenths = match unit.primary() {
+        Unit::Imperial => (depth as i64 * 100 + i64::from(FOOT / 2)) / i64::from(FOOT),
+        _ => (depth as i64 + 50) / 100,
+    };
+
+    if tenths < 100 {
+        Digits::tenths(tenths)
+    } else {
+        Digits::new(tenths / 10)
+    }
+}
+
+/// Millimeters in whole feet, towards zero
+pub(crate) fn mm2ft<T: Mul<Output = T> + Div<Output = T> + FromPrimitive>(depth: T) -> T {
+    depth * T::from_u32(10).unwrap() / T::from_u32(FOOT).unwrap()
+}
+
+/// Rate in m/min converted to `unit` per minute
+pub(crate) fn rate_in(rate: i32, unit: Unit) -> i32 {
+    if unit.primary() == Unit::Imperial {
+        mm2ft(rate * 1000)
+    } else {
+        rate
+    }
+}
+
+/// Depth in millimeters, shown like `depth_digits`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Depth {
+    pub millimeters: u32,
+    pub unit: Unit,
+}
+
+impl Depth {
+    pub const fn new(millimeters: u32, unit: Unit) -> Self {
+        Depth { millimeters, unit }
+    }
+
+    /// The number without the unit, for the main page without `core::fmt`
+    pub fn digits(&self) -> Digits {
+        depth_digits(self.millimeters, self.unit)
+    }
+
+    /// Append the number and the unit right aligned in a field of `width` characters, like `{:>width$}`
+    pub fn push(&self, buf: &mut UiBuffer, width: usize) -> fmt::Result {
+        let unit = self.unit.primary().as_str();
+        format::push_digits(buf, &self.digits(), width.saturating_sub(unit.len()), ' ')?;
+        format::push_str(buf, unit)
+    }
+}
+
+impl fmt::Display for Depth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut text = TextBuffer::<12>::new();
+        write!(text, "{}{}", self.digits(), self.unit.primary().as_str());
+        f.pad(text.as_str())
+    }
+}
+
+/// Temperature in tenths of a degree Celsius, shown with one decimal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Temperature {
+    pub tenths: i16,
+    pub unit: Unit,
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (tenths, symbol) = match self.unit.primary() {
+            Unit::Imperial => (i64::from(self.tenths) * 9 / 5 + 320, "F"),
+            _ => (i64::from(self.tenths), "C"),
+        };
+        let mut text = TextBuffer::<8>::new();
+        write!(text, "{}{}", Digits::tenths(tenths).as_str(), symbol);
+        f.pad(text.as_str())
+    }
+}
+
+/// Tank pressure in whole bar, shown in bar or psi
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pressure {
+    pub bar: u32,
+    pub unit: Unit,
+}
+
+impl Pressure {
+    pub const fn new(bar: u32, unit: Unit) -> Self {
+        Pressure { bar, unit }
+    }
+
+    /// The number in the unit and the unit, for the main page without `core::fmt`
+    pub fn value(&self) -> (u64, &'static str) {
+        match self.unit.primary() {
+            // 1 bar is 14.5038 psi, rounded to the nearest
+            Unit::Imperial => ((u64::from(self.bar) * 145_038 + 5_000) / 10_000, "PSI"),
+            _ => (u64::from(self.bar), "BAR"),
+        }
+    }
+}
+
+impl fmt::Display for Pressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (value, symbol) = self.value();
+        let mut text = TextBuffer::<12>::new();
+        write!(text, "{}{}", value, symbol);
+        f.pad(text.as_str())
+    }
+}
+
+/// Ambient pressure in millibar, shown in bar with one decimal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmbientPressure {
+    pub millibar: u32,
+}
+
+impl AmbientPressure {
+    pub const fn new(millibar: u32) -> Self {
+        AmbientPressure { millibar }
+    }
+
+    /// The number without the unit, for the main page without `core::fmt`
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::units::AmbientPressure;
+    /// assert_eq!(AmbientPressure::new(2_825).digits().as_str(), "2.8");
+    /// assert_eq!(AmbientPressure::new(2_850).digits().as_str(), "2.9");
+    /// ```
+    ///
+    pub fn digits(&self) -> Digits {
+        Digits::tenths((i64::from(self.millibar) + 50) / 100)
+    }
+}
+
+impl fmt::Display for AmbientPressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut text = TextBuffer::<12>::new();
+        write!(text, "{}BAR", self.digits());
+        f.pad(text.as_str())
+    }
+}
+
+/// What the depth line of the main page shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DepthDisplay {
+    #[default]
+    Depth,
+    /// The ambient pressure instead of the depth
+    Pressure,
+    /// The ambient pressure next to the depth, while there is room for it
+    Both,
+}
+
+impl DepthDisplay {
+    pub fn next(self) -> Self {
+        match self {
+            DepthDisplay::Depth => DepthDisplay::Pressure,
+            DepthDisplay::Pressure => DepthDisplay::Both,
+            DepthDisplay::Both => DepthDisplay::Depth,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DepthDisplay::Depth => "DEPTH",
+            DepthDisplay::Pressure => "BAR",
+            DepthDisplay::Both => "DEPTH+BAR",
+        }
+    }
+
+    /// Whether the ambient pressure is shown
+    pub fn pressure(&self) -> bool {
+        *self != DepthDisplay::Depth
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(format!("{:>6}", Depth::new(12_345, Unit::Metric)), "   12M");
+        assert_eq!(format!("{:>6}", Depth::new(12_345, Unit::Imperial)), "  40FT");
+        assert_eq!(format!("{:>6}", Depth::new(2_000, Unit::Both)), "  2.0M");
+        for (depth, unit) in [(12_345, Unit::Metric), (12_345, Unit::Imperial), (2_000, Unit::Both)] {
+            let mut fast = UiBuffer::new();
+            Depth::new(depth, unit).push(&mut fast, 6).unwrap();
+            assert_eq!(fast.as_str(), format!("{:>6}", Depth::new(depth, unit)));
+        }
+        // Whole feet are cut off, the tenths rounded
+        assert_eq!(mm2ft(3_048), 10);
+        assert_eq!(mm2ft(-20_000), -65);
+        assert_eq!(depth_digits(3_000, Unit::Imperial).as_str(), "9.8");
+
+        let temperature = Temperature { tenths: -25, unit: Unit::Metric };
+        assert_eq!(format!("{}", temperature), "-2.5C");
+        let temperature = Temperature {
+            tenths: 200,
+            unit: Unit::Imperial,
+        };
+        assert_eq!(format!("{}", temperature), "68.0F");
+
+        assert_eq!(format!("{:>7}", Pressure::new(200, Unit::Metric)), " 200BAR");
+        assert_eq!(format!("{:>7}", Pressure::new(200, Unit::Imperial)), "2901PSI");
+        assert_eq!(format!("{:>7}", AmbientPressure::new(10_980)), "11.0BAR");
+    }
+}
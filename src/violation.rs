@@ -0,0 +1,78 @@
+//! Missed decompression stops
+//!
+//! Surfacing while the decompression model still has a ceiling is a violation. Like real dive
+//! computers this one then refuses to plan dives for `LOCKOUT`, the main page shows a banner with
+//! the hours left and the warnings page records the missed stop.
+//!
+//! The lockout counts simulated time and only lives in RAM, there is no flash storage yet.
+
+use fugit::{MicrosDurationU32, MicrosDurationU64, SecsDurationU32};
+
+use crate::clock::Countdown;
+
+/// Time dive planning stays locked after a violation
+pub const LOCKOUT: SecsDurationU32 = SecsDurationU32::hours(24);
+
+#[derive(Debug, Clone, Copy)]
+pub struct Lockout {
+    remaining: Countdown,
+}
+
+impl Lockout {
+    /// No lockout
+    pub const fn new() -> Self {
+        Lockout { remaining: Countdown::new() }
+    }
+
+    /// Lock for `LOCKOUT` from now, also when already locked
+    pub fn start(&mut self) {
+        self.remaining.start(MicrosDurationU64::secs(LOCKOUT.to_secs() as u64));
+    }
+
+    /// Count down `duration`
+    pub fn tick(&mut self, duration: MicrosDurationU32) {
+        self.remaining.tick(duration.into());
+    }
+
+    pub fn active(&self) -> bool {
+        self.remaining.active()
+    }
+
+    /// Hours left, rounded up
+    pub fn hours_left(&self) -> u32 {
+        self.remaining.remaining().to_micros().div_ceil(MicrosDurationU64::hours(1).to_micros()) as u32
+    }
+}
+
+impl Default for Lockout {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_lockout_counts_down() {
+        let mut lockout = Lockout::new();
+        assert!(!lockout.active());
+
+        lockout.start();
+        assert_eq!(lockout.hours_left(), 24);
+        lockout.tick(MicrosDurationU32::secs(60));
+        assert_eq!(lockout.hours_left(), 24);
+
+        for _ in 0..23 {
+            lockout.tick(MicrosDurationU32::minutes(60));
+        }
+        assert!(lockout.active());
+        assert_eq!(lockout.hours_left(), 1);
+
+        lockout.tick(MicrosDurationU32::minutes(59));
+        assert!(!lockout.active());
+        assert_eq!(lockout.hours_left(), 0);
+    }
+}
@@ -0,0 +1,200 @@
+//! Date and time of day
+//!
+//! The device has no battery backed clock, so it only knows the date once a host gives it over
+//! the console: `time set <unix-ts>` takes the Unix time in seconds, sets the RTC and shifts the
+//! log timestamps onto it, `time get` prints the Unix time back. The host CLI does both before it
+//! downloads the logs, so their times line up with the ones of the host.
+//!
+//! `WallClock` only keeps the Unix time of the boot. Log timestamps and the times of the dive that
+//! is going on are kept as time since boot, they move onto the new time as a whole instead of
+//! being rewritten one by one. The drift correction still applies to the time since boot.
+
+use core::{
+    fmt,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use pimoroni_pico_explorer::hal::rtc::{DateTime, DayOfWeek, RealTimeClock};
+
+use crate::clock::Instant;
+
+/// Earliest time that can be set, the RTC starts its years at 2000
+pub const MIN_UNIX_TIME: u32 = 946_684_800;
+
+/// Unix time of the boot in seconds for the log timestamps, zero until the time is set
+static BOOT_UNIX_SECS: AtomicU32 = AtomicU32::new(0);
+
+/// Seconds to add to the time since boot of the log timestamps
+pub fn log_offset() -> u32 {
+    BOOT_UNIX_SECS.load(Ordering::Relaxed)
+}
+
+/// Unix time of the boot, once a host gave the time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WallClock {
+    /// In seconds
+    boot: Option<u32>,
+}
+
+impl WallClock {
+    pub const fn new() -> Self {
+        WallClock { boot: None }
+    }
+
+    /// The Unix time was `unix` seconds at `now`, corrected for the drift
+    ///
+    /// The log timestamps move onto it too. Returns false for a time before `MIN_UNIX_TIME`.
+    pub fn set(&mut self, now: Instant, unix: u32) -> bool {
+        let since_boot = now.duration_since_epoch().to_secs();
+        let Some(boot) = u64::from(unix).checked_sub(since_boot).filter(|_| unix >= MIN_UNIX_TIME) else {
+            return false;
+        };
+        self.boot = Some(boot as u32);
+        BOOT_UNIX_SECS.store(boot as u32, Ordering::Relaxed);
+        true
+    }
+
+    /// Seconds to add to a time since boot, zero before the time was set
+    pub fn offset(&self) -> u32 {
+        self.boot.unwrap_or(0)
+    }
+
+    /// Unix time in seconds at `now`, `None` before the time was set
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{clock::Instant, wall_clock::WallClock};
+    /// let mut wall_clock = WallClock::new();
+    /// assert_eq!(wall_clock.unix_time(Instant::from_ticks(0)), None);
+    /// wall_clock.set(Instant::from_ticks(60_000_000), 1_760_659_200);
+    /// assert_eq!(wall_clock.unix_time(Instant::from_ticks(90_500_000)), Some(1_760_659_230));
+    /// ```
+    ///
+    pub fn unix_time(&self, now: Instant) -> Option<u32> {
+        let boot = self.boot?;
+        Some(boot.saturating_add(now.duration_since_epoch().to_secs().min(u64::from(u32::MAX)) as u32))
+    }
+}
+
+/// Calendar date and time of day in UTC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CivilTime {
+    pub year: u16,
+    /// 1 to 12
+    pub month: u8,
+    /// 1 to 31
+    pub day: u8,
+    /// 0 for Sunday to 6 for Saturday
+    pub weekday: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl CivilTime {
+    /// Date and time of `unix` seconds since 1970
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::wall_clock::CivilTime;
+    /// let time = CivilTime::from_unix(1_709_210_096);
+    /// assert_eq!(format!("{}", time), "2024-02-29 12:34:56");
+    /// assert_eq!(time.weekday, 4);
+    /// ```
+    ///
+    pub fn from_unix(unix: u32) -> Self {
+        let days = unix / 86_400;
+        let seconds = unix % 86_400;
+
+        // Days to a date in the proleptic Gregorian calendar, with years starting in March so
+        // the leap day is the last day of the year
+        let z = days + 719_468;
+        let era = z / 146_097;
+        let day_of_era = z % 146_097;
+        let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let month_from_march = (5 * day_of_year + 2) / 153;
+        let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+        let month = if month_from_march < 10 { month_from_march + 3 } else { month_from_march - 9 };
+        let year = year_of_era + era * 400 + u32::from(month <= 2);
+
+        CivilTime {
+            year: year as u16,
+            month: month as u8,
+            day: day as u8,
+            // 1 January 1970 was a Thursday
+            weekday: ((days + 4) % 7) as u8,
+            hour: (seconds / 3600) as u8,
+            minute: (seconds / 60 % 60) as u8,
+            second: (seconds % 60) as u8,
+        }
+    }
+}
+
+impl fmt::Display for CivilTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+            self.year, self.month, self.day, self.hour, self.minute, self.second
+        )
+    }
+}
+
+/// Clock hardware the console sets the date and time of
+pub trait SetDateTime {
+    /// Returns false when the hardware refused it
+    fn set_date_time(&mut self, time: &CivilTime) -> bool;
+}
+
+/// An RTC alarm that is already scheduled keeps its time of day, it goes off at the wrong
+/// moment once and is set again after it
+impl SetDateTime for RealTimeClock {
+    fn set_date_time(&mut self, time: &CivilTime) -> bool {
+        const DAYS: [DayOfWeek; 7] = [
+            DayOfWeek::Sunday,
+            DayOfWeek::Monday,
+            DayOfWeek::Tuesday,
+            DayOfWeek::Wednesday,
+            DayOfWeek::Thursday,
+            DayOfWeek::Friday,
+            DayOfWeek::Saturday,
+        ];
+        let date_time = DateTime {
+            year: time.year,
+            month: time.month,
+            day: time.day,
+            day_of_week: DAYS[usize::from(time.weekday) % 7],
+            hour: time.hour,
+            minute: time.minute,
+            second: time.second,
+        };
+        self.set_datetime(date_time).is_ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_civil_time() {
+        assert_eq!(format!("{}", CivilTime::from_unix(MIN_UNIX_TIME)), "2000-01-01 00:00:00");
+        assert_eq!(CivilTime::from_unix(MIN_UNIX_TIME).weekday, 6);
+        // Around the leap day of a year divisible by 100 and 400
+        assert_eq!(format!("{}", CivilTime::from_unix(951_868_799)), "2000-02-29 23:59:59");
+        assert_eq!(format!("{}", CivilTime::from_unix(951_868_800)), "2000-03-01 00:00:00");
+        assert_eq!(format!("{}", CivilTime::from_unix(1_760_659_200)), "2025-10-17 00:00:00");
+        assert_eq!(CivilTime::from_unix(1_760_659_200).weekday, 5);
+        assert_eq!(format!("{}", CivilTime::from_unix(u32::MAX)), "2106-02-07 06:28:15");
+
+        // The time of the boot can't be before 1970, nor the time before 2000
+        let mut wall_clock = WallClock::new();
+        assert!(!wall_clock.set(Instant::from_ticks(0), MIN_UNIX_TIME - 1));
+        assert!(!wall_clock.set(Instant::from_ticks(u64::MAX), MIN_UNIX_TIME));
+        assert_eq!(wall_clock.unix_time(Instant::from_ticks(0)), None);
+    }
+}
@@ -0,0 +1,153 @@
+//! Fast boot after a watchdog reset
+//!
+//! The dive tick keeps a `RetainedState` in a RAM section the startup code doesn't zero. RAM
+//! keeps its contents through a watchdog reset, so when the reset reason says the watchdog
+//! fired and the retained words check out, init skips the setup wizard and restores the page and
+//! the dive before the first frame is drawn. A mid-dive reset then looks like a dropped frame.
+//!
+//! After a power-on the RAM holds noise, the magic and the CRC keep it from being taken for a
+//! state. Only the depth, rate, air and dive time are kept. The tissues are loaded as if the whole
+//! dive was spent at the retained depth, which errs on the safe side, the profile and the alarm
+//! history start over.
+
+use core::{mem::MaybeUninit, ptr};
+
+use crc::{Crc, CRC_32_ISO_HDLC};
+use fugit::MicrosDurationU64;
+use pimoroni_pico_explorer::hal::pac;
+
+use crate::ui::Page;
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+
+/// Marks the retained words as holding a `RetainedState`
+const RETAINED_MAGIC: u32 = 0x3A7B_00D5;
+
+/// Words of a `RetainedState`, the last one is the CRC of the others
+const WORDS: usize = 8;
+
+#[cfg_attr(target_os = "none", link_section = ".uninit.RETAINED")]
+static mut RETAINED: MaybeUninit<[u32; WORDS]> = MaybeUninit::uninit();
+
+/// What a warm boot restores
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetainedState {
+    pub page: Page,
+    /// Depth in millimeters
+    pub depth: u32,
+    /// Rate in meters per minute
+    pub rate: i32,
+    /// Air in centiliters
+    pub air: u32,
+    pub edt: MicrosDurationU64,
+}
+
+impl RetainedState {
+    /// Contents of the retained words
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use dive_computer::{ui::Page, warm_boot::RetainedState};
+    /// use fugit::MicrosDurationU64;
+    /// let state = RetainedState { page: Page::Tech, depth: 18_000, rate: -9, air: 4_200, edt: MicrosDurationU64::secs(600) };
+    /// assert_eq!(RetainedState::from_words(state.to_words()), Some(state));
+    /// assert_eq!(RetainedState::from_words([0; 8]), None);
+    /// ```
+    ///
+    pub fn to_words(&self) -> [u32; WORDS] {
+        let edt = self.edt.to_micros();
+        let mut words = [
+            RETAINED_MAGIC,
+            self.page as u32,
+            self.depth,
+            self.rate as u32,
+            self.air,
+            edt as u32,
+            (edt >> 32) as u32,
+            0,
+        ];
+        words[WORDS - 1] = checksum(&words);
+        words
+    }
+
+    /// State kept in the retained words, if they hold a valid one
+    pub fn from_words(words: [u32; WORDS]) -> Option<Self> {
+        let [magic, page, depth, rate, air, edt_low, edt_high, crc] = words;
+        if magic != RETAINED_MAGIC || crc != checksum(&words) {
+            return None;
+        }
+
+        Some(RetainedState {
+            page: *Page::ALL.get(page as usize)?,
+            depth,
+            rate: rate as i32,
+            air,
+            edt: MicrosDurationU64::micros(u64::from(edt_high) << 32 | u64::from(edt_low)),
+        })
+    }
+}
+
+/// CRC of all words but the last
+fn checksum(words: &[u32; WORDS]) -> u32 {
+    let mut digest = CRC.digest();
+    for word in &words[..WORDS - 1] {
+        digest.update(&word.to_le_bytes());
+    }
+    digest.finalize()
+}
+
+/// Keep `state` for a warm boot
+pub fn store(state: &RetainedState) {
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!(RETAINED).cast::<[u32; WORDS]>(), state.to_words()) };
+}
+
+/// Drop the kept state, e.g. before a reboot that should start over
+pub fn forget() {
+    unsafe { ptr::write_volatile(ptr::addr_of_mut!(RETAINED).cast::<[u32; WORDS]>(), [0; WORDS]) };
+}
+
+/// State kept before a watchdog reset, `None` after any other reset
+///
+/// It is forgotten, so a state is restored at most once.
+pub fn take() -> Option<RetainedState> {
+    let watchdog = unsafe { &*pac::WATCHDOG::ptr() };
+    let reason = watchdog.reason.read();
+    let warm = reason.timer().bit_is_set() || reason.force().bit_is_set();
+
+    let words = unsafe { ptr::read_volatile(ptr::addr_of!(RETAINED).cast::<[u32; WORDS]>()) };
+    forget();
+    warm.then(|| RetainedState::from_words(words)).flatten()
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_words() {
+        let state = RetainedState {
+            page: Page::Main,
+            depth: 31_400,
+            rate: -18,
+            air: 1_250,
+            edt: MicrosDurationU64::secs(5_000),
+        };
+        let words = state.to_words();
+        assert_eq!(RetainedState::from_words(words), Some(state));
+
+        // Any changed word is noise, like RAM after a power-on
+        for index in 0..WORDS {
+            let mut corrupt = words;
+            corrupt[index] ^= 1 << 7;
+            assert_eq!(RetainedState::from_words(corrupt), None, "word {}", index);
+        }
+
+        // A page that doesn't exist, even with a matching CRC
+        let mut unknown = words;
+        unknown[1] = Page::ALL.len() as u32;
+        unknown[WORDS - 1] = checksum(&unknown);
+        assert_eq!(RetainedState::from_words(unknown), None);
+    }
+}
@@ -0,0 +1,613 @@
+//! Graphics drawn next to the page text
+//!
+//! The fonts only have text glyphs, anything else is drawn with primitives here. So is text in
+//! another size than the page font.
+
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyleBuilder},
+    pixelcolor::Rgb565,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle, Triangle},
+    text::{Baseline, Text},
+};
+
+#[cfg(feature = "logbook")]
+use embedded_graphics::primitives::Line;
+
+use crate::{ascent::Coaching, fault::FaultCode, text_buffer::TextBuffer, theme::FONT_7SEG_20X40, trend::Trend, units::Depth, SecondaryReadings, SECONDARY_WIDTH};
+#[cfg(feature = "logbook")]
+use crate::{
+    audit::icon,
+    dive_log::{DiveLog, SAMPLE_INTERVAL},
+};
+
+/// Size of the trend arrow, one line of `FONT_10X20` high
+pub const TREND_ARROW_SIZE: Size = Size::new(16, 20);
+
+/// Top left of the trend arrow on the main page, right of the depth line
+pub const DEPTH_TREND_POSITION: Point = Point::new(222, 55);
+
+/// Top left of the ascent arrows on the main page, right of the ascent line
+pub const ASCENT_ARROWS_POSITION: Point = Point::new(222, 95);
+
+/// Top left of the padlock, right of the title line on every page
+pub const PADLOCK_POSITION: Point = Point::new(222, 13);
+
+/// Top left of the fault code, in the bottom right corner of every page
+pub const FAULT_CODE_POSITION: Point = Point::new(226, 228);
+
+/// Room for the two characters of a fault code in `FONT_6X10`
+const FAULT_CODE_SIZE: Size = Size::new(12, 10);
+
+/// Top left of the secondary unit values on the main page, right of the narrowed depth and rate
+pub const SECONDARY_POSITION: Point = Point::new(222 - 10 * SECONDARY_WIDTH as i32, 55);
+
+/// Top left of the fill progress bar on the surface page, below the filling line when the last dive has a temperature
+pub const FILL_BAR_POSITION: Point = Point::new(20, 195);
+
+/// Size of the fill progress bar, as wide as a line of text
+const FILL_BAR_SIZE: Size = Size::new(200, 16);
+
+/// Top left of the profile graph on the surface page, below the battery line in the place of the fill bar
+pub const PROFILE_GRAPH_POSITION: Point = Point::new(20, 180);
+
+/// Size of the profile graph, as wide as a line of text and down to the fault code
+const PROFILE_GRAPH_SIZE: Size = Size::new(200, 44);
+
+/// Height of the row of action icons above the profile, a line of `FONT_6X10`
+const PROFILE_ICON_HEIGHT: u32 = 12;
+
+/// Top left of the depth on the emergency ascent guide, over its second and third line
+pub const CESA_DEPTH_POSITION: Point = Point::new(20, 35);
+
+/// Top left of the ascent arrows on the emergency ascent guide, right of the ascent line
+pub const CESA_ARROWS_POSITION: Point = Point::new(222, 75);
+
+/// Top left of the rate bar on the emergency ascent guide, over the line below the ascent line
+pub const RATE_BAR_POSITION: Point = Point::new(20, 97);
+
+/// Room for five characters of the 7-segment font, the deepest depth shown
+const LARGE_DEPTH_SIZE: Size = Size::new(5 * 22, 40);
+
+/// Size of the rate bar, as wide as a line of text
+const RATE_BAR_SIZE: Size = Size::new(200, 16);
+
+/// Room for the secondary unit values, the depth and rate lines
+const SECONDARY_SIZE: Size = Size::new(10 * SECONDARY_WIDTH as u32 - 2, 40);
+
+/// Arrow pointing up or down, or a dash when steady
+pub struct TrendArrow {
+    trend: Trend,
+    top_left: Point,
+    color: Rgb565,
+    /// Cleared first, `None` when the background was drawn already
+    background_color: Option<Rgb565>,
+}
+
+impl TrendArrow {
+    pub fn new(trend: Trend, top_left: Point, color: Rgb565, background_color: Option<Rgb565>) -> Self {
+        TrendArrow {
+            trend,
+            top_left,
+            color,
+            background_color,
+        }
+    }
+}
+
+impl Dimensions for TrendArrow {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.top_left, TREND_ARROW_SIZE)
+    }
+}
+
+impl Drawable for TrendArrow {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        // Clear the previous arrow
+        if let Some(background_color) = self.background_color {
+            self.bounding_box().into_styled(PrimitiveStyle::with_fill(background_color)).draw(target)?;
+        }
+
+        let style = PrimitiveStyle::with_fill(self.color);
+        let (width, height) = (TREND_ARROW_SIZE.width as i32, TREND_ARROW_SIZE.height as i32);
+        let origin = self.top_left;
+
+        match self.trend {
+            Trend::Up => Triangle::new(
+                origin + Point::new(width / 2, 2),
+                origin + Point::new(0, height - 3),
+                origin + Point::new(width - 1, height - 3),
+            )
+            .into_styled(style)
+            .draw(target),
+            Trend::Down => Triangle::new(
+                origin + Point::new(0, 2),
+                origin + Point::new(width - 1, 2),
+                origin + Point::new(width / 2, height - 3),
+            )
+            .into_styled(style)
+            .draw(target),
+            Trend::Steady => Rectangle::new(origin + Point::new(1, height / 2 - 2), Size::new(width as u32 - 2, 4))
+                .into_styled(style)
+                .draw(target),
+        }
+    }
+}
+
+/// Up to three stacked arrows pointing up, more arrows for a faster ascent
+pub struct AscentArrows {
+    count: u8,
+    top_left: Point,
+    color: Rgb565,
+    background_color: Option<Rgb565>,
+}
+
+impl AscentArrows {
+    pub fn new(coaching: Coaching, top_left: Point, color: Rgb565, background_color: Option<Rgb565>) -> Self {
+        AscentArrows {
+            count: coaching.arrows(),
+            top_left,
+            color,
+            background_color,
+        }
+    }
+}
+
+impl Dimensions for AscentArrows {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.top_left, TREND_ARROW_SIZE)
+    }
+}
+
+impl Drawable for AscentArrows {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        // Clear the previous arrows
+        if let Some(background_color) = self.background_color {
+            self.bounding_box().into_styled(PrimitiveStyle::with_fill(background_color)).draw(target)?;
+        }
+
+        let style = PrimitiveStyle::with_fill(self.color);
+        let width = TREND_ARROW_SIZE.width as i32;
+
+        // Bottom arrow first
+        for arrow in 0..self.count as i32 {
+            let top = self.top_left + Point::new(0, 13 - 6 * arrow);
+            Triangle::new(top + Point::new(width / 2, 0), top + Point::new(0, 5), top + Point::new(width - 1, 5))
+                .into_styled(style)
+                .draw(target)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Padlock shown while the buttons are locked, nothing otherwise
+pub struct Padlock {
+    locked: bool,
+    top_left: Point,
+    color: Rgb565,
+    background_color: Option<Rgb565>,
+}
+
+impl Padlock {
+    pub fn new(locked: bool, top_left: Point, color: Rgb565, background_color: Option<Rgb565>) -> Self {
+        Padlock {
+            locked,
+            top_left,
+            color,
+            background_color,
+        }
+    }
+}
+
+impl Dimensions for Padlock {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.top_left, TREND_ARROW_SIZE)
+    }
+}
+
+impl Drawable for Padlock {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        // Clear the previous padlock
+        if let Some(background_color) = self.background_color {
+            self.bounding_box().into_styled(PrimitiveStyle::with_fill(background_color)).draw(target)?;
+        }
+
+        if !self.locked {
+            return Ok(());
+        }
+
+        // Shackle on top of the body
+        Rectangle::new(self.top_left + Point::new(3, 2), Size::new(10, 10))
+            .into_styled(PrimitiveStyle::with_stroke(self.color, 2))
+            .draw(target)?;
+        Rectangle::new(self.top_left + Point::new(0, 9), Size::new(16, 10))
+            .into_styled(PrimitiveStyle::with_fill(self.color))
+            .draw(target)
+    }
+}
+
+/// Code of the newest fault in small text, nothing without one
+pub struct FaultBadge {
+    code: Option<FaultCode>,
+    top_left: Point,
+    color: Rgb565,
+    background_color: Option<Rgb565>,
+}
+
+impl FaultBadge {
+    pub fn new(code: Option<FaultCode>, top_left: Point, color: Rgb565, background_color: Option<Rgb565>) -> Self {
+        FaultBadge {
+            code,
+            top_left,
+            color,
+            background_color,
+        }
+    }
+}
+
+impl Dimensions for FaultBadge {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.top_left, FAULT_CODE_SIZE)
+    }
+}
+
+impl Drawable for FaultBadge {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        // Clear the previous code
+        if let Some(background_color) = self.background_color {
+            self.bounding_box().into_styled(PrimitiveStyle::with_fill(background_color)).draw(target)?;
+        }
+
+        let Some(code) = self.code else {
+            return Ok(());
+        };
+
+        let style = MonoTextStyleBuilder::new().font(&FONT_6X10).text_color(self.color).build();
+        Text::with_baseline(code.code(), self.top_left, style, Baseline::Top).draw(target)?;
+        Ok(())
+    }
+}
+
+/// Depth and rate in the secondary unit in small text, nothing when there is none
+pub struct SecondaryUnits {
+    readings: Option<SecondaryReadings>,
+    top_left: Point,
+    color: Rgb565,
+    background_color: Option<Rgb565>,
+}
+
+impl SecondaryUnits {
+    pub fn new(readings: Option<SecondaryReadings>, top_left: Point, color: Rgb565, background_color: Option<Rgb565>) -> Self {
+        SecondaryUnits {
+            readings,
+            top_left,
+            color,
+            background_color,
+        }
+    }
+}
+
+impl Dimensions for SecondaryUnits {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.top_left, SECONDARY_SIZE)
+    }
+}
+
+impl Drawable for SecondaryUnits {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        // Clear the previous values
+        if let Some(background_color) = self.background_color {
+            self.bounding_box().into_styled(PrimitiveStyle::with_fill(background_color)).draw(target)?;
+        }
+
+        let Some(readings) = self.readings else {
+            return Ok(());
+        };
+
+        // At most 9 characters of 6 pixels fit, the values stay well within that
+        let mut depth = TextBuffer::<10>::new();
+        write!(depth, "{:>7}", Depth::new(readings.depth, readings.unit));
+        let mut rate = TextBuffer::<10>::new();
+        write!(rate, "{:>4}{}/M", readings.rate, readings.unit.as_str());
+
+        // Bottom aligned with the page font on the depth and rate lines
+        let style = MonoTextStyleBuilder::new().font(&FONT_6X10).text_color(self.color).build();
+        Text::with_baseline(depth.as_str(), self.top_left + Point::new(0, 6), style, Baseline::Top).draw(target)?;
+        Text::with_baseline(rate.as_str(), self.top_left + Point::new(0, 26), style, Baseline::Top).draw(target)?;
+
+        Ok(())
+    }
+}
+
+/// Bar filling up with the tank while the compressor is connected, nothing otherwise
+pub struct FillBar {
+    /// Tank pressure in percent of a full tank
+    percent: Option<u8>,
+    top_left: Point,
+    color: Rgb565,
+    background_color: Option<Rgb565>,
+}
+
+impl FillBar {
+    pub fn new(percent: Option<u8>, top_left: Point, color: Rgb565, background_color: Option<Rgb565>) -> Self {
+        FillBar {
+            percent,
+            top_left,
+            color,
+            background_color,
+        }
+    }
+}
+
+impl Dimensions for FillBar {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.top_left, FILL_BAR_SIZE)
+    }
+}
+
+impl Drawable for FillBar {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        // Clear the previous bar
+        if let Some(background_color) = self.background_color {
+            self.bounding_box().into_styled(PrimitiveStyle::with_fill(background_color)).draw(target)?;
+        }
+
+        let Some(percent) = self.percent else {
+            return Ok(());
+        };
+
+        // Outline with the filled part inside
+        self.bounding_box().into_styled(PrimitiveStyle::with_stroke(self.color, 2)).draw(target)?;
+        let inner = FILL_BAR_SIZE - Size::new(8, 8);
+        let width = inner.width * percent.min(100) as u32 / 100;
+        Rectangle::new(self.top_left + Point::new(4, 4), Size::new(width, inner.height))
+            .into_styled(PrimitiveStyle::with_fill(self.color))
+            .draw(target)
+    }
+}
+
+/// Profile of the last dive with an icon for every action of the diver, nothing without one
+#[cfg(feature = "logbook")]
+pub struct ProfileGraph {
+    log: Option<DiveLog>,
+    top_left: Point,
+    color: Rgb565,
+    background_color: Option<Rgb565>,
+}
+
+#[cfg(feature = "logbook")]
+impl ProfileGraph {
+    pub fn new(log: Option<DiveLog>, top_left: Point, color: Rgb565, background_color: Option<Rgb565>) -> Self {
+        ProfileGraph {
+            log,
+            top_left,
+            color,
+            background_color,
+        }
+    }
+}
+
+#[cfg(feature = "logbook")]
+impl Dimensions for ProfileGraph {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.top_left, PROFILE_GRAPH_SIZE)
+    }
+}
+
+#[cfg(feature = "logbook")]
+impl Drawable for ProfileGraph {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        // Clear the previous graph
+        if let Some(background_color) = self.background_color {
+            self.bounding_box().into_styled(PrimitiveStyle::with_fill(background_color)).draw(target)?;
+        }
+
+        let Some(log) = &self.log else {
+            return Ok(());
+        };
+        let last = log.depths().count().saturating_sub(1) as i32;
+        let deepest = log.depths().max().unwrap_or(0);
+        if last == 0 || deepest == 0 {
+            return Ok(());
+        }
+
+        // Time to the right and depth down, the deepest sample at the bottom
+        let width = PROFILE_GRAPH_SIZE.width as i32 - 1;
+        let height = (PROFILE_GRAPH_SIZE.height - PROFILE_ICON_HEIGHT) as i32 - 1;
+        let origin = self.top_left + Point::new(0, PROFILE_ICON_HEIGHT as i32);
+        let style = PrimitiveStyle::with_stroke(self.color, 1);
+        let mut previous = None;
+        for (index, depth) in log.depths().enumerate() {
+            let y = (u64::from(depth) * height as u64 / u64::from(deepest)) as i32;
+            let point = origin + Point::new(index as i32 * width / last, y);
+            if let Some(previous) = previous {
+                Line::new(previous, point).into_styled(style).draw(target)?;
+            }
+            previous = Some(point);
+        }
+
+        // Each icon centered over the time of its action
+        let text_style = MonoTextStyleBuilder::new().font(&FONT_6X10).text_color(self.color).build();
+        let duration = last as u32 * SAMPLE_INTERVAL.to_secs();
+        for event in log.events() {
+            let x = (u32::from(event.time).min(duration) * width as u32 / duration) as i32;
+            let mut glyph = [0; 4];
+            let glyph = icon(event.action).encode_utf8(&mut glyph);
+            let left = (x - 3).clamp(0, width - 5);
+            Text::with_baseline(glyph, self.top_left + Point::new(left, 0), text_style, Baseline::Top).draw(target)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Depth in the 7-segment font, without the unit
+pub struct LargeDepth {
+    depth: Depth,
+    top_left: Point,
+    color: Rgb565,
+    background_color: Option<Rgb565>,
+}
+
+impl LargeDepth {
+    pub fn new(depth: Depth, top_left: Point, color: Rgb565, background_color: Option<Rgb565>) -> Self {
+        LargeDepth {
+            depth,
+            top_left,
+            color,
+            background_color,
+        }
+    }
+}
+
+impl Dimensions for LargeDepth {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.top_left, LARGE_DEPTH_SIZE)
+    }
+}
+
+impl Drawable for LargeDepth {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        // Clear the previous depth
+        if let Some(background_color) = self.background_color {
+            self.bounding_box().into_styled(PrimitiveStyle::with_fill(background_color)).draw(target)?;
+        }
+
+        let style = MonoTextStyleBuilder::new().font(&FONT_7SEG_20X40).text_color(self.color).build();
+        Text::with_baseline(self.depth.digits().as_str(), self.top_left, style, Baseline::Top).draw(target)?;
+
+        Ok(())
+    }
+}
+
+/// Bar of the ascent rate with a mark for the target rate in the middle
+pub struct RateBar {
+    /// Ascent rate in percent of the target rate
+    percent: u32,
+    top_left: Point,
+    color: Rgb565,
+    background_color: Option<Rgb565>,
+}
+
+impl RateBar {
+    pub fn new(percent: u32, top_left: Point, color: Rgb565, background_color: Option<Rgb565>) -> Self {
+        RateBar {
+            percent,
+            top_left,
+            color,
+            background_color,
+        }
+    }
+}
+
+impl Dimensions for RateBar {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(self.top_left, RATE_BAR_SIZE)
+    }
+}
+
+impl Drawable for RateBar {
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        // Clear the previous bar
+        if let Some(background_color) = self.background_color {
+            self.bounding_box().into_styled(PrimitiveStyle::with_fill(background_color)).draw(target)?;
+        }
+
+        // Outline with the rate inside, full at twice the target
+        self.bounding_box().into_styled(PrimitiveStyle::with_stroke(self.color, 2)).draw(target)?;
+        let inner = RATE_BAR_SIZE - Size::new(8, 8);
+        let width = inner.width * self.percent.min(200) / 200;
+        Rectangle::new(self.top_left + Point::new(4, 4), Size::new(width, inner.height))
+            .into_styled(PrimitiveStyle::with_fill(self.color))
+            .draw(target)?;
+
+        // The target mark is a line in front of the rate and a gap in it past the target
+        let mark = match self.percent >= 100 {
+            true => self.background_color,
+            false => Some(self.color),
+        };
+        let Some(mark) = mark else {
+            return Ok(());
+        };
+        Rectangle::new(self.top_left + Point::new(RATE_BAR_SIZE.width as i32 / 2 - 1, 4), Size::new(2, inner.height))
+            .into_styled(PrimitiveStyle::with_fill(mark))
+            .draw(target)
+    }
+}
+
+/// Two drawables drawn as one, e.g. to send them to the screen in the same batch
+pub struct Pair<'a, A, B>(pub &'a A, pub &'a B);
+
+impl<A, B> Drawable for Pair<'_, A, B>
+where
+    A: Drawable<Color = Rgb565>,
+    B: Drawable<Color = Rgb565>,
+{
+    type Color = Rgb565;
+    type Output = ();
+
+    fn draw<D>(&self, target: &mut D) -> Result<Self::Output, D::Error>
+    where
+        D: DrawTarget<Color = Self::Color>,
+    {
+        self.0.draw(target)?;
+        self.1.draw(target)?;
+
+        Ok(())
+    }
+}